@@ -0,0 +1,273 @@
+// matter-project/analysis-core/src/baseline.rs
+/*!
+Baseline management - pins a golden result per scenario and annotates later runs with deltas against it
+
+A scenario is identified by the protocol and the set of analysis sections a
+result contains (`throughput`, `osi_layer_7_application.scenarios`,
+`measured_energy.phases`, ...), so a run with `--throughput` is never compared
+against a baseline recorded without it. The scenario hash is the first 12 hex
+digits of the SHA-256 of that description, and each baseline is stored as
+`<results>/baselines/<hash>.json` together with when and from where it was
+pinned. Every analyzer run looks up the baseline for its own scenario and, if
+there is one, records the changed metrics under `baseline_comparison`.
+*/
+
+use crate::diff::{diff_results, print_changes, DiffConfig, MetricChange};
+use crate::netns::NAMESPACE_SECTION;
+use crate::schema::{load_result, upgrade};
+use crate::units::UNITS_SECTION;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Top-level field of a result that holds its deltas against the baseline.
+pub const BASELINE_SECTION: &str = "baseline_comparison";
+
+#[derive(Debug, Clone)]
+pub enum BaselineCommand {
+    /// Pin a result file as the golden result for its scenario.
+    Set(PathBuf),
+    /// List baselines, or compare a result file with its baseline.
+    Show(Option<PathBuf>),
+}
+
+#[derive(Debug, Clone)]
+pub struct BaselineConfig {
+    pub command: BaselineCommand,
+    pub dir: PathBuf,
+    pub threshold_percent: f64,
+}
+
+impl Default for BaselineConfig {
+    fn default() -> Self {
+        Self {
+            command: BaselineCommand::Show(None),
+            dir: PathBuf::from("../results/baselines"),
+            threshold_percent: DiffConfig::default().threshold_percent,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BaselineRecord {
+    pub scenario_hash: String,
+    pub protocol: String,
+    pub sections: Vec<String>,
+    pub pinned_at: DateTime<Utc>,
+    pub source_file: String,
+    pub result: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BaselineComparison {
+    pub scenario_hash: String,
+    pub pinned_at: DateTime<Utc>,
+    pub baseline_source: String,
+    pub threshold_percent: f64,
+    pub regressions: usize,
+    pub improvements: usize,
+    /// Metrics whose value differs from the baseline; unchanged ones are left out.
+    pub changes: Vec<MetricChange>,
+}
+
+/// Analysis sections present in a result: non-null top-level objects and arrays, and those one level down.
+pub fn scenario_sections(result: &Value) -> Vec<String> {
+    let is_section = |value: &Value| value.is_object() || value.is_array();
+    let mut sections = Vec::new();
+    if let Value::Object(fields) = result {
+        let measured = |key: &str| ![BASELINE_SECTION, UNITS_SECTION, NAMESPACE_SECTION].contains(&key);
+        for (key, value) in fields.iter().filter(|(key, value)| measured(key) && is_section(value)) {
+            sections.push(key.clone());
+            if let Value::Object(inner) = value {
+                sections.extend(inner.iter().filter(|(_, v)| is_section(v)).map(|(k, _)| format!("{}.{}", key, k)));
+            }
+        }
+    }
+    sections.sort();
+    sections
+}
+
+fn protocol_of(result: &Value) -> Result<String> {
+    let name = result
+        .get("protocol_name")
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("no protocol_name; not an analyzer result"))?;
+    Ok(name.split('_').next().unwrap_or(name).to_string())
+}
+
+/// Hash identifying the scenario a result was measured under.
+pub fn scenario_hash(result: &Value) -> Result<String> {
+    let description = format!("{}\n{}", protocol_of(result)?, scenario_sections(result).join("\n"));
+    let hash = digest::digest(&digest::SHA256, description.as_bytes());
+    Ok(hash.as_ref()[..6].iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+pub struct BaselineStore {
+    dir: PathBuf,
+}
+
+impl BaselineStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, hash: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", hash))
+    }
+
+    /// Pins `result` as the baseline of its scenario, replacing any earlier one.
+    pub fn pin(&self, mut result: Value, source_file: &Path) -> Result<BaselineRecord> {
+        if let Value::Object(fields) = &mut result {
+            fields.remove(BASELINE_SECTION);
+        }
+        let record = BaselineRecord {
+            scenario_hash: scenario_hash(&result)?,
+            protocol: protocol_of(&result)?,
+            sections: scenario_sections(&result),
+            pinned_at: Utc::now(),
+            source_file: source_file.display().to_string(),
+            result,
+        };
+        std::fs::create_dir_all(&self.dir).with_context(|| format!("creating {}", self.dir.display()))?;
+        let path = self.path(&record.scenario_hash);
+        std::fs::write(&path, serde_json::to_string_pretty(&record)?).with_context(|| format!("writing {}", path.display()))?;
+        Ok(record)
+    }
+
+    pub fn get(&self, hash: &str) -> Result<Option<BaselineRecord>> {
+        let path = self.path(hash);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let json = std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+        Ok(Some(serde_json::from_str(&json).with_context(|| format!("parsing {}", path.display()))?))
+    }
+
+    /// All pinned baselines, oldest first.
+    pub fn list(&self) -> Result<Vec<BaselineRecord>> {
+        if !self.dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut records = Vec::new();
+        for entry in std::fs::read_dir(&self.dir).with_context(|| format!("reading {}", self.dir.display()))? {
+            let path = entry?.path();
+            if let Some(hash) = path.file_stem().filter(|_| path.extension().is_some_and(|e| e == "json")) {
+                records.extend(self.get(&hash.to_string_lossy())?);
+            }
+        }
+        records.sort_by_key(|r| r.pinned_at);
+        Ok(records)
+    }
+
+    /// Deltas of `result` against the baseline of its scenario, if one is pinned.
+    pub fn compare(&self, result: &Value, threshold_percent: f64) -> Result<Option<BaselineComparison>> {
+        let Some(baseline) = self.get(&scenario_hash(result)?)? else {
+            return Ok(None);
+        };
+        // Baselines pinned before the last schema change are upgraded like any other result
+        let report = diff_results(&upgrade(baseline.result)?, result, threshold_percent);
+        Ok(Some(BaselineComparison {
+            scenario_hash: baseline.scenario_hash,
+            pinned_at: baseline.pinned_at,
+            baseline_source: baseline.source_file,
+            threshold_percent,
+            regressions: report.regressions,
+            improvements: report.improvements,
+            changes: report.changes.into_iter().filter(|c| c.old != c.new).collect(),
+        }))
+    }
+}
+
+pub struct BaselineManager {
+    config: BaselineConfig,
+}
+
+impl BaselineManager {
+    pub fn new(config: BaselineConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn run(&self) -> Result<()> {
+        let store = BaselineStore::new(&self.config.dir);
+        match &self.config.command {
+            BaselineCommand::Set(file) => {
+                let result = load_result(file)?;
+                let previous = store.get(&scenario_hash(&result)?)?;
+                let record = store.pin(result, file)?;
+                println!("\n📌 Pinned {} as the {} baseline for scenario {}", file.display(), record.protocol, record.scenario_hash);
+                println!("------------------------------------------------");
+                println!("✅ Sections: {}", record.sections.join(", "));
+                if let Some(previous) = previous {
+                    println!("✅ Replaces the baseline pinned {} from {}", previous.pinned_at.to_rfc3339(), previous.source_file);
+                }
+                println!("✅ Saved to: {}", store.path(&record.scenario_hash).display());
+            }
+            BaselineCommand::Show(None) => {
+                let records = store.list()?;
+                println!("\n📌 Baselines in {} ({})", self.config.dir.display(), records.len());
+                println!("------------------------------------------------");
+                for record in &records {
+                    println!("✅ {} {}: pinned {} from {} ({} sections)",
+                             record.scenario_hash, record.protocol, record.pinned_at.to_rfc3339(),
+                             record.source_file, record.sections.len());
+                }
+            }
+            BaselineCommand::Show(Some(file)) => {
+                let result = load_result(file)?;
+                let hash = scenario_hash(&result)?;
+                let comparison = store
+                    .compare(&result, self.config.threshold_percent)?
+                    .ok_or_else(|| anyhow!("no baseline pinned for scenario {} of {}", hash, file.display()))?;
+                println!("\n📌 {} against baseline {} (pinned {} from {})",
+                         file.display(), hash, comparison.pinned_at.to_rfc3339(), comparison.baseline_source);
+                println!("------------------------------------------------");
+                print_changes(&comparison.changes, false);
+                println!("\n{} regressions, {} improvements beyond {}%, {} metrics changed",
+                         comparison.regressions, comparison.improvements, comparison.threshold_percent,
+                         comparison.changes.len());
+            }
+        }
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn hash_depends_on_sections_not_values() {
+        let plain = json!({"protocol_name": "Matter_Protocol_Analysis", "osi_layer_4_transport": {"udp_discovery_time_ms": 1.0}, "throughput": null});
+        let slower = json!({"protocol_name": "Matter_Protocol_Analysis", "osi_layer_4_transport": {"udp_discovery_time_ms": 9.0}, "throughput": null});
+        let with_throughput = json!({"protocol_name": "Matter_Protocol_Analysis", "osi_layer_4_transport": {"udp_discovery_time_ms": 1.0}, "throughput": {"runs": []}});
+        let mut in_namespace = plain.clone();
+        in_namespace[NAMESPACE_SECTION] = json!({"name": "analyzer-1", "impairment": null});
+        assert_eq!(scenario_hash(&plain).unwrap(), scenario_hash(&slower).unwrap());
+        assert_eq!(scenario_hash(&plain).unwrap(), scenario_hash(&in_namespace).unwrap());
+        assert_ne!(scenario_hash(&plain).unwrap(), scenario_hash(&with_throughput).unwrap());
+        assert_eq!(scenario_sections(&with_throughput), vec!["osi_layer_4_transport", "throughput", "throughput.runs"]);
+    }
+
+    #[test]
+    fn runs_compare_against_their_own_scenario() {
+        let dir = std::env::temp_dir().join(format!("baselines-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = BaselineStore::new(&dir);
+        let baseline = json!({"protocol_name": "Matter", "osi_layer_5_session": {"commissioning_time_ms": 400.0}});
+        store.pin(baseline, Path::new("golden.json")).unwrap();
+
+        let run = json!({"protocol_name": "Matter", "osi_layer_5_session": {"commissioning_time_ms": 500.0}});
+        let comparison = store.compare(&run, 5.0).unwrap().unwrap();
+        assert_eq!(comparison.regressions, 1);
+        assert_eq!(comparison.changes[0].metric, "osi_layer_5_session.commissioning_time_ms");
+        let other = json!({"protocol_name": "LwM2M", "osi_layer_5_session": {"commissioning_time_ms": 500.0}});
+        assert!(store.compare(&other, 5.0).unwrap().is_none());
+        assert_eq!(store.list().unwrap().len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,449 @@
+// matter-project/analysis-core/src/budget.rs
+/*!
+Latency and overhead budgets - per-metric limits from a policy file, checked against results with a pass/fail exit code
+
+Firmware teams have budgets: commissioning must finish within 1.2 s at the
+95th percentile, a message may not carry more than 32 bytes of overhead. A
+policy file states them one per line, and `budget` (or `--budgets` after a
+run) checks them against results so a CI job fails when one is exceeded:
+
+```text
+# budgets.toml
+commissioning_time_ms.p95 < 1200
+secure_session.per_message_overhead_bytes <= 32
+
+[matter]
+transport_modes.udp_mrp.p95_latency_ms < 5
+```
+
+Each budget is a metric, an optional statistic, one of `<`, `<=`, `>` or `>=`
+and a limit in the unit the metric's name gives. Metrics match like
+`query`'s `metric=`: the full path as printed by `diff`, any trailing part of
+it such as the last segment, or a glob when the name has `*`. Values under
+`metric_retries` are retry counts keyed by metric name, and only match when
+the budget names that section.
+
+The statistic (`min`, `max`, `mean`, `median` or `p1` to `p100`, nearest
+rank) is taken over every value the metric matches in every checked run.
+Without one, every matching value must be within the limit. Budgets under a
+`[protocol]` header only look at runs of that protocol; those above the first
+header look at all runs. A budget that matches no value fails too, so a
+renamed metric or a disabled analyzer cannot pass unnoticed. A failed check
+exits with [`OVER_BUDGET_EXIT_CODE`], and errors such as an unreadable policy
+exit with 1.
+*/
+
+use crate::campaign::{CampaignMerger, CampaignRun, MergeConfig};
+use crate::diff::flatten;
+use crate::query::{metric_matches, Operator};
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Exit code when a budget is exceeded or not measured; errors exit with 1.
+pub const OVER_BUDGET_EXIT_CODE: i32 = 2;
+
+#[derive(Debug, Clone)]
+pub struct BudgetConfig {
+    pub policy: PathBuf,
+    /// Result files, directories or merged datasets; the latest Matter result when empty.
+    pub inputs: Vec<PathBuf>,
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self {
+            policy: PathBuf::from("budgets.toml"),
+            inputs: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Statistic {
+    Min,
+    Max,
+    Mean,
+    /// Nearest-rank percentile, 1 to 100; `median` is 50.
+    Percentile(u8),
+}
+
+impl FromStr for Statistic {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "min" => Ok(Statistic::Min),
+            "max" => Ok(Statistic::Max),
+            "mean" | "avg" => Ok(Statistic::Mean),
+            "median" => Ok(Statistic::Percentile(50)),
+            other => other
+                .strip_prefix('p')
+                .and_then(|rank| rank.parse().ok())
+                .filter(|rank| (1..=100).contains(rank))
+                .map(Statistic::Percentile)
+                .ok_or_else(|| anyhow!("unknown statistic: {} (expected min, max, mean, median or p1 to p100)", other)),
+        }
+    }
+}
+
+impl fmt::Display for Statistic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statistic::Min => f.write_str("min"),
+            Statistic::Max => f.write_str("max"),
+            Statistic::Mean => f.write_str("mean"),
+            Statistic::Percentile(rank) => write!(f, "p{}", rank),
+        }
+    }
+}
+
+impl Statistic {
+    /// The statistic of `values`, which must not be empty, and the index of the value it is when it is one of them.
+    fn of(self, values: &[f64]) -> (f64, Option<usize>) {
+        let mut order: Vec<usize> = (0..values.len()).collect();
+        order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+        let index = match self {
+            Statistic::Min => order[0],
+            Statistic::Max => order[order.len() - 1],
+            Statistic::Mean => return (values.iter().sum::<f64>() / values.len() as f64, None),
+            Statistic::Percentile(rank) => {
+                let position = (rank as f64 / 100.0 * values.len() as f64).ceil() as usize;
+                order[position.max(1) - 1]
+            }
+        };
+        (values[index], Some(index))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Budget {
+    /// Line in the policy file, counted from 1.
+    pub line: usize,
+    /// From the `[protocol]` header above the budget; `None` checks runs of every protocol.
+    pub protocol: Option<String>,
+    pub metric: String,
+    /// `None` holds every matching value to the limit.
+    pub statistic: Option<Statistic>,
+    pub operator: Operator,
+    pub limit: f64,
+}
+
+impl fmt::Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(protocol) = &self.protocol {
+            write!(f, "[{}] ", protocol)?;
+        }
+        f.write_str(&self.metric)?;
+        if let Some(statistic) = self.statistic {
+            write!(f, ".{}", statistic)?;
+        }
+        let operator = match self.operator {
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            _ => ">=",
+        };
+        write!(f, " {} {}", operator, self.limit)
+    }
+}
+
+impl Budget {
+    fn parse(line: usize, protocol: Option<&str>, text: &str) -> Result<Self> {
+        let at = text
+            .find(['<', '>', '=', '!'])
+            .ok_or_else(|| anyhow!("line {}: expected <metric> <operator> <limit>, found {}", line, text))?;
+        let (name, rest) = (text[..at].trim(), &text[at..]);
+        let (operator, limit) = if let Some(limit) = rest.strip_prefix("<=") {
+            (Operator::Le, limit)
+        } else if let Some(limit) = rest.strip_prefix(">=") {
+            (Operator::Ge, limit)
+        } else if let Some(limit) = rest.strip_prefix('<') {
+            (Operator::Lt, limit)
+        } else if let Some(limit) = rest.strip_prefix('>') {
+            (Operator::Gt, limit)
+        } else {
+            return Err(anyhow!("line {}: a budget is a bound; expected <, <=, > or >= after {}", line, name));
+        };
+        let limit = limit.trim();
+        let limit: f64 = limit.parse().map_err(|_| anyhow!("line {}: the limit must be a number, not {}", line, limit))?;
+
+        let (metric, statistic) = match name.rsplit_once('.') {
+            Some((metric, statistic)) => match statistic.parse() {
+                Ok(statistic) => (metric, Some(statistic)),
+                Err(_) => (name, None),
+            },
+            None => (name, None),
+        };
+        if metric.is_empty() {
+            return Err(anyhow!("line {}: no metric before {}", line, rest));
+        }
+        Ok(Self {
+            line,
+            protocol: protocol.map(str::to_string),
+            metric: metric.to_string(),
+            statistic,
+            operator,
+            limit,
+        })
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        metric_matches(&self.metric, path)
+    }
+
+    fn applies_to(&self, protocol: &str) -> bool {
+        self.protocol.as_ref().is_none_or(|wanted| wanted.eq_ignore_ascii_case(protocol))
+    }
+
+    /// What a value is held to without a statistic: the worst one.
+    fn statistic_or_worst(&self) -> Statistic {
+        self.statistic.unwrap_or(match self.operator {
+            Operator::Gt | Operator::Ge => Statistic::Min,
+            _ => Statistic::Max,
+        })
+    }
+
+    fn holds(&self, value: f64) -> bool {
+        match self.operator {
+            Operator::Lt => value < self.limit,
+            Operator::Le => value <= self.limit,
+            Operator::Gt => value > self.limit,
+            _ => value >= self.limit,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BudgetPolicy {
+    pub budgets: Vec<Budget>,
+}
+
+impl FromStr for BudgetPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut policy = BudgetPolicy::default();
+        let mut protocol: Option<&str> = None;
+        for (index, line) in s.lines().enumerate() {
+            let text = line.split('#').next().unwrap_or_default().trim();
+            if text.is_empty() {
+                continue;
+            }
+            if let Some(header) = text.strip_prefix('[').and_then(|header| header.strip_suffix(']')) {
+                let header = header.trim();
+                if header.is_empty() {
+                    return Err(anyhow!("line {}: a [protocol] header needs a protocol name", index + 1));
+                }
+                protocol = Some(header);
+                continue;
+            }
+            policy.budgets.push(Budget::parse(index + 1, protocol, text)?);
+        }
+        if policy.budgets.is_empty() {
+            return Err(anyhow!("no budgets in the policy"));
+        }
+        Ok(policy)
+    }
+}
+
+/// How one budget fared against the checked runs.
+#[derive(Debug, Clone)]
+pub struct BudgetOutcome {
+    pub budget: Budget,
+    pub passed: bool,
+    /// Values the metric matched across the checked runs; none fails the budget.
+    pub values: usize,
+    /// The value held to the limit: the statistic, or the worst value without one.
+    pub value: Option<f64>,
+    /// `<run>: <metric path>` of that value, when it is one of the measured values.
+    pub at: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BudgetReport {
+    pub runs: usize,
+    pub outcomes: Vec<BudgetOutcome>,
+}
+
+impl BudgetReport {
+    pub fn passed(&self) -> bool {
+        self.outcomes.iter().all(|outcome| outcome.passed)
+    }
+
+    pub fn print(&self, policy: &Path) {
+        println!("\n🎯 Budgets in {} against {} runs", policy.display(), self.runs);
+        println!("------------------------------------------------");
+        for outcome in &self.outcomes {
+            let marker = if outcome.passed { "✅" } else { "❌" };
+            match outcome.value {
+                None => println!("⚠️ {}: not measured", outcome.budget),
+                Some(value) => match &outcome.at {
+                    Some(at) if outcome.values > 1 => println!("{} {}: {} at {}", marker, outcome.budget, value, at),
+                    _ => println!("{} {}: {} ({} values)", marker, outcome.budget, value, outcome.values),
+                },
+            }
+        }
+        let failed = self.outcomes.iter().filter(|outcome| !outcome.passed).count();
+        let unmeasured = self.outcomes.iter().filter(|outcome| outcome.value.is_none()).count();
+        if failed == 0 {
+            println!("\n✅ All {} budgets met", self.outcomes.len());
+        } else {
+            println!("\n❌ {} of {} budgets failed ({} not measured)", failed, self.outcomes.len(), unmeasured);
+        }
+    }
+}
+
+impl BudgetPolicy {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading budget policy {}", path.display()))?;
+        text.parse().with_context(|| format!("in budget policy {}", path.display()))
+    }
+
+    /// Checks every budget against the runs in `inputs`, loaded the same way `merge` loads them.
+    pub fn check(&self, inputs: Vec<PathBuf>) -> Result<BudgetReport> {
+        let dataset = CampaignMerger::new(MergeConfig { inputs, ..MergeConfig::default() }).merge()?;
+        if dataset.runs.is_empty() {
+            return Err(anyhow!("no results to check budgets against"));
+        }
+        Ok(self.check_runs(&dataset.runs))
+    }
+
+    pub fn check_runs(&self, runs: &[CampaignRun]) -> BudgetReport {
+        let metrics: Vec<BTreeMap<String, f64>> = runs
+            .iter()
+            .map(|run| {
+                let mut metrics = BTreeMap::new();
+                flatten(&run.result, String::new(), &mut metrics);
+                metrics
+            })
+            .collect();
+
+        let outcomes = self
+            .budgets
+            .iter()
+            .map(|budget| {
+                let mut values = Vec::new();
+                let mut places = Vec::new();
+                for (run, metrics) in runs.iter().zip(&metrics).filter(|(run, _)| budget.applies_to(&run.provenance.protocol)) {
+                    for (path, value) in metrics.iter().filter(|(path, _)| budget.matches(path)) {
+                        values.push(*value);
+                        places.push(format!("{}: {}", run.key, path));
+                    }
+                }
+                if values.is_empty() {
+                    return BudgetOutcome { budget: budget.clone(), passed: false, values: 0, value: None, at: None };
+                }
+                let (value, index) = budget.statistic_or_worst().of(&values);
+                BudgetOutcome {
+                    budget: budget.clone(),
+                    passed: budget.holds(value),
+                    values: values.len(),
+                    value: Some(value),
+                    at: index.map(|index| places.swap_remove(index)),
+                }
+            })
+            .collect();
+        BudgetReport { runs: runs.len(), outcomes }
+    }
+}
+
+pub struct BudgetCheck {
+    config: BudgetConfig,
+}
+
+impl BudgetCheck {
+    pub fn new(config: BudgetConfig) -> Self {
+        Self { config }
+    }
+
+    /// Prints every budget's outcome; the caller exits with [`OVER_BUDGET_EXIT_CODE`] when the report did not pass.
+    pub fn run(&self) -> Result<BudgetReport> {
+        let policy = BudgetPolicy::load(&self.config.policy)?;
+        let inputs = if self.config.inputs.is_empty() {
+            vec![PathBuf::from("../results/matter_real_analysis.json")]
+        } else {
+            self.config.inputs.clone()
+        };
+        let report = policy.check(inputs)?;
+        report.print(&self.config.policy);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::campaign::RunProvenance;
+    use serde_json::json;
+
+    fn run(key: &str, protocol: &str, result: serde_json::Value) -> CampaignRun {
+        CampaignRun {
+            key: key.to_string(),
+            provenance: RunProvenance {
+                source_file: format!("{}.json", key),
+                sha256: String::new(),
+                protocol: protocol.to_string(),
+                analysis_timestamp: None,
+                saved_at: None,
+                host: None,
+                device_class: None,
+                file_modified: None,
+            },
+            result,
+        }
+    }
+
+    #[test]
+    fn budgets_hold_statistics_of_matching_metrics_to_their_limits() {
+        let policy: BudgetPolicy = "
+            # budgets.toml
+            commissioning_time_ms.p95 < 1200
+            per_message_overhead_bytes <= 32   # every run
+            [LwM2M]
+            registration_time_ms.median > 0
+            [matter]
+            udp_discovery_time_ms < 10
+            missing_metric_ms < 5
+        "
+        .parse()
+        .unwrap();
+        assert_eq!(policy.budgets.len(), 5);
+        assert_eq!(policy.budgets[0].statistic, Some(Statistic::Percentile(95)));
+        assert_eq!(policy.budgets[3].to_string(), "[matter] udp_discovery_time_ms < 10");
+
+        let runs = [
+            run("matter/a", "Matter", json!({
+                "osi_layer_5_session": {"commissioning_time_ms": 900.0},
+                "osi_layer_5_session.secure_session": {"per_message_overhead_bytes": 24},
+                "osi_layer_4_transport": {"udp_discovery_time_ms": 13.0},
+                "metric_retries": {"commissioning_time_ms": 5000.0}
+            })),
+            run("matter/b", "Matter", json!({
+                "osi_layer_5_session": {"commissioning_time_ms": 1100.0},
+                "osi_layer_4_transport": {"udp_discovery_time_ms": 4.0}
+            })),
+            run("lwm2m/a", "LwM2M", json!({"osi_layer_5_session": {"commissioning_time_ms": 1500.0}})),
+        ];
+        let report = policy.check_runs(&runs);
+        let outcome = |line: usize| report.outcomes.iter().find(|o| o.budget.line == line).unwrap();
+
+        // p95 of 900, 1100 and 1500 is the largest; retry counts are left out
+        assert!(!outcome(3).passed);
+        assert_eq!(outcome(3).value, Some(1500.0));
+        assert_eq!(outcome(3).at.as_deref(), Some("lwm2m/a: osi_layer_5_session.commissioning_time_ms"));
+        assert!(outcome(4).passed);
+        assert_eq!(outcome(4).values, 1);
+        assert_eq!(outcome(6).value, None);
+        assert!(!outcome(6).passed);
+        assert_eq!(outcome(8).value, Some(13.0));
+        assert!(!outcome(8).passed);
+        assert!(!report.passed());
+
+        assert!("udp_discovery_time_ms = 10".parse::<BudgetPolicy>().is_err());
+        assert!("udp_discovery_time_ms < fast".parse::<BudgetPolicy>().is_err());
+        assert!("[]\nx < 1".parse::<BudgetPolicy>().is_err());
+    }
+}
@@ -0,0 +1,349 @@
+// matter-project/analysis-core/src/campaign.rs
+/*!
+Campaign aggregation - merges result files from many hosts, days and scenarios into one dataset
+
+Inputs are result JSON files or directories searched recursively. Each result
+becomes a run keyed `<protocol>/<input name>/<relative path>`, so the
+directory layout of a campaign (for example `campaign/pi4/2025-06-23/scenarios.json`)
+survives in the key, and the run carries its provenance: source file, SHA-256
+of the file, host description from `test_environment`, timestamp and file
+modification time. Byte-identical files are kept once. A merged dataset can be
+merged again; its runs keep their keys and provenance.
+*/
+
+use crate::integrity::sha256_hex;
+use crate::results_store::RunIndex;
+use crate::schema::upgrade;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const CAMPAIGN_FORMAT_VERSION: &str = "1";
+
+#[derive(Debug, Clone)]
+pub struct MergeConfig {
+    /// Result files, or directories searched recursively for `*.json`.
+    pub inputs: Vec<PathBuf>,
+    pub output: PathBuf,
+}
+
+impl Default for MergeConfig {
+    fn default() -> Self {
+        Self {
+            inputs: Vec::new(),
+            output: PathBuf::from("../results/campaign.json"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunProvenance {
+    pub source_file: String,
+    pub sha256: String,
+    pub protocol: String,
+    pub analysis_timestamp: Option<String>,
+    /// When the results store saved the run, for files listed in a run index.
+    #[serde(default)]
+    pub saved_at: Option<DateTime<Utc>>,
+    /// CPU model, OS and architecture from `test_environment`, when the result has one.
+    pub host: Option<String>,
+    pub device_class: Option<String>,
+    pub file_modified: Option<DateTime<Utc>>,
+}
+
+impl RunProvenance {
+    /// When the run was made: the time it was saved, else its analysis timestamp, else the file's modification time.
+    pub fn timestamp(&self) -> Option<String> {
+        self.saved_at
+            .map(|t| t.to_rfc3339())
+            .or_else(|| self.analysis_timestamp.clone())
+            .or_else(|| self.file_modified.map(|t| t.to_rfc3339()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CampaignRun {
+    pub key: String,
+    pub provenance: RunProvenance,
+    pub result: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedFile {
+    pub file: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CampaignDataset {
+    pub format_version: String,
+    pub merged_at: DateTime<Utc>,
+    pub runs: Vec<CampaignRun>,
+    pub skipped: Vec<SkippedFile>,
+}
+
+impl CampaignDataset {
+    pub fn protocols(&self) -> Vec<String> {
+        let mut protocols: Vec<String> = self.runs.iter().map(|r| r.provenance.protocol.clone()).collect();
+        protocols.sort();
+        protocols.dedup();
+        protocols
+    }
+}
+
+pub struct CampaignMerger {
+    config: MergeConfig,
+}
+
+impl CampaignMerger {
+    pub fn new(config: MergeConfig) -> Self {
+        Self { config }
+    }
+
+    /// Merges the inputs and writes the dataset to the configured output.
+    pub fn merge_and_save(&self) -> Result<CampaignDataset> {
+        println!("\n🗂️ Merging Campaign Results ({} inputs)", self.config.inputs.len());
+        println!("----------------------------------------");
+
+        let dataset = self.merge()?;
+        for protocol in dataset.protocols() {
+            let runs = dataset.runs.iter().filter(|r| r.provenance.protocol == protocol).count();
+            println!("✅ {}: {} runs", protocol, runs);
+        }
+        for skipped in &dataset.skipped {
+            println!("⚠️ Skipped {}: {}", skipped.file, skipped.reason);
+        }
+
+        if let Some(parent) = self.config.output.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.config.output, serde_json::to_string_pretty(&dataset)?)
+            .with_context(|| format!("writing {}", self.config.output.display()))?;
+        println!("✅ {} runs saved to: {}", dataset.runs.len(), self.config.output.display());
+        Ok(dataset)
+    }
+
+    pub fn merge(&self) -> Result<CampaignDataset> {
+        let mut dataset = CampaignDataset {
+            format_version: CAMPAIGN_FORMAT_VERSION.to_string(),
+            merged_at: Utc::now(),
+            runs: Vec::new(),
+            skipped: Vec::new(),
+        };
+        // SHA-256 → key of the run already taken from that content
+        let mut seen: HashMap<String, String> = HashMap::new();
+
+        for input in &self.config.inputs {
+            let root_name = input
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "input".to_string());
+            let files = if input.is_dir() {
+                json_files(input)?
+            } else if input.is_file() {
+                vec![input.clone()]
+            } else {
+                return Err(anyhow!("{} does not exist", input.display()));
+            };
+
+            for file in files {
+                if same_file(&file, &self.config.output) {
+                    continue;
+                }
+                let relative = if input.is_dir() {
+                    let relative = file.strip_prefix(input).unwrap_or(&file).with_extension("");
+                    format!("{}/{}", root_name, path_key(&relative))
+                } else {
+                    root_name.clone()
+                };
+                if let Err(e) = self.add_file(&mut dataset, &mut seen, &file, &relative) {
+                    dataset.skipped.push(SkippedFile { file: file.display().to_string(), reason: format!("{:#}", e) });
+                }
+            }
+        }
+
+        dataset.runs.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(dataset)
+    }
+
+    fn add_file(&self, dataset: &mut CampaignDataset, seen: &mut HashMap<String, String>, file: &Path, relative: &str) -> Result<()> {
+        let bytes = std::fs::read(file).with_context(|| format!("reading {}", file.display()))?;
+        let value: Value = serde_json::from_slice(&bytes).context("not valid JSON")?;
+
+        // An earlier merge: take its runs as they are
+        if value.get("format_version").is_some() && value.get("runs").is_some() {
+            let nested: CampaignDataset = serde_json::from_value(value).context("not a campaign dataset")?;
+            for run in nested.runs {
+                if let Some(existing) = seen.get(&run.provenance.sha256) {
+                    dataset.skipped.push(SkippedFile {
+                        file: run.provenance.source_file.clone(),
+                        reason: format!("duplicate of {}", existing),
+                    });
+                    continue;
+                }
+                let key = unique_key(dataset, &run.key);
+                seen.insert(run.provenance.sha256.clone(), key.clone());
+                let result = upgrade(run.result)?;
+                dataset.runs.push(CampaignRun { key, result, ..run });
+            }
+            dataset.skipped.extend(nested.skipped);
+            return Ok(());
+        }
+
+        let sha256 = sha256_hex(&bytes);
+        if let Some(existing) = seen.get(&sha256) {
+            return Err(anyhow!("duplicate of {}", existing));
+        }
+
+        let value = upgrade(value)?;
+        let protocol = value
+            .get("protocol_name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("no protocol_name; not an analyzer result"))?;
+        let protocol = protocol.split('_').next().unwrap_or(protocol).to_string();
+        let environment = value.get("test_environment");
+        let text = |value: Option<&Value>, field: &str| value.and_then(|v| v.get(field)).and_then(Value::as_str).map(str::to_string);
+        let host = environment.map(|_| {
+            format!("{} ({}/{})",
+                    text(environment, "cpu_model").unwrap_or_else(|| "unknown CPU".to_string()),
+                    text(environment, "os_platform").unwrap_or_default(),
+                    text(environment, "architecture").unwrap_or_default())
+        });
+
+        let key = unique_key(dataset, &format!("{}/{}", protocol.to_lowercase(), relative));
+        seen.insert(sha256.clone(), key.clone());
+        dataset.runs.push(CampaignRun {
+            key,
+            provenance: RunProvenance {
+                source_file: file.display().to_string(),
+                sha256,
+                protocol,
+                analysis_timestamp: text(Some(&value), "analysis_timestamp"),
+                saved_at: saved_at(file),
+                host,
+                device_class: text(environment, "device_class"),
+                file_modified: std::fs::metadata(file).and_then(|m| m.modified()).ok().map(DateTime::<Utc>::from),
+            },
+            result: value,
+        });
+        Ok(())
+    }
+}
+
+/// When the results store saved `file`, if the run index next to it lists it.
+fn saved_at(file: &Path) -> Option<DateTime<Utc>> {
+    let index = std::fs::read_to_string(file.with_file_name("index.json")).ok()?;
+    let index: RunIndex = serde_json::from_str(&index).ok()?;
+    let name = file.file_name()?.to_str()?;
+    index.runs.into_iter().find(|entry| entry.file == name).map(|entry| entry.saved_at)
+}
+
+/// `*.json` files under `dir`, in path order.
+fn json_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir).with_context(|| format!("reading {}", dir.display()))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|e| e == "json") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Path components joined with `/` on every platform.
+fn path_key(path: &Path) -> String {
+    path.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// `key`, or `key~2`, `key~3`, … if a different run already has it.
+fn unique_key(dataset: &CampaignDataset, key: &str) -> String {
+    let taken = |candidate: &str| dataset.runs.iter().any(|r| r.key == candidate);
+    if !taken(key) {
+        return key.to_string();
+    }
+    (2..).map(|n| format!("{}~{}", key, n)).find(|candidate| !taken(candidate)).expect("unbounded suffixes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn campaign_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("campaign-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("pi4/day1")).unwrap();
+        std::fs::create_dir_all(dir.join("laptop/day1")).unwrap();
+        let result = |timestamp: &str| {
+            format!(r#"{{"protocol_name":"Matter_Protocol_Analysis","analysis_timestamp":"{}","test_environment":{{"cpu_model":"Cortex-A72","os_platform":"linux","architecture":"aarch64","device_class":"constrained"}}}}"#, timestamp)
+        };
+        std::fs::write(dir.join("pi4/day1/matter.json"), result("2025-06-23T10:00:00Z")).unwrap();
+        std::fs::write(dir.join("laptop/day1/matter.json"), result("2025-06-23T11:00:00Z")).unwrap();
+        std::fs::write(dir.join("laptop/day1/copy.json"), result("2025-06-23T11:00:00Z")).unwrap();
+        std::fs::write(dir.join("laptop/notes.json"), "[1, 2]").unwrap();
+        dir
+    }
+
+    #[test]
+    fn keys_keep_campaign_layout() {
+        let dir = campaign_dir("keys");
+        let merger = CampaignMerger::new(MergeConfig { inputs: vec![dir.clone()], output: dir.join("merged.json") });
+        let dataset = merger.merge().unwrap();
+        let root = dir.file_name().unwrap().to_string_lossy();
+        let keys: Vec<_> = dataset.runs.iter().map(|r| r.key.clone()).collect();
+        assert_eq!(keys, vec![format!("matter/{}/laptop/day1/copy", root), format!("matter/{}/pi4/day1/matter", root)]);
+        // The byte-identical laptop/day1/matter.json and the non-result notes.json are skipped
+        assert_eq!(dataset.skipped.len(), 2);
+        assert_eq!(dataset.runs[1].provenance.host.as_deref(), Some("Cortex-A72 (linux/aarch64)"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn merged_dataset_merges_again() {
+        let dir = campaign_dir("nested");
+        let output = dir.join("merged.json");
+        let first = CampaignMerger::new(MergeConfig { inputs: vec![dir.clone()], output: output.clone() })
+            .merge_and_save()
+            .unwrap();
+        let second = CampaignMerger::new(MergeConfig { inputs: vec![output.clone()], output: dir.join("again.json") })
+            .merge()
+            .unwrap();
+        let keys = |d: &CampaignDataset| d.runs.iter().map(|r| r.key.clone()).collect::<Vec<_>>();
+        assert_eq!(keys(&first), keys(&second));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stored_runs_are_timed_by_the_run_index() {
+        use crate::results_store::{ResultsConfig, ResultsStore};
+        use crate::run_metadata::RunMetadata;
+
+        let dir = std::env::temp_dir().join(format!("campaign-store-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = ResultsStore::new(ResultsConfig { dir: dir.clone(), ..ResultsConfig::default() });
+        let json = r#"{"protocol_name":"Matter_Protocol_Analysis","analysis_timestamp":"2025-01-07T12:00:00Z"}"#;
+        let saved = store.save("Matter", json, &RunMetadata::default()).unwrap();
+        let dataset = CampaignMerger::new(MergeConfig { inputs: vec![saved.path], output: dir.join("merged.json") })
+            .merge()
+            .unwrap();
+        let saved_at = store.index().unwrap().runs[0].saved_at;
+        assert_eq!(dataset.runs[0].provenance.saved_at, Some(saved_at));
+        assert_eq!(dataset.runs[0].provenance.timestamp(), Some(saved_at.to_rfc3339()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
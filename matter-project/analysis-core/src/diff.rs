@@ -0,0 +1,265 @@
+// matter-project/analysis-core/src/diff.rs
+/*!
+Result diff - per-metric changes between two result files, with regressions over a threshold highlighted
+
+Both files are flattened into numeric metrics keyed by JSON path. Array
+elements are keyed by their `protocol`, `name`, `scenario`, `case`, `kind`,
+`mechanism` or `backend` field when they have one, so reordered lists still
+line up, and by index otherwise. Whether a change is a regression depends on
+the metric's direction, taken from its name: times, bytes, losses and
+failures should go down, and scores, throughput and success rates should go
+up. Metrics whose direction is not known are shown but never flagged.
+*/
+
+use crate::baseline::BASELINE_SECTION;
+use crate::schema::load_result;
+use crate::units::UNITS_SECTION;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Fields that identify an array element, in order of preference.
+const ELEMENT_KEYS: [&str; 7] = ["protocol", "name", "scenario", "case", "kind", "mechanism", "backend"];
+
+/// Name fragments of metrics where a larger value is worse.
+const LOWER_IS_BETTER: [&str; 18] = [
+    "_ms", "_us", "_ns", "time", "latency", "delay", "jitter", "bytes", "overhead", "loss", "retransmissions",
+    "retries", "restarts", "failures", "panics", "anomal", "cycles", "instructions",
+];
+/// Name fragments of metrics where a larger value is better; checked first.
+const HIGHER_IS_BETTER: [&str; 8] =
+    ["score", "efficiency", "throughput", "mbps", "success", "delivered_ratio", "goodput", "max_connections"];
+
+#[derive(Debug, Clone)]
+pub struct DiffConfig {
+    pub old: PathBuf,
+    pub new: PathBuf,
+    /// Changes in the worse direction beyond this many percent are regressions.
+    pub threshold_percent: f64,
+    pub show_unchanged: bool,
+    /// Return an error when any regression is found, for use in scripts.
+    pub fail_on_regression: bool,
+}
+
+impl Default for DiffConfig {
+    fn default() -> Self {
+        Self {
+            old: PathBuf::new(),
+            new: PathBuf::new(),
+            threshold_percent: 5.0,
+            show_unchanged: false,
+            fail_on_regression: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    LowerIsBetter,
+    HigherIsBetter,
+    Unknown,
+}
+
+impl Direction {
+    pub fn of(path: &str) -> Self {
+        let name = path.rsplit('.').next().unwrap_or(path).to_ascii_lowercase();
+        if HIGHER_IS_BETTER.iter().any(|fragment| name.contains(fragment)) {
+            Direction::HigherIsBetter
+        } else if LOWER_IS_BETTER.iter().any(|fragment| name.contains(fragment)) {
+            Direction::LowerIsBetter
+        } else {
+            Direction::Unknown
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricChange {
+    pub metric: String,
+    pub old: Option<f64>,
+    pub new: Option<f64>,
+    /// Relative to the old value; `None` when a side is missing or the old value is zero.
+    pub change_percent: Option<f64>,
+    pub direction: Direction,
+    pub regression: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiffReport {
+    pub threshold_percent: f64,
+    pub changes: Vec<MetricChange>,
+    pub regressions: usize,
+    pub improvements: usize,
+}
+
+/// Compares two parsed results metric by metric.
+pub fn diff_results(old: &Value, new: &Value, threshold_percent: f64) -> DiffReport {
+    let mut old_metrics = BTreeMap::new();
+    let mut new_metrics = BTreeMap::new();
+    flatten(old, String::new(), &mut old_metrics);
+    flatten(new, String::new(), &mut new_metrics);
+
+    let mut metrics: Vec<&String> = old_metrics.keys().chain(new_metrics.keys()).collect();
+    metrics.sort();
+    metrics.dedup();
+
+    let mut report = DiffReport { threshold_percent, changes: Vec::new(), regressions: 0, improvements: 0 };
+    for metric in metrics {
+        let old = old_metrics.get(metric).copied();
+        let new = new_metrics.get(metric).copied();
+        let change_percent = match (old, new) {
+            (Some(old), Some(new)) if old != 0.0 => Some((new - old) / old.abs() * 100.0),
+            _ => None,
+        };
+        let direction = Direction::of(metric);
+        let worse = match (direction, old, new) {
+            (Direction::LowerIsBetter, Some(old), Some(new)) => new > old,
+            (Direction::HigherIsBetter, Some(old), Some(new)) => new < old,
+            _ => false,
+        };
+        // A metric that was zero has no percentage; any move in the worse direction counts
+        let beyond_threshold = change_percent.map_or(old == Some(0.0), |p| p.abs() > threshold_percent);
+        let regression = worse && beyond_threshold;
+        let improvement = !worse && direction != Direction::Unknown && old != new && beyond_threshold;
+        report.regressions += regression as usize;
+        report.improvements += improvement as usize;
+        report.changes.push(MetricChange { metric: metric.clone(), old, new, change_percent, direction, regression });
+    }
+    report
+}
+
+/// Numeric leaves of `value` keyed by JSON path, with array elements labelled as described above.
+pub fn flatten(value: &Value, path: String, metrics: &mut BTreeMap<String, f64>) {
+    let join = |key: &str| if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) };
+    match value {
+        Value::Number(n) => {
+            if let Some(n) = n.as_f64() {
+                metrics.insert(path, n);
+            }
+        }
+        Value::Object(fields) => {
+            for (key, field) in fields {
+                // Deltas against a pinned baseline and the unit table are not measurements of this run
+                if path.is_empty() && (key == BASELINE_SECTION || key == UNITS_SECTION) {
+                    continue;
+                }
+                flatten(field, join(key), metrics);
+            }
+        }
+        Value::Array(elements) => {
+            let labels: Vec<String> = elements
+                .iter()
+                .enumerate()
+                .map(|(index, element)| {
+                    ELEMENT_KEYS
+                        .iter()
+                        .find_map(|key| element.get(key).and_then(label_of).map(|v| format!("{}={}", key, v)))
+                        .unwrap_or_else(|| index.to_string())
+                })
+                .collect();
+            for (index, (element, label)) in elements.iter().zip(&labels).enumerate() {
+                // Elements sharing a label keep their position too
+                let element_path = if labels.iter().filter(|l| *l == label).count() > 1 {
+                    format!("{}[{}#{}]", path, label, index)
+                } else {
+                    format!("{}[{}]", path, label)
+                };
+                flatten(element, element_path, metrics);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn label_of(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+pub struct ResultDiff {
+    config: DiffConfig,
+}
+
+impl ResultDiff {
+    pub fn new(config: DiffConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn run(&self) -> Result<DiffReport> {
+        let report = diff_results(&load_result(&self.config.old)?, &load_result(&self.config.new)?, self.config.threshold_percent);
+
+        println!("\n🔍 {} → {} (regression threshold {}%)",
+                 self.config.old.display(), self.config.new.display(), self.config.threshold_percent);
+        println!("------------------------------------------------");
+        print_changes(&report.changes, self.config.show_unchanged);
+        println!("\n{} regressions, {} improvements beyond {}%, {} metrics compared",
+                 report.regressions, report.improvements, self.config.threshold_percent, report.changes.len());
+
+        if self.config.fail_on_regression && report.regressions > 0 {
+            return Err(anyhow!("{} metrics regressed by more than {}%", report.regressions, self.config.threshold_percent));
+        }
+        Ok(report)
+    }
+}
+
+/// One line per metric: marker, path, old → new and the relative change.
+pub(crate) fn print_changes(changes: &[MetricChange], show_unchanged: bool) {
+    let number = |v: Option<f64>| v.map_or_else(|| "—".to_string(), |v| format!("{:.4}", v).trim_end_matches('0').trim_end_matches('.').to_string());
+    for change in changes {
+        let unchanged = change.old == change.new;
+        if unchanged && !show_unchanged {
+            continue;
+        }
+        let marker = match (change.old, change.new) {
+            _ if change.regression => "❌",
+            (None, Some(_)) => "➕",
+            (Some(_), None) => "➖",
+            _ if unchanged => "  ",
+            _ => match (change.direction, change.new > change.old) {
+                (Direction::Unknown, _) => "•",
+                (Direction::LowerIsBetter, false) | (Direction::HigherIsBetter, true) => "✅",
+                _ => "•",
+            },
+        };
+        let percent = change.change_percent.map_or_else(String::new, |p| format!(" ({:+.1}%)", p));
+        println!("{} {}: {} → {}{}", marker, change.metric, number(change.old), number(change.new), percent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn change<'a>(report: &'a DiffReport, metric: &str) -> &'a MetricChange {
+        report.changes.iter().find(|c| c.metric == metric).unwrap()
+    }
+
+    #[test]
+    fn direction_decides_regressions() {
+        let old = json!({"handshake_time_ms": 10.0, "efficiency_score": 0.8, "cpu_cores": 4});
+        let new = json!({"handshake_time_ms": 12.0, "efficiency_score": 0.9, "cpu_cores": 8});
+        let report = diff_results(&old, &new, 5.0);
+        assert!(change(&report, "handshake_time_ms").regression);
+        assert!(!change(&report, "efficiency_score").regression);
+        assert!(!change(&report, "cpu_cores").regression);
+        assert_eq!((report.regressions, report.improvements), (1, 1));
+    }
+
+    #[test]
+    fn array_elements_match_by_protocol() {
+        let old = json!({"protocols": [{"protocol": "Matter", "wire_bytes": 100}, {"protocol": "MQTT", "wire_bytes": 50}]});
+        let new = json!({"protocols": [{"protocol": "MQTT", "wire_bytes": 50}, {"protocol": "Matter", "wire_bytes": 103}]});
+        let report = diff_results(&old, &new, 5.0);
+        let matter = change(&report, "protocols[protocol=Matter].wire_bytes");
+        assert!((matter.change_percent.unwrap() - 3.0).abs() < 1e-9);
+        assert!(!matter.regression);
+        assert_eq!(change(&report, "protocols[protocol=MQTT].wire_bytes").change_percent, Some(0.0));
+    }
+}
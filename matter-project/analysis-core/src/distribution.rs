@@ -0,0 +1,163 @@
+// matter-project/analysis-core/src/distribution.rs
+/*!
+Latency distribution shape - jitter, skewness and bimodality of per-iteration timings
+
+Means and percentiles tell how slow a protocol is, not how steady it is.
+Two protocols with the same mean can differ completely: one answers in
+10ms every time, the other in 5ms nine times out of ten and in 55ms when it
+retransmits. [`LatencyDistribution::from_samples`] takes the samples in the
+order they were taken and describes their shape:
+
+- jitter is the mean absolute difference between consecutive samples, RFC
+  3550's interarrival jitter without the smoothing. Its variance is
+  reported alongside;
+- standard deviation, skewness and excess kurtosis are the bias-corrected
+  sample estimates;
+- Sarle's bimodality coefficient, (g² + 1) / (k + 3(n-1)² / ((n-2)(n-3))),
+  exceeds 5/9 when the samples probably come from two modes, such as a fast
+  path and a retransmission path. 5/9 is its value for a uniform
+  distribution. The coefficient also climbs for a handful of outliers, such
+  as warm-up iterations, so `bimodal` further needs the smaller of the two
+  groups found by splitting the sorted samples where the within-group
+  spread is least to hold at least 5% of them. A single, very skewed mode
+  can still pass (an exponential sits exactly at 5/9), so `bimodal` is a
+  hint to look at the samples, not proof.
+
+Shape figures need at least four samples and some spread; below that they
+stay zero.
+*/
+
+use serde::{Deserialize, Serialize};
+
+/// Sarle's bimodality coefficient of a uniform distribution.
+pub const BIMODALITY_THRESHOLD: f64 = 5.0 / 9.0;
+/// Share of samples the smaller mode needs before the samples count as bimodal.
+pub const MIN_MODE_SHARE: f64 = 0.05;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencyDistribution {
+    pub samples: usize,
+    pub mean_ms: f64,
+    pub std_dev_ms: f64,
+    /// Mean absolute difference between consecutive samples.
+    pub jitter_ms: f64,
+    /// Variance of the differences between consecutive samples.
+    pub jitter_variance_ms2: f64,
+    /// Positive when the tail is on the slow side.
+    pub skewness: f64,
+    /// Positive when outliers are heavier than a normal distribution's.
+    pub excess_kurtosis: f64,
+    pub bimodality_coefficient: f64,
+    /// Share of samples in the smaller group of the best two-group split.
+    pub minor_mode_share: f64,
+    pub bimodal: bool,
+}
+
+impl LatencyDistribution {
+    /// `samples` in milliseconds, in the order they were taken.
+    pub fn from_samples(samples: &[f64]) -> Self {
+        let n = samples.len();
+        if n == 0 {
+            return Self::default();
+        }
+        let nf = n as f64;
+        let mean_ms = samples.iter().sum::<f64>() / nf;
+
+        let differences: Vec<f64> = samples.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        let (jitter_ms, jitter_variance_ms2) = if differences.is_empty() {
+            (0.0, 0.0)
+        } else {
+            let count = differences.len() as f64;
+            let mean = differences.iter().sum::<f64>() / count;
+            (
+                differences.iter().map(|d| d.abs()).sum::<f64>() / count,
+                differences.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / count,
+            )
+        };
+
+        let moment = |power: i32| samples.iter().map(|x| (x - mean_ms).powi(power)).sum::<f64>() / nf;
+        let (m2, m3, m4) = (moment(2), moment(3), moment(4));
+        let std_dev_ms = if n > 1 { (m2 * nf / (nf - 1.0)).sqrt() } else { 0.0 };
+
+        let mut distribution = Self { samples: n, mean_ms, std_dev_ms, jitter_ms, jitter_variance_ms2, ..Self::default() };
+        // Relative to the mean, so identical samples read as flat despite rounding in the sum
+        if n < 4 || std_dev_ms <= f64::EPSILON * mean_ms.abs().max(1.0) {
+            return distribution;
+        }
+        let skewness = m3 / m2.powf(1.5) * (nf * (nf - 1.0)).sqrt() / (nf - 2.0);
+        let excess_kurtosis = ((nf + 1.0) * (m4 / (m2 * m2) - 3.0) + 6.0) * (nf - 1.0) / ((nf - 2.0) * (nf - 3.0));
+        let bimodality_coefficient =
+            (skewness * skewness + 1.0) / (excess_kurtosis + 3.0 * (nf - 1.0).powi(2) / ((nf - 2.0) * (nf - 3.0)));
+
+        distribution.skewness = skewness;
+        distribution.excess_kurtosis = excess_kurtosis;
+        distribution.bimodality_coefficient = bimodality_coefficient;
+        distribution.minor_mode_share = minor_mode_share(samples);
+        distribution.bimodal =
+            bimodality_coefficient > BIMODALITY_THRESHOLD && distribution.minor_mode_share >= MIN_MODE_SHARE;
+        distribution
+    }
+}
+
+/// Splits the sorted samples in two where the summed within-group squared deviation is least
+/// (one-dimensional 2-means) and returns the smaller group's share.
+fn minor_mode_share(samples: &[f64]) -> f64 {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let n = sorted.len();
+    let (mut sums, mut squares) = (vec![0.0; n + 1], vec![0.0; n + 1]);
+    for (i, x) in sorted.iter().enumerate() {
+        sums[i + 1] = sums[i] + x;
+        squares[i + 1] = squares[i] + x * x;
+    }
+    // Squared deviation of sorted[from..to] from its mean
+    let spread = |from: usize, to: usize| {
+        let (sum, count) = (sums[to] - sums[from], (to - from) as f64);
+        squares[to] - squares[from] - sum * sum / count
+    };
+    let best = (1..n).min_by(|&a, &b| (spread(0, a) + spread(a, n)).total_cmp(&(spread(0, b) + spread(b, n))));
+    best.map_or(0.0, |split| split.min(n - split) as f64 / n as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Triangular noise around 10ms, from a fixed LCG.
+    fn steady_like(count: usize) -> impl Iterator<Item = f64> {
+        let mut state = 12345u64;
+        let mut unit = move || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        (0..count).map(move |_| 10.0 + 0.2 * (unit() + unit() - 1.0))
+    }
+
+    #[test]
+    fn equal_means_with_different_shapes_are_told_apart() {
+        let steady: Vec<f64> = steady_like(400).collect();
+        // Nine fast answers, then one retransmission, repeated
+        let tailed: Vec<f64> = (0..400).map(|i| if i % 10 == 9 { 55.0 } else { 5.0 }).collect();
+        // Alternating fast and slow paths
+        let split: Vec<f64> = (0..400).map(|i| if i % 2 == 0 { 5.0 } else { 15.0 }).collect();
+
+        let [steady, tailed, split] = [steady, tailed, split].map(|samples| LatencyDistribution::from_samples(&samples));
+        for distribution in [&steady, &tailed, &split] {
+            assert!((distribution.mean_ms - 10.0).abs() < 0.05, "{:?}", distribution);
+        }
+        assert!(!steady.bimodal && steady.skewness.abs() < 0.5 && steady.jitter_ms < 0.2);
+        assert!(tailed.skewness > 2.0 && tailed.jitter_ms > 9.0);
+        assert!(split.bimodal && split.skewness.abs() < 1e-9);
+        assert_eq!(split.jitter_ms, 10.0);
+        assert_eq!(split.minor_mode_share, 0.5);
+
+        // One slow warm-up iteration is an outlier, not a second mode
+        let warm_up: Vec<f64> = std::iter::once(50.0).chain(steady_like(199)).collect();
+        let warm_up = LatencyDistribution::from_samples(&warm_up);
+        assert!(warm_up.bimodality_coefficient > BIMODALITY_THRESHOLD && !warm_up.bimodal);
+
+        let flat = LatencyDistribution::from_samples(&[3.0; 10]);
+        assert_eq!((flat.std_dev_ms, flat.skewness, flat.bimodal), (0.0, 0.0, false));
+        assert_eq!(LatencyDistribution::from_samples(&[]).samples, 0);
+    }
+}
@@ -0,0 +1,200 @@
+// matter-project/analysis-core/src/events.rs
+/*!
+Run event log - structured progress events in a file, apart from the console output
+
+The console output is written for people: emoji, dashes and numbers mixed
+into sentences. Automation that wants to follow a run reads this log
+instead. It is one JSON object per line, flushed as it is written:
+
+```text
+{"timestamp":"2025-06-23T10:00:00.000Z","elapsed_ms":0.0,"event":"run_started"}
+{"timestamp":"2025-06-23T10:00:00.120Z","elapsed_ms":120.4,"event":"phase_started","phase":"throughput"}
+{"timestamp":"2025-06-23T10:00:05.480Z","elapsed_ms":5480.9,"event":"phase_finished","phase":"throughput","outcome":"ok","duration_ms":5360.5}
+{"timestamp":"2025-06-23T10:00:06.010Z","elapsed_ms":6010.2,"event":"run_finished","outcome":"ok","detail":"../results/runs/matter_20250623T100006Z.json"}
+```
+
+A phase finishes `ok`, `failed` (it returned an error, in `detail`),
+`cancelled` (the watchdog stopped it) or `skipped` (a phase it needs failed).
+Stages run under a [`Watchdog`](crate::watchdog::Watchdog) that has the log
+are recorded by it; blocking analyzers go through [`EventLog::phase`]. A run
+that ends without [`EventLog::run_finished`] gets a `failed` `run_finished`
+when the log is dropped, so a reader always sees the run end.
+*/
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    RunStarted,
+    PhaseStarted,
+    PhaseFinished,
+    RunFinished,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Ok,
+    Failed,
+    Cancelled,
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub timestamp: DateTime<Utc>,
+    /// Since the log was opened.
+    pub elapsed_ms: f64,
+    pub event: EventKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phase: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<Outcome>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<f64>,
+    /// The error or reason for a phase that did not finish `ok`, or where the run's result was saved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug)]
+struct EventWriter {
+    file: Option<File>,
+    opened: Instant,
+    run_open: bool,
+}
+
+impl EventWriter {
+    fn write(&mut self, event: EventKind, phase: Option<&str>, outcome: Option<Outcome>, duration: Option<Duration>, detail: Option<String>) {
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        match event {
+            EventKind::RunStarted => self.run_open = true,
+            EventKind::RunFinished => self.run_open = false,
+            _ => {}
+        }
+        let event = Event {
+            timestamp: Utc::now(),
+            elapsed_ms: self.opened.elapsed().as_secs_f64() * 1000.0,
+            event,
+            phase: phase.map(str::to_string),
+            outcome,
+            duration_ms: duration.map(|d| d.as_secs_f64() * 1000.0),
+            detail,
+        };
+        let written = serde_json::to_string(&event)
+            .map_err(std::io::Error::from)
+            .and_then(|line| writeln!(file, "{}", line))
+            .and_then(|_| file.flush());
+        if let Err(e) = written {
+            eprintln!("⚠️ Event log stopped: {}", e);
+            self.file = None;
+        }
+    }
+}
+
+impl Drop for EventWriter {
+    fn drop(&mut self) {
+        if self.run_open {
+            self.write(EventKind::RunFinished, None, Some(Outcome::Failed), None, Some("run ended with an error".to_string()));
+        }
+    }
+}
+
+/// A handle to the run's event log; clones write to the same file. The default writes nothing.
+#[derive(Debug, Clone, Default)]
+pub struct EventLog {
+    writer: Option<Arc<Mutex<EventWriter>>>,
+}
+
+impl EventLog {
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Creates (or truncates) the log file.
+    pub fn create(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+        }
+        let file = File::create(path).with_context(|| format!("writing {}", path.display()))?;
+        let writer = EventWriter { file: Some(file), opened: Instant::now(), run_open: false };
+        Ok(Self { writer: Some(Arc::new(Mutex::new(writer))) })
+    }
+
+    pub fn run_started(&self) {
+        self.write(EventKind::RunStarted, None, None, None, None);
+    }
+
+    pub fn run_finished(&self, outcome: Outcome, detail: Option<String>) {
+        self.write(EventKind::RunFinished, None, Some(outcome), None, detail);
+    }
+
+    pub fn phase_started(&self, phase: &str) {
+        self.write(EventKind::PhaseStarted, Some(phase), None, None, None);
+    }
+
+    pub fn phase_finished(&self, phase: &str, outcome: Outcome, duration: Duration, detail: Option<String>) {
+        self.write(EventKind::PhaseFinished, Some(phase), Some(outcome), Some(duration), detail);
+    }
+
+    /// Runs a blocking phase between its start and finish events; its error is logged and returned.
+    pub fn phase<T>(&self, phase: &str, run: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.phase_started(phase);
+        let start = Instant::now();
+        let result = run();
+        match &result {
+            Ok(_) => self.phase_finished(phase, Outcome::Ok, start.elapsed(), None),
+            Err(e) => self.phase_finished(phase, Outcome::Failed, start.elapsed(), Some(format!("{:#}", e))),
+        }
+        result
+    }
+
+    fn write(&self, event: EventKind, phase: Option<&str>, outcome: Option<Outcome>, duration: Option<Duration>, detail: Option<String>) {
+        if let Some(writer) = &self.writer {
+            writer.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).write(event, phase, outcome, duration, detail);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_and_unfinished_runs_are_logged() {
+        let path = std::env::temp_dir().join(format!("event-log-{}.ndjson", std::process::id()));
+        let log = EventLog::create(&path).unwrap();
+        log.run_started();
+        assert_eq!(log.phase("handshakes", || Ok(3)).unwrap(), 3);
+        assert!(log.phase("replay", || Err::<(), _>(anyhow::anyhow!("socket closed"))).is_err());
+        drop(log);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let events: Vec<Event> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        let kinds: Vec<EventKind> = events.iter().map(|e| e.event).collect();
+        assert_eq!(kinds, vec![
+            EventKind::RunStarted,
+            EventKind::PhaseStarted,
+            EventKind::PhaseFinished,
+            EventKind::PhaseStarted,
+            EventKind::PhaseFinished,
+            EventKind::RunFinished,
+        ]);
+        assert_eq!(events[2].outcome, Some(Outcome::Ok));
+        assert_eq!(events[4].phase.as_deref(), Some("replay"));
+        assert_eq!(events[4].detail.as_deref(), Some("socket closed"));
+        assert_eq!(events[5].outcome, Some(Outcome::Failed));
+        assert!(!text.contains("\"detail\":null"));
+    }
+}
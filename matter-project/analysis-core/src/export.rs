@@ -0,0 +1,353 @@
+// matter-project/analysis-core/src/export.rs
+/*!
+Spreadsheet export - stored results as an Excel workbook with one sheet per OSI layer and a protocol comparison
+
+Results are loaded the same way `merge` and `query` load them. The workbook
+opens on a `Comparison` sheet: one row per metric of the four layer sections
+and `summary_metrics`, one column per protocol, each cell the mean over that
+protocol's runs. A colour scale runs across each row, green at the better end
+and red at the worse one, using the same direction rules as `diff`. Metrics
+with no known direction stay uncoloured.
+
+After it come `L4 Transport`, `L5 Session`, `L6 Presentation` and
+`L7 Application`, each with one row per run and one column per metric of
+that layer. Every metric column carries a data bar. Values are converted to
+the export's [`Units`], and each metric's unit is given next to it. Writing
+the file needs the `xlsx` feature; the tables themselves are built without it.
+*/
+
+use crate::campaign::{CampaignDataset, CampaignMerger, MergeConfig};
+use crate::diff::{flatten, Direction};
+use crate::query::QueryConfig;
+use crate::units::{Unit, Units};
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Result section and sheet name of each layer, in workbook order.
+const LAYER_SHEETS: [(&str, &str); 4] = [
+    ("osi_layer_4_transport", "L4 Transport"),
+    ("osi_layer_5_session", "L5 Session"),
+    ("osi_layer_6_presentation", "L6 Presentation"),
+    ("osi_layer_7_application", "L7 Application"),
+];
+/// Compared alongside the layers, though it gets no sheet of its own.
+const SUMMARY_SECTION: &str = "summary_metrics";
+const COMPARISON_SHEET: &str = "Comparison";
+
+#[derive(Debug, Clone)]
+pub struct ExportConfig {
+    /// Result files, directories or merged datasets; see [`QueryConfig::default_inputs`] when empty.
+    pub inputs: Vec<PathBuf>,
+    pub output: PathBuf,
+    pub units: Units,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            inputs: Vec::new(),
+            output: PathBuf::from("../results/comparison.xlsx"),
+            units: Units::default(),
+        }
+    }
+}
+
+/// One layer's metrics for every run.
+#[derive(Debug, Clone)]
+pub struct LayerSheet {
+    pub name: String,
+    /// Metric paths below the layer section, one column each.
+    pub metrics: Vec<String>,
+    /// The unit of each metric column, when its name gives one.
+    pub units: Vec<Option<Unit>>,
+    pub rows: Vec<LayerRow>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LayerRow {
+    pub key: String,
+    pub protocol: String,
+    pub timestamp: Option<String>,
+    /// By metric column; `None` where the run does not report the metric.
+    pub values: Vec<Option<f64>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ComparisonRow {
+    pub metric: String,
+    pub unit: Option<Unit>,
+    pub direction: Direction,
+    /// Mean over each protocol's runs, by protocol column.
+    pub means: Vec<Option<f64>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportTables {
+    pub protocols: Vec<String>,
+    pub comparison: Vec<ComparisonRow>,
+    pub layers: Vec<LayerSheet>,
+}
+
+impl ExportTables {
+    pub fn of(dataset: &CampaignDataset, units: &Units) -> Self {
+        let flattened: Vec<BTreeMap<String, f64>> = dataset
+            .runs
+            .iter()
+            .map(|run| {
+                let mut metrics = BTreeMap::new();
+                flatten(&run.result, String::new(), &mut metrics);
+                metrics
+            })
+            .collect();
+
+        let layers = LAYER_SHEETS
+            .iter()
+            .map(|(section, name)| {
+                let prefix = format!("{}.", section);
+                let mut metrics: Vec<String> = flattened
+                    .iter()
+                    .flat_map(|run| run.keys())
+                    .filter_map(|path| path.strip_prefix(&prefix))
+                    .map(str::to_string)
+                    .collect();
+                metrics.sort();
+                metrics.dedup();
+                let column_units = metrics.iter().map(|metric| units.unit_for(metric)).collect();
+                let rows = dataset
+                    .runs
+                    .iter()
+                    .zip(&flattened)
+                    .map(|(run, values)| LayerRow {
+                        key: run.key.clone(),
+                        protocol: run.provenance.protocol.clone(),
+                        timestamp: run.provenance.timestamp(),
+                        values: metrics
+                            .iter()
+                            .map(|metric| values.get(&format!("{}{}", prefix, metric)).map(|value| units.convert(metric, *value).0))
+                            .collect(),
+                    })
+                    .collect();
+                LayerSheet { name: name.to_string(), metrics, units: column_units, rows }
+            })
+            .collect();
+
+        let protocols = dataset.protocols();
+        let compared = |path: &str| {
+            LAYER_SHEETS
+                .iter()
+                .map(|(section, _)| *section)
+                .chain([SUMMARY_SECTION])
+                .any(|section| path.strip_prefix(section).is_some_and(|rest| rest.starts_with('.')))
+        };
+        // Sum and count per metric and protocol column
+        let mut totals: BTreeMap<&str, Vec<(f64, usize)>> = BTreeMap::new();
+        for (run, values) in dataset.runs.iter().zip(&flattened) {
+            let Some(column) = protocols.iter().position(|p| *p == run.provenance.protocol) else {
+                continue;
+            };
+            for (metric, value) in values.iter().filter(|(metric, _)| compared(metric)) {
+                let cells = totals.entry(metric).or_insert_with(|| vec![(0.0, 0); protocols.len()]);
+                cells[column].0 += value;
+                cells[column].1 += 1;
+            }
+        }
+        let comparison = totals
+            .into_iter()
+            .map(|(metric, cells)| ComparisonRow {
+                metric: metric.to_string(),
+                unit: units.unit_for(metric),
+                direction: Direction::of(metric),
+                means: cells
+                    .into_iter()
+                    .map(|(sum, count)| (count > 0).then(|| units.convert(metric, sum / count as f64).0))
+                    .collect(),
+            })
+            .collect();
+
+        ExportTables { protocols, comparison, layers }
+    }
+}
+
+pub struct ResultExport {
+    config: ExportConfig,
+}
+
+impl ResultExport {
+    pub fn new(config: ExportConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn run(&self) -> Result<ExportTables> {
+        let inputs = if self.config.inputs.is_empty() { QueryConfig::default_inputs() } else { self.config.inputs.clone() };
+        let dataset = CampaignMerger::new(MergeConfig { inputs, ..MergeConfig::default() }).merge()?;
+        if dataset.runs.is_empty() {
+            return Err(anyhow!("no results to export"));
+        }
+        let tables = ExportTables::of(&dataset, &self.config.units);
+
+        println!("\n📗 Exporting Results to Excel ({} runs)", dataset.runs.len());
+        println!("----------------------------------------");
+        for skipped in &dataset.skipped {
+            println!("⚠️ Skipped {}: {}", skipped.file, skipped.reason);
+        }
+        println!("✅ {}: {} metrics across {}", COMPARISON_SHEET, tables.comparison.len(), tables.protocols.join(", "));
+        for layer in &tables.layers {
+            println!("✅ {}: {} metrics", layer.name, layer.metrics.len());
+        }
+
+        write_workbook(&tables, &self.config.output)?;
+        println!("✅ Workbook saved to: {}", self.config.output.display());
+        Ok(tables)
+    }
+}
+
+#[cfg(feature = "xlsx")]
+fn write_workbook(tables: &ExportTables, path: &Path) -> Result<()> {
+    use rust_xlsxwriter::{ConditionalFormat3ColorScale, ConditionalFormatDataBar, Format, Workbook};
+
+    const GREEN: &str = "#63BE7B";
+    const YELLOW: &str = "#FFEB84";
+    const RED: &str = "#F8696B";
+
+    let mut workbook = Workbook::new();
+    let header = Format::new().set_bold().set_background_color("#D9E1F2");
+
+    let sheet = workbook.add_worksheet();
+    sheet.set_name(COMPARISON_SHEET)?;
+    sheet.write_string_with_format(0, 0, "Metric", &header)?;
+    sheet.write_string_with_format(0, 1, "Unit", &header)?;
+    sheet.write_string_with_format(0, 2, "Better", &header)?;
+    for (column, protocol) in tables.protocols.iter().enumerate() {
+        sheet.write_string_with_format(0, 3 + column as u16, protocol, &header)?;
+    }
+    let last_column = 2 + tables.protocols.len() as u16;
+    for (index, row) in tables.comparison.iter().enumerate() {
+        let line = 1 + index as u32;
+        sheet.write_string(line, 0, &row.metric)?;
+        let (better, colors) = match row.direction {
+            Direction::LowerIsBetter => ("lower", Some((GREEN, RED))),
+            Direction::HigherIsBetter => ("higher", Some((RED, GREEN))),
+            Direction::Unknown => ("", None),
+        };
+        sheet.write_string(line, 1, row.unit.map(|unit| unit.to_string()).unwrap_or_default())?;
+        sheet.write_string(line, 2, better)?;
+        for (column, mean) in row.means.iter().enumerate() {
+            if let Some(mean) = mean {
+                sheet.write_number(line, 3 + column as u16, *mean)?;
+            }
+        }
+        if let (Some((lowest, highest)), true) = (colors, tables.protocols.len() > 1) {
+            let scale = ConditionalFormat3ColorScale::new()
+                .set_minimum_color(lowest)
+                .set_midpoint_color(YELLOW)
+                .set_maximum_color(highest);
+            sheet.add_conditional_format(line, 3, line, last_column, &scale)?;
+        }
+    }
+    sheet.set_freeze_panes(1, 3)?;
+    sheet.autofit();
+
+    for layer in &tables.layers {
+        let sheet = workbook.add_worksheet();
+        sheet.set_name(&layer.name)?;
+        for (column, title) in ["Run", "Protocol", "Timestamp"].into_iter().enumerate() {
+            sheet.write_string_with_format(0, column as u16, title, &header)?;
+        }
+        for (column, (metric, unit)) in layer.metrics.iter().zip(&layer.units).enumerate() {
+            let title = match unit {
+                Some(unit) => format!("{} ({})", metric, unit),
+                None => metric.clone(),
+            };
+            sheet.write_string_with_format(0, 3 + column as u16, &title, &header)?;
+        }
+        for (index, row) in layer.rows.iter().enumerate() {
+            let line = 1 + index as u32;
+            sheet.write_string(line, 0, &row.key)?;
+            sheet.write_string(line, 1, &row.protocol)?;
+            sheet.write_string(line, 2, row.timestamp.as_deref().unwrap_or_default())?;
+            for (column, value) in row.values.iter().enumerate() {
+                if let Some(value) = value {
+                    sheet.write_number(line, 3 + column as u16, *value)?;
+                }
+            }
+        }
+        if !layer.rows.is_empty() {
+            for column in 0..layer.metrics.len() as u16 {
+                sheet.add_conditional_format(1, 3 + column, layer.rows.len() as u32, 3 + column, &ConditionalFormatDataBar::new())?;
+            }
+        }
+        sheet.set_freeze_panes(1, 3)?;
+        sheet.autofit();
+    }
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    workbook.save(path)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "xlsx"))]
+fn write_workbook(_tables: &ExportTables, path: &Path) -> Result<()> {
+    Err(anyhow!("cannot write {}: built without the `xlsx` feature", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::campaign::{CampaignRun, RunProvenance};
+    use serde_json::json;
+
+    fn run(protocol: &str, key: &str, result: serde_json::Value) -> CampaignRun {
+        CampaignRun {
+            key: key.to_string(),
+            provenance: RunProvenance {
+                source_file: format!("{}.json", key),
+                sha256: String::new(),
+                protocol: protocol.to_string(),
+                analysis_timestamp: Some("2025-06-23T10:00:00Z".to_string()),
+                saved_at: None,
+                host: None,
+                device_class: None,
+                file_modified: None,
+            },
+            result,
+        }
+    }
+
+    #[test]
+    fn tables_split_layers_and_average_per_protocol() {
+        let dataset = CampaignDataset {
+            format_version: "1".to_string(),
+            merged_at: chrono::Utc::now(),
+            runs: vec![
+                run("Matter", "matter/1", json!({"osi_layer_5_session": {"commissioning_time_ms": 400.0}, "summary_metrics": {"efficiency_score": 0.6}})),
+                run("Matter", "matter/2", json!({"osi_layer_5_session": {"commissioning_time_ms": 600.0}, "summary_metrics": {"efficiency_score": 0.8}})),
+                run("LwM2M", "lwm2m/1", json!({"osi_layer_5_session": {"registration_time_ms": 250.0}, "osi_layer_7_application": {"discovery_time_ms": 3.0}})),
+            ],
+            skipped: Vec::new(),
+        };
+        let tables = ExportTables::of(&dataset, &Units::default());
+
+        assert_eq!(tables.protocols, vec!["LwM2M", "Matter"]);
+        let session = &tables.layers[1];
+        assert_eq!(session.name, "L5 Session");
+        assert_eq!(session.metrics, vec!["commissioning_time_ms", "registration_time_ms"]);
+        assert_eq!(session.rows[2].values, vec![None, Some(250.0)]);
+        assert!(tables.layers[0].metrics.is_empty());
+
+        let metrics: Vec<&str> = tables.comparison.iter().map(|row| row.metric.as_str()).collect();
+        assert_eq!(metrics, vec![
+            "osi_layer_5_session.commissioning_time_ms",
+            "osi_layer_5_session.registration_time_ms",
+            "osi_layer_7_application.discovery_time_ms",
+            "summary_metrics.efficiency_score",
+        ]);
+        assert_eq!(tables.comparison[0].means, vec![None, Some(500.0)]);
+        assert_eq!(tables.comparison[0].unit.map(|unit| unit.to_string()).as_deref(), Some("ms"));
+        assert_eq!(tables.comparison[0].direction, Direction::LowerIsBetter);
+        assert_eq!(tables.comparison[3].direction, Direction::HigherIsBetter);
+        assert!((tables.comparison[3].means[1].unwrap() - 0.7).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,370 @@
+// matter-project/analysis-core/src/impairment.rs
+/*!
+Impairment profiles - named cellular and satellite links, and a matrix run of every protocol across them
+
+A profile gives the netem settings for the uplink (device to network) and
+the downlink separately. Constrained links are asymmetric, and their loss
+comes in bursts rather than packet by packet. `--impairment <profile>`
+applies one to a single run in a network namespace. [`ImpairmentMatrix`]
+runs the analyzer once for every protocol × profile cell, each in its own
+namespace. It always includes the unimpaired `none` profile and compares
+every cell with that protocol's `none` run. The result is a
+[`DegradationSurface`]: how far each metric in the four OSI-layer sections
+moved under each profile, so protocols can be compared by how they degrade
+rather than by their best case.
+
+Figures are typical published values, not measurements of a particular
+network: one-way delay and jitter, loss with its burst correlation, and the
+rate each way.
+*/
+
+use crate::diff::{diff_results, Direction};
+use crate::netns::{Impairment, Netem};
+use crate::plugin::{installed, REQUIRED_SECTIONS};
+use crate::results_store::{ResultsConfig, ResultsStore};
+use crate::schema::load_result;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The profile every other one is compared with.
+pub const REFERENCE_PROFILE: &str = "none";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImpairmentProfile {
+    pub name: String,
+    pub description: String,
+    pub uplink: Netem,
+    pub downlink: Netem,
+}
+
+impl ImpairmentProfile {
+    /// What the namespace applies; `None` for the unimpaired profile.
+    pub fn impairment(&self) -> Option<Impairment> {
+        if self.uplink == Netem::default() && self.downlink == Netem::default() {
+            return None;
+        }
+        Some(Impairment { profile: Some(self.name.clone()), uplink: self.uplink.clone(), downlink: self.downlink.clone() })
+    }
+}
+
+pub fn profiles() -> Vec<ImpairmentProfile> {
+    let netem = |delay_ms, jitter_ms, loss_percent, loss_correlation_percent, rate_kbit| Netem {
+        delay_ms,
+        jitter_ms,
+        loss_percent,
+        loss_correlation_percent,
+        rate_kbit: Some(rate_kbit),
+        ..Netem::default()
+    };
+    vec![
+        ImpairmentProfile {
+            name: REFERENCE_PROFILE.to_string(),
+            description: "No impairment, the reference for the others".to_string(),
+            uplink: Netem::default(),
+            downlink: Netem::default(),
+        },
+        ImpairmentProfile {
+            name: "lte-m".to_string(),
+            description: "LTE-M (Cat-M1, half duplex): ~100ms RTT, 375/300 kbit/s up/down".to_string(),
+            uplink: netem(50.0, 15.0, 0.5, 0.0, 375),
+            downlink: netem(50.0, 15.0, 0.5, 0.0, 300),
+        },
+        ImpairmentProfile {
+            name: "nb-iot".to_string(),
+            description: "NB-IoT (Cat-NB1): ~1.6s RTT with wide jitter, 62/27 kbit/s up/down, bursty loss".to_string(),
+            uplink: netem(800.0, 300.0, 1.0, 25.0, 62),
+            downlink: netem(800.0, 300.0, 1.0, 25.0, 27),
+        },
+        ImpairmentProfile {
+            name: "geo-satellite".to_string(),
+            description: "GEO satellite: ~600ms RTT, 2/20 Mbit/s up/down, sporadic burst loss".to_string(),
+            uplink: netem(300.0, 10.0, 0.5, 50.0, 2000),
+            downlink: netem(300.0, 10.0, 0.5, 50.0, 20_000),
+        },
+    ]
+}
+
+pub fn profile(name: &str) -> Result<ImpairmentProfile> {
+    let known = profiles();
+    let names: Vec<String> = known.iter().map(|p| p.name.clone()).collect();
+    known
+        .into_iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| anyhow!("unknown impairment profile {}; known profiles: {}", name, names.join(", ")))
+}
+
+#[derive(Debug, Clone)]
+pub struct MatrixConfig {
+    /// Profile names; `none` is added when missing. Empty means every profile.
+    pub profiles: Vec<String>,
+    /// Protocols to run. Empty means the built-in one plus every installed plugin.
+    pub protocols: Vec<String>,
+    pub plugin_dir: PathBuf,
+    /// Each cell's run goes to `<output_dir>/<protocol>/<profile>`, the surface to `degradation_surface.json`.
+    pub output_dir: PathBuf,
+    /// Changes for the worse beyond this many percent count as regressions.
+    pub threshold_percent: f64,
+    /// Passed on to every run, e.g. `--throughput`.
+    pub analyzer_args: Vec<String>,
+}
+
+impl Default for MatrixConfig {
+    fn default() -> Self {
+        Self {
+            profiles: Vec::new(),
+            protocols: Vec::new(),
+            plugin_dir: PathBuf::from("plugins"),
+            output_dir: PathBuf::from("../results/matrix"),
+            threshold_percent: 5.0,
+            analyzer_args: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SurfacePoint {
+    pub value: Option<f64>,
+    /// Relative to the protocol's unimpaired run.
+    pub change_percent: Option<f64>,
+    pub regression: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatrixCell {
+    pub protocol: String,
+    pub profile: String,
+    pub result_file: Option<String>,
+    /// Why the cell has no result.
+    pub error: Option<String>,
+    pub regressions: usize,
+    pub improvements: usize,
+    /// Metrics of the OSI-layer sections, by JSON path.
+    pub metrics: BTreeMap<String, SurfacePoint>,
+}
+
+impl MatrixCell {
+    fn new(protocol: &str, profile: &str) -> Self {
+        Self {
+            protocol: protocol.to_string(),
+            profile: profile.to_string(),
+            result_file: None,
+            error: None,
+            regressions: 0,
+            improvements: 0,
+            metrics: BTreeMap::new(),
+        }
+    }
+
+    fn failed(protocol: &str, profile: &str, error: String) -> Self {
+        Self { error: Some(error), ..Self::new(protocol, profile) }
+    }
+
+    /// The metric that got worst, as (path, change in percent).
+    pub fn worst(&self) -> Option<(&str, f64)> {
+        self.metrics
+            .iter()
+            .filter(|(_, point)| point.regression)
+            .filter_map(|(metric, point)| point.change_percent.map(|change| (metric.as_str(), change)))
+            .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DegradationSurface {
+    pub generated_at: DateTime<Utc>,
+    pub threshold_percent: f64,
+    pub profiles: Vec<ImpairmentProfile>,
+    pub protocols: Vec<String>,
+    /// Protocol-major, profiles in the order given.
+    pub cells: Vec<MatrixCell>,
+}
+
+/// Compares one cell's result with its protocol's unimpaired result.
+pub fn surface_cell(protocol: &str, profile: &str, reference: &Value, result: &Value, threshold_percent: f64) -> MatrixCell {
+    let report = diff_results(reference, result, threshold_percent);
+    let mut cell = MatrixCell::new(protocol, profile);
+    for change in report.changes {
+        if !REQUIRED_SECTIONS.iter().any(|section| change.metric.starts_with(section)) || change.direction == Direction::Unknown {
+            continue;
+        }
+        cell.regressions += change.regression as usize;
+        let better = match change.direction {
+            Direction::LowerIsBetter => change.new < change.old,
+            _ => change.new > change.old,
+        };
+        cell.improvements += (!change.regression && better && change.change_percent.is_some_and(|p| p.abs() > threshold_percent)) as usize;
+        cell.metrics.insert(
+            change.metric,
+            SurfacePoint { value: change.new, change_percent: change.change_percent, regression: change.regression },
+        );
+    }
+    cell
+}
+
+pub struct ImpairmentMatrix {
+    config: MatrixConfig,
+    built_in: String,
+}
+
+impl ImpairmentMatrix {
+    /// `built_in` is the protocol the analyzer measures without `--plugin`.
+    pub fn new(config: MatrixConfig, built_in: &str) -> Self {
+        Self { config, built_in: built_in.to_lowercase() }
+    }
+
+    /// Runs `analyzer` for every cell, sequentially so cells do not compete for the CPU, and saves the surface.
+    pub fn run(&self, analyzer: &Path) -> Result<DegradationSurface> {
+        let profiles = self.profiles()?;
+        let protocols = self.protocols()?;
+        std::fs::create_dir_all(&self.config.output_dir)
+            .with_context(|| format!("creating {}", self.config.output_dir.display()))?;
+        println!("\n🛰️ Impairment matrix: {} protocols × {} profiles", protocols.len(), profiles.len());
+        println!("------------------------------------------------");
+
+        let mut cells = Vec::new();
+        for protocol in &protocols {
+            let mut reference = None;
+            for profile in &profiles {
+                let cell = match self.run_cell(analyzer, protocol, &profile.name) {
+                    Ok((file, result)) if profile.name == REFERENCE_PROFILE => {
+                        let mut cell = surface_cell(protocol, &profile.name, &result, &result, self.config.threshold_percent);
+                        cell.result_file = Some(file);
+                        reference = Some(result);
+                        cell
+                    }
+                    Ok((file, result)) => match &reference {
+                        Some(reference) => {
+                            let mut cell = surface_cell(protocol, &profile.name, reference, &result, self.config.threshold_percent);
+                            cell.result_file = Some(file);
+                            cell
+                        }
+                        None => MatrixCell::failed(protocol, &profile.name, "no unimpaired run to compare with".to_string()),
+                    },
+                    Err(e) => MatrixCell::failed(protocol, &profile.name, format!("{:#}", e)),
+                };
+                match (&cell.error, cell.worst()) {
+                    (Some(error), _) => println!("❌ {} / {}: {}", protocol, profile.name, error),
+                    (None, Some((metric, change))) => println!("📉 {} / {}: {} regressions, worst {} ({:+.1}%)",
+                                                              protocol, profile.name, cell.regressions, metric, change),
+                    (None, None) => println!("✅ {} / {}: no regressions", protocol, profile.name),
+                }
+                cells.push(cell);
+            }
+        }
+
+        let surface = DegradationSurface {
+            generated_at: Utc::now(),
+            threshold_percent: self.config.threshold_percent,
+            profiles,
+            protocols,
+            cells,
+        };
+        print_surface(&surface);
+        let path = self.config.output_dir.join("degradation_surface.json");
+        std::fs::write(&path, serde_json::to_string_pretty(&surface)?).with_context(|| format!("writing {}", path.display()))?;
+        println!("\n✅ Degradation surface saved to: {}", path.display());
+        Ok(surface)
+    }
+
+    fn profiles(&self) -> Result<Vec<ImpairmentProfile>> {
+        if self.config.profiles.is_empty() {
+            return Ok(profiles());
+        }
+        let mut selected = vec![profile(REFERENCE_PROFILE)?];
+        for name in &self.config.profiles {
+            let profile = profile(name)?;
+            if !selected.iter().any(|p| p.name == profile.name) {
+                selected.push(profile);
+            }
+        }
+        Ok(selected)
+    }
+
+    fn protocols(&self) -> Result<Vec<String>> {
+        if !self.config.protocols.is_empty() {
+            return Ok(self.config.protocols.clone());
+        }
+        let mut protocols = vec![self.built_in.clone()];
+        protocols.extend(installed(&self.config.plugin_dir)?.into_iter().map(|(name, _)| name));
+        Ok(protocols)
+    }
+
+    /// Runs one cell into its own results directory and returns its result file and result.
+    fn run_cell(&self, analyzer: &Path, protocol: &str, profile: &str) -> Result<(String, Value)> {
+        let dir = self.config.output_dir.join(protocol).join(profile);
+        std::fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+        let log_path = dir.join("analyzer.log");
+        let log = std::fs::File::create(&log_path).with_context(|| format!("creating {}", log_path.display()))?;
+
+        let mut command = Command::new(analyzer);
+        if !protocol.eq_ignore_ascii_case(&self.built_in) {
+            command.arg("--plugin").arg(protocol).arg("--plugin-dir").arg(&self.config.plugin_dir);
+        }
+        command
+            .args(["--netns", "--impairment", profile, "--tag", &format!("impairment={}", profile), "--results-dir"])
+            .arg(&dir)
+            .args(&self.config.analyzer_args)
+            .stdout(Stdio::from(log.try_clone()?))
+            .stderr(Stdio::from(log));
+        let status = command.status().with_context(|| format!("starting {}", analyzer.display()))?;
+        if !status.success() {
+            return Err(anyhow!("analyzer exited with {}; see {}", status, log_path.display()));
+        }
+
+        let store = ResultsStore::new(ResultsConfig { dir: dir.clone(), ..ResultsConfig::default() });
+        let run = store.index()?.runs.pop().ok_or_else(|| anyhow!("analyzer saved no result; see {}", log_path.display()))?;
+        let file = dir.join("runs").join(&run.file);
+        Ok((file.display().to_string(), load_result(&file)?))
+    }
+}
+
+/// Regressions per cell, one row per protocol.
+fn print_surface(surface: &DegradationSurface) {
+    println!("\n📉 Regressions beyond {}% against {}", surface.threshold_percent, REFERENCE_PROFILE);
+    let header: Vec<String> = surface.profiles.iter().map(|p| format!("{:>14}", p.name)).collect();
+    println!("{:<16}{}", "protocol", header.join(""));
+    for protocol in &surface.protocols {
+        let row: Vec<String> = surface
+            .cells
+            .iter()
+            .filter(|cell| &cell.protocol == protocol)
+            .map(|cell| format!("{:>14}", if cell.error.is_some() { "failed".to_string() } else { cell.regressions.to_string() }))
+            .collect();
+        println!("{:<16}{}", protocol, row.join(""));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn cells_are_compared_with_the_unimpaired_run() {
+        assert!(profile(REFERENCE_PROFILE).unwrap().impairment().is_none());
+        let nb_iot = profile("NB-IoT").unwrap().impairment().unwrap();
+        assert!(nb_iot.uplink.rate_kbit > nb_iot.downlink.rate_kbit);
+        assert!(profile("wifi").unwrap_err().to_string().contains("geo-satellite"));
+
+        let reference = json!({
+            "osi_layer_4_transport": {"udp_discovery_time_ms": 10.0, "efficiency_score": 0.8},
+            "osi_layer_5_session": {"commissioning_time_ms": 100.0},
+            "test_environment": {"total_memory_mb": 1024}
+        });
+        let impaired = json!({
+            "osi_layer_4_transport": {"udp_discovery_time_ms": 1610.0, "efficiency_score": 0.9},
+            "osi_layer_5_session": {"commissioning_time_ms": 102.0},
+            "test_environment": {"total_memory_mb": 2048}
+        });
+        let cell = surface_cell("matter", "nb-iot", &reference, &impaired, 5.0);
+        assert_eq!((cell.regressions, cell.improvements), (1, 1));
+        assert_eq!(cell.metrics.len(), 3);
+        assert_eq!(cell.worst(), Some(("osi_layer_4_transport.udp_discovery_time_ms", 16000.0)));
+        assert_eq!(surface_cell("matter", REFERENCE_PROFILE, &reference, &reference, 5.0).regressions, 0);
+    }
+}
@@ -0,0 +1,319 @@
+// matter-project/analysis-core/src/integrity.rs
+/*!
+Result integrity - SHA-256 checksums and Ed25519 signatures for result files
+
+Each protected file gets sidecars next to it: `<file>.sha256` in the
+`sha256sum` format, so `sha256sum -c` works without this tool, and, when
+signed, `<file>.sig` holding the analyzer name and version, the file's
+SHA-256, the signing time, the public key and the Ed25519 signature over
+those fields. Verification recomputes the hash and checks the signature;
+`--public-key` additionally pins which key must have signed. Keys are
+PKCS#8 files created with `integrity keygen`.
+*/
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use ring::{digest, rand};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Sidecar extensions written next to a protected file.
+pub const SIDECAR_EXTENSIONS: [&str; 2] = ["sha256", "sig"];
+/// Environment variable read when `--key` is not given.
+pub const SIGNING_KEY_ENV: &str = "ANALYZER_SIGNING_KEY";
+
+#[derive(Debug, Clone)]
+pub enum IntegrityCommand {
+    /// Write a new PKCS#8 Ed25519 key.
+    Keygen,
+    Checksum,
+    Sign,
+    Verify,
+}
+
+#[derive(Debug, Clone)]
+pub struct IntegrityConfig {
+    pub command: IntegrityCommand,
+    pub files: Vec<PathBuf>,
+    /// Signing key for `sign` and `keygen`; falls back to `$ANALYZER_SIGNING_KEY`.
+    pub key: Option<PathBuf>,
+    /// Hex Ed25519 public key that signatures must come from.
+    pub public_key: Option<String>,
+}
+
+impl Default for IntegrityConfig {
+    fn default() -> Self {
+        Self {
+            command: IntegrityCommand::Verify,
+            files: Vec::new(),
+            key: None,
+            public_key: None,
+        }
+    }
+}
+
+impl IntegrityConfig {
+    pub fn key_path(&self) -> Result<PathBuf> {
+        self.key
+            .clone()
+            .or_else(|| std::env::var_os(SIGNING_KEY_ENV).map(PathBuf::from))
+            .ok_or_else(|| anyhow!("no signing key: pass --key <path> or set {}", SIGNING_KEY_ENV))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultSignature {
+    pub algorithm: String,
+    pub analyzer: String,
+    pub analyzer_version: String,
+    pub sha256: String,
+    pub signed_at: DateTime<Utc>,
+    pub public_key: String,
+    pub signature: String,
+}
+
+impl ResultSignature {
+    /// The bytes covered by the signature.
+    fn message(&self) -> String {
+        format!("{}\n{}\n{}\n{}\n{}", self.algorithm, self.analyzer, self.analyzer_version, self.sha256, self.signed_at.to_rfc3339())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Verification {
+    pub file: String,
+    pub checksum_ok: Option<bool>,
+    pub signature_ok: Option<bool>,
+    /// Analyzer version that signed the file.
+    pub signed_by: Option<String>,
+    pub problem: Option<String>,
+}
+
+impl Verification {
+    pub fn ok(&self) -> bool {
+        self.problem.is_none()
+    }
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    hex(digest::digest(&digest::SHA256, bytes).as_ref())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Result<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return Err(anyhow!("invalid hex: {}", text));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|_| anyhow!("invalid hex: {}", text)))
+        .collect()
+}
+
+/// `<file>.<extension>`, keeping the file's own extension.
+pub fn sidecar(file: &Path, extension: &str) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+fn file_name(file: &Path) -> String {
+    file.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// Creates a new signing key; refuses to overwrite an existing one.
+pub fn generate_key(path: &Path) -> Result<String> {
+    if path.exists() {
+        return Err(anyhow!("{} already exists; not overwriting a signing key", path.display()));
+    }
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rand::SystemRandom::new()).map_err(|_| anyhow!("key generation failed"))?;
+    let key = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).map_err(|_| anyhow!("generated key does not parse"))?;
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, pkcs8.as_ref()).with_context(|| format!("writing {}", path.display()))?;
+    Ok(hex(key.public_key().as_ref()))
+}
+
+pub fn load_key(path: &Path) -> Result<Ed25519KeyPair> {
+    let pkcs8 = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    Ed25519KeyPair::from_pkcs8(&pkcs8).map_err(|_| anyhow!("{} is not a PKCS#8 Ed25519 key", path.display()))
+}
+
+/// Writes `<file>.sha256` and returns the hash.
+pub fn write_checksum(file: &Path) -> Result<String> {
+    let bytes = std::fs::read(file).with_context(|| format!("reading {}", file.display()))?;
+    let sha256 = sha256_hex(&bytes);
+    let path = sidecar(file, "sha256");
+    std::fs::write(&path, format!("{}  {}\n", sha256, file_name(file))).with_context(|| format!("writing {}", path.display()))?;
+    Ok(sha256)
+}
+
+/// Writes the checksum and `<file>.sig`.
+pub fn sign_file(file: &Path, key: &Ed25519KeyPair) -> Result<ResultSignature> {
+    let mut signature = ResultSignature {
+        algorithm: "ed25519".to_string(),
+        analyzer: env!("CARGO_PKG_NAME").to_string(),
+        analyzer_version: env!("CARGO_PKG_VERSION").to_string(),
+        sha256: write_checksum(file)?,
+        signed_at: Utc::now(),
+        public_key: hex(key.public_key().as_ref()),
+        signature: String::new(),
+    };
+    signature.signature = hex(key.sign(signature.message().as_bytes()).as_ref());
+    let path = sidecar(file, "sig");
+    std::fs::write(&path, serde_json::to_string_pretty(&signature)?).with_context(|| format!("writing {}", path.display()))?;
+    Ok(signature)
+}
+
+/// Checks whichever sidecars exist; a file with neither fails.
+pub fn verify_file(file: &Path, public_key: Option<&str>) -> Verification {
+    let mut verification = Verification {
+        file: file.display().to_string(),
+        checksum_ok: None,
+        signature_ok: None,
+        signed_by: None,
+        problem: None,
+    };
+    if let Err(e) = check(file, public_key, &mut verification) {
+        verification.problem = Some(format!("{:#}", e));
+    }
+    verification
+}
+
+fn check(file: &Path, public_key: Option<&str>, verification: &mut Verification) -> Result<()> {
+    let sha256 = sha256_hex(&std::fs::read(file).with_context(|| format!("reading {}", file.display()))?);
+    let checksum_path = sidecar(file, "sha256");
+    let signature_path = sidecar(file, "sig");
+    if !checksum_path.exists() && !signature_path.exists() {
+        return Err(anyhow!("no .sha256 or .sig next to the file"));
+    }
+
+    if checksum_path.exists() {
+        let recorded = std::fs::read_to_string(&checksum_path)?;
+        let recorded = recorded.split_whitespace().next().unwrap_or_default().to_ascii_lowercase();
+        verification.checksum_ok = Some(recorded == sha256);
+        if recorded != sha256 {
+            return Err(anyhow!("content does not match {}", checksum_path.display()));
+        }
+    }
+
+    if !signature_path.exists() {
+        if public_key.is_some() {
+            return Err(anyhow!("a public key was given but the file is not signed"));
+        }
+        return Ok(());
+    }
+    let json = std::fs::read_to_string(&signature_path)?;
+    let signature: ResultSignature = serde_json::from_str(&json).with_context(|| format!("parsing {}", signature_path.display()))?;
+    verification.signed_by = Some(format!("{} {}", signature.analyzer, signature.analyzer_version));
+    if signature.algorithm != "ed25519" {
+        return Err(anyhow!("unsupported signature algorithm {}", signature.algorithm));
+    }
+    if let Some(expected) = public_key {
+        if !expected.eq_ignore_ascii_case(&signature.public_key) {
+            return Err(anyhow!("signed by {}, not the expected key", signature.public_key));
+        }
+    }
+    let valid = UnparsedPublicKey::new(&ED25519, unhex(&signature.public_key)?)
+        .verify(signature.message().as_bytes(), &unhex(&signature.signature)?)
+        .is_ok();
+    verification.signature_ok = Some(valid && signature.sha256 == sha256);
+    if !valid {
+        return Err(anyhow!("signature does not verify"));
+    }
+    if signature.sha256 != sha256 {
+        return Err(anyhow!("content differs from the signed SHA-256"));
+    }
+    Ok(())
+}
+
+pub struct IntegrityTool {
+    config: IntegrityConfig,
+}
+
+impl IntegrityTool {
+    pub fn new(config: IntegrityConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn run(&self) -> Result<()> {
+        match self.config.command {
+            IntegrityCommand::Keygen => {
+                let path = self.config.key_path()?;
+                let public_key = generate_key(&path)?;
+                println!("\n🔑 Signing key written to {}", path.display());
+                println!("------------------------------------------------");
+                println!("✅ Public key: {}", public_key);
+            }
+            IntegrityCommand::Checksum => {
+                println!("\n🔒 Checksumming {} files", self.config.files.len());
+                println!("------------------------------------------------");
+                for file in &self.config.files {
+                    println!("✅ {}: {}", file.display(), write_checksum(file)?);
+                }
+            }
+            IntegrityCommand::Sign => {
+                let key = load_key(&self.config.key_path()?)?;
+                println!("\n🔏 Signing {} files with {}", self.config.files.len(), hex(key.public_key().as_ref()));
+                println!("------------------------------------------------");
+                for file in &self.config.files {
+                    let signature = sign_file(file, &key)?;
+                    println!("✅ {}: {}", file.display(), signature.sha256);
+                }
+            }
+            IntegrityCommand::Verify => {
+                println!("\n🔎 Verifying {} files", self.config.files.len());
+                println!("------------------------------------------------");
+                let mut failures = 0;
+                for file in &self.config.files {
+                    let verification = verify_file(file, self.config.public_key.as_deref());
+                    match (&verification.problem, &verification.signed_by) {
+                        (Some(problem), _) => {
+                            failures += 1;
+                            println!("❌ {}: {}", verification.file, problem);
+                        }
+                        (None, Some(signer)) => println!("✅ {}: signed by {}", verification.file, signer),
+                        (None, None) => println!("✅ {}: checksum matches (unsigned)", verification.file),
+                    }
+                }
+                if failures > 0 {
+                    return Err(anyhow!("{} of {} files failed verification", failures, self.config.files.len()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_file_verifies_until_modified() {
+        let dir = std::env::temp_dir().join(format!("integrity-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("matter_real_analysis.json");
+        std::fs::write(&file, r#"{"protocol_name":"Matter"}"#).unwrap();
+        let public_key = generate_key(&dir.join("key.pk8")).unwrap();
+        assert!(generate_key(&dir.join("key.pk8")).is_err());
+
+        sign_file(&file, &load_key(&dir.join("key.pk8")).unwrap()).unwrap();
+        let verification = verify_file(&file, Some(&public_key));
+        assert!(verification.ok(), "{:?}", verification.problem);
+        assert_eq!(verification.signature_ok, Some(true));
+        assert!(!verify_file(&file, Some(&"00".repeat(32))).ok());
+
+        std::fs::write(&file, r#"{"protocol_name":"Matter","tampered":true}"#).unwrap();
+        let verification = verify_file(&file, None);
+        assert_eq!(verification.checksum_ok, Some(false));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,39 @@
+// matter-project/analysis-core/src/lib.rs
+/*!
+Protocol-independent analysis core: the result schema and its units, run
+storage, comparison, budgets, trends, querying, spreadsheet export, dataset packaging,
+integrity, the plugin interface, and the layer pipeline with its watchdog,
+retry policy and run event log
+
+Nothing here knows about a particular protocol. The Matter analyzer and
+third-party protocol plugins produce results in the same schema, and
+everything in this crate works on those results.
+*/
+
+pub mod baseline;
+pub mod budget;
+pub mod campaign;
+pub mod diff;
+pub mod distribution;
+pub mod events;
+pub mod export;
+pub mod impairment;
+pub mod integrity;
+pub mod netns;
+pub mod package;
+pub mod pipeline;
+pub mod platform;
+pub mod plugin;
+pub mod ports;
+pub mod query;
+pub mod redact;
+pub mod results_store;
+pub mod retry;
+pub mod run_metadata;
+pub mod samples;
+pub mod schema;
+pub mod scoring;
+pub mod trends;
+pub mod tunables;
+pub mod units;
+pub mod watchdog;
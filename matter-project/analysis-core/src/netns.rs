@@ -0,0 +1,265 @@
+// matter-project/analysis-core/src/netns.rs
+/*!
+Network namespaces - runs an analysis in its own Linux network namespace with an optional netem impairment
+
+[`Namespace::create`] adds a namespace `analyzer<N>` and connects it to the
+host with a veth pair, `anh<N>` on the host and `ans<N>` inside, addressed
+10.203.N.1/30 and 10.203.N.2/30. N is the first index whose namespace does not
+exist yet. Creating the namespace is what claims the index, so concurrent
+analyses never share a namespace, an interface or a subnet. Inside the
+namespace, loopback is private: fixtures, listeners and ephemeral ports of
+one analysis are invisible to every other.
+
+An [`Impairment`] puts a netem qdisc on each egress: the uplink one on the
+namespace's end of the veth pair, and the downlink one on the host's end.
+The namespace's loopback carries both directions of a local exchange. It
+gets the uplink qdisc, so each packet passes through exactly one qdisc, and
+an asymmetric impairment only shows up in traffic that leaves the namespace. The
+namespace and its interfaces are removed when the [`Namespace`] is dropped. All of this needs Linux, iproute2 and root or
+CAP_NET_ADMIN.
+*/
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::net::Ipv4Addr;
+use std::process::Command;
+
+/// Set for the analysis running inside a namespace, to its [`NamespaceInfo`] as JSON.
+pub const NETNS_ENV: &str = "ANALYZER_NETNS";
+/// Top-level field of a result that describes the namespace it was measured in.
+pub const NAMESPACE_SECTION: &str = "network_namespace";
+
+/// Namespace indices, and so /30 subnets in 10.203.0.0/16, that may be claimed.
+const MAX_NAMESPACES: u8 = 254;
+
+#[derive(Debug, Clone, Default)]
+pub struct NetnsConfig {
+    pub impairment: Option<Impairment>,
+}
+
+/// A netem qdisc: `delay_ms=20,jitter_ms=5,loss_percent=1,loss_correlation_percent=25,reorder_percent=10,duplicate_percent=2,rate_kbit=1000`,
+/// every key optional.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Netem {
+    pub delay_ms: f64,
+    pub jitter_ms: f64,
+    pub loss_percent: f64,
+    /// How much each loss depends on the previous one; higher values make losses come in bursts.
+    #[serde(default)]
+    pub loss_correlation_percent: f64,
+    /// Packets sent at once instead of after the delay, overtaking those still delayed; needs a delay.
+    #[serde(default)]
+    pub reorder_percent: f64,
+    /// Packets sent twice.
+    #[serde(default)]
+    pub duplicate_percent: f64,
+    pub rate_kbit: Option<u32>,
+}
+
+/// Netem per direction, as seen from the analysis inside the namespace.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Impairment {
+    /// The named profile this came from, if any.
+    pub profile: Option<String>,
+    /// Namespace to host, and within the namespace's loopback.
+    pub uplink: Netem,
+    /// Host to namespace.
+    pub downlink: Netem,
+}
+
+impl Impairment {
+    pub fn symmetric(netem: Netem) -> Self {
+        Self { profile: None, uplink: netem.clone(), downlink: netem }
+    }
+}
+
+impl std::str::FromStr for Netem {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let mut netem = Netem::default();
+        for pair in spec.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').ok_or_else(|| anyhow!("netem expects key=value, got {}", pair))?;
+            match key.trim() {
+                "delay_ms" => netem.delay_ms = value.trim().parse()?,
+                "jitter_ms" => netem.jitter_ms = value.trim().parse()?,
+                "loss_percent" => netem.loss_percent = value.trim().parse()?,
+                "loss_correlation_percent" => netem.loss_correlation_percent = value.trim().parse()?,
+                "reorder_percent" => netem.reorder_percent = value.trim().parse()?,
+                "duplicate_percent" => netem.duplicate_percent = value.trim().parse()?,
+                "rate_kbit" => netem.rate_kbit = Some(value.trim().parse()?),
+                other => return Err(anyhow!("unknown netem setting {}", other)),
+            }
+        }
+        let percent = 0.0..=100.0;
+        let percentages = [netem.loss_percent, netem.loss_correlation_percent, netem.reorder_percent, netem.duplicate_percent];
+        if netem.delay_ms < 0.0 || netem.jitter_ms < 0.0 || !percentages.iter().all(|p| percent.contains(p)) {
+            return Err(anyhow!("netem delays must not be negative and percentages must be between 0 and 100"));
+        }
+        if netem.reorder_percent > 0.0 && netem.delay_ms == 0.0 {
+            return Err(anyhow!("netem reordering needs a delay for the other packets to wait out"));
+        }
+        Ok(netem)
+    }
+}
+
+impl Netem {
+    /// Arguments after `tc qdisc add dev <dev> root`.
+    pub fn qdisc_args(&self) -> Vec<String> {
+        let mut args = vec!["netem".to_string(), "delay".to_string(), format!("{}ms", self.delay_ms)];
+        if self.jitter_ms > 0.0 {
+            args.push(format!("{}ms", self.jitter_ms));
+        }
+        if self.loss_percent > 0.0 {
+            args.extend(["loss".to_string(), format!("{}%", self.loss_percent)]);
+            if self.loss_correlation_percent > 0.0 {
+                args.push(format!("{}%", self.loss_correlation_percent));
+            }
+        }
+        if self.duplicate_percent > 0.0 {
+            args.extend(["duplicate".to_string(), format!("{}%", self.duplicate_percent)]);
+        }
+        if self.reorder_percent > 0.0 {
+            args.extend(["reorder".to_string(), format!("{}%", self.reorder_percent)]);
+        }
+        if let Some(rate) = self.rate_kbit {
+            args.extend(["rate".to_string(), format!("{}kbit", rate)]);
+        }
+        args
+    }
+}
+
+/// Stored in the result under [`NAMESPACE_SECTION`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NamespaceInfo {
+    pub name: String,
+    pub host_interface: String,
+    pub namespace_interface: String,
+    pub host_addr: Ipv4Addr,
+    pub namespace_addr: Ipv4Addr,
+    pub impairment: Option<Impairment>,
+}
+
+pub struct Namespace {
+    info: NamespaceInfo,
+}
+
+impl Namespace {
+    pub fn create(config: &NetnsConfig) -> Result<Self> {
+        if !cfg!(target_os = "linux") {
+            return Err(anyhow!("network namespaces are Linux-only"));
+        }
+        let mut namespace = None;
+        for index in 1..=MAX_NAMESPACES {
+            let name = format!("analyzer{}", index);
+            match run("ip", &["netns", "add", &name]) {
+                Ok(()) => {
+                    namespace = Some(Self {
+                        info: NamespaceInfo {
+                            name,
+                            host_interface: format!("anh{}", index),
+                            namespace_interface: format!("ans{}", index),
+                            host_addr: Ipv4Addr::new(10, 203, index, 1),
+                            namespace_addr: Ipv4Addr::new(10, 203, index, 2),
+                            impairment: config.impairment.clone(),
+                        },
+                    });
+                    break;
+                }
+                Err(e) if e.to_string().contains("File exists") => continue,
+                Err(e) => return Err(e.context("creating a network namespace needs root or CAP_NET_ADMIN")),
+            }
+        }
+        let namespace = namespace.ok_or_else(|| anyhow!("all {} analyzer namespaces are in use", MAX_NAMESPACES))?;
+        // From here on a failed step drops the namespace, which removes what was set up so far
+        namespace.connect().with_context(|| format!("setting up network namespace {}", namespace.info.name))?;
+        Ok(namespace)
+    }
+
+    pub fn info(&self) -> &NamespaceInfo {
+        &self.info
+    }
+
+    /// `program` run inside the namespace.
+    pub fn command(&self, program: impl AsRef<OsStr>) -> Command {
+        let mut command = Command::new("ip");
+        command.args(["netns", "exec", &self.info.name]).arg(program);
+        command
+    }
+
+    fn connect(&self) -> Result<()> {
+        let info = &self.info;
+        let (ns, host_if, ns_if) = (info.name.as_str(), info.host_interface.as_str(), info.namespace_interface.as_str());
+        run("ip", &["link", "add", host_if, "type", "veth", "peer", "name", ns_if, "netns", ns])?;
+        run("ip", &["addr", "add", &format!("{}/30", info.host_addr), "dev", host_if])?;
+        run("ip", &["link", "set", host_if, "up"])?;
+        run("ip", &["-n", ns, "addr", "add", &format!("{}/30", info.namespace_addr), "dev", ns_if])?;
+        run("ip", &["-n", ns, "link", "set", ns_if, "up"])?;
+        run("ip", &["-n", ns, "link", "set", "lo", "up"])?;
+        // Without a default route multicast has nowhere to go, and discovery needs it
+        run("ip", &["-n", ns, "route", "add", "224.0.0.0/4", "dev", ns_if])?;
+
+        if let Some(impairment) = &info.impairment {
+            add_qdisc(&[], host_if, &impairment.downlink)?;
+            for dev in [ns_if, "lo"] {
+                add_qdisc(&["-n", ns], dev, &impairment.uplink)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Namespace {
+    fn drop(&mut self) {
+        // Deleting the namespace destroys its end of the veth pair, which takes the host end with it
+        if let Err(e) = run("ip", &["netns", "del", &self.info.name]) {
+            log::warn!("Could not remove network namespace {}: {:#}", self.info.name, e);
+        }
+        let _ = run("ip", &["link", "del", &self.info.host_interface]);
+    }
+}
+
+/// The namespace this analysis was started in by a parent analyzer, if any.
+pub fn current() -> Option<NamespaceInfo> {
+    std::env::var(NETNS_ENV).ok().and_then(|info| serde_json::from_str(&info).ok())
+}
+
+fn add_qdisc(namespace: &[&str], dev: &str, netem: &Netem) -> Result<()> {
+    let qdisc = netem.qdisc_args();
+    let qdisc: Vec<&str> = qdisc.iter().map(String::as_str).collect();
+    run("tc", &[namespace, &["qdisc", "add", "dev", dev, "root"], qdisc.as_slice()].concat())
+}
+
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(program).args(args).output().with_context(|| format!("running {}", program))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn netem_specs_become_qdisc_arguments() {
+        let netem: Netem = "delay_ms=20, jitter_ms=5,loss_percent=1.5,rate_kbit=1000".parse().unwrap();
+        assert_eq!(netem.qdisc_args().join(" "), "netem delay 20ms 5ms loss 1.5% rate 1000kbit");
+        let bursty: Netem = "loss_percent=1,loss_correlation_percent=25".parse().unwrap();
+        assert_eq!(bursty.qdisc_args().join(" "), "netem delay 0ms loss 1% 25%");
+        assert_eq!("delay_ms=0".parse::<Netem>().unwrap().qdisc_args().join(" "), "netem delay 0ms");
+        let shuffled: Netem = "delay_ms=10,reorder_percent=25,duplicate_percent=2".parse().unwrap();
+        assert_eq!(shuffled.qdisc_args().join(" "), "netem delay 10ms duplicate 2% reorder 25%");
+        assert!("reorder_percent=25".parse::<Netem>().is_err());
+        assert!("loss_percent=150".parse::<Netem>().is_err());
+        assert!("latency=5".parse::<Netem>().is_err());
+        assert!("delay_ms".parse::<Netem>().is_err());
+    }
+}
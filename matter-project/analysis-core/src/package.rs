@@ -0,0 +1,376 @@
+// matter-project/analysis-core/src/package.rs
+/*!
+Dataset packaging - results, captures, configs, schema and environment in one checksummed archive for publication
+
+`package` collects everything a reader needs to check a paper's numbers into
+a single `.tar.gz`, ready to attach to the paper or deposit on Zenodo. All
+files sit under one directory named after the archive:
+
+```text
+dataset/
+  manifest.json      what is in the package, run by run, with each file's SHA-256
+  SHA256SUMS         `sha256sum -c` format, covering every other file
+  schema.json        result schema version and every metric's unit and direction
+  environment.json   test environment, namespace, tags and notes of each run
+  results/<key>.json the result files, byte for byte, keyed as `merge` keys them
+  captures/<name>    packet captures given with --capture
+  configs/<path>     configuration files and directories given with --config
+```
+
+Results are loaded the same way `merge` loads them, so files that are not
+results are skipped and listed, and duplicates are packaged once. A run that
+came from a merged dataset rather than its own file is written out as stored
+in the dataset. Captures and configs are packaged as they are; redact them
+first with `redact` when they should not be published as recorded. The
+manifest and archive are built without the `package` feature, which is only
+needed to write the `.tar.gz`. A `<archive>.sha256` sidecar is written next
+to it for the deposit form.
+*/
+
+use crate::campaign::{CampaignMerger, MergeConfig, CAMPAIGN_FORMAT_VERSION};
+use crate::diff::{flatten, Direction};
+use crate::integrity::{sha256_hex, write_checksum};
+use crate::query::QueryConfig;
+use crate::schema::{schema_version, RESULT_SCHEMA_VERSION};
+use crate::units::Unit;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const PACKAGE_FORMAT_VERSION: &str = "1";
+
+#[derive(Debug, Clone)]
+pub struct PackageConfig {
+    /// Result files, directories or merged datasets; see [`QueryConfig::default_inputs`] when empty.
+    pub inputs: Vec<PathBuf>,
+    /// Packet captures, packaged under `captures/`.
+    pub captures: Vec<PathBuf>,
+    /// Configuration files or directories, packaged under `configs/`.
+    pub configs: Vec<PathBuf>,
+    pub title: Option<String>,
+    pub output: PathBuf,
+}
+
+impl Default for PackageConfig {
+    fn default() -> Self {
+        Self {
+            inputs: Vec::new(),
+            captures: Vec::new(),
+            configs: Vec::new(),
+            title: None,
+            output: PathBuf::from("../results/dataset.tar.gz"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    Result,
+    Capture,
+    Config,
+    Schema,
+    Environment,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// Relative to the package directory, with `/` separators.
+    pub path: String,
+    pub kind: FileKind,
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestRun {
+    pub key: String,
+    pub path: String,
+    pub protocol: String,
+    /// When the run was made, as in `merge` provenance.
+    pub timestamp: Option<String>,
+    pub host: Option<String>,
+    pub device_class: Option<String>,
+    /// Of the packaged file, which may predate the current schema.
+    pub schema_version: u32,
+    /// `false` when the run came from a merged dataset and was written out from it.
+    pub original_file: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageManifest {
+    pub format_version: String,
+    pub title: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub created_with: String,
+    pub result_schema_version: u32,
+    pub runs: Vec<ManifestRun>,
+    pub files: Vec<ManifestFile>,
+    /// Inputs that were not packaged, with the reason.
+    pub skipped: Vec<String>,
+}
+
+/// A package laid out in memory: the manifest and every file under the package directory, manifest included.
+#[derive(Debug, Clone)]
+pub struct DatasetPackage {
+    /// The directory the files sit under inside the archive.
+    pub root: String,
+    pub manifest: PackageManifest,
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+impl DatasetPackage {
+    pub fn build(config: &PackageConfig) -> Result<Self> {
+        let inputs = if config.inputs.is_empty() { QueryConfig::default_inputs() } else { config.inputs.clone() };
+        let dataset = CampaignMerger::new(MergeConfig { inputs, ..MergeConfig::default() }).merge()?;
+        if dataset.runs.is_empty() {
+            return Err(anyhow!("no results to package"));
+        }
+
+        let mut package = DatasetPackage {
+            root: package_root(&config.output),
+            manifest: PackageManifest {
+                format_version: PACKAGE_FORMAT_VERSION.to_string(),
+                title: config.title.clone(),
+                created_at: Utc::now(),
+                created_with: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+                result_schema_version: RESULT_SCHEMA_VERSION,
+                runs: Vec::new(),
+                files: Vec::new(),
+                skipped: dataset.skipped.iter().map(|s| format!("{}: {}", s.file, s.reason)).collect(),
+            },
+            files: Vec::new(),
+        };
+
+        let mut metrics = BTreeMap::new();
+        let mut environments = BTreeMap::new();
+        for run in &dataset.runs {
+            let path = format!("results/{}.json", run.key);
+            // The file as measured when it is still there unchanged, so its checksums and signatures still hold
+            let original = std::fs::read(&run.provenance.source_file)
+                .ok()
+                .filter(|bytes| sha256_hex(bytes) == run.provenance.sha256);
+            let original_file = original.is_some();
+            let bytes = match original {
+                Some(bytes) => bytes,
+                None => serde_json::to_vec_pretty(&run.result)?,
+            };
+            let version = schema_version(&serde_json::from_slice(&bytes)?)?;
+            package.add(&path, FileKind::Result, bytes)?;
+            package.manifest.runs.push(ManifestRun {
+                key: run.key.clone(),
+                path,
+                protocol: run.provenance.protocol.clone(),
+                timestamp: run.provenance.timestamp(),
+                host: run.provenance.host.clone(),
+                device_class: run.provenance.device_class.clone(),
+                schema_version: version,
+                original_file,
+            });
+            flatten(&run.result, String::new(), &mut metrics);
+            let field = |name: &str| run.result.get(name).cloned().unwrap_or(Value::Null);
+            environments.insert(run.key.clone(), json!({
+                "test_environment": field("test_environment"),
+                "network_namespace": field("network_namespace"),
+                "run_metadata": field("run_metadata"),
+                "timestamp": run.provenance.timestamp(),
+            }));
+        }
+
+        for capture in &config.captures {
+            let name = capture
+                .file_name()
+                .ok_or_else(|| anyhow!("{} is not a capture file", capture.display()))?
+                .to_string_lossy()
+                .into_owned();
+            package.add(&format!("captures/{}", name), FileKind::Capture, read(capture)?)?;
+        }
+        for config_path in &config.configs {
+            let root = config_path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| "config".to_string());
+            if config_path.is_dir() {
+                for file in files_under(config_path)? {
+                    let relative = file.strip_prefix(config_path).unwrap_or(&file);
+                    let relative: Vec<_> = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+                    package.add(&format!("configs/{}/{}", root, relative.join("/")), FileKind::Config, read(&file)?)?;
+                }
+            } else {
+                package.add(&format!("configs/{}", root), FileKind::Config, read(config_path)?)?;
+            }
+        }
+
+        let schema = json!({
+            "result_schema_version": RESULT_SCHEMA_VERSION,
+            "campaign_format_version": CAMPAIGN_FORMAT_VERSION,
+            "metrics": metrics
+                .keys()
+                .map(|metric| (metric.clone(), json!({
+                    "unit": Unit::of(metric).map(|unit| unit.to_string()),
+                    "direction": Direction::of(metric),
+                })))
+                .collect::<serde_json::Map<String, Value>>(),
+        });
+        package.add("schema.json", FileKind::Schema, serde_json::to_vec_pretty(&schema)?)?;
+        package.add("environment.json", FileKind::Environment, serde_json::to_vec_pretty(&environments)?)?;
+
+        // Checksums cover everything but themselves; the manifest is listed in SHA256SUMS only
+        let manifest = serde_json::to_vec_pretty(&package.manifest)?;
+        package.files.push(("manifest.json".to_string(), manifest));
+        let sums: String = package.files.iter().map(|(path, bytes)| format!("{}  {}\n", sha256_hex(bytes), path)).collect();
+        package.files.push(("SHA256SUMS".to_string(), sums.into_bytes()));
+        Ok(package)
+    }
+
+    fn add(&mut self, path: &str, kind: FileKind, bytes: Vec<u8>) -> Result<()> {
+        if self.files.iter().any(|(existing, _)| existing == path) {
+            return Err(anyhow!("two inputs would both be packaged as {}", path));
+        }
+        self.manifest.files.push(ManifestFile { path: path.to_string(), kind, bytes: bytes.len() as u64, sha256: sha256_hex(&bytes) });
+        self.files.push((path.to_string(), bytes));
+        Ok(())
+    }
+}
+
+fn read(file: &Path) -> Result<Vec<u8>> {
+    std::fs::read(file).with_context(|| format!("reading {}", file.display()))
+}
+
+/// Every file under `dir`, in path order.
+fn files_under(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir).with_context(|| format!("reading {}", dir.display()))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+pub struct DatasetPackager {
+    config: PackageConfig,
+}
+
+impl DatasetPackager {
+    pub fn new(config: PackageConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn run(&self) -> Result<PackageManifest> {
+        let package = DatasetPackage::build(&self.config)?;
+        let manifest = &package.manifest;
+        let count = |kind: FileKind| manifest.files.iter().filter(|f| f.kind == kind).count();
+
+        println!("\n📦 Packaging Dataset ({} runs)", manifest.runs.len());
+        println!("----------------------------------------");
+        for skipped in &manifest.skipped {
+            println!("⚠️ Skipped {}", skipped);
+        }
+        let mut protocols: Vec<&str> = manifest.runs.iter().map(|run| run.protocol.as_str()).collect();
+        protocols.sort();
+        protocols.dedup();
+        println!("✅ Results: {} runs of {}", manifest.runs.len(), protocols.join(", "));
+        println!("✅ Captures: {}, configs: {}", count(FileKind::Capture), count(FileKind::Config));
+        let rewritten = manifest.runs.iter().filter(|run| !run.original_file).count();
+        if rewritten > 0 {
+            println!("⚠️ {} runs came from merged datasets and are packaged as stored there", rewritten);
+        }
+
+        write_archive(&package, &self.config.output)?;
+        let sha256 = write_checksum(&self.config.output)?;
+        println!("✅ Package saved to: {} (sha256 {})", self.config.output.display(), sha256);
+        Ok(package.manifest)
+    }
+}
+
+/// The archive's name without `.tar.gz`/`.tgz`.
+fn package_root(output: &Path) -> String {
+    let name = output.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let root = name.strip_suffix(".tar.gz").or_else(|| name.strip_suffix(".tgz")).unwrap_or(&name);
+    if root.is_empty() { "dataset".to_string() } else { root.to_string() }
+}
+
+#[cfg(feature = "package")]
+fn write_archive(package: &DatasetPackage, output: &Path) -> Result<()> {
+    use flate2::{write::GzEncoder, Compression};
+
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+    }
+    let file = std::fs::File::create(output).with_context(|| format!("writing {}", output.display()))?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::best()));
+    let modified = package.manifest.created_at.timestamp().max(0) as u64;
+    for (path, bytes) in &package.files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(bytes.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(modified);
+        header.set_cksum();
+        archive
+            .append_data(&mut header, format!("{}/{}", package.root, path), bytes.as_slice())
+            .with_context(|| format!("adding {}", path))?;
+    }
+    archive.into_inner()?.finish()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "package"))]
+fn write_archive(_package: &DatasetPackage, output: &Path) -> Result<()> {
+    Err(anyhow!("cannot write {}: built without the `package` feature", output.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packages_results_configs_and_checksums() {
+        let dir = std::env::temp_dir().join(format!("package-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("results")).unwrap();
+        std::fs::create_dir_all(dir.join("profiles")).unwrap();
+        let result = r#"{"protocol_name":"Matter","osi_layer_5_session":{"commissioning_time_ms":90.2},"test_environment":{"os_platform":"linux"}}"#;
+        std::fs::write(dir.join("results/matter.json"), result).unwrap();
+        std::fs::write(dir.join("results/notes.json"), "[1, 2]").unwrap();
+        std::fs::write(dir.join("profiles/lte.json"), "{}").unwrap();
+
+        let config = PackageConfig {
+            inputs: vec![dir.join("results")],
+            configs: vec![dir.join("profiles")],
+            title: Some("Matter on a Pi".to_string()),
+            ..PackageConfig::default()
+        };
+        let package = DatasetPackage::build(&config).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let paths: Vec<&str> = package.files.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(paths, vec![
+            "results/matter/results/matter.json",
+            "configs/profiles/lte.json",
+            "schema.json",
+            "environment.json",
+            "manifest.json",
+            "SHA256SUMS",
+        ]);
+        // Byte for byte, so existing checksums of the result still match
+        assert_eq!(package.files[0].1, result.as_bytes());
+        assert!(package.manifest.runs[0].original_file);
+        assert_eq!(package.manifest.runs[0].schema_version, 1);
+        assert_eq!(package.manifest.skipped.len(), 1);
+        let schema: Value = serde_json::from_slice(&package.files[2].1).unwrap();
+        assert_eq!(schema["metrics"]["osi_layer_5_session.commissioning_time_ms"]["unit"], "ms");
+        let sums = String::from_utf8(package.files[5].1.clone()).unwrap();
+        assert_eq!(sums.lines().count(), 5);
+        assert!(sums.contains(&format!("{}  manifest.json", sha256_hex(&package.files[4].1))));
+        assert_eq!(package.root, "dataset");
+        assert_eq!(package_root(Path::new("out/matter-2025.tgz")), "matter-2025");
+    }
+}
@@ -0,0 +1,245 @@
+// matter-project/analysis-core/src/pipeline.rs
+/*!
+Layer pipeline - layer analyses as stages in a dependency graph that share one context
+
+Each stage names the stages it depends on, such as transport → session →
+application, with presentation independent of them. [`LayerPipeline`] runs
+the stages in dependency order, and among stages that are ready it keeps the
+order they were added in. Stages run one at a time, so no stage's timing
+overlaps another's. [`LayerContext`] holds typed resources shared between
+stages: a stage can leave a connection or session there for the stages after
+it to reuse, and leave its measurements for whoever assembles the result.
+
+Each stage runs under a [`Watchdog`], and finds the watchdog's
+[`StageProbe`] for it in the context. A stage the watchdog cancels does not
+stop the pipeline. The stages that depend on it, directly or through other
+stages, are skipped and recorded as failed as well. Independent stages
+still run.
+*/
+
+use anyhow::{anyhow, Context, Result};
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Instant;
+
+use crate::watchdog::{StageProbe, Watchdog};
+
+pub type StageFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + 'a>>;
+
+pub trait LayerStage {
+    fn name(&self) -> &'static str;
+
+    /// Stages that must finish before this one starts.
+    fn depends_on(&self) -> &[&'static str] {
+        &[]
+    }
+
+    fn run<'a>(&'a self, context: &'a mut LayerContext) -> StageFuture<'a>;
+}
+
+/// Resources shared between stages, one value per type.
+#[derive(Default)]
+pub struct LayerContext {
+    resources: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl LayerContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores a resource, returning the one of the same type it replaces.
+    pub fn insert<T: 'static>(&mut self, value: T) -> Option<T> {
+        self.resources
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.resources.get(&TypeId::of::<T>()).and_then(|value| value.downcast_ref())
+    }
+
+    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.resources.get_mut(&TypeId::of::<T>()).and_then(|value| value.downcast_mut())
+    }
+
+    pub fn take<T: 'static>(&mut self) -> Option<T> {
+        self.resources
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+
+    /// Like [`get`](Self::get), but a missing resource is an error naming its type.
+    pub fn require<T: 'static>(&self) -> Result<&T> {
+        self.get().ok_or_else(|| anyhow!("no {} in the layer context", type_name::<T>()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StageTiming {
+    pub stage: &'static str,
+    pub elapsed_ms: f64,
+}
+
+#[derive(Default)]
+pub struct LayerPipeline {
+    stages: Vec<Box<dyn LayerStage>>,
+}
+
+impl LayerPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stage(mut self, stage: impl LayerStage + 'static) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Stage names in the order they will run.
+    pub fn order(&self) -> Result<Vec<&'static str>> {
+        Ok(self.schedule()?.into_iter().map(|index| self.stages[index].name()).collect())
+    }
+
+    /// Runs every stage, returning the timings of those that finished.
+    /// Stages the watchdog cancelled or skipped are in its failures.
+    pub async fn run(&self, context: &mut LayerContext, watchdog: &mut Watchdog) -> Result<Vec<StageTiming>> {
+        let mut timings = Vec::with_capacity(self.stages.len());
+        for index in self.schedule()? {
+            let stage = &self.stages[index];
+            if let Some(failed) = stage.depends_on().iter().find(|dependency| watchdog.has_failed(dependency)) {
+                watchdog.skip(stage.name(), format!("skipped because the {} stage failed", failed));
+                continue;
+            }
+            let start = Instant::now();
+            let finished = watchdog
+                .guard(stage.name(), |probe| {
+                    context.insert::<StageProbe>(probe);
+                    stage.run(context)
+                })
+                .await
+                .with_context(|| format!("{} stage", stage.name()))?;
+            context.take::<StageProbe>();
+            if finished.is_some() {
+                timings.push(StageTiming { stage: stage.name(), elapsed_ms: start.elapsed().as_secs_f64() * 1000.0 });
+            }
+        }
+        Ok(timings)
+    }
+
+    fn schedule(&self) -> Result<Vec<usize>> {
+        let names: Vec<&str> = self.stages.iter().map(|stage| stage.name()).collect();
+        for (index, stage) in self.stages.iter().enumerate() {
+            if names[..index].contains(&stage.name()) {
+                return Err(anyhow!("layer stage {} is added twice", stage.name()));
+            }
+            if let Some(missing) = stage.depends_on().iter().find(|dependency| !names.contains(dependency)) {
+                return Err(anyhow!("layer stage {} depends on unknown stage {}", stage.name(), missing));
+            }
+        }
+
+        let mut order: Vec<usize> = Vec::with_capacity(self.stages.len());
+        while order.len() < self.stages.len() {
+            let finished = |name: &&str| order.iter().any(|&index| names[index] == *name);
+            let ready = (0..self.stages.len())
+                .find(|index| !order.contains(index) && self.stages[*index].depends_on().iter().all(finished));
+            match ready {
+                Some(index) => order.push(index),
+                None => {
+                    let stuck: Vec<&str> =
+                        (0..self.stages.len()).filter(|index| !order.contains(index)).map(|index| names[index]).collect();
+                    return Err(anyhow!("layer stages {} depend on each other in a cycle", stuck.join(", ")));
+                }
+            }
+        }
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watchdog::WatchdogConfig;
+    use std::time::Duration;
+
+    struct Stage(&'static str, &'static [&'static str]);
+
+    impl LayerStage for Stage {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn depends_on(&self) -> &[&'static str] {
+            self.1
+        }
+
+        fn run<'a>(&'a self, context: &'a mut LayerContext) -> StageFuture<'a> {
+            Box::pin(async move {
+                let mut ran = context.take::<Vec<&'static str>>().unwrap_or_default();
+                ran.push(self.0);
+                context.insert(ran);
+                Ok(())
+            })
+        }
+    }
+
+    #[test]
+    fn stages_run_after_their_dependencies() {
+        let pipeline = LayerPipeline::new()
+            .stage(Stage("application", &["session"]))
+            .stage(Stage("presentation", &[]))
+            .stage(Stage("session", &["transport"]))
+            .stage(Stage("transport", &[]));
+        assert_eq!(pipeline.order().unwrap(), vec!["presentation", "transport", "session", "application"]);
+
+        let cycle = LayerPipeline::new().stage(Stage("a", &["b"])).stage(Stage("b", &["a"]));
+        assert!(cycle.order().is_err());
+        assert!(LayerPipeline::new().stage(Stage("a", &["missing"])).order().is_err());
+        assert!(LayerPipeline::new().stage(Stage("a", &[])).stage(Stage("a", &[])).order().is_err());
+    }
+
+    #[test]
+    fn context_shares_resources_between_stages() {
+        let mut context = LayerContext::new();
+        let pipeline = LayerPipeline::new().stage(Stage("session", &["transport"])).stage(Stage("transport", &[]));
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        let timings = runtime.block_on(pipeline.run(&mut context, &mut Watchdog::default())).unwrap();
+
+        assert_eq!(timings.len(), 2);
+        assert_eq!(context.get::<Vec<&str>>(), Some(&vec!["transport", "session"]));
+        assert!(context.require::<u32>().is_err());
+    }
+
+    struct Hang;
+
+    impl LayerStage for Hang {
+        fn name(&self) -> &'static str {
+            "transport"
+        }
+
+        fn run<'a>(&'a self, _context: &'a mut LayerContext) -> StageFuture<'a> {
+            Box::pin(std::future::pending())
+        }
+    }
+
+    #[test]
+    fn stages_after_a_hung_stage_are_skipped() {
+        let mut context = LayerContext::new();
+        let mut watchdog = Watchdog::new(WatchdogConfig { stage_timeout: Duration::from_millis(5), ceiling_factor: 2 });
+        let pipeline = LayerPipeline::new()
+            .stage(Hang)
+            .stage(Stage("session", &["transport"]))
+            .stage(Stage("application", &["session"]))
+            .stage(Stage("presentation", &[]));
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        let timings = runtime.block_on(pipeline.run(&mut context, &mut watchdog)).unwrap();
+
+        assert_eq!(timings.iter().map(|timing| timing.stage).collect::<Vec<_>>(), vec!["presentation"]);
+        let failed: Vec<&str> = watchdog.failures().iter().map(|failure| failure.stage.as_str()).collect();
+        assert_eq!(failed, vec!["transport", "session", "application"]);
+    }
+}
@@ -0,0 +1,369 @@
+// matter-project/analysis-core/src/platform.rs
+/*!
+Runtime platform detection - distinguishes desktop, Raspberry Pi and constrained hosts
+
+Besides the host itself, [`PlatformCapabilities`] records which measurements
+this OS and build can actually make: multicast discovery, `SO_REUSEPORT`,
+packet capture, batched UDP I/O, hardware counters and an I2C power meter.
+Each capability is probed at run time where that is possible. Since it is
+recorded in the result's `test_environment`, a metric missing from a
+Windows or macOS run can be told apart from one that was measured as zero.
+The socket buffers and sysctls that bound throughput are recorded there too,
+as [`NetworkTunables`].
+*/
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+use crate::tunables::NetworkTunables;
+
+/// Administratively scoped group for the multicast probe; sent with TTL 0 so it never leaves the host.
+const PROBE_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 77, 77);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceClass {
+    Desktop,
+    SingleBoardComputer,
+    Constrained,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestEnvironment {
+    pub os_platform: String,
+    pub architecture: String,
+    pub device_class: DeviceClass,
+    pub cpu_model: String,
+    pub cpu_cores: u32,
+    pub total_memory_mb: u64,
+    /// Results written before capabilities were recorded have none.
+    #[serde(default)]
+    pub capabilities: PlatformCapabilities,
+    /// Effective socket buffers, congestion control and sysctls; empty in older results.
+    #[serde(default)]
+    pub network_tunables: NetworkTunables,
+}
+
+impl TestEnvironment {
+    pub fn detect() -> Self {
+        let architecture = std::env::consts::ARCH.to_string();
+        let cpu_model = detect_cpu_model();
+        let cpu_cores = std::thread::available_parallelism()
+            .map(|n| n.get() as u32)
+            .unwrap_or(1);
+        let total_memory_mb = detect_total_memory_mb();
+        let device_class = classify(&architecture, &cpu_model, cpu_cores, total_memory_mb);
+
+        let capabilities = PlatformCapabilities::detect();
+        let network_tunables = NetworkTunables::detect(socket_buffer_bytes(device_class));
+
+        debug!("Detected {:?} ({}, {} cores, {}MB)", device_class, cpu_model, cpu_cores, total_memory_mb);
+
+        Self {
+            os_platform: std::env::consts::OS.to_string(),
+            architecture,
+            device_class,
+            cpu_model,
+            cpu_cores,
+            total_memory_mb,
+            capabilities,
+            network_tunables,
+        }
+    }
+
+    /// Sample count for tight timing loops; smaller boards get fewer to keep runs short.
+    pub fn calibration_samples(&self) -> u32 {
+        match self.device_class {
+            DeviceClass::Desktop => 1000,
+            DeviceClass::SingleBoardComputer => 500,
+            DeviceClass::Constrained => 200,
+        }
+    }
+
+    /// Receive buffer for measurement sockets, sized to what the board can spare.
+    pub fn socket_buffer_bytes(&self) -> usize {
+        socket_buffer_bytes(self.device_class)
+    }
+
+    /// Binds a UDP measurement socket with buffers appropriate for this host.
+    pub fn bind_udp_socket(&self, addr: SocketAddr) -> anyhow::Result<UdpSocket> {
+        bind_udp_with_buffer(addr, self.socket_buffer_bytes())
+    }
+}
+
+fn socket_buffer_bytes(device_class: DeviceClass) -> usize {
+    match device_class {
+        DeviceClass::Desktop => 4 * 1024 * 1024,
+        DeviceClass::SingleBoardComputer => 1024 * 1024,
+        DeviceClass::Constrained => 256 * 1024,
+    }
+}
+
+/// Binds a UDP socket with the given send/receive buffer sizes requested.
+pub fn bind_udp_with_buffer(addr: SocketAddr, buffer_bytes: usize) -> anyhow::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+
+    // Kernels may clamp the request (rmem_max/wmem_max); the effective size is what it is
+    if let Err(e) = socket.set_recv_buffer_size(buffer_bytes) {
+        debug!("Could not set receive buffer size: {}", e);
+    }
+    if let Err(e) = socket.set_send_buffer_size(buffer_bytes) {
+        debug!("Could not set send buffer size: {}", e);
+    }
+
+    socket.bind(&addr.into())?;
+    socket.set_nonblocking(true)?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Capability {
+    pub available: bool,
+    /// How it was detected, or why it is missing and what falls back.
+    pub detail: String,
+}
+
+impl Capability {
+    fn yes(detail: impl Into<String>) -> Self {
+        Self { available: true, detail: detail.into() }
+    }
+
+    fn no(detail: impl Into<String>) -> Self {
+        Self { available: false, detail: detail.into() }
+    }
+}
+
+/// What this OS and build can measure.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlatformCapabilities {
+    /// Joining an IPv4 group and receiving our own datagram, as mDNS discovery needs.
+    pub udp_multicast: Capability,
+    /// `SO_REUSEPORT`, for several listeners sharing one port.
+    pub reuse_port: Capability,
+    /// `sendmmsg`/`recvmmsg` for the throughput stage's batched mode.
+    pub batched_udp: Capability,
+    /// perf_event cycle and instruction counters for CPU cost.
+    pub hardware_counters: Capability,
+    /// An i2c-dev bus for the INA219 power meter.
+    pub i2c_power_meter: Capability,
+}
+
+impl PlatformCapabilities {
+    pub fn detect() -> Self {
+        let capabilities = Self {
+            udp_multicast: probe_multicast(),
+            reuse_port: probe_reuse_port(),
+            batched_udp: if cfg!(target_os = "linux") {
+                Capability::yes("sendmmsg/recvmmsg")
+            } else {
+                Capability::no("Linux-only; throughput sends one datagram per call")
+            },
+            hardware_counters: probe_hardware_counters(),
+            i2c_power_meter: probe_i2c(),
+        };
+        for (name, capability) in capabilities.unavailable() {
+            debug!("{} unavailable: {}", name, capability.detail);
+        }
+        capabilities
+    }
+
+    pub fn all(&self) -> [(&'static str, &Capability); 5] {
+        [
+            ("udp_multicast", &self.udp_multicast),
+            ("reuse_port", &self.reuse_port),
+            ("batched_udp", &self.batched_udp),
+            ("hardware_counters", &self.hardware_counters),
+            ("i2c_power_meter", &self.i2c_power_meter),
+        ]
+    }
+
+    pub fn unavailable(&self) -> impl Iterator<Item = (&'static str, &Capability)> {
+        self.all().into_iter().filter(|(_, capability)| !capability.available)
+    }
+}
+
+/// Joins the probe group on the default interface and waits briefly for our own looped-back datagram.
+/// Windows needs the socket bound to the wildcard address before joining, so that is what this does everywhere.
+fn probe_multicast() -> Capability {
+    let attempt = || -> std::io::Result<bool> {
+        let socket = std::net::UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.join_multicast_v4(&PROBE_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+        socket.set_multicast_loop_v4(true)?;
+        socket.set_multicast_ttl_v4(0)?;
+        socket.set_read_timeout(Some(Duration::from_millis(200)))?;
+        let port = socket.local_addr()?.port();
+        socket.send_to(b"multicast-probe", SocketAddrV4::new(PROBE_GROUP, port))?;
+        let mut buffer = [0u8; 32];
+        Ok(matches!(socket.recv_from(&mut buffer), Ok((15, _))))
+    };
+    match attempt() {
+        Ok(true) => Capability::yes(format!("joined {} and received loopback", PROBE_GROUP)),
+        Ok(false) => Capability::no("joined the group but nothing was looped back; discovery is not measured"),
+        Err(e) => Capability::no(format!("{}; discovery is not measured", e)),
+    }
+}
+
+#[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+fn probe_reuse_port() -> Capability {
+    let attempt = || -> std::io::Result<()> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_port(true)
+    };
+    match attempt() {
+        Ok(()) => Capability::yes("SO_REUSEPORT"),
+        Err(e) => Capability::no(format!("SO_REUSEPORT rejected: {}", e)),
+    }
+}
+
+#[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+fn probe_reuse_port() -> Capability {
+    // Windows' SO_REUSEADDR lets a second socket steal the port rather than share it, so it is not a substitute
+    Capability::no("no SO_REUSEPORT on this OS; each listener gets its own port")
+}
+
+fn probe_hardware_counters() -> Capability {
+    if !cfg!(target_os = "linux") {
+        return Capability::no("perf_event is Linux-only; CPU cost is wall time only");
+    }
+    match std::fs::read_to_string("/proc/sys/kernel/perf_event_paranoid").map(|level| level.trim().parse::<i32>()) {
+        Ok(Ok(level)) if level <= 2 => Capability::yes(format!("perf_event (paranoid level {})", level)),
+        Ok(Ok(level)) => Capability::no(format!("perf_event_paranoid is {}; CPU cost is wall time only", level)),
+        _ => Capability::no("kernel without perf events; CPU cost is wall time only"),
+    }
+}
+
+fn probe_i2c() -> Capability {
+    if !cfg!(target_os = "linux") {
+        return Capability::no("INA219 over i2c-dev is Linux-only; a serial meter still works");
+    }
+    let buses: Vec<String> = std::fs::read_dir("/dev")
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|name| name.starts_with("i2c-"))
+                .collect()
+        })
+        .unwrap_or_default();
+    if buses.is_empty() {
+        Capability::no("no /dev/i2c-* bus")
+    } else {
+        Capability::yes(buses.join(", "))
+    }
+}
+
+fn classify(architecture: &str, cpu_model: &str, cpu_cores: u32, total_memory_mb: u64) -> DeviceClass {
+    let arm = matches!(architecture, "aarch64" | "arm");
+
+    if cpu_cores <= 1 || (total_memory_mb > 0 && total_memory_mb < 1024) {
+        DeviceClass::Constrained
+    } else if cpu_model.contains("Raspberry Pi") || (arm && total_memory_mb <= 8192) {
+        DeviceClass::SingleBoardComputer
+    } else {
+        DeviceClass::Desktop
+    }
+}
+
+fn detect_cpu_model() -> String {
+    // The device tree carries the board name on Raspberry Pi and most ARM SBCs
+    if let Ok(model) = std::fs::read_to_string("/proc/device-tree/model") {
+        return model.trim_end_matches('\0').trim().to_string();
+    }
+
+    if let Ok(cpuinfo) = std::fs::read_to_string("/proc/cpuinfo") {
+        for line in cpuinfo.lines() {
+            let mut parts = line.splitn(2, ':');
+            let key = parts.next().unwrap_or("").trim();
+            if key == "model name" || key == "Model" {
+                return parts.next().unwrap_or("").trim().to_string();
+            }
+        }
+    }
+
+    if let Some(model) = macos_sysctl("machdep.cpu.brand_string") {
+        return model;
+    }
+    if let Some(model) = windows_registry_value(r"HKLM\HARDWARE\DESCRIPTION\System\CentralProcessor\0", "ProcessorNameString") {
+        return model;
+    }
+
+    "unknown".to_string()
+}
+
+fn detect_total_memory_mb() -> u64 {
+    std::fs::read_to_string("/proc/meminfo")
+        .ok()
+        .and_then(|meminfo| {
+            meminfo
+                .lines()
+                .find(|line| line.starts_with("MemTotal:"))
+                .and_then(|line| line.split_whitespace().nth(1))
+                .and_then(|kb| kb.parse::<u64>().ok())
+        })
+        .map(|kb| kb / 1024)
+        .or_else(|| macos_sysctl("hw.memsize").and_then(|bytes| bytes.parse::<u64>().ok()).map(|bytes| bytes / (1024 * 1024)))
+        .or_else(windows_total_memory_mb)
+        .unwrap_or(0)
+}
+
+fn macos_sysctl(name: &str) -> Option<String> {
+    if !cfg!(target_os = "macos") {
+        return None;
+    }
+    command_output("sysctl", &["-n", name])
+}
+
+/// wmic is gone from recent Windows 11 builds, so PowerShell is the fallback.
+fn windows_total_memory_mb() -> Option<u64> {
+    if !cfg!(windows) {
+        return None;
+    }
+    command_output("wmic", &["computersystem", "get", "TotalPhysicalMemory", "/value"])
+        .and_then(|output| output.trim().strip_prefix("TotalPhysicalMemory=").map(str::to_string))
+        .or_else(|| command_output("powershell", &["-NoProfile", "-Command", "(Get-CimInstance Win32_ComputerSystem).TotalPhysicalMemory"]))
+        .and_then(|bytes| bytes.trim().parse::<u64>().ok())
+        .map(|bytes| bytes / (1024 * 1024))
+}
+
+fn windows_registry_value(key: &str, value: &str) -> Option<String> {
+    if !cfg!(windows) {
+        return None;
+    }
+    // reg query prints "    ProcessorNameString    REG_SZ    <value>"
+    command_output("reg", &["query", key, "/v", value])?
+        .lines()
+        .find_map(|line| line.split_once("REG_SZ").map(|(_, data)| data.trim().to_string()))
+}
+
+/// Trimmed stdout of a successful command, or None if it is missing, fails or prints nothing.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new(program).args(args).output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !stdout.is_empty()).then_some(stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities_are_recorded_and_optional_in_old_results() {
+        let environment = TestEnvironment::detect();
+        assert_eq!(environment.capabilities.batched_udp.available, cfg!(target_os = "linux"));
+        assert!(environment.capabilities.all().iter().all(|(_, capability)| !capability.detail.is_empty()));
+
+        let mut recorded = serde_json::to_value(&environment).unwrap();
+        assert!(recorded["capabilities"]["udp_multicast"]["available"].is_boolean());
+        assert_eq!(recorded["network_tunables"]["requested_socket_buffer_bytes"], environment.socket_buffer_bytes());
+        recorded.as_object_mut().unwrap().remove("capabilities");
+        recorded.as_object_mut().unwrap().remove("network_tunables");
+        recorded["pcap_available"] = serde_json::json!(false);
+        let old: TestEnvironment = serde_json::from_value(recorded).unwrap();
+        assert_eq!(old.capabilities.unavailable().count(), 5);
+        assert!(old.network_tunables.sysctls.is_empty());
+    }
+}
@@ -0,0 +1,386 @@
+// matter-project/analysis-core/src/plugin.rs
+/*!
+Protocol plugins - third-party analyzers run as subprocesses that exchange JSON over stdin/stdout
+
+A plugin is any executable, in any language, so a vendor can add its own
+protocol without forking the crate. `--plugin acme` runs
+`<plugin dir>/analyzer-plugin-acme` (`.exe` on Windows) in place of the
+built-in Matter measurements, while the rest of the run stays the same: run
+metadata, baseline comparison, redaction, the results directory and
+integrity sidecars. Because the result lands as `acme_real_analysis.json`,
+`merge`, `query`, `diff` and `baseline` treat it like any other protocol.
+
+Interface version 1: the analyzer writes one JSON request to the plugin's
+stdin and closes it. The plugin writes one JSON response to stdout, and
+anything it prints to stderr is shown as progress. Every response carries
+`interface_version`. A failing plugin exits non-zero, or responds with
+`{"interface_version": 1, "error": "..."}`.
+
+- `{"interface_version": 1, "command": "describe"}` is answered with
+  `{"interface_version": 1, "name", "protocol", "version", "description"}`.
+- `{"interface_version": 1, "command": "analyze", "schema_version",
+  "analyzer_version", "run_metadata", "options"}` is answered with
+  `{"interface_version": 1, "result": {...}}`. The result follows the given
+  result schema version, and the four `osi_layer_*` sections are required.
+  The analyzer fills in `protocol_name`, `analysis_timestamp`,
+  `test_environment` and `run_metadata` when they are missing, and records
+  the plugin under `plugin`.
+
+The analyzer's `examples/analyzer_plugin.rs` is a minimal plugin to start from.
+*/
+
+use crate::platform::TestEnvironment;
+use crate::run_metadata::RunMetadata;
+use crate::schema::{upgrade, RESULT_SCHEMA_VERSION};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+pub const PLUGIN_INTERFACE_VERSION: u32 = 1;
+
+/// Executables in the plugin directory named `analyzer-plugin-<name>` are plugins.
+pub const PLUGIN_PREFIX: &str = "analyzer-plugin-";
+
+/// Sections the comparison scripts read from every protocol's result.
+pub const REQUIRED_SECTIONS: [&str; 4] = [
+    "osi_layer_4_transport",
+    "osi_layer_5_session",
+    "osi_layer_6_presentation",
+    "osi_layer_7_application",
+];
+
+#[derive(Debug, Clone)]
+pub struct PluginConfig {
+    /// Plugin name in `dir`, or a path to the executable.
+    pub plugin: String,
+    pub dir: PathBuf,
+    /// `--plugin-option key=value` pairs, passed through uninterpreted.
+    pub options: BTreeMap<String, String>,
+    pub timeout: Duration,
+}
+
+impl Default for PluginConfig {
+    fn default() -> Self {
+        Self {
+            plugin: String::new(),
+            dir: PathBuf::from("plugins"),
+            options: BTreeMap::new(),
+            timeout: Duration::from_secs(600),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum PluginToolCommand {
+    List,
+    Describe(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PluginToolConfig {
+    pub command: PluginToolCommand,
+    pub dir: PathBuf,
+}
+
+impl Default for PluginToolConfig {
+    fn default() -> Self {
+        Self { command: PluginToolCommand::List, dir: PluginConfig::default().dir }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum PluginRequest {
+    Describe,
+    Analyze {
+        schema_version: u32,
+        analyzer_version: String,
+        run_metadata: RunMetadata,
+        options: BTreeMap<String, String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginDescription {
+    pub name: String,
+    /// One word; becomes the `<protocol>_real_analysis.json` file name and the `protocol` in queries.
+    pub protocol: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+/// Stored in the result under `plugin`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginProvenance {
+    pub name: String,
+    pub version: String,
+    pub interface_version: u32,
+    pub executable: String,
+}
+
+pub struct Plugin {
+    executable: PathBuf,
+    timeout: Duration,
+}
+
+impl Plugin {
+    /// Finds a plugin by name in `dir`, or takes `spec` as a path when it names a file.
+    pub fn resolve(spec: &str, dir: &Path, timeout: Duration) -> Result<Self> {
+        let path = Path::new(spec);
+        let executable = if path.is_file() || path.components().count() > 1 {
+            path.to_path_buf()
+        } else {
+            dir.join(format!("{}{}{}", PLUGIN_PREFIX, spec, std::env::consts::EXE_SUFFIX))
+        };
+        if !executable.is_file() {
+            return Err(anyhow!("no plugin {} (looked for {})", spec, executable.display()));
+        }
+        Ok(Self { executable, timeout })
+    }
+
+    pub fn executable(&self) -> &Path {
+        &self.executable
+    }
+
+    pub fn describe(&self) -> Result<PluginDescription> {
+        let response = self.call(&PluginRequest::Describe)?;
+        let description: PluginDescription = serde_json::from_value(response)
+            .with_context(|| format!("{} gave an invalid description", self.executable.display()))?;
+        if description.protocol.is_empty() || !description.protocol.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(anyhow!("plugin protocol must be letters, digits or '-', got {:?}", description.protocol));
+        }
+        Ok(description)
+    }
+
+    /// Runs the plugin's analysis and returns its result in the current schema.
+    pub fn analyze(
+        &self,
+        description: &PluginDescription,
+        metadata: &RunMetadata,
+        options: &BTreeMap<String, String>,
+    ) -> Result<Value> {
+        let response = self.call(&PluginRequest::Analyze {
+            schema_version: RESULT_SCHEMA_VERSION,
+            analyzer_version: env!("CARGO_PKG_VERSION").to_string(),
+            run_metadata: metadata.clone(),
+            options: options.clone(),
+        })?;
+        let result = response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| anyhow!("{} responded without a result", self.executable.display()))?;
+        let provenance = PluginProvenance {
+            name: description.name.clone(),
+            version: description.version.clone(),
+            interface_version: PLUGIN_INTERFACE_VERSION,
+            executable: self.executable.display().to_string(),
+        };
+        complete_result(result, description, metadata, &provenance)
+    }
+
+    fn call(&self, request: &PluginRequest) -> Result<Value> {
+        let mut message = serde_json::to_value(request)?;
+        message["interface_version"] = json!(PLUGIN_INTERFACE_VERSION);
+
+        let mut child = Command::new(&self.executable)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .with_context(|| format!("starting {}", self.executable.display()))?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        writeln!(stdin, "{}", message).with_context(|| format!("writing to {}", self.executable.display()))?;
+        drop(stdin);
+
+        // Read on another thread so a large response cannot block the plugin while we wait on it
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let reader = std::thread::spawn(move || {
+            let mut output = String::new();
+            stdout.read_to_string(&mut output).map(|_| output)
+        });
+        let started = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if started.elapsed() > self.timeout {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(anyhow!("{} timed out after {:?}", self.executable.display(), self.timeout));
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        };
+        let output = reader.join().map_err(|_| anyhow!("reading plugin output panicked"))??;
+
+        let response: Option<Value> = serde_json::from_str(&output).ok();
+        if let Some(error) = response.as_ref().and_then(|r| r.get("error")).and_then(Value::as_str) {
+            return Err(anyhow!("plugin {} failed: {}", self.executable.display(), error));
+        }
+        if !status.success() {
+            return Err(anyhow!("plugin {} exited with {}", self.executable.display(), status));
+        }
+        let response = response.ok_or_else(|| anyhow!("{} did not write a JSON response", self.executable.display()))?;
+        match response.get("interface_version").and_then(Value::as_u64) {
+            Some(version) if version == u64::from(PLUGIN_INTERFACE_VERSION) => Ok(response),
+            version => Err(anyhow!(
+                "{} speaks plugin interface {:?}, this analyzer speaks {}",
+                self.executable.display(),
+                version,
+                PLUGIN_INTERFACE_VERSION
+            )),
+        }
+    }
+}
+
+/// Upgrades and checks a plugin's result and fills in the fields the analyzer owns.
+pub fn complete_result(
+    result: Value,
+    description: &PluginDescription,
+    metadata: &RunMetadata,
+    provenance: &PluginProvenance,
+) -> Result<Value> {
+    let mut result = upgrade(result).context("plugin result")?;
+    let fields = result.as_object_mut().expect("upgrade only accepts objects");
+    for section in REQUIRED_SECTIONS {
+        if !fields.get(section).is_some_and(Value::is_object) {
+            return Err(anyhow!("plugin result has no {} section", section));
+        }
+    }
+
+    let protocol_name = fields
+        .entry("protocol_name")
+        .or_insert_with(|| json!(format!("{}_Protocol_Analysis", description.protocol)));
+    let prefix = protocol_name.as_str().map(|name| name.split('_').next().unwrap_or(name));
+    if !prefix.is_some_and(|prefix| prefix.eq_ignore_ascii_case(&description.protocol)) {
+        return Err(anyhow!(
+            "plugin result protocol_name {} does not start with its protocol {}",
+            protocol_name,
+            description.protocol
+        ));
+    }
+    fields.entry("analysis_timestamp").or_insert_with(|| json!(chrono::Utc::now().to_rfc3339()));
+    if !fields.contains_key("test_environment") {
+        fields.insert("test_environment".to_string(), serde_json::to_value(TestEnvironment::detect())?);
+    }
+    fields.insert("run_metadata".to_string(), serde_json::to_value(metadata)?);
+    fields.insert("plugin".to_string(), serde_json::to_value(provenance)?);
+    Ok(result)
+}
+
+/// Installed plugins as `(name, executable)`, sorted by name.
+pub fn installed(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut plugins = Vec::new();
+    if !dir.is_dir() {
+        return Ok(plugins);
+    }
+    for entry in std::fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        let name = name.strip_suffix(std::env::consts::EXE_SUFFIX).unwrap_or(name);
+        if let Some(name) = name.strip_prefix(PLUGIN_PREFIX) {
+            if path.is_file() && !name.is_empty() {
+                plugins.push((name.to_string(), path.clone()));
+            }
+        }
+    }
+    plugins.sort();
+    Ok(plugins)
+}
+
+pub struct PluginTool {
+    config: PluginToolConfig,
+}
+
+impl PluginTool {
+    pub fn new(config: PluginToolConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn run(&self) -> Result<()> {
+        let timeout = Duration::from_secs(30);
+        match &self.config.command {
+            PluginToolCommand::List => {
+                let plugins = installed(&self.config.dir)?;
+                println!("\n🧩 {} plugins in {}", plugins.len(), self.config.dir.display());
+                println!("------------------------------------------------");
+                for (name, executable) in plugins {
+                    match Plugin::resolve(&executable.display().to_string(), &self.config.dir, timeout)
+                        .and_then(|plugin| plugin.describe())
+                    {
+                        Ok(description) => println!("✅ {}: {} {} - {}",
+                                                    name, description.protocol, description.version, description.description),
+                        Err(e) => println!("⚠️ {}: {:#}", name, e),
+                    }
+                }
+            }
+            PluginToolCommand::Describe(spec) => {
+                let plugin = Plugin::resolve(spec, &self.config.dir, timeout)?;
+                let description = plugin.describe()?;
+                println!("\n🧩 {} ({})", description.name, plugin.executable().display());
+                println!("------------------------------------------------");
+                println!("✅ Protocol: {}", description.protocol);
+                println!("✅ Version: {}", description.version);
+                println!("✅ Interface: {}", PLUGIN_INTERFACE_VERSION);
+                if !description.description.is_empty() {
+                    println!("✅ {}", description.description);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acme() -> (PluginDescription, PluginProvenance) {
+        let description = PluginDescription {
+            name: "acme".to_string(),
+            protocol: "Acme".to_string(),
+            version: "1.0.0".to_string(),
+            description: String::new(),
+        };
+        let provenance = PluginProvenance {
+            name: "acme".to_string(),
+            version: "1.0.0".to_string(),
+            interface_version: PLUGIN_INTERFACE_VERSION,
+            executable: "plugins/analyzer-plugin-acme".to_string(),
+        };
+        (description, provenance)
+    }
+
+    #[test]
+    fn plugin_results_are_completed_and_checked() {
+        let (description, provenance) = acme();
+        let mut metadata = RunMetadata::default();
+        metadata.add_tag("network=wifi5").unwrap();
+        let sections = json!({
+            "schema_version": RESULT_SCHEMA_VERSION,
+            "osi_layer_4_transport": {"udp_discovery_time_ms": 3.0},
+            "osi_layer_5_session": {},
+            "osi_layer_6_presentation": {},
+            "osi_layer_7_application": {},
+            "test_environment": {"device_class": "constrained"}
+        });
+
+        let result = complete_result(sections.clone(), &description, &metadata, &provenance).unwrap();
+        assert_eq!(result["protocol_name"], json!("Acme_Protocol_Analysis"));
+        assert_eq!(result["run_metadata"]["tags"]["network"], json!("wifi5"));
+        assert_eq!(result["test_environment"], json!({"device_class": "constrained"}));
+        assert_eq!(result["plugin"]["version"], json!("1.0.0"));
+
+        let mut other = sections.clone();
+        other["protocol_name"] = json!("Matter_Protocol_Analysis");
+        assert!(complete_result(other, &description, &metadata, &provenance).is_err());
+        let mut partial = sections;
+        partial.as_object_mut().unwrap().remove("osi_layer_5_session");
+        assert!(complete_result(partial, &description, &metadata, &provenance).is_err());
+    }
+}
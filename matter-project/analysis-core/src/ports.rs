@@ -0,0 +1,125 @@
+// matter-project/analysis-core/src/ports.rs
+/*!
+Port allocation - reserved ephemeral ports, so parallel runs and CI jobs do not collide
+
+[`PortAllocator::reserve`] asks the OS for a free port and keeps it bound for
+both UDP and TCP under a name until the listener that needs it takes the
+socket with [`take_udp`](PortAllocator::take_udp) or
+[`take_tcp`](PortAllocator::take_tcp). Between reserving and listening the
+port is never free, so a second analyzer, agent or fixture set on the same
+host cannot grab it. Processes that only learn their peer's ports at run
+time read them from a ports file written with
+[`write_ports_file`](PortAllocator::write_ports_file), a JSON object of
+name → port.
+*/
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, UdpSocket};
+use std::path::Path;
+
+/// Attempts at finding a port that is free for UDP as well as TCP.
+const RESERVE_ATTEMPTS: u32 = 16;
+
+struct Reservation {
+    addr: SocketAddr,
+    udp: Option<UdpSocket>,
+    tcp: Option<TcpListener>,
+}
+
+pub struct PortAllocator {
+    ip: IpAddr,
+    reservations: BTreeMap<String, Reservation>,
+}
+
+impl PortAllocator {
+    pub fn new(ip: IpAddr) -> Self {
+        Self { ip, reservations: BTreeMap::new() }
+    }
+
+    pub fn loopback() -> Self {
+        Self::new(IpAddr::V4(Ipv4Addr::LOCALHOST))
+    }
+
+    /// Reserves a port that is free for both UDP and TCP and holds it under `name`.
+    pub fn reserve(&mut self, name: &str) -> Result<SocketAddr> {
+        if self.reservations.contains_key(name) {
+            return Err(anyhow!("port {} is already reserved", name));
+        }
+        for _ in 0..RESERVE_ATTEMPTS {
+            let tcp = TcpListener::bind(SocketAddr::new(self.ip, 0))?;
+            let addr = tcp.local_addr()?;
+            // Another process may hold the UDP side of the port the OS picked for TCP
+            if let Ok(udp) = UdpSocket::bind(addr) {
+                self.reservations.insert(name.to_string(), Reservation { addr, udp: Some(udp), tcp: Some(tcp) });
+                return Ok(addr);
+            }
+        }
+        Err(anyhow!("no port free for both UDP and TCP on {} after {} attempts", self.ip, RESERVE_ATTEMPTS))
+    }
+
+    pub fn addr(&self, name: &str) -> Option<SocketAddr> {
+        self.reservations.get(name).map(|reservation| reservation.addr)
+    }
+
+    /// Hands over the reserved UDP socket; the TCP side stays reserved. Needs a tokio runtime.
+    pub fn take_udp(&mut self, name: &str) -> Result<tokio::net::UdpSocket> {
+        let socket = self.reservation(name)?.udp.take().ok_or_else(|| anyhow!("UDP port {} was already taken", name))?;
+        socket.set_nonblocking(true)?;
+        Ok(tokio::net::UdpSocket::from_std(socket)?)
+    }
+
+    /// Hands over the reserved TCP listener; the UDP side stays reserved. Needs a tokio runtime.
+    pub fn take_tcp(&mut self, name: &str) -> Result<tokio::net::TcpListener> {
+        let listener = self.reservation(name)?.tcp.take().ok_or_else(|| anyhow!("TCP port {} was already taken", name))?;
+        listener.set_nonblocking(true)?;
+        Ok(tokio::net::TcpListener::from_std(listener)?)
+    }
+
+    /// Every reservation, taken or not, by name.
+    pub fn ports(&self) -> BTreeMap<String, u16> {
+        self.reservations.iter().map(|(name, reservation)| (name.clone(), reservation.addr.port())).collect()
+    }
+
+    pub fn write_ports_file(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(&self.ports())?)
+            .with_context(|| format!("writing ports file {}", path.display()))
+    }
+
+    fn reservation(&mut self, name: &str) -> Result<&mut Reservation> {
+        self.reservations.get_mut(name).ok_or_else(|| anyhow!("no port reserved as {}", name))
+    }
+}
+
+pub fn read_ports_file(path: &Path) -> Result<BTreeMap<String, u16>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("reading ports file {}", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("parsing ports file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserved_ports_stay_bound_until_taken() {
+        let mut ports = PortAllocator::loopback();
+        let echo = ports.reserve("udp_echo").unwrap();
+        let sink = ports.reserve("tcp_sink").unwrap();
+        assert_ne!(echo.port(), sink.port());
+        assert!(ports.reserve("udp_echo").is_err());
+        assert!(UdpSocket::bind(echo).is_err());
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_io().build().unwrap();
+        let _guard = runtime.enter();
+        assert_eq!(ports.take_udp("udp_echo").unwrap().local_addr().unwrap(), echo);
+        assert!(ports.take_udp("udp_echo").is_err());
+        assert_eq!(ports.take_tcp("tcp_sink").unwrap().local_addr().unwrap(), sink);
+
+        let path = std::env::temp_dir().join(format!("analyzer-ports-{}.json", std::process::id()));
+        ports.write_ports_file(&path).unwrap();
+        let read = read_ports_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read, ports.ports());
+        assert_eq!(read["tcp_sink"], sink.port());
+    }
+}
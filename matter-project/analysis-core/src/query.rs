@@ -0,0 +1,507 @@
+// matter-project/analysis-core/src/query.rs
+/*!
+Result queries - filters stored results down to rows of metrics and emits them as CSV or JSON
+
+Queries run over result files, directories of them and merged campaign
+datasets, loaded the same way `merge` loads them. Every numeric metric of
+every run becomes one row: run key, protocol, timestamp, host, device class,
+experiment ID, tags, metric path (as printed by `diff`) and value. Runs can
+also be selected by `tag.<key>` and by their notes. A query is a list of conditions
+joined by `AND`, optionally followed by `ORDER BY` and `LIMIT`:
+
+```text
+protocol=matter AND metric=commissioning_time_ms ORDER BY timestamp
+metric ~ "*latency*" AND value > 100 ORDER BY value DESC LIMIT 10
+```
+
+Operators are `=`, `!=`, `<`, `<=`, `>`, `>=` and `~` (contains, or a glob
+when the pattern has `*`). Text comparisons ignore case, `value` compares as
+a number, and `metric=name` matches either the full path or its last segment.
+
+Values are converted to the query's [`Units`] before the conditions see
+them, and each row says which unit its value is in.
+*/
+
+use crate::campaign::{CampaignMerger, MergeConfig};
+use crate::diff::flatten;
+use crate::run_metadata::{tags_text, RunMetadata};
+use crate::units::{Units, KEYED_BY_METRIC};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            other => Err(anyhow!("unknown query format: {} (expected csv or json)", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryConfig {
+    pub query: String,
+    /// Result files, directories or merged datasets; see [`QueryConfig::default_inputs`] when empty.
+    pub inputs: Vec<PathBuf>,
+    pub format: OutputFormat,
+    pub units: Units,
+}
+
+impl Default for QueryConfig {
+    fn default() -> Self {
+        Self {
+            query: String::new(),
+            inputs: Vec::new(),
+            format: OutputFormat::Csv,
+            units: Units::default(),
+        }
+    }
+}
+
+impl QueryConfig {
+    /// The merged campaign when there is one, the results directory otherwise.
+    pub fn default_inputs() -> Vec<PathBuf> {
+        let campaign = MergeConfig::default().output;
+        if campaign.is_file() {
+            vec![campaign]
+        } else {
+            vec![PathBuf::from("../results")]
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryRow {
+    pub key: String,
+    pub protocol: String,
+    pub timestamp: Option<String>,
+    pub host: Option<String>,
+    pub device_class: Option<String>,
+    pub experiment_id: Option<String>,
+    pub tags: BTreeMap<String, String>,
+    pub notes: Vec<String>,
+    pub metric: String,
+    pub value: f64,
+    /// The unit `value` is in, when the metric's name gives one.
+    pub unit: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Field {
+    Key,
+    Protocol,
+    Timestamp,
+    Host,
+    DeviceClass,
+    Experiment,
+    /// `tag.<key>`: the run's value for that tag.
+    Tag(String),
+    /// Every note of the run, one per line.
+    Note,
+    Metric,
+    Value,
+}
+
+impl FromStr for Field {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "key" | "run" => Ok(Field::Key),
+            "protocol" => Ok(Field::Protocol),
+            "timestamp" | "time" => Ok(Field::Timestamp),
+            "host" => Ok(Field::Host),
+            "device_class" => Ok(Field::DeviceClass),
+            "experiment" | "experiment_id" => Ok(Field::Experiment),
+            "note" | "notes" => Ok(Field::Note),
+            "metric" => Ok(Field::Metric),
+            "value" => Ok(Field::Value),
+            tag if tag.starts_with("tag.") && tag.len() > 4 => Ok(Field::Tag(s["tag.".len()..].to_string())),
+            other => Err(anyhow!(
+                "unknown query field: {} (expected key, protocol, timestamp, host, device_class, experiment, tag.<key>, note, metric or value)",
+                other
+            )),
+        }
+    }
+}
+
+impl Field {
+    fn text<'a>(&self, row: &'a QueryRow) -> Option<Cow<'a, str>> {
+        let borrowed = |text: &'a str| Some(Cow::Borrowed(text));
+        match self {
+            Field::Key => borrowed(&row.key),
+            Field::Protocol => borrowed(&row.protocol),
+            Field::Timestamp => row.timestamp.as_deref().and_then(borrowed),
+            Field::Host => row.host.as_deref().and_then(borrowed),
+            Field::DeviceClass => row.device_class.as_deref().and_then(borrowed),
+            Field::Experiment => row.experiment_id.as_deref().and_then(borrowed),
+            // Tag keys ignore case like the rest of the query
+            Field::Tag(key) => row.tags.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).and_then(|(_, v)| borrowed(v)),
+            Field::Note if row.notes.is_empty() => None,
+            Field::Note => Some(Cow::Owned(row.notes.join("\n"))),
+            Field::Metric => borrowed(&row.metric),
+            Field::Value => None,
+        }
+    }
+
+    fn compare(&self, a: &QueryRow, b: &QueryRow) -> Ordering {
+        match self {
+            Field::Value => a.value.total_cmp(&b.value),
+            _ => self.text(a).cmp(&self.text(b)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Like,
+}
+
+#[derive(Debug, Clone)]
+pub struct Condition {
+    pub field: Field,
+    pub operator: Operator,
+    pub value: String,
+}
+
+impl Condition {
+    fn matches(&self, row: &QueryRow) -> Result<bool> {
+        let ordering = if self.field == Field::Value {
+            if self.operator == Operator::Like {
+                return Err(anyhow!("value cannot be matched with ~"));
+            }
+            let expected: f64 = self.value.parse().map_err(|_| anyhow!("value must be compared with a number, not {}", self.value))?;
+            row.value.total_cmp(&expected)
+        } else {
+            let Some(actual) = self.field.text(row) else {
+                return Ok(self.operator == Operator::Ne);
+            };
+            let actual = actual.to_lowercase();
+            let expected = self.value.to_lowercase();
+            match self.operator {
+                Operator::Like if expected.contains('*') => return Ok(glob_matches(&expected, &actual)),
+                Operator::Like => return Ok(actual.contains(&expected)),
+                // A bare metric name matches wherever it sits in the result
+                Operator::Eq | Operator::Ne if self.field == Field::Metric => {
+                    let last = actual.rsplit('.').next().unwrap_or(&actual);
+                    let equal = actual == expected || last == expected;
+                    return Ok(equal == (self.operator == Operator::Eq));
+                }
+                _ => actual.as_str().cmp(expected.as_str()),
+            }
+        };
+        Ok(match self.operator {
+            Operator::Eq => ordering == Ordering::Equal,
+            Operator::Ne => ordering != Ordering::Equal,
+            Operator::Lt => ordering == Ordering::Less,
+            Operator::Le => ordering != Ordering::Greater,
+            Operator::Gt => ordering == Ordering::Greater,
+            Operator::Ge => ordering != Ordering::Less,
+            Operator::Like => unreachable!("handled above"),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub conditions: Vec<Condition>,
+    /// Field and whether it sorts descending.
+    pub order_by: Option<(Field, bool)>,
+    pub limit: Option<usize>,
+}
+
+impl FromStr for Query {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let tokens = tokenize(s)?;
+        let mut tokens = tokens.iter().map(String::as_str).peekable();
+        let keyword = |token: Option<&&str>, word: &str| token.is_some_and(|t| t.eq_ignore_ascii_case(word));
+        let mut query = Query::default();
+
+        while tokens.peek().is_some() && !keyword(tokens.peek(), "order") && !keyword(tokens.peek(), "limit") {
+            if !query.conditions.is_empty() {
+                if !keyword(tokens.peek(), "and") {
+                    return Err(anyhow!("expected AND, ORDER BY or LIMIT, found {}", tokens.peek().unwrap()));
+                }
+                tokens.next();
+            }
+            let field = tokens.next().ok_or_else(|| anyhow!("expected a condition after AND"))?.parse()?;
+            let operator = match tokens.next() {
+                Some("=") | Some("==") => Operator::Eq,
+                Some("!=") => Operator::Ne,
+                Some("<") => Operator::Lt,
+                Some("<=") => Operator::Le,
+                Some(">") => Operator::Gt,
+                Some(">=") => Operator::Ge,
+                Some("~") => Operator::Like,
+                other => return Err(anyhow!("expected an operator after {:?}, found {:?}", field, other)),
+            };
+            let value = tokens.next().ok_or_else(|| anyhow!("expected a value after {:?}", operator))?.to_string();
+            query.conditions.push(Condition { field, operator, value });
+        }
+
+        if keyword(tokens.peek(), "order") {
+            tokens.next();
+            if !keyword(tokens.next().as_ref(), "by") {
+                return Err(anyhow!("expected BY after ORDER"));
+            }
+            let field = tokens.next().ok_or_else(|| anyhow!("expected a field after ORDER BY"))?.parse()?;
+            let descending = keyword(tokens.peek(), "desc");
+            if descending || keyword(tokens.peek(), "asc") {
+                tokens.next();
+            }
+            query.order_by = Some((field, descending));
+        }
+
+        if keyword(tokens.peek(), "limit") {
+            tokens.next();
+            query.limit = Some(tokens.next().ok_or_else(|| anyhow!("expected a number after LIMIT"))?.parse()?);
+        }
+
+        if let Some(extra) = tokens.next() {
+            return Err(anyhow!("unexpected {} at the end of the query", extra));
+        }
+        Ok(query)
+    }
+}
+
+/// Splits a query into words, quoted strings and operators, so `protocol=matter` needs no spaces.
+fn tokenize(query: &str) -> Result<Vec<String>> {
+    let is_operator = |c: char| matches!(c, '=' | '!' | '<' | '>' | '~');
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut quoted = String::new();
+            loop {
+                match chars.next() {
+                    Some(q) if q == c => break,
+                    Some(q) => quoted.push(q),
+                    None => return Err(anyhow!("unterminated quote in query")),
+                }
+            }
+            tokens.push(quoted);
+        } else if is_operator(c) {
+            chars.next();
+            let mut operator = c.to_string();
+            if let Some(&'=') = chars.peek() {
+                operator.push('=');
+                chars.next();
+            }
+            tokens.push(operator);
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || is_operator(c) || c == '"' || c == '\'' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(word);
+        }
+    }
+    Ok(tokens)
+}
+
+/// Whether `pattern` names the metric at `path`: the full path, a trailing part of it, or a glob when it has `*`.
+/// Retry counts share their metric's name, so they only match a pattern that names their section.
+pub(crate) fn metric_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, path) = (pattern.to_lowercase(), path.to_lowercase());
+    if KEYED_BY_METRIC.iter().any(|section| path.starts_with(&format!("{}.", section)))
+        && !KEYED_BY_METRIC.iter().any(|section| pattern.starts_with(section))
+    {
+        return false;
+    }
+    if pattern.contains('*') {
+        return glob_matches(&pattern, &path);
+    }
+    path == pattern || path.ends_with(&format!(".{}", pattern))
+}
+
+/// `*` matches any run of characters; everything else matches itself.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || text.len() < first.len() + last.len() || !text.ends_with(last) {
+        return false;
+    }
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Runs `query` over every numeric metric of the inputs, converted to `units`.
+pub fn run_query(query: &Query, inputs: Vec<PathBuf>, units: &Units) -> Result<Vec<QueryRow>> {
+    let dataset = CampaignMerger::new(MergeConfig { inputs, ..MergeConfig::default() }).merge()?;
+    let mut rows = Vec::new();
+    for run in &dataset.runs {
+        let mut metrics = BTreeMap::new();
+        flatten(&run.result, String::new(), &mut metrics);
+        let provenance = &run.provenance;
+        let metadata = RunMetadata::of(&run.result);
+        let timestamp = provenance.timestamp();
+        for (metric, value) in metrics {
+            let (value, unit) = units.convert(&metric, value);
+            let row = QueryRow {
+                key: run.key.clone(),
+                protocol: provenance.protocol.clone(),
+                timestamp: timestamp.clone(),
+                host: provenance.host.clone(),
+                device_class: provenance.device_class.clone(),
+                experiment_id: metadata.experiment_id.clone(),
+                tags: metadata.tags.clone(),
+                notes: metadata.notes.clone(),
+                metric,
+                value,
+                unit: unit.map(|unit| unit.to_string()),
+            };
+            let mut keep = true;
+            for condition in &query.conditions {
+                keep = keep && condition.matches(&row)?;
+            }
+            if keep {
+                rows.push(row);
+            }
+        }
+    }
+
+    if let Some((field, descending)) = &query.order_by {
+        rows.sort_by(|a, b| {
+            let ordering = field.compare(a, b);
+            if *descending { ordering.reverse() } else { ordering }
+        });
+    }
+    if let Some(limit) = query.limit {
+        rows.truncate(limit);
+    }
+    Ok(rows)
+}
+
+pub struct ResultQuery {
+    config: QueryConfig,
+}
+
+impl ResultQuery {
+    pub fn new(config: QueryConfig) -> Self {
+        Self { config }
+    }
+
+    /// Prints the matching rows to stdout; the row count goes to stderr so output can be piped.
+    pub fn run(&self) -> Result<Vec<QueryRow>> {
+        let query: Query = self.config.query.parse()?;
+        let inputs = if self.config.inputs.is_empty() { QueryConfig::default_inputs() } else { self.config.inputs.clone() };
+        let rows = run_query(&query, inputs, &self.config.units)?;
+
+        match self.config.format {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&rows)?),
+            OutputFormat::Csv => {
+                println!("key,protocol,timestamp,host,device_class,experiment_id,tags,metric,value,unit");
+                for row in &rows {
+                    let tags = tags_text(&row.tags);
+                    let fields = [
+                        row.key.as_str(),
+                        row.protocol.as_str(),
+                        row.timestamp.as_deref().unwrap_or_default(),
+                        row.host.as_deref().unwrap_or_default(),
+                        row.device_class.as_deref().unwrap_or_default(),
+                        row.experiment_id.as_deref().unwrap_or_default(),
+                        &tags,
+                        row.metric.as_str(),
+                    ];
+                    let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+                    println!("{},{},{}", fields.join(","), row.value, row.unit.as_deref().unwrap_or_default());
+                }
+            }
+        }
+        eprintln!("🔎 {} rows", rows.len());
+        Ok(rows)
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(protocol: &str, metric: &str, value: f64) -> QueryRow {
+        QueryRow {
+            key: format!("{}/run", protocol.to_lowercase()),
+            protocol: protocol.to_string(),
+            timestamp: Some("2025-06-23T10:00:00Z".to_string()),
+            host: None,
+            device_class: None,
+            experiment_id: Some("sweep-1".to_string()),
+            tags: BTreeMap::from([("network".to_string(), "wifi5".to_string())]),
+            notes: vec!["door open".to_string()],
+            metric: metric.to_string(),
+            value,
+            unit: None,
+        }
+    }
+
+    fn matches(query: &str, row: &QueryRow) -> bool {
+        let query: Query = query.parse().unwrap();
+        query.conditions.iter().all(|c| c.matches(row).unwrap())
+    }
+
+    #[test]
+    fn parses_conditions_order_and_limit() {
+        let query: Query = "protocol=matter AND metric ~ \"*time_ms\" AND value >= 10 ORDER BY value DESC LIMIT 5".parse().unwrap();
+        assert_eq!(query.conditions.len(), 3);
+        assert_eq!(query.conditions[2].operator, Operator::Ge);
+        assert_eq!(query.order_by, Some((Field::Value, true)));
+        assert_eq!(query.limit, Some(5));
+        assert!("protocol matter".parse::<Query>().is_err());
+        assert!("protocol=matter ORDER timestamp".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn metric_names_match_last_segment() {
+        let commissioning = row("Matter", "osi_layer_5_session.commissioning_time_ms", 452.0);
+        assert!(matches("protocol=MATTER AND metric=commissioning_time_ms", &commissioning));
+        assert!(matches("metric=osi_layer_5_session.commissioning_time_ms AND value>400", &commissioning));
+        assert!(!matches("metric=session", &commissioning));
+        assert!(matches("metric~session", &commissioning));
+        assert!(matches("metric ~ 'osi_*_ms'", &commissioning));
+        assert!(!matches("protocol!=matter", &commissioning));
+        assert!(matches("tag.network=WiFi5 AND experiment=sweep-1 AND note~door", &commissioning));
+        assert!(!matches("tag.location=lab2", &commissioning));
+    }
+}
@@ -0,0 +1,535 @@
+// matter-project/analysis-core/src/redact.rs
+/*!
+Redaction - strips hostnames, usernames and MAC/IP addresses from results and captures before publication
+
+Only text is rewritten; numbers in results, and packet sizes and timestamps in
+captures, are left exactly as measured. Each address is replaced by a stable
+pseudonym (IPv4 from the 198.18.0.0/15 benchmarking range, IPv6 from
+2001:db8::/32, locally administered MACs), so which packets share a host is
+still visible. One pseudonym table covers every file in a single invocation.
+Loopback, unspecified, multicast and broadcast addresses carry no identity
+and are kept.
+
+Sensitive words are this host's name, the current user, their home directory
+and any `--redact-term`. In results and text files they become
+`<redacted-host>` and friends. In captures they are masked with `x` so lengths
+do not change, and known addresses are also replaced where they appear inside
+payloads (mDNS records, Matter hostnames built from the MAC). Captures must be
+classic pcap with Ethernet or raw IP framing; IPv4 header and TCP/UDP
+checksums are recomputed for packets that were captured whole.
+*/
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct RedactConfig {
+    /// Result JSON, pcap captures or text files.
+    pub files: Vec<PathBuf>,
+    pub output_dir: PathBuf,
+    /// Extra words to redact besides the host and user names.
+    pub terms: Vec<String>,
+}
+
+impl Default for RedactConfig {
+    fn default() -> Self {
+        Self {
+            files: Vec::new(),
+            output_dir: PathBuf::from("../results/redacted"),
+            terms: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct RedactionCounts {
+    pub addresses: usize,
+    pub macs: usize,
+    pub terms: usize,
+}
+
+struct Term {
+    text: String,
+    replacement: &'static str,
+}
+
+pub struct Redactor {
+    terms: Vec<Term>,
+    ipv4: HashMap<Ipv4Addr, Ipv4Addr>,
+    ipv6: HashMap<Ipv6Addr, Ipv6Addr>,
+    macs: HashMap<[u8; 6], [u8; 6]>,
+    pub counts: RedactionCounts,
+}
+
+impl Redactor {
+    /// Redacts this host's name, the current user and home directory, and `extra_terms`.
+    pub fn new(extra_terms: &[String]) -> Self {
+        let mut terms = Vec::new();
+        if let Some(home) = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) {
+            terms.push((home.to_string_lossy().into_owned(), "~"));
+        }
+        if let Some(host) = local_hostname() {
+            terms.push((host, "<redacted-host>"));
+        }
+        if let Some(user) = std::env::var("USER").ok().or_else(|| std::env::var("USERNAME").ok()) {
+            terms.push((user, "<redacted-user>"));
+        }
+        terms.extend(extra_terms.iter().map(|t| (t.clone(), "<redacted>")));
+        Self::with_terms(terms)
+    }
+
+    /// Redacts exactly `terms` (word, replacement) plus addresses; longer words are matched first.
+    pub fn with_terms(terms: Vec<(String, &'static str)>) -> Self {
+        let mut terms: Vec<Term> = terms
+            .into_iter()
+            .filter(|(text, _)| !text.trim().is_empty())
+            .map(|(text, replacement)| Term { text, replacement })
+            .collect();
+        terms.sort_by_key(|t| std::cmp::Reverse(t.text.len()));
+        Self { terms, ipv4: HashMap::new(), ipv6: HashMap::new(), macs: HashMap::new(), counts: RedactionCounts::default() }
+    }
+
+    fn map_ipv4(&mut self, addr: Ipv4Addr) -> Ipv4Addr {
+        if addr.is_loopback() || addr.is_unspecified() || addr.is_multicast() || addr.is_broadcast() {
+            return addr;
+        }
+        self.counts.addresses += 1;
+        let next = self.ipv4.len() as u32 + 1;
+        *self.ipv4.entry(addr).or_insert_with(|| Ipv4Addr::from(u32::from(Ipv4Addr::new(198, 18, 0, 0)) + next))
+    }
+
+    fn map_ipv6(&mut self, addr: Ipv6Addr) -> Ipv6Addr {
+        if addr.is_loopback() || addr.is_unspecified() || addr.is_multicast() {
+            return addr;
+        }
+        if let Some(v4) = addr.to_ipv4_mapped() {
+            return self.map_ipv4(v4).to_ipv6_mapped();
+        }
+        self.counts.addresses += 1;
+        let next = self.ipv6.len() as u128 + 1;
+        *self.ipv6.entry(addr).or_insert_with(|| Ipv6Addr::from(u128::from(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0)) + next))
+    }
+
+    fn map_mac(&mut self, mac: [u8; 6]) -> [u8; 6] {
+        // Broadcast, multicast and all-zero MACs identify nothing
+        if mac[0] & 1 == 1 || mac == [0; 6] {
+            return mac;
+        }
+        self.counts.macs += 1;
+        let next = self.macs.len() as u32 + 1;
+        *self.macs.entry(mac).or_insert_with(|| {
+            let n = next.to_be_bytes();
+            [0x02, 0, n[0], n[1], n[2], n[3]]
+        })
+    }
+
+    /// Redacts every string in a result; numbers are untouched.
+    pub fn redact_value(&mut self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.redact_text(text),
+            Value::Array(elements) => elements.iter_mut().for_each(|e| self.redact_value(e)),
+            Value::Object(fields) => fields.values_mut().for_each(|v| self.redact_value(v)),
+            _ => {}
+        }
+    }
+
+    pub fn redact_text(&mut self, text: &str) -> String {
+        let mut text = text.to_string();
+        for term in &self.terms {
+            let (found, replaced) = replace_word(&text, &term.text, term.replacement);
+            self.counts.terms += found;
+            text = replaced;
+        }
+
+        let is_token = |c: char| c.is_ascii_alphanumeric() || matches!(c, ':' | '.' | '-');
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find(is_token) {
+            out.push_str(&rest[..start]);
+            let end = rest[start..].find(|c| !is_token(c)).map_or(rest.len(), |e| start + e);
+            let token = &rest[start..end];
+            // A sentence may end right after an address
+            let trimmed = token.trim_end_matches(['.', ':', '-']);
+            match self.redact_token(trimmed) {
+                Some(replacement) => {
+                    out.push_str(&replacement);
+                    out.push_str(&token[trimmed.len()..]);
+                }
+                None => out.push_str(token),
+            }
+            rest = &rest[end..];
+        }
+        out.push_str(rest);
+
+        // Matter hostnames are the MAC in upper-case hex
+        for (mac, pseudonym) in &self.macs {
+            out = replace_word(&out, &hex(mac, true), &hex(pseudonym, true)).1;
+        }
+        out
+    }
+
+    fn redact_token(&mut self, token: &str) -> Option<String> {
+        if let Ok(SocketAddr::V4(addr)) = token.parse::<SocketAddr>() {
+            return Some(format!("{}:{}", self.map_ipv4(*addr.ip()), addr.port()));
+        }
+        match token.parse::<IpAddr>() {
+            Ok(IpAddr::V4(addr)) => return Some(self.map_ipv4(addr).to_string()),
+            // Hex words joined by `::` (`d::f`) parse as IPv6 too; addresses in results carry digits
+            Ok(IpAddr::V6(addr)) if token.contains(|c: char| c.is_ascii_digit()) => return Some(self.map_ipv6(addr).to_string()),
+            _ => {}
+        }
+        parse_mac(token).map(|(mac, separator)| {
+            let pseudonym = self.map_mac(mac);
+            pseudonym.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(&separator.to_string())
+        })
+    }
+
+    /// A copy of a classic pcap capture with addresses and sensitive payload words redacted.
+    pub fn redact_pcap(&mut self, capture: &[u8]) -> Result<Vec<u8>> {
+        if capture.len() < 24 {
+            return Err(anyhow!("too short for a pcap header"));
+        }
+        let magic = [capture[0], capture[1], capture[2], capture[3]];
+        let little_endian = match magic {
+            [0xd4, 0xc3, 0xb2, 0xa1] | [0x4d, 0x3c, 0xb2, 0xa1] => true,
+            [0xa1, 0xb2, 0xc3, 0xd4] | [0xa1, 0xb2, 0x3c, 0x4d] => false,
+            [0x0a, 0x0d, 0x0d, 0x0a] => return Err(anyhow!("pcapng is not supported; convert with `editcap -F pcap`")),
+            _ => return Err(anyhow!("not a pcap capture")),
+        };
+        let read_u32 = |bytes: &[u8]| {
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            if little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) }
+        };
+        let link_type = read_u32(&capture[20..24]);
+        if !matches!(link_type, 1 | 101 | 228 | 229) {
+            return Err(anyhow!("link type {} is not supported (Ethernet or raw IP only)", link_type));
+        }
+
+        let mut out = capture.to_vec();
+        let mut offset = 24;
+        while offset + 16 <= out.len() {
+            let captured = read_u32(&out[offset + 8..offset + 12]) as usize;
+            let original = read_u32(&out[offset + 12..offset + 16]) as usize;
+            let start = offset + 16;
+            let end = start.checked_add(captured).filter(|e| *e <= out.len()).ok_or_else(|| anyhow!("truncated record at byte {}", offset))?;
+            let complete = captured == original;
+            let packet = &mut out[start..end];
+            match link_type {
+                1 => self.redact_ethernet(packet, complete),
+                _ => self.redact_ip(packet, complete),
+            }
+            offset = end;
+        }
+        Ok(out)
+    }
+
+    fn redact_ethernet(&mut self, frame: &mut [u8], complete: bool) {
+        if frame.len() < 14 {
+            return;
+        }
+        for at in [0, 6] {
+            let mac = self.map_mac(frame[at..at + 6].try_into().expect("six bytes"));
+            frame[at..at + 6].copy_from_slice(&mac);
+        }
+        let mut header = 14;
+        let mut ether_type = u16::from_be_bytes([frame[12], frame[13]]);
+        // 802.1Q tag
+        if ether_type == 0x8100 && frame.len() >= 18 {
+            ether_type = u16::from_be_bytes([frame[16], frame[17]]);
+            header = 18;
+        }
+        let payload = &mut frame[header..];
+        match ether_type {
+            0x0800 | 0x86dd => self.redact_ip(payload, complete),
+            // Ethernet/IPv4 ARP: sender and target hardware and protocol addresses
+            0x0806 if payload.len() >= 28 => {
+                for (mac_at, ip_at) in [(8, 14), (18, 24)] {
+                    let mac = self.map_mac(payload[mac_at..mac_at + 6].try_into().expect("six bytes"));
+                    payload[mac_at..mac_at + 6].copy_from_slice(&mac);
+                    let ip = self.map_ipv4(Ipv4Addr::from(<[u8; 4]>::try_from(&payload[ip_at..ip_at + 4]).expect("four bytes")));
+                    payload[ip_at..ip_at + 4].copy_from_slice(&ip.octets());
+                }
+            }
+            _ => self.mask_payload(payload),
+        }
+    }
+
+    fn redact_ip(&mut self, packet: &mut [u8], complete: bool) {
+        match packet.first().map(|b| b >> 4) {
+            Some(4) if packet.len() >= 20 => {
+                let header = (packet[0] & 0x0f) as usize * 4;
+                if header < 20 || packet.len() < header {
+                    return;
+                }
+                for at in [12, 16] {
+                    let addr = self.map_ipv4(Ipv4Addr::from(<[u8; 4]>::try_from(&packet[at..at + 4]).expect("four bytes")));
+                    packet[at..at + 4].copy_from_slice(&addr.octets());
+                }
+                let total = (u16::from_be_bytes([packet[2], packet[3]]) as usize).clamp(header, packet.len());
+                let fragmented = packet[6] & 0x20 != 0 || u16::from_be_bytes([packet[6], packet[7]]) & 0x1fff != 0;
+                let addresses = packet[12..20].to_vec();
+                let protocol = packet[9];
+                self.redact_transport(protocol, &mut packet[header..total], &addresses, complete && !fragmented, false);
+                packet[10..12].copy_from_slice(&[0, 0]);
+                let sum = checksum(&[&packet[..header]]);
+                packet[10..12].copy_from_slice(&sum.to_be_bytes());
+            }
+            Some(6) if packet.len() >= 40 => {
+                for at in [8, 24] {
+                    let addr = self.map_ipv6(Ipv6Addr::from(<[u8; 16]>::try_from(&packet[at..at + 16]).expect("sixteen bytes")));
+                    packet[at..at + 16].copy_from_slice(&addr.octets());
+                }
+                let total = (40 + u16::from_be_bytes([packet[4], packet[5]]) as usize).min(packet.len());
+                let addresses = packet[8..40].to_vec();
+                let next_header = packet[6];
+                self.redact_transport(next_header, &mut packet[40..total], &addresses, complete, true);
+            }
+            _ => self.mask_payload(packet),
+        }
+    }
+
+    /// Masks the payload and recomputes the TCP/UDP checksum when the whole segment was captured.
+    fn redact_transport(&mut self, protocol: u8, segment: &mut [u8], addresses: &[u8], complete: bool, ipv6: bool) {
+        let (header, checksum_at) = match protocol {
+            17 if segment.len() >= 8 => (8, 6),
+            6 if segment.len() >= 20 => (((segment[12] >> 4) as usize * 4).clamp(20, segment.len()), 16),
+            _ => {
+                self.mask_payload(segment);
+                return;
+            }
+        };
+        self.mask_payload(&mut segment[header..]);
+        // IPv4 UDP may leave the checksum out
+        let unused = protocol == 17 && !ipv6 && segment[6..8] == [0, 0];
+        if !complete || unused {
+            return;
+        }
+        segment[checksum_at..checksum_at + 2].copy_from_slice(&[0, 0]);
+        let length = (segment.len() as u16).to_be_bytes();
+        let mut sum = checksum(&[addresses, &[0, protocol], &length, segment]);
+        if protocol == 17 && sum == 0 {
+            sum = 0xffff;
+        }
+        segment[checksum_at..checksum_at + 2].copy_from_slice(&sum.to_be_bytes());
+    }
+
+    /// Same-length replacement so packet sizes stay as measured.
+    fn mask_payload(&mut self, payload: &mut [u8]) {
+        let mut replacements: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+        for (mac, pseudonym) in &self.macs {
+            replacements.push((mac.to_vec(), pseudonym.to_vec()));
+            for upper in [true, false] {
+                replacements.push((hex(mac, upper).into_bytes(), hex(pseudonym, upper).into_bytes()));
+            }
+        }
+        replacements.extend(self.ipv4.iter().map(|(a, p)| (a.octets().to_vec(), p.octets().to_vec())));
+        replacements.extend(self.ipv6.iter().map(|(a, p)| (a.octets().to_vec(), p.octets().to_vec())));
+        for (from, to) in &replacements {
+            let mut at = 0;
+            while let Some(found) = find(&payload[at..], from) {
+                payload[at + found..at + found + to.len()].copy_from_slice(to);
+                at += found + from.len();
+            }
+        }
+
+        for term in &self.terms {
+            let needle = term.text.to_ascii_lowercase().into_bytes();
+            let mut at = 0;
+            while at + needle.len() <= payload.len() {
+                let candidate = &payload[at..at + needle.len()];
+                let bounded = (at == 0 || !payload[at - 1].is_ascii_alphanumeric())
+                    && payload.get(at + needle.len()).is_none_or(|b| !b.is_ascii_alphanumeric());
+                if bounded && candidate.eq_ignore_ascii_case(&needle) {
+                    payload[at..at + needle.len()].fill(b'x');
+                    self.counts.terms += 1;
+                    at += needle.len();
+                } else {
+                    at += 1;
+                }
+            }
+        }
+    }
+}
+
+fn local_hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty() && host != "localhost")
+}
+
+/// Case-insensitive replacement of `word` where it is not part of a longer alphanumeric word.
+fn replace_word(text: &str, word: &str, replacement: &str) -> (usize, String) {
+    let lower = text.to_ascii_lowercase();
+    let needle = word.to_ascii_lowercase();
+    let mut out = String::with_capacity(text.len());
+    let mut found = 0;
+    let mut last = 0;
+    for (at, _) in lower.match_indices(&needle) {
+        if at < last {
+            continue;
+        }
+        let before = text[..at].chars().next_back();
+        let after = text[at + needle.len()..].chars().next();
+        if before.is_some_and(|c| c.is_alphanumeric()) || after.is_some_and(|c| c.is_alphanumeric()) {
+            continue;
+        }
+        out.push_str(&text[last..at]);
+        out.push_str(replacement);
+        last = at + needle.len();
+        found += 1;
+    }
+    out.push_str(&text[last..]);
+    (found, out)
+}
+
+fn parse_mac(token: &str) -> Option<([u8; 6], char)> {
+    let separator = token.chars().nth(2).filter(|c| *c == ':' || *c == '-')?;
+    let parts: Vec<&str> = token.split(separator).collect();
+    if parts.len() != 6 || parts.iter().any(|p| p.len() != 2) {
+        return None;
+    }
+    let mut mac = [0u8; 6];
+    for (byte, part) in mac.iter_mut().zip(&parts) {
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+    Some((mac, separator))
+}
+
+fn hex(bytes: &[u8], upper: bool) -> String {
+    bytes.iter().map(|b| if upper { format!("{:02X}", b) } else { format!("{:02x}", b) }).collect()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Internet checksum over the concatenation of `chunks`.
+fn checksum(chunks: &[&[u8]]) -> u16 {
+    let mut sum: u32 = 0;
+    let mut bytes = chunks.iter().flat_map(|chunk| chunk.iter().copied());
+    while let Some(high) = bytes.next() {
+        let low = bytes.next().unwrap_or(0);
+        sum += u16::from_be_bytes([high, low]) as u32;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+pub struct RedactTool {
+    config: RedactConfig,
+}
+
+impl RedactTool {
+    pub fn new(config: RedactConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn run(&self) -> Result<RedactionCounts> {
+        println!("\n🕶️ Redacting {} files into {}", self.config.files.len(), self.config.output_dir.display());
+        println!("------------------------------------------------");
+        std::fs::create_dir_all(&self.config.output_dir)
+            .with_context(|| format!("creating {}", self.config.output_dir.display()))?;
+
+        let mut redactor = Redactor::new(&self.config.terms);
+        for file in &self.config.files {
+            let bytes = std::fs::read(file).with_context(|| format!("reading {}", file.display()))?;
+            let before = redactor.counts;
+            let extension = file.extension().map(|e| e.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+            let redacted = match extension.as_str() {
+                "pcap" | "cap" => redactor.redact_pcap(&bytes).with_context(|| format!("redacting {}", file.display()))?,
+                "json" => {
+                    let mut value: Value = serde_json::from_slice(&bytes).with_context(|| format!("parsing {}", file.display()))?;
+                    redactor.redact_value(&mut value);
+                    serde_json::to_vec_pretty(&value)?
+                }
+                _ => {
+                    let text = String::from_utf8(bytes).map_err(|_| anyhow!("{} is neither JSON, pcap nor text", file.display()))?;
+                    redactor.redact_text(&text).into_bytes()
+                }
+            };
+            let name = file.file_name().ok_or_else(|| anyhow!("{} has no file name", file.display()))?;
+            let output = self.config.output_dir.join(name);
+            if output.canonicalize().ok() == file.canonicalize().ok() {
+                return Err(anyhow!("refusing to overwrite {} with its redacted copy", file.display()));
+            }
+            std::fs::write(&output, redacted).with_context(|| format!("writing {}", output.display()))?;
+            let counts = redactor.counts;
+            println!("✅ {} → {} ({} addresses, {} MACs, {} words)",
+                     file.display(), output.display(),
+                     counts.addresses - before.addresses, counts.macs - before.macs, counts.terms - before.terms);
+        }
+        Ok(redactor.counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn redactor() -> Redactor {
+        Redactor::with_terms(vec![("/home/alice".to_string(), "~"), ("raspberrypi".to_string(), "<redacted-host>"), ("alice".to_string(), "<redacted-user>")])
+    }
+
+    #[test]
+    fn results_keep_numbers_and_lose_identities() {
+        let mut result = json!({
+            "source": "/home/alice/capabilities on raspberrypi.local",
+            "agent": "192.168.1.20:7700",
+            "peer": "fe80::1ff:fe23:4567:890a",
+            "loopback": "127.0.0.1",
+            "adapter": "dc:a6:32:12:34:56 / DCA632123456",
+            "path": "std::fs and version 0.1.0",
+            "commissioning_time_ms": 452.0
+        });
+        let mut redactor = redactor();
+        redactor.redact_value(&mut result);
+        assert_eq!(result["source"], json!("~/capabilities on <redacted-host>.local"));
+        assert_eq!(result["agent"], json!("198.18.0.1:7700"));
+        assert_eq!(result["peer"], json!("2001:db8::1"));
+        assert_eq!(result["loopback"], json!("127.0.0.1"));
+        assert_eq!(result["adapter"], json!("02:00:00:00:00:01 / 020000000001"));
+        assert_eq!(result["path"], json!("std::fs and version 0.1.0"));
+        assert_eq!(result["commissioning_time_ms"], json!(452.0));
+    }
+
+    #[test]
+    fn pcap_addresses_change_and_checksums_stay_valid() {
+        // One Ethernet/IPv4/UDP packet whose payload names the host
+        let payload = b"raspberrypi.local";
+        let mut udp = vec![0x15, 0xb3, 0x15, 0xb3, 0, (8 + payload.len()) as u8, 0x12, 0x34];
+        udp.extend_from_slice(payload);
+        let mut ip = vec![0x45, 0, 0, (20 + udp.len()) as u8, 0, 0, 0x40, 0, 64, 17, 0, 0, 192, 168, 1, 20, 192, 168, 1, 30];
+        let sum = checksum(&[&ip]);
+        ip[10..12].copy_from_slice(&sum.to_be_bytes());
+        ip.extend_from_slice(&udp);
+        let mut frame = vec![0xdc, 0xa6, 0x32, 1, 2, 3, 0xdc, 0xa6, 0x32, 4, 5, 6, 0x08, 0x00];
+        frame.extend_from_slice(&ip);
+        let mut capture = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 1, 0, 0, 0];
+        capture.extend_from_slice(&[0; 8]);
+        capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        capture.extend_from_slice(&frame);
+
+        let redacted = redactor().redact_pcap(&capture).unwrap();
+        assert_eq!(redacted.len(), capture.len());
+        let frame = &redacted[40..];
+        assert_eq!(&frame[..6], &[0x02, 0, 0, 0, 0, 1]);
+        let ip = &frame[14..];
+        assert_eq!(&ip[12..20], &[198, 18, 0, 1, 198, 18, 0, 2]);
+        assert_eq!(checksum(&[&ip[..20]]), 0);
+        let udp = &ip[20..];
+        assert_eq!(&udp[8..], b"xxxxxxxxxxx.local");
+        assert_eq!(checksum(&[&ip[12..20], &[0, 17], &udp[4..6], udp]), 0);
+    }
+}
@@ -0,0 +1,234 @@
+// matter-project/analysis-core/src/results_store.rs
+/*!
+Results directory - timestamped run files, a run index, retention and gzip archiving
+
+Each run is written to `<results>/runs/<protocol>_<UTC timestamp>.json` and
+recorded in `<results>/runs/index.json` with its experiment ID and tags, so
+earlier runs are never overwritten. The newest run is also copied to the
+fixed `<protocol>_real_analysis.json` that the comparison scripts read.
+Retention (`--keep-runs`, `--keep-days`) applies to the timestamped files:
+expired runs are deleted, or with `--archive` compressed into
+`<results>/runs/archive/` and kept in the index. Archiving needs the
+`archive` feature. With `--checksum` or `--sign-key` both files get integrity
+sidecars, which follow their run into the archive.
+*/
+
+use crate::integrity::{load_key, sidecar, sign_file, write_checksum, SIDECAR_EXTENSIONS};
+use crate::run_metadata::RunMetadata;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct ResultsConfig {
+    pub dir: PathBuf,
+    /// Keep at most this many runs as plain files; older ones expire.
+    pub keep_runs: Option<usize>,
+    /// Runs saved more than this many days ago expire.
+    pub keep_days: Option<u32>,
+    /// Compress expired runs into the archive instead of deleting them.
+    pub archive: bool,
+    /// Write `.sha256` sidecars for saved runs.
+    pub checksum: bool,
+    /// Sign saved runs with this PKCS#8 Ed25519 key (implies checksums).
+    pub sign_key: Option<PathBuf>,
+}
+
+impl Default for ResultsConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("../results"),
+            keep_runs: None,
+            keep_days: None,
+            archive: false,
+            checksum: false,
+            sign_key: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Path relative to the runs directory.
+    pub file: String,
+    pub protocol: String,
+    pub saved_at: DateTime<Utc>,
+    pub experiment_id: Option<String>,
+    pub tags: BTreeMap<String, String>,
+    pub archived: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunIndex {
+    /// Oldest first.
+    pub runs: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SavedRun {
+    pub path: PathBuf,
+    pub latest: PathBuf,
+    pub archived: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+pub struct ResultsStore {
+    config: ResultsConfig,
+}
+
+impl ResultsStore {
+    pub fn new(config: ResultsConfig) -> Self {
+        Self { config }
+    }
+
+    fn runs_dir(&self) -> PathBuf {
+        self.config.dir.join("runs")
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.runs_dir().join("index.json")
+    }
+
+    pub fn index(&self) -> Result<RunIndex> {
+        let path = self.index_path();
+        if !path.is_file() {
+            return Ok(RunIndex::default());
+        }
+        let json = std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Writes a run under a new timestamped name, refreshes the latest copy and applies retention.
+    pub fn save(&self, protocol: &str, json: &str, metadata: &RunMetadata) -> Result<SavedRun> {
+        let runs_dir = self.runs_dir();
+        std::fs::create_dir_all(&runs_dir).with_context(|| format!("creating {}", runs_dir.display()))?;
+        let mut index = self.index()?;
+
+        let saved_at = Utc::now();
+        let stem = format!("{}_{}", protocol.to_lowercase(), saved_at.format("%Y%m%dT%H%M%SZ"));
+        // Two runs within the same second get a counter
+        let file = (1..)
+            .map(|n| if n == 1 { format!("{}.json", stem) } else { format!("{}_{}.json", stem, n) })
+            .find(|name| !runs_dir.join(name).exists() && !index.runs.iter().any(|r| r.file == *name))
+            .expect("unbounded counters");
+        let path = runs_dir.join(&file);
+        std::fs::write(&path, json).with_context(|| format!("writing {}", path.display()))?;
+
+        let latest = self.config.dir.join(format!("{}_real_analysis.json", protocol.to_lowercase()));
+        std::fs::write(&latest, json).with_context(|| format!("writing {}", latest.display()))?;
+        if let Some(key) = &self.config.sign_key {
+            let key = load_key(key)?;
+            sign_file(&path, &key)?;
+            sign_file(&latest, &key)?;
+        } else if self.config.checksum {
+            write_checksum(&path)?;
+            write_checksum(&latest)?;
+        }
+
+        index.runs.push(IndexEntry {
+            file,
+            protocol: protocol.to_string(),
+            saved_at,
+            experiment_id: metadata.experiment_id.clone(),
+            tags: metadata.tags.clone(),
+            archived: false,
+        });
+        let (archived, removed) = self.apply_retention(&mut index, saved_at)?;
+        std::fs::write(self.index_path(), serde_json::to_string_pretty(&index)?)
+            .with_context(|| format!("writing {}", self.index_path().display()))?;
+
+        Ok(SavedRun { path, latest, archived, removed })
+    }
+
+    /// Expires plain runs beyond the configured count or age; the newest run never expires.
+    fn apply_retention(&self, index: &mut RunIndex, now: DateTime<Utc>) -> Result<(Vec<String>, Vec<String>)> {
+        let (mut archived, mut removed) = (Vec::new(), Vec::new());
+        let mut live = 0;
+        let mut kept = Vec::with_capacity(index.runs.len());
+        for mut entry in index.runs.drain(..).rev() {
+            if entry.archived {
+                kept.push(entry);
+                continue;
+            }
+            live += 1;
+            let too_many = self.config.keep_runs.is_some_and(|keep| live > keep);
+            let too_old = self.config.keep_days.is_some_and(|days| now - entry.saved_at > Duration::days(days.into()));
+            if live == 1 || !(too_many || too_old) {
+                kept.push(entry);
+                continue;
+            }
+
+            let path = self.runs_dir().join(&entry.file);
+            if self.config.archive {
+                let archive_dir = self.runs_dir().join("archive");
+                std::fs::create_dir_all(&archive_dir).with_context(|| format!("creating {}", archive_dir.display()))?;
+                let file = format!("archive/{}.gz", entry.file);
+                gzip(&path, &self.runs_dir().join(&file))?;
+                std::fs::remove_file(&path).with_context(|| format!("removing {}", path.display()))?;
+                for extension in SIDECAR_EXTENSIONS {
+                    let side = sidecar(&path, extension);
+                    if side.exists() {
+                        let target = archive_dir.join(side.file_name().expect("sidecar has a name"));
+                        std::fs::rename(&side, &target).with_context(|| format!("moving {}", side.display()))?;
+                    }
+                }
+                archived.push(file.clone());
+                entry.file = file;
+                entry.archived = true;
+                kept.push(entry);
+            } else {
+                for path in std::iter::once(path.clone()).chain(SIDECAR_EXTENSIONS.map(|e| sidecar(&path, e))) {
+                    if path.exists() {
+                        std::fs::remove_file(&path).with_context(|| format!("removing {}", path.display()))?;
+                    }
+                }
+                removed.push(entry.file);
+            }
+        }
+        kept.reverse();
+        index.runs = kept;
+        Ok((archived, removed))
+    }
+}
+
+#[cfg(feature = "archive")]
+fn gzip(source: &Path, target: &Path) -> Result<()> {
+    use flate2::{write::GzEncoder, Compression};
+
+    let mut input = std::fs::File::open(source).with_context(|| format!("reading {}", source.display()))?;
+    let output = std::fs::File::create(target).with_context(|| format!("writing {}", target.display()))?;
+    let mut encoder = GzEncoder::new(output, Compression::best());
+    std::io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "archive"))]
+fn gzip(source: &Path, _target: &Path) -> Result<()> {
+    Err(anyhow::anyhow!("cannot archive {}: built without the `archive` feature", source.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retention_keeps_newest_runs() {
+        let dir = std::env::temp_dir().join(format!("results-store-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = ResultsStore::new(ResultsConfig { dir: dir.clone(), keep_runs: Some(2), ..ResultsConfig::default() });
+        let saved: Vec<SavedRun> = (0..3)
+            .map(|run| store.save("Matter", &format!("{{\"run\":{}}}", run), &RunMetadata::default()).unwrap())
+            .collect();
+
+        assert_eq!(saved[2].removed.len(), 1);
+        assert!(!saved[0].path.exists() && saved[1].path.exists() && saved[2].path.exists());
+        let index = store.index().unwrap();
+        let files: Vec<_> = index.runs.iter().map(|r| store.runs_dir().join(&r.file)).collect();
+        assert_eq!(files, vec![saved[1].path.clone(), saved[2].path.clone()]);
+        assert_eq!(std::fs::read_to_string(dir.join("matter_real_analysis.json")).unwrap(), "{\"run\":2}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
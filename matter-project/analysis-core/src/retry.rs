@@ -0,0 +1,181 @@
+// matter-project/analysis-core/src/retry.rs
+/*!
+Retry policy - bounded retries with exponential backoff and jitter for flaky network steps
+
+Discovery, connection and commissioning steps run under one [`RetryPolicy`]:
+each attempt gets the attempt timeout, and a failed attempt is retried after
+`initial_backoff * multiplier^n`, capped at `max_backoff` and spread by
+±`jitter` so parallel runs do not retry in lockstep. Retries are never
+hidden: the stage records how many each metric needed in [`RetryCounts`],
+which goes into the result next to the metrics, so a time measured on the
+third attempt is visibly not a clean one.
+*/
+
+use anyhow::{anyhow, Result};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts including the first one; 1 disables retries.
+    pub max_attempts: u32,
+    /// Each attempt fails if it has not finished within this time.
+    pub attempt_timeout: Duration,
+    pub initial_backoff: Duration,
+    pub multiplier: f64,
+    pub max_backoff: Duration,
+    /// Backoffs vary by up to this fraction either way.
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            attempt_timeout: Duration::from_secs(5),
+            initial_backoff: Duration::from_millis(100),
+            multiplier: 2.0,
+            max_backoff: Duration::from_secs(2),
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// No retries, for runs that must fail on the first problem.
+    pub fn none() -> Self {
+        Self { max_attempts: 1, ..Self::default() }
+    }
+
+    /// The wait before retry number `retry` (1 for the first retry), without jitter.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self.initial_backoff.as_secs_f64() * self.multiplier.powi(retry.saturating_sub(1) as i32);
+        Duration::from_secs_f64(backoff.min(self.max_backoff.as_secs_f64()))
+    }
+
+    pub fn attempts(&self, operation: impl Into<String>) -> Attempts<'_> {
+        Attempts { policy: self, operation: operation.into(), made: 0, rng: SystemRandom::new() }
+    }
+
+    /// Runs `attempt` until it succeeds or the attempts run out.
+    pub async fn run<T, F, Fut>(&self, operation: &str, mut attempt: F) -> Result<Retried<T>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempts = self.attempts(operation);
+        loop {
+            if let Some(value) = attempts.attempt(attempt()).await? {
+                return Ok(Retried { value, retries: attempts.retries() });
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Retried<T> {
+    pub value: T,
+    pub retries: u32,
+}
+
+/// Attempts at one operation, for callers whose attempts borrow mutable state:
+/// `loop { if let Some(v) = attempts.attempt(session.request(..)).await? { break v } }`.
+pub struct Attempts<'p> {
+    policy: &'p RetryPolicy,
+    operation: String,
+    made: u32,
+    rng: SystemRandom,
+}
+
+impl Attempts<'_> {
+    /// Makes one attempt. A failure with attempts to spare waits out the backoff and
+    /// returns `Ok(None)`; the last failure is returned as the error.
+    pub async fn attempt<T>(&mut self, future: impl Future<Output = Result<T>>) -> Result<Option<T>> {
+        self.made += 1;
+        let error = match tokio::time::timeout(self.policy.attempt_timeout, future).await {
+            Ok(Ok(value)) => return Ok(Some(value)),
+            Ok(Err(e)) => e,
+            Err(_) => anyhow!("timed out after {:?}", self.policy.attempt_timeout),
+        };
+        if self.made >= self.policy.max_attempts {
+            return Err(error.context(format!("{} failed after {} attempt(s)", self.operation, self.made)));
+        }
+
+        let backoff = self.jittered(self.policy.backoff(self.made));
+        println!("🔁 {} attempt {} failed ({}); retrying in {:.0}ms", self.operation, self.made, error, backoff.as_secs_f64() * 1000.0);
+        tokio::time::sleep(backoff).await;
+        Ok(None)
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.made.saturating_sub(1)
+    }
+
+    fn jittered(&self, backoff: Duration) -> Duration {
+        let mut bytes = [0u8; 4];
+        let unit = match self.rng.fill(&mut bytes) {
+            Ok(()) => u32::from_le_bytes(bytes) as f64 / u32::MAX as f64,
+            Err(_) => 0.5,
+        };
+        backoff.mul_f64((1.0 + self.policy.jitter * (2.0 * unit - 1.0)).max(0.0))
+    }
+}
+
+/// Retries each metric's measurement needed, by metric name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RetryCounts(pub BTreeMap<String, u32>);
+
+impl RetryCounts {
+    pub fn record(&mut self, metric: &str, retries: u32) {
+        *self.0.entry(metric.to_string()).or_default() += retries;
+    }
+
+    pub fn total(&self) -> u32 {
+        self.0.values().sum()
+    }
+
+    /// Metrics measured on a retry, for warnings.
+    pub fn retried(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.0.iter().filter(|(_, retries)| **retries > 0).map(|(metric, retries)| (metric.as_str(), *retries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_attempts_are_retried_and_counted() {
+        let policy = RetryPolicy { initial_backoff: Duration::from_millis(1), ..RetryPolicy::default() };
+        assert_eq!(policy.backoff(1), Duration::from_millis(1));
+        assert_eq!(policy.backoff(3), Duration::from_millis(4));
+        assert_eq!(RetryPolicy::default().backoff(10), Duration::from_secs(2));
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        runtime.block_on(async {
+            let mut calls = 0;
+            let retried = policy
+                .run("flaky", || {
+                    calls += 1;
+                    let call = calls;
+                    async move { if call < 3 { Err(anyhow!("lost")) } else { Ok(call) } }
+                })
+                .await
+                .unwrap();
+            assert_eq!((retried.value, retried.retries), (3, 2));
+
+            let error = policy.run("down", || async { Err::<(), _>(anyhow!("refused")) }).await.unwrap_err();
+            assert!(error.to_string().contains("down failed after 3 attempt(s)"));
+        });
+
+        let mut counts = RetryCounts::default();
+        counts.record("udp_discovery_time_ms", 0);
+        counts.record("commissioning_time_ms", 2);
+        assert_eq!(counts.total(), 2);
+        assert_eq!(counts.retried().collect::<Vec<_>>(), vec![("commissioning_time_ms", 2)]);
+    }
+}
@@ -0,0 +1,64 @@
+// matter-project/analysis-core/src/run_metadata.rs
+/*!
+Run metadata - experiment ID, key/value tags and free-text notes attached to a run
+
+Given on the command line (`--experiment-id pi-sweep --tag network=wifi5
+--tag location=lab2 --note "door open"`), stored in the result JSON under
+`run_metadata`, and available to `query` as `experiment`, `tag.<key>` and
+`note`.
+*/
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunMetadata {
+    pub experiment_id: Option<String>,
+    pub tags: BTreeMap<String, String>,
+    pub notes: Vec<String>,
+}
+
+impl RunMetadata {
+    /// Adds a `key=value` tag; a later tag with the same key replaces the earlier one.
+    pub fn add_tag(&mut self, tag: &str) -> Result<()> {
+        let (key, value) = tag.split_once('=').ok_or_else(|| anyhow!("tag must be key=value, got {}", tag))?;
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')) {
+            return Err(anyhow!("tag key must be letters, digits, '_', '-' or '.', got {:?}", key));
+        }
+        self.tags.insert(key.to_string(), value.trim().to_string());
+        Ok(())
+    }
+
+    /// The metadata stored in a result, or empty metadata for results recorded without it.
+    pub fn of(result: &Value) -> Self {
+        result
+            .get("run_metadata")
+            .and_then(|metadata| serde_json::from_value(metadata.clone()).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Tags as `key=value` pairs joined with `;`.
+pub fn tags_text(tags: &BTreeMap<String, String>) -> String {
+    tags.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join(";")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_parse_and_replace() {
+        let mut metadata = RunMetadata::default();
+        metadata.add_tag("network=wifi5").unwrap();
+        metadata.add_tag("location = lab2").unwrap();
+        metadata.add_tag("network=ethernet").unwrap();
+        assert_eq!(tags_text(&metadata.tags), "location=lab2;network=ethernet");
+        assert!(metadata.add_tag("network").is_err());
+        assert!(metadata.add_tag("=wifi5").is_err());
+        assert!(metadata.add_tag("net work=wifi5").is_err());
+    }
+}
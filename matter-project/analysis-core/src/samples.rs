@@ -0,0 +1,178 @@
+// matter-project/analysis-core/src/samples.rs
+/*!
+Per-iteration samples - the raw timings behind the summaries, as an Arrow IPC file for notebooks
+
+Results keep means, percentiles and distribution shapes, not the samples they
+were computed from. With `--samples` every analyzer that times iterations
+hands its samples to [`record`] as it summarizes them, in the order they were
+taken, and the run writes them next to its result file as
+`<run>.samples.arrow`: an Arrow IPC file (Feather v2), which
+`pandas.read_feather` and `pyarrow.feather.read_table` load without parsing.
+
+One row per sample, in long format:
+
+| Column      | Type                 | Example                          |
+|-------------|----------------------|----------------------------------|
+| `run`       | categorical          | `matter_20250623T100000Z`        |
+| `section`   | categorical          | `transport_modes`                |
+| `layer`     | categorical          | `transport`                      |
+| `protocol`  | categorical          | `Matter`                         |
+| `scenario`  | categorical          | `udp_mrp`                        |
+| `metric`    | categorical          | `latency_ms`                     |
+| `unit`      | categorical, nullable| `ms`                             |
+| `iteration` | uint32               | `0`, `1`, ... within the series  |
+| `value`     | float64              | `0.412`                          |
+
+Categorical columns are dictionary-encoded, so they arrive as `category` in
+pandas. `section` is the result section the samples were summarized into.
+Recording is off unless the run asks for it, so analyzers call [`record`]
+unconditionally. Writing the file needs the `arrow` feature.
+*/
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layer {
+    Network,
+    Transport,
+    Session,
+    Presentation,
+    Application,
+}
+
+impl Layer {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Layer::Network => "network",
+            Layer::Transport => "transport",
+            Layer::Session => "session",
+            Layer::Presentation => "presentation",
+            Layer::Application => "application",
+        }
+    }
+}
+
+/// The samples behind one summary, in the order they were taken.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SampleSeries {
+    pub section: String,
+    pub layer: Layer,
+    pub protocol: String,
+    pub scenario: String,
+    pub metric: String,
+    pub values: Vec<f64>,
+}
+
+/// Series recorded since [`start_recording`]; `None` while not recording.
+static RECORDED: Mutex<Option<Vec<SampleSeries>>> = Mutex::new(None);
+
+fn recorded() -> MutexGuard<'static, Option<Vec<SampleSeries>>> {
+    RECORDED.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Starts keeping the series passed to [`record`], dropping any kept before.
+pub fn start_recording() {
+    *recorded() = Some(Vec::new());
+}
+
+/// Stops recording and returns what was recorded.
+pub fn take_recorded() -> Vec<SampleSeries> {
+    recorded().take().unwrap_or_default()
+}
+
+/// Keeps a copy of `values` when recording; does nothing otherwise.
+pub fn record(section: &str, layer: Layer, protocol: &str, scenario: &str, metric: &str, values: &[f64]) {
+    if let Some(series) = recorded().as_mut() {
+        series.push(SampleSeries {
+            section: section.to_string(),
+            layer,
+            protocol: protocol.to_string(),
+            scenario: scenario.to_string(),
+            metric: metric.to_string(),
+            values: values.to_vec(),
+        });
+    }
+}
+
+/// Writes `series` as one Arrow IPC file, with `run` in every row; returns the number of rows.
+#[cfg(feature = "arrow")]
+pub fn write_samples(path: &Path, run: &str, series: &[SampleSeries]) -> Result<usize> {
+    use crate::units::Unit;
+    use anyhow::Context;
+    use arrow_array::types::Int32Type;
+    use arrow_array::{ArrayRef, DictionaryArray, Float64Array, RecordBatch, UInt32Array};
+    use arrow_ipc::writer::FileWriter;
+    use arrow_schema::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    let rows = || series.iter().flat_map(|s| s.values.iter().enumerate().map(move |(iteration, value)| (s, iteration, *value)));
+    let category = |label: &dyn Fn(&SampleSeries) -> &str| -> ArrayRef {
+        Arc::new(rows().map(|(s, _, _)| label(s)).collect::<DictionaryArray<Int32Type>>())
+    };
+    let units: Vec<Option<String>> = series.iter().map(|s| Unit::of(&s.metric).map(|unit| unit.to_string())).collect();
+    let units: DictionaryArray<Int32Type> =
+        series.iter().zip(&units).flat_map(|(s, unit)| std::iter::repeat_n(unit.as_deref(), s.values.len())).collect();
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(rows().map(|_| run).collect::<DictionaryArray<Int32Type>>()),
+        category(&|s| &s.section),
+        category(&|s| s.layer.as_str()),
+        category(&|s| &s.protocol),
+        category(&|s| &s.scenario),
+        category(&|s| &s.metric),
+        Arc::new(units),
+        Arc::new(rows().map(|(_, iteration, _)| iteration as u32).collect::<UInt32Array>()),
+        Arc::new(rows().map(|(_, _, value)| value).collect::<Float64Array>()),
+    ];
+
+    let categorical = |name: &str, nullable: bool| {
+        Field::new(name, DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)), nullable)
+    };
+    let schema = Arc::new(Schema::new(vec![
+        categorical("run", false),
+        categorical("section", false),
+        categorical("layer", false),
+        categorical("protocol", false),
+        categorical("scenario", false),
+        categorical("metric", false),
+        categorical("unit", true),
+        Field::new("iteration", DataType::UInt32, false),
+        Field::new("value", DataType::Float64, false),
+    ]));
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+    let file = std::fs::File::create(path).with_context(|| format!("writing {}", path.display()))?;
+    let mut writer = FileWriter::try_new(file, &schema)?;
+    writer.write(&batch)?;
+    writer.finish()?;
+    Ok(batch.num_rows())
+}
+
+#[cfg(not(feature = "arrow"))]
+pub fn write_samples(path: &Path, _run: &str, _series: &[SampleSeries]) -> Result<usize> {
+    Err(anyhow::anyhow!("cannot write {}: built without the `arrow` feature", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series_are_kept_only_while_recording() {
+        record("transport_modes", Layer::Transport, "Matter", "tcp", "latency_ms", &[1.0]);
+        start_recording();
+        record("transport_modes", Layer::Transport, "Matter", "udp_mrp", "latency_ms", &[0.4, 0.6, 0.5]);
+        record("telemetry", Layer::Application, "MQTT", "10 Hz", "latency_ms", &[2.0]);
+        let series = take_recorded();
+        record("telemetry", Layer::Application, "MQTT", "10 Hz", "latency_ms", &[3.0]);
+
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].scenario, "udp_mrp");
+        assert_eq!(series[0].values, vec![0.4, 0.6, 0.5]);
+        assert_eq!(series[1].layer.as_str(), "application");
+        assert!(take_recorded().is_empty());
+    }
+}
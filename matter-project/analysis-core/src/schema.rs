@@ -0,0 +1,147 @@
+// matter-project/analysis-core/src/schema.rs
+/*!
+Result schema versions - the `schema_version` written into results and upgrades for older files
+
+Every result the analyzer writes carries `schema_version`. Loaders (`diff`,
+`merge`, `query`, `baseline`) pass files through [`upgrade`], which applies
+the migrations from the file's version up to [`RESULT_SCHEMA_VERSION`], so
+datasets collected with older analyzers still line up metric by metric with
+new ones. Files without the field are version 1. Each migration only
+rewrites fields still in their old form, so unversioned files written by
+later analyzers pass through unchanged. Files from a newer analyzer are
+rejected rather than misread.
+
+Version history:
+
+1. The original OSI-layer results: `session_efficiency`, a scalar
+   `interoperability_score`, and presentation sizes without
+   `tlv_overhead_bytes`.
+2. Current layout: `session_establishment_efficiency`, the `interoperability`
+   section with per-protocol scores, and a versioned transport efficiency
+   model.
+*/
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Map, Value};
+use std::path::Path;
+
+pub const RESULT_SCHEMA_VERSION: u32 = 2;
+
+/// `MIGRATIONS[n - 1]` upgrades version `n` to `n + 1`.
+const MIGRATIONS: [fn(&mut Map<String, Value>); 1] = [v1_to_v2];
+
+/// The version a result declares, 1 when it declares none.
+pub fn schema_version(result: &Value) -> Result<u32> {
+    match result.get("schema_version") {
+        None => Ok(1),
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|v| *v >= 1)
+            .ok_or_else(|| anyhow!("schema_version must be a positive integer, got {}", version)),
+    }
+}
+
+/// Upgrades a result to the current schema version.
+pub fn upgrade(mut result: Value) -> Result<Value> {
+    let version = schema_version(&result)?;
+    if version > RESULT_SCHEMA_VERSION {
+        return Err(anyhow!(
+            "schema version {} is newer than this analyzer supports ({}); update the analyzer",
+            version,
+            RESULT_SCHEMA_VERSION
+        ));
+    }
+    let fields = result.as_object_mut().ok_or_else(|| anyhow!("a result must be a JSON object"))?;
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        migration(fields);
+    }
+    fields.insert("schema_version".to_string(), json!(RESULT_SCHEMA_VERSION));
+    Ok(result)
+}
+
+/// Reads a result file and upgrades it to the current schema version.
+pub fn load_result(path: &Path) -> Result<Value> {
+    let json = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let value = serde_json::from_str(&json).with_context(|| format!("parsing {}", path.display()))?;
+    upgrade(value).with_context(|| format!("upgrading {}", path.display()))
+}
+
+fn v1_to_v2(result: &mut Map<String, Value>) {
+    let protocol = result
+        .get("protocol_name")
+        .and_then(Value::as_str)
+        .map(|name| name.split('_').next().unwrap_or(name).to_string());
+
+    if let Some(Value::Object(transport)) = result.get_mut("osi_layer_4_transport") {
+        if transport.contains_key("efficiency_score") && !transport.contains_key("efficiency_model_version") {
+            transport.insert("efficiency_model_version".to_string(), json!("unversioned"));
+        }
+    }
+
+    if let Some(Value::Object(session)) = result.get_mut("osi_layer_5_session") {
+        if !session.contains_key("session_establishment_efficiency") {
+            if let Some(efficiency) = session.remove("session_efficiency") {
+                session.insert("session_establishment_efficiency".to_string(), efficiency);
+            }
+        }
+    }
+
+    if let Some(Value::Object(presentation)) = result.get_mut("osi_layer_6_presentation") {
+        let size = |field: &str| presentation.get(field).and_then(Value::as_i64);
+        if let (Some(raw), Some(encoded), false) =
+            (size("raw_data_size_bytes"), size("encoded_size_bytes"), presentation.contains_key("tlv_overhead_bytes"))
+        {
+            // Only an encoding that grew the data has overhead to record
+            if encoded >= raw {
+                presentation.insert("tlv_overhead_bytes".to_string(), json!(encoded - raw));
+            }
+        }
+    }
+
+    if let Some(Value::Object(application)) = result.get_mut("osi_layer_7_application") {
+        if !application.contains_key("interoperability") {
+            if let Some(score) = application.remove("interoperability_score") {
+                application.insert(
+                    "interoperability".to_string(),
+                    json!({
+                        "model_version": "unversioned",
+                        "source": "migrated from interoperability_score",
+                        "protocols": [{"protocol": protocol, "score": score}],
+                    }),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_results_upgrade_to_current_names() {
+        let legacy = json!({
+            "protocol_name": "Matter",
+            "osi_layer_4_transport": {"efficiency_score": 0.75},
+            "osi_layer_5_session": {"session_efficiency": 0.65},
+            "osi_layer_6_presentation": {"raw_data_size_bytes": 156, "encoded_size_bytes": 179},
+            "osi_layer_7_application": {"interoperability_score": 0.95}
+        });
+        let upgraded = upgrade(legacy).unwrap();
+        assert_eq!(upgraded["schema_version"], json!(RESULT_SCHEMA_VERSION));
+        assert_eq!(upgraded["osi_layer_5_session"]["session_establishment_efficiency"], json!(0.65));
+        assert_eq!(upgraded["osi_layer_6_presentation"]["tlv_overhead_bytes"], json!(23));
+        assert_eq!(upgraded["osi_layer_7_application"]["interoperability"]["protocols"][0],
+                   json!({"protocol": "Matter", "score": 0.95}));
+        // Already current: unchanged apart from the version
+        assert_eq!(upgrade(upgraded.clone()).unwrap(), upgraded);
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        assert!(upgrade(json!({"schema_version": RESULT_SCHEMA_VERSION + 1})).is_err());
+        assert!(upgrade(json!({"schema_version": 0})).is_err());
+        assert!(upgrade(json!([1, 2])).is_err());
+    }
+}
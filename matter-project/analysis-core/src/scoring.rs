@@ -0,0 +1,160 @@
+// matter-project/analysis-core/src/scoring.rs
+/*!
+Transport efficiency scoring model.
+
+Every efficiency score reported by the analyzers comes from this module so the
+numbers stay comparable across analyzers and releases. Any change to ranges,
+weights or the combination rule must bump [`EFFICIENCY_MODEL_VERSION`], which
+is recorded alongside each score.
+
+Model (version 1):
+
+| Input             | Normalization (0 = worst, 1 = best)     | Weight |
+|-------------------|-----------------------------------------|--------|
+| throughput_mbps   | linear over 0 ..= 1000 Mbps             | 0.30   |
+| round_trip_time_ms| 1 - rtt / 100 ms, clamped               | 0.30   |
+| packet_loss_rate  | 1 - loss / 0.05 (5% loss scores 0)      | 0.20   |
+| success_rate      | taken as-is (fraction of successful ops)| 0.20   |
+
+The score is the weighted mean of the normalized inputs that were actually
+measured; inputs left as `None` are dropped and the remaining weights are
+renormalized, so an unmeasured input neither rewards nor penalizes a protocol.
+*/
+
+use serde::{Deserialize, Serialize};
+
+pub const EFFICIENCY_MODEL_VERSION: &str = "1";
+
+const THROUGHPUT_BEST_MBPS: f64 = 1000.0;
+const RTT_WORST_MS: f64 = 100.0;
+const LOSS_WORST_RATE: f64 = 0.05;
+
+const WEIGHT_THROUGHPUT: f64 = 0.30;
+const WEIGHT_RTT: f64 = 0.30;
+const WEIGHT_LOSS: f64 = 0.20;
+const WEIGHT_SUCCESS: f64 = 0.20;
+
+#[derive(Debug, Clone, Default)]
+pub struct EfficiencyInputs {
+    pub throughput_mbps: Option<f64>,
+    pub round_trip_time_ms: Option<f64>,
+    pub packet_loss_rate: Option<f64>,
+    pub success_rate: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EfficiencyScore {
+    pub score: f64,
+    pub model_version: String,
+    pub inputs_used: u32,
+}
+
+pub fn efficiency_score(inputs: &EfficiencyInputs) -> EfficiencyScore {
+    let components = [
+        (inputs.throughput_mbps.map(|t| t / THROUGHPUT_BEST_MBPS), WEIGHT_THROUGHPUT),
+        (inputs.round_trip_time_ms.map(|rtt| 1.0 - rtt / RTT_WORST_MS), WEIGHT_RTT),
+        (inputs.packet_loss_rate.map(|loss| 1.0 - loss / LOSS_WORST_RATE), WEIGHT_LOSS),
+        (inputs.success_rate, WEIGHT_SUCCESS),
+    ];
+
+    let mut weighted_sum = 0.0;
+    let mut weight_total = 0.0;
+    let mut inputs_used = 0;
+
+    for (normalized, weight) in components {
+        if let Some(value) = normalized.filter(|v| v.is_finite()) {
+            weighted_sum += value.clamp(0.0, 1.0) * weight;
+            weight_total += weight;
+            inputs_used += 1;
+        }
+    }
+
+    EfficiencyScore {
+        score: if weight_total > 0.0 { weighted_sum / weight_total } else { 0.0 },
+        model_version: EFFICIENCY_MODEL_VERSION.to_string(),
+        inputs_used,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all(throughput: f64, rtt: f64, loss: f64, success: f64) -> EfficiencyInputs {
+        EfficiencyInputs {
+            throughput_mbps: Some(throughput),
+            round_trip_time_ms: Some(rtt),
+            packet_loss_rate: Some(loss),
+            success_rate: Some(success),
+        }
+    }
+
+    #[test]
+    fn ideal_inputs_score_one() {
+        let score = efficiency_score(&all(1000.0, 0.0, 0.0, 1.0));
+        assert!((score.score - 1.0).abs() < 1e-9);
+        assert_eq!(score.inputs_used, 4);
+        assert_eq!(score.model_version, EFFICIENCY_MODEL_VERSION);
+    }
+
+    #[test]
+    fn worst_inputs_score_zero() {
+        let score = efficiency_score(&all(0.0, 100.0, 0.05, 0.0));
+        assert!(score.score.abs() < 1e-9);
+    }
+
+    #[test]
+    fn out_of_range_inputs_are_clamped() {
+        let score = efficiency_score(&all(5000.0, -3.0, -1.0, 2.0));
+        assert!((score.score - 1.0).abs() < 1e-9);
+
+        let score = efficiency_score(&all(-1.0, 1000.0, 0.9, -1.0));
+        assert!(score.score.abs() < 1e-9);
+    }
+
+    #[test]
+    fn weights_are_applied() {
+        // Only throughput perfect: score equals the throughput weight
+        let score = efficiency_score(&all(1000.0, 100.0, 0.05, 0.0));
+        assert!((score.score - WEIGHT_THROUGHPUT).abs() < 1e-9);
+    }
+
+    #[test]
+    fn missing_inputs_are_renormalized() {
+        let inputs = EfficiencyInputs {
+            round_trip_time_ms: Some(50.0),
+            success_rate: Some(1.0),
+            ..Default::default()
+        };
+        let score = efficiency_score(&inputs);
+        let expected = (0.5 * WEIGHT_RTT + WEIGHT_SUCCESS) / (WEIGHT_RTT + WEIGHT_SUCCESS);
+        assert!((score.score - expected).abs() < 1e-9);
+        assert_eq!(score.inputs_used, 2);
+    }
+
+    #[test]
+    fn no_inputs_score_zero() {
+        let score = efficiency_score(&EfficiencyInputs::default());
+        assert_eq!(score.score, 0.0);
+        assert_eq!(score.inputs_used, 0);
+    }
+
+    #[test]
+    fn higher_rtt_never_scores_better() {
+        let fast = efficiency_score(&all(100.0, 5.0, 0.001, 1.0));
+        let slow = efficiency_score(&all(100.0, 40.0, 0.001, 1.0));
+        assert!(fast.score > slow.score);
+    }
+
+    #[test]
+    fn non_finite_inputs_are_ignored() {
+        let inputs = EfficiencyInputs {
+            throughput_mbps: Some(f64::NAN),
+            success_rate: Some(1.0),
+            ..Default::default()
+        };
+        let score = efficiency_score(&inputs);
+        assert_eq!(score.inputs_used, 1);
+        assert!((score.score - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,572 @@
+// matter-project/analysis-core/src/trends.rs
+/*!
+Run history trends - rolling means, change points and anomaly flags over the stored runs, with a guess at the cause
+
+Every run is saved under `<results>/runs/` and recorded in the run index, so
+the history of each metric is already on disk. `trends` reads it oldest first,
+per protocol, and for every numeric metric of the latest run:
+
+- compares the latest value with the mean and standard deviation of the
+  `window` values before it, and flags it when it lies `sigma` or more
+  standard deviations away. A metric that never varied is flagged as soon as
+  it changes;
+- finds the split of the whole series where the means before and after differ
+  most in pooled standard deviations (Cohen's d), and reports it as a change
+  point when that shift is `sigma` or more.
+
+A flagged value is a suspected environmental change when the latest run was
+measured in a different environment from the window (CPU, OS, device class,
+tags, impairment or sysctls), when the harness noise floor rose by `sigma` or
+more, or when it is a timing and more than a quarter of the latest run's other
+timings were flagged with it: a busier host moves everything at once. Sizes
+and counts do not move with timing noise, so only a changed environment
+explains them. Anything else is a suspected genuine change. These are hints
+to where to look first, not verdicts.
+
+The report is printed and written as JSON with the series and rolling mean of
+every flagged metric, or of every metric asked for with `--metric`, which
+`run_comparison_analysis.py` charts. Archived runs are compressed and left
+out.
+*/
+
+use crate::diff::flatten;
+use crate::query::metric_matches;
+use crate::results_store::{ResultsConfig, ResultsStore};
+use crate::schema::load_result;
+use crate::units::Unit;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+/// Values before the latest a metric needs for the latest to be judged.
+const MIN_HISTORY: usize = 3;
+/// Values each side of a change point needs.
+const MIN_SEGMENT: usize = 3;
+/// Share of the latest run's other timings that, flagged along with one, points at the host rather than the protocol.
+const BROAD_SHIFT_SHARE: f64 = 0.25;
+/// Relative differences this small are left by float arithmetic, not by a change.
+const FLAT_TOLERANCE: f64 = 1e-9;
+/// Change points printed; the JSON has all of them.
+const PRINTED_CHANGE_POINTS: usize = 10;
+/// Result sections that describe the run rather than measure the protocol.
+const NOT_MEASURED: [&str; 5] = ["schema_version", "test_environment", "measurement_calibration", "run_metadata", "network_namespace"];
+
+#[derive(Debug, Clone)]
+pub struct TrendConfig {
+    /// Results directory whose run index holds the history.
+    pub dir: PathBuf,
+    /// Values before the latest that its rolling mean and standard deviation are taken over.
+    pub window: usize,
+    /// Deviations and shifts of this many standard deviations or more are flagged.
+    pub sigma: f64,
+    /// Metric names, trailing paths or globs to look at; every metric when empty.
+    pub metrics: Vec<String>,
+    /// Only runs of this protocol; every protocol when unset.
+    pub protocol: Option<String>,
+    /// Where the report is written; `trends.json` in `dir` when unset.
+    pub output: Option<PathBuf>,
+}
+
+impl TrendConfig {
+    pub fn output(&self) -> PathBuf {
+        self.output.clone().unwrap_or_else(|| self.dir.join("trends.json"))
+    }
+}
+
+impl Default for TrendConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("../results"),
+            window: 10,
+            sigma: 3.0,
+            metrics: Vec::new(),
+            protocol: None,
+            output: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cause {
+    Environmental,
+    Genuine,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Anomaly {
+    pub metric: String,
+    pub unit: Option<String>,
+    pub latest: f64,
+    pub rolling_mean: f64,
+    pub rolling_std_dev: f64,
+    /// Distance of the latest value from the rolling mean; `None` when the window never varied.
+    pub deviation_sigma: Option<f64>,
+    pub cause: Cause,
+    pub reasons: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangePoint {
+    pub metric: String,
+    /// First run after the change.
+    pub run: String,
+    pub before_mean: f64,
+    pub after_mean: f64,
+    /// Shift in pooled standard deviations; `None` when neither side varied.
+    pub shift_sigma: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricSeries {
+    /// Positions in the protocol's `runs` of the runs that have the metric.
+    pub runs: Vec<usize>,
+    pub values: Vec<f64>,
+    /// Mean of the up to `window` values ending at each value.
+    pub rolling_mean: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolTrends {
+    pub protocol: String,
+    /// Run files, oldest first; the last is the latest run.
+    pub runs: Vec<String>,
+    pub saved_at: Vec<DateTime<Utc>>,
+    /// How the latest run's environment differs from the window's, as `fact: before → latest`.
+    pub environment_changes: Vec<String>,
+    /// Metrics of the latest run with enough history to judge.
+    pub metrics_checked: usize,
+    pub anomalies: Vec<Anomaly>,
+    pub change_points: Vec<ChangePoint>,
+    pub series: BTreeMap<String, MetricSeries>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrendReport {
+    pub generated_at: DateTime<Utc>,
+    pub window: usize,
+    pub sigma: f64,
+    pub protocols: Vec<ProtocolTrends>,
+}
+
+/// One stored run: what it measured and where it was measured.
+#[derive(Debug, Clone)]
+pub struct HistoryRun {
+    pub file: String,
+    pub saved_at: DateTime<Utc>,
+    pub metrics: BTreeMap<String, f64>,
+    /// Facts about the host and setup that can move timings, such as `cpu_model` or `tag.network`.
+    pub environment: BTreeMap<String, String>,
+    pub noise_floor_ms: Option<f64>,
+}
+
+/// Stored runs by protocol, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct RunHistory {
+    pub protocols: BTreeMap<String, Vec<HistoryRun>>,
+    /// Indexed runs that could not be read, with why.
+    pub unreadable: Vec<String>,
+}
+
+impl HistoryRun {
+    pub fn from_result(file: &str, saved_at: DateTime<Utc>, result: &Value) -> Self {
+        let mut metrics = BTreeMap::new();
+        flatten(result, String::new(), &mut metrics);
+        metrics.retain(|path, _| !NOT_MEASURED.iter().any(|section| path == section || path.starts_with(&format!("{}.", section))));
+        Self {
+            file: file.to_string(),
+            saved_at,
+            metrics,
+            environment: environment_of(result),
+            noise_floor_ms: result.pointer("/measurement_calibration/noise_floor_ms").and_then(Value::as_f64),
+        }
+    }
+}
+
+fn environment_of(result: &Value) -> BTreeMap<String, String> {
+    let mut facts = BTreeMap::new();
+    for fact in ["cpu_model", "os_platform", "architecture", "device_class"] {
+        if let Some(value) = result.pointer(&format!("/test_environment/{}", fact)).and_then(Value::as_str) {
+            facts.insert(fact.to_string(), value.to_string());
+        }
+    }
+    if let Some(impairment) = result.pointer("/network_namespace/impairment").filter(|impairment| !impairment.is_null()) {
+        let profile = impairment.get("profile").and_then(Value::as_str);
+        facts.insert("impairment".to_string(), profile.map_or_else(|| impairment.to_string(), str::to_string));
+    }
+    if let Some(Value::Object(tags)) = result.pointer("/run_metadata/tags") {
+        for (key, value) in tags {
+            facts.insert(format!("tag.{}", key), value.as_str().map_or_else(|| value.to_string(), str::to_string));
+        }
+    }
+    if let Some(Value::Array(sysctls)) = result.pointer("/test_environment/network_tunables/applied") {
+        for sysctl in sysctls.iter().filter(|sysctl| sysctl.get("applied").and_then(Value::as_bool) == Some(true)) {
+            if let (Some(name), Some(value)) = (sysctl.get("name").and_then(Value::as_str), sysctl.get("value").and_then(Value::as_str)) {
+                facts.insert(format!("sysctl.{}", name), value.to_string());
+            }
+        }
+    }
+    facts
+}
+
+/// Mean and sample standard deviation; the deviation is 0 below two values.
+fn mean_and_std_dev(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if values.len() < 2 {
+        return (mean, 0.0);
+    }
+    (mean, (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt())
+}
+
+/// Relative to the mean, so values that never varied read as flat despite rounding.
+fn is_flat(spread: f64, mean: f64) -> bool {
+    spread <= FLAT_TOLERANCE * mean.abs().max(1.0)
+}
+
+/// How many standard deviations `value` lies from `window`'s mean; infinite when the window never varied and `value` differs.
+fn deviation(value: f64, window: &[f64]) -> (f64, f64, f64) {
+    let (mean, std_dev) = mean_and_std_dev(window);
+    let sigmas = if !is_flat(std_dev, mean) {
+        (value - mean) / std_dev
+    } else if is_flat((value - mean).abs(), mean) {
+        0.0
+    } else {
+        f64::INFINITY.copysign(value - mean)
+    };
+    (mean, std_dev, sigmas)
+}
+
+/// The split of `values` whose sides differ most in mean, in pooled standard deviations: the position after it,
+/// both means and the shift, which is infinite when neither side varied.
+fn change_point(values: &[f64]) -> Option<(usize, f64, f64, f64)> {
+    let n = values.len();
+    if n < 2 * MIN_SEGMENT {
+        return None;
+    }
+    (MIN_SEGMENT..=n - MIN_SEGMENT)
+        .map(|split| {
+            let (before, after) = (&values[..split], &values[split..]);
+            let (before_mean, before_std_dev) = mean_and_std_dev(before);
+            let (after_mean, after_std_dev) = mean_and_std_dev(after);
+            let pooled = (((before.len() - 1) as f64 * before_std_dev.powi(2) + (after.len() - 1) as f64 * after_std_dev.powi(2))
+                / (n - 2) as f64)
+                .sqrt();
+            let difference = (after_mean - before_mean).abs();
+            let shift = if !is_flat(pooled, before_mean) {
+                difference / pooled
+            } else if is_flat(difference, before_mean) {
+                0.0
+            } else {
+                f64::INFINITY
+            };
+            (split, before_mean, after_mean, shift)
+        })
+        .max_by(|a, b| a.3.total_cmp(&b.3))
+}
+
+fn finite(sigmas: f64) -> Option<f64> {
+    sigmas.is_finite().then_some(sigmas)
+}
+
+/// Trends of one protocol's runs, oldest first.
+pub fn analyze_history(protocol: &str, runs: &[HistoryRun], config: &TrendConfig) -> ProtocolTrends {
+    let mut trends = ProtocolTrends {
+        protocol: protocol.to_string(),
+        runs: runs.iter().map(|run| run.file.clone()).collect(),
+        saved_at: runs.iter().map(|run| run.saved_at).collect(),
+        environment_changes: Vec::new(),
+        metrics_checked: 0,
+        anomalies: Vec::new(),
+        change_points: Vec::new(),
+        series: BTreeMap::new(),
+    };
+    let Some((latest, earlier)) = runs.split_last() else {
+        return trends;
+    };
+    let window_runs = &earlier[earlier.len().saturating_sub(config.window)..];
+
+    // Each fact compared with its most common value across the window
+    let facts: BTreeSet<&String> = window_runs.iter().chain([latest]).flat_map(|run| run.environment.keys()).collect();
+    for fact in facts {
+        let mut counts: BTreeMap<Option<&String>, usize> = BTreeMap::new();
+        for run in window_runs {
+            *counts.entry(run.environment.get(fact)).or_default() += 1;
+        }
+        let usual = counts.into_iter().max_by_key(|(_, count)| *count).and_then(|(value, _)| value);
+        let now = latest.environment.get(fact);
+        if !window_runs.is_empty() && usual != now {
+            let text = |value: Option<&String>| value.map_or("none".to_string(), String::clone);
+            trends.environment_changes.push(format!("{}: {} → {}", fact, text(usual), text(now)));
+        }
+    }
+    let noise_floors: Vec<f64> = window_runs.iter().filter_map(|run| run.noise_floor_ms).collect();
+    let noisier = match latest.noise_floor_ms {
+        Some(now) if noise_floors.len() >= MIN_HISTORY => {
+            let (mean, _, sigmas) = deviation(now, &noise_floors);
+            (sigmas >= config.sigma).then(|| format!("harness noise floor rose from {:.4} to {:.4} ms", mean, now))
+        }
+        _ => None,
+    };
+
+    let wanted = |metric: &str| config.metrics.is_empty() || config.metrics.iter().any(|pattern| metric_matches(pattern, metric));
+    let (mut timings_checked, mut timings_flagged) = (0, 0);
+    let mut flagged = Vec::new();
+    for metric in latest.metrics.keys().filter(|metric| wanted(metric)) {
+        let (positions, values): (Vec<usize>, Vec<f64>) =
+            runs.iter().enumerate().filter_map(|(position, run)| run.metrics.get(metric).map(|value| (position, *value))).unzip();
+        let Some((&value, history)) = values.split_last() else {
+            continue;
+        };
+        let is_timing = matches!(Unit::of(metric), Some(Unit::Time(_)));
+        if history.len() >= MIN_HISTORY {
+            trends.metrics_checked += 1;
+            let window = &history[history.len().saturating_sub(config.window)..];
+            let (mean, std_dev, sigmas) = deviation(value, window);
+            timings_checked += usize::from(is_timing);
+            if sigmas.abs() >= config.sigma {
+                timings_flagged += usize::from(is_timing);
+                flagged.push((metric.clone(), value, mean, std_dev, sigmas));
+            }
+        }
+        if let Some((split, before_mean, after_mean, shift)) = change_point(&values) {
+            if shift >= config.sigma {
+                trends.change_points.push(ChangePoint {
+                    metric: metric.clone(),
+                    run: runs[positions[split]].file.clone(),
+                    before_mean,
+                    after_mean,
+                    shift_sigma: finite(shift),
+                });
+            }
+        }
+        let rolling_mean = (0..values.len())
+            .map(|end| mean_and_std_dev(&values[(end + 1).saturating_sub(config.window)..=end]).0)
+            .collect();
+        trends.series.insert(metric.clone(), MetricSeries { runs: positions, values, rolling_mean });
+    }
+
+    // Flagged timings other than the one at hand
+    let (others_checked, others_flagged) = (timings_checked.saturating_sub(1), timings_flagged.saturating_sub(1));
+    let broad = others_checked > 0 && others_flagged as f64 / others_checked as f64 > BROAD_SHIFT_SHARE;
+    for (metric, latest_value, mean, std_dev, sigmas) in flagged {
+        let unit = Unit::of(&metric);
+        let mut reasons = Vec::new();
+        if !trends.environment_changes.is_empty() {
+            reasons.push(format!("environment changed: {}", trends.environment_changes.join(", ")));
+        }
+        if matches!(unit, Some(Unit::Time(_))) {
+            reasons.extend(noisier.clone());
+            if broad {
+                reasons.push(format!("{} of {} other timings moved with it", others_flagged, others_checked));
+            }
+        }
+        let cause = if reasons.is_empty() { Cause::Genuine } else { Cause::Environmental };
+        if cause == Cause::Genuine {
+            reasons.push(match unit {
+                Some(Unit::Time(_)) => format!("same environment; {} of {} other timings moved", others_flagged, others_checked),
+                _ => "same environment; sizes and counts do not move with timing noise".to_string(),
+            });
+        }
+        trends.anomalies.push(Anomaly {
+            metric,
+            unit: unit.map(|unit| unit.to_string()),
+            latest: latest_value,
+            rolling_mean: mean,
+            rolling_std_dev: std_dev,
+            deviation_sigma: finite(sigmas),
+            cause,
+            reasons,
+        });
+    }
+    trends.anomalies.sort_by(|a, b| {
+        let size = |anomaly: &Anomaly| anomaly.deviation_sigma.map_or(f64::INFINITY, f64::abs);
+        size(b).total_cmp(&size(a))
+    });
+    trends.change_points.sort_by(|a, b| b.shift_sigma.unwrap_or(f64::INFINITY).total_cmp(&a.shift_sigma.unwrap_or(f64::INFINITY)));
+
+    // Charts cover what was flagged, or everything asked for by name
+    if config.metrics.is_empty() {
+        let charted: BTreeSet<&String> =
+            trends.anomalies.iter().map(|a| &a.metric).chain(trends.change_points.iter().map(|c| &c.metric)).collect();
+        let series = std::mem::take(&mut trends.series);
+        trends.series = series.into_iter().filter(|(metric, _)| charted.contains(metric)).collect();
+    }
+    trends
+}
+
+fn value_text(value: f64, unit: Option<&str>) -> String {
+    // Six significant digits, so small shifts stay visible without printing float noise
+    let decimals = (5.0 - value.abs().log10().floor()).clamp(0.0, 12.0) as usize;
+    let number = if value.fract() == 0.0 {
+        format!("{}", value)
+    } else {
+        format!("{:.*}", decimals, value).trim_end_matches('0').trim_end_matches('.').to_string()
+    };
+    match unit {
+        Some(unit) => format!("{} {}", number, unit),
+        None => number,
+    }
+}
+
+fn sigma_text(sigmas: Option<f64>) -> String {
+    sigmas.map_or("was constant".to_string(), |sigmas| format!("{:+.1}σ", sigmas))
+}
+
+pub struct TrendAnalyzer {
+    config: TrendConfig,
+}
+
+impl TrendAnalyzer {
+    pub fn new(config: TrendConfig) -> Self {
+        Self { config }
+    }
+
+    /// The unarchived runs in the run index.
+    pub fn load_history(&self) -> Result<RunHistory> {
+        let index = ResultsStore::new(ResultsConfig { dir: self.config.dir.clone(), ..ResultsConfig::default() }).index()?;
+        let runs_dir = self.config.dir.join("runs");
+        let mut history = RunHistory::default();
+        let wanted = |protocol: &str| self.config.protocol.as_ref().is_none_or(|wanted| wanted.eq_ignore_ascii_case(protocol));
+        for entry in index.runs.iter().filter(|entry| !entry.archived && wanted(&entry.protocol)) {
+            match load_result(&runs_dir.join(&entry.file)) {
+                Ok(result) => {
+                    let run = HistoryRun::from_result(&entry.file, entry.saved_at, &result);
+                    history.protocols.entry(entry.protocol.clone()).or_default().push(run);
+                }
+                Err(e) => history.unreadable.push(format!("{}: {:#}", entry.file, e)),
+            }
+        }
+        for runs in history.protocols.values_mut() {
+            runs.sort_by_key(|run| run.saved_at);
+        }
+        Ok(history)
+    }
+
+    pub fn run(&self) -> Result<TrendReport> {
+        if self.config.window < 2 || self.config.sigma <= 0.0 {
+            return Err(anyhow!("trends need a window of at least 2 runs and a positive sigma"));
+        }
+        let history = self.load_history()?;
+        if history.protocols.is_empty() {
+            return Err(anyhow!("no runs in the run index under {}", self.config.dir.join("runs").display()));
+        }
+        let report = TrendReport {
+            generated_at: Utc::now(),
+            window: self.config.window,
+            sigma: self.config.sigma,
+            protocols: history.protocols.iter().map(|(protocol, runs)| analyze_history(protocol, runs, &self.config)).collect(),
+        };
+
+        for file in &history.unreadable {
+            println!("⚠️ Skipped {}", file);
+        }
+        for trends in &report.protocols {
+            self.print(trends);
+        }
+        let output = self.config.output();
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+        }
+        std::fs::write(&output, serde_json::to_string_pretty(&report)?)
+            .with_context(|| format!("writing {}", output.display()))?;
+        println!("\n✅ Trends saved to: {}", output.display());
+        Ok(report)
+    }
+
+    fn print(&self, trends: &ProtocolTrends) {
+        println!("\n📈 {} trends over {} runs (rolling window {}, flagged at {}σ)",
+                 trends.protocol, trends.runs.len(), self.config.window, self.config.sigma);
+        println!("------------------------------------------------");
+        let latest = trends.runs.last().map(String::as_str).unwrap_or_default();
+        if trends.metrics_checked == 0 {
+            println!("⚠️ Not enough history: a metric needs {} earlier runs before its latest value is judged", MIN_HISTORY);
+            return;
+        }
+        if trends.environment_changes.is_empty() {
+            println!("✅ Latest run {} was measured in the same environment as the runs before it", latest);
+        } else {
+            println!("⚠️ Latest run {} was measured in a different environment: {}", latest, trends.environment_changes.join(", "));
+        }
+        for anomaly in &trends.anomalies {
+            let (marker, cause) = match anomaly.cause {
+                Cause::Environmental => ("⚠️", "environmental"),
+                Cause::Genuine => ("❌", "genuine"),
+            };
+            let unit = anomaly.unit.as_deref();
+            println!("{} Suspected {}: {} is {} against {} ± {} ({}); {}",
+                     marker, cause, anomaly.metric, value_text(anomaly.latest, unit),
+                     value_text(anomaly.rolling_mean, unit), value_text(anomaly.rolling_std_dev, unit),
+                     sigma_text(anomaly.deviation_sigma), anomaly.reasons.join("; "));
+        }
+        for change in trends.change_points.iter().take(PRINTED_CHANGE_POINTS) {
+            let unit = Unit::of(&change.metric).map(|unit| unit.to_string());
+            println!("🔀 Change point in {} at {}: {} → {} ({})",
+                     change.metric, change.run, value_text(change.before_mean, unit.as_deref()),
+                     value_text(change.after_mean, unit.as_deref()),
+                     change.shift_sigma.map_or("step from a constant".to_string(), |shift| format!("{:.1}σ shift", shift)));
+        }
+        if trends.change_points.len() > PRINTED_CHANGE_POINTS {
+            println!("🔀 ... and {} more change points in the JSON report", trends.change_points.len() - PRINTED_CHANGE_POINTS);
+        }
+        println!("✅ {} of {} metrics within {}σ of their rolling mean",
+                 trends.metrics_checked - trends.anomalies.len(), trends.metrics_checked, self.config.sigma);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn history(cpu_model_of_latest: &str) -> Vec<HistoryRun> {
+        let commissioning = [100.0, 101.0, 99.0, 100.0, 102.0, 98.0, 100.0, 130.0];
+        let step = [5.0, 5.1, 4.9, 5.0, 9.0, 9.1, 8.9, 9.0];
+        let discovery = [10.0, 10.2, 9.9, 10.1, 10.0, 9.8, 10.0, 10.05];
+        (0..commissioning.len())
+            .map(|i| {
+                let latest = i == commissioning.len() - 1;
+                let result = json!({
+                    "test_environment": {"cpu_model": if latest { cpu_model_of_latest } else { "Cortex-A72" }, "cpu_cores": 4},
+                    "osi_layer_5_session": {"commissioning_time_ms": commissioning[i], "pairing_overhead_bytes": if latest { 32 } else { 24 }},
+                    "osi_layer_4_transport": {"udp_discovery_time_ms": discovery[i]},
+                    "throughput": {"setup_time_ms": step[i]}
+                });
+                let saved_at = Utc.with_ymd_and_hms(2025, 6, 1 + i as u32, 10, 0, 0).unwrap();
+                HistoryRun::from_result(&format!("matter_{}.json", i), saved_at, &result)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn latest_deviations_are_flagged_and_attributed() {
+        let config = TrendConfig::default();
+        let trends = analyze_history("Matter", &history("Cortex-A72"), &config);
+        assert!(trends.environment_changes.is_empty());
+        assert_eq!(trends.metrics_checked, 4);
+        assert!(!trends.series.contains_key("test_environment.cpu_cores"));
+
+        let flagged: Vec<(&str, Cause)> = trends.anomalies.iter().map(|a| (a.metric.as_str(), a.cause)).collect();
+        assert_eq!(flagged, vec![
+            ("osi_layer_5_session.pairing_overhead_bytes", Cause::Genuine),
+            ("osi_layer_5_session.commissioning_time_ms", Cause::Genuine),
+        ]);
+        assert_eq!(trends.anomalies[0].deviation_sigma, None);
+        assert!(trends.anomalies[1].deviation_sigma.unwrap() > 10.0);
+
+        // The step happened mid-history and has settled, so it is a change point but not an anomaly
+        assert_eq!(trends.change_points.len(), 1);
+        assert_eq!(trends.change_points[0].metric, "throughput.setup_time_ms");
+        assert_eq!(trends.change_points[0].run, "matter_4.json");
+        assert_eq!(trends.series["throughput.setup_time_ms"].values.len(), 8);
+        assert!(!trends.series.contains_key("osi_layer_4_transport.udp_discovery_time_ms"));
+
+        let moved = analyze_history("Matter", &history("Intel i7"), &config);
+        assert_eq!(moved.environment_changes, vec!["cpu_model: Cortex-A72 → Intel i7"]);
+        assert!(moved.anomalies.iter().all(|a| a.cause == Cause::Environmental));
+    }
+}
@@ -0,0 +1,225 @@
+// matter-project/analysis-core/src/tunables.rs
+/*!
+Network tunables - socket buffers, congestion control and sysctls that bound throughput
+
+Two hosts running the same build can differ by an order of magnitude in
+throughput because of settings nobody looked at. A 4 MiB buffer request
+silently becomes 208 KiB under Linux's default `rmem_max`. A different
+congestion-control algorithm reshapes every TCP transfer. The settings are
+recorded in the result's `test_environment` so the difference is visible:
+
+- the receive and send buffers a UDP measurement socket actually got for
+  the size it asked for. Linux reports double the granted size, since the
+  kernel counts its bookkeeping in the buffer;
+- the buffers of a fresh TCP socket, and the congestion-control algorithm;
+- the sysctls that cap or default those buffers and the queues in front of
+  them, read from `/proc/sys` on Linux and `sysctl -n` on macOS.
+
+[`apply_sysctls`] sets sysctls for one run, which on Linux needs root. The
+returned guard restores the previous values when it is dropped.
+*/
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Read on Linux when present.
+const LINUX_SYSCTLS: [&str; 12] = [
+    "net.core.rmem_default",
+    "net.core.rmem_max",
+    "net.core.wmem_default",
+    "net.core.wmem_max",
+    "net.core.netdev_max_backlog",
+    "net.core.default_qdisc",
+    "net.ipv4.udp_mem",
+    "net.ipv4.udp_rmem_min",
+    "net.ipv4.tcp_rmem",
+    "net.ipv4.tcp_wmem",
+    "net.ipv4.tcp_congestion_control",
+    "net.ipv4.tcp_slow_start_after_idle",
+];
+
+/// Read on macOS when present.
+const MACOS_SYSCTLS: [&str; 5] = [
+    "kern.ipc.maxsockbuf",
+    "net.inet.udp.recvspace",
+    "net.inet.udp.maxdgram",
+    "net.inet.tcp.sendspace",
+    "net.inet.tcp.recvspace",
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NetworkTunables {
+    /// Buffer size measurement sockets ask for on this host.
+    pub requested_socket_buffer_bytes: usize,
+    /// SO_RCVBUF and SO_SNDBUF a UDP socket reported after that request.
+    pub udp_recv_buffer_bytes: Option<usize>,
+    pub udp_send_buffer_bytes: Option<usize>,
+    /// SO_RCVBUF and SO_SNDBUF of a TCP socket left at the defaults.
+    pub tcp_recv_buffer_bytes: Option<usize>,
+    pub tcp_send_buffer_bytes: Option<usize>,
+    pub tcp_congestion_control: Option<String>,
+    /// By sysctl name, as the OS prints them.
+    pub sysctls: BTreeMap<String, String>,
+    /// Sysctls this run set with `--sysctl`.
+    pub applied: Vec<AppliedSysctl>,
+}
+
+impl NetworkTunables {
+    pub fn detect(requested_socket_buffer_bytes: usize) -> Self {
+        let udp = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).ok();
+        if let Some(socket) = &udp {
+            // Clamped requests are what this records, so a refusal is not an error either
+            let _ = socket.set_recv_buffer_size(requested_socket_buffer_bytes);
+            let _ = socket.set_send_buffer_size(requested_socket_buffer_bytes);
+        }
+        let tcp = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP)).ok();
+        let sysctls = read_sysctls();
+
+        Self {
+            requested_socket_buffer_bytes,
+            udp_recv_buffer_bytes: udp.as_ref().and_then(|socket| socket.recv_buffer_size().ok()),
+            udp_send_buffer_bytes: udp.as_ref().and_then(|socket| socket.send_buffer_size().ok()),
+            tcp_recv_buffer_bytes: tcp.as_ref().and_then(|socket| socket.recv_buffer_size().ok()),
+            tcp_send_buffer_bytes: tcp.as_ref().and_then(|socket| socket.send_buffer_size().ok()),
+            tcp_congestion_control: sysctls
+                .get("net.ipv4.tcp_congestion_control")
+                .cloned()
+                .or_else(|| read_sysctl("net.inet.tcp.cc.algorithm")),
+            sysctls,
+            applied: Vec::new(),
+        }
+    }
+
+    /// Whether the UDP buffers came out smaller than requested. On Linux the
+    /// reported size is double the grant, so it is halved before comparing.
+    pub fn udp_buffers_clamped(&self) -> bool {
+        let granted = |reported: usize| if cfg!(target_os = "linux") { reported / 2 } else { reported };
+        [self.udp_recv_buffer_bytes, self.udp_send_buffer_bytes]
+            .into_iter()
+            .flatten()
+            .any(|reported| granted(reported) < self.requested_socket_buffer_bytes)
+    }
+}
+
+fn read_sysctls() -> BTreeMap<String, String> {
+    let names: &[&str] = match std::env::consts::OS {
+        "linux" => &LINUX_SYSCTLS,
+        "macos" => &MACOS_SYSCTLS,
+        _ => &[],
+    };
+    names.iter().filter_map(|name| Some((name.to_string(), read_sysctl(name)?))).collect()
+}
+
+/// Whitespace-normalised value of a sysctl, or None where it does not exist.
+pub fn read_sysctl(name: &str) -> Option<String> {
+    let value = match std::env::consts::OS {
+        "linux" => std::fs::read_to_string(proc_path(name)).ok()?,
+        "macos" => {
+            let output = std::process::Command::new("sysctl").args(["-n", name]).output().ok()?;
+            if !output.status.success() {
+                return None;
+            }
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+        _ => return None,
+    };
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!value.is_empty()).then_some(value)
+}
+
+fn proc_path(name: &str) -> PathBuf {
+    PathBuf::from("/proc/sys").join(name.replace('.', "/"))
+}
+
+/// One `--sysctl name=value` and what became of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedSysctl {
+    pub name: String,
+    pub value: String,
+    /// Value before the run; None when the sysctl could not be read.
+    pub previous: Option<String>,
+    pub applied: bool,
+    /// Why it was not applied.
+    pub error: Option<String>,
+}
+
+/// Restores the sysctls it applied when dropped.
+#[derive(Debug, Default)]
+pub struct SysctlGuard {
+    applied: Vec<AppliedSysctl>,
+}
+
+impl SysctlGuard {
+    pub fn applied(&self) -> &[AppliedSysctl] {
+        &self.applied
+    }
+}
+
+impl Drop for SysctlGuard {
+    fn drop(&mut self) {
+        for setting in self.applied.iter().filter(|setting| setting.applied) {
+            let Some(previous) = &setting.previous else { continue };
+            match write_sysctl(&setting.name, previous) {
+                Ok(()) => debug!("Restored {} = {}", setting.name, previous),
+                Err(e) => warn!("Could not restore {} to {}: {}", setting.name, previous, e),
+            }
+        }
+    }
+}
+
+/// Sets each `(name, value)` for the lifetime of the guard. A setting that
+/// cannot be applied is recorded with its error rather than failing the run.
+pub fn apply_sysctls(settings: &[(String, String)]) -> SysctlGuard {
+    let applied = settings
+        .iter()
+        .map(|(name, value)| {
+            let previous = read_sysctl(name);
+            let result = if previous.is_none() {
+                Err(format!("{} does not exist on this host", name))
+            } else {
+                write_sysctl(name, value).map_err(|e| e.to_string())
+            };
+            AppliedSysctl {
+                name: name.clone(),
+                value: value.clone(),
+                previous,
+                applied: result.is_ok(),
+                error: result.err(),
+            }
+        })
+        .collect();
+    SysctlGuard { applied }
+}
+
+fn write_sysctl(name: &str, value: &str) -> std::io::Result<()> {
+    if !cfg!(target_os = "linux") {
+        return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "setting sysctls is only supported on Linux"));
+    }
+    // Multi-value sysctls such as tcp_rmem take tab-separated fields
+    std::fs::write(proc_path(name), value.split_whitespace().collect::<Vec<_>>().join("\t"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effective_buffers_and_sysctls_are_recorded() {
+        let tunables = NetworkTunables::detect(64 * 1024);
+        assert!(tunables.udp_recv_buffer_bytes.is_some_and(|bytes| bytes > 0));
+        assert!(tunables.tcp_send_buffer_bytes.is_some_and(|bytes| bytes > 0));
+        if cfg!(target_os = "linux") {
+            assert!(tunables.sysctls.contains_key("net.core.rmem_max"));
+            assert_eq!(tunables.tcp_congestion_control, tunables.sysctls.get("net.ipv4.tcp_congestion_control").cloned());
+        }
+        // Far beyond any default rmem_max
+        assert!(NetworkTunables::detect(1 << 30).udp_buffers_clamped());
+
+        let guard = apply_sysctls(&[("net.core.no_such_setting".to_string(), "1".to_string())]);
+        let [setting] = guard.applied() else { panic!("{:?}", guard.applied()) };
+        assert!(!setting.applied && setting.previous.is_none() && setting.error.is_some());
+    }
+}
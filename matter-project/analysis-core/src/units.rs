@@ -0,0 +1,247 @@
+// matter-project/analysis-core/src/units.rs
+/*!
+Units - the unit of each metric, read from its name, and conversion to the units and precision a reader asks for
+
+Results carry units in their field names: `commissioning_time_ms`,
+`tlv_overhead_bytes`, `keep_alive_secs`. A bare number loses that as soon
+as it leaves its field, in a spreadsheet cell or a query row, and floats are
+written with whatever digits the arithmetic left. This module reads the
+unit from the last segment of a metric path, converts between units of the
+same dimension, and rounds to a fixed number of decimals:
+
+- time: `_ns`, `_us`, `_ms`, `_s` and `_secs`;
+- size: `_bytes`, `_kb`/`_kib` (KiB) and `_mb`/`_mib` (MiB), binary, so 1 KiB is 1024 bytes.
+
+Rates (`_per_s`, `_mbps`) and everything else are left unconverted. Result
+files keep the units their field names give, so runs stay comparable; they
+gain a `units` section naming the unit of every metric and the precision the
+file was written at. `query` and `export` convert to the requested units and
+give each value's unit explicitly.
+*/
+
+use crate::diff::flatten;
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Top-level result section naming each metric's unit; not a measurement itself.
+pub const UNITS_SECTION: &str = "units";
+/// Sections keyed by metric name whose values are counts, not the metric itself.
+pub(crate) const KEYED_BY_METRIC: [&str; 1] = ["metric_retries"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeUnit {
+    Ns,
+    Us,
+    Ms,
+    S,
+}
+
+impl TimeUnit {
+    fn seconds(self) -> f64 {
+        match self {
+            TimeUnit::Ns => 1e-9,
+            TimeUnit::Us => 1e-6,
+            TimeUnit::Ms => 1e-3,
+            TimeUnit::S => 1.0,
+        }
+    }
+}
+
+impl FromStr for TimeUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "ns" => Ok(TimeUnit::Ns),
+            "us" | "µs" => Ok(TimeUnit::Us),
+            "ms" => Ok(TimeUnit::Ms),
+            "s" => Ok(TimeUnit::S),
+            other => Err(anyhow!("unknown time unit: {} (expected ns, us, ms or s)", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeUnit {
+    Bytes,
+    KiB,
+    MiB,
+}
+
+impl SizeUnit {
+    fn bytes(self) -> f64 {
+        match self {
+            SizeUnit::Bytes => 1.0,
+            SizeUnit::KiB => 1024.0,
+            SizeUnit::MiB => 1024.0 * 1024.0,
+        }
+    }
+}
+
+impl FromStr for SizeUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "b" | "bytes" => Ok(SizeUnit::Bytes),
+            "kib" | "kb" => Ok(SizeUnit::KiB),
+            "mib" | "mb" => Ok(SizeUnit::MiB),
+            other => Err(anyhow!("unknown size unit: {} (expected bytes, KiB or MiB)", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Time(TimeUnit),
+    Size(SizeUnit),
+}
+
+impl Unit {
+    /// The unit `metric` is in, from the suffix of its last path segment.
+    pub fn of(metric: &str) -> Option<Unit> {
+        if KEYED_BY_METRIC.iter().any(|section| metric.strip_prefix(section).is_some_and(|rest| rest.starts_with('.'))) {
+            return None;
+        }
+        let name = metric.rsplit('.').next().unwrap_or(metric).to_ascii_lowercase();
+        if name.ends_with("_per_s") || name.ends_with("_per_sec") {
+            return None;
+        }
+        let suffix = name.rsplit('_').next().filter(|suffix| suffix.len() < name.len())?;
+        match suffix {
+            "ns" => Some(Unit::Time(TimeUnit::Ns)),
+            "us" => Some(Unit::Time(TimeUnit::Us)),
+            "ms" => Some(Unit::Time(TimeUnit::Ms)),
+            "s" | "secs" => Some(Unit::Time(TimeUnit::S)),
+            "bytes" => Some(Unit::Size(SizeUnit::Bytes)),
+            "kb" | "kib" => Some(Unit::Size(SizeUnit::KiB)),
+            "mb" | "mib" => Some(Unit::Size(SizeUnit::MiB)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Unit::Time(TimeUnit::Ns) => "ns",
+            Unit::Time(TimeUnit::Us) => "us",
+            Unit::Time(TimeUnit::Ms) => "ms",
+            Unit::Time(TimeUnit::S) => "s",
+            Unit::Size(SizeUnit::Bytes) => "bytes",
+            Unit::Size(SizeUnit::KiB) => "KiB",
+            Unit::Size(SizeUnit::MiB) => "MiB",
+        })
+    }
+}
+
+/// The units and precision outputs are written in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Units {
+    pub time: TimeUnit,
+    pub size: SizeUnit,
+    /// Decimal places to round to; `None` keeps every digit.
+    pub precision: Option<u32>,
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Self { time: TimeUnit::Ms, size: SizeUnit::Bytes, precision: None }
+    }
+}
+
+impl Units {
+    /// `value` of `metric` in these units, rounded, with the unit it is now in when `metric` has one.
+    pub fn convert(&self, metric: &str, value: f64) -> (f64, Option<Unit>) {
+        match Unit::of(metric) {
+            Some(Unit::Time(from)) => (self.round(value * from.seconds() / self.time.seconds()), self.unit_for(metric)),
+            Some(Unit::Size(from)) => (self.round(value * from.bytes() / self.size.bytes()), self.unit_for(metric)),
+            None => (self.round(value), None),
+        }
+    }
+
+    /// The unit [`Units::convert`] puts `metric` in.
+    pub fn unit_for(&self, metric: &str) -> Option<Unit> {
+        Unit::of(metric).map(|unit| match unit {
+            Unit::Time(_) => Unit::Time(self.time),
+            Unit::Size(_) => Unit::Size(self.size),
+        })
+    }
+
+    pub fn round(&self, value: f64) -> f64 {
+        match self.precision {
+            Some(decimals) => {
+                let scale = 10f64.powi(decimals as i32);
+                (value * scale).round() / scale
+            }
+            None => value,
+        }
+    }
+
+    /// Rounds every float in a result and adds its `units` section. The values keep the units their names give.
+    pub fn annotate(&self, result: &mut Value) {
+        self.round_floats(result);
+        let metrics = unit_table(result);
+        if let Value::Object(fields) = result {
+            fields.insert(UNITS_SECTION.to_string(), json!({"precision": self.precision, "metrics": metrics}));
+        }
+    }
+
+    /// Rounds every float in `value` to the precision; integers are left as they are.
+    pub fn round_floats(&self, value: &mut Value) {
+        if self.precision.is_none() {
+            return;
+        }
+        match value {
+            Value::Number(n) if n.is_f64() => {
+                if let Some(rounded) = n.as_f64().and_then(|v| serde_json::Number::from_f64(self.round(v))) {
+                    *n = rounded;
+                }
+            }
+            Value::Array(elements) => elements.iter_mut().for_each(|element| self.round_floats(element)),
+            Value::Object(fields) => fields.values_mut().for_each(|field| self.round_floats(field)),
+            _ => {}
+        }
+    }
+}
+
+/// The unit of every numeric metric in `value` whose name gives one, by metric path.
+pub fn unit_table(value: &Value) -> BTreeMap<String, String> {
+    let mut metrics = BTreeMap::new();
+    flatten(value, String::new(), &mut metrics);
+    metrics
+        .into_keys()
+        .filter_map(|metric| Unit::of(&metric).map(|unit| (metric, unit.to_string())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn units_come_from_names_and_convert_within_a_dimension() {
+        assert_eq!(Unit::of("osi_layer_5_session.commissioning_time_ms"), Some(Unit::Time(TimeUnit::Ms)));
+        assert_eq!(Unit::of("keep_alive_secs"), Some(Unit::Time(TimeUnit::S)));
+        assert_eq!(Unit::of("memory_per_connection_kb"), Some(Unit::Size(SizeUnit::KiB)));
+        assert_eq!(Unit::of("peak_wire_bytes_per_s"), None);
+        assert_eq!(Unit::of("goodput_mbps"), None);
+        assert_eq!(Unit::of("metric_retries.commissioning_time_ms"), None);
+        assert_eq!(Unit::of("ms"), None);
+
+        let units = Units { time: TimeUnit::Us, size: SizeUnit::KiB, precision: Some(2) };
+        assert_eq!(units.convert("commissioning_time_ms", 1.234567), (1234.57, Some(Unit::Time(TimeUnit::Us))));
+        assert_eq!(units.convert("tlv_overhead_bytes", 1536.0), (1.5, Some(Unit::Size(SizeUnit::KiB))));
+        assert_eq!(units.convert("efficiency_score", 0.87654), (0.88, None));
+
+        let mut result = json!({"osi_layer_4_transport": {"udp_discovery_time_ms": 0.0016929999999999998, "total_transport_overhead": 60}});
+        Units { precision: Some(3), ..Units::default() }.annotate(&mut result);
+        assert_eq!(result["osi_layer_4_transport"]["udp_discovery_time_ms"], 0.002);
+        assert_eq!(result["osi_layer_4_transport"]["total_transport_overhead"], 60);
+        assert_eq!(result["units"]["precision"], 3);
+        assert_eq!(result["units"]["metrics"], json!({"osi_layer_4_transport.udp_discovery_time_ms": "ms"}));
+    }
+}
@@ -0,0 +1,293 @@
+// matter-project/analysis-core/src/watchdog.rs
+/*!
+Stage watchdog - cancels analyzer stages that hang instead of letting them stall the whole run
+
+Every stage runs under a hard ceiling: the configured stage timeout times a
+factor, 10 by default. That way a slow stage keeps going, but one stuck on a
+receive that never completes does not. When a stage passes its ceiling, the
+watchdog prints what the stage was waiting on and the state of its sockets.
+It then drops the stage's future, which cancels it at its pending await,
+and records a [`StageFailure`]. The run carries on and the failure goes into
+the result. A watchdog given an [`EventLog`] also logs every stage's start
+and outcome there.
+
+Stages tell the watchdog what they are doing through the [`StageProbe`] it
+hands them. `probe.pending("...")` marks an await, and `probe.socket(...)`
+registers a socket. Stages that never use their probe still get cancelled.
+Their diagnostics just say less. Blocking code that never reaches an await
+cannot be cancelled this way.
+*/
+
+use crate::events::{EventLog, Outcome};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use socket2::{SockRef, Socket};
+use std::future::Future;
+use std::mem::MaybeUninit;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct WatchdogConfig {
+    /// The time a stage is expected to need at most.
+    pub stage_timeout: Duration,
+    /// A stage is cancelled once it has run for `stage_timeout * ceiling_factor`.
+    pub ceiling_factor: u32,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self { stage_timeout: Duration::from_secs(60), ceiling_factor: 10 }
+    }
+}
+
+impl WatchdogConfig {
+    pub fn ceiling(&self) -> Duration {
+        self.stage_timeout * self.ceiling_factor
+    }
+}
+
+/// A stage the watchdog cancelled, or one skipped because a stage it needs was cancelled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageFailure {
+    pub stage: String,
+    pub reason: String,
+    pub elapsed_ms: f64,
+    /// What the stage was awaiting when it was cancelled, outermost first.
+    pub pending: Vec<String>,
+    pub sockets: Vec<SocketState>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SocketState {
+    pub name: String,
+    pub local_addr: Option<String>,
+    pub peer_addr: Option<String>,
+    /// Pending socket error (`SO_ERROR`), if any.
+    pub error: Option<String>,
+    /// Size of the next queued datagram or stream bytes, or 0 when nothing has arrived.
+    pub queued_bytes: usize,
+}
+
+#[derive(Default)]
+struct ProbeState {
+    next_id: u64,
+    pending: Vec<(u64, String, Instant)>,
+    sockets: Vec<(String, Socket)>,
+}
+
+/// Lets a stage tell the watchdog what it is waiting on.
+#[derive(Clone, Default)]
+pub struct StageProbe {
+    state: Arc<Mutex<ProbeState>>,
+}
+
+impl StageProbe {
+    /// Marks `what` as pending until the returned guard is dropped.
+    pub fn pending(&self, what: impl Into<String>) -> PendingGuard {
+        let mut state = self.lock();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.pending.push((id, what.into(), Instant::now()));
+        PendingGuard { probe: self.clone(), id }
+    }
+
+    /// Registers a socket whose state is reported if the stage hangs.
+    pub fn socket<S>(&self, name: impl Into<String>, socket: &S) -> Result<()>
+    where
+        for<'s> SockRef<'s>: From<&'s S>,
+    {
+        // A duplicate handle keeps the socket inspectable for as long as the probe exists.
+        // tokio sockets are already non-blocking; this makes sure peeking at them cannot block
+        let socket = SockRef::from(socket).try_clone()?;
+        socket.set_nonblocking(true)?;
+        self.lock().sockets.push((name.into(), socket));
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ProbeState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn pending_now(&self) -> Vec<String> {
+        self.lock()
+            .pending
+            .iter()
+            .map(|(_, what, since)| format!("{} (for {:.1}s)", what, since.elapsed().as_secs_f64()))
+            .collect()
+    }
+
+    fn socket_states(&self) -> Vec<SocketState> {
+        self.lock()
+            .sockets
+            .iter()
+            .map(|(name, socket)| SocketState {
+                name: name.clone(),
+                local_addr: socket.local_addr().ok().and_then(|addr| addr.as_socket()).map(|addr| addr.to_string()),
+                peer_addr: socket.peer_addr().ok().and_then(|addr| addr.as_socket()).map(|addr| addr.to_string()),
+                error: socket.take_error().ok().flatten().map(|e| e.to_string()),
+                queued_bytes: queued_bytes(socket),
+            })
+            .collect()
+    }
+}
+
+/// Registered sockets are non-blocking, so peeking returns at once when nothing is queued.
+fn queued_bytes(socket: &Socket) -> usize {
+    let mut buffer = [MaybeUninit::<u8>::uninit(); 65_536];
+    socket.peek(&mut buffer).unwrap_or(0)
+}
+
+pub struct PendingGuard {
+    probe: StageProbe,
+    id: u64,
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        self.probe.lock().pending.retain(|(id, _, _)| *id != self.id);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Watchdog {
+    config: WatchdogConfig,
+    failures: Vec<StageFailure>,
+    events: EventLog,
+}
+
+impl Watchdog {
+    pub fn new(config: WatchdogConfig) -> Self {
+        Self { config, failures: Vec::new(), events: EventLog::disabled() }
+    }
+
+    /// Logs each stage's start and outcome to `events`.
+    pub fn with_event_log(mut self, events: EventLog) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Runs a stage under the ceiling. `Ok(None)` means the watchdog cancelled it;
+    /// errors the stage returns itself are passed through.
+    pub async fn guard<T, F, Fut>(&mut self, stage: &str, start_stage: F) -> Result<Option<T>>
+    where
+        F: FnOnce(StageProbe) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let probe = StageProbe::default();
+        self.events.phase_started(stage);
+        let start = Instant::now();
+        let future = std::pin::pin!(start_stage(probe.clone()));
+        match tokio::time::timeout(self.config.ceiling(), future).await {
+            Ok(Ok(value)) => {
+                self.events.phase_finished(stage, Outcome::Ok, start.elapsed(), None);
+                Ok(Some(value))
+            }
+            Ok(Err(e)) => {
+                self.events.phase_finished(stage, Outcome::Failed, start.elapsed(), Some(format!("{:#}", e)));
+                Err(e)
+            }
+            Err(_) => {
+                // Collected before returning, while the cancelled future still owns its sockets
+                let failure = StageFailure {
+                    stage: stage.to_string(),
+                    reason: format!(
+                        "cancelled after exceeding the {:.0}s ceiling ({}× the {:.0}s stage timeout)",
+                        self.config.ceiling().as_secs_f64(),
+                        self.config.ceiling_factor,
+                        self.config.stage_timeout.as_secs_f64()
+                    ),
+                    elapsed_ms: start.elapsed().as_secs_f64() * 1000.0,
+                    pending: probe.pending_now(),
+                    sockets: probe.socket_states(),
+                };
+                print_failure(&failure);
+                self.events.phase_finished(stage, Outcome::Cancelled, start.elapsed(), Some(failure.reason.clone()));
+                self.failures.push(failure);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Records a stage that was not run because a stage it depends on failed.
+    pub fn skip(&mut self, stage: &str, reason: impl Into<String>) {
+        let failure = StageFailure {
+            stage: stage.to_string(),
+            reason: reason.into(),
+            elapsed_ms: 0.0,
+            pending: Vec::new(),
+            sockets: Vec::new(),
+        };
+        println!("⏭️ Skipping {} stage: {}", failure.stage, failure.reason);
+        self.events.phase_finished(stage, Outcome::Skipped, Duration::ZERO, Some(failure.reason.clone()));
+        self.failures.push(failure);
+    }
+
+    pub fn has_failed(&self, stage: &str) -> bool {
+        self.failures.iter().any(|failure| failure.stage == stage)
+    }
+
+    pub fn failures(&self) -> &[StageFailure] {
+        &self.failures
+    }
+
+    pub fn into_failures(self) -> Vec<StageFailure> {
+        self.failures
+    }
+}
+
+fn print_failure(failure: &StageFailure) {
+    println!("\n⏱️ Watchdog: {} stage {}", failure.stage, failure.reason);
+    println!("{}", "-".repeat(50));
+    if failure.pending.is_empty() {
+        println!("⏳ Pending: nothing reported by the stage");
+    }
+    for pending in &failure.pending {
+        println!("⏳ Pending: {}", pending);
+    }
+    for socket in &failure.sockets {
+        println!(
+            "🔌 {}: {} → {}, {} bytes queued{}",
+            socket.name,
+            socket.local_addr.as_deref().unwrap_or("unbound"),
+            socket.peer_addr.as_deref().unwrap_or("unconnected"),
+            socket.queued_bytes,
+            socket.error.as_deref().map(|e| format!(", error: {}", e)).unwrap_or_default()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hung_stages_are_cancelled_with_diagnostics() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let mut watchdog = Watchdog::new(WatchdogConfig { stage_timeout: Duration::from_millis(5), ceiling_factor: 2 });
+
+            let finished = watchdog.guard("quick", |_| async { Ok(7) }).await.unwrap();
+            assert_eq!(finished, Some(7));
+
+            let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let hung = watchdog
+                .guard("hung", |probe| async move {
+                    probe.socket("listener", &socket)?;
+                    let _waiting = probe.pending("recv from device");
+                    std::future::pending::<Result<()>>().await
+                })
+                .await
+                .unwrap();
+            assert!(hung.is_none());
+            assert!(watchdog.guard("failing", |_| async { Err::<(), _>(anyhow::anyhow!("bad")) }).await.is_err());
+
+            let failure = &watchdog.failures()[0];
+            assert_eq!(failure.stage, "hung");
+            assert!(failure.pending[0].starts_with("recv from device"));
+            assert_eq!(failure.sockets[0].name, "listener");
+            assert_eq!(failure.sockets[0].queued_bytes, 0);
+            assert!(!watchdog.has_failed("failing"));
+        });
+    }
+}
@@ -0,0 +1,84 @@
+// matter-project/examples/analyzer_plugin.rs
+// Minimal protocol plugin for a made-up "Acme" protocol (see src/plugin.rs)
+//
+// Build it and install it under the name the analyzer looks for:
+//   cargo build --example analyzer_plugin
+//   copy target\debug\examples\analyzer_plugin.exe plugins\analyzer-plugin-acme.exe
+//   cargo run -- --plugin acme --plugin-option latency_ms=20
+
+use serde_json::{json, Value};
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+const INTERFACE_VERSION: u32 = 1;
+
+fn main() {
+    let mut input = String::new();
+    let response = match std::io::stdin().read_to_string(&mut input) {
+        Ok(_) => match serde_json::from_str::<Value>(&input) {
+            Ok(request) => handle(&request),
+            Err(e) => Err(format!("invalid request: {}", e)),
+        },
+        Err(e) => Err(format!("reading request: {}", e)),
+    };
+    let mut response = response.unwrap_or_else(|error| json!({"error": error}));
+    response["interface_version"] = json!(INTERFACE_VERSION);
+    println!("{}", response);
+}
+
+fn handle(request: &Value) -> Result<Value, String> {
+    if request["interface_version"] != json!(INTERFACE_VERSION) {
+        return Err(format!("unsupported interface version {}", request["interface_version"]));
+    }
+    match request["command"].as_str() {
+        Some("describe") => Ok(json!({
+            "name": "acme",
+            "protocol": "Acme",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Example plugin with simulated Acme timings",
+        })),
+        Some("analyze") => analyze(request),
+        other => Err(format!("unknown command {:?}", other)),
+    }
+}
+
+fn analyze(request: &Value) -> Result<Value, String> {
+    let latency_ms: u64 = match request["options"]["latency_ms"].as_str() {
+        Some(value) => value.parse().map_err(|_| format!("latency_ms must be a number, got {}", value))?,
+        None => 10,
+    };
+    // Progress goes to stderr; stdout is reserved for the response
+    eprintln!("📡 Simulating Acme exchanges with {}ms latency...", latency_ms);
+    let discovery = timed(latency_ms);
+    let handshake = timed(latency_ms * 3);
+
+    Ok(json!({
+        "result": {
+            "schema_version": request["schema_version"],
+            "protocol_name": "Acme_Protocol_Analysis",
+            "osi_layer_4_transport": {
+                "udp_discovery_time_ms": discovery,
+                "tcp_connection_time_ms": 0.0,
+                "total_transport_overhead": 16,
+            },
+            "osi_layer_5_session": {
+                "commissioning_time_ms": handshake,
+                "pairing_overhead_bytes": 96,
+            },
+            "osi_layer_6_presentation": {
+                "encoding_time_ms": 0.01,
+                "tlv_overhead_bytes": 12,
+            },
+            "osi_layer_7_application": {
+                "discovery_time_ms": discovery,
+                "application_overhead_bytes": 20,
+            },
+        }
+    }))
+}
+
+fn timed(simulated_ms: u64) -> f64 {
+    let start = Instant::now();
+    std::thread::sleep(Duration::from_millis(simulated_ms));
+    start.elapsed().as_secs_f64() * 1000.0
+}
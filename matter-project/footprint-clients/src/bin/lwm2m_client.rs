@@ -0,0 +1,30 @@
+// matter-project/footprint-clients/src/bin/lwm2m_client.rs
+// Minimal LwM2M client: one CoAP registration (POST /rd) over UDP
+
+use std::net::UdpSocket;
+
+fn build_registration() -> Vec<u8> {
+    let mut message = vec![
+        0x40, 0x02, 0x00, 0x01, // CON, POST, message ID 1, no token
+        0xB2, b'r', b'd',       // Uri-Path (11) "rd"
+        0x11, 0x28,             // Content-Format (12) = 40 (link-format)
+    ];
+
+    // Uri-Query (15): first delta 3 from Content-Format, then repeated
+    let queries = ["ep=client", "lt=300", "lwm2m=1.1"];
+    for (index, query) in queries.iter().enumerate() {
+        let delta = if index == 0 { 3 } else { 0 };
+        message.push((delta << 4) | query.len() as u8);
+        message.extend_from_slice(query.as_bytes());
+    }
+
+    message.push(0xFF);
+    message.extend_from_slice(b"</1/0>,</3/0>");
+    message
+}
+
+fn main() -> std::io::Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.send_to(&build_registration(), "127.0.0.1:5683")?;
+    Ok(())
+}
@@ -0,0 +1,43 @@
+// matter-project/footprint-clients/src/bin/matter_client.rs
+// Minimal Matter client: one OnOff Toggle InvokeRequest over UDP
+
+use metrics_core::tlv::{Tag, TlvError, TlvWriter};
+use std::net::UdpSocket;
+
+fn build_invoke_request(buf: &mut [u8]) -> Result<usize, TlvError> {
+    // Message header: flags, session ID, security flags, message counter
+    let header_len = 8;
+    buf[..header_len].copy_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00]);
+
+    // Protocol header: exchange flags (initiator + reliable), InvokeRequest, exchange ID, IM protocol
+    let proto_len = 6;
+    buf[header_len..header_len + proto_len].copy_from_slice(&[0x05, 0x08, 0x01, 0x00, 0x01, 0x00]);
+
+    let mut writer = TlvWriter::new(&mut buf[header_len + proto_len..]);
+    writer.start_struct(Tag::Anonymous)?;
+    writer.bool(Tag::Context(0), false)?;
+    writer.bool(Tag::Context(1), false)?;
+    writer.start_array(Tag::Context(2))?;
+    writer.start_struct(Tag::Anonymous)?;
+    writer.start_list(Tag::Context(0))?;
+    writer.u16(Tag::Context(0), 1)?;
+    writer.u32(Tag::Context(1), 0x0006)?;
+    writer.u32(Tag::Context(2), 0x02)?;
+    writer.end_container()?;
+    writer.start_struct(Tag::Context(1))?;
+    writer.end_container()?;
+    writer.end_container()?;
+    writer.end_container()?;
+    writer.end_container()?;
+
+    Ok(header_len + proto_len + writer.len())
+}
+
+fn main() -> std::io::Result<()> {
+    let mut buf = [0u8; 128];
+    let len = build_invoke_request(&mut buf).map_err(|e| std::io::Error::other(format!("{:?}", e)))?;
+
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.send_to(&buf[..len], "127.0.0.1:5540")?;
+    Ok(())
+}
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use matter_research_analyzer::robustness::{parse_packet, PacketKind};
+
+fuzz_target!(|data: &[u8]| {
+    parse_packet(PacketKind::Discovery, data);
+});
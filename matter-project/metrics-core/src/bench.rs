@@ -0,0 +1,97 @@
+// matter-project/metrics-core/src/bench.rs
+/*!
+Encoding benchmarks runnable on both host and device
+*/
+
+use crate::metrics::PresentationSample;
+use crate::tlv::{Tag, TlvError, TlvWriter};
+
+/// Monotonic microsecond clock supplied by the platform (std Instant,
+/// embassy-time, esp-hal timers, ...).
+pub trait Clock {
+    fn now_us(&self) -> u64;
+}
+
+/// Buffer size that comfortably fits [`encode_reference_report`].
+pub const REFERENCE_REPORT_CAPACITY: usize = 256;
+
+/// Encodes the reference attribute report used by every presentation benchmark:
+/// a light's OnOff/Level/ColorTemperature, a temperature reading and Basic
+/// Information strings, each as an AttributeReportIB-style path + data pair.
+pub fn encode_reference_report(writer: &mut TlvWriter<'_>) -> Result<(), TlvError> {
+    writer.start_struct(Tag::Anonymous)?;
+    writer.start_array(Tag::Context(1))?;
+
+    attribute(writer, 1, 0x0006, 0x0000, |w| w.bool(Tag::Context(2), true))?;
+    attribute(writer, 1, 0x0008, 0x0000, |w| w.u8(Tag::Context(2), 254))?;
+    attribute(writer, 1, 0x0300, 0x0007, |w| w.u16(Tag::Context(2), 370))?;
+    attribute(writer, 2, 0x0402, 0x0000, |w| w.i16(Tag::Context(2), 2150))?;
+    attribute(writer, 0, 0x0028, 0x0001, |w| w.utf8(Tag::Context(2), "TestVendor"))?;
+    attribute(writer, 0, 0x0028, 0x0003, |w| w.utf8(Tag::Context(2), "Research OnOff Light"))?;
+    attribute(writer, 0, 0x0028, 0x000A, |w| w.utf8(Tag::Context(2), "1.0.0"))?;
+
+    writer.end_container()?;
+    writer.end_container()
+}
+
+fn attribute<F>(writer: &mut TlvWriter<'_>, endpoint: u16, cluster: u32, attribute: u32, data: F) -> Result<(), TlvError>
+where
+    F: FnOnce(&mut TlvWriter<'_>) -> Result<(), TlvError>,
+{
+    writer.start_struct(Tag::Anonymous)?;
+    writer.start_list(Tag::Context(1))?;
+    writer.u16(Tag::Context(2), endpoint)?;
+    writer.u32(Tag::Context(3), cluster)?;
+    writer.u32(Tag::Context(4), attribute)?;
+    writer.end_container()?;
+    data(writer)?;
+    writer.end_container()
+}
+
+/// Encodes the reference report `iterations` times and reports the average cost.
+pub fn benchmark_tlv_encoding<C: Clock>(clock: &C, buf: &mut [u8], iterations: u32) -> Result<PresentationSample, TlvError> {
+    let mut raw_size_bytes = 0;
+    let mut encoded_size_bytes = 0;
+
+    let start = clock.now_us();
+    for _ in 0..iterations {
+        let mut writer = TlvWriter::new(buf);
+        encode_reference_report(&mut writer)?;
+        raw_size_bytes = writer.raw_bytes() as u32;
+        encoded_size_bytes = writer.len() as u32;
+    }
+    let total_time_us = clock.now_us().saturating_sub(start);
+
+    Ok(PresentationSample {
+        iterations,
+        total_time_us,
+        raw_size_bytes,
+        encoded_size_bytes,
+    })
+}
+
+#[cfg(feature = "std")]
+pub struct StdClock {
+    origin: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl StdClock {
+    pub fn new() -> Self {
+        Self { origin: std::time::Instant::now() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for StdClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for StdClock {
+    fn now_us(&self) -> u64 {
+        self.origin.elapsed().as_micros() as u64
+    }
+}
@@ -0,0 +1,18 @@
+// matter-project/metrics-core/src/lib.rs
+/*!
+Protocol measurement definitions that build without std or tokio.
+
+The same metric types and encoding benchmarks run on the desktop analyzer and
+on embedded targets (ESP32 via esp-rs, nRF via Embassy). Embedded firmware
+supplies its own [`bench::Clock`] and ships the resulting samples back to the
+desktop comparison engine using the optional `serde` derives.
+*/
+
+#![no_std]
+
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod bench;
+pub mod metrics;
+pub mod tlv;
@@ -0,0 +1,81 @@
+// matter-project/metrics-core/src/metrics.rs
+/*!
+Fixed-size metric types shared between host and device
+*/
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OsiLayer {
+    Transport = 4,
+    Session = 5,
+    Presentation = 6,
+    Application = 7,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Phase {
+    UdpDiscovery,
+    TcpConnection,
+    Commissioning,
+    Encoding,
+    ClusterDiscovery,
+}
+
+impl Phase {
+    pub fn layer(&self) -> OsiLayer {
+        match self {
+            Phase::UdpDiscovery | Phase::TcpConnection => OsiLayer::Transport,
+            Phase::Commissioning => OsiLayer::Session,
+            Phase::Encoding => OsiLayer::Presentation,
+            Phase::ClusterDiscovery => OsiLayer::Application,
+        }
+    }
+}
+
+/// A single timed phase, in microseconds so it fits devices without floats to spare.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TimingSample {
+    pub phase: Phase,
+    pub duration_us: u32,
+}
+
+impl TimingSample {
+    pub fn duration_ms(&self) -> f64 {
+        self.duration_us as f64 / 1000.0
+    }
+}
+
+/// Result of a presentation-layer encoding benchmark.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PresentationSample {
+    pub iterations: u32,
+    pub total_time_us: u64,
+    pub raw_size_bytes: u32,
+    pub encoded_size_bytes: u32,
+}
+
+impl PresentationSample {
+    pub fn encoding_time_ms(&self) -> f64 {
+        if self.iterations == 0 {
+            return 0.0;
+        }
+        self.total_time_us as f64 / self.iterations as f64 / 1000.0
+    }
+
+    pub fn overhead_bytes(&self) -> u32 {
+        self.encoded_size_bytes.saturating_sub(self.raw_size_bytes)
+    }
+
+    pub fn compression_ratio(&self) -> f64 {
+        if self.encoded_size_bytes == 0 {
+            return 0.0;
+        }
+        self.raw_size_bytes as f64 / self.encoded_size_bytes as f64
+    }
+}
@@ -0,0 +1,365 @@
+// matter-project/metrics-core/src/tlv.rs
+/*!
+Minimal Matter TLV writer and reader over caller-provided buffers (no allocation)
+
+The reader decodes the element types the writer produces and rejects anything
+else, so bytes from a peer come back as an error rather than a panic.
+*/
+
+// Tag control (upper 3 bits of the control octet)
+const TAG_ANONYMOUS: u8 = 0x00;
+const TAG_CONTEXT: u8 = 0x20;
+
+// Element types (lower 5 bits)
+const TYPE_SIGNED_2: u8 = 0x01;
+const TYPE_UNSIGNED_1: u8 = 0x04;
+const TYPE_UNSIGNED_2: u8 = 0x05;
+const TYPE_UNSIGNED_4: u8 = 0x06;
+const TYPE_UNSIGNED_8: u8 = 0x07;
+const TYPE_FALSE: u8 = 0x08;
+const TYPE_TRUE: u8 = 0x09;
+const TYPE_UTF8_1: u8 = 0x0C;
+const TYPE_OCTETS_1: u8 = 0x10;
+const TYPE_OCTETS_2: u8 = 0x11;
+const TYPE_NULL: u8 = 0x14;
+const TYPE_STRUCT: u8 = 0x15;
+const TYPE_ARRAY: u8 = 0x16;
+const TYPE_LIST: u8 = 0x17;
+const TYPE_END_OF_CONTAINER: u8 = 0x18;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlvError {
+    BufferFull,
+    StringTooLong,
+    /// The input ends inside an element.
+    Truncated,
+    /// A tag control or element type this reader does not decode.
+    Unsupported(u8),
+    InvalidUtf8,
+}
+
+/// Element tag: anonymous or a context-specific tag number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tag {
+    Anonymous,
+    Context(u8),
+}
+
+/// A decoded element value; containers are read as their start and end markers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlvValue<'a> {
+    Bool(bool),
+    Null,
+    Unsigned(u64),
+    Signed(i64),
+    Utf8(&'a str),
+    Octets(&'a [u8]),
+    StructStart,
+    ArrayStart,
+    ListStart,
+    EndOfContainer,
+}
+
+/// Writes TLV elements and tracks how many of the bytes are raw value data,
+/// so the framing overhead can be reported.
+pub struct TlvWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    raw_bytes: usize,
+}
+
+impl<'a> TlvWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0, raw_bytes: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn raw_bytes(&self) -> usize {
+        self.raw_bytes
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    pub fn start_struct(&mut self, tag: Tag) -> Result<(), TlvError> {
+        self.control(tag, TYPE_STRUCT)
+    }
+
+    pub fn start_array(&mut self, tag: Tag) -> Result<(), TlvError> {
+        self.control(tag, TYPE_ARRAY)
+    }
+
+    pub fn start_list(&mut self, tag: Tag) -> Result<(), TlvError> {
+        self.control(tag, TYPE_LIST)
+    }
+
+    pub fn end_container(&mut self) -> Result<(), TlvError> {
+        self.push(&[TYPE_END_OF_CONTAINER])
+    }
+
+    pub fn bool(&mut self, tag: Tag, value: bool) -> Result<(), TlvError> {
+        // Booleans live entirely in the control octet; count it as the value
+        self.control(tag, if value { TYPE_TRUE } else { TYPE_FALSE })?;
+        self.raw_bytes += 1;
+        Ok(())
+    }
+
+    pub fn null(&mut self, tag: Tag) -> Result<(), TlvError> {
+        self.control(tag, TYPE_NULL)
+    }
+
+    pub fn u8(&mut self, tag: Tag, value: u8) -> Result<(), TlvError> {
+        self.control(tag, TYPE_UNSIGNED_1)?;
+        self.value(&[value])
+    }
+
+    pub fn u16(&mut self, tag: Tag, value: u16) -> Result<(), TlvError> {
+        self.control(tag, TYPE_UNSIGNED_2)?;
+        self.value(&value.to_le_bytes())
+    }
+
+    pub fn u32(&mut self, tag: Tag, value: u32) -> Result<(), TlvError> {
+        self.control(tag, TYPE_UNSIGNED_4)?;
+        self.value(&value.to_le_bytes())
+    }
+
+    pub fn u64(&mut self, tag: Tag, value: u64) -> Result<(), TlvError> {
+        self.control(tag, TYPE_UNSIGNED_8)?;
+        self.value(&value.to_le_bytes())
+    }
+
+    pub fn i16(&mut self, tag: Tag, value: i16) -> Result<(), TlvError> {
+        self.control(tag, TYPE_SIGNED_2)?;
+        self.value(&value.to_le_bytes())
+    }
+
+    pub fn utf8(&mut self, tag: Tag, value: &str) -> Result<(), TlvError> {
+        let len = u8::try_from(value.len()).map_err(|_| TlvError::StringTooLong)?;
+        self.control(tag, TYPE_UTF8_1)?;
+        self.push(&[len])?;
+        self.value(value.as_bytes())
+    }
+
+    pub fn octets(&mut self, tag: Tag, value: &[u8]) -> Result<(), TlvError> {
+        match u8::try_from(value.len()) {
+            Ok(len) => {
+                self.control(tag, TYPE_OCTETS_1)?;
+                self.push(&[len])?;
+            }
+            Err(_) => {
+                let len = u16::try_from(value.len()).map_err(|_| TlvError::StringTooLong)?;
+                self.control(tag, TYPE_OCTETS_2)?;
+                self.push(&len.to_le_bytes())?;
+            }
+        }
+        self.value(value)
+    }
+
+    fn control(&mut self, tag: Tag, element_type: u8) -> Result<(), TlvError> {
+        match tag {
+            Tag::Anonymous => self.push(&[TAG_ANONYMOUS | element_type]),
+            Tag::Context(number) => self.push(&[TAG_CONTEXT | element_type, number]),
+        }
+    }
+
+    fn value(&mut self, bytes: &[u8]) -> Result<(), TlvError> {
+        self.push(bytes)?;
+        self.raw_bytes += bytes.len();
+        Ok(())
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> Result<(), TlvError> {
+        let end = self.len + bytes.len();
+        if end > self.buf.len() {
+            return Err(TlvError::BufferFull);
+        }
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Reads TLV elements one at a time from a byte slice.
+pub struct TlvReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> TlvReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// The next element, or `None` at the end of the input.
+    pub fn next_element(&mut self) -> Result<Option<(Tag, TlvValue<'a>)>, TlvError> {
+        if self.pos == self.buf.len() {
+            return Ok(None);
+        }
+        let control = self.take(1)?[0];
+        let element_type = control & 0x1F;
+        if element_type == TYPE_END_OF_CONTAINER {
+            return match control {
+                TYPE_END_OF_CONTAINER => Ok(Some((Tag::Anonymous, TlvValue::EndOfContainer))),
+                other => Err(TlvError::Unsupported(other)),
+            };
+        }
+        let tag = match control & 0xE0 {
+            TAG_ANONYMOUS => Tag::Anonymous,
+            TAG_CONTEXT => Tag::Context(self.take(1)?[0]),
+            _ => return Err(TlvError::Unsupported(control)),
+        };
+        let value = match element_type {
+            TYPE_SIGNED_2 => TlvValue::Signed(i16::from_le_bytes(self.array()?).into()),
+            TYPE_UNSIGNED_1 => TlvValue::Unsigned(self.take(1)?[0].into()),
+            TYPE_UNSIGNED_2 => TlvValue::Unsigned(u16::from_le_bytes(self.array()?).into()),
+            TYPE_UNSIGNED_4 => TlvValue::Unsigned(u32::from_le_bytes(self.array()?).into()),
+            TYPE_UNSIGNED_8 => TlvValue::Unsigned(u64::from_le_bytes(self.array()?)),
+            TYPE_FALSE => TlvValue::Bool(false),
+            TYPE_TRUE => TlvValue::Bool(true),
+            TYPE_UTF8_1 => {
+                let len = self.take(1)?[0].into();
+                TlvValue::Utf8(core::str::from_utf8(self.take(len)?).map_err(|_| TlvError::InvalidUtf8)?)
+            }
+            TYPE_OCTETS_1 => {
+                let len = self.take(1)?[0].into();
+                TlvValue::Octets(self.take(len)?)
+            }
+            TYPE_OCTETS_2 => {
+                let len = u16::from_le_bytes(self.array()?).into();
+                TlvValue::Octets(self.take(len)?)
+            }
+            TYPE_NULL => TlvValue::Null,
+            TYPE_STRUCT => TlvValue::StructStart,
+            TYPE_ARRAY => TlvValue::ArrayStart,
+            TYPE_LIST => TlvValue::ListStart,
+            _ => return Err(TlvError::Unsupported(control)),
+        };
+        Ok(Some((tag, value)))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], TlvError> {
+        let bytes = self.buf.get(self.pos..self.pos + len).ok_or(TlvError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], TlvError> {
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(self.take(N)?);
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_all(buf: &mut [u8]) -> Result<usize, TlvError> {
+        let mut writer = TlvWriter::new(buf);
+        writer.start_struct(Tag::Anonymous)?;
+        writer.bool(Tag::Context(0), true)?;
+        writer.null(Tag::Context(1))?;
+        writer.u8(Tag::Context(2), 0xAB)?;
+        writer.u16(Tag::Context(3), 0xBEEF)?;
+        writer.u32(Tag::Context(4), 0xDEAD_BEEF)?;
+        writer.u64(Tag::Context(5), u64::MAX)?;
+        writer.i16(Tag::Context(6), -2)?;
+        writer.utf8(Tag::Context(7), "on/off")?;
+        writer.octets(Tag::Context(8), &[1, 2, 3])?;
+        writer.octets(Tag::Context(9), &[7; 300])?;
+        writer.start_array(Tag::Context(10))?;
+        writer.bool(Tag::Anonymous, false)?;
+        writer.end_container()?;
+        writer.start_list(Tag::Context(11))?;
+        writer.end_container()?;
+        writer.end_container()?;
+        Ok(writer.len())
+    }
+
+    fn decode_all(bytes: &[u8]) -> Result<usize, TlvError> {
+        let mut reader = TlvReader::new(bytes);
+        let mut elements = 0;
+        while reader.next_element()?.is_some() {
+            elements += 1;
+        }
+        Ok(elements)
+    }
+
+    #[test]
+    fn written_elements_read_back() {
+        let mut buf = [0u8; 512];
+        let len = encode_all(&mut buf).unwrap();
+        let mut reader = TlvReader::new(&buf[..len]);
+        let mut next = || reader.next_element().unwrap().unwrap();
+
+        assert_eq!(next(), (Tag::Anonymous, TlvValue::StructStart));
+        assert_eq!(next(), (Tag::Context(0), TlvValue::Bool(true)));
+        assert_eq!(next(), (Tag::Context(1), TlvValue::Null));
+        assert_eq!(next(), (Tag::Context(2), TlvValue::Unsigned(0xAB)));
+        assert_eq!(next(), (Tag::Context(3), TlvValue::Unsigned(0xBEEF)));
+        assert_eq!(next(), (Tag::Context(4), TlvValue::Unsigned(0xDEAD_BEEF)));
+        assert_eq!(next(), (Tag::Context(5), TlvValue::Unsigned(u64::MAX)));
+        assert_eq!(next(), (Tag::Context(6), TlvValue::Signed(-2)));
+        assert_eq!(next(), (Tag::Context(7), TlvValue::Utf8("on/off")));
+        assert_eq!(next(), (Tag::Context(8), TlvValue::Octets(&[1, 2, 3])));
+        assert_eq!(next(), (Tag::Context(9), TlvValue::Octets(&[7; 300])));
+        assert_eq!(next(), (Tag::Context(10), TlvValue::ArrayStart));
+        assert_eq!(next(), (Tag::Anonymous, TlvValue::Bool(false)));
+        assert_eq!(next(), (Tag::Anonymous, TlvValue::EndOfContainer));
+        assert_eq!(next(), (Tag::Context(11), TlvValue::ListStart));
+        assert_eq!(next(), (Tag::Anonymous, TlvValue::EndOfContainer));
+        assert_eq!(next(), (Tag::Anonymous, TlvValue::EndOfContainer));
+        assert_eq!(reader.next_element(), Ok(None));
+    }
+
+    #[test]
+    fn truncated_and_unknown_input_is_an_error() {
+        let mut buf = [0u8; 512];
+        let len = encode_all(&mut buf).unwrap();
+        assert_eq!(decode_all(&buf[..len]), Ok(17));
+
+        // Every cut inside an element fails; cuts between elements just end the input early
+        let boundaries = {
+            let mut reader = TlvReader::new(&buf[..len]);
+            let mut ends = [0usize; 18];
+            for end in ends.iter_mut().skip(1) {
+                reader.next_element().unwrap();
+                *end = reader.pos;
+            }
+            ends
+        };
+        for cut in 0..len {
+            let decoded = decode_all(&buf[..cut]);
+            if boundaries.contains(&cut) {
+                assert!(decoded.is_ok(), "cut at {}", cut);
+            } else {
+                assert_eq!(decoded, Err(TlvError::Truncated), "cut at {}", cut);
+            }
+        }
+
+        assert_eq!(decode_all(&[0x2C, 0x01, 0x02, 0xFF, 0xFE]), Err(TlvError::InvalidUtf8));
+        assert_eq!(decode_all(&[0x0A]), Err(TlvError::Unsupported(0x0A)));
+        assert_eq!(decode_all(&[0xC4, 0x01]), Err(TlvError::Unsupported(0xC4)));
+        assert_eq!(decode_all(&[0x11, 0xFF, 0xFF]), Err(TlvError::Truncated));
+    }
+
+    #[test]
+    fn writing_past_the_buffer_is_an_error() {
+        let mut full = [0u8; 512];
+        let len = encode_all(&mut full).unwrap();
+        for size in 0..len {
+            let mut buf = [0u8; 512];
+            assert_eq!(encode_all(&mut buf[..size]), Err(TlvError::BufferFull), "buffer of {}", size);
+        }
+        let long = core::str::from_utf8(&[b'x'; 256]).unwrap();
+        assert_eq!(TlvWriter::new(&mut full).utf8(Tag::Anonymous, long), Err(TlvError::StringTooLong));
+    }
+}
@@ -0,0 +1,349 @@
+// matter-project/src/access_control.rs
+/*!
+Access control overhead - per-request ACL authorization cost as the ACL grows
+*/
+
+use anyhow::{anyhow, Result};
+use metrics_core::tlv::{Tag, TlvWriter};
+use serde::{Deserialize, Serialize};
+use std::hint::black_box;
+use std::time::Instant;
+
+use crate::builder::analyzer_builder;
+
+const FABRIC_INDEX: u8 = 1;
+const ADMIN_NODE_ID: u64 = 0x0001_0000_0000_0001;
+const FIRST_SUBJECT_NODE_ID: u64 = 0x0002_0000_0000_0000;
+const UNKNOWN_NODE_ID: u64 = 0x0FFF_0000_0000_0000;
+
+// Clusters on the virtual device's endpoint 1 (endpoint 0 carries the utility clusters)
+const ENDPOINT_CLUSTERS: &[(u16, u32)] = &[
+    (0, 0x001D), // Descriptor
+    (0, 0x001F), // Access Control
+    (0, 0x0028), // Basic Information
+    (1, 0x0006), // On/Off
+    (1, 0x0008), // Level Control
+    (1, 0x0300), // Color Control
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Privilege {
+    View = 1,
+    ProxyView = 2,
+    Operate = 3,
+    Manage = 4,
+    Administer = 5,
+}
+
+impl Privilege {
+    /// Whether an entry granting `self` allows a request needing `required`.
+    /// Administer also covers ProxyView; otherwise privileges are ordered.
+    fn grants(self, required: Privilege) -> bool {
+        match (self, required) {
+            (Privilege::Administer, _) => true,
+            (_, Privilege::ProxyView) => false,
+            (Privilege::ProxyView, _) => required == Privilege::View,
+            _ => self >= required,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMode {
+    Case = 2,
+    Group = 3,
+}
+
+/// Null fields are wildcards.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AclTarget {
+    pub cluster: Option<u32>,
+    pub endpoint: Option<u16>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AccessControlEntry {
+    pub fabric_index: u8,
+    pub privilege: Privilege,
+    pub auth_mode: AuthMode,
+    /// Empty means any subject on the fabric.
+    pub subjects: Vec<u64>,
+    /// Empty means every endpoint and cluster.
+    pub targets: Vec<AclTarget>,
+}
+
+/// One incoming interaction to authorize.
+#[derive(Debug, Clone, Copy)]
+pub struct AccessRequest {
+    pub fabric_index: u8,
+    pub auth_mode: AuthMode,
+    pub subject: u64,
+    pub endpoint: u16,
+    pub cluster: u32,
+    pub privilege: Privilege,
+}
+
+/// Minimal access control cluster state of a virtual device.
+pub struct AccessControlList {
+    entries: Vec<AccessControlEntry>,
+}
+
+impl AccessControlList {
+    /// Commissioner admin entry followed by `entries - 1` operational grants,
+    /// alternating cluster-, endpoint- and device-wide targets.
+    pub fn generate(entries: usize) -> Self {
+        let mut acl = vec![AccessControlEntry {
+            fabric_index: FABRIC_INDEX,
+            privilege: Privilege::Administer,
+            auth_mode: AuthMode::Case,
+            subjects: vec![ADMIN_NODE_ID],
+            targets: Vec::new(),
+        }];
+
+        for index in 1..entries {
+            let (endpoint, cluster) = ENDPOINT_CLUSTERS[index % ENDPOINT_CLUSTERS.len()];
+            let privilege = [Privilege::View, Privilege::Operate, Privilege::Manage][index % 3];
+            let target = match index % 3 {
+                0 => AclTarget { cluster: Some(cluster), endpoint: Some(endpoint) },
+                1 => AclTarget { cluster: None, endpoint: Some(endpoint) },
+                _ => AclTarget { cluster: Some(cluster), endpoint: None },
+            };
+            acl.push(AccessControlEntry {
+                fabric_index: FABRIC_INDEX,
+                privilege,
+                auth_mode: AuthMode::Case,
+                subjects: vec![FIRST_SUBJECT_NODE_ID + index as u64],
+                targets: vec![target],
+            });
+        }
+
+        Self { entries: acl }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Access check as the Matter spec defines it: any entry on the request's
+    /// fabric and auth mode that grants the privilege, names the subject and
+    /// covers the target allows the request.
+    pub fn check(&self, request: &AccessRequest) -> bool {
+        self.entries.iter().any(|entry| {
+            entry.fabric_index == request.fabric_index
+                && entry.auth_mode == request.auth_mode
+                && entry.privilege.grants(request.privilege)
+                && (entry.subjects.is_empty() || entry.subjects.contains(&request.subject))
+                && (entry.targets.is_empty()
+                    || entry.targets.iter().any(|target| {
+                        target.endpoint.is_none_or(|endpoint| endpoint == request.endpoint)
+                            && target.cluster.is_none_or(|cluster| cluster == request.cluster)
+                    }))
+        })
+    }
+
+    /// Size of the ACL attribute as the device stores and reports it (Matter TLV).
+    pub fn encoded_size(&self) -> Result<usize> {
+        let mut buffer = vec![0u8; 64 + self.entries.len() * 64];
+        let mut writer = TlvWriter::new(&mut buffer);
+        encode_entries(&mut writer, &self.entries).map_err(|e| anyhow!("ACL encoding failed: {:?}", e))?;
+        Ok(writer.len())
+    }
+}
+
+fn encode_entries(writer: &mut TlvWriter, entries: &[AccessControlEntry]) -> Result<(), metrics_core::tlv::TlvError> {
+    writer.start_array(Tag::Anonymous)?;
+    for entry in entries {
+        writer.start_struct(Tag::Anonymous)?;
+        writer.u8(Tag::Context(1), entry.privilege as u8)?;
+        writer.u8(Tag::Context(2), entry.auth_mode as u8)?;
+        writer.start_array(Tag::Context(3))?;
+        for subject in &entry.subjects {
+            writer.u64(Tag::Anonymous, *subject)?;
+        }
+        writer.end_container()?;
+        writer.start_array(Tag::Context(4))?;
+        for target in &entry.targets {
+            writer.start_struct(Tag::Anonymous)?;
+            if let Some(cluster) = target.cluster {
+                writer.u32(Tag::Context(0), cluster)?;
+            }
+            if let Some(endpoint) = target.endpoint {
+                writer.u16(Tag::Context(1), endpoint)?;
+            }
+            writer.end_container()?;
+        }
+        writer.end_container()?;
+        writer.u8(Tag::Context(254), entry.fabric_index)?;
+        writer.end_container()?;
+    }
+    writer.end_container()
+}
+
+#[derive(Debug, Clone)]
+pub struct AclConfig {
+    pub entry_counts: Vec<usize>,
+    pub requests: u32,
+}
+
+impl Default for AclConfig {
+    fn default() -> Self {
+        Self {
+            entry_counts: vec![1, 4, 16, 64, 256],
+            requests: 20_000,
+        }
+    }
+}
+
+analyzer_builder!(AclOverheadAnalyzerBuilder => AclOverheadAnalyzer(AclConfig) {
+    entry_counts: Vec<usize>,
+    requests: u32,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AclOverheadMetrics {
+    pub requests_per_level: u32,
+    pub levels: Vec<AclLevel>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AclLevel {
+    pub entries: usize,
+    pub encoded_acl_bytes: usize,
+    /// Mixed requests from configured subjects (and some unknown ones).
+    pub mean_check_ns: f64,
+    /// Denied requests, which have to scan every entry.
+    pub denied_check_ns: f64,
+    pub allowed_ratio: f64,
+}
+
+pub struct AclOverheadAnalyzer {
+    config: AclConfig,
+}
+
+impl AclOverheadAnalyzer {
+    pub fn new(config: AclConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_acl_overhead(&mut self) -> Result<AclOverheadMetrics> {
+        println!("\n🛂 Analyzing Access Control Overhead");
+        println!("------------------------------------");
+
+        let mut levels = Vec::new();
+        for &entries in &self.config.entry_counts {
+            let acl = AccessControlList::generate(entries.max(1));
+            let requests = self.requests(acl.len());
+
+            let start = Instant::now();
+            let allowed = requests.iter().filter(|request| acl.check(black_box(request))).count();
+            let mean_check_ns = start.elapsed().as_nanos() as f64 / requests.len() as f64;
+
+            let denied = AccessRequest { subject: UNKNOWN_NODE_ID, ..requests[0] };
+            let start = Instant::now();
+            for _ in 0..self.config.requests {
+                black_box(acl.check(black_box(&denied)));
+            }
+            let denied_check_ns = start.elapsed().as_nanos() as f64 / self.config.requests.max(1) as f64;
+
+            let level = AclLevel {
+                entries: acl.len(),
+                encoded_acl_bytes: acl.encoded_size()?,
+                mean_check_ns,
+                denied_check_ns,
+                allowed_ratio: allowed as f64 / requests.len() as f64,
+            };
+            println!("✅ {} entries ({}B): {:.0}ns mean, {:.0}ns denied, {:.0}% allowed",
+                     level.entries, level.encoded_acl_bytes, level.mean_check_ns,
+                     level.denied_check_ns, level.allowed_ratio * 100.0);
+            levels.push(level);
+        }
+
+        Ok(AclOverheadMetrics {
+            requests_per_level: self.config.requests,
+            levels,
+        })
+    }
+
+    /// Pseudo-random read/invoke/write requests; about one in ten comes from
+    /// a subject the ACL does not know.
+    fn requests(&self, entries: usize) -> Vec<AccessRequest> {
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        (0..self.config.requests.max(1))
+            .map(|_| {
+                let pick = next() as usize;
+                let (endpoint, cluster) = ENDPOINT_CLUSTERS[pick % ENDPOINT_CLUSTERS.len()];
+                let subject = match pick % 10 {
+                    0 => UNKNOWN_NODE_ID,
+                    1 => ADMIN_NODE_ID,
+                    _ => FIRST_SUBJECT_NODE_ID + ((pick >> 8) % entries) as u64,
+                };
+                AccessRequest {
+                    fabric_index: FABRIC_INDEX,
+                    auth_mode: AuthMode::Case,
+                    subject,
+                    endpoint,
+                    cluster,
+                    privilege: [Privilege::View, Privilege::Operate, Privilege::Manage][(pick >> 4) % 3],
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(subject: u64, endpoint: u16, cluster: u32, privilege: Privilege) -> AccessRequest {
+        AccessRequest { fabric_index: FABRIC_INDEX, auth_mode: AuthMode::Case, subject, endpoint, cluster, privilege }
+    }
+
+    #[test]
+    fn privileges_grant_lower_ones_except_proxy_view() {
+        assert!(Privilege::Administer.grants(Privilege::ProxyView));
+        assert!(Privilege::Manage.grants(Privilege::Operate));
+        assert!(Privilege::Manage.grants(Privilege::View));
+        assert!(!Privilege::Manage.grants(Privilege::ProxyView));
+        assert!(Privilege::ProxyView.grants(Privilege::View));
+        assert!(!Privilege::ProxyView.grants(Privilege::Operate));
+        assert!(!Privilege::View.grants(Privilege::Operate));
+    }
+
+    #[test]
+    fn entries_match_subject_target_and_fabric() {
+        // Entry 1 grants Operate on endpoint 0 (any cluster), entry 2 Manage on Basic Information (any endpoint)
+        let acl = AccessControlList::generate(3);
+        let (first, second) = (FIRST_SUBJECT_NODE_ID + 1, FIRST_SUBJECT_NODE_ID + 2);
+
+        assert!(acl.check(&request(ADMIN_NODE_ID, 1, 0x0300, Privilege::Administer)));
+        assert!(acl.check(&request(first, 0, 0x0028, Privilege::Operate)));
+        assert!(!acl.check(&request(first, 1, 0x0006, Privilege::View)), "other endpoint");
+        assert!(!acl.check(&request(first, 0, 0x0028, Privilege::Manage)), "privilege too high");
+        assert!(acl.check(&request(second, 3, 0x0028, Privilege::Manage)));
+        assert!(!acl.check(&request(second, 1, 0x0006, Privilege::View)), "other cluster");
+        assert!(!acl.check(&request(UNKNOWN_NODE_ID, 1, 0x0006, Privilege::View)));
+        assert!(!acl.check(&AccessRequest { fabric_index: 2, ..request(ADMIN_NODE_ID, 1, 0x0006, Privilege::View) }));
+    }
+
+    #[test]
+    fn encoded_size_grows_with_each_entry() {
+        // Array, then the admin entry: privilege, auth mode, one subject, no targets, fabric index
+        let admin = 1 + (1 + 3 + 3 + (2 + 9 + 1) + (2 + 1) + 3 + 1) + 1;
+        assert_eq!(AccessControlList::generate(1).encoded_size().unwrap(), admin);
+
+        let sizes: Vec<usize> = [1, 4, 16].iter().map(|n| AccessControlList::generate(*n).encoded_size().unwrap()).collect();
+        assert!(sizes.windows(2).all(|pair| pair[1] > pair[0]));
+        assert_eq!(AccessControlList::generate(16).len(), 16);
+    }
+}
@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use crate::interoperability::{InteroperabilityAnalyzer, InteroperabilityConfig};
+use crate::scenarios::DeviceScenario;
 use std::time::Instant;
 use tokio::time::{sleep, Duration};
 
@@ -13,11 +15,13 @@ pub struct ApplicationMetrics {
     pub application_overhead_bytes: u32,
 }
 
-pub struct ApplicationAnalyzer;
+pub struct ApplicationAnalyzer {
+    scenarios: Vec<DeviceScenario>,
+}
 
 impl ApplicationAnalyzer {
-    pub fn new() -> Self {
-        Self
+    pub fn new(scenarios: Vec<DeviceScenario>) -> Self {
+        Self { scenarios }
     }
     
     pub async fn analyze_layer_7(&mut self) -> anyhow::Result<ApplicationMetrics> {
@@ -31,18 +35,18 @@ impl ApplicationAnalyzer {
         
         let discovery_time = start.elapsed().as_millis() as f64;
         
-        let clusters = vec![
-            "Basic_Information_0x0028".to_string(),
-            "Identify_0x0003".to_string(),
-            "Groups_0x0004".to_string(),
-            "OnOff_0x0006".to_string(),
-            "Level_Control_0x0008".to_string(),
-            "Color_Control_0x0300".to_string(),
-            "Temperature_Measurement_0x0402".to_string(),
-            "Pressure_Measurement_0x0403".to_string(),
-            "Illuminance_Measurement_0x0400".to_string(),
-            "Door_Lock_0x0101".to_string(),
-        ];
+        // Union of the clusters the selected device scenarios implement
+        let mut clusters: Vec<String> = Vec::new();
+        for scenario in &self.scenarios {
+            for cluster in scenario.clusters() {
+                if !clusters.contains(&cluster) {
+                    clusters.push(cluster);
+                }
+            }
+        }
+        
+        let interoperability = InteroperabilityAnalyzer::new(InteroperabilityConfig::default())
+            .analyze_interoperability()?;
         
         let metrics = ApplicationMetrics {
             application_protocol: "Matter".to_string(),
@@ -50,7 +54,7 @@ impl ApplicationAnalyzer {
             supported_clusters: clusters.clone(),
             discovery_time_ms: discovery_time,
             clusters_discovered: clusters.len() as u32,
-            interoperability_score: 0.95,
+            interoperability_score: interoperability.score_for("Matter").unwrap_or(0.0),
             application_overhead_bytes: 267,
         };
         
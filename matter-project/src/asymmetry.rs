@@ -0,0 +1,375 @@
+// matter-project/src/asymmetry.rs
+/*!
+Asymmetric links - how acknowledgement patterns fare when the uplink is the narrow side
+
+A device reports a run of readings to a server through a [`ShapedRelay`],
+once over a symmetric reference link and once over an asymmetric one with
+the same round trip but a fraction of the uplink rate. Fire-and-forget
+patterns (MQTT QoS 0, CoAP NON) only pay for their own bytes on the
+uplink. Acknowledged ones keep one message in flight and wait for the
+downlink in between, and MQTT QoS 2 crosses the narrow uplink twice per
+message (PUBLISH, then PUBREL). The slowdown is the asymmetric run's
+duration over the reference run's.
+
+The relay shapes application messages only; see [`crate::netsim`] for
+what that leaves out.
+*/
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::time::{timeout, Instant};
+
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{
+    coap_message, mqtt_packet, mqtt_publish, uri_path, Carrier, COAP_ACK, COAP_CHANGED, COAP_CON, COAP_NON, COAP_POST,
+    MQTT_PUBACK,
+};
+use crate::netsim::{AsymmetricLink, LinkShape, LinkStats, ShapedRelay};
+use crate::replay::{MQTT_PUBCOMP, MQTT_PUBLISH_QOS2, MQTT_PUBREC, MQTT_PUBREL};
+
+const MQTT_REPORT_TOPIC: &str = "sensors/dev1/report";
+/// Generous for any sensible link; a run that takes longer has stalled.
+const RUN_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Clone)]
+pub struct AsymmetryConfig {
+    pub reference: AsymmetricLink,
+    pub asymmetric: AsymmetricLink,
+    /// Readings reported per protocol and link.
+    pub messages: usize,
+    pub payload_bytes: usize,
+}
+
+impl Default for AsymmetryConfig {
+    fn default() -> Self {
+        // Both round trips are 40ms; the asymmetric uplink has a tenth of the rate
+        Self {
+            reference: AsymmetricLink::symmetric(LinkShape::new(20.0, 250)),
+            asymmetric: AsymmetricLink { uplink: LinkShape::new(30.0, 25), downlink: LinkShape::new(10.0, 250) },
+            messages: 20,
+            payload_bytes: 64,
+        }
+    }
+}
+
+analyzer_builder!(AsymmetryAnalyzerBuilder => AsymmetryAnalyzer(AsymmetryConfig) {
+    reference: AsymmetricLink,
+    asymmetric: AsymmetricLink,
+    messages: usize,
+    payload_bytes: usize,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AsymmetryMetrics {
+    pub messages: usize,
+    pub payload_bytes: usize,
+    pub reference: AsymmetricLink,
+    pub asymmetric: AsymmetricLink,
+    pub protocols: Vec<AsymmetryProtocolResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AsymmetryProtocolResult {
+    pub protocol: String,
+    pub transport: String,
+    pub uplink_messages_per_reading: usize,
+    pub downlink_messages_per_reading: usize,
+    pub reference: AsymmetryRun,
+    pub asymmetric: AsymmetryRun,
+    /// Asymmetric duration over reference duration.
+    pub slowdown: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AsymmetryRun {
+    /// First send to the last message of the last reading arriving.
+    pub duration_ms: f64,
+    pub readings_per_s: f64,
+    pub ms_per_reading: f64,
+    pub link: LinkStats,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AckPattern {
+    MqttQos0,
+    MqttQos1,
+    MqttQos2,
+    CoapNon,
+    CoapCon,
+}
+
+const PATTERNS: [AckPattern; 5] =
+    [AckPattern::MqttQos0, AckPattern::MqttQos1, AckPattern::MqttQos2, AckPattern::CoapNon, AckPattern::CoapCon];
+
+/// One message of an exchange, in the direction it travels.
+#[derive(Debug, Clone)]
+enum Step {
+    Up(Vec<u8>),
+    Down(Vec<u8>),
+}
+
+impl AckPattern {
+    fn name(self) -> &'static str {
+        match self {
+            AckPattern::MqttQos0 => "MQTT QoS 0",
+            AckPattern::MqttQos1 => "MQTT QoS 1",
+            AckPattern::MqttQos2 => "MQTT QoS 2",
+            AckPattern::CoapNon => "CoAP NON",
+            AckPattern::CoapCon => "CoAP CON",
+        }
+    }
+
+    fn carrier(self) -> Carrier {
+        match self {
+            AckPattern::CoapNon | AckPattern::CoapCon => Carrier::Udp,
+            _ => Carrier::Tcp,
+        }
+    }
+
+    /// The messages of one reading, in order.
+    fn exchange(self, reading: usize, payload: &[u8]) -> Vec<Step> {
+        let id = (reading % 0xFFFF) as u16 + 1;
+        let id_bytes = id.to_be_bytes();
+        match self {
+            AckPattern::MqttQos0 => vec![Step::Up(mqtt_publish(MQTT_REPORT_TOPIC, None, payload))],
+            AckPattern::MqttQos1 => vec![
+                Step::Up(mqtt_publish(MQTT_REPORT_TOPIC, Some(id), payload)),
+                Step::Down(mqtt_packet(MQTT_PUBACK, &id_bytes)),
+            ],
+            AckPattern::MqttQos2 => {
+                let mut publish = mqtt_publish(MQTT_REPORT_TOPIC, Some(id), payload);
+                publish[0] = MQTT_PUBLISH_QOS2;
+                vec![
+                    Step::Up(publish),
+                    Step::Down(mqtt_packet(MQTT_PUBREC, &id_bytes)),
+                    Step::Up(mqtt_packet(MQTT_PUBREL, &id_bytes)),
+                    Step::Down(mqtt_packet(MQTT_PUBCOMP, &id_bytes)),
+                ]
+            }
+            AckPattern::CoapNon => vec![Step::Up(coap_message(COAP_NON, COAP_POST, id, &uri_path(&["report"]), payload))],
+            AckPattern::CoapCon => vec![
+                Step::Up(coap_message(COAP_CON, COAP_POST, id, &uri_path(&["report"]), payload)),
+                Step::Down(coap_message(COAP_ACK, COAP_CHANGED, id, &[], &[])),
+            ],
+        }
+    }
+}
+
+pub struct AsymmetryAnalyzer {
+    config: AsymmetryConfig,
+}
+
+impl AsymmetryAnalyzer {
+    pub fn new(config: AsymmetryConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_asymmetry(&mut self) -> Result<AsymmetryMetrics> {
+        println!("\n📡 Analyzing Asymmetric Links ({} readings per protocol)", self.config.messages);
+        println!("------------------------------------------------");
+        let describe = |link: &AsymmetricLink| {
+            let shape = |s: &LinkShape| match s.rate_kbit {
+                Some(rate) => format!("{} kbit/s, {}ms", rate, s.delay_ms),
+                None => format!("unlimited, {}ms", s.delay_ms),
+            };
+            format!("up {} / down {}", shape(&link.uplink), shape(&link.downlink))
+        };
+        println!("  Reference:  {}", describe(&self.config.reference));
+        println!("  Asymmetric: {}", describe(&self.config.asymmetric));
+
+        let payload = vec![0x5Au8; self.config.payload_bytes];
+        let mut protocols = Vec::new();
+        for pattern in PATTERNS {
+            let script: Vec<Vec<Step>> = (0..self.config.messages).map(|reading| pattern.exchange(reading, &payload)).collect();
+            let reference = run_pattern(pattern, &self.config.reference, &script).await?;
+            let asymmetric = run_pattern(pattern, &self.config.asymmetric, &script).await?;
+            let slowdown = if reference.duration_ms > 0.0 { asymmetric.duration_ms / reference.duration_ms } else { 0.0 };
+            println!(
+                "  {:<12} {:>8.1} ms/reading -> {:>8.1} ms/reading  ({:.2}x)",
+                pattern.name(),
+                reference.ms_per_reading,
+                asymmetric.ms_per_reading,
+                slowdown
+            );
+
+            let exchange = script.first().map(Vec::as_slice).unwrap_or_default();
+            protocols.push(AsymmetryProtocolResult {
+                protocol: pattern.name().to_string(),
+                transport: format!("{:?}", pattern.carrier()).to_uppercase(),
+                uplink_messages_per_reading: exchange.iter().filter(|step| matches!(step, Step::Up(_))).count(),
+                downlink_messages_per_reading: exchange.iter().filter(|step| matches!(step, Step::Down(_))).count(),
+                reference,
+                asymmetric,
+                slowdown,
+            });
+        }
+
+        Ok(AsymmetryMetrics {
+            messages: self.config.messages,
+            payload_bytes: self.config.payload_bytes,
+            reference: self.config.reference.clone(),
+            asymmetric: self.config.asymmetric.clone(),
+            protocols,
+        })
+    }
+}
+
+async fn run_pattern(pattern: AckPattern, link: &AsymmetricLink, script: &[Vec<Step>]) -> Result<AsymmetryRun> {
+    let carrier = pattern.carrier();
+    let (server_addr, server) = match carrier {
+        Carrier::Udp => {
+            let socket = UdpSocket::bind("127.0.0.1:0").await?;
+            let addr = socket.local_addr()?;
+            let script = script.to_vec();
+            (addr, tokio::spawn(async move { serve(Endpoint::Udp(socket, None), &script).await }))
+        }
+        Carrier::Tcp => {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            let script = script.to_vec();
+            (
+                addr,
+                tokio::spawn(async move {
+                    let (stream, _) = listener.accept().await?;
+                    stream.set_nodelay(true)?;
+                    serve(Endpoint::Tcp(stream), &script).await
+                }),
+            )
+        }
+    };
+    let relay = ShapedRelay::spawn(carrier, link.clone(), server_addr).await?;
+    let device = Endpoint::connect(carrier, relay.addr()).await?;
+
+    let start = Instant::now();
+    let (device_done, server_done) = timeout(RUN_TIMEOUT, async {
+        let device_done = drive(device, script).await?;
+        let server_done = server.await??;
+        anyhow::Ok((device_done, server_done))
+    })
+    .await
+    .map_err(|_| anyhow!("{}: no progress within {:?}", pattern.name(), RUN_TIMEOUT))??;
+
+    let duration = device_done.max(server_done) - start;
+    let duration_ms = duration.as_secs_f64() * 1000.0;
+    let readings = script.len().max(1) as f64;
+    Ok(AsymmetryRun {
+        duration_ms,
+        readings_per_s: if duration_ms > 0.0 { readings * 1000.0 / duration_ms } else { 0.0 },
+        ms_per_reading: duration_ms / readings,
+        link: relay.stats(),
+    })
+}
+
+/// Device side: sends its steps and waits for each downlink one; returns when the last step is done.
+async fn drive(mut endpoint: Endpoint, script: &[Vec<Step>]) -> Result<Instant> {
+    for step in script.iter().flatten() {
+        match step {
+            Step::Up(message) => endpoint.send(message).await?,
+            Step::Down(message) => endpoint.expect(message).await?,
+        }
+    }
+    Ok(Instant::now())
+}
+
+/// Server side: the mirror image of [`drive`].
+async fn serve(mut endpoint: Endpoint, script: &[Vec<Step>]) -> Result<Instant> {
+    for step in script.iter().flatten() {
+        match step {
+            Step::Up(message) => endpoint.expect(message).await?,
+            Step::Down(message) => endpoint.send(message).await?,
+        }
+    }
+    Ok(Instant::now())
+}
+
+enum Endpoint {
+    /// The peer is learnt from the first datagram when not connected.
+    Udp(UdpSocket, Option<SocketAddr>),
+    Tcp(TcpStream),
+}
+
+impl Endpoint {
+    async fn connect(carrier: Carrier, addr: SocketAddr) -> Result<Self> {
+        match carrier {
+            Carrier::Udp => {
+                let socket = UdpSocket::bind("127.0.0.1:0").await?;
+                Ok(Endpoint::Udp(socket, Some(addr)))
+            }
+            Carrier::Tcp => {
+                let stream = TcpStream::connect(addr).await?;
+                stream.set_nodelay(true)?;
+                Ok(Endpoint::Tcp(stream))
+            }
+        }
+    }
+
+    async fn send(&mut self, message: &[u8]) -> Result<()> {
+        match self {
+            Endpoint::Udp(socket, peer) => {
+                let peer = peer.ok_or_else(|| anyhow!("nothing received yet, so no peer to answer"))?;
+                socket.send_to(message, peer).await?;
+            }
+            Endpoint::Tcp(stream) => stream.write_all(message).await?,
+        }
+        Ok(())
+    }
+
+    /// Receives the next message and checks it is `expected`.
+    async fn expect(&mut self, expected: &[u8]) -> Result<()> {
+        let received = match self {
+            Endpoint::Udp(socket, peer) => {
+                let mut buffer = vec![0u8; 2048];
+                let (len, from) = socket.recv_from(&mut buffer).await?;
+                peer.get_or_insert(from);
+                buffer.truncate(len);
+                buffer
+            }
+            Endpoint::Tcp(stream) => {
+                // Frames are known in advance, so their length is too
+                let mut buffer = vec![0u8; expected.len()];
+                stream.read_exact(&mut buffer).await?;
+                buffer
+            }
+        };
+        if received != expected {
+            return Err(anyhow!("unexpected message {:02x?}", &received[..received.len().min(8)]));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_narrow_uplink_hurts_chatty_patterns_most() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let metrics = runtime
+            .block_on(
+                AsymmetryAnalyzer::builder()
+                    .reference(AsymmetricLink::symmetric(LinkShape::new(2.0, 1000)))
+                    .asymmetric(AsymmetricLink { uplink: LinkShape::new(2.0, 50), downlink: LinkShape::new(2.0, 1000) })
+                    .messages(4usize)
+                    .build()
+                    .analyze_asymmetry(),
+            )
+            .unwrap();
+        assert_eq!(metrics.protocols.len(), PATTERNS.len());
+        let protocol = |name: &str| metrics.protocols.iter().find(|p| p.protocol == name).unwrap();
+
+        let qos2 = protocol("MQTT QoS 2");
+        assert_eq!((qos2.uplink_messages_per_reading, qos2.downlink_messages_per_reading), (2, 2));
+        assert_eq!(qos2.asymmetric.link.uplink_packets, 8);
+        assert!(qos2.asymmetric.link.uplink_bytes > qos2.asymmetric.link.downlink_bytes);
+        for result in &metrics.protocols {
+            assert!(result.slowdown > 1.0, "{}: {}", result.protocol, result.slowdown);
+        }
+        // QoS 2 sends more over the narrow uplink than QoS 0 and waits for every acknowledgement
+        assert!(qos2.asymmetric.duration_ms > protocol("MQTT QoS 0").asymmetric.duration_ms);
+    }
+}
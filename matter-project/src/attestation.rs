@@ -0,0 +1,550 @@
+// matter-project/src/attestation.rs
+/*!
+Device attestation certificates - generated PAA → PAI → DAC chain, DER sizes and validation timing
+*/
+
+use anyhow::{anyhow, Result};
+use ring::rand::SecureRandom;
+use ring::signature::KeyPair;
+use ring::{digest, rand, signature};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::builder::analyzer_builder;
+
+// Test vendor / product as used by the Matter SDK test certificates
+const VENDOR_ID: &str = "FFF1";
+const PRODUCT_ID: &str = "8000";
+
+// Pre-encoded OID contents
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x02];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
+const OID_PRIME256V1: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+const OID_MATTER_VID: &[u8] = &[0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xA2, 0x7C, 0x02, 0x01];
+const OID_MATTER_PID: &[u8] = &[0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0xA2, 0x7C, 0x02, 0x02];
+const OID_BASIC_CONSTRAINTS: &[u8] = &[0x55, 0x1D, 0x13];
+const OID_KEY_USAGE: &[u8] = &[0x55, 0x1D, 0x0F];
+const OID_SUBJECT_KEY_ID: &[u8] = &[0x55, 0x1D, 0x0E];
+const OID_AUTHORITY_KEY_ID: &[u8] = &[0x55, 0x1D, 0x23];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttestationMetrics {
+    pub signature_algorithm: String,
+    pub paa_der_bytes: usize,
+    pub pai_der_bytes: usize,
+    pub dac_der_bytes: usize,
+    /// PAI + DAC, which the device sends during attestation (the PAA is looked up).
+    pub attestation_chain_bytes: usize,
+    pub validation_iterations: u32,
+    pub validation_time_us: f64,
+}
+
+/// A DER certificate and the key pair it certifies.
+pub struct IssuedCertificate {
+    pub der: Vec<u8>,
+    key_pair: signature::EcdsaKeyPair,
+}
+
+/// Product Attestation Authority → Product Attestation Intermediate → Device Attestation Certificate.
+pub struct AttestationChain {
+    pub paa: IssuedCertificate,
+    pub pai: IssuedCertificate,
+    pub dac: IssuedCertificate,
+}
+
+impl AttestationChain {
+    pub fn generate() -> Result<Self> {
+        let rng = rand::SystemRandom::new();
+        let paa_key = generate_key(&rng)?;
+        let pai_key = generate_key(&rng)?;
+        let dac_key = generate_key(&rng)?;
+
+        let paa_name = name(&[(OID_COMMON_NAME, "Matter Test PAA"), (OID_MATTER_VID, VENDOR_ID)]);
+        let pai_name = name(&[(OID_COMMON_NAME, "Matter Test PAI"), (OID_MATTER_VID, VENDOR_ID)]);
+        let dac_name = name(&[
+            (OID_COMMON_NAME, "Matter Test DAC"),
+            (OID_MATTER_VID, VENDOR_ID),
+            (OID_MATTER_PID, PRODUCT_ID),
+        ]);
+
+        let paa = issue(&rng, &paa_name, &paa_name, &paa_key, &paa_key, CertificateRole::Root)?;
+        let pai = issue(&rng, &pai_name, &paa_name, &pai_key, &paa_key, CertificateRole::Intermediate)?;
+        let dac = issue(&rng, &dac_name, &pai_name, &dac_key, &pai_key, CertificateRole::Device)?;
+
+        Ok(Self {
+            paa: IssuedCertificate { der: paa, key_pair: paa_key },
+            pai: IssuedCertificate { der: pai, key_pair: pai_key },
+            dac: IssuedCertificate { der: dac, key_pair: dac_key },
+        })
+    }
+
+    /// Validates DAC → PAI → PAA the way a commissioner does: names chain,
+    /// CA constraints and path length hold, vendor IDs match and every
+    /// signature verifies (the PAA against itself, as the trust anchor).
+    pub fn validate(&self) -> Result<()> {
+        let paa = ParsedCertificate::parse(&self.paa.der)?;
+        let pai = ParsedCertificate::parse(&self.pai.der)?;
+        let dac = ParsedCertificate::parse(&self.dac.der)?;
+
+        if paa.issuer != paa.subject || !paa.is_ca {
+            return Err(anyhow!("PAA is not a self-signed CA"));
+        }
+        if pai.issuer != paa.subject || !pai.is_ca || pai.path_len != Some(0) {
+            return Err(anyhow!("PAI is not an intermediate CA issued by the PAA"));
+        }
+        if dac.issuer != pai.subject || dac.is_ca {
+            return Err(anyhow!("DAC is not an end-entity certificate issued by the PAI"));
+        }
+        if find_attribute(dac.subject, OID_MATTER_VID) != find_attribute(pai.subject, OID_MATTER_VID) {
+            return Err(anyhow!("DAC and PAI vendor IDs differ"));
+        }
+
+        paa.verify_signed_by(paa.public_key)?;
+        pai.verify_signed_by(paa.public_key)?;
+        dac.verify_signed_by(pai.public_key)?;
+        Ok(())
+    }
+
+    /// Signs an attestation challenge with the DAC key (what the device does per commissioning).
+    pub fn sign_challenge(&self, challenge: &[u8]) -> Result<Vec<u8>> {
+        let rng = rand::SystemRandom::new();
+        Ok(self
+            .dac
+            .key_pair
+            .sign(&rng, challenge)
+            .map_err(|_| anyhow!("attestation signing failed"))?
+            .as_ref()
+            .to_vec())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AttestationConfig {
+    /// Chain validations timed and averaged.
+    pub validation_iterations: u32,
+}
+
+impl Default for AttestationConfig {
+    fn default() -> Self {
+        Self { validation_iterations: 200 }
+    }
+}
+
+analyzer_builder!(AttestationAnalyzerBuilder => AttestationAnalyzer(AttestationConfig) {
+    validation_iterations: u32,
+});
+
+pub struct AttestationAnalyzer {
+    config: AttestationConfig,
+}
+
+impl Default for AttestationAnalyzer {
+    fn default() -> Self {
+        Self::new(AttestationConfig::default())
+    }
+}
+
+impl AttestationAnalyzer {
+    pub fn new(config: AttestationConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_attestation(&mut self) -> Result<AttestationMetrics> {
+        println!("\n📜 Analyzing Device Attestation Certificate Chain");
+        println!("-------------------------------------------------");
+
+        let chain = AttestationChain::generate()?;
+        chain.validate()?;
+
+        let iterations = self.config.validation_iterations.max(1);
+        let start = Instant::now();
+        for _ in 0..iterations {
+            chain.validate()?;
+        }
+        let validation_time_us = start.elapsed().as_secs_f64() * 1e6 / iterations as f64;
+
+        let metrics = AttestationMetrics {
+            signature_algorithm: "ecdsa-with-SHA256 (P-256)".to_string(),
+            paa_der_bytes: chain.paa.der.len(),
+            pai_der_bytes: chain.pai.der.len(),
+            dac_der_bytes: chain.dac.der.len(),
+            attestation_chain_bytes: chain.pai.der.len() + chain.dac.der.len(),
+            validation_iterations: iterations,
+            validation_time_us,
+        };
+
+        println!("✅ DER Sizes: PAA {}B, PAI {}B, DAC {}B", metrics.paa_der_bytes, metrics.pai_der_bytes, metrics.dac_der_bytes);
+        println!("✅ Chain Validation: {:.1}µs", metrics.validation_time_us);
+
+        Ok(metrics)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CertificateRole {
+    Root,
+    Intermediate,
+    Device,
+}
+
+fn generate_key(rng: &rand::SystemRandom) -> Result<signature::EcdsaKeyPair> {
+    let pkcs8 = signature::EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_ASN1_SIGNING, rng)
+        .map_err(|_| anyhow!("failed to generate attestation key"))?;
+    signature::EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), rng)
+        .map_err(|_| anyhow!("failed to load attestation key"))
+}
+
+/// X.501 Name with one attribute per RDN, all UTF8String.
+fn name(attributes: &[(&[u8], &str)]) -> Vec<u8> {
+    let rdns: Vec<Vec<u8>> = attributes
+        .iter()
+        .map(|(oid, value)| der::set(&[&der::sequence(&[&der::oid(oid), &der::utf8(value)])]))
+        .collect();
+    der::sequence(&rdns.iter().map(Vec::as_slice).collect::<Vec<_>>())
+}
+
+fn issue(
+    rng: &rand::SystemRandom,
+    subject: &[u8],
+    issuer: &[u8],
+    subject_key: &signature::EcdsaKeyPair,
+    issuer_key: &signature::EcdsaKeyPair,
+    role: CertificateRole,
+) -> Result<Vec<u8>> {
+    let mut serial = [0u8; 8];
+    rng.fill(&mut serial).map_err(|_| anyhow!("serial generation failed"))?;
+    serial[0] &= 0x7F; // keep the INTEGER positive
+
+    let signature_algorithm = der::sequence(&[&der::oid(OID_ECDSA_WITH_SHA256)]);
+    let validity = der::sequence(&[&der::utc_time("230101000000Z"), &der::generalized_time("99991231235959Z")]);
+    let public_key_info = der::sequence(&[
+        &der::sequence(&[&der::oid(OID_EC_PUBLIC_KEY), &der::oid(OID_PRIME256V1)]),
+        &der::bit_string(subject_key.public_key().as_ref()),
+    ]);
+
+    let basic_constraints = match role {
+        CertificateRole::Root => der::sequence(&[&der::boolean(true)]),
+        CertificateRole::Intermediate => der::sequence(&[&der::boolean(true), &der::integer(&[0])]),
+        CertificateRole::Device => der::sequence(&[]),
+    };
+    // keyCertSign | cRLSign for CAs, digitalSignature for the DAC
+    let key_usage = match role {
+        CertificateRole::Device => der::bit_string_with_unused(&[0x80], 7),
+        _ => der::bit_string_with_unused(&[0x06], 1),
+    };
+    let subject_key_id = key_identifier(subject_key.public_key().as_ref());
+    let authority_key_id = key_identifier(issuer_key.public_key().as_ref());
+
+    let mut extensions = vec![
+        extension(OID_BASIC_CONSTRAINTS, true, &basic_constraints),
+        extension(OID_KEY_USAGE, true, &key_usage),
+        extension(OID_SUBJECT_KEY_ID, false, &der::octet_string(&subject_key_id)),
+    ];
+    if role != CertificateRole::Root {
+        extensions.push(extension(OID_AUTHORITY_KEY_ID, false, &der::sequence(&[&der::tagged(0x80, &authority_key_id)])));
+    }
+    let extensions = der::tagged(0xA3, &der::sequence(&extensions.iter().map(Vec::as_slice).collect::<Vec<_>>()));
+
+    let tbs = der::sequence(&[
+        &der::tagged(0xA0, &der::integer(&[2])), // v3
+        &der::integer(&serial),
+        &signature_algorithm,
+        issuer,
+        &validity,
+        subject,
+        &public_key_info,
+        &extensions,
+    ]);
+
+    let signature = issuer_key.sign(rng, &tbs).map_err(|_| anyhow!("certificate signing failed"))?;
+    Ok(der::sequence(&[&tbs, &signature_algorithm, &der::bit_string(signature.as_ref())]))
+}
+
+fn extension(oid: &[u8], critical: bool, value: &[u8]) -> Vec<u8> {
+    if critical {
+        der::sequence(&[&der::oid(oid), &der::boolean(true), &der::octet_string(value)])
+    } else {
+        der::sequence(&[&der::oid(oid), &der::octet_string(value)])
+    }
+}
+
+/// SHA-1 of the public key (RFC 5280 method 1), as Matter key identifiers use.
+fn key_identifier(public_key: &[u8]) -> Vec<u8> {
+    digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, public_key).as_ref().to_vec()
+}
+
+/// The fields of a DER certificate that chain validation needs.
+struct ParsedCertificate<'a> {
+    tbs: &'a [u8],
+    signature: &'a [u8],
+    issuer: &'a [u8],
+    subject: &'a [u8],
+    public_key: &'a [u8],
+    is_ca: bool,
+    path_len: Option<u8>,
+}
+
+impl<'a> ParsedCertificate<'a> {
+    fn parse(certificate: &'a [u8]) -> Result<Self> {
+        let (_, certificate, _) = der::read(certificate, 0x30)?;
+        let (tbs, tbs_content, rest) = der::read(certificate, 0x30)?;
+        let (_, _, rest) = der::read(rest, 0x30)?; // signature algorithm
+        let (_, signature, _) = der::read(rest, 0x03)?;
+
+        let (_, _, fields) = der::read(tbs_content, 0xA0)?; // version
+        let (_, _, fields) = der::read(fields, 0x02)?; // serial
+        let (_, _, fields) = der::read(fields, 0x30)?; // signature algorithm
+        let (issuer, _, fields) = der::read(fields, 0x30)?;
+        let (_, _, fields) = der::read(fields, 0x30)?; // validity
+        let (subject, _, fields) = der::read(fields, 0x30)?;
+        let (_, key_info, fields) = der::read(fields, 0x30)?;
+        let (_, _, key_info) = der::read(key_info, 0x30)?; // algorithm
+        let (_, public_key, _) = der::read(key_info, 0x03)?;
+
+        let (mut is_ca, mut path_len) = (false, None);
+        if let Ok((_, extensions, _)) = der::read(fields, 0xA3) {
+            let (_, mut extensions, _) = der::read(extensions, 0x30)?;
+            while !extensions.is_empty() {
+                let (_, extension, rest) = der::read(extensions, 0x30)?;
+                extensions = rest;
+
+                let (_, oid, mut extension) = der::read(extension, 0x06)?;
+                if oid != OID_BASIC_CONSTRAINTS {
+                    continue;
+                }
+                if let Ok((_, _, rest)) = der::read(extension, 0x01) {
+                    extension = rest; // critical flag
+                }
+                let (_, value, _) = der::read(extension, 0x04)?;
+                let (_, mut constraints, _) = der::read(value, 0x30)?;
+                if let Ok((_, flag, rest)) = der::read(constraints, 0x01) {
+                    is_ca = flag == [0xFF];
+                    constraints = rest;
+                }
+                if let Ok((_, length, _)) = der::read(constraints, 0x02) {
+                    path_len = length.last().copied();
+                }
+            }
+        }
+
+        Ok(Self {
+            tbs,
+            // BIT STRING content starts with the unused-bits count
+            signature: signature.get(1..).ok_or_else(|| anyhow!("empty signature"))?,
+            issuer,
+            subject,
+            public_key: public_key.get(1..).ok_or_else(|| anyhow!("empty public key"))?,
+            is_ca,
+            path_len,
+        })
+    }
+
+    fn verify_signed_by(&self, issuer_public_key: &[u8]) -> Result<()> {
+        signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_ASN1, issuer_public_key)
+            .verify(self.tbs, self.signature)
+            .map_err(|_| anyhow!("certificate signature invalid"))
+    }
+}
+
+/// Value of the first attribute with `oid` in a Name.
+fn find_attribute<'a>(name: &'a [u8], oid: &[u8]) -> Option<&'a [u8]> {
+    let (_, mut rdns, _) = der::read(name, 0x30).ok()?;
+    while !rdns.is_empty() {
+        let (_, rdn, rest) = der::read(rdns, 0x31).ok()?;
+        rdns = rest;
+        let (_, attribute, _) = der::read(rdn, 0x30).ok()?;
+        let (_, attribute_oid, value) = der::read(attribute, 0x06).ok()?;
+        if attribute_oid == oid {
+            return der::read(value, 0x0C).ok().map(|(_, content, _)| content);
+        }
+    }
+    None
+}
+
+/// Reads a certificate the way chain validation does, without checking signatures.
+pub(crate) fn certificate_parses(certificate: &[u8]) -> bool {
+    let Ok(parsed) = ParsedCertificate::parse(certificate) else {
+        return false;
+    };
+    find_attribute(parsed.subject, OID_MATTER_VID);
+    find_attribute(parsed.issuer, OID_COMMON_NAME);
+    true
+}
+
+/// Just enough DER to write and read certificates.
+mod der {
+    use anyhow::{anyhow, Result};
+
+    pub fn tagged(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        let len = content.len();
+        if len < 0x80 {
+            out.push(len as u8);
+        } else {
+            let bytes: Vec<u8> = len.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+            out.push(0x80 | bytes.len() as u8);
+            out.extend_from_slice(&bytes);
+        }
+        out.extend_from_slice(content);
+        out
+    }
+
+    pub fn sequence(parts: &[&[u8]]) -> Vec<u8> {
+        tagged(0x30, &parts.concat())
+    }
+
+    pub fn set(parts: &[&[u8]]) -> Vec<u8> {
+        tagged(0x31, &parts.concat())
+    }
+
+    pub fn boolean(value: bool) -> Vec<u8> {
+        tagged(0x01, &[if value { 0xFF } else { 0x00 }])
+    }
+
+    /// Unsigned big-endian integer; a leading zero keeps it positive.
+    pub fn integer(value: &[u8]) -> Vec<u8> {
+        if value.first().is_some_and(|b| b & 0x80 != 0) {
+            tagged(0x02, &[&[0u8][..], value].concat())
+        } else {
+            tagged(0x02, value)
+        }
+    }
+
+    pub fn bit_string(value: &[u8]) -> Vec<u8> {
+        bit_string_with_unused(value, 0)
+    }
+
+    pub fn bit_string_with_unused(value: &[u8], unused_bits: u8) -> Vec<u8> {
+        tagged(0x03, &[&[unused_bits][..], value].concat())
+    }
+
+    pub fn octet_string(value: &[u8]) -> Vec<u8> {
+        tagged(0x04, value)
+    }
+
+    pub fn oid(encoded: &[u8]) -> Vec<u8> {
+        tagged(0x06, encoded)
+    }
+
+    pub fn utf8(value: &str) -> Vec<u8> {
+        tagged(0x0C, value.as_bytes())
+    }
+
+    pub fn utc_time(value: &str) -> Vec<u8> {
+        tagged(0x17, value.as_bytes())
+    }
+
+    pub fn generalized_time(value: &str) -> Vec<u8> {
+        tagged(0x18, value.as_bytes())
+    }
+
+    /// Reads one element with the expected tag: (whole element, content, remainder).
+    pub fn read(input: &[u8], expected_tag: u8) -> Result<(&[u8], &[u8], &[u8])> {
+        let (&tag, rest) = input.split_first().ok_or_else(|| anyhow!("truncated DER"))?;
+        if tag != expected_tag {
+            return Err(anyhow!("expected DER tag {:#04x}, found {:#04x}", expected_tag, tag));
+        }
+        let (&first, rest) = rest.split_first().ok_or_else(|| anyhow!("truncated DER length"))?;
+
+        let (len, header) = if first < 0x80 {
+            (first as usize, 2)
+        } else {
+            let count = (first & 0x7F) as usize;
+            if count == 0 || count > 4 || rest.len() < count {
+                return Err(anyhow!("unsupported DER length"));
+            }
+            let len = rest[..count].iter().fold(0usize, |acc, b| (acc << 8) | *b as usize);
+            (len, 2 + count)
+        };
+
+        if input.len() < header + len {
+            return Err(anyhow!("truncated DER content"));
+        }
+        Ok((&input[..header + len], &input[header..header + len], &input[header + len..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_dac_verifies_against_the_pai_and_paa() {
+        let chain = AttestationChain::generate().unwrap();
+        chain.validate().unwrap();
+
+        let paa = ParsedCertificate::parse(&chain.paa.der).unwrap();
+        let pai = ParsedCertificate::parse(&chain.pai.der).unwrap();
+        let dac = ParsedCertificate::parse(&chain.dac.der).unwrap();
+        dac.verify_signed_by(pai.public_key).unwrap();
+        pai.verify_signed_by(paa.public_key).unwrap();
+        // The PAA signs the PAI only; the DAC is one step further down
+        assert!(dac.verify_signed_by(paa.public_key).is_err());
+        assert_eq!((paa.is_ca, pai.is_ca, dac.is_ca), (true, true, false));
+        assert_eq!(pai.path_len, Some(0));
+        assert_eq!(find_attribute(dac.subject, OID_MATTER_PID), Some(PRODUCT_ID.as_bytes()));
+
+        let challenge = b"attestation challenge";
+        let signed = chain.sign_challenge(challenge).unwrap();
+        signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_ASN1, dac.public_key)
+            .verify(challenge, &signed)
+            .unwrap();
+    }
+
+    #[test]
+    fn tampered_or_foreign_certificates_fail_validation() {
+        let mut chain = AttestationChain::generate().unwrap();
+        // One byte of the DAC's subject changed after signing
+        let position = chain.dac.der.windows(3).position(|w| w == b"DAC").unwrap();
+        chain.dac.der[position] = b'X';
+        assert!(chain.validate().is_err());
+
+        // A PAI with the same name but another key neither signed the DAC nor was signed by this PAA
+        let mut chain = AttestationChain::generate().unwrap();
+        chain.pai = AttestationChain::generate().unwrap().pai;
+        assert!(chain.validate().is_err());
+    }
+
+    #[test]
+    fn der_lengths_switch_to_the_long_form_at_128_bytes() {
+        assert_eq!(der::tagged(0x04, &[0xAA; 0x7F])[..2], [0x04, 0x7F]);
+        assert_eq!(der::tagged(0x04, &[0xAA; 0x80])[..3], [0x04, 0x81, 0x80]);
+        assert_eq!(der::tagged(0x04, &[0xAA; 0x1234])[..4], [0x04, 0x82, 0x12, 0x34]);
+        assert_eq!(der::integer(&[0x80, 0x01]), [0x02, 0x03, 0x00, 0x80, 0x01]);
+        assert_eq!(der::integer(&[0x7F]), [0x02, 0x01, 0x7F]);
+
+        let encoded = [der::octet_string(&[7; 300]), der::boolean(true)].concat();
+        let (element, content, rest) = der::read(&encoded, 0x04).unwrap();
+        assert_eq!((element.len(), content, rest), (304, &[7u8; 300][..], &[0x01, 0x01, 0xFF][..]));
+        assert!(der::read(&encoded, 0x30).is_err(), "wrong tag");
+        assert!(der::read(&encoded[..100], 0x04).is_err(), "truncated content");
+    }
+
+    #[test]
+    fn chain_bytes_cover_what_the_device_sends() {
+        let metrics = AttestationAnalyzer::builder().validation_iterations(2u32).build().analyze_attestation().unwrap();
+        assert_eq!(metrics.attestation_chain_bytes, metrics.pai_der_bytes + metrics.dac_der_bytes);
+        assert_eq!(metrics.validation_iterations, 2);
+        // Matter caps DER certificates at 600 bytes
+        for bytes in [metrics.paa_der_bytes, metrics.pai_der_bytes, metrics.dac_der_bytes] {
+            assert!((300..=600).contains(&bytes), "{} bytes", bytes);
+        }
+    }
+
+    /// The chain as OpenSSL reads it, so the DER is not only accepted by the parser above.
+    #[cfg(feature = "tls-openssl")]
+    #[test]
+    fn openssl_verifies_the_generated_chain() {
+        use openssl::x509::X509;
+
+        let chain = AttestationChain::generate().unwrap();
+        let paa = X509::from_der(&chain.paa.der).unwrap();
+        let pai = X509::from_der(&chain.pai.der).unwrap();
+        let dac = X509::from_der(&chain.dac.der).unwrap();
+        assert!(dac.verify(&pai.public_key().unwrap()).unwrap());
+        assert!(pai.verify(&paa.public_key().unwrap()).unwrap());
+        assert!(!dac.verify(&paa.public_key().unwrap()).unwrap());
+        assert_eq!(pai.issued(&dac), openssl::x509::X509VerifyResult::OK);
+    }
+}
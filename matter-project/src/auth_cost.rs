@@ -0,0 +1,623 @@
+// matter-project/src/auth_cost.rs
+/*!
+PSK vs certificate authentication cost - DTLS-PSK, DTLS-cert and CASE with operational certificates
+*/
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use metrics_core::tlv::{Tag, TlvWriter};
+use ring::rand::SecureRandom;
+use ring::signature::KeyPair;
+use ring::{aead, agreement, digest, hkdf, rand, signature};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::builder::analyzer_builder;
+use crate::handshake::{AuthMode, HandshakeAnalyzer, HandshakeConfig, LinkProfile, SecurityProtocol};
+
+/// CASE exchanges averaged per figure unless configured otherwise.
+pub(crate) const CASE_ITERATIONS: u32 = 50;
+
+// Matter operational credentials kept by a device besides its certificates
+const P256_PRIVATE_KEY_BYTES: usize = 32;
+const IPK_BYTES: usize = 16;
+const RESUMPTION_ID_BYTES: usize = 16;
+// DTLS-PSK credentials as provisioned by the openssl backend
+const PSK_IDENTITY_BYTES: usize = 15;
+const PSK_KEY_BYTES: usize = 16;
+// Unsecured Matter message header + protocol header carried by each CASE message
+const MATTER_FRAMING_BYTES: usize = 8 + 6;
+
+// Matter certificate TLV tags
+const CERT_SERIAL: u8 = 1;
+const CERT_SIG_ALGO: u8 = 2;
+const CERT_ISSUER: u8 = 3;
+const CERT_NOT_BEFORE: u8 = 4;
+const CERT_NOT_AFTER: u8 = 5;
+const CERT_SUBJECT: u8 = 6;
+const CERT_PUBKEY_ALGO: u8 = 7;
+const CERT_EC_CURVE: u8 = 8;
+const CERT_EC_PUBKEY: u8 = 9;
+const CERT_EXTENSIONS: u8 = 10;
+const CERT_SIGNATURE: u8 = 11;
+// Matter DN attribute tags
+const DN_NODE_ID: u8 = 17;
+const DN_ICAC_ID: u8 = 19;
+const DN_RCAC_ID: u8 = 20;
+const DN_FABRIC_ID: u8 = 21;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthCostMetrics {
+    pub schemes: Vec<AuthSchemeCost>,
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthSchemeCost {
+    pub scheme: String,
+    pub backend: String,
+    /// Host compute time for both ends of one full handshake (no link delay).
+    pub handshake_time_ms: f64,
+    pub handshake_bytes: usize,
+    pub handshake_messages: usize,
+    pub device_storage: DeviceStorage,
+}
+
+/// Credential bytes a device must persist to use the scheme.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceStorage {
+    pub own_credential_bytes: usize,
+    pub trust_anchor_bytes: usize,
+    pub secret_key_bytes: usize,
+    pub total_bytes: usize,
+}
+
+impl DeviceStorage {
+    fn new(own_credential_bytes: usize, trust_anchor_bytes: usize, secret_key_bytes: usize) -> Self {
+        Self {
+            own_credential_bytes,
+            trust_anchor_bytes,
+            secret_key_bytes,
+            total_bytes: own_credential_bytes + trust_anchor_bytes + secret_key_bytes,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthCostConfig {
+    /// CASE exchanges run and averaged for the CASE figures.
+    pub case_iterations: u32,
+}
+
+impl Default for AuthCostConfig {
+    fn default() -> Self {
+        Self { case_iterations: CASE_ITERATIONS }
+    }
+}
+
+analyzer_builder!(AuthCostAnalyzerBuilder => AuthCostAnalyzer(AuthCostConfig) {
+    case_iterations: u32,
+});
+
+pub struct AuthCostAnalyzer {
+    config: AuthCostConfig,
+}
+
+impl Default for AuthCostAnalyzer {
+    fn default() -> Self {
+        Self::new(AuthCostConfig::default())
+    }
+}
+
+impl AuthCostAnalyzer {
+    pub fn new(config: AuthCostConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_auth_cost(&mut self) -> Result<AuthCostMetrics> {
+        println!("\n🔑 Comparing PSK vs Certificate Authentication Cost");
+        println!("---------------------------------------------------");
+
+        let mut schemes = Vec::new();
+
+        // DTLS 1.2 with PSK and with a certificate, full handshakes on loopback
+        let dtls = HandshakeAnalyzer::new(HandshakeConfig {
+            protocols: vec![SecurityProtocol::Dtls12],
+            auth_modes: vec![AuthMode::Psk, AuthMode::Certificate],
+            links: vec![LinkProfile::loopback()],
+            trials: 1,
+        })
+        .analyze_handshakes()?;
+
+        for result in dtls.results.iter().filter(|r| !r.resumption_requested) {
+            let device_storage = match (result.auth, result.certificate_der_bytes) {
+                (AuthMode::Certificate, Some(certificate)) => {
+                    // Own certificate plus the CA certificate it chains to
+                    DeviceStorage::new(certificate, certificate, P256_PRIVATE_KEY_BYTES)
+                }
+                _ => DeviceStorage::new(PSK_IDENTITY_BYTES, 0, PSK_KEY_BYTES),
+            };
+            schemes.push(AuthSchemeCost {
+                scheme: match result.auth {
+                    AuthMode::Psk => "dtls12_psk",
+                    AuthMode::Certificate => "dtls12_certificate",
+                }
+                .to_string(),
+                backend: result.backend.clone(),
+                handshake_time_ms: result.compute_time_ms,
+                handshake_bytes: result.client_bytes + result.server_bytes,
+                handshake_messages: result.flights.len(),
+                device_storage,
+            });
+        }
+
+        let mut notes = dtls.unsupported;
+        if schemes.is_empty() {
+            warn!("⚠️ No DTLS backend compiled in; only CASE is measured");
+        }
+
+        schemes.push(case_operational_cost(self.config.case_iterations.max(1))?);
+
+        for scheme in &schemes {
+            println!("✅ {} ({}): {:.2}ms, {}B over {} messages, {}B device storage",
+                     scheme.scheme, scheme.backend, scheme.handshake_time_ms,
+                     scheme.handshake_bytes, scheme.handshake_messages, scheme.device_storage.total_bytes);
+        }
+
+        notes.push("CASE encrypts Sigma2/Sigma3 with AES-128-GCM as a stand-in for AES-CCM (same 16-byte tag)".to_string());
+        Ok(AuthCostMetrics { schemes, notes })
+    }
+}
+
+/// A Matter TLV certificate and the key pair it certifies.
+struct OperationalCredential {
+    tlv: Vec<u8>,
+    key_pair: signature::EcdsaKeyPair,
+}
+
+impl OperationalCredential {
+    fn public_key(&self) -> &[u8] {
+        self.key_pair.public_key().as_ref()
+    }
+}
+
+/// Root → intermediate → node operational chain for one fabric.
+struct OperationalChain {
+    rcac: OperationalCredential,
+    icac: OperationalCredential,
+    noc: OperationalCredential,
+}
+
+pub(crate) fn case_operational_cost(iterations: u32) -> Result<AuthSchemeCost> {
+    Ok(case_operational_exchange(iterations)?.0)
+}
+
+/// The CASE cost together with the size of each message on the wire, Sigma1 to StatusReport.
+pub(crate) fn case_operational_exchange(iterations: u32) -> Result<(AuthSchemeCost, Vec<usize>)> {
+    let rng = rand::SystemRandom::new();
+    let initiator = issue_chain(&rng, 0x0000_0000_0000_0001)?;
+    let responder = issue_chain(&rng, 0x0000_0000_0000_0002)?;
+
+    let mut message_sizes = Vec::new();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        message_sizes = case_exchange(&rng, &initiator, &responder)?;
+    }
+    let handshake_time_ms = start.elapsed().as_secs_f64() * 1000.0 / iterations as f64;
+
+    let cost = AuthSchemeCost {
+        scheme: "case_operational_certs".to_string(),
+        backend: "ring".to_string(),
+        handshake_time_ms,
+        handshake_bytes: message_sizes.iter().sum(),
+        handshake_messages: message_sizes.len(),
+        device_storage: DeviceStorage::new(
+            initiator.noc.tlv.len() + initiator.icac.tlv.len(),
+            initiator.rcac.tlv.len(),
+            P256_PRIVATE_KEY_BYTES + IPK_BYTES,
+        ),
+    };
+    Ok((cost, message_sizes))
+}
+
+/// CASE session resumption: Sigma1 with a resumption id and MIC, Sigma2_Resume and
+/// a StatusReport. Keys come from the earlier session's shared secret, so no
+/// certificates are sent or verified.
+pub(crate) fn case_resumption_cost(iterations: u32) -> Result<AuthSchemeCost> {
+    let rng = rand::SystemRandom::new();
+    let mut shared_secret = [0u8; 32];
+    rng.fill(&mut shared_secret).map_err(|_| anyhow!("random generation failed"))?;
+
+    let mut message_sizes = Vec::new();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        message_sizes = case_resumption_exchange(&rng, &shared_secret)?;
+    }
+    let handshake_time_ms = start.elapsed().as_secs_f64() * 1000.0 / iterations as f64;
+
+    Ok(AuthSchemeCost {
+        scheme: "case_resumption".to_string(),
+        backend: "ring".to_string(),
+        handshake_time_ms,
+        handshake_bytes: message_sizes.iter().sum(),
+        handshake_messages: message_sizes.len(),
+        // Resumption id and shared secret on top of the operational credentials
+        device_storage: DeviceStorage::new(0, 0, RESUMPTION_ID_BYTES + shared_secret.len()),
+    })
+}
+
+fn case_resumption_exchange(rng: &rand::SystemRandom, shared_secret: &[u8]) -> Result<Vec<usize>> {
+    let mut random = [0u8; 32];
+    let mut resumption_id = [0u8; RESUMPTION_ID_BYTES];
+    rng.fill(&mut random).map_err(|_| anyhow!("random generation failed"))?;
+    rng.fill(&mut resumption_id).map_err(|_| anyhow!("random generation failed"))?;
+
+    // Sigma1 still carries an ephemeral key in case the responder falls back to a full exchange
+    let ephemeral = agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, rng)
+        .map_err(|_| anyhow!("ephemeral key generation failed"))?;
+    let ephemeral_public = ephemeral
+        .compute_public_key()
+        .map_err(|_| anyhow!("public key derivation failed"))?;
+    let initiator_mic = resumption_mic(shared_secret, &random, &resumption_id, b"Sigma1_Resume")?;
+    let sigma1 = encode_message(|w| {
+        w.octets(Tag::Context(1), &random)?;
+        w.u16(Tag::Context(2), 0x1001)?;
+        w.octets(Tag::Context(3), &[0u8; 32])?;
+        w.octets(Tag::Context(4), ephemeral_public.as_ref())?;
+        w.octets(Tag::Context(6), &resumption_id)?;
+        w.octets(Tag::Context(7), &initiator_mic)
+    })?;
+
+    // Responder checks the MIC and answers with a fresh resumption id
+    if resumption_mic(shared_secret, &random, &resumption_id, b"Sigma1_Resume")? != initiator_mic {
+        return Err(anyhow!("Sigma1 resumption MIC invalid"));
+    }
+    rng.fill(&mut resumption_id).map_err(|_| anyhow!("random generation failed"))?;
+    let responder_mic = resumption_mic(shared_secret, &random, &resumption_id, b"Sigma2_Resume")?;
+    let sigma2_resume = encode_message(|w| {
+        w.octets(Tag::Context(1), &resumption_id)?;
+        w.octets(Tag::Context(2), &responder_mic)?;
+        w.u16(Tag::Context(3), 0x2002)
+    })?;
+
+    let status_report = 2 + 4 + 2;
+
+    Ok([sigma1.len(), sigma2_resume.len(), status_report]
+        .iter()
+        .map(|payload| payload + MATTER_FRAMING_BYTES)
+        .collect())
+}
+
+/// Tag of an empty message sealed with a key derived from the resumed session's secret.
+fn resumption_mic(shared_secret: &[u8], random: &[u8], resumption_id: &[u8], info: &[u8]) -> Result<Vec<u8>> {
+    let mut key = [0u8; 16];
+    hkdf::Salt::new(hkdf::HKDF_SHA256, &[random, resumption_id].concat())
+        .extract(shared_secret)
+        .expand(&[info], &aead::AES_128_GCM)
+        .and_then(|okm| okm.fill(&mut key))
+        .map_err(|_| anyhow!("resumption key derivation failed"))?;
+    let key = aead::LessSafeKey::new(
+        aead::UnboundKey::new(&aead::AES_128_GCM, &key).map_err(|_| anyhow!("invalid resumption key"))?,
+    );
+    seal(&key, Vec::new())
+}
+
+fn issue_chain(rng: &rand::SystemRandom, node_id: u64) -> Result<OperationalChain> {
+    const FABRIC_ID: u64 = 0xFAB0_0000_0000_0001;
+
+    let rcac_key = generate_key(rng)?;
+    let icac_key = generate_key(rng)?;
+    let noc_key = generate_key(rng)?;
+
+    let rcac_subject = [(DN_RCAC_ID, 0xCACA_CACA_0000_0001)];
+    let icac_subject = [(DN_ICAC_ID, 0xCACA_CACA_0000_0002)];
+    let noc_subject = [(DN_NODE_ID, node_id), (DN_FABRIC_ID, FABRIC_ID)];
+
+    let rcac = issue_certificate(rng, &rcac_subject, &rcac_subject, &rcac_key, &rcac_key, true)?;
+    let icac = issue_certificate(rng, &icac_subject, &rcac_subject, &icac_key, &rcac_key, true)?;
+    let noc = issue_certificate(rng, &noc_subject, &icac_subject, &noc_key, &icac_key, false)?;
+
+    Ok(OperationalChain {
+        rcac: OperationalCredential { tlv: rcac, key_pair: rcac_key },
+        icac: OperationalCredential { tlv: icac, key_pair: icac_key },
+        noc: OperationalCredential { tlv: noc, key_pair: noc_key },
+    })
+}
+
+fn generate_key(rng: &rand::SystemRandom) -> Result<signature::EcdsaKeyPair> {
+    let pkcs8 = signature::EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, rng)
+        .map_err(|_| anyhow!("failed to generate operational key"))?;
+    signature::EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), rng)
+        .map_err(|_| anyhow!("failed to load operational key"))
+}
+
+/// Encodes a Matter TLV certificate; the signature covers the TLV encoding of
+/// the other fields (Matter signs the equivalent X.509 TBS, which is the same
+/// amount of signing work).
+fn issue_certificate(
+    rng: &rand::SystemRandom,
+    subject: &[(u8, u64)],
+    issuer: &[(u8, u64)],
+    subject_key: &signature::EcdsaKeyPair,
+    issuer_key: &signature::EcdsaKeyPair,
+    is_ca: bool,
+) -> Result<Vec<u8>> {
+    let mut serial = [0u8; 8];
+    rng.fill(&mut serial).map_err(|_| anyhow!("serial generation failed"))?;
+    let subject_key_id = key_identifier(subject_key.public_key().as_ref());
+    let authority_key_id = key_identifier(issuer_key.public_key().as_ref());
+
+    let mut buffer = [0u8; 512];
+    let mut writer = TlvWriter::new(&mut buffer);
+    encode_tbs(&mut writer, &serial, subject, issuer, subject_key.public_key().as_ref(), is_ca,
+               &subject_key_id, &authority_key_id)
+        .map_err(|e| anyhow!("certificate encoding failed: {:?}", e))?;
+
+    let signature = issuer_key.sign(rng, writer.as_bytes()).map_err(|_| anyhow!("certificate signing failed"))?;
+    writer
+        .octets(Tag::Context(CERT_SIGNATURE), signature.as_ref())
+        .and_then(|_| writer.end_container())
+        .map_err(|e| anyhow!("certificate encoding failed: {:?}", e))?;
+
+    Ok(writer.as_bytes().to_vec())
+}
+
+/// Writes the opening struct and every field except the signature.
+#[allow(clippy::too_many_arguments)]
+fn encode_tbs(
+    writer: &mut TlvWriter,
+    serial: &[u8],
+    subject: &[(u8, u64)],
+    issuer: &[(u8, u64)],
+    public_key: &[u8],
+    is_ca: bool,
+    subject_key_id: &[u8],
+    authority_key_id: &[u8],
+) -> Result<(), metrics_core::tlv::TlvError> {
+    writer.start_struct(Tag::Anonymous)?;
+    writer.octets(Tag::Context(CERT_SERIAL), serial)?;
+    writer.u8(Tag::Context(CERT_SIG_ALGO), 1)?; // ecdsa-with-SHA256
+
+    writer.start_list(Tag::Context(CERT_ISSUER))?;
+    for (attribute, value) in issuer {
+        writer.u64(Tag::Context(*attribute), *value)?;
+    }
+    writer.end_container()?;
+
+    writer.u32(Tag::Context(CERT_NOT_BEFORE), 0x2D6F_0000)?;
+    writer.u32(Tag::Context(CERT_NOT_AFTER), 0)?; // no well-defined expiration
+
+    writer.start_list(Tag::Context(CERT_SUBJECT))?;
+    for (attribute, value) in subject {
+        writer.u64(Tag::Context(*attribute), *value)?;
+    }
+    writer.end_container()?;
+
+    writer.u8(Tag::Context(CERT_PUBKEY_ALGO), 1)?; // EC public key
+    writer.u8(Tag::Context(CERT_EC_CURVE), 1)?; // prime256v1
+    writer.octets(Tag::Context(CERT_EC_PUBKEY), public_key)?;
+
+    writer.start_list(Tag::Context(CERT_EXTENSIONS))?;
+    writer.start_struct(Tag::Context(1))?; // basic constraints
+    writer.bool(Tag::Context(1), is_ca)?;
+    writer.end_container()?;
+    // key usage: keyCertSign|cRLSign for CAs, digitalSignature for nodes
+    writer.u16(Tag::Context(2), if is_ca { 0x0060 } else { 0x0001 })?;
+    if !is_ca {
+        writer.start_array(Tag::Context(3))?; // extended key usage
+        writer.u8(Tag::Anonymous, 2)?; // clientAuth
+        writer.u8(Tag::Anonymous, 1)?; // serverAuth
+        writer.end_container()?;
+    }
+    writer.octets(Tag::Context(4), subject_key_id)?;
+    writer.octets(Tag::Context(5), authority_key_id)?;
+    writer.end_container()
+}
+
+/// SHA-1 of the public key, as Matter derives key identifiers.
+fn key_identifier(public_key: &[u8]) -> Vec<u8> {
+    digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, public_key).as_ref().to_vec()
+}
+
+/// Runs both ends of one CASE exchange; returns the message sizes on the wire.
+fn case_exchange(
+    rng: &rand::SystemRandom,
+    initiator: &OperationalChain,
+    responder: &OperationalChain,
+) -> Result<Vec<usize>> {
+    let mut random = [0u8; 32];
+    rng.fill(&mut random).map_err(|_| anyhow!("random generation failed"))?;
+
+    // Sigma1: random, session id, destination id, ephemeral public key
+    let initiator_ephemeral = agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, rng)
+        .map_err(|_| anyhow!("ephemeral key generation failed"))?;
+    let initiator_public = initiator_ephemeral
+        .compute_public_key()
+        .map_err(|_| anyhow!("public key derivation failed"))?;
+    let destination_id = [0u8; 32];
+    let sigma1 = encode_message(|w| {
+        w.octets(Tag::Context(1), &random)?;
+        w.u16(Tag::Context(2), 0x1001)?;
+        w.octets(Tag::Context(3), &destination_id)?;
+        w.octets(Tag::Context(4), initiator_public.as_ref())
+    })?;
+
+    // Sigma2: responder signs its credentials and both ephemeral keys, then encrypts them
+    let responder_ephemeral = agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, rng)
+        .map_err(|_| anyhow!("ephemeral key generation failed"))?;
+    let responder_public = responder_ephemeral
+        .compute_public_key()
+        .map_err(|_| anyhow!("public key derivation failed"))?;
+    let responder_key = session_key(responder_ephemeral, initiator_public.as_ref(), b"Sigma2")?;
+    let tbe2 = signed_credentials(rng, responder, responder_public.as_ref(), initiator_public.as_ref())?;
+    let encrypted2 = seal(&responder_key, tbe2)?;
+    let sigma2 = encode_message(|w| {
+        w.octets(Tag::Context(1), &random)?;
+        w.u16(Tag::Context(2), 0x2002)?;
+        w.octets(Tag::Context(3), responder_public.as_ref())?;
+        w.octets(Tag::Context(4), &encrypted2)
+    })?;
+
+    // Initiator: derive the same key, decrypt and validate the responder
+    let initiator_key = session_key(initiator_ephemeral, responder_public.as_ref(), b"Sigma2")?;
+    let opened2 = open(&initiator_key, encrypted2)?;
+    validate_peer(responder, &opened2, responder_public.as_ref(), initiator_public.as_ref())?;
+
+    // Sigma3: initiator's credentials, validated by the responder
+    let tbe3 = signed_credentials(rng, initiator, initiator_public.as_ref(), responder_public.as_ref())?;
+    let encrypted3 = seal(&initiator_key, tbe3)?;
+    let sigma3 = encode_message(|w| w.octets(Tag::Context(1), &encrypted3))?;
+
+    let opened3 = open(&responder_key, encrypted3)?;
+    validate_peer(initiator, &opened3, initiator_public.as_ref(), responder_public.as_ref())?;
+
+    // StatusReport closing the exchange: general code, protocol id, protocol code
+    let status_report = 2 + 4 + 2;
+
+    Ok([sigma1.len(), sigma2.len(), sigma3.len(), status_report]
+        .iter()
+        .map(|payload| payload + MATTER_FRAMING_BYTES)
+        .collect())
+}
+
+fn encode_message<F>(body: F) -> Result<Vec<u8>>
+where
+    F: FnOnce(&mut TlvWriter) -> Result<(), metrics_core::tlv::TlvError>,
+{
+    let mut buffer = [0u8; 2048];
+    let mut writer = TlvWriter::new(&mut buffer);
+    writer
+        .start_struct(Tag::Anonymous)
+        .and_then(|_| body(&mut writer))
+        .and_then(|_| writer.end_container())
+        .map_err(|e| anyhow!("CASE message encoding failed: {:?}", e))?;
+    Ok(writer.as_bytes().to_vec())
+}
+
+/// TBE payload: NOC, ICAC and a signature over them plus both ephemeral keys.
+fn signed_credentials(
+    rng: &rand::SystemRandom,
+    chain: &OperationalChain,
+    own_ephemeral: &[u8],
+    peer_ephemeral: &[u8],
+) -> Result<Vec<u8>> {
+    let tbs = [chain.noc.tlv.as_slice(), chain.icac.tlv.as_slice(), own_ephemeral, peer_ephemeral].concat();
+    let signature = chain.noc.key_pair.sign(rng, &tbs).map_err(|_| anyhow!("Sigma signing failed"))?;
+    encode_message(|w| {
+        w.octets(Tag::Context(1), &chain.noc.tlv)?;
+        w.octets(Tag::Context(2), &chain.icac.tlv)?;
+        w.octets(Tag::Context(3), signature.as_ref())
+    })
+}
+
+/// Verifies the peer's chain (NOC by ICAC, ICAC by RCAC) and its Sigma signature.
+fn validate_peer(chain: &OperationalChain, opened: &[u8], peer_ephemeral: &[u8], own_ephemeral: &[u8]) -> Result<()> {
+    verify_certificate(&chain.icac, chain.rcac.public_key())?;
+    verify_certificate(&chain.noc, chain.icac.public_key())?;
+
+    // The signature is the trailing 64-byte octet string before the end of the struct
+    let signature = opened
+        .len()
+        .checked_sub(1 + 64)
+        .map(|start| &opened[start..start + 64])
+        .ok_or_else(|| anyhow!("Sigma payload too short"))?;
+    let tbs = [chain.noc.tlv.as_slice(), chain.icac.tlv.as_slice(), peer_ephemeral, own_ephemeral].concat();
+    signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, chain.noc.public_key())
+        .verify(&tbs, signature)
+        .map_err(|_| anyhow!("Sigma signature invalid"))
+}
+
+fn verify_certificate(certificate: &OperationalCredential, issuer_public_key: &[u8]) -> Result<()> {
+    // Layout: ... signature tag (2) + length (1) + 64-byte signature + end of container (1)
+    let tlv = &certificate.tlv;
+    let signature_start = tlv.len().checked_sub(1 + 64).ok_or_else(|| anyhow!("certificate too short"))?;
+    let tbs_end = signature_start.checked_sub(3).ok_or_else(|| anyhow!("certificate too short"))?;
+    signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, issuer_public_key)
+        .verify(&tlv[..tbs_end], &tlv[signature_start..signature_start + 64])
+        .map_err(|_| anyhow!("certificate signature invalid"))
+}
+
+fn session_key(
+    ephemeral: agreement::EphemeralPrivateKey,
+    peer_public: &[u8],
+    info: &[u8],
+) -> Result<aead::LessSafeKey> {
+    let mut key = [0u8; 16];
+    agreement::agree_ephemeral(
+        ephemeral,
+        &agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, peer_public),
+        |shared_secret| {
+            hkdf::Salt::new(hkdf::HKDF_SHA256, b"CASE salt")
+                .extract(shared_secret)
+                .expand(&[info], &aead::AES_128_GCM)
+                .and_then(|okm| okm.fill(&mut key))
+        },
+    )
+    .map_err(|_| anyhow!("key agreement failed"))?
+    .map_err(|_| anyhow!("session key derivation failed"))?;
+
+    Ok(aead::LessSafeKey::new(
+        aead::UnboundKey::new(&aead::AES_128_GCM, &key).map_err(|_| anyhow!("invalid session key"))?,
+    ))
+}
+
+fn seal(key: &aead::LessSafeKey, mut payload: Vec<u8>) -> Result<Vec<u8>> {
+    key.seal_in_place_append_tag(aead::Nonce::assume_unique_for_key([0u8; aead::NONCE_LEN]), aead::Aad::empty(), &mut payload)
+        .map_err(|_| anyhow!("Sigma encryption failed"))?;
+    Ok(payload)
+}
+
+fn open(key: &aead::LessSafeKey, mut payload: Vec<u8>) -> Result<Vec<u8>> {
+    let len = key
+        .open_in_place(aead::Nonce::assume_unique_for_key([0u8; aead::NONCE_LEN]), aead::Aad::empty(), &mut payload)
+        .map_err(|_| anyhow!("Sigma decryption failed"))?
+        .len();
+    payload.truncate(len);
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics_core::tlv::{TlvReader, TlvValue};
+
+    #[test]
+    fn issued_certificates_verify_only_against_their_issuer() {
+        let rng = rand::SystemRandom::new();
+        let chain = issue_chain(&rng, 1).unwrap();
+
+        verify_certificate(&chain.rcac, chain.rcac.public_key()).unwrap();
+        verify_certificate(&chain.icac, chain.rcac.public_key()).unwrap();
+        verify_certificate(&chain.noc, chain.icac.public_key()).unwrap();
+        assert!(verify_certificate(&chain.noc, chain.rcac.public_key()).is_err());
+
+        let mut reader = TlvReader::new(&chain.noc.tlv);
+        assert_eq!(reader.next_element().unwrap(), Some((Tag::Anonymous, TlvValue::StructStart)));
+        assert_eq!(reader.next_element().unwrap().unwrap().0, Tag::Context(CERT_SERIAL));
+        assert_eq!(chain.noc.tlv.last(), Some(&0x18), "certificate struct is closed");
+    }
+
+    #[test]
+    fn case_bytes_and_storage_are_the_sums_of_their_parts() {
+        let (cost, message_sizes) = case_operational_exchange(1).unwrap();
+        assert_eq!(message_sizes.len(), 4);
+        assert_eq!(message_sizes[3], 2 + 4 + 2 + MATTER_FRAMING_BYTES);
+        assert_eq!(cost.handshake_messages, 4);
+        assert_eq!(cost.handshake_bytes, message_sizes.iter().sum::<usize>());
+
+        let storage = &cost.device_storage;
+        assert_eq!(storage.secret_key_bytes, P256_PRIVATE_KEY_BYTES + IPK_BYTES);
+        assert_eq!(storage.total_bytes, storage.own_credential_bytes + storage.trust_anchor_bytes + storage.secret_key_bytes);
+        assert!(storage.own_credential_bytes > storage.trust_anchor_bytes, "NOC and ICAC outweigh the RCAC");
+    }
+
+    #[test]
+    fn resumption_skips_the_certificates() {
+        let full = case_operational_cost(1).unwrap();
+        let resumption = case_resumption_cost(1).unwrap();
+
+        assert_eq!(resumption.handshake_messages, 3);
+        assert_eq!(resumption.device_storage.total_bytes, RESUMPTION_ID_BYTES + 32);
+        assert!(resumption.handshake_bytes * 3 < full.handshake_bytes);
+    }
+}
@@ -0,0 +1,756 @@
+// matter-project/src/bacnet.rs
+/*!
+BACnet/IP - Who-Is/I-Am discovery, ReadProperty latency and APDU overhead
+
+BACnet is the incumbent protocol of building automation: HVAC controllers,
+VAV boxes and lighting panels expose objects (Device, Analog Input, ...)
+whose properties a workstation reads. On BACnet/IP every message is a UDP
+datagram to port 47808:
+
+- A 4-byte BVLC header says whether the datagram is unicast or broadcast.
+- The NPDU carries the network layer: a version, control flags and, for
+  global broadcasts, the destination network 0xFFFF with a hop count.
+- The APDU holds the service. Its parameters are tagged: application tags
+  for typed values, and context tags and opening/closing tags for fields.
+
+Discovery is a broadcast Who-Is (optionally limited to a device instance
+range), answered by one broadcast I-Am per device. Reads are confirmed
+requests: ReadProperty asks for one property of one object, and
+ReadPropertyMultiple for many properties of many objects. Each is answered
+with a ComplexACK.
+
+Local devices on loopback UDP each hold a Device object and a set of
+Analog Input points (zone temperatures). A workstation discovers them,
+times ReadProperty of a present value, and reads 1 to N points with
+ReadProperty, ReadPropertyMultiple and, for comparison, a Matter
+ReadRequest/ReportData of the same Temperature Measurement values. A
+broadcast is sent to each device socket in turn but counted on the wire
+once. The Matter side is encoded with the real encoders, secured headers
+and MIC included, and is not sent.
+*/
+
+use anyhow::{anyhow, bail, Result};
+use metrics_core::tlv::Tag;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+
+use crate::bdx::{matter_message, MRP_STANDALONE_ACK, PROTOCOL_SECURE_CHANNEL};
+use crate::bridge::{read_request, report_data};
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{tlv, IM_REPORT_DATA, PROTOCOL_IM};
+use crate::transport_modes::IPV4_UDP_HEADER_BYTES;
+
+// BVLC (Annex J)
+const BVLC_TYPE_BACNET_IP: u8 = 0x81;
+const BVLC_ORIGINAL_UNICAST: u8 = 0x0A;
+const BVLC_ORIGINAL_BROADCAST: u8 = 0x0B;
+const BVLC_HEADER_BYTES: usize = 4;
+
+// NPDU
+const NPDU_VERSION: u8 = 0x01;
+const NPDU_EXPECTING_REPLY: u8 = 0x04;
+const NPDU_DESTINATION_PRESENT: u8 = 0x20;
+/// Destination network 0xFFFF (global broadcast), no address, hop count 255.
+const NPDU_GLOBAL_BROADCAST: [u8; 6] = [NPDU_VERSION, NPDU_DESTINATION_PRESENT, 0xFF, 0xFF, 0x00, 0xFF];
+
+// APDU types and services
+const PDU_CONFIRMED_REQUEST: u8 = 0x00;
+const PDU_UNCONFIRMED_REQUEST: u8 = 0x10;
+const PDU_COMPLEX_ACK: u8 = 0x30;
+const PDU_ERROR: u8 = 0x50;
+const SERVICE_I_AM: u8 = 0x00;
+const SERVICE_WHO_IS: u8 = 0x08;
+const SERVICE_READ_PROPERTY: u8 = 0x0C;
+const SERVICE_READ_PROPERTY_MULTIPLE: u8 = 0x0E;
+/// No segmentation, up to 1476-byte APDUs.
+const MAX_APDU_ACCEPTED: u8 = 0x05;
+const MAX_APDU_BYTES: u16 = 1476;
+const SEGMENTATION_NONE: u8 = 3;
+/// Placeholder vendor identifier for the lab devices.
+const VENDOR_ID: u8 = 0xFF;
+const ERROR_CLASS_OBJECT: u8 = 1;
+const ERROR_CLASS_PROPERTY: u8 = 2;
+const ERROR_UNKNOWN_OBJECT: u8 = 31;
+const ERROR_UNKNOWN_PROPERTY: u8 = 32;
+
+// Objects and properties
+const OBJECT_ANALOG_INPUT: u32 = 0;
+const OBJECT_DEVICE: u32 = 8;
+const PROPERTY_DESCRIPTION: u32 = 28;
+const PROPERTY_FIRMWARE_REVISION: u32 = 44;
+const PROPERTY_MODEL_NAME: u32 = 70;
+const PROPERTY_OBJECT_NAME: u32 = 77;
+const PROPERTY_PRESENT_VALUE: u32 = 85;
+const PROPERTY_STATUS_FLAGS: u32 = 111;
+const PROPERTY_UNITS: u32 = 117;
+const PROPERTY_VENDOR_NAME: u32 = 121;
+const UNITS_DEGREES_CELSIUS: u8 = 62;
+
+// Application tags
+const TAG_UNSIGNED: u8 = 2;
+const TAG_REAL: u8 = 4;
+const TAG_CHARACTER_STRING: u8 = 7;
+const TAG_BIT_STRING: u8 = 8;
+const TAG_ENUMERATED: u8 = 9;
+const TAG_OBJECT_IDENTIFIER: u8 = 12;
+
+// Matter Temperature Measurement cluster, the counterpart of an Analog Input in degrees Celsius
+const TEMPERATURE_MEASUREMENT_CLUSTER: u32 = 0x0402;
+const MEASURED_VALUE: u32 = 0x0000;
+
+const FIRST_DEVICE_INSTANCE: u32 = 1000;
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct BacnetConfig {
+    /// Devices answering Who-Is.
+    pub devices: usize,
+    /// Analog Input points on each device.
+    pub points_per_device: usize,
+    /// Points read at once in the ReadProperty/ReadPropertyMultiple/Matter comparison.
+    pub point_counts: Vec<usize>,
+    /// Reads timed per run.
+    pub iterations: usize,
+}
+
+impl Default for BacnetConfig {
+    fn default() -> Self {
+        Self {
+            devices: 20,
+            points_per_device: 16,
+            point_counts: vec![1, 4, 16],
+            iterations: 200,
+        }
+    }
+}
+
+analyzer_builder!(BacnetAnalyzerBuilder => BacnetAnalyzer(BacnetConfig) {
+    devices: usize,
+    points_per_device: usize,
+    point_counts: Vec<usize>,
+    iterations: usize,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BacnetMetrics {
+    pub devices: usize,
+    pub discovery: Vec<BacnetDiscoveryRun>,
+    pub read_property: BacnetReadPropertyRun,
+    pub point_reads: Vec<PointReadRun>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BacnetDiscoveryRun {
+    /// "global" or the device instance range the Who-Is was limited to.
+    pub scope: String,
+    pub i_am_received: usize,
+    /// Who-Is sent until the last I-Am arrived.
+    pub elapsed_ms: f64,
+    pub who_is_bytes: usize,
+    pub i_am_bytes: usize,
+    /// The Who-Is once (it is broadcast) and every I-Am.
+    pub total_bytes: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BacnetReadPropertyRun {
+    /// Datagram sizes with IPv4/UDP headers.
+    pub request_bytes: usize,
+    pub response_bytes: usize,
+    /// BVLC and NPDU bytes in each direction.
+    pub bvlc_npdu_bytes: usize,
+    pub request_apdu_bytes: usize,
+    pub response_apdu_bytes: usize,
+    /// The REAL present value itself, 4 bytes.
+    pub value_bytes: usize,
+    pub mean_latency_ms: f64,
+    pub p95_latency_ms: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PointReadRun {
+    pub method: String,
+    pub points: usize,
+    pub messages: usize,
+    /// Both directions, with IPv4/UDP headers.
+    pub bytes: usize,
+    pub bytes_per_point: f64,
+    /// Mean time to read all points; not measured for Matter, which is only encoded.
+    pub mean_latency_ms: Option<f64>,
+}
+
+/// One application-tagged property value, encoded.
+#[derive(Debug, Clone)]
+struct PropertyValue(Vec<u8>);
+
+impl PropertyValue {
+    fn real(value: f32) -> Self {
+        Self(application_tag(TAG_REAL, &value.to_be_bytes()))
+    }
+
+    fn enumerated(value: u8) -> Self {
+        Self(application_tag(TAG_ENUMERATED, &[value]))
+    }
+
+    fn text(value: &str) -> Self {
+        // Character set 0 (UTF-8) precedes the characters
+        Self(application_tag(TAG_CHARACTER_STRING, &[&[0u8][..], value.as_bytes()].concat()))
+    }
+
+    fn status_flags() -> Self {
+        // Four flags (in-alarm, fault, overridden, out-of-service), all clear; 4 unused bits
+        Self(application_tag(TAG_BIT_STRING, &[0x04, 0x00]))
+    }
+
+    fn object(object_type: u32, instance: u32) -> Self {
+        Self(application_tag(TAG_OBJECT_IDENTIFIER, &object_identifier(object_type, instance).to_be_bytes()))
+    }
+}
+
+fn object_identifier(object_type: u32, instance: u32) -> u32 {
+    object_type << 22 | (instance & 0x3F_FFFF)
+}
+
+/// Application tag with its length, using the extended length byte above 4 bytes.
+fn application_tag(tag: u8, value: &[u8]) -> Vec<u8> {
+    tag_header(tag, false, value.len()).into_iter().chain(value.iter().copied()).collect()
+}
+
+fn context_tag(tag: u8, value: &[u8]) -> Vec<u8> {
+    tag_header(tag, true, value.len()).into_iter().chain(value.iter().copied()).collect()
+}
+
+fn tag_header(tag: u8, context: bool, len: usize) -> Vec<u8> {
+    let class = if context { 0x08 } else { 0x00 };
+    if len <= 4 {
+        vec![tag << 4 | class | len as u8]
+    } else {
+        vec![tag << 4 | class | 0x05, len as u8]
+    }
+}
+
+fn opening_tag(tag: u8) -> u8 {
+    tag << 4 | 0x0E
+}
+
+fn closing_tag(tag: u8) -> u8 {
+    tag << 4 | 0x0F
+}
+
+/// A context-tagged unsigned in as few bytes as hold it.
+fn context_unsigned(tag: u8, value: u32) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take(3).take_while(|byte| **byte == 0).count();
+    context_tag(tag, &bytes[skip..])
+}
+
+/// A tag read from the front of `input`: opening/closing marker, value and the rest.
+struct TagView<'a> {
+    opening: bool,
+    closing: bool,
+    value: &'a [u8],
+    rest: &'a [u8],
+}
+
+fn read_tag(input: &[u8]) -> Option<TagView<'_>> {
+    let first = *input.first()?;
+    let (context, lvt) = (first & 0x08 != 0, first & 0x07);
+    if context && (lvt == 6 || lvt == 7) {
+        return Some(TagView { opening: lvt == 6, closing: lvt == 7, value: &[], rest: &input[1..] });
+    }
+    let (len, header) = if lvt == 5 { (*input.get(1)? as usize, 2) } else { (lvt as usize, 1) };
+    Some(TagView {
+        opening: false,
+        closing: false,
+        value: input.get(header..header + len)?,
+        rest: &input[header + len..],
+    })
+}
+
+fn unsigned(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |value, byte| value << 8 | *byte as u32)
+}
+
+/// BVLC and NPDU around an APDU.
+fn datagram(function: u8, npdu: &[u8], apdu: &[u8]) -> Vec<u8> {
+    let len = BVLC_HEADER_BYTES + npdu.len() + apdu.len();
+    let mut message = vec![BVLC_TYPE_BACNET_IP, function, (len >> 8) as u8, len as u8];
+    message.extend_from_slice(npdu);
+    message.extend_from_slice(apdu);
+    message
+}
+
+/// The APDU of a datagram, past BVLC and NPDU.
+fn apdu(message: &[u8]) -> Option<&[u8]> {
+    if *message.first()? != BVLC_TYPE_BACNET_IP || *message.get(4)? != NPDU_VERSION {
+        return None;
+    }
+    let control = *message.get(5)?;
+    let mut at = 6;
+    if control & NPDU_DESTINATION_PRESENT != 0 {
+        let address_len = *message.get(at + 2)? as usize;
+        at += 3 + address_len;
+    }
+    if control & 0x08 != 0 {
+        let address_len = *message.get(at + 2)? as usize;
+        at += 3 + address_len;
+    }
+    if control & NPDU_DESTINATION_PRESENT != 0 {
+        at += 1; // hop count
+    }
+    message.get(at..)
+}
+
+fn who_is(range: Option<(u32, u32)>) -> Vec<u8> {
+    let mut apdu = vec![PDU_UNCONFIRMED_REQUEST, SERVICE_WHO_IS];
+    if let Some((low, high)) = range {
+        apdu.extend(context_unsigned(0, low));
+        apdu.extend(context_unsigned(1, high));
+    }
+    datagram(BVLC_ORIGINAL_BROADCAST, &NPDU_GLOBAL_BROADCAST, &apdu)
+}
+
+fn i_am(instance: u32) -> Vec<u8> {
+    let mut apdu = vec![PDU_UNCONFIRMED_REQUEST, SERVICE_I_AM];
+    apdu.extend(PropertyValue::object(OBJECT_DEVICE, instance).0);
+    apdu.extend(application_tag(TAG_UNSIGNED, &MAX_APDU_BYTES.to_be_bytes()));
+    apdu.extend(application_tag(TAG_ENUMERATED, &[SEGMENTATION_NONE]));
+    apdu.extend(application_tag(TAG_UNSIGNED, &[VENDOR_ID]));
+    datagram(BVLC_ORIGINAL_BROADCAST, &NPDU_GLOBAL_BROADCAST, &apdu)
+}
+
+fn confirmed_request(invoke_id: u8, service: u8, parameters: &[u8]) -> Vec<u8> {
+    let apdu = [&[PDU_CONFIRMED_REQUEST, MAX_APDU_ACCEPTED, invoke_id, service][..], parameters].concat();
+    datagram(BVLC_ORIGINAL_UNICAST, &[NPDU_VERSION, NPDU_EXPECTING_REPLY], &apdu)
+}
+
+fn read_property(invoke_id: u8, object: u32, property: u32) -> Vec<u8> {
+    let parameters = [context_tag(0, &object.to_be_bytes()), context_unsigned(1, property)].concat();
+    confirmed_request(invoke_id, SERVICE_READ_PROPERTY, &parameters)
+}
+
+/// ReadPropertyMultiple of the same properties on each object.
+fn read_property_multiple(invoke_id: u8, objects: &[u32], properties: &[u32]) -> Vec<u8> {
+    let mut parameters = Vec::new();
+    for object in objects {
+        parameters.extend(context_tag(0, &object.to_be_bytes()));
+        parameters.push(opening_tag(1));
+        for &property in properties {
+            parameters.extend(context_unsigned(0, property));
+        }
+        parameters.push(closing_tag(1));
+    }
+    confirmed_request(invoke_id, SERVICE_READ_PROPERTY_MULTIPLE, &parameters)
+}
+
+/// One simulated controller: a Device object and its Analog Input points.
+struct Device {
+    instance: u32,
+    properties: HashMap<(u32, u32), PropertyValue>,
+}
+
+impl Device {
+    fn new(instance: u32, points: usize) -> Self {
+        let device = object_identifier(OBJECT_DEVICE, instance);
+        let mut properties = HashMap::new();
+        properties.insert((device, PROPERTY_OBJECT_NAME), PropertyValue::text(&format!("AHU-{}", instance)));
+        properties.insert((device, PROPERTY_VENDOR_NAME), PropertyValue::text("Lab Controls"));
+        properties.insert((device, PROPERTY_MODEL_NAME), PropertyValue::text("VAV-200"));
+        properties.insert((device, PROPERTY_FIRMWARE_REVISION), PropertyValue::text("4.1.0"));
+        for point in 1..=points as u32 {
+            let object = object_identifier(OBJECT_ANALOG_INPUT, point);
+            let temperature = 19.5 + (instance * 7 + point * 3) as f32 % 60.0 / 10.0;
+            properties.insert((object, PROPERTY_OBJECT_NAME), PropertyValue::text(&format!("Zone {} Temp", point)));
+            properties.insert((object, PROPERTY_DESCRIPTION), PropertyValue::text("Zone air temperature"));
+            properties.insert((object, PROPERTY_PRESENT_VALUE), PropertyValue::real(temperature));
+            properties.insert((object, PROPERTY_STATUS_FLAGS), PropertyValue::status_flags());
+            properties.insert((object, PROPERTY_UNITS), PropertyValue::enumerated(UNITS_DEGREES_CELSIUS));
+        }
+        Self { instance, properties }
+    }
+
+    /// The reply to one datagram, or `None` when the device stays silent.
+    fn handle(&self, message: &[u8]) -> Option<Vec<u8>> {
+        let apdu = apdu(message)?;
+        match (*apdu.first()? & 0xF0, *apdu.get(1)?) {
+            (PDU_UNCONFIRMED_REQUEST, SERVICE_WHO_IS) => {
+                let range = read_tag(&apdu[2..]).and_then(|low| Some((unsigned(low.value), unsigned(read_tag(low.rest)?.value))));
+                match range {
+                    Some((low, high)) if self.instance < low || self.instance > high => None,
+                    _ => Some(i_am(self.instance)),
+                }
+            }
+            (PDU_CONFIRMED_REQUEST, _) => {
+                let (invoke_id, service) = (*apdu.get(2)?, *apdu.get(3)?);
+                let parameters = apdu.get(4..)?;
+                let ack = match service {
+                    SERVICE_READ_PROPERTY => self.read_property(parameters),
+                    SERVICE_READ_PROPERTY_MULTIPLE => self.read_property_multiple(parameters),
+                    _ => return None,
+                };
+                let apdu = match ack {
+                    Ok(body) => [&[PDU_COMPLEX_ACK, invoke_id, service][..], &body].concat(),
+                    Err((class, code)) => [
+                        vec![PDU_ERROR, invoke_id, service],
+                        application_tag(TAG_ENUMERATED, &[class]),
+                        application_tag(TAG_ENUMERATED, &[code]),
+                    ]
+                    .concat(),
+                };
+                Some(datagram(BVLC_ORIGINAL_UNICAST, &[NPDU_VERSION, 0x00], &apdu))
+            }
+            _ => None,
+        }
+    }
+
+    fn lookup(&self, object: u32, property: u32) -> Result<&PropertyValue, (u8, u8)> {
+        self.properties.get(&(object, property)).ok_or_else(|| {
+            if self.properties.keys().any(|(known, _)| *known == object) {
+                (ERROR_CLASS_PROPERTY, ERROR_UNKNOWN_PROPERTY)
+            } else {
+                (ERROR_CLASS_OBJECT, ERROR_UNKNOWN_OBJECT)
+            }
+        })
+    }
+
+    fn read_property(&self, parameters: &[u8]) -> Result<Vec<u8>, (u8, u8)> {
+        let malformed = (ERROR_CLASS_PROPERTY, ERROR_UNKNOWN_PROPERTY);
+        let object = read_tag(parameters).ok_or(malformed)?;
+        let property = read_tag(object.rest).ok_or(malformed)?;
+        let (object_id, property_id) = (unsigned(object.value), unsigned(property.value));
+        let value = self.lookup(object_id, property_id)?;
+        let mut ack = context_tag(0, &object_id.to_be_bytes());
+        ack.extend(context_unsigned(1, property_id));
+        ack.push(opening_tag(3));
+        ack.extend_from_slice(&value.0);
+        ack.push(closing_tag(3));
+        Ok(ack)
+    }
+
+    fn read_property_multiple(&self, mut parameters: &[u8]) -> Result<Vec<u8>, (u8, u8)> {
+        let malformed = (ERROR_CLASS_PROPERTY, ERROR_UNKNOWN_PROPERTY);
+        let mut ack = Vec::new();
+        while !parameters.is_empty() {
+            let object = read_tag(parameters).ok_or(malformed)?;
+            let object_id = unsigned(object.value);
+            let opening = read_tag(object.rest).ok_or(malformed)?;
+            if !opening.opening {
+                return Err(malformed);
+            }
+            ack.extend(context_tag(0, &object_id.to_be_bytes()));
+            ack.push(opening_tag(1));
+            let mut rest = opening.rest;
+            loop {
+                let tag = read_tag(rest).ok_or(malformed)?;
+                rest = tag.rest;
+                if tag.closing {
+                    break;
+                }
+                let property_id = unsigned(tag.value);
+                ack.extend(context_unsigned(2, property_id));
+                ack.push(opening_tag(4));
+                ack.extend_from_slice(&self.lookup(object_id, property_id)?.0);
+                ack.push(closing_tag(4));
+            }
+            ack.push(closing_tag(1));
+            parameters = rest;
+        }
+        Ok(ack)
+    }
+}
+
+/// Devices each on their own loopback socket; stopped when dropped.
+struct DeviceServers {
+    addrs: Vec<SocketAddr>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl DeviceServers {
+    async fn start(devices: usize, points: usize) -> Result<Self> {
+        let mut addrs = Vec::with_capacity(devices);
+        let mut tasks = Vec::with_capacity(devices);
+        for i in 0..devices as u32 {
+            let socket = UdpSocket::bind("127.0.0.1:0").await?;
+            addrs.push(socket.local_addr()?);
+            let device = Arc::new(Device::new(FIRST_DEVICE_INSTANCE + i, points));
+            tasks.push(tokio::spawn(async move {
+                let mut buffer = vec![0u8; 1500];
+                while let Ok((len, from)) = socket.recv_from(&mut buffer).await {
+                    if let Some(reply) = device.handle(&buffer[..len]) {
+                        let _ = socket.send_to(&reply, from).await;
+                    }
+                }
+            }));
+        }
+        Ok(Self { addrs, tasks })
+    }
+}
+
+impl Drop for DeviceServers {
+    fn drop(&mut self) {
+        self.tasks.iter().for_each(JoinHandle::abort);
+    }
+}
+
+/// The workstation's socket and invoke ID counter.
+struct Workstation {
+    socket: UdpSocket,
+    invoke_id: u8,
+    buffer: Vec<u8>,
+}
+
+impl Workstation {
+    async fn bind() -> Result<Self> {
+        Ok(Self { socket: UdpSocket::bind("127.0.0.1:0").await?, invoke_id: 0, buffer: vec![0u8; 1500] })
+    }
+
+    fn next_invoke_id(&mut self) -> u8 {
+        self.invoke_id = self.invoke_id.wrapping_add(1);
+        self.invoke_id
+    }
+
+    async fn receive(&mut self) -> Result<Vec<u8>> {
+        let (len, _) = tokio::time::timeout(REPLY_TIMEOUT, self.socket.recv_from(&mut self.buffer))
+            .await
+            .map_err(|_| anyhow!("no BACnet reply"))??;
+        Ok(self.buffer[..len].to_vec())
+    }
+
+    /// Sends a confirmed request and returns its ComplexACK.
+    async fn confirmed(&mut self, request: &[u8], device: SocketAddr) -> Result<Vec<u8>> {
+        self.socket.send_to(request, device).await?;
+        let reply = self.receive().await?;
+        let ack = apdu(&reply).ok_or_else(|| anyhow!("malformed BACnet reply"))?;
+        match ack.first().map(|kind| kind & 0xF0) {
+            Some(PDU_COMPLEX_ACK) if ack.get(1) == apdu(request).and_then(|apdu| apdu.get(2)) => Ok(reply),
+            Some(PDU_ERROR) => bail!("BACnet error class {:?} code {:?}", ack.get(4), ack.get(6)),
+            _ => bail!("unexpected BACnet reply"),
+        }
+    }
+}
+
+pub struct BacnetAnalyzer {
+    config: BacnetConfig,
+}
+
+impl BacnetAnalyzer {
+    pub fn new(config: BacnetConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_bacnet(&mut self) -> Result<BacnetMetrics> {
+        println!("\n🏢 Analyzing BACnet/IP Discovery and Property Reads");
+        println!("---------------------------------------------------");
+
+        let devices = self.config.devices.max(1);
+        let points = self.config.points_per_device.max(1);
+        let servers = DeviceServers::start(devices, points).await?;
+        let mut workstation = Workstation::bind().await?;
+
+        let target = FIRST_DEVICE_INSTANCE + devices as u32 / 2;
+        let mut discovery = Vec::new();
+        for range in [None, Some((target, target))] {
+            let run = self.discover(&mut workstation, &servers, range).await?;
+            println!("✅ Who-Is ({}): {} I-Am in {:.3}ms, {} B Who-Is + {} B I-Am = {} B",
+                     run.scope, run.i_am_received, run.elapsed_ms, run.who_is_bytes, run.i_am_bytes, run.total_bytes);
+            discovery.push(run);
+        }
+
+        let device = servers.addrs[0];
+        let present_value = object_identifier(OBJECT_ANALOG_INPUT, 1);
+        let mut latencies = Vec::with_capacity(self.config.iterations);
+        let mut sizes = (0, 0);
+        for _ in 0..self.config.iterations.max(1) {
+            let invoke_id = workstation.next_invoke_id();
+            let request = read_property(invoke_id, present_value, PROPERTY_PRESENT_VALUE);
+            let start = Instant::now();
+            let reply = workstation.confirmed(&request, device).await?;
+            latencies.push(start.elapsed().as_secs_f64() * 1000.0);
+            sizes = (request.len(), reply.len());
+        }
+        latencies.sort_by(|a, b| a.total_cmp(b));
+        let unicast_npdu = BVLC_HEADER_BYTES + 2;
+        let read_property = BacnetReadPropertyRun {
+            request_bytes: sizes.0 + IPV4_UDP_HEADER_BYTES,
+            response_bytes: sizes.1 + IPV4_UDP_HEADER_BYTES,
+            bvlc_npdu_bytes: unicast_npdu,
+            request_apdu_bytes: sizes.0 - unicast_npdu,
+            response_apdu_bytes: sizes.1 - unicast_npdu,
+            value_bytes: 4,
+            mean_latency_ms: latencies.iter().sum::<f64>() / latencies.len() as f64,
+            p95_latency_ms: percentile(&latencies, 95.0),
+        };
+        println!("✅ ReadProperty present-value: {} B request ({} B APDU), {} B response ({} B APDU), mean {:.3}ms, p95 {:.3}ms",
+                 read_property.request_bytes, read_property.request_apdu_bytes, read_property.response_bytes,
+                 read_property.response_apdu_bytes, read_property.mean_latency_ms, read_property.p95_latency_ms);
+
+        let mut point_reads = Vec::new();
+        for &count in &self.config.point_counts {
+            let count = count.clamp(1, points);
+            let objects: Vec<u32> = (1..=count as u32).map(|point| object_identifier(OBJECT_ANALOG_INPUT, point)).collect();
+            point_reads.push(self.time_reads(&mut workstation, device, &objects, false).await?);
+            point_reads.push(self.time_reads(&mut workstation, device, &objects, true).await?);
+            point_reads.push(matter_read(count)?);
+        }
+        for run in &point_reads {
+            println!("✅ {} point(s) with {}: {} messages, {} B ({:.1} B/point){}",
+                     run.points, run.method, run.messages, run.bytes, run.bytes_per_point,
+                     run.mean_latency_ms.map(|ms| format!(", mean {:.3}ms", ms)).unwrap_or_default());
+        }
+
+        Ok(BacnetMetrics { devices, discovery, read_property, point_reads })
+    }
+
+    async fn discover(
+        &self,
+        workstation: &mut Workstation,
+        servers: &DeviceServers,
+        range: Option<(u32, u32)>,
+    ) -> Result<BacnetDiscoveryRun> {
+        let request = who_is(range);
+        let expected = match range {
+            Some((low, high)) => (low..=high).filter(|instance| {
+                (FIRST_DEVICE_INSTANCE..FIRST_DEVICE_INSTANCE + servers.addrs.len() as u32).contains(instance)
+            }).count(),
+            None => servers.addrs.len(),
+        };
+        let start = Instant::now();
+        for addr in &servers.addrs {
+            workstation.socket.send_to(&request, *addr).await?;
+        }
+        let mut i_am_bytes = 0;
+        let mut received = 0;
+        while received < expected {
+            let reply = workstation.receive().await?;
+            if apdu(&reply).and_then(|apdu| apdu.get(..2)) == Some(&[PDU_UNCONFIRMED_REQUEST, SERVICE_I_AM][..]) {
+                received += 1;
+                i_am_bytes += reply.len() + IPV4_UDP_HEADER_BYTES;
+            }
+        }
+        let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+        let who_is_bytes = request.len() + IPV4_UDP_HEADER_BYTES;
+        Ok(BacnetDiscoveryRun {
+            scope: match range {
+                None => "global".to_string(),
+                Some((low, high)) => format!("instances {}-{}", low, high),
+            },
+            i_am_received: received,
+            elapsed_ms,
+            who_is_bytes,
+            i_am_bytes,
+            total_bytes: who_is_bytes + i_am_bytes,
+        })
+    }
+
+    /// Reads the present value of each object with one ReadProperty each, or one ReadPropertyMultiple.
+    async fn time_reads(&self, workstation: &mut Workstation, device: SocketAddr, objects: &[u32], multiple: bool) -> Result<PointReadRun> {
+        let (mut bytes, mut messages) = (0, 0);
+        let mut total = Duration::ZERO;
+        let iterations = self.config.iterations.max(1);
+        for _ in 0..iterations {
+            let (mut round_bytes, mut round_messages) = (0, 0);
+            let start = Instant::now();
+            if multiple {
+                let request = read_property_multiple(workstation.next_invoke_id(), objects, &[PROPERTY_PRESENT_VALUE]);
+                let reply = workstation.confirmed(&request, device).await?;
+                round_bytes += request.len() + reply.len() + 2 * IPV4_UDP_HEADER_BYTES;
+                round_messages += 2;
+            } else {
+                for &object in objects {
+                    let request = read_property(workstation.next_invoke_id(), object, PROPERTY_PRESENT_VALUE);
+                    let reply = workstation.confirmed(&request, device).await?;
+                    round_bytes += request.len() + reply.len() + 2 * IPV4_UDP_HEADER_BYTES;
+                    round_messages += 2;
+                }
+            }
+            total += start.elapsed();
+            (bytes, messages) = (round_bytes, round_messages);
+        }
+        Ok(PointReadRun {
+            method: if multiple { "BACnet ReadPropertyMultiple" } else { "BACnet ReadProperty" }.to_string(),
+            points: objects.len(),
+            messages,
+            bytes,
+            bytes_per_point: bytes as f64 / objects.len() as f64,
+            mean_latency_ms: Some(total.as_secs_f64() * 1000.0 / iterations as f64),
+        })
+    }
+}
+
+/// A Matter ReadRequest of MeasuredValue on `points` endpoints, its ReportData and the standalone ack.
+fn matter_read(points: usize) -> Result<PointReadRun> {
+    let paths: Vec<(Option<u16>, u32, Option<u32>)> = (1..=points as u16)
+        .map(|endpoint| (Some(endpoint), TEMPERATURE_MEASUREMENT_CLUSTER, Some(MEASURED_VALUE)))
+        .collect();
+    let request = read_request(&paths)?;
+    let mut reports = Vec::new();
+    for endpoint in 1..=points as u16 {
+        reports.extend(tlv(|writer| {
+            writer.start_struct(Tag::Anonymous)?;
+            writer.start_struct(Tag::Context(1))?; // AttributeDataIB
+            writer.u32(Tag::Context(0), 1)?; // DataVersion
+            writer.start_list(Tag::Context(1))?;
+            writer.u16(Tag::Context(2), endpoint)?;
+            writer.u32(Tag::Context(3), TEMPERATURE_MEASUREMENT_CLUSTER)?;
+            writer.u32(Tag::Context(4), MEASURED_VALUE)?;
+            writer.end_container()?;
+            writer.i16(Tag::Context(2), 2150)?; // 21.50 °C
+            writer.end_container()?;
+            writer.end_container()
+        })?);
+    }
+    let report = matter_message(PROTOCOL_IM, IM_REPORT_DATA, &report_data(&reports, false));
+    let ack = matter_message(PROTOCOL_SECURE_CHANNEL, MRP_STANDALONE_ACK, &[]);
+    let bytes = request.len() + report.len() + ack.len() + 3 * IPV4_UDP_HEADER_BYTES;
+    Ok(PointReadRun {
+        method: "Matter Read".to_string(),
+        points,
+        messages: 3,
+        bytes,
+        bytes_per_point: bytes as f64 / points as f64,
+        mean_latency_ms: None,
+    })
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((p / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn discovers_devices_and_multiple_reads_beat_single_reads() {
+        let metrics = BacnetAnalyzer::builder()
+            .devices(5usize)
+            .points_per_device(4usize)
+            .point_counts(vec![4usize])
+            .iterations(3usize)
+            .build()
+            .analyze_bacnet()
+            .await
+            .unwrap();
+
+        assert_eq!(metrics.discovery[0].i_am_received, 5);
+        assert_eq!(metrics.discovery[1].i_am_received, 1);
+        // BVLC 4 + NPDU 2, then 4 APDU header bytes, object identifier 5 and property 2
+        assert_eq!(metrics.read_property.request_bytes, 17 + IPV4_UDP_HEADER_BYTES);
+
+        let [single, multiple, matter] = &metrics.point_reads[..] else {
+            panic!("expected three point reads");
+        };
+        assert_eq!((single.messages, multiple.messages, matter.messages), (8, 2, 3));
+        assert!(multiple.bytes < single.bytes / 2);
+        assert!(matter.bytes > multiple.bytes);
+    }
+}
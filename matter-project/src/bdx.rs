@@ -0,0 +1,553 @@
+// matter-project/src/bdx.rs
+/*!
+Matter Bulk Data Exchange - init handshake, block round trips and throughput over UDP (MRP) and TCP
+*/
+
+use anyhow::{anyhow, Result};
+use bytes::BufMut;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+
+use crate::builder::analyzer_builder;
+use crate::message::{self, MessageHeader, ProtocolHeader, MIC_BYTES};
+use crate::transport_modes::{
+    frame, IPV4_TCP_HEADER_BYTES, IPV4_UDP_HEADER_BYTES, MRP_ACTIVE_RETRANS_TIMEOUT, MRP_BACKOFF_BASE,
+    MRP_MAX_TRANSMISSIONS, TCP_HANDSHAKE_BYTES, TCP_LENGTH_PREFIX_BYTES,
+};
+
+// Matter message layer
+pub(crate) const PROTOCOL_SECURE_CHANNEL: u16 = 0x0000;
+pub(crate) const PROTOCOL_BDX: u16 = 0x0002;
+pub(crate) const MATTER_HEADER_BYTES: usize = 14;
+pub(crate) const MATTER_MIC_BYTES: usize = MIC_BYTES;
+const OPCODE_OFFSET: usize = 9;
+pub(crate) const MRP_STANDALONE_ACK: u8 = 0x10;
+
+// BDX opcodes and control flags
+pub(crate) const BDX_RECEIVE_INIT: u8 = 0x04;
+pub(crate) const BDX_RECEIVE_ACCEPT: u8 = 0x05;
+pub(crate) const BDX_BLOCK_QUERY: u8 = 0x10;
+pub(crate) const BDX_BLOCK: u8 = 0x11;
+pub(crate) const BDX_BLOCK_EOF: u8 = 0x12;
+pub(crate) const BDX_BLOCK_ACK_EOF: u8 = 0x14;
+const BDX_RECEIVER_DRIVE: u8 = 0x20;
+const BDX_RANGE_DEFINITE_LENGTH: u8 = 0x01;
+const BDX_RANGE_START_OFFSET: u8 = 0x02;
+
+/// Largest block that keeps a secured BDX message inside the 1280-byte IPv6
+/// minimum MTU (40 IPv6 + 8 UDP, Matter headers/MIC, 4-byte block counter).
+pub const MAX_UDP_BLOCK_SIZE: usize = 1280 - 40 - 8 - MATTER_HEADER_BYTES - MATTER_MIC_BYTES - 4;
+
+const FILE_DESIGNATOR: &str = "bdx-test.bin";
+
+/// Secured Matter message: message header, protocol header, payload and a (zeroed) MIC.
+pub(crate) fn matter_message(protocol: u16, opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(MATTER_HEADER_BYTES + payload.len() + MATTER_MIC_BYTES);
+    matter_message_into(&mut message, protocol, opcode, payload);
+    message
+}
+
+/// [`matter_message`], appended to `out`.
+pub(crate) fn matter_message_into(out: &mut impl BufMut, protocol: u16, opcode: u8, payload: &[u8]) {
+    message::encode_into(out, &MessageHeader::secured(1, 0), &ProtocolHeader::request(protocol, opcode, 1), payload);
+}
+
+/// BDX opcode of a message, if it is one.
+pub(crate) fn bdx_opcode(message: &[u8]) -> Option<u8> {
+    if message.len() < MATTER_HEADER_BYTES + MATTER_MIC_BYTES || message[12..14] != PROTOCOL_BDX.to_le_bytes() {
+        return None;
+    }
+    Some(message[OPCODE_OFFSET])
+}
+
+fn bdx_payload(message: &[u8]) -> &[u8] {
+    &message[MATTER_HEADER_BYTES..message.len() - MATTER_MIC_BYTES]
+}
+
+/// Receiver-driven ReceiveInit, optionally resuming at `start_offset`.
+pub(crate) fn receive_init(max_block_size: u16, start_offset: Option<u32>, file_designator: &str) -> Vec<u8> {
+    let mut init = vec![BDX_RECEIVER_DRIVE, 0];
+    init.extend_from_slice(&max_block_size.to_le_bytes());
+    if let Some(offset) = start_offset {
+        init[1] |= BDX_RANGE_START_OFFSET;
+        init.extend_from_slice(&offset.to_le_bytes());
+    }
+    init.extend_from_slice(&(file_designator.len() as u16).to_le_bytes());
+    init.extend_from_slice(file_designator.as_bytes());
+    matter_message(PROTOCOL_BDX, BDX_RECEIVE_INIT, &init)
+}
+
+pub(crate) fn receive_accept(max_block_size: u16, length: u32) -> Vec<u8> {
+    let mut accept = vec![BDX_RECEIVER_DRIVE, BDX_RANGE_DEFINITE_LENGTH];
+    accept.extend_from_slice(&max_block_size.to_le_bytes());
+    accept.extend_from_slice(&length.to_le_bytes());
+    matter_message(PROTOCOL_BDX, BDX_RECEIVE_ACCEPT, &accept)
+}
+
+/// (proposed max block size, start offset) of a ReceiveInit.
+fn parse_receive_init(message: &[u8]) -> Option<(u16, u32)> {
+    let payload = bdx_payload(message);
+    let max_block_size = u16::from_le_bytes(payload.get(2..4)?.try_into().ok()?);
+    let start_offset = if payload.get(1)? & BDX_RANGE_START_OFFSET != 0 {
+        u32::from_le_bytes(payload.get(4..8)?.try_into().ok()?)
+    } else {
+        0
+    };
+    Some((max_block_size, start_offset))
+}
+
+pub(crate) fn block_query(counter: u32) -> Vec<u8> {
+    matter_message(PROTOCOL_BDX, BDX_BLOCK_QUERY, &counter.to_le_bytes())
+}
+
+pub(crate) fn block_ack_eof(counter: u32) -> Vec<u8> {
+    matter_message(PROTOCOL_BDX, BDX_BLOCK_ACK_EOF, &counter.to_le_bytes())
+}
+
+pub(crate) fn block(counter: u32, data: &[u8], eof: bool) -> Vec<u8> {
+    let mut payload = counter.to_le_bytes().to_vec();
+    payload.extend_from_slice(data);
+    matter_message(PROTOCOL_BDX, if eof { BDX_BLOCK_EOF } else { BDX_BLOCK }, &payload)
+}
+
+/// Block counter carried by a BlockQuery, Block, BlockEOF or BlockAckEOF.
+pub(crate) fn block_counter(message: &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(bdx_payload(message).get(..4)?.try_into().ok()?))
+}
+
+/// Data carried by a Block or BlockEOF.
+pub(crate) fn block_data(message: &[u8]) -> Option<&[u8]> {
+    match bdx_opcode(message)? {
+        BDX_BLOCK | BDX_BLOCK_EOF => bdx_payload(message).get(4..),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BdxConfig {
+    pub transfer_bytes: usize,
+    pub udp_block_sizes: Vec<usize>,
+    pub tcp_block_sizes: Vec<usize>,
+    /// Fraction of UDP datagrams the sender drops, to exercise MRP.
+    pub udp_loss_rate: f64,
+}
+
+impl Default for BdxConfig {
+    fn default() -> Self {
+        Self {
+            transfer_bytes: 256 * 1024,
+            udp_block_sizes: vec![256, 512, 1024, MAX_UDP_BLOCK_SIZE],
+            tcp_block_sizes: vec![1024, 4096, 16384, 65535],
+            udp_loss_rate: 0.0,
+        }
+    }
+}
+
+analyzer_builder!(BdxAnalyzerBuilder => BdxAnalyzer(BdxConfig) {
+    transfer_bytes: usize,
+    udp_block_sizes: Vec<usize>,
+    tcp_block_sizes: Vec<usize>,
+    udp_loss_rate: f64,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BdxMetrics {
+    pub transfer_bytes: usize,
+    pub udp_loss_rate: f64,
+    pub max_udp_block_size: usize,
+    pub runs: Vec<BdxRun>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BdxRun {
+    pub transport: String,
+    pub block_size: usize,
+    pub blocks: usize,
+    /// TCP connect; zero for UDP.
+    pub connection_setup_ms: f64,
+    /// ReceiveInit → ReceiveAccept.
+    pub init_handshake_ms: f64,
+    pub block_round_trip_ms: f64,
+    pub block_round_trip_p95_ms: f64,
+    pub transfer_time_ms: f64,
+    pub throughput_kbps: f64,
+    /// Bytes on the wire including IPv4 and UDP/TCP headers.
+    pub wire_bytes: usize,
+    pub overhead_ratio: f64,
+    pub retransmissions: u32,
+}
+
+pub struct BdxAnalyzer {
+    config: BdxConfig,
+}
+
+impl BdxAnalyzer {
+    pub fn new(config: BdxConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_bdx(&mut self) -> Result<BdxMetrics> {
+        println!("\n📦 Analyzing Matter Bulk Data Exchange ({} KB)", self.config.transfer_bytes / 1024);
+        println!("-----------------------------------------------");
+
+        let data: Arc<Vec<u8>> = Arc::new((0..self.config.transfer_bytes.max(1)).map(|i| (i * 31 % 251) as u8).collect());
+        let mut runs = Vec::new();
+
+        for &block_size in &self.config.udp_block_sizes {
+            if block_size == 0 || block_size > MAX_UDP_BLOCK_SIZE {
+                debug!("skipping UDP block size {} (max {})", block_size, MAX_UDP_BLOCK_SIZE);
+                continue;
+            }
+            runs.push(self.run_udp(&data, block_size).await?);
+        }
+        for &block_size in &self.config.tcp_block_sizes {
+            if block_size == 0 || block_size > u16::MAX as usize {
+                debug!("skipping TCP block size {} (BDX max {})", block_size, u16::MAX);
+                continue;
+            }
+            runs.push(self.run_tcp(&data, block_size).await?);
+        }
+
+        for run in &runs {
+            println!("✅ {} {}B blocks: init {:.2}ms, block RTT {:.3}ms, {:.0} kbps, {:.1}% overhead",
+                     run.transport, run.block_size, run.init_handshake_ms, run.block_round_trip_ms,
+                     run.throughput_kbps, run.overhead_ratio * 100.0);
+        }
+
+        Ok(BdxMetrics {
+            transfer_bytes: data.len(),
+            udp_loss_rate: self.config.udp_loss_rate,
+            max_udp_block_size: MAX_UDP_BLOCK_SIZE,
+            runs,
+        })
+    }
+
+    async fn run_udp(&self, data: &Arc<Vec<u8>>, block_size: usize) -> Result<BdxRun> {
+        let responder = UdpSocket::bind("127.0.0.1:0").await?;
+        let responder_addr = responder.local_addr()?;
+        let responder_task = tokio::spawn(udp_responder(responder, data.clone(), self.config.udp_loss_rate));
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        socket.connect(responder_addr).await?;
+        let mut link = UdpLink { socket, buffer: vec![0u8; 2048], wire_bytes: 0, retransmissions: 0 };
+
+        let result = async {
+            let init_start = Instant::now();
+            link.exchange(&receive_init(block_size as u16, None, FILE_DESIGNATOR), |m| bdx_opcode(m) == Some(BDX_RECEIVE_ACCEPT)).await?;
+            let init_handshake_ms = init_start.elapsed().as_secs_f64() * 1000.0;
+
+            let mut received = Vec::with_capacity(data.len());
+            let mut round_trips = Vec::new();
+            let transfer_start = Instant::now();
+            let mut counter = 0u32;
+            loop {
+                let start = Instant::now();
+                let response = link
+                    .exchange(&block_query(counter), |m| block_data(m).is_some() && block_counter(m) == Some(counter))
+                    .await?;
+                round_trips.push(start.elapsed().as_secs_f64() * 1000.0);
+                received.extend_from_slice(block_data(&response).unwrap_or_default());
+                if bdx_opcode(&response) == Some(BDX_BLOCK_EOF) {
+                    break;
+                }
+                counter += 1;
+            }
+            // BlockAckEOF is reliable too; the sender answers with a standalone MRP ack
+            link.exchange(&block_ack_eof(counter), |m| m.get(OPCODE_OFFSET) == Some(&MRP_STANDALONE_ACK)).await?;
+            let transfer_time = transfer_start.elapsed();
+
+            if received != **data {
+                return Err(anyhow!("UDP BDX transfer with {}B blocks corrupted the data", block_size));
+            }
+            Ok(summarize("udp_mrp", block_size, 0.0, init_handshake_ms, round_trips, transfer_time, data.len(), link.wire_bytes, link.retransmissions))
+        }
+        .await;
+
+        responder_task.abort();
+        result
+    }
+
+    async fn run_tcp(&self, data: &Arc<Vec<u8>>, block_size: usize) -> Result<BdxRun> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let server_addr = listener.local_addr()?;
+        let server = tokio::spawn(tcp_responder(listener, data.clone()));
+
+        let setup_start = Instant::now();
+        let mut stream = TcpStream::connect(server_addr).await?;
+        stream.set_nodelay(true)?;
+        let connection_setup_ms = setup_start.elapsed().as_secs_f64() * 1000.0;
+        let mut wire_bytes = TCP_HANDSHAKE_BYTES;
+
+        let result = async {
+            let init_start = Instant::now();
+            let accept = tcp_exchange(&mut stream, &receive_init(block_size as u16, None, FILE_DESIGNATOR), &mut wire_bytes).await?;
+            if bdx_opcode(&accept) != Some(BDX_RECEIVE_ACCEPT) {
+                return Err(anyhow!("expected ReceiveAccept"));
+            }
+            let init_handshake_ms = init_start.elapsed().as_secs_f64() * 1000.0;
+
+            let mut received = Vec::with_capacity(data.len());
+            let mut round_trips = Vec::new();
+            let transfer_start = Instant::now();
+            let mut counter = 0u32;
+            loop {
+                let start = Instant::now();
+                let response = tcp_exchange(&mut stream, &block_query(counter), &mut wire_bytes).await?;
+                round_trips.push(start.elapsed().as_secs_f64() * 1000.0);
+                received.extend_from_slice(block_data(&response).ok_or_else(|| anyhow!("expected Block {}", counter))?);
+                if bdx_opcode(&response) == Some(BDX_BLOCK_EOF) {
+                    break;
+                }
+                counter += 1;
+            }
+            // TCP carries reliability, so BlockAckEOF needs no MRP acknowledgement
+            let ack = frame(&block_ack_eof(counter));
+            stream.write_all(&ack).await?;
+            wire_bytes += ack.len() + IPV4_TCP_HEADER_BYTES;
+            let transfer_time = transfer_start.elapsed();
+
+            if received != **data {
+                return Err(anyhow!("TCP BDX transfer with {}B blocks corrupted the data", block_size));
+            }
+            Ok(summarize("tcp", block_size, connection_setup_ms, init_handshake_ms, round_trips, transfer_time, data.len(), wire_bytes, 0))
+        }
+        .await;
+
+        drop(stream);
+        server.abort();
+        result
+    }
+}
+
+/// Receiver side over UDP with MRP retransmission of unanswered messages.
+struct UdpLink {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+    wire_bytes: usize,
+    retransmissions: u32,
+}
+
+impl UdpLink {
+    async fn exchange(&mut self, request: &[u8], is_response: impl Fn(&[u8]) -> bool) -> Result<Vec<u8>> {
+        for attempt in 0..MRP_MAX_TRANSMISSIONS {
+            if attempt > 0 {
+                self.retransmissions += 1;
+            }
+            self.socket.send(request).await?;
+            self.wire_bytes += request.len() + IPV4_UDP_HEADER_BYTES;
+
+            let deadline = tokio::time::Instant::now() + MRP_ACTIVE_RETRANS_TIMEOUT.mul_f64(MRP_BACKOFF_BASE.powi(attempt as i32));
+            // Stale duplicates of earlier responses are discarded until the deadline
+            while let Ok(received) = tokio::time::timeout_at(deadline, self.socket.recv(&mut self.buffer)).await {
+                let len = received?;
+                let message = &self.buffer[..len];
+                if is_response(message) {
+                    self.wire_bytes += len + IPV4_UDP_HEADER_BYTES;
+                    return Ok(message.to_vec());
+                }
+            }
+        }
+        Err(anyhow!("no response after {} MRP transmissions", MRP_MAX_TRANSMISSIONS))
+    }
+}
+
+/// Answers one BDX message as the sender; the transfer state is the block size
+/// and start offset from the last ReceiveInit.
+pub(crate) struct Sender {
+    data: Arc<Vec<u8>>,
+    block_size: usize,
+    start_offset: usize,
+}
+
+impl Sender {
+    pub(crate) fn new(data: Arc<Vec<u8>>) -> Self {
+        Self { data, block_size: MAX_UDP_BLOCK_SIZE, start_offset: 0 }
+    }
+
+    pub(crate) fn respond(&mut self, message: &[u8], reliable_transport: bool) -> Option<Vec<u8>> {
+        match bdx_opcode(message)? {
+            BDX_RECEIVE_INIT => {
+                let (max_block_size, start_offset) = parse_receive_init(message)?;
+                self.block_size = max_block_size.max(1) as usize;
+                self.start_offset = (start_offset as usize).min(self.data.len());
+                Some(receive_accept(max_block_size, (self.data.len() - self.start_offset) as u32))
+            }
+            BDX_BLOCK_QUERY => {
+                let counter = block_counter(message)?;
+                let start = (self.start_offset + counter as usize * self.block_size).min(self.data.len());
+                let end = (start + self.block_size).min(self.data.len());
+                Some(block(counter, &self.data[start..end], end == self.data.len()))
+            }
+            BDX_BLOCK_ACK_EOF if !reliable_transport => Some(matter_message(PROTOCOL_SECURE_CHANNEL, MRP_STANDALONE_ACK, &[])),
+            _ => None,
+        }
+    }
+}
+
+async fn udp_responder(socket: UdpSocket, data: Arc<Vec<u8>>, loss_rate: f64) -> Result<()> {
+    let mut sender = Sender::new(data);
+    let mut buffer = vec![0u8; 2048];
+    let mut rng_state = 0x2545_F491_4F6C_DD1Du64;
+
+    loop {
+        let (len, peer) = socket.recv_from(&mut buffer).await?;
+        if let Some(response) = sender.respond(&buffer[..len], false) {
+            // Deterministic xorshift so impaired runs are repeatable
+            rng_state ^= rng_state << 13;
+            rng_state ^= rng_state >> 7;
+            rng_state ^= rng_state << 17;
+            let sample = (rng_state >> 11) as f64 / (1u64 << 53) as f64;
+            if sample < loss_rate {
+                continue;
+            }
+            socket.send_to(&response, peer).await?;
+        }
+    }
+}
+
+async fn tcp_exchange(stream: &mut TcpStream, request: &[u8], wire_bytes: &mut usize) -> Result<Vec<u8>> {
+    let framed = frame(request);
+    stream.write_all(&framed).await?;
+    *wire_bytes += framed.len() + IPV4_TCP_HEADER_BYTES;
+
+    let mut length = [0u8; TCP_LENGTH_PREFIX_BYTES];
+    stream.read_exact(&mut length).await?;
+    let mut response = vec![0u8; u32::from_le_bytes(length) as usize];
+    stream.read_exact(&mut response).await?;
+    // One IPv4/TCP header per MSS-sized segment of the response
+    *wire_bytes += TCP_LENGTH_PREFIX_BYTES + response.len() + (TCP_LENGTH_PREFIX_BYTES + response.len()).div_ceil(1460) * IPV4_TCP_HEADER_BYTES;
+    Ok(response)
+}
+
+async fn tcp_responder(listener: TcpListener, data: Arc<Vec<u8>>) -> Result<()> {
+    let (mut stream, _) = listener.accept().await?;
+    stream.set_nodelay(true)?;
+    let mut sender = Sender::new(data);
+
+    loop {
+        let mut length = [0u8; TCP_LENGTH_PREFIX_BYTES];
+        if stream.read_exact(&mut length).await.is_err() {
+            return Ok(());
+        }
+        let mut request = vec![0u8; u32::from_le_bytes(length) as usize];
+        stream.read_exact(&mut request).await?;
+
+        if let Some(response) = sender.respond(&request, true) {
+            stream.write_all(&frame(&response)).await?;
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn summarize(
+    transport: &str,
+    block_size: usize,
+    connection_setup_ms: f64,
+    init_handshake_ms: f64,
+    mut round_trips: Vec<f64>,
+    transfer_time: Duration,
+    transfer_bytes: usize,
+    wire_bytes: usize,
+    retransmissions: u32,
+) -> BdxRun {
+    round_trips.sort_by(|a, b| a.total_cmp(b));
+    let mean = round_trips.iter().sum::<f64>() / round_trips.len().max(1) as f64;
+    let p95 = round_trips
+        .get(((round_trips.len().max(1) - 1) as f64 * 0.95).round() as usize)
+        .copied()
+        .unwrap_or(0.0);
+    let transfer_time_ms = transfer_time.as_secs_f64() * 1000.0;
+
+    BdxRun {
+        transport: transport.to_string(),
+        block_size,
+        blocks: round_trips.len(),
+        connection_setup_ms,
+        init_handshake_ms,
+        block_round_trip_ms: mean,
+        block_round_trip_p95_ms: p95,
+        transfer_time_ms,
+        throughput_kbps: transfer_bytes as f64 * 8.0 / transfer_time_ms,
+        wire_bytes,
+        overhead_ratio: wire_bytes.saturating_sub(transfer_bytes) as f64 / transfer_bytes as f64,
+        retransmissions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Queries blocks from a fresh sender until BlockEOF, returning each block's opcode and data.
+    fn drain(data: Vec<u8>, block_size: u16) -> Vec<(u8, Vec<u8>)> {
+        let len = data.len() as u32;
+        let mut sender = Sender::new(Arc::new(data));
+        let accept = sender.respond(&receive_init(block_size, None, FILE_DESIGNATOR), false).unwrap();
+        assert_eq!(accept, receive_accept(block_size, len));
+
+        let mut blocks = Vec::new();
+        for counter in 0.. {
+            let response = sender.respond(&block_query(counter), false).unwrap();
+            assert_eq!(block_counter(&response), Some(counter));
+            let opcode = bdx_opcode(&response).unwrap();
+            blocks.push((opcode, block_data(&response).unwrap().to_vec()));
+            if opcode == BDX_BLOCK_EOF {
+                break;
+            }
+        }
+        blocks
+    }
+
+    #[test]
+    fn data_is_split_into_blocks_ending_with_block_eof() {
+        let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        let blocks = drain(data.clone(), 256);
+
+        let sizes: Vec<usize> = blocks.iter().map(|(_, block)| block.len()).collect();
+        assert_eq!(sizes, [256, 256, 256, 232]);
+        let opcodes: Vec<u8> = blocks.iter().map(|(opcode, _)| *opcode).collect();
+        assert_eq!(opcodes, [BDX_BLOCK, BDX_BLOCK, BDX_BLOCK, BDX_BLOCK_EOF]);
+        assert_eq!(blocks.into_iter().flat_map(|(_, block)| block).collect::<Vec<u8>>(), data);
+
+        // An exact multiple ends on a full BlockEOF rather than an empty trailing block
+        let blocks = drain(vec![7; 1024], 256);
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(blocks[3], (BDX_BLOCK_EOF, vec![7; 256]));
+
+        // A zero-length transfer is a single empty BlockEOF
+        assert_eq!(drain(Vec::new(), 256), [(BDX_BLOCK_EOF, Vec::new())]);
+    }
+
+    #[test]
+    fn resumed_transfers_start_at_the_offset_and_acks_depend_on_transport() {
+        let data: Vec<u8> = (0..100).collect();
+        let mut sender = Sender::new(Arc::new(data.clone()));
+        let accept = sender.respond(&receive_init(64, Some(40), FILE_DESIGNATOR), true).unwrap();
+        assert_eq!(accept, receive_accept(64, 60));
+        let response = sender.respond(&block_query(0), true).unwrap();
+        assert_eq!(bdx_opcode(&response), Some(BDX_BLOCK_EOF));
+        assert_eq!(block_data(&response), Some(&data[40..]));
+
+        // Only MRP needs a standalone ack for BlockAckEOF
+        assert_eq!(sender.respond(&block_ack_eof(0), true), None);
+        let ack = sender.respond(&block_ack_eof(0), false).unwrap();
+        assert_eq!(ack.get(OPCODE_OFFSET), Some(&MRP_STANDALONE_ACK));
+    }
+
+    #[tokio::test]
+    async fn loopback_transfers_count_one_round_trip_per_block() {
+        let analyzer = BdxAnalyzer::new(BdxConfig::default());
+        let data = Arc::new((0..4096).map(|i| i as u8).collect::<Vec<u8>>());
+        let empty = Arc::new(Vec::new());
+
+        assert_eq!(analyzer.run_udp(&data, 1024).await.unwrap().blocks, 4);
+        assert_eq!(analyzer.run_udp(&data, 1000).await.unwrap().blocks, 5);
+        assert_eq!(analyzer.run_tcp(&data, 4096).await.unwrap().blocks, 1);
+        assert_eq!(analyzer.run_udp(&empty, 1024).await.unwrap().blocks, 1);
+        assert_eq!(analyzer.run_tcp(&empty, 1024).await.unwrap().blocks, 1);
+    }
+}
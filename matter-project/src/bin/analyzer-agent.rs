@@ -0,0 +1,47 @@
+// matter-project/src/bin/analyzer-agent.rs
+/*!
+Lightweight measurement agent for two-host experiments.
+
+Runs on the device-side host (e.g. a Raspberry Pi) and answers the
+coordinator's clock sync, one-way probe, echo and commissioning requests.
+With `--ports-file <path>` it listens on a reserved ephemeral port instead
+of the given one and writes it to the file as `agent`, so parallel agents
+on one host do not collide.
+*/
+
+use analysis_core::ports::PortAllocator;
+use matter_research_analyzer::distributed::DistributedAgent;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+const DEFAULT_BIND: &str = "0.0.0.0:7700";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut bind = None;
+    let mut ports_file = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ports-file" => {
+                ports_file = Some(PathBuf::from(args.next().ok_or_else(|| anyhow::anyhow!("--ports-file needs a path"))?));
+            }
+            _ => bind = Some(arg),
+        }
+    }
+    let bind_addr: SocketAddr = bind.unwrap_or_else(|| DEFAULT_BIND.to_string()).parse()?;
+
+    let mut agent = DistributedAgent::new(bind_addr);
+    match ports_file {
+        Some(path) => {
+            let mut ports = PortAllocator::new(bind_addr.ip());
+            ports.reserve("agent")?;
+            let listener = ports.take_tcp("agent")?;
+            ports.write_ports_file(&path)?;
+            agent.run_on(listener).await
+        }
+        None => agent.run().await,
+    }
+}
@@ -0,0 +1,63 @@
+// matter-project/src/bin/analyzer-fixtures.rs
+/*!
+Standalone fixture servers for measurements from another host or tool.
+
+Starts the UDP echo, TCP sink, CoAP and HTTP fixtures and serves until
+Ctrl-C. Pass a bind IP to listen somewhere other than all interfaces. The
+fixtures use fixed ports unless `--ports-file <path>` is given; then they
+take reserved ephemeral ports and write them to the file, so parallel runs
+on one host do not collide.
+*/
+
+use analysis_core::ports::PortAllocator;
+use matter_research_analyzer::fixtures::{FixtureConfig, Fixtures};
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+const DEFAULT_BIND: &str = "0.0.0.0";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let mut bind = None;
+    let mut ports_file = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ports-file" => {
+                ports_file = Some(PathBuf::from(args.next().ok_or_else(|| anyhow::anyhow!("--ports-file needs a path"))?));
+            }
+            _ => bind = Some(arg),
+        }
+    }
+    let bind_ip: IpAddr = bind.unwrap_or_else(|| DEFAULT_BIND.to_string()).parse()?;
+
+    let fixtures = match &ports_file {
+        Some(path) => {
+            let mut ports = PortAllocator::new(bind_ip);
+            let fixtures = Fixtures::start_reserved(&mut ports)?;
+            ports.write_ports_file(path)?;
+            fixtures
+        }
+        None => {
+            let config = FixtureConfig {
+                bind_ip,
+                udp_echo_port: 7701,
+                tcp_sink_port: 7702,
+                coap_port: 5683,
+                http_port: 8080,
+            };
+            Fixtures::start(&config).await?
+        }
+    };
+    for fixture in fixtures.all() {
+        println!("✅ {} listening on {}", fixture.name, fixture.addr);
+    }
+    tokio::signal::ctrl_c().await?;
+
+    for fixture in fixtures.all() {
+        println!("📊 {}: {} bytes received", fixture.name, fixture.received_bytes());
+    }
+    Ok(())
+}
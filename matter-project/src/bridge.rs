@@ -0,0 +1,636 @@
+// matter-project/src/bridge.rs
+/*!
+Matter bridge discovery - Descriptor/PartsList reads and per-bridged-device overhead as the bridge grows
+*/
+
+use anyhow::{anyhow, Result};
+use metrics_core::tlv::{Tag, TlvError, TlvWriter};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+use crate::bdx::{matter_message, MATTER_HEADER_BYTES, MATTER_MIC_BYTES, MRP_STANDALONE_ACK, PROTOCOL_SECURE_CHANNEL};
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{tlv, IM_READ_REQUEST, IM_REPORT_DATA, IM_STATUS_RESPONSE, PROTOCOL_IM};
+use crate::transport_modes::IPV4_UDP_HEADER_BYTES;
+
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// ReportData payload that keeps a secured message inside the 1280-byte IPv6 minimum MTU.
+const MAX_REPORT_PAYLOAD: usize = 1280 - 40 - 8 - MATTER_HEADER_BYTES - MATTER_MIC_BYTES;
+/// Room left for AttributeReportIBs after the ReportData framing.
+const MAX_REPORT_IBS: usize = MAX_REPORT_PAYLOAD - 16;
+
+// Clusters and attributes read during discovery
+const DESCRIPTOR_CLUSTER: u32 = 0x001D;
+const DEVICE_TYPE_LIST: u32 = 0x0000;
+const SERVER_LIST: u32 = 0x0001;
+const CLIENT_LIST: u32 = 0x0002;
+const PARTS_LIST: u32 = 0x0003;
+const BRIDGED_DEVICE_BASIC_INFORMATION_CLUSTER: u32 = 0x0039;
+const VENDOR_NAME: u32 = 0x0001;
+const PRODUCT_NAME: u32 = 0x0003;
+const NODE_LABEL: u32 = 0x0005;
+const REACHABLE: u32 = 0x0011;
+const UNIQUE_ID: u32 = 0x0012;
+const ON_OFF_CLUSTER: u32 = 0x0006;
+const ON_OFF: u32 = 0x0000;
+
+// Device types
+const ROOT_NODE: (u32, u16) = (0x0016, 1);
+const AGGREGATOR: (u32, u16) = (0x000E, 1);
+const BRIDGED_NODE: (u32, u16) = (0x0013, 2);
+const ON_OFF_LIGHT: (u32, u16) = (0x0100, 3);
+
+const AGGREGATOR_ENDPOINT: u16 = 1;
+
+#[derive(Debug, Clone)]
+pub struct BridgeConfig {
+    /// Bridged endpoints behind the aggregator for each run.
+    pub endpoint_counts: Vec<usize>,
+    pub rounds: u32,
+}
+
+impl Default for BridgeConfig {
+    fn default() -> Self {
+        Self {
+            endpoint_counts: vec![1, 8, 32, 64, 128],
+            rounds: 5,
+        }
+    }
+}
+
+analyzer_builder!(BridgeAnalyzerBuilder => BridgeAnalyzer(BridgeConfig) {
+    endpoint_counts: Vec<usize>,
+    rounds: u32,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BridgeMetrics {
+    pub max_report_payload_bytes: usize,
+    pub runs: Vec<BridgeRun>,
+    /// Least-squares slope of each run metric over the bridged endpoint count.
+    pub per_bridged_device: Vec<BridgeMarginalCost>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BridgeRun {
+    pub bridged_endpoints: usize,
+    /// `wildcard` reads every endpoint's Descriptor and Bridged Device Basic
+    /// Information in one request; `per_endpoint` walks the PartsList.
+    pub strategy: String,
+    pub read_requests: usize,
+    pub report_chunks: usize,
+    /// All messages in both directions, including StatusResponses and MRP acks.
+    pub messages: usize,
+    /// Bytes on the wire including IPv4 and UDP headers.
+    pub wire_bytes: usize,
+    pub discovery_time_ms: f64,
+    pub discovery_time_p95_ms: f64,
+    /// Encoded AttributeReportIBs for the whole bridge (the device's attribute payload).
+    pub attribute_data_bytes: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BridgeMarginalCost {
+    pub strategy: String,
+    pub messages: f64,
+    pub wire_bytes: f64,
+    pub discovery_time_ms: f64,
+    pub attribute_data_bytes: f64,
+}
+
+pub struct BridgeAnalyzer {
+    config: BridgeConfig,
+}
+
+impl BridgeAnalyzer {
+    pub fn new(config: BridgeConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_bridge(&mut self) -> Result<BridgeMetrics> {
+        println!("\n🌉 Analyzing Matter Bridge Discovery ({:?} bridged endpoints)", self.config.endpoint_counts);
+        println!("------------------------------------------------------------");
+
+        let mut runs = Vec::new();
+        for &bridged in &self.config.endpoint_counts {
+            let bridge = Arc::new(BridgeModel::new(bridged)?);
+            for strategy in [Strategy::Wildcard, Strategy::PerEndpoint] {
+                let run = self.run_discovery(&bridge, strategy).await?;
+                println!("✅ {} bridged, {}: {} requests, {} chunks, {:.2}ms, {}B on the wire",
+                         run.bridged_endpoints, run.strategy, run.read_requests, run.report_chunks,
+                         run.discovery_time_ms, run.wire_bytes);
+                runs.push(run);
+            }
+        }
+
+        let per_bridged_device: Vec<BridgeMarginalCost> = [Strategy::Wildcard, Strategy::PerEndpoint]
+            .into_iter()
+            .map(|strategy| {
+                let strategy_runs: Vec<&BridgeRun> = runs.iter().filter(|r| r.strategy == strategy.name()).collect();
+                let slope = |metric: fn(&BridgeRun) -> f64| {
+                    least_squares_slope(&strategy_runs.iter().map(|r| (r.bridged_endpoints as f64, metric(r))).collect::<Vec<_>>())
+                };
+                BridgeMarginalCost {
+                    strategy: strategy.name().to_string(),
+                    messages: slope(|r| r.messages as f64),
+                    wire_bytes: slope(|r| r.wire_bytes as f64),
+                    discovery_time_ms: slope(|r| r.discovery_time_ms),
+                    attribute_data_bytes: slope(|r| r.attribute_data_bytes as f64),
+                }
+            })
+            .collect();
+        for cost in &per_bridged_device {
+            println!("📈 {} per bridged device: +{:.1} msgs, +{:.0}B, +{:.3}ms, +{:.0}B attribute data",
+                     cost.strategy, cost.messages, cost.wire_bytes, cost.discovery_time_ms, cost.attribute_data_bytes);
+        }
+
+        Ok(BridgeMetrics {
+            max_report_payload_bytes: MAX_REPORT_PAYLOAD,
+            runs,
+            per_bridged_device,
+        })
+    }
+
+    async fn run_discovery(&self, bridge: &Arc<BridgeModel>, strategy: Strategy) -> Result<BridgeRun> {
+        let responder = UdpSocket::bind("127.0.0.1:0").await?;
+        let responder_addr = responder.local_addr()?;
+        let responder_task = tokio::spawn(respond(responder, bridge.clone()));
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        socket.connect(responder_addr).await?;
+        let mut client = Client { socket, buffer: vec![0u8; 2048], counts: ExchangeCounts::default() };
+
+        let result = async {
+            let mut times = Vec::new();
+            for _ in 0..self.config.rounds.max(1) {
+                client.counts = ExchangeCounts::default();
+                let start = Instant::now();
+                match strategy {
+                    Strategy::Wildcard => {
+                        client.read(&wildcard_request()?).await?;
+                    }
+                    Strategy::PerEndpoint => {
+                        client.read(&parts_list_request()?).await?;
+                        // The root PartsList names every other endpoint
+                        for endpoint in bridge.endpoints.iter().skip(1) {
+                            client.read(&endpoint_request(endpoint.id)?).await?;
+                        }
+                    }
+                }
+                times.push(start.elapsed().as_secs_f64() * 1000.0);
+            }
+            times.sort_by(|a, b| a.total_cmp(b));
+
+            Ok(BridgeRun {
+                bridged_endpoints: bridge.bridged,
+                strategy: strategy.name().to_string(),
+                read_requests: client.counts.requests,
+                report_chunks: client.counts.chunks,
+                messages: client.counts.messages,
+                wire_bytes: client.counts.bytes + client.counts.messages * IPV4_UDP_HEADER_BYTES,
+                discovery_time_ms: times.iter().sum::<f64>() / times.len() as f64,
+                discovery_time_p95_ms: times[((times.len() - 1) as f64 * 0.95).round() as usize],
+                attribute_data_bytes: bridge.attribute_data_bytes(),
+            })
+        }
+        .await;
+
+        responder_task.abort();
+        result
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Strategy {
+    Wildcard,
+    PerEndpoint,
+}
+
+impl Strategy {
+    fn name(&self) -> &'static str {
+        match self {
+            Strategy::Wildcard => "wildcard",
+            Strategy::PerEndpoint => "per_endpoint",
+        }
+    }
+}
+
+fn least_squares_slope(points: &[(f64, f64)]) -> f64 {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let covariance: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    if variance == 0.0 {
+        0.0
+    } else {
+        covariance / variance
+    }
+}
+
+enum AttributeValue {
+    Bool(bool),
+    Utf8(String),
+    U16List(Vec<u16>),
+    U32List(Vec<u32>),
+    DeviceTypes(Vec<(u32, u16)>),
+}
+
+impl AttributeValue {
+    fn len(&self) -> usize {
+        match self {
+            AttributeValue::U16List(items) => items.len(),
+            AttributeValue::U32List(items) => items.len(),
+            AttributeValue::DeviceTypes(items) => items.len(),
+            AttributeValue::Bool(_) | AttributeValue::Utf8(_) => 1,
+        }
+    }
+
+    fn is_list(&self) -> bool {
+        !matches!(self, AttributeValue::Bool(_) | AttributeValue::Utf8(_))
+    }
+
+    /// Writes the whole value, or with `item` a single list entry.
+    fn write(&self, writer: &mut TlvWriter, tag: Tag, item: Option<usize>) -> Result<(), TlvError> {
+        let range = match item {
+            Some(index) => index..index + 1,
+            None => 0..self.len(),
+        };
+        if item.is_none() && self.is_list() {
+            writer.start_array(tag)?;
+        }
+        let entry_tag = if item.is_some() { tag } else { Tag::Anonymous };
+        match self {
+            AttributeValue::Bool(value) => writer.bool(tag, *value)?,
+            AttributeValue::Utf8(value) => writer.utf8(tag, value)?,
+            AttributeValue::U16List(items) => {
+                for value in &items[range] {
+                    writer.u16(entry_tag, *value)?;
+                }
+            }
+            AttributeValue::U32List(items) => {
+                for value in &items[range] {
+                    writer.u32(entry_tag, *value)?;
+                }
+            }
+            AttributeValue::DeviceTypes(items) => {
+                for (device_type, revision) in &items[range] {
+                    writer.start_struct(entry_tag)?;
+                    writer.u32(Tag::Context(0), *device_type)?;
+                    writer.u16(Tag::Context(1), *revision)?;
+                    writer.end_container()?;
+                }
+            }
+        }
+        if item.is_none() && self.is_list() {
+            writer.end_container()?;
+        }
+        Ok(())
+    }
+}
+
+struct Attribute {
+    cluster: u32,
+    attribute: u32,
+    value: AttributeValue,
+}
+
+struct Endpoint {
+    id: u16,
+    attributes: Vec<Attribute>,
+}
+
+/// Root node, an aggregator and `bridged` on/off lights behind it, each
+/// with the attributes a controller reads to discover them.
+struct BridgeModel {
+    bridged: usize,
+    endpoints: Vec<Endpoint>,
+    /// Prepared ReportData chunks per request the controller sends.
+    reports: HashMap<Vec<u8>, Vec<Vec<u8>>>,
+}
+
+fn descriptor(device_types: Vec<(u32, u16)>, servers: Vec<u32>, parts: Vec<u16>) -> Vec<Attribute> {
+    let attribute = |attribute, value| Attribute { cluster: DESCRIPTOR_CLUSTER, attribute, value };
+    vec![
+        attribute(DEVICE_TYPE_LIST, AttributeValue::DeviceTypes(device_types)),
+        attribute(SERVER_LIST, AttributeValue::U32List(servers)),
+        attribute(CLIENT_LIST, AttributeValue::U32List(Vec::new())),
+        attribute(PARTS_LIST, AttributeValue::U16List(parts)),
+    ]
+}
+
+impl BridgeModel {
+    fn new(bridged: usize) -> Result<Self> {
+        if bridged + 1 >= u16::MAX as usize {
+            return Err(anyhow!("{} bridged endpoints do not fit the endpoint ID space", bridged));
+        }
+        let bridged_ids: Vec<u16> = (0..bridged as u16).map(|i| AGGREGATOR_ENDPOINT + 1 + i).collect();
+
+        let mut endpoints = vec![
+            Endpoint {
+                id: 0,
+                attributes: descriptor(
+                    vec![ROOT_NODE],
+                    vec![DESCRIPTOR_CLUSTER, 0x001F, 0x0028, 0x0030, 0x0031, 0x003E],
+                    std::iter::once(AGGREGATOR_ENDPOINT).chain(bridged_ids.iter().copied()).collect(),
+                ),
+            },
+            Endpoint {
+                id: AGGREGATOR_ENDPOINT,
+                attributes: descriptor(vec![AGGREGATOR], vec![DESCRIPTOR_CLUSTER], bridged_ids.clone()),
+            },
+        ];
+        for &id in &bridged_ids {
+            let mut attributes = descriptor(
+                vec![ON_OFF_LIGHT, BRIDGED_NODE],
+                vec![DESCRIPTOR_CLUSTER, BRIDGED_DEVICE_BASIC_INFORMATION_CLUSTER, ON_OFF_CLUSTER],
+                Vec::new(),
+            );
+            let info = |attribute, value| Attribute { cluster: BRIDGED_DEVICE_BASIC_INFORMATION_CLUSTER, attribute, value };
+            attributes.extend([
+                info(VENDOR_NAME, AttributeValue::Utf8("Acme Lighting".to_string())),
+                info(PRODUCT_NAME, AttributeValue::Utf8("Bridged Light".to_string())),
+                info(NODE_LABEL, AttributeValue::Utf8(format!("Light {}", id - AGGREGATOR_ENDPOINT))),
+                info(REACHABLE, AttributeValue::Bool(true)),
+                info(UNIQUE_ID, AttributeValue::Utf8(format!("acme-light-{:08x}", id))),
+            ]);
+            attributes.push(Attribute { cluster: ON_OFF_CLUSTER, attribute: ON_OFF, value: AttributeValue::Bool(false) });
+            endpoints.push(Endpoint { id, attributes });
+        }
+
+        let mut model = Self { bridged, endpoints, reports: HashMap::new() };
+        let discovery_clusters = [DESCRIPTOR_CLUSTER, BRIDGED_DEVICE_BASIC_INFORMATION_CLUSTER];
+
+        let wildcard = model.report_chunks(|_, attribute| discovery_clusters.contains(&attribute.cluster))?;
+        model.reports.insert(wildcard_request()?, wildcard);
+        let parts = model.report_chunks(|endpoint, attribute| {
+            endpoint.id == 0 && attribute.cluster == DESCRIPTOR_CLUSTER && attribute.attribute == PARTS_LIST
+        })?;
+        model.reports.insert(parts_list_request()?, parts);
+        for id in model.endpoints.iter().skip(1).map(|e| e.id).collect::<Vec<_>>() {
+            let chunks = model.report_chunks(|endpoint, attribute| {
+                endpoint.id == id && discovery_clusters.contains(&attribute.cluster)
+            })?;
+            model.reports.insert(endpoint_request(id)?, chunks);
+        }
+        Ok(model)
+    }
+
+    fn attribute_data_bytes(&self) -> usize {
+        self.endpoints
+            .iter()
+            .flat_map(|endpoint| endpoint.attributes.iter().map(move |attribute| (endpoint.id, attribute)))
+            .map(|(id, attribute)| attribute_report_ibs(id, attribute).map(|ibs| ibs.iter().map(Vec::len).sum()).unwrap_or(0))
+            .sum()
+    }
+
+    /// Packs the matching attributes into ReportData messages no larger than the MTU allows.
+    fn report_chunks(&self, matches: impl Fn(&Endpoint, &Attribute) -> bool) -> Result<Vec<Vec<u8>>> {
+        let mut ibs = Vec::new();
+        for endpoint in &self.endpoints {
+            for attribute in endpoint.attributes.iter().filter(|a| matches(endpoint, a)) {
+                ibs.extend(attribute_report_ibs(endpoint.id, attribute)?);
+            }
+        }
+
+        let mut groups: Vec<Vec<u8>> = vec![Vec::new()];
+        for ib in ibs {
+            let current = groups.last_mut().expect("at least one group");
+            if !current.is_empty() && current.len() + ib.len() > MAX_REPORT_IBS {
+                groups.push(ib);
+            } else {
+                current.extend_from_slice(&ib);
+            }
+        }
+
+        let last = groups.len() - 1;
+        Ok(groups
+            .iter()
+            .enumerate()
+            .map(|(i, group)| matter_message(PROTOCOL_IM, IM_REPORT_DATA, &report_data(group, i < last)))
+            .collect())
+    }
+}
+
+/// AttributeReportIBs for one attribute. Lists too long for one message are
+/// sent as an empty list followed by one append (ListIndex null) per entry.
+fn attribute_report_ibs(endpoint: u16, attribute: &Attribute) -> Result<Vec<Vec<u8>>> {
+    match attribute_report_ib(endpoint, attribute, None) {
+        Ok(ib) => return Ok(vec![ib]),
+        Err(TlvError::BufferFull) if attribute.value.is_list() => {}
+        Err(e) => return Err(anyhow!("TLV encoding failed: {:?}", e)),
+    }
+
+    let empty = AttributeValue::U16List(Vec::new());
+    let mut ibs = vec![attribute_report_ib(endpoint, &Attribute { value: empty, ..*attribute }, None)
+        .map_err(|e| anyhow!("TLV encoding failed: {:?}", e))?];
+    for index in 0..attribute.value.len() {
+        ibs.push(attribute_report_ib(endpoint, attribute, Some(index)).map_err(|e| anyhow!("TLV encoding failed: {:?}", e))?);
+    }
+    Ok(ibs)
+}
+
+fn attribute_report_ib(endpoint: u16, attribute: &Attribute, item: Option<usize>) -> Result<Vec<u8>, TlvError> {
+    let mut buffer = [0u8; MAX_REPORT_IBS];
+    let mut writer = TlvWriter::new(&mut buffer);
+    writer.start_struct(Tag::Anonymous)?;
+    writer.start_struct(Tag::Context(1))?; // AttributeDataIB
+    writer.u32(Tag::Context(0), 1)?; // DataVersion
+    writer.start_list(Tag::Context(1))?;
+    writer.u16(Tag::Context(2), endpoint)?;
+    writer.u32(Tag::Context(3), attribute.cluster)?;
+    writer.u32(Tag::Context(4), attribute.attribute)?;
+    if item.is_some() {
+        writer.null(Tag::Context(5))?; // ListIndex: append
+    }
+    writer.end_container()?;
+    attribute.value.write(&mut writer, Tag::Context(2), item)?;
+    writer.end_container()?;
+    writer.end_container()?;
+    Ok(writer.as_bytes().to_vec())
+}
+
+/// ReportData around pre-encoded AttributeReportIBs. The last chunk suppresses
+/// its StatusResponse; earlier ones set MoreChunkedMessages.
+pub(crate) fn report_data(ibs: &[u8], more_chunks: bool) -> Vec<u8> {
+    let mut payload = vec![0x15];
+    if more_chunks {
+        payload.extend_from_slice(&[0x29, 0x03]); // MoreChunkedMessages = true
+    }
+    payload.extend_from_slice(&[0x36, 0x01]); // AttributeReportIBs array
+    payload.extend_from_slice(ibs);
+    payload.push(0x18);
+    if !more_chunks {
+        payload.extend_from_slice(&[0x29, 0x04]); // SuppressResponse = true
+    }
+    payload.extend_from_slice(&[0x24, 0xFF, 0x0B, 0x18]); // InteractionModelRevision
+    payload
+}
+
+pub(crate) fn more_chunks(report: &[u8]) -> bool {
+    report.get(MATTER_HEADER_BYTES + 1..MATTER_HEADER_BYTES + 3) == Some(&[0x29, 0x03][..])
+}
+
+/// ReadRequest for the given (endpoint, cluster, attribute) paths; `None` is a wildcard.
+pub(crate) fn read_request(paths: &[(Option<u16>, u32, Option<u32>)]) -> Result<Vec<u8>> {
+    let payload = tlv(|writer| {
+        writer.start_struct(Tag::Anonymous)?;
+        writer.start_array(Tag::Context(0))?;
+        for (endpoint, cluster, attribute) in paths {
+            writer.start_list(Tag::Anonymous)?;
+            if let Some(endpoint) = endpoint {
+                writer.u16(Tag::Context(2), *endpoint)?;
+            }
+            writer.u32(Tag::Context(3), *cluster)?;
+            if let Some(attribute) = attribute {
+                writer.u32(Tag::Context(4), *attribute)?;
+            }
+            writer.end_container()?;
+        }
+        writer.end_container()?;
+        writer.bool(Tag::Context(3), true)?; // FabricFiltered
+        writer.u8(Tag::Context(255), 11)?;
+        writer.end_container()
+    })?;
+    Ok(matter_message(PROTOCOL_IM, IM_READ_REQUEST, &payload))
+}
+
+fn wildcard_request() -> Result<Vec<u8>> {
+    read_request(&[(None, DESCRIPTOR_CLUSTER, None), (None, BRIDGED_DEVICE_BASIC_INFORMATION_CLUSTER, None)])
+}
+
+fn parts_list_request() -> Result<Vec<u8>> {
+    read_request(&[(Some(0), DESCRIPTOR_CLUSTER, Some(PARTS_LIST))])
+}
+
+fn endpoint_request(endpoint: u16) -> Result<Vec<u8>> {
+    read_request(&[
+        (Some(endpoint), DESCRIPTOR_CLUSTER, None),
+        (Some(endpoint), BRIDGED_DEVICE_BASIC_INFORMATION_CLUSTER, None),
+    ])
+}
+
+fn status_response() -> Vec<u8> {
+    matter_message(PROTOCOL_IM, IM_STATUS_RESPONSE, &[0x15, 0x24, 0x00, 0x00, 0x24, 0xFF, 0x0B, 0x18])
+}
+
+pub(crate) fn is_status_response(message: &[u8]) -> bool {
+    message.get(9) == Some(&IM_STATUS_RESPONSE) && message.get(12..14) == Some(&PROTOCOL_IM.to_le_bytes()[..])
+}
+
+/// Every message of a discovery against a bridge with `bridged` endpoints, in a stable order.
+pub(crate) fn discovery_messages(bridged: usize) -> Result<Vec<Vec<u8>>> {
+    let model = BridgeModel::new(bridged)?;
+    let mut messages = vec![status_response()];
+    for (request, chunks) in model.reports {
+        messages.push(request);
+        messages.extend(chunks);
+    }
+    messages.sort();
+    Ok(messages)
+}
+
+/// Answers ReadRequests from the prepared reports, sending each further chunk
+/// once the previous one has been acknowledged with a StatusResponse.
+async fn respond(socket: UdpSocket, bridge: Arc<BridgeModel>) -> Result<()> {
+    let mut buffer = vec![0u8; 2048];
+    let mut pending: VecDeque<Vec<u8>> = VecDeque::new();
+
+    loop {
+        let (len, peer) = socket.recv_from(&mut buffer).await?;
+        let message = &buffer[..len];
+        if let Some(chunks) = bridge.reports.get(message) {
+            pending = chunks.iter().cloned().collect();
+        } else if !is_status_response(message) {
+            continue;
+        }
+        if let Some(chunk) = pending.pop_front() {
+            socket.send_to(&chunk, peer).await?;
+        }
+    }
+}
+
+#[derive(Default)]
+struct ExchangeCounts {
+    requests: usize,
+    chunks: usize,
+    messages: usize,
+    bytes: usize,
+}
+
+struct Client {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+    counts: ExchangeCounts,
+}
+
+impl Client {
+    async fn send(&mut self, message: &[u8]) -> Result<()> {
+        self.socket.send(message).await?;
+        self.counts.messages += 1;
+        self.counts.bytes += message.len();
+        Ok(())
+    }
+
+    /// One read interaction: request, then chunks until the last one, which gets an MRP ack.
+    async fn read(&mut self, request: &[u8]) -> Result<()> {
+        self.counts.requests += 1;
+        self.send(request).await?;
+        loop {
+            let len = timeout(RECEIVE_TIMEOUT, self.socket.recv(&mut self.buffer)).await??;
+            self.counts.messages += 1;
+            self.counts.bytes += len;
+            self.counts.chunks += 1;
+            if more_chunks(&self.buffer[..len]) {
+                self.send(&status_response()).await?;
+            } else {
+                return self.send(&matter_message(PROTOCOL_SECURE_CHANNEL, MRP_STANDALONE_ACK, &[])).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marginal_cost_is_the_least_squares_slope() {
+        let line: Vec<(f64, f64)> = [1.0, 4.0, 16.0, 64.0].iter().map(|x| (*x, 2.5 * x + 100.0)).collect();
+        assert!((least_squares_slope(&line) - 2.5).abs() < 1e-9);
+        assert_eq!(least_squares_slope(&[(8.0, 1.0), (8.0, 3.0)]), 0.0);
+    }
+
+    #[test]
+    fn large_bridges_chunk_reports_within_the_mtu() {
+        let bridge = BridgeModel::new(500).unwrap();
+        let wildcard = &bridge.reports[&wildcard_request().unwrap()];
+        assert!(wildcard.len() > 1);
+        for (i, chunk) in wildcard.iter().enumerate() {
+            assert!(chunk.len() + 40 + 8 <= 1280, "chunk {} is {} bytes", i, chunk.len());
+            assert_eq!(more_chunks(chunk), i < wildcard.len() - 1);
+        }
+
+        // 501 PartsList entries do not fit one IB: an empty list, then one append per entry
+        let parts_list = &bridge.endpoints[0].attributes[3];
+        assert_eq!(attribute_report_ibs(0, parts_list).unwrap().len(), 1 + 501);
+        let small = BridgeModel::new(2).unwrap();
+        assert_eq!(attribute_report_ibs(0, &small.endpoints[0].attributes[3]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn every_bridged_endpoint_can_be_read_on_its_own() {
+        let bridge = BridgeModel::new(3).unwrap();
+        // Wildcard, PartsList and one per endpoint except the root
+        assert_eq!(bridge.reports.len(), 2 + 4);
+        assert!(bridge.reports.contains_key(&endpoint_request(AGGREGATOR_ENDPOINT + 3).unwrap()));
+        assert!(is_status_response(&status_response()));
+        assert!(!is_status_response(&parts_list_request().unwrap()));
+        assert!(bridge.attribute_data_bytes() > BridgeModel::new(2).unwrap().attribute_data_bytes());
+    }
+}
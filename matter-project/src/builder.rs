@@ -0,0 +1,67 @@
+// matter-project/src/builder.rs
+/*!
+Analyzer builders - `BdxAnalyzer::builder().transfer_bytes(64 * 1024).udp_loss_rate(0.05).build()`
+
+Every analyzer takes a plain `...Config` struct with public fields and a
+`Default`. `analyzer_builder!` gives the analyzer a `builder()` that starts
+from those defaults and has one setter per config field, so library users
+can change the settings they care about without spelling out the whole
+config.
+*/
+
+/// `analyzer_builder!(Builder => Analyzer(Config) { field: Type, ... })` generates the builder
+/// and `Analyzer::builder()`. Arguments of `new` after the config become arguments of `build`.
+macro_rules! analyzer_builder {
+    ($builder:ident => $analyzer:ident($config:ty $(, $arg:ident: $arg_ty:ty)*) { $($field:ident: $ty:ty),* $(,)? }) => {
+        #[doc = concat!("Builds a [`", stringify!($analyzer), "`], starting from the [`", stringify!($config), "`] defaults.")]
+        #[derive(Debug, Clone, Default)]
+        pub struct $builder {
+            config: $config,
+        }
+
+        impl $builder {
+            $(
+                pub fn $field(mut self, $field: impl Into<$ty>) -> Self {
+                    self.config.$field = $field.into();
+                    self
+                }
+            )*
+
+            /// The configuration the analyzer will be built with.
+            pub fn config(&self) -> &$config {
+                &self.config
+            }
+
+            pub fn build(self $(, $arg: $arg_ty)*) -> $analyzer {
+                $analyzer::new(self.config $(, $arg)*)
+            }
+        }
+
+        impl $analyzer {
+            pub fn builder() -> $builder {
+                $builder::default()
+            }
+        }
+    };
+}
+
+pub(crate) use analyzer_builder;
+
+#[cfg(test)]
+mod tests {
+    use crate::bdx::{BdxAnalyzer, BdxConfig};
+    use crate::icd::IcdAnalyzer;
+    use std::time::Duration;
+
+    #[test]
+    fn builders_start_from_defaults() {
+        let builder = BdxAnalyzer::builder().transfer_bytes(64usize * 1024).udp_block_sizes([512usize]);
+        assert_eq!(builder.config().transfer_bytes, 64 * 1024);
+        assert_eq!(builder.config().udp_block_sizes, vec![512]);
+        assert_eq!(builder.config().tcp_block_sizes, BdxConfig::default().tcp_block_sizes);
+
+        let builder = IcdAnalyzer::builder().awake_window(Duration::from_millis(250)).loss_rate(0.1);
+        assert_eq!(builder.config().awake_window, Duration::from_millis(250));
+        assert_eq!(builder.config().loss_rate, 0.1);
+    }
+}
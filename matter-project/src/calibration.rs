@@ -0,0 +1,207 @@
+// matter-project/src/calibration.rs
+/*!
+Timing harness calibration - measures the analyzer's own measurement overhead
+*/
+
+use serde::{Deserialize, Serialize};
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+use crate::builder::analyzer_builder;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalibrationMetrics {
+    pub timer_resolution_ns: f64,
+    pub instant_overhead_ns: f64,
+    pub async_scheduling_latency_us: f64,
+    pub syscall_overhead_us: f64,
+    pub measurement_overhead_ms: f64,
+    pub noise_floor_ms: f64,
+    pub samples: u32,
+}
+
+impl CalibrationMetrics {
+    /// Subtracts the harness overhead from a raw measurement, clamping at zero.
+    pub fn correct_ms(&self, measured_ms: f64) -> f64 {
+        (measured_ms - self.measurement_overhead_ms).max(0.0)
+    }
+
+    /// True when a figure is too small to be distinguished from harness noise.
+    pub fn is_below_noise_floor(&self, measured_ms: f64) -> bool {
+        measured_ms < self.noise_floor_ms
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CalibrationConfig {
+    /// Timer reads and empty measurements taken for each estimate.
+    pub samples: u32,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        Self { samples: 1000 }
+    }
+}
+
+analyzer_builder!(TimingCalibratorBuilder => TimingCalibrator(CalibrationConfig) {
+    samples: u32,
+});
+
+pub struct TimingCalibrator {
+    samples: u32,
+}
+
+impl Default for TimingCalibrator {
+    fn default() -> Self {
+        Self::new(CalibrationConfig::default())
+    }
+}
+
+impl TimingCalibrator {
+    pub fn new(config: CalibrationConfig) -> Self {
+        Self { samples: config.samples.max(1) }
+    }
+
+    pub fn with_samples(samples: u32) -> Self {
+        Self::new(CalibrationConfig { samples })
+    }
+
+    pub async fn calibrate(&mut self) -> anyhow::Result<CalibrationMetrics> {
+        println!("\n⏱️ Calibrating measurement harness");
+        println!("----------------------------------");
+
+        let timer_resolution_ns = self.measure_timer_resolution();
+        let instant_overhead_ns = self.measure_instant_overhead();
+        let async_scheduling_latency_us = self.measure_async_scheduling_latency().await;
+        let syscall_overhead_us = self.measure_syscall_overhead()?;
+
+        // A single timed phase pays for two Instant reads and one scheduler hop
+        let measurement_overhead_ms =
+            (2.0 * instant_overhead_ns) / 1_000_000.0 + async_scheduling_latency_us / 1000.0;
+
+        // Anything within a few multiples of the overhead/resolution is indistinguishable from noise
+        let noise_floor_ms = (measurement_overhead_ms + timer_resolution_ns / 1_000_000.0) * 3.0;
+
+        let metrics = CalibrationMetrics {
+            timer_resolution_ns,
+            instant_overhead_ns,
+            async_scheduling_latency_us,
+            syscall_overhead_us,
+            measurement_overhead_ms,
+            noise_floor_ms,
+            samples: self.samples,
+        };
+
+        println!("✅ Timer Resolution: {:.0}ns", metrics.timer_resolution_ns);
+        println!("✅ Instant Overhead: {:.0}ns", metrics.instant_overhead_ns);
+        println!("✅ Async Scheduling Latency: {:.2}µs", metrics.async_scheduling_latency_us);
+        println!("✅ Syscall Overhead: {:.2}µs", metrics.syscall_overhead_us);
+        println!("✅ Noise Floor: {:.4}ms", metrics.noise_floor_ms);
+
+        Ok(metrics)
+    }
+
+    fn measure_timer_resolution(&self) -> f64 {
+        // Smallest non-zero step observed between consecutive clock reads
+        let mut smallest = Duration::MAX;
+
+        for _ in 0..self.samples {
+            let first = Instant::now();
+            let mut next = Instant::now();
+            while next == first {
+                next = Instant::now();
+            }
+            smallest = smallest.min(next - first);
+        }
+
+        smallest.as_nanos() as f64
+    }
+
+    fn measure_instant_overhead(&self) -> f64 {
+        let start = Instant::now();
+        for _ in 0..self.samples {
+            std::hint::black_box(Instant::now());
+        }
+
+        start.elapsed().as_nanos() as f64 / self.samples as f64
+    }
+
+    async fn measure_async_scheduling_latency(&self) -> f64 {
+        let mut latencies: Vec<f64> = Vec::with_capacity(self.samples as usize);
+
+        for _ in 0..self.samples {
+            let start = Instant::now();
+            tokio::task::yield_now().await;
+            latencies.push(start.elapsed().as_nanos() as f64 / 1000.0);
+        }
+
+        median(&mut latencies)
+    }
+
+    fn measure_syscall_overhead(&self) -> anyhow::Result<f64> {
+        // getsockname() is about the cheapest syscall reachable through std
+        let socket = UdpSocket::bind("127.0.0.1:0")?;
+        let mut latencies: Vec<f64> = Vec::with_capacity(self.samples as usize);
+
+        for _ in 0..self.samples {
+            let start = Instant::now();
+            std::hint::black_box(socket.local_addr()?);
+            latencies.push(start.elapsed().as_nanos() as f64 / 1000.0);
+        }
+
+        Ok(median(&mut latencies))
+    }
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+
+    values.sort_by(|a, b| a.total_cmp(b));
+    values[values.len() / 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(measurement_overhead_ms: f64, noise_floor_ms: f64) -> CalibrationMetrics {
+        CalibrationMetrics {
+            timer_resolution_ns: 0.0,
+            instant_overhead_ns: 0.0,
+            async_scheduling_latency_us: 0.0,
+            syscall_overhead_us: 0.0,
+            measurement_overhead_ms,
+            noise_floor_ms,
+            samples: 1,
+        }
+    }
+
+    #[test]
+    fn corrections_subtract_the_overhead_and_clamp_at_zero() {
+        let calibration = metrics(0.25, 1.0);
+        assert_eq!(calibration.correct_ms(2.0), 1.75);
+        assert_eq!(calibration.correct_ms(0.1), 0.0);
+        assert!(calibration.is_below_noise_floor(0.99));
+        assert!(!calibration.is_below_noise_floor(1.0));
+    }
+
+    #[test]
+    fn median_takes_the_upper_middle_value() {
+        assert_eq!(median(&mut []), 0.0);
+        assert_eq!(median(&mut [7.0]), 7.0);
+        assert_eq!(median(&mut [9.0, 1.0, 5.0]), 5.0);
+        assert_eq!(median(&mut [4.0, 1.0, 3.0, 2.0]), 3.0);
+    }
+
+    #[tokio::test]
+    async fn noise_floor_covers_the_measurement_overhead() {
+        let calibration = TimingCalibrator::with_samples(50).calibrate().await.unwrap();
+        assert_eq!(calibration.samples, 50);
+        assert!(calibration.timer_resolution_ns > 0.0);
+        assert!(calibration.noise_floor_ms >= 3.0 * calibration.measurement_overhead_ms);
+        assert!(!calibration.is_below_noise_floor(calibration.noise_floor_ms));
+    }
+}
@@ -0,0 +1,536 @@
+// matter-project/src/cellular.rs
+/*!
+NB-IoT and LTE-M link model - RRC state costs, PSM/eDRX sleep and per-message radio energy
+
+On cellular IoT the radio state machine costs more than the bytes do. A
+device in RRC idle must do random access and RRC connection setup before
+it can send anything. It then stays connected (in connected-mode DRX)
+until the network's inactivity timer releases it, unless the device sends
+a Release Assistance Indication (RAI) with its last message. Once idle it
+listens for paging on an eDRX cycle for the PSM active timer (T3324). It
+then enters power saving mode, where it is unreachable until it next has
+something to send.
+
+Each protocol's message pattern is laid on that timeline for one sensor
+report per interval:
+
+- CoAP NON: one uplink datagram.
+- LwM2M Notify: a confirmable notification and its ACK.
+- MQTT reconnecting: TCP handshake, CONNECT, PUBLISH QoS 1, DISCONNECT and
+  teardown at every report.
+- MQTT persistent: PUBLISH and PUBACK on a kept connection, plus a PINGREQ
+  every keep-alive so the carrier NAT does not drop it.
+- Matter subscription: ReportData, StatusResponse and ack on a kept CASE
+  session, plus an empty liveness report every max interval.
+
+A response waits one round trip through the core network and the
+internet. Every message pays the uplink or downlink scheduling delay and
+its airtime at the RAT's rate. Energy is the time spent in each state at
+that state's power. The RAT figures are representative of NB-IoT and Cat-M1
+modules at 3.6 V, not of one datasheet. IP headers are counted without
+ROHC, and TLS/DTLS is left out. The model is deterministic.
+*/
+
+use anyhow::Result;
+use metrics_core::tlv::Tag;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::bdx::{matter_message, MRP_STANDALONE_ACK, PROTOCOL_SECURE_CHANNEL};
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{
+    coap_message, coap_uint, mqtt_packet, mqtt_publish, mqtt_string, tlv, uri_path, COAP_ACK, COAP_CON, COAP_CONTENT,
+    COAP_NON, COAP_OPTION_CONTENT_FORMAT, COAP_OPTION_OBSERVE, COAP_PUT, IM_REPORT_DATA, IM_STATUS_RESPONSE,
+    MQTT_CONNACK, MQTT_CONNECT, MQTT_DISCONNECT, MQTT_PUBACK, PROTOCOL_IM,
+};
+use crate::transport_modes::{IPV4_TCP_HEADER_BYTES, IPV4_UDP_HEADER_BYTES};
+
+/// Core network and internet round trip between the device's answer-seeking message and its reply.
+const SERVER_ROUND_TRIP_MS: f64 = 80.0;
+const BATTERY_VOLTS: f64 = 3.6;
+/// Size of the message used for the per-message radio cost of each RAT.
+const REFERENCE_MESSAGE_BYTES: usize = 100;
+const MQTT_PINGREQ: u8 = 0xC0;
+const MQTT_PINGRESP: u8 = 0xD0;
+/// SenML+CBOR
+const CONTENT_FORMAT_SENML_CBOR: usize = 112;
+
+/// Radio timings and state powers of one cellular IoT RAT.
+#[derive(Debug, Clone, Copy)]
+struct Rat {
+    name: &'static str,
+    /// Random access, RRC connection setup and service request.
+    rrc_setup_ms: f64,
+    rrc_release_ms: f64,
+    uplink_kbit: f64,
+    downlink_kbit: f64,
+    /// Scheduling request to uplink grant, and downlink assignment delay, in connected mode.
+    uplink_scheduling_ms: f64,
+    downlink_scheduling_ms: f64,
+    tx_mw: f64,
+    rx_mw: f64,
+    /// Connected-mode DRX while waiting for the inactivity timer.
+    connected_mw: f64,
+    /// Idle with eDRX paging occasions, averaged.
+    edrx_mw: f64,
+    psm_mw: f64,
+}
+
+const NB_IOT: Rat = Rat {
+    name: "NB-IoT",
+    rrc_setup_ms: 600.0,
+    rrc_release_ms: 50.0,
+    uplink_kbit: 20.0,
+    downlink_kbit: 25.0,
+    uplink_scheduling_ms: 150.0,
+    downlink_scheduling_ms: 100.0,
+    tx_mw: 750.0,
+    rx_mw: 200.0,
+    connected_mw: 30.0,
+    edrx_mw: 1.0,
+    psm_mw: 0.015,
+};
+
+const LTE_M: Rat = Rat {
+    name: "LTE-M",
+    rrc_setup_ms: 150.0,
+    rrc_release_ms: 20.0,
+    uplink_kbit: 300.0,
+    downlink_kbit: 300.0,
+    uplink_scheduling_ms: 20.0,
+    downlink_scheduling_ms: 10.0,
+    tx_mw: 650.0,
+    rx_mw: 250.0,
+    connected_mw: 40.0,
+    edrx_mw: 1.2,
+    psm_mw: 0.02,
+};
+
+impl Rat {
+    /// Airtime and energy of one message, scheduling included.
+    fn message(&self, message: &Message) -> (f64, f64) {
+        let (scheduling, rate, power) = match message.direction {
+            Direction::Up => (self.uplink_scheduling_ms, self.uplink_kbit, self.tx_mw),
+            Direction::Down => (self.downlink_scheduling_ms, self.downlink_kbit, self.rx_mw),
+        };
+        // kbit/s is bits per millisecond
+        let airtime = message.bytes as f64 * 8.0 / rate;
+        (scheduling + airtime, (scheduling * self.rx_mw + airtime * power) / 1000.0)
+    }
+
+    fn setup_mj(&self) -> f64 {
+        // Random access and setup alternate transmitting and listening
+        self.rrc_setup_ms * (self.tx_mw + self.rx_mw) / 2.0 / 1000.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Release {
+    /// The network releases the connection after the inactivity timer.
+    InactivityTimer,
+    /// The device's last message carries a Release Assistance Indication.
+    Rai,
+}
+
+#[derive(Debug, Clone)]
+pub struct CellularConfig {
+    /// Time between sensor reports.
+    pub report_intervals: Vec<Duration>,
+    pub payload_bytes: usize,
+    pub inactivity_timer: Duration,
+    /// T3324: how long the device stays pageable in idle before PSM.
+    pub psm_active_timer: Duration,
+    pub edrx_cycle: Duration,
+    /// MQTT keep-alive, short enough to hold a carrier NAT binding.
+    pub mqtt_keep_alive: Duration,
+    /// Max interval of the Matter subscription, after which an empty report is due.
+    pub subscription_max_interval: Duration,
+    pub battery_mah: f64,
+}
+
+impl Default for CellularConfig {
+    fn default() -> Self {
+        Self {
+            report_intervals: vec![Duration::from_secs(60), Duration::from_secs(900), Duration::from_secs(3600)],
+            payload_bytes: 16,
+            inactivity_timer: Duration::from_secs(20),
+            psm_active_timer: Duration::from_secs(30),
+            edrx_cycle: Duration::from_millis(20_480),
+            mqtt_keep_alive: Duration::from_secs(300),
+            subscription_max_interval: Duration::from_secs(1800),
+            battery_mah: 2600.0,
+        }
+    }
+}
+
+analyzer_builder!(CellularAnalyzerBuilder => CellularAnalyzer(CellularConfig) {
+    report_intervals: Vec<Duration>,
+    payload_bytes: usize,
+    inactivity_timer: Duration,
+    psm_active_timer: Duration,
+    edrx_cycle: Duration,
+    mqtt_keep_alive: Duration,
+    subscription_max_interval: Duration,
+    battery_mah: f64,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CellularMetrics {
+    pub rats: Vec<RatProfile>,
+    pub runs: Vec<CellularRun>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RatProfile {
+    pub rat: String,
+    pub rrc_setup_ms: f64,
+    pub rrc_setup_mj: f64,
+    /// A 100-byte uplink message in connected mode, scheduling included.
+    pub uplink_message_ms: f64,
+    pub uplink_message_mj: f64,
+    pub uplink_uj_per_byte: f64,
+    pub downlink_uj_per_byte: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CellularRun {
+    pub rat: String,
+    pub release: String,
+    pub protocol: String,
+    pub report_interval_s: f64,
+    /// Messages per report interval, keep-alives included.
+    pub messages: usize,
+    /// Uplink and downlink bytes per report interval, keep-alives included.
+    pub bytes: usize,
+    /// RRC connection setups per report interval.
+    pub rrc_setups: usize,
+    /// From the sensor reading to the end of the report's exchange, RRC setup included.
+    pub report_latency_ms: f64,
+    /// All radio energy over one report interval, sleep included.
+    pub energy_per_report_mj: f64,
+    pub average_power_mw: f64,
+    pub battery_years: f64,
+    /// Mean wait of a downlink command arriving at a random time.
+    pub downlink_latency_s: f64,
+    /// Share of time the device is connected or pageable.
+    pub reachable_fraction: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Message {
+    direction: Direction,
+    /// IP packet size.
+    bytes: usize,
+    /// Sent in reply to the peer's previous message, one server round trip later.
+    reply: bool,
+}
+
+fn up(bytes: usize) -> Message {
+    Message { direction: Direction::Up, bytes, reply: false }
+}
+
+fn down(bytes: usize) -> Message {
+    Message { direction: Direction::Down, bytes, reply: true }
+}
+
+/// A protocol's messages for one report, and for its keep-alive if it holds state in the network.
+struct Pattern {
+    name: &'static str,
+    report: Vec<Message>,
+    keep_alive: Option<(Duration, Vec<Message>)>,
+}
+
+/// Totals of one report interval in steady state.
+#[derive(Debug, Default)]
+struct Cycle {
+    energy_mj: f64,
+    messages: usize,
+    bytes: usize,
+    rrc_setups: usize,
+    report_latency_ms: f64,
+    /// Sum over the cycle of (state duration × mean downlink wait in it), and the time reachable.
+    downlink_wait_ms2: f64,
+    reachable_ms: f64,
+}
+
+pub struct CellularAnalyzer {
+    config: CellularConfig,
+}
+
+impl CellularAnalyzer {
+    pub fn new(config: CellularConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_cellular(&mut self) -> Result<CellularMetrics> {
+        println!("\n📶 Analyzing NB-IoT/LTE-M Link Costs (RRC, PSM/eDRX)");
+        println!("----------------------------------------------------");
+
+        let mut rats = Vec::new();
+        for rat in [NB_IOT, LTE_M] {
+            let reference = up(REFERENCE_MESSAGE_BYTES);
+            let (uplink_ms, uplink_mj) = rat.message(&reference);
+            let profile = RatProfile {
+                rat: rat.name.to_string(),
+                rrc_setup_ms: rat.rrc_setup_ms,
+                rrc_setup_mj: rat.setup_mj(),
+                uplink_message_ms: uplink_ms,
+                uplink_message_mj: uplink_mj,
+                uplink_uj_per_byte: 8.0 / rat.uplink_kbit * rat.tx_mw,
+                downlink_uj_per_byte: 8.0 / rat.downlink_kbit * rat.rx_mw,
+            };
+            println!("✅ {}: RRC setup {:.0}ms/{:.0}mJ, {}-byte uplink {:.0}ms/{:.1}mJ, {:.0}µJ/B up, {:.0}µJ/B down",
+                     profile.rat, profile.rrc_setup_ms, profile.rrc_setup_mj, REFERENCE_MESSAGE_BYTES,
+                     profile.uplink_message_ms, profile.uplink_message_mj, profile.uplink_uj_per_byte,
+                     profile.downlink_uj_per_byte);
+            rats.push(profile);
+        }
+
+        let patterns = self.patterns()?;
+        let mut runs = Vec::new();
+        for rat in [NB_IOT, LTE_M] {
+            for release in [Release::InactivityTimer, Release::Rai] {
+                let release_name = match release {
+                    Release::InactivityTimer => format!("inactivity timer {}s", self.config.inactivity_timer.as_secs()),
+                    Release::Rai => "RAI".to_string(),
+                };
+                println!("✅ {} with {}:", rat.name, release_name);
+                for pattern in &patterns {
+                    let mut line = Vec::new();
+                    for &interval in &self.config.report_intervals {
+                        let run = self.run(&rat, release, pattern, interval, &release_name);
+                        line.push(format!("@{}s {:.2}s, {:.0}mJ, {:.1}y",
+                                          run.report_interval_s, run.report_latency_ms / 1000.0,
+                                          run.energy_per_report_mj, run.battery_years));
+                        runs.push(run);
+                    }
+                    println!("   {}: {}", pattern.name, line.join(" | "));
+                }
+            }
+        }
+
+        Ok(CellularMetrics { rats, runs })
+    }
+
+    fn run(&self, rat: &Rat, release: Release, pattern: &Pattern, interval: Duration, release_name: &str) -> CellularRun {
+        // A second pass starts in the state the first left the device in
+        let first = self.cycle(rat, release, pattern, interval, false);
+        let cycle = self.cycle(rat, release, pattern, interval, first.1);
+        let cycle = cycle.0;
+        let interval_ms = interval.as_secs_f64() * 1000.0;
+        let average_power_mw = cycle.energy_mj / interval.as_secs_f64().max(f64::EPSILON);
+        let battery_j = self.config.battery_mah * 3.6 * BATTERY_VOLTS;
+        CellularRun {
+            rat: rat.name.to_string(),
+            release: release_name.to_string(),
+            protocol: pattern.name.to_string(),
+            report_interval_s: interval.as_secs_f64(),
+            messages: cycle.messages,
+            bytes: cycle.bytes,
+            rrc_setups: cycle.rrc_setups,
+            report_latency_ms: cycle.report_latency_ms,
+            energy_per_report_mj: cycle.energy_mj,
+            average_power_mw,
+            battery_years: battery_j / (average_power_mw / 1000.0) / (365.25 * 86_400.0),
+            downlink_latency_s: cycle.downlink_wait_ms2 / interval_ms.max(f64::EPSILON) / 1000.0,
+            reachable_fraction: cycle.reachable_ms / interval_ms.max(f64::EPSILON),
+        }
+    }
+
+    /// One report interval: the report, then keep-alives evenly spread if the
+    /// interval is longer than the keep-alive. Returns whether the device
+    /// is still connected at the end.
+    fn cycle(&self, rat: &Rat, release: Release, pattern: &Pattern, interval: Duration, mut connected: bool) -> (Cycle, bool) {
+        let interval_ms = interval.as_secs_f64() * 1000.0;
+        let mut events: Vec<&[Message]> = vec![&pattern.report];
+        if let Some((keep_alive, messages)) = &pattern.keep_alive {
+            let extra = (interval.as_secs_f64() / keep_alive.as_secs_f64().max(f64::EPSILON)).ceil() as usize;
+            events.extend(std::iter::repeat_n(messages.as_slice(), extra.saturating_sub(1)));
+        }
+        let gap = interval_ms / events.len() as f64;
+        let inactivity_ms = match release {
+            Release::InactivityTimer => self.config.inactivity_timer.as_secs_f64() * 1000.0,
+            Release::Rai => 0.0,
+        };
+        let active_timer_ms = self.config.psm_active_timer.as_secs_f64() * 1000.0;
+        let edrx_wait_ms = self.config.edrx_cycle.as_secs_f64() * 1000.0 / 2.0 + rat.rrc_setup_ms;
+
+        let mut cycle = Cycle::default();
+        for (i, messages) in events.into_iter().enumerate() {
+            let mut elapsed = 0.0;
+            if !connected {
+                elapsed += rat.rrc_setup_ms;
+                cycle.energy_mj += rat.setup_mj();
+                cycle.rrc_setups += 1;
+            }
+            for message in messages {
+                if message.reply {
+                    elapsed += SERVER_ROUND_TRIP_MS;
+                    cycle.energy_mj += SERVER_ROUND_TRIP_MS * rat.connected_mw / 1000.0;
+                }
+                let (airtime, energy) = rat.message(message);
+                elapsed += airtime;
+                cycle.energy_mj += energy;
+                cycle.bytes += message.bytes;
+            }
+            cycle.messages += messages.len();
+            if i == 0 {
+                cycle.report_latency_ms = elapsed;
+            }
+            // While active, a downlink command waits for the ongoing exchange
+            cycle.downlink_wait_ms2 += elapsed.min(gap) * rat.downlink_scheduling_ms;
+            cycle.reachable_ms += elapsed.min(gap);
+
+            let idle = (gap - elapsed).max(0.0);
+            if idle < inactivity_ms + rat.rrc_release_ms {
+                cycle.energy_mj += idle * rat.connected_mw / 1000.0;
+                cycle.downlink_wait_ms2 += idle * rat.downlink_scheduling_ms;
+                cycle.reachable_ms += idle;
+                connected = true;
+                continue;
+            }
+            cycle.energy_mj += (inactivity_ms * rat.connected_mw + rat.rrc_release_ms * rat.rx_mw) / 1000.0;
+            cycle.downlink_wait_ms2 += (inactivity_ms + rat.rrc_release_ms) * rat.downlink_scheduling_ms;
+            let asleep = idle - inactivity_ms - rat.rrc_release_ms;
+            let pageable = asleep.min(active_timer_ms);
+            let psm = asleep - pageable;
+            cycle.energy_mj += (pageable * rat.edrx_mw + psm * rat.psm_mw) / 1000.0;
+            // In PSM a command waits for the next wake-up, on average half the sleep, then the setup
+            cycle.downlink_wait_ms2 += pageable * edrx_wait_ms + psm * (psm / 2.0 + rat.rrc_setup_ms);
+            cycle.reachable_ms += inactivity_ms + rat.rrc_release_ms + pageable;
+            connected = false;
+        }
+        (cycle, connected)
+    }
+
+    fn patterns(&self) -> Result<Vec<Pattern>> {
+        let payload = vec![0x42u8; self.config.payload_bytes];
+        let udp = |message: Vec<u8>| message.len() + IPV4_UDP_HEADER_BYTES;
+        let tcp = |segment: Vec<u8>| segment.len() + IPV4_TCP_HEADER_BYTES;
+
+        let coap_non = udp(coap_message(COAP_NON, COAP_PUT, 1, &uri_path(&["sensors", "temp"]), &payload));
+        let notify = udp(coap_message(
+            COAP_CON,
+            COAP_CONTENT,
+            2,
+            &[
+                (COAP_OPTION_OBSERVE, coap_uint(12)),
+                (COAP_OPTION_CONTENT_FORMAT, coap_uint(CONTENT_FORMAT_SENML_CBOR)),
+            ],
+            &payload,
+        ));
+        let coap_ack = udp(coap_message(COAP_ACK, 0, 2, &[], &[]));
+
+        let mut connect = mqtt_string("MQTT");
+        connect.extend_from_slice(&[4, 0x02]);
+        connect.extend_from_slice(&(self.config.mqtt_keep_alive.as_secs() as u16).to_be_bytes());
+        connect.extend_from_slice(&mqtt_string("nbiot-dev1"));
+        let publish = tcp(mqtt_publish("sensors/dev1/temp", Some(1), &payload));
+        let puback = tcp(mqtt_packet(MQTT_PUBACK, &1u16.to_be_bytes()));
+        let bare = IPV4_TCP_HEADER_BYTES;
+
+        let report = |values: &[u8]| {
+            tlv(|writer| {
+                writer.start_struct(Tag::Anonymous)?;
+                writer.u32(Tag::Context(0), 1)?; // SubscriptionId
+                writer.start_array(Tag::Context(1))?;
+                if !values.is_empty() {
+                    writer.start_struct(Tag::Anonymous)?;
+                    writer.start_struct(Tag::Context(1))?; // AttributeDataIB
+                    writer.u32(Tag::Context(0), 1)?; // DataVersion
+                    writer.start_list(Tag::Context(1))?;
+                    writer.u16(Tag::Context(2), 1)?;
+                    writer.u32(Tag::Context(3), 0x0402)?;
+                    writer.u32(Tag::Context(4), 0x0000)?;
+                    writer.end_container()?;
+                    writer.octets(Tag::Context(2), values)?;
+                    writer.end_container()?;
+                    writer.end_container()?;
+                }
+                writer.end_container()?;
+                writer.u8(Tag::Context(255), 11)?;
+                writer.end_container()
+            })
+        };
+        let report_data = udp(matter_message(PROTOCOL_IM, IM_REPORT_DATA, &report(&payload)?));
+        let liveness = udp(matter_message(PROTOCOL_IM, IM_REPORT_DATA, &report(&[])?));
+        let status = udp(matter_message(PROTOCOL_IM, IM_STATUS_RESPONSE, &[0x15, 0x24, 0x00, 0x00, 0x18]));
+        let standalone_ack = udp(matter_message(PROTOCOL_SECURE_CHANNEL, MRP_STANDALONE_ACK, &[]));
+
+        Ok(vec![
+            Pattern { name: "CoAP NON", report: vec![up(coap_non)], keep_alive: None },
+            Pattern { name: "LwM2M Notify (CON)", report: vec![up(notify), down(coap_ack)], keep_alive: None },
+            Pattern {
+                name: "MQTT reconnecting",
+                report: vec![
+                    up(bare), // SYN
+                    down(bare),
+                    up(tcp(mqtt_packet(MQTT_CONNECT, &connect))),
+                    down(tcp(mqtt_packet(MQTT_CONNACK, &[0, 0]))),
+                    up(publish),
+                    down(puback),
+                    up(tcp(mqtt_packet(MQTT_DISCONNECT, &[]))),
+                    up(bare), // FIN
+                    down(bare),
+                    up(bare),
+                ],
+                keep_alive: None,
+            },
+            Pattern {
+                name: "MQTT persistent",
+                report: vec![up(publish), down(puback), up(bare)],
+                keep_alive: Some((
+                    self.config.mqtt_keep_alive,
+                    vec![up(tcp(mqtt_packet(MQTT_PINGREQ, &[]))), down(tcp(mqtt_packet(MQTT_PINGRESP, &[]))), up(bare)],
+                )),
+            },
+            Pattern {
+                name: "Matter subscription",
+                report: vec![up(report_data), down(status), up(standalone_ack)],
+                keep_alive: Some((self.config.subscription_max_interval, vec![up(liveness), down(status), up(standalone_ack)])),
+            },
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rrc_setups_and_tails_dominate_and_rai_saves_energy() {
+        let metrics = CellularAnalyzer::builder()
+            .report_intervals(vec![Duration::from_secs(10), Duration::from_secs(3600)])
+            .build()
+            .analyze_cellular()
+            .unwrap();
+        let run = |rat: &str, rai: bool, protocol: &str, interval: f64| {
+            metrics
+                .runs
+                .iter()
+                .find(|run| {
+                    run.rat == rat && (run.release == "RAI") == rai && run.protocol == protocol && run.report_interval_s == interval
+                })
+                .unwrap()
+        };
+
+        // Reports every 10 s never let the inactivity timer expire
+        assert_eq!(run("NB-IoT", false, "CoAP NON", 10.0).rrc_setups, 0);
+        assert_eq!(run("NB-IoT", true, "CoAP NON", 10.0).rrc_setups, 1);
+
+        let (tail, rai) = (run("NB-IoT", false, "CoAP NON", 3600.0), run("NB-IoT", true, "CoAP NON", 3600.0));
+        assert!(rai.energy_per_report_mj < tail.energy_per_report_mj);
+        assert!(rai.battery_years > tail.battery_years);
+
+        let reconnecting = run("NB-IoT", true, "MQTT reconnecting", 3600.0);
+        assert!(reconnecting.report_latency_ms > 3.0 * rai.report_latency_ms);
+        assert_eq!(run("NB-IoT", true, "MQTT persistent", 3600.0).rrc_setups, 12);
+        assert!(run("LTE-M", true, "CoAP NON", 3600.0).report_latency_ms < rai.report_latency_ms);
+    }
+}
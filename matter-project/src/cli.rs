@@ -0,0 +1,1065 @@
+// matter-project/src/cli.rs
+/*!
+Command line options for the analyzer binary
+*/
+
+use analysis_core::baseline::{BaselineCommand, BaselineConfig};
+use analysis_core::budget::BudgetConfig;
+use analysis_core::campaign::MergeConfig;
+use analysis_core::diff::DiffConfig;
+use analysis_core::export::ExportConfig;
+use analysis_core::integrity::{load_key, IntegrityCommand, IntegrityConfig};
+use analysis_core::impairment::{self, MatrixConfig};
+use analysis_core::netns::{Impairment, NetnsConfig};
+use analysis_core::package::PackageConfig;
+use analysis_core::plugin::{PluginConfig, PluginToolCommand, PluginToolConfig};
+use analysis_core::query::QueryConfig;
+use analysis_core::redact::RedactConfig;
+use analysis_core::results_store::ResultsConfig;
+use analysis_core::retry::RetryPolicy;
+use analysis_core::run_metadata::RunMetadata;
+use analysis_core::trends::TrendConfig;
+use analysis_core::watchdog::WatchdogConfig;
+use anyhow::{anyhow, Result};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::access_control::AclConfig;
+use crate::asymmetry::AsymmetryConfig;
+use crate::bacnet::BacnetConfig;
+use crate::bdx::BdxConfig;
+use crate::bridge::BridgeConfig;
+use crate::cellular::CellularConfig;
+use crate::coap_bindings::CoapBindingConfig;
+use crate::commissioning_faults::CommissioningFaultConfig;
+use crate::concurrency::ConcurrencyConfig;
+use crate::congestion::CongestionConfig;
+use crate::contention::ContentionConfig;
+use crate::dds::DdsConfig;
+use crate::end_to_end::EndToEndConfig;
+use crate::fan_out::FanOutConfig;
+use crate::feature_matrix::FeatureMatrixConfig;
+use crate::firmware_update::FirmwareUpdateConfig;
+use crate::gateway::GatewayConfig;
+use crate::handshake::{HandshakeConfig, LinkProfile};
+use crate::home_assistant::HomeAssistantConfig;
+use crate::icd::IcdConfig;
+use crate::interoperability::InteroperabilityConfig;
+use crate::large_payload::LargePayloadConfig;
+use crate::lowpan::LowpanConfig;
+use crate::mqtt_sn::{MqttSnConfig, MqttTransport};
+use crate::naming::NamingConfig;
+use crate::power::PowerMeterConfig;
+use crate::reference::ReferenceConfig;
+use crate::rekey::RekeyConfig;
+use crate::reordering::ReorderConfig;
+use crate::robustness::RobustnessConfig;
+use crate::saturation::SaturationConfig;
+use crate::scenarios::ScenarioConfig;
+use crate::sigfox::SigfoxConfig;
+use crate::snmp::SnmpConfig;
+use crate::socket_options::SocketMatrixConfig;
+use crate::start_modes::StartModeConfig;
+use crate::stream::StreamFormat;
+use crate::telemetry::TelemetryConfig;
+use crate::throughput::{ThroughputBackend, ThroughputConfig};
+use crate::topology::TopologyConfig;
+use crate::transport_modes::TransportModeConfig;
+use crate::wisun::WisunConfig;
+use crate::workload::WorkloadConfig;
+use crate::zwave::ZwaveConfig;
+
+#[derive(Debug, Default)]
+pub struct CliOptions {
+    /// Run the UDP throughput test (against the agent when `--coordinator` is given).
+    pub throughput: bool,
+    pub throughput_config: ThroughputConfig,
+    /// Probe how many simultaneous sessions can be held open.
+    pub concurrency: bool,
+    pub concurrency_config: ConcurrencyConfig,
+    /// Repeat the TCP latency/throughput tests across socket-option combinations.
+    pub socket_matrix: bool,
+    pub socket_matrix_config: SocketMatrixConfig,
+    /// Run the TCP-based protocols under each congestion controller (Linux).
+    pub congestion: bool,
+    pub congestion_config: CongestionConfig,
+    /// Compare Matter operational exchanges over UDP (MRP) and TCP.
+    pub transport_modes: bool,
+    pub transport_mode_config: TransportModeConfig,
+    /// Compare the LwM2M exchanges over CoAP/UDP, CoAP/TCP and CoAP over WebSockets.
+    pub coap_bindings: bool,
+    pub coap_binding_config: CoapBindingConfig,
+    /// Compare MQTT-SN against a gateway with MQTT over TCP, WebSockets and TLS.
+    pub mqtt_sn: bool,
+    pub mqtt_sn_config: MqttSnConfig,
+    /// Compare DTLS/TLS handshakes across the compiled-in backends.
+    pub handshakes: bool,
+    pub handshake_config: HandshakeConfig,
+    /// Compare PSK and certificate authentication (DTLS-PSK, DTLS-cert, CASE).
+    pub auth_cost: bool,
+    /// Generate a PAA → PAI → DAC chain and time its validation.
+    pub attestation: bool,
+    /// Measure per-request authorization cost as the device ACL grows.
+    pub acl_overhead: bool,
+    pub acl_config: AclConfig,
+    /// Compare the bytes MQTT topics, CoAP paths and Matter attribute paths add per message.
+    pub naming: bool,
+    pub naming_config: NamingConfig,
+    /// Transfer a firmware image with each protocol's update mechanism, clean and interrupted.
+    pub firmware_update: bool,
+    pub firmware_update_config: FirmwareUpdateConfig,
+    /// Measure BDX transfers over UDP (MRP) and TCP across block sizes.
+    pub bdx: bool,
+    pub bdx_config: BdxConfig,
+    /// Move 64 KB to 10 MB payloads with BDX, CoAP Block2 and HTTP ranges, measured and over a lossy link.
+    pub large_payload: bool,
+    pub large_payload_config: LargePayloadConfig,
+    /// Stream periodic sensor telemetry with each protocol's reporting mechanism.
+    pub telemetry: bool,
+    pub telemetry_config: TelemetryConfig,
+    /// Model one sensor reporting to N observers through each protocol's fan-out.
+    pub fan_out: bool,
+    pub fan_out_config: FanOutConfig,
+    /// Run DDS discovery, QoS matching and sustained publish rates over RTPS.
+    pub dds: bool,
+    pub dds_config: DdsConfig,
+    /// Poll a local SNMP agent with GET and GETBULK over v2c and each v3 security level.
+    pub snmp: bool,
+    pub snmp_config: SnmpConfig,
+    /// Discover BACnet/IP devices and read their points, next to the same reads over Matter.
+    pub bacnet: bool,
+    pub bacnet_config: BacnetConfig,
+    /// Model report cost over NB-IoT and LTE-M, with RRC setup, inactivity tail and PSM/eDRX sleep.
+    pub cellular: bool,
+    pub cellular_config: CellularConfig,
+    /// Check which protocol messages fit Sigfox's 12-byte uplink and 8-byte downlink.
+    pub sigfox: bool,
+    pub sigfox_config: SigfoxConfig,
+    /// Frame each protocol's messages as 6LoWPAN over 802.15.4 with IPHC/NHC compression.
+    pub lowpan: bool,
+    pub lowpan_config: LowpanConfig,
+    /// Carry measured single-hop exchanges across a multi-hop Wi-SUN (802.15.4g) mesh.
+    pub wisun: bool,
+    pub wisun_config: WisunConfig,
+    /// Time Z-Wave inclusion and commands through a Serial API controller, simulated unless a port is given.
+    pub zwave: bool,
+    pub zwave_config: ZwaveConfig,
+    /// Time translation through protocol bridges (Zigbee2MQTT, LwM2M, Matter) against direct delivery.
+    pub gateway: bool,
+    pub gateway_config: GatewayConfig,
+    /// Toggle entities through a Home Assistant instance and time their state changes.
+    pub home_assistant: bool,
+    pub home_assistant_config: HomeAssistantConfig,
+    /// Simulate command delivery to a sleepy device that only wakes to poll.
+    pub icd: bool,
+    pub icd_config: IcdConfig,
+    /// Discover a Matter bridge with a growing number of bridged endpoints.
+    pub bridge: bool,
+    pub bridge_config: BridgeConfig,
+    /// Simulate Wi-Fi airtime contention with a growing number of competing stations.
+    pub contention: bool,
+    pub contention_config: ContentionConfig,
+    /// Compare acknowledgement patterns over a symmetric and an uplink-constrained shaped link.
+    pub asymmetry: bool,
+    pub asymmetry_config: AsymmetryConfig,
+    /// Hold back and duplicate messages in flight, and measure what each protocol delivers and how late.
+    pub reordering: bool,
+    pub reorder_config: ReorderConfig,
+    /// Separate first-contact costs (discovery, commissioning, first command) from commands over an existing session.
+    pub start_modes: bool,
+    pub start_mode_config: StartModeConfig,
+    /// Model "app button → light on" end to end for each protocol.
+    pub end_to_end: bool,
+    pub end_to_end_config: EndToEndConfig,
+    /// Model each protocol's typical device → hub → cloud path and the overhead of every hop.
+    pub topologies: bool,
+    pub topology_config: TopologyConfig,
+    /// Raise each telemetry stream's rate until delivery or latency collapses.
+    pub saturation: bool,
+    pub saturation_config: SaturationConfig,
+    /// Encode the light/lock/thermostat/sensor workloads with every protocol.
+    pub scenarios: bool,
+    pub scenario_config: ScenarioConfig,
+    /// Inject wrong passcodes, PASE drops and CSR timeouts into commissioning.
+    pub commissioning_faults: bool,
+    pub commissioning_fault_config: CommissioningFaultConfig,
+    /// Measure session re-keying (CASE, DTLS, MQTT reconnect) and its steady-state cost.
+    pub rekey: bool,
+    pub rekey_config: RekeyConfig,
+    /// Replay captured messages and duplicates against each protocol's duplicate detection.
+    pub replay: bool,
+    /// Fuzz the analyzers' packet parsers with mutated messages.
+    pub robustness: bool,
+    pub robustness_config: RobustnessConfig,
+    /// Replay a generated mixed workload through every protocol's encodings.
+    pub workload: bool,
+    pub workload_config: WorkloadConfig,
+    /// Where the capability matrices behind the interoperability score come from.
+    pub interoperability_config: InteroperabilityConfig,
+    /// Where the qualitative feature files (security, multicast, mesh, OTA, governance) come from.
+    pub feature_matrix_config: FeatureMatrixConfig,
+    /// Act as coordinator against a remote agent for one-way latency measurement.
+    pub coordinator_target: Option<SocketAddr>,
+    /// External power meter to sample during the run (`ina219:...` or `serial:...`).
+    pub power_meter: Option<PowerMeterConfig>,
+    /// Build the minimal per-protocol clients and record their code footprint.
+    pub footprint: bool,
+    /// Count CPU cycles/instructions for encode, handshake and message processing.
+    pub cpu_cost: bool,
+    /// Experiment ID, tags and notes from `--experiment-id`, `--tag key=value` and `--note`.
+    pub run_metadata: RunMetadata,
+    /// Where runs are saved and how long they are kept (`--results-dir`, `--keep-runs`, `--keep-days`, `--archive`).
+    pub results_config: ResultsConfig,
+    /// Strip hostnames, usernames and MAC/IP addresses from the saved result.
+    pub redact: bool,
+    /// Extra words for `--redact` to remove, from `--redact-term`.
+    pub redact_terms: Vec<String>,
+    /// Write each finished measurement to stdout as it completes (`--stream ndjson`).
+    pub stream: Option<StreamFormat>,
+    /// Write phase start/finish events as JSON lines to this file (`--event-log path`).
+    pub event_log: Option<std::path::PathBuf>,
+    /// Decimal places for floats in the result file and the stream (`--precision`); every digit when unset.
+    pub precision: Option<u32>,
+    /// Save per-iteration samples next to the result as `<run>.samples.arrow` (`--samples`).
+    pub samples: bool,
+    /// Check the saved result against this budget policy and exit with code 2 when it is exceeded (`--budgets path`).
+    pub budgets: Option<std::path::PathBuf>,
+    /// Retries for discovery, connection and commissioning (`--retry-attempts`, `--retry-backoff-ms`, `--retry-jitter`).
+    pub retry_policy: RetryPolicy,
+    /// Hard ceiling for hung stages (`--stage-timeout-s`, `--watchdog-factor`).
+    pub watchdog_config: WatchdogConfig,
+    /// Sysctls to set for the run and restore afterwards, from repeated `--sysctl name=value` (Linux, root).
+    pub sysctls: Vec<(String, String)>,
+    /// Run the analysis in its own Linux network namespace (`--netns`), optionally impaired with `--netem`,
+    /// `--netem-uplink`/`--netem-downlink` or `--impairment <profile>`.
+    pub netns: Option<NetnsConfig>,
+    /// Run a third-party protocol plugin (`--plugin name`) instead of the built-in Matter measurements.
+    pub plugin: Option<PluginConfig>,
+    /// `merge <files or directories> [--output path]`: combine result files instead of analyzing.
+    pub merge: Option<MergeConfig>,
+    /// `diff <old.json> <new.json> [--threshold pct]`: compare two result files instead of analyzing.
+    pub diff: Option<DiffConfig>,
+    /// `query "<conditions>" [--input path] [--format csv|json] [--time-unit us] [--size-unit KiB] [--precision n]`: filter stored results instead of analyzing.
+    pub query: Option<QueryConfig>,
+    /// `export [files or directories] [--output path.xlsx] [--time-unit us] [--size-unit KiB] [--precision n]`: write stored results to an Excel workbook instead of analyzing.
+    pub export: Option<ExportConfig>,
+    /// `compare --reference <dataset, file or URL> [files or directories] [--tolerance pct]`: position stored results against
+    /// a published reference dataset instead of analyzing; without `--reference`, list the bundled datasets.
+    pub compare: Option<ReferenceConfig>,
+    /// `baseline set [result.json]` / `baseline show [result.json]`: manage pinned golden results instead of analyzing.
+    pub baseline: Option<BaselineConfig>,
+    /// `budget [policy] [files or directories]`: check stored results against a budget policy instead of analyzing;
+    /// exits with code 2 when a budget is exceeded.
+    pub budget: Option<BudgetConfig>,
+    /// `trends [--dir results] [--window n] [--sigma s] [--metric name] [--protocol name] [--output path]`: flag metrics
+    /// whose latest value strays from the run history instead of analyzing.
+    pub trends: Option<TrendConfig>,
+    /// `package [files or directories] [--capture file] [--config path] [--title text] [--output path.tar.gz]`: bundle
+    /// results, captures and configs into a checksummed archive for publication instead of analyzing.
+    pub package: Option<PackageConfig>,
+    /// `integrity keygen|checksum|sign|verify [files]`: protect or check result files instead of analyzing.
+    pub integrity: Option<IntegrityConfig>,
+    /// `redact <files> [--output-dir dir]`: redact existing results and captures instead of analyzing.
+    pub redaction: Option<RedactConfig>,
+    /// `matrix [--profiles a,b] [--protocols x,y] [--output-dir dir] [-- analyzer args]`: run every protocol under every impairment profile.
+    pub matrix: Option<MatrixConfig>,
+    /// `plugin list` / `plugin describe <name>`: inspect installed protocol plugins instead of analyzing.
+    pub plugin_tool: Option<PluginToolConfig>,
+}
+
+impl CliOptions {
+    pub fn from_env() -> Result<Self> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self> {
+        let mut options = CliOptions::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "merge" if options.merge.is_none() => options.merge = Some(parse_merge(&mut args)?),
+                "diff" if options.diff.is_none() => options.diff = Some(parse_diff(&mut args)?),
+                "matrix" if options.matrix.is_none() => options.matrix = Some(parse_matrix(&mut args)?),
+                "query" if options.query.is_none() => options.query = Some(parse_query(&mut args)?),
+                "export" if options.export.is_none() => options.export = Some(parse_export(&mut args)?),
+                "compare" if options.compare.is_none() => options.compare = Some(parse_compare(&mut args)?),
+                "baseline" if options.baseline.is_none() => options.baseline = Some(parse_baseline(&mut args)?),
+                "budget" if options.budget.is_none() => options.budget = Some(parse_budget(&mut args)?),
+                "trends" if options.trends.is_none() => options.trends = Some(parse_trends(&mut args)?),
+                "package" if options.package.is_none() => options.package = Some(parse_package(&mut args)?),
+                "integrity" if options.integrity.is_none() => options.integrity = Some(parse_integrity(&mut args)?),
+                "redact" if options.redaction.is_none() => options.redaction = Some(parse_redact(&mut args)?),
+                "plugin" if options.plugin_tool.is_none() => options.plugin_tool = Some(parse_plugin_tool(&mut args)?),
+                "--coordinator" => options.coordinator_target = Some(next_value(&mut args, &arg)?.parse()?),
+                "--power-meter" => options.power_meter = Some(next_value(&mut args, &arg)?.parse()?),
+                "--footprint" => options.footprint = true,
+                "--experiment-id" => options.run_metadata.experiment_id = Some(next_value(&mut args, &arg)?),
+                "--tag" => options.run_metadata.add_tag(&next_value(&mut args, &arg)?)?,
+                "--note" => options.run_metadata.notes.push(next_value(&mut args, &arg)?),
+                "--results-dir" => options.results_config.dir = next_value(&mut args, &arg)?.into(),
+                "--keep-runs" => {
+                    let keep: usize = next_value(&mut args, &arg)?.parse()?;
+                    if keep == 0 {
+                        return Err(anyhow!("--keep-runs must keep at least the current run"));
+                    }
+                    options.results_config.keep_runs = Some(keep);
+                }
+                "--keep-days" => options.results_config.keep_days = Some(next_value(&mut args, &arg)?.parse()?),
+                "--redact" => options.redact = true,
+                "--stream" => options.stream = Some(next_value(&mut args, &arg)?.parse()?),
+                "--event-log" => options.event_log = Some(next_value(&mut args, &arg)?.into()),
+                "--precision" => options.precision = Some(next_value(&mut args, &arg)?.parse()?),
+                "--samples" => options.samples = true,
+                "--budgets" => options.budgets = Some(next_value(&mut args, &arg)?.into()),
+                "--redact-term" => options.redact_terms.push(next_value(&mut args, &arg)?),
+                "--plugin" => options.plugin.get_or_insert_with(PluginConfig::default).plugin = next_value(&mut args, &arg)?,
+                "--plugin-dir" => options.plugin.get_or_insert_with(PluginConfig::default).dir = next_value(&mut args, &arg)?.into(),
+                "--plugin-option" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let (key, value) = value
+                        .split_once('=')
+                        .ok_or_else(|| anyhow!("--plugin-option expects key=value, got {}", value))?;
+                    let config = options.plugin.get_or_insert_with(PluginConfig::default);
+                    config.options.insert(key.trim().to_string(), value.trim().to_string());
+                }
+                "--sysctl" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let (name, value) = value
+                        .split_once('=')
+                        .ok_or_else(|| anyhow!("--sysctl expects name=value, got {}", value))?;
+                    options.sysctls.push((name.trim().to_string(), value.trim().to_string()));
+                }
+                "--plugin-timeout-s" => {
+                    options.plugin.get_or_insert_with(PluginConfig::default).timeout =
+                        Duration::from_secs(next_value(&mut args, &arg)?.parse()?);
+                }
+                "--retry-attempts" => {
+                    options.retry_policy.max_attempts = next_value(&mut args, &arg)?.parse()?;
+                    if options.retry_policy.max_attempts == 0 {
+                        return Err(anyhow!("--retry-attempts must be at least 1"));
+                    }
+                }
+                "--retry-backoff-ms" => {
+                    options.retry_policy.initial_backoff = Duration::from_millis(next_value(&mut args, &arg)?.parse()?);
+                }
+                "--retry-jitter" => {
+                    options.retry_policy.jitter = next_value(&mut args, &arg)?.parse()?;
+                    if !(0.0..=1.0).contains(&options.retry_policy.jitter) {
+                        return Err(anyhow!("--retry-jitter must be between 0 and 1"));
+                    }
+                }
+                "--stage-timeout-s" => {
+                    options.watchdog_config.stage_timeout = Duration::from_secs(next_value(&mut args, &arg)?.parse()?);
+                }
+                "--watchdog-factor" => {
+                    options.watchdog_config.ceiling_factor = next_value(&mut args, &arg)?.parse()?;
+                    if options.watchdog_config.ceiling_factor == 0 {
+                        return Err(anyhow!("--watchdog-factor must be at least 1"));
+                    }
+                }
+                "--netns" => {
+                    options.netns.get_or_insert_with(NetnsConfig::default);
+                }
+                "--netem" => {
+                    options.netns.get_or_insert_with(NetnsConfig::default).impairment =
+                        Some(Impairment::symmetric(next_value(&mut args, &arg)?.parse()?));
+                }
+                "--netem-uplink" => {
+                    let config = options.netns.get_or_insert_with(NetnsConfig::default);
+                    config.impairment.get_or_insert_with(Impairment::default).uplink = next_value(&mut args, &arg)?.parse()?;
+                }
+                "--netem-downlink" => {
+                    let config = options.netns.get_or_insert_with(NetnsConfig::default);
+                    config.impairment.get_or_insert_with(Impairment::default).downlink = next_value(&mut args, &arg)?.parse()?;
+                }
+                "--impairment" => {
+                    options.netns.get_or_insert_with(NetnsConfig::default).impairment =
+                        impairment::profile(&next_value(&mut args, &arg)?)?.impairment();
+                }
+                "--checksum" => options.results_config.checksum = true,
+                "--sign-key" => {
+                    let key = std::path::PathBuf::from(next_value(&mut args, &arg)?);
+                    // A bad key should fail now, not after the whole run
+                    load_key(&key)?;
+                    options.results_config.sign_key = Some(key);
+                }
+                "--archive" => {
+                    // Checked up front so a long run is not lost to a missing feature at save time
+                    if !cfg!(feature = "archive") {
+                        return Err(anyhow!("--archive needs the archive feature: cargo run --features archive"));
+                    }
+                    options.results_config.archive = true;
+                }
+                "--cpu-cost" => options.cpu_cost = true,
+                "--throughput" => options.throughput = true,
+                "--packet-sizes" => {
+                    options.throughput_config.packet_sizes = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|size| size.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--throughput-duration-ms" => {
+                    options.throughput_config.duration = Duration::from_millis(next_value(&mut args, &arg)?.parse()?);
+                }
+                "--udp-batch" => options.throughput_config.batch_size = next_value(&mut args, &arg)?.parse()?,
+                "--io-backend" => {
+                    options.throughput_config.backend = next_value(&mut args, &arg)?.parse::<ThroughputBackend>()?;
+                }
+                "--socket-buffer" => options.throughput_config.socket_buffer_bytes = next_value(&mut args, &arg)?.parse()?,
+                "--concurrency" => options.concurrency = true,
+                "--concurrency-cap" => options.concurrency_config.max_connections = next_value(&mut args, &arg)?.parse()?,
+                "--plain-sessions" => options.concurrency_config.secure_sessions = false,
+                "--socket-matrix" => options.socket_matrix = true,
+                "--socket-matrix-rounds" => options.socket_matrix_config.round_trips = next_value(&mut args, &arg)?.parse()?,
+                "--congestion" => options.congestion = true,
+                "--congestion-algorithms" => {
+                    options.congestion_config.algorithms = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|name| name.trim().to_string())
+                        .filter(|name| !name.is_empty())
+                        .collect();
+                }
+                "--congestion-bytes" => options.congestion_config.transfer_bytes = next_value(&mut args, &arg)?.parse()?,
+                "--transport-modes" => options.transport_modes = true,
+                "--mode-loss" => options.transport_mode_config.udp_loss_rate = next_value(&mut args, &arg)?.parse()?,
+                "--coap-bindings" => options.coap_bindings = true,
+                "--coap-binding-loss" => options.coap_binding_config.udp_loss_rate = next_value(&mut args, &arg)?.parse()?,
+                "--coap-binding-rounds" => options.coap_binding_config.rounds = next_value(&mut args, &arg)?.parse()?,
+                "--mqtt-sn" => options.mqtt_sn = true,
+                "--mqtt-sn-publishes" => options.mqtt_sn_config.publishes = next_value(&mut args, &arg)?.parse()?,
+                "--mqtt-sn-payload" => options.mqtt_sn_config.payload_bytes = next_value(&mut args, &arg)?.parse()?,
+                "--mqtt-sn-buffered" => options.mqtt_sn_config.buffered_messages = next_value(&mut args, &arg)?.parse()?,
+                "--mqtt-transports" => {
+                    options.mqtt_sn_config.mqtt_transports = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|name| name.trim().parse::<MqttTransport>())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--mqtt-session-topics" => options.mqtt_sn_config.session_topics = next_value(&mut args, &arg)?.parse()?,
+                "--mqtt-session-expiry-ms" => {
+                    options.mqtt_sn_config.session_expiry = Duration::from_millis(next_value(&mut args, &arg)?.parse()?);
+                }
+                "--mqtt-state-interval-ms" => {
+                    options.mqtt_sn_config.state_interval = Duration::from_millis(next_value(&mut args, &arg)?.parse()?);
+                }
+                "--mqtt-will-keep-alive" => options.mqtt_sn_config.will_keep_alive_secs = next_value(&mut args, &arg)?.parse()?,
+                "--handshakes" => options.handshakes = true,
+                "--handshake-link" => {
+                    // <one-way delay ms>,<loss rate> for the impaired link
+                    let value = next_value(&mut args, &arg)?;
+                    let (delay, loss) = value
+                        .split_once(',')
+                        .ok_or_else(|| anyhow!("--handshake-link expects <delay_ms>,<loss_rate>"))?;
+                    options.handshake_config.links = vec![
+                        LinkProfile::loopback(),
+                        LinkProfile::impaired(delay.trim().parse()?, loss.trim().parse()?),
+                    ];
+                }
+                "--auth-cost" => options.auth_cost = true,
+                "--attestation" => options.attestation = true,
+                "--acl-overhead" => options.acl_overhead = true,
+                "--acl-entries" => {
+                    options.acl_config.entry_counts = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|count| count.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--naming" => options.naming = true,
+                "--naming-payloads" => {
+                    options.naming_config.payload_sizes = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|size| size.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--ota" => options.firmware_update = true,
+                "--ota-image-kb" => {
+                    let kb = next_value(&mut args, &arg)?.parse::<usize>()?;
+                    options.firmware_update_config.image_bytes =
+                        kb.checked_mul(1024).ok_or_else(|| anyhow!("--ota-image-kb {} is too large", kb))?;
+                }
+                "--ota-interrupt" => options.firmware_update_config.interrupt_at = next_value(&mut args, &arg)?.parse()?,
+                "--bdx" => options.bdx = true,
+                "--bdx-kb" => {
+                    let kb = next_value(&mut args, &arg)?.parse::<usize>()?;
+                    options.bdx_config.transfer_bytes =
+                        kb.checked_mul(1024).ok_or_else(|| anyhow!("--bdx-kb {} is too large", kb))?;
+                }
+                "--bdx-loss" => options.bdx_config.udp_loss_rate = next_value(&mut args, &arg)?.parse()?,
+                "--large-payload" => options.large_payload = true,
+                "--large-payload-kb" => {
+                    options.large_payload_config.payload_sizes = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|kb| {
+                            let kb = kb.trim().parse::<usize>()?;
+                            kb.checked_mul(1024).ok_or_else(|| anyhow!("--large-payload-kb {} is too large", kb))
+                        })
+                        .collect::<Result<_>>()?;
+                }
+                "--large-payload-loss" => {
+                    options.large_payload_config.loss_rates = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|rate| rate.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--large-payload-delay-ms" => {
+                    options.large_payload_config.link_delay = Duration::from_millis(next_value(&mut args, &arg)?.parse()?);
+                }
+                "--large-payload-rate-kbit" => options.large_payload_config.link_rate_kbit = next_value(&mut args, &arg)?.parse()?,
+                "--telemetry" => options.telemetry = true,
+                "--telemetry-rates" => {
+                    options.telemetry_config.rates_hz = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|rate| rate.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--telemetry-duration-ms" => {
+                    options.telemetry_config.duration = Duration::from_millis(next_value(&mut args, &arg)?.parse()?);
+                }
+                "--fan-out" => options.fan_out = true,
+                "--fan-out-observers" => {
+                    options.fan_out_config.observer_counts = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|count| count.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--fan-out-values" => options.fan_out_config.values_per_sample = next_value(&mut args, &arg)?.parse()?,
+                "--fan-out-rate-kbit" => options.fan_out_config.device_rate_kbit = next_value(&mut args, &arg)?.parse()?,
+                "--dds" => options.dds = true,
+                "--dds-rates" => {
+                    options.dds_config.publish_rates = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|rate| rate.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--dds-payload" => options.dds_config.payload_bytes = next_value(&mut args, &arg)?.parse()?,
+                "--dds-loss" => options.dds_config.loss_rate = next_value(&mut args, &arg)?.parse()?,
+                "--dds-duration-ms" => {
+                    options.dds_config.duration = Duration::from_millis(next_value(&mut args, &arg)?.parse()?);
+                }
+                "--snmp" => options.snmp = true,
+                "--snmp-oids" => {
+                    options.snmp_config.oid_counts = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|count| count.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--snmp-interfaces" => options.snmp_config.interfaces = next_value(&mut args, &arg)?.parse()?,
+                "--snmp-max-repetitions" => {
+                    options.snmp_config.max_repetitions = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|repetitions| repetitions.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--snmp-community" => options.snmp_config.community = next_value(&mut args, &arg)?,
+                "--bacnet" => options.bacnet = true,
+                "--bacnet-devices" => options.bacnet_config.devices = next_value(&mut args, &arg)?.parse()?,
+                "--bacnet-points" => {
+                    options.bacnet_config.point_counts = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|count| count.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--cellular" => options.cellular = true,
+                "--cellular-intervals-s" => {
+                    options.cellular_config.report_intervals = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|secs| secs.trim().parse().map(Duration::from_secs))
+                        .collect::<Result<_, _>>()?;
+                }
+                "--cellular-payload" => options.cellular_config.payload_bytes = next_value(&mut args, &arg)?.parse()?,
+                "--cellular-inactivity-s" => {
+                    options.cellular_config.inactivity_timer = Duration::from_secs(next_value(&mut args, &arg)?.parse()?);
+                }
+                "--cellular-keep-alive-s" => {
+                    options.cellular_config.mqtt_keep_alive = Duration::from_secs(next_value(&mut args, &arg)?.parse()?);
+                }
+                "--sigfox" => options.sigfox = true,
+                "--sigfox-payloads" => {
+                    options.sigfox_config.payload_sizes = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|size| size.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--lowpan" => options.lowpan = true,
+                "--lowpan-values" => {
+                    options.lowpan_config.report_values = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|size| size.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--wisun" => options.wisun = true,
+                "--wisun-hops" => {
+                    options.wisun_config.hop_counts = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|hops| hops.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--wisun-hop-distribution" => {
+                    options.wisun_config.hop_distribution = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|weight| weight.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--wisun-rate-kbit" => options.wisun_config.rate_kbit = next_value(&mut args, &arg)?.parse()?,
+                "--wisun-loss" => options.wisun_config.hop_loss = next_value(&mut args, &arg)?.parse()?,
+                "--wisun-forwarding-ms" => {
+                    options.wisun_config.forwarding_delay = Duration::from_millis(next_value(&mut args, &arg)?.parse()?);
+                }
+                "--zwave" => options.zwave = true,
+                "--zwave-port" => options.zwave_config.serial_port = Some(next_value(&mut args, &arg)?),
+                "--zwave-node" => {
+                    options.zwave_config.node = Some(next_value(&mut args, &arg)?.parse()?);
+                    options.zwave_config.include = false;
+                }
+                "--zwave-rate-kbit" => options.zwave_config.data_rate_kbit = next_value(&mut args, &arg)?.parse()?,
+                "--zwave-loss" => options.zwave_config.frame_loss = next_value(&mut args, &arg)?.parse()?,
+                "--zwave-repeaters" => options.zwave_config.repeaters = next_value(&mut args, &arg)?.parse()?,
+                "--gateway" => options.gateway = true,
+                "--gateway-iterations" => options.gateway_config.iterations = next_value(&mut args, &arg)?.parse()?,
+                "--home-assistant" => options.home_assistant = true,
+                "--ha-url" => options.home_assistant_config.url = next_value(&mut args, &arg)?,
+                "--ha-token" => options.home_assistant_config.token = Some(next_value(&mut args, &arg)?),
+                "--ha-entities" => {
+                    options.home_assistant_config.entities =
+                        next_value(&mut args, &arg)?.split(',').map(|v| v.trim().to_string()).collect()
+                }
+                "--ha-commands" => options.home_assistant_config.commands = next_value(&mut args, &arg)?.parse()?,
+                "--ha-interval-ms" => {
+                    options.home_assistant_config.interval = Duration::from_millis(next_value(&mut args, &arg)?.parse()?)
+                }
+                "--ha-timeout-ms" => {
+                    options.home_assistant_config.timeout = Duration::from_millis(next_value(&mut args, &arg)?.parse()?)
+                }
+                "--icd" => options.icd = true,
+                "--icd-poll-ms" => {
+                    options.icd_config.poll_intervals = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|ms| ms.trim().parse().map(Duration::from_millis))
+                        .collect::<Result<_, _>>()?;
+                }
+                "--icd-awake-ms" => options.icd_config.awake_window = Duration::from_millis(next_value(&mut args, &arg)?.parse()?),
+                "--icd-loss" => options.icd_config.loss_rate = next_value(&mut args, &arg)?.parse()?,
+                "--icd-update-ms" => options.icd_config.update_interval = Duration::from_millis(next_value(&mut args, &arg)?.parse()?),
+                "--icd-offline-commands" => {
+                    options.icd_config.commands_per_window = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|n| n.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--icd-queue-capacity" => options.icd_config.queue_capacity = next_value(&mut args, &arg)?.parse()?,
+                "--bridge" => options.bridge = true,
+                "--bridge-endpoints" => {
+                    options.bridge_config.endpoint_counts = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|count| count.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--wifi-contention" => options.contention = true,
+                "--contention-stations" => {
+                    options.contention_config.station_counts = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|count| count.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--contention-load" => options.contention_config.background_frames_per_s = next_value(&mut args, &arg)?.parse()?,
+                "--contention-phy-mbps" => options.contention_config.phy_rate_mbps = next_value(&mut args, &arg)?.parse()?,
+                "--asymmetric-links" => options.asymmetry = true,
+                "--asymmetric-uplink" => options.asymmetry_config.asymmetric.uplink = next_value(&mut args, &arg)?.parse()?,
+                "--asymmetric-downlink" => options.asymmetry_config.asymmetric.downlink = next_value(&mut args, &arg)?.parse()?,
+                "--asymmetric-messages" => options.asymmetry_config.messages = next_value(&mut args, &arg)?.parse()?,
+                "--reordering" => options.reordering = true,
+                "--reorder-percent" => options.reorder_config.reorder_percent = next_value(&mut args, &arg)?.parse()?,
+                "--reorder-delay-ms" => options.reorder_config.reorder_delay_ms = next_value(&mut args, &arg)?.parse()?,
+                "--duplicate-percent" => options.reorder_config.duplicate_percent = next_value(&mut args, &arg)?.parse()?,
+                "--reorder-messages" => options.reorder_config.messages = next_value(&mut args, &arg)?.parse()?,
+                "--cold-warm" => options.start_modes = true,
+                "--cold-starts" => options.start_mode_config.cold_starts = next_value(&mut args, &arg)?.parse()?,
+                "--warm-commands" => options.start_mode_config.warm_commands = next_value(&mut args, &arg)?.parse()?,
+                "--end-to-end" => options.end_to_end = true,
+                "--e2e-presses" => options.end_to_end_config.presses = next_value(&mut args, &arg)?.parse()?,
+                "--e2e-cold-every" => options.end_to_end_config.cold_every = next_value(&mut args, &arg)?.parse()?,
+                "--e2e-loss" => options.end_to_end_config.loss_rate = next_value(&mut args, &arg)?.parse()?,
+                "--e2e-broker-delay-ms" => {
+                    options.end_to_end_config.broker_delay = Duration::from_millis(next_value(&mut args, &arg)?.parse()?)
+                }
+                "--e2e-via-cloud" => options.end_to_end_config.via_cloud = true,
+                "--e2e-wan-delay-ms" => {
+                    options.end_to_end_config.wan_delay = Duration::from_millis(next_value(&mut args, &arg)?.parse()?)
+                }
+                "--topologies" => options.topologies = true,
+                "--topology-commands" => options.topology_config.commands = next_value(&mut args, &arg)?.parse()?,
+                "--wan-delay-ms" => options.topology_config.wan_delay = Duration::from_millis(next_value(&mut args, &arg)?.parse()?),
+                "--wan-loss" => options.topology_config.wan_loss = next_value(&mut args, &arg)?.parse()?,
+                "--radio-loss" => options.topology_config.radio_loss = next_value(&mut args, &arg)?.parse()?,
+                "--saturation" => options.saturation = true,
+                "--saturation-max-rate" => options.saturation_config.max_rate_hz = next_value(&mut args, &arg)?.parse()?,
+                "--saturation-step-ms" => {
+                    options.saturation_config.step_duration = Duration::from_millis(next_value(&mut args, &arg)?.parse()?)
+                }
+                "--scenarios" => options.scenarios = true,
+                "--scenario-devices" => {
+                    options.scenario_config.scenarios = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|name| name.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--commissioning-faults" => options.commissioning_faults = true,
+                "--commissioning-failures" => {
+                    options.commissioning_fault_config.failure_counts = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|count| count.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--commissioning-retries" => {
+                    options.commissioning_fault_config.max_retries = next_value(&mut args, &arg)?.parse()?;
+                }
+                "--rekey" => options.rekey = true,
+                "--rekey-interval-s" => {
+                    options.rekey_config.rotation_intervals = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|s| s.trim().parse().map(Duration::from_secs))
+                        .collect::<Result<_, _>>()?;
+                }
+                "--rekey-delay-ms" => options.rekey_config.one_way_delay_ms = next_value(&mut args, &arg)?.parse()?,
+                "--replay" => options.replay = true,
+                "--robustness" => options.robustness = true,
+                "--fuzz-iterations" => options.robustness_config.iterations = next_value(&mut args, &arg)?.parse()?,
+                "--fuzz-seed" => options.robustness_config.seed = next_value(&mut args, &arg)?.parse()?,
+                "--fuzz-targets" => {
+                    options.robustness_config.kinds = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|kind| kind.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--workload" => options.workload = true,
+                "--workload-patterns" => {
+                    options.workload_config.patterns = next_value(&mut args, &arg)?
+                        .split(',')
+                        .map(|pattern| pattern.trim().parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                "--workload-devices" => options.workload_config.devices = next_value(&mut args, &arg)?.parse()?,
+                "--workload-hours" => {
+                    let hours: f64 = next_value(&mut args, &arg)?.parse()?;
+                    options.workload_config.duration = Duration::from_secs_f64(hours * 3600.0);
+                }
+                "--workload-seed" => options.workload_config.seed = next_value(&mut args, &arg)?.parse()?,
+                "--capabilities-dir" => {
+                    let dir: std::path::PathBuf = next_value(&mut args, &arg)?.into();
+                    options.feature_matrix_config.features_dir = Some(dir.join("features"));
+                    options.interoperability_config.capabilities_dir = Some(dir);
+                }
+                other => return Err(anyhow!("unknown argument: {}", other)),
+            }
+        }
+        if options.plugin.as_ref().is_some_and(|plugin| plugin.plugin.is_empty()) {
+            return Err(anyhow!("--plugin-dir, --plugin-option and --plugin-timeout-s need --plugin <name>"));
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_merge<I: Iterator<Item = String>>(args: &mut I) -> Result<MergeConfig> {
+    let mut config = MergeConfig::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => config.output = next_value(args, &arg)?.into(),
+            flag if flag.starts_with("--") => return Err(anyhow!("unknown merge argument: {}", flag)),
+            path => config.inputs.push(path.into()),
+        }
+    }
+    if config.inputs.is_empty() {
+        return Err(anyhow!("merge needs at least one result file or directory"));
+    }
+    Ok(config)
+}
+
+fn parse_diff<I: Iterator<Item = String>>(args: &mut I) -> Result<DiffConfig> {
+    let mut config = DiffConfig::default();
+    let mut files = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threshold" => config.threshold_percent = next_value(args, &arg)?.parse()?,
+            "--all" => config.show_unchanged = true,
+            "--fail-on-regression" => config.fail_on_regression = true,
+            flag if flag.starts_with("--") => return Err(anyhow!("unknown diff argument: {}", flag)),
+            path => files.push(path.into()),
+        }
+    }
+    let [old, new]: [std::path::PathBuf; 2] =
+        files.try_into().map_err(|_| anyhow!("diff needs exactly two result files: <old.json> <new.json>"))?;
+    config.old = old;
+    config.new = new;
+    Ok(config)
+}
+
+fn parse_matrix<I: Iterator<Item = String>>(args: &mut I) -> Result<MatrixConfig> {
+    let mut config = MatrixConfig::default();
+    let list = |value: String| value.split(',').map(|item| item.trim().to_string()).filter(|item| !item.is_empty()).collect();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--profiles" => config.profiles = list(next_value(args, &arg)?),
+            "--protocols" => config.protocols = list(next_value(args, &arg)?),
+            "--plugin-dir" => config.plugin_dir = next_value(args, &arg)?.into(),
+            "--output-dir" => config.output_dir = next_value(args, &arg)?.into(),
+            "--threshold" => config.threshold_percent = next_value(args, &arg)?.parse()?,
+            // Everything after -- goes to each run
+            "--" => config.analyzer_args.extend(args.by_ref()),
+            other => return Err(anyhow!("unknown matrix argument: {}", other)),
+        }
+    }
+    for profile in &config.profiles {
+        impairment::profile(profile)?;
+    }
+    Ok(config)
+}
+
+fn parse_query<I: Iterator<Item = String>>(args: &mut I) -> Result<QueryConfig> {
+    let mut config = QueryConfig::default();
+    let mut words = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => config.inputs.push(next_value(args, &arg)?.into()),
+            "--format" => config.format = next_value(args, &arg)?.parse()?,
+            "--time-unit" => config.units.time = next_value(args, &arg)?.parse()?,
+            "--size-unit" => config.units.size = next_value(args, &arg)?.parse()?,
+            "--precision" => config.units.precision = Some(next_value(args, &arg)?.parse()?),
+            flag if flag.starts_with("--") => return Err(anyhow!("unknown query argument: {}", flag)),
+            word => words.push(word.to_string()),
+        }
+    }
+    // The query may arrive as one quoted argument or as separate words
+    config.query = words.join(" ");
+    Ok(config)
+}
+
+fn parse_export<I: Iterator<Item = String>>(args: &mut I) -> Result<ExportConfig> {
+    let mut config = ExportConfig::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => config.output = next_value(args, &arg)?.into(),
+            "--time-unit" => config.units.time = next_value(args, &arg)?.parse()?,
+            "--size-unit" => config.units.size = next_value(args, &arg)?.parse()?,
+            "--precision" => config.units.precision = Some(next_value(args, &arg)?.parse()?),
+            flag if flag.starts_with("--") => return Err(anyhow!("unknown export argument: {}", flag)),
+            path => config.inputs.push(path.into()),
+        }
+    }
+    Ok(config)
+}
+
+fn parse_compare<I: Iterator<Item = String>>(args: &mut I) -> Result<ReferenceConfig> {
+    let mut config = ReferenceConfig::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--reference" => config.reference = Some(next_value(args, &arg)?),
+            "--tolerance" => config.tolerance_percent = next_value(args, &arg)?.parse()?,
+            flag if flag.starts_with("--") => return Err(anyhow!("unknown compare argument: {}", flag)),
+            path => config.inputs.push(path.into()),
+        }
+    }
+    Ok(config)
+}
+
+fn parse_baseline<I: Iterator<Item = String>>(args: &mut I) -> Result<BaselineConfig> {
+    let mut config = BaselineConfig::default();
+    let action = args.next().ok_or_else(|| anyhow!("baseline needs an action: set or show"))?;
+    let mut file = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dir" => config.dir = next_value(args, &arg)?.into(),
+            "--threshold" => config.threshold_percent = next_value(args, &arg)?.parse()?,
+            flag if flag.starts_with("--") => return Err(anyhow!("unknown baseline argument: {}", flag)),
+            path if file.is_none() => file = Some(std::path::PathBuf::from(path)),
+            extra => return Err(anyhow!("baseline takes one result file, got another: {}", extra)),
+        }
+    }
+    config.command = match action.as_str() {
+        "set" => BaselineCommand::Set(file.unwrap_or_else(|| "../results/matter_real_analysis.json".into())),
+        "show" => BaselineCommand::Show(file),
+        other => return Err(anyhow!("unknown baseline action: {} (expected set or show)", other)),
+    };
+    Ok(config)
+}
+
+fn parse_budget<I: Iterator<Item = String>>(args: &mut I) -> Result<BudgetConfig> {
+    let mut config = BudgetConfig::default();
+    let mut policy = None;
+    for arg in args.by_ref() {
+        match arg.as_str() {
+            flag if flag.starts_with("--") => return Err(anyhow!("unknown budget argument: {}", flag)),
+            path if policy.is_none() => policy = Some(path.into()),
+            path => config.inputs.push(path.into()),
+        }
+    }
+    if let Some(policy) = policy {
+        config.policy = policy;
+    }
+    Ok(config)
+}
+
+fn parse_trends<I: Iterator<Item = String>>(args: &mut I) -> Result<TrendConfig> {
+    let mut config = TrendConfig::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dir" => config.dir = next_value(args, &arg)?.into(),
+            "--window" => config.window = next_value(args, &arg)?.parse()?,
+            "--sigma" => config.sigma = next_value(args, &arg)?.parse()?,
+            "--metric" => config.metrics.push(next_value(args, &arg)?),
+            "--protocol" => config.protocol = Some(next_value(args, &arg)?),
+            "--output" => config.output = Some(next_value(args, &arg)?.into()),
+            other => return Err(anyhow!("unknown trends argument: {}", other)),
+        }
+    }
+    Ok(config)
+}
+
+fn parse_package<I: Iterator<Item = String>>(args: &mut I) -> Result<PackageConfig> {
+    let mut config = PackageConfig::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--capture" => config.captures.push(next_value(args, &arg)?.into()),
+            "--config" => config.configs.push(next_value(args, &arg)?.into()),
+            "--title" => config.title = Some(next_value(args, &arg)?),
+            "--output" => config.output = next_value(args, &arg)?.into(),
+            flag if flag.starts_with("--") => return Err(anyhow!("unknown package argument: {}", flag)),
+            path => config.inputs.push(path.into()),
+        }
+    }
+    Ok(config)
+}
+
+fn parse_integrity<I: Iterator<Item = String>>(args: &mut I) -> Result<IntegrityConfig> {
+    let mut config = IntegrityConfig::default();
+    let action = args.next().ok_or_else(|| anyhow!("integrity needs an action: keygen, checksum, sign or verify"))?;
+    config.command = match action.as_str() {
+        "keygen" => IntegrityCommand::Keygen,
+        "checksum" => IntegrityCommand::Checksum,
+        "sign" => IntegrityCommand::Sign,
+        "verify" => IntegrityCommand::Verify,
+        other => return Err(anyhow!("unknown integrity action: {} (expected keygen, checksum, sign or verify)", other)),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--key" => config.key = Some(next_value(args, &arg)?.into()),
+            "--public-key" => config.public_key = Some(next_value(args, &arg)?),
+            flag if flag.starts_with("--") => return Err(anyhow!("unknown integrity argument: {}", flag)),
+            path => config.files.push(path.into()),
+        }
+    }
+    match config.command {
+        IntegrityCommand::Keygen if !config.files.is_empty() => Err(anyhow!("integrity keygen takes --key <path>, not files")),
+        IntegrityCommand::Keygen => Ok(config),
+        _ if config.files.is_empty() => Err(anyhow!("integrity {} needs at least one result file", action)),
+        _ => Ok(config),
+    }
+}
+
+fn parse_redact<I: Iterator<Item = String>>(args: &mut I) -> Result<RedactConfig> {
+    let mut config = RedactConfig::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output-dir" => config.output_dir = next_value(args, &arg)?.into(),
+            "--term" => config.terms.push(next_value(args, &arg)?),
+            flag if flag.starts_with("--") => return Err(anyhow!("unknown redact argument: {}", flag)),
+            path => config.files.push(path.into()),
+        }
+    }
+    if config.files.is_empty() {
+        return Err(anyhow!("redact needs at least one result, capture or text file"));
+    }
+    Ok(config)
+}
+
+fn parse_plugin_tool<I: Iterator<Item = String>>(args: &mut I) -> Result<PluginToolConfig> {
+    let mut config = PluginToolConfig::default();
+    let action = args.next().ok_or_else(|| anyhow!("plugin needs an action: list or describe"))?;
+    let mut spec = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dir" => config.dir = next_value(args, &arg)?.into(),
+            flag if flag.starts_with("--") => return Err(anyhow!("unknown plugin argument: {}", flag)),
+            name if spec.is_none() => spec = Some(name.to_string()),
+            extra => return Err(anyhow!("unexpected plugin argument: {}", extra)),
+        }
+    }
+    config.command = match (action.as_str(), spec) {
+        ("list", None) => PluginToolCommand::List,
+        ("describe", Some(spec)) => PluginToolCommand::Describe(spec),
+        ("list", Some(_)) => return Err(anyhow!("plugin list takes only --dir")),
+        ("describe", None) => return Err(anyhow!("plugin describe needs a plugin name or path")),
+        (other, _) => return Err(anyhow!("unknown plugin action: {} (expected list or describe)", other)),
+    };
+    Ok(config)
+}
+
+fn next_value<I: Iterator<Item = String>>(args: &mut I, flag: &str) -> Result<String> {
+    args.next().ok_or_else(|| anyhow!("{} requires a value", flag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn parse(args: &[&str]) -> Result<CliOptions> {
+        CliOptions::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn subcommands_take_the_arguments_after_them() {
+        let options = parse(&["--keep-runs", "3", "--tag", "network=wifi5", "trends", "--window", "5", "--metric", "commissioning_time_ms"]).unwrap();
+        assert_eq!(options.results_config.keep_runs, Some(3));
+        assert_eq!(options.run_metadata.tags.get("network").map(String::as_str), Some("wifi5"));
+        let trends = options.trends.unwrap();
+        assert_eq!(trends.window, 5);
+        assert_eq!(trends.metrics, vec!["commissioning_time_ms"]);
+        assert!(options.merge.is_none() && options.query.is_none() && options.baseline.is_none());
+
+        let options = parse(&["budget", "limits.toml", "../results/runs"]).unwrap();
+        let budget = options.budget.unwrap();
+        assert_eq!(budget.policy, PathBuf::from("limits.toml"));
+        assert_eq!(budget.inputs, vec![PathBuf::from("../results/runs")]);
+    }
+
+    #[test]
+    fn missing_values_and_unknown_flags_are_errors() {
+        assert_eq!(parse(&["--results-dir"]).unwrap_err().to_string(), "--results-dir requires a value");
+        assert_eq!(parse(&["trends", "--sigma"]).unwrap_err().to_string(), "--sigma requires a value");
+        assert_eq!(parse(&["--bogus"]).unwrap_err().to_string(), "unknown argument: --bogus");
+        assert_eq!(parse(&["trends", "--bogus"]).unwrap_err().to_string(), "unknown trends argument: --bogus");
+        assert!(parse(&["--keep-runs", "0"]).is_err());
+    }
+
+    #[test]
+    fn kilobyte_sizes_that_overflow_are_errors() {
+        let too_large = usize::MAX.to_string();
+        assert_eq!(parse(&["--ota-image-kb", "512"]).unwrap().firmware_update_config.image_bytes, 512 * 1024);
+        assert_eq!(
+            parse(&["--ota-image-kb", &too_large]).unwrap_err().to_string(),
+            format!("--ota-image-kb {} is too large", too_large)
+        );
+        assert_eq!(parse(&["--bdx-kb", "64"]).unwrap().bdx_config.transfer_bytes, 64 * 1024);
+        assert_eq!(
+            parse(&["--bdx-kb", &too_large]).unwrap_err().to_string(),
+            format!("--bdx-kb {} is too large", too_large)
+        );
+        assert_eq!(parse(&["--large-payload-kb", "1, 16"]).unwrap().large_payload_config.payload_sizes, [1024, 16 * 1024]);
+        assert_eq!(
+            parse(&["--large-payload-kb", &format!("1,{}", too_large)]).unwrap_err().to_string(),
+            format!("--large-payload-kb {} is too large", too_large)
+        );
+    }
+
+    #[test]
+    fn baseline_set_defaults_to_the_latest_result() {
+        let options = parse(&["baseline", "set"]).unwrap();
+        match options.baseline.unwrap().command {
+            BaselineCommand::Set(file) => assert_eq!(file, PathBuf::from("../results/matter_real_analysis.json")),
+            other => panic!("expected baseline set, got {:?}", other),
+        }
+        assert!(matches!(parse(&["baseline", "show"]).unwrap().baseline.unwrap().command, BaselineCommand::Show(None)));
+        assert!(parse(&["baseline", "pin"]).is_err());
+    }
+}
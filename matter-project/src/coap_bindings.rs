@@ -0,0 +1,756 @@
+// matter-project/src/coap_bindings.rs
+/*!
+CoAP over UDP, TCP and WebSockets - the LwM2M 1.1 bindings compared on one workload
+
+LwM2M 1.1 lets a client reach its server over CoAP/TCP and CoAP over
+WebSockets (RFC 8323) as well as CoAP/UDP. The reliable bindings drop the
+message ID and type, so there are no confirmable retransmissions and no
+piggybacked ACKs. They pay instead for a connection: the TCP handshake and
+a Capabilities and Settings Message (CSM) from each side, plus the HTTP
+upgrade and per-frame masking on WebSockets.
+
+The same LwM2M exchanges (register, read, write, update) run over each
+binding against a local server. UDP follows RFC 7252 confirmable
+retransmission: a 2 s ACK timeout, doubled on each of up to 4
+retransmissions, without the random factor so runs repeat. The loss rate
+drops datagrams at the UDP server only. Loopback TCP is not impaired, so a
+loss comparison needs a real impaired link.
+*/
+
+use analysis_core::distribution::LatencyDistribution;
+use analysis_core::samples::{self, Layer};
+use anyhow::{anyhow, Result};
+use log::debug;
+use rand::RngCore;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{
+    coap_message, coap_parse, coap_uint, uri_path, COAP_ACK, COAP_CHANGED, COAP_CON, COAP_CONTENT, COAP_GET,
+    COAP_OPTION_URI_PATH, COAP_POST, COAP_PUT,
+};
+use crate::transport_modes::{IPV4_TCP_HEADER_BYTES, IPV4_UDP_HEADER_BYTES, TCP_HANDSHAKE_BYTES};
+
+// RFC 7252 confirmable transmission
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_RETRANSMIT: u32 = 4;
+/// Version/type/token length, code and message ID.
+const UDP_HEADER_BYTES: usize = 4;
+const COAP_CREATED: u8 = 0x41;
+const COAP_OPTION_LOCATION_PATH: u16 = 8;
+const COAP_OPTION_URI_QUERY: u16 = 15;
+
+// RFC 8323
+const COAP_CSM: u8 = 0xE1;
+const CSM_OPTION_MAX_MESSAGE_SIZE: u16 = 2;
+const MAX_MESSAGE_SIZE: usize = 1152;
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// FIN with the text opcode, for JSON APIs.
+pub(crate) const WEBSOCKET_TEXT: u8 = 0x81;
+/// FIN with the binary opcode; CoAP travels in binary frames.
+const WEBSOCKET_BINARY: u8 = 0x82;
+/// FIN with the close, ping and pong opcodes.
+pub(crate) const WEBSOCKET_CLOSE: u8 = 0x88;
+pub(crate) const WEBSOCKET_PING: u8 = 0x89;
+pub(crate) const WEBSOCKET_PONG: u8 = 0x8A;
+const WEBSOCKET_MASKED: u8 = 0x80;
+
+struct LwM2mExchange {
+    name: &'static str,
+    code: u8,
+    path: &'static [&'static str],
+    query: &'static [&'static str],
+    payload: &'static [u8],
+    response_code: u8,
+    location: &'static [&'static str],
+    response_payload: &'static [u8],
+}
+
+const EXCHANGES: [LwM2mExchange; 4] = [
+    LwM2mExchange {
+        name: "Register",
+        code: COAP_POST,
+        path: &["rd"],
+        query: &["ep=analyzer-client", "lt=300", "lwm2m=1.1"],
+        payload: b"</1/0>,</3/0>,</5/0>",
+        response_code: COAP_CREATED,
+        location: &["rd", "5a3f"],
+        response_payload: b"",
+    },
+    LwM2mExchange {
+        name: "Read",
+        code: COAP_GET,
+        path: &["3", "0", "0"],
+        query: &[],
+        payload: b"",
+        response_code: COAP_CONTENT,
+        location: &[],
+        response_payload: b"Open Mobile Alliance",
+    },
+    LwM2mExchange {
+        name: "Write",
+        code: COAP_PUT,
+        path: &["1", "0", "1"],
+        query: &[],
+        payload: b"300",
+        response_code: COAP_CHANGED,
+        location: &[],
+        response_payload: b"",
+    },
+    LwM2mExchange {
+        name: "Update",
+        code: COAP_POST,
+        path: &["rd", "5a3f"],
+        query: &[],
+        payload: b"",
+        response_code: COAP_CHANGED,
+        location: &[],
+        response_payload: b"",
+    },
+];
+
+#[derive(Debug, Clone)]
+pub struct CoapBindingConfig {
+    pub rounds: u32,
+    /// Fraction of datagrams the UDP server drops, to exercise confirmable retransmission.
+    pub udp_loss_rate: f64,
+}
+
+impl Default for CoapBindingConfig {
+    fn default() -> Self {
+        Self { rounds: 20, udp_loss_rate: 0.0 }
+    }
+}
+
+analyzer_builder!(CoapBindingComparisonBuilder => CoapBindingComparison(CoapBindingConfig) {
+    rounds: u32,
+    udp_loss_rate: f64,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CoapBindingMetrics {
+    pub exchanges_per_binding: u32,
+    pub udp_loss_rate: f64,
+    pub bindings: Vec<BindingMetrics>,
+    /// Each reliable binding against CoAP/UDP.
+    pub deltas: Vec<BindingDelta>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BindingMetrics {
+    pub binding: String,
+    /// Connect, WebSocket upgrade and CSM exchange; 0 for UDP.
+    pub connection_setup_ms: f64,
+    /// Bytes on the wire before the first exchange, including IP/TCP headers.
+    pub setup_bytes: usize,
+    pub median_latency_ms: f64,
+    pub p95_latency_ms: f64,
+    /// CoAP header, token, length fields and WebSocket framing per message, without options or payload.
+    pub framing_bytes_per_message: f64,
+    /// Estimated bytes on the wire per exchange, including IP/UDP/TCP headers,
+    /// retransmissions and the setup spread over the exchanges (pure TCP ACKs not counted).
+    pub wire_bytes_per_exchange: f64,
+    pub success_rate: f64,
+    pub retransmissions: u32,
+    pub distribution: LatencyDistribution,
+}
+
+/// A reliable binding minus CoAP/UDP; positive means it is slower / larger / more reliable.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BindingDelta {
+    pub binding: String,
+    pub median_latency_ms: f64,
+    pub p95_latency_ms: f64,
+    pub framing_bytes_per_message: f64,
+    pub wire_bytes_per_exchange: f64,
+    pub success_rate: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Binding {
+    Tcp,
+    WebSocket,
+}
+
+impl Binding {
+    fn name(self) -> &'static str {
+        match self {
+            Binding::Tcp => "tcp",
+            Binding::WebSocket => "websocket",
+        }
+    }
+}
+
+pub struct CoapBindingComparison {
+    config: CoapBindingConfig,
+}
+
+impl CoapBindingComparison {
+    pub fn new(config: CoapBindingConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_coap_bindings(&mut self) -> Result<CoapBindingMetrics> {
+        println!("\n🧷 Comparing CoAP Bindings: UDP vs TCP vs WebSocket");
+        println!("------------------------------------------------");
+
+        let udp = self.run_udp().await?;
+        let tcp = self.run_reliable(Binding::Tcp).await?;
+        let websocket = self.run_reliable(Binding::WebSocket).await?;
+
+        for binding in [&udp, &tcp, &websocket] {
+            println!("✅ CoAP/{}: {:.3}ms setup ({} bytes), {:.3}ms median, {:.1} framing bytes/message, {:.0} wire bytes/exchange, {:.1}% success, {} retransmissions",
+                     binding.binding, binding.connection_setup_ms, binding.setup_bytes, binding.median_latency_ms,
+                     binding.framing_bytes_per_message, binding.wire_bytes_per_exchange,
+                     binding.success_rate * 100.0, binding.retransmissions);
+        }
+
+        let deltas = [&tcp, &websocket]
+            .into_iter()
+            .map(|reliable| BindingDelta {
+                binding: reliable.binding.clone(),
+                median_latency_ms: reliable.median_latency_ms - udp.median_latency_ms,
+                p95_latency_ms: reliable.p95_latency_ms - udp.p95_latency_ms,
+                framing_bytes_per_message: reliable.framing_bytes_per_message - udp.framing_bytes_per_message,
+                wire_bytes_per_exchange: reliable.wire_bytes_per_exchange - udp.wire_bytes_per_exchange,
+                success_rate: reliable.success_rate - udp.success_rate,
+            })
+            .collect();
+
+        Ok(CoapBindingMetrics {
+            exchanges_per_binding: self.config.rounds * EXCHANGES.len() as u32,
+            udp_loss_rate: self.config.udp_loss_rate,
+            bindings: vec![udp, tcp, websocket],
+            deltas,
+        })
+    }
+
+    async fn run_udp(&self) -> Result<BindingMetrics> {
+        let server = UdpSocket::bind("127.0.0.1:0").await?;
+        let server_addr = server.local_addr()?;
+        let server_task = tokio::spawn(udp_server(server, self.config.udp_loss_rate));
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        socket.connect(server_addr).await?;
+
+        let mut tally = Tally::default();
+        let mut message_id = 0u16;
+        let mut buffer = vec![0u8; 2048];
+
+        for _ in 0..self.config.rounds {
+            for exchange in &EXCHANGES {
+                message_id = message_id.wrapping_add(1);
+                let request = request(exchange, message_id);
+                let start = Instant::now();
+                let mut response = None;
+
+                for attempt in 0..=MAX_RETRANSMIT {
+                    if attempt > 0 {
+                        tally.retransmissions += 1;
+                    }
+                    socket.send(&request).await?;
+                    tally.message(request.len() + IPV4_UDP_HEADER_BYTES, udp_framing(&request));
+
+                    let timeout = ACK_TIMEOUT * 2u32.pow(attempt);
+                    if let Some(len) = wait_for_ack(&socket, &mut buffer, message_id, timeout).await? {
+                        response = Some(buffer[..len].to_vec());
+                        break;
+                    }
+                }
+
+                match response {
+                    Some(response) if coap_parse(&response).is_some_and(|r| r.code == exchange.response_code) => {
+                        tally.latencies.push(start.elapsed().as_secs_f64() * 1000.0);
+                        tally.message(response.len() + IPV4_UDP_HEADER_BYTES, udp_framing(&response));
+                    }
+                    _ => {
+                        tally.failures += 1;
+                        debug!("CoAP/UDP {} #{} unanswered after {} transmissions", exchange.name, message_id, MAX_RETRANSMIT + 1);
+                    }
+                }
+            }
+        }
+
+        server_task.abort();
+        Ok(tally.summarize("udp", 0.0, 0))
+    }
+
+    async fn run_reliable(&self, binding: Binding) -> Result<BindingMetrics> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let server_addr = listener.local_addr()?;
+        let server = tokio::spawn(reliable_server(listener, binding));
+
+        let setup_start = Instant::now();
+        let stream = TcpStream::connect(server_addr).await?;
+        stream.set_nodelay(true)?;
+        let mut stream = BufReader::new(stream);
+        let mut setup_bytes = TCP_HANDSHAKE_BYTES;
+        if binding == Binding::WebSocket {
+            setup_bytes += websocket_upgrade(&mut stream, server_addr, "/.well-known/coap", Some("coap")).await? + 2 * IPV4_TCP_HEADER_BYTES;
+        }
+        let csm = csm(binding);
+        let sent = send(&mut stream, binding, &csm, true).await?;
+        let (received, _) = receive(&mut stream, binding).await?;
+        let connection_setup_ms = setup_start.elapsed().as_secs_f64() * 1000.0;
+        setup_bytes += sent + received + 2 * IPV4_TCP_HEADER_BYTES;
+
+        let mut tally = Tally::default();
+        for _ in 0..self.config.rounds {
+            for exchange in &EXCHANGES {
+                let request = request(exchange, 0);
+                let start = Instant::now();
+                match reliable_exchange(&mut stream, binding, &request, exchange.response_code).await {
+                    Ok((sent, received)) => {
+                        tally.latencies.push(start.elapsed().as_secs_f64() * 1000.0);
+                        let (request_body, response_body) = (request.len() - udp_framing(&request), received.1);
+                        tally.message(sent + IPV4_TCP_HEADER_BYTES, sent - request_body);
+                        tally.message(received.0 + IPV4_TCP_HEADER_BYTES, received.0 - response_body);
+                    }
+                    Err(e) => {
+                        tally.failures += 1;
+                        debug!("CoAP/{} {} failed: {}", binding.name(), exchange.name, e);
+                    }
+                }
+            }
+        }
+
+        drop(stream);
+        server.abort();
+        Ok(tally.summarize(binding.name(), connection_setup_ms, setup_bytes))
+    }
+}
+
+/// Latencies and bytes counted while one binding runs.
+#[derive(Default)]
+struct Tally {
+    latencies: Vec<f64>,
+    wire_bytes: usize,
+    framing_bytes: usize,
+    messages: usize,
+    failures: u32,
+    retransmissions: u32,
+}
+
+impl Tally {
+    fn message(&mut self, wire_bytes: usize, framing_bytes: usize) {
+        self.wire_bytes += wire_bytes;
+        self.framing_bytes += framing_bytes;
+        self.messages += 1;
+    }
+
+    fn summarize(self, binding: &str, connection_setup_ms: f64, setup_bytes: usize) -> BindingMetrics {
+        samples::record("coap_bindings", Layer::Transport, "LwM2M", binding, "latency_ms", &self.latencies);
+        let distribution = LatencyDistribution::from_samples(&self.latencies);
+        let mut latencies = self.latencies;
+        latencies.sort_by(|a, b| a.total_cmp(b));
+        let total = latencies.len() + self.failures as usize;
+        let per = |value: usize, count: usize| if count > 0 { value as f64 / count as f64 } else { 0.0 };
+
+        BindingMetrics {
+            binding: binding.to_string(),
+            connection_setup_ms,
+            setup_bytes,
+            median_latency_ms: percentile(&latencies, 0.5),
+            p95_latency_ms: percentile(&latencies, 0.95),
+            framing_bytes_per_message: per(self.framing_bytes, self.messages),
+            // Spread the connection setup over the exchanges it served
+            wire_bytes_per_exchange: per(self.wire_bytes + if total > 0 { setup_bytes } else { 0 }, total),
+            success_rate: per(latencies.len(), total),
+            retransmissions: self.retransmissions,
+            distribution,
+        }
+    }
+}
+
+/// The request for `exchange` in its CoAP/UDP form; the reliable bindings re-frame it.
+fn request(exchange: &LwM2mExchange, message_id: u16) -> Vec<u8> {
+    let mut options = uri_path(exchange.path);
+    options.extend(exchange.query.iter().map(|q| (COAP_OPTION_URI_QUERY, q.as_bytes().to_vec())));
+    coap_message(COAP_CON, exchange.code, message_id, &options, exchange.payload)
+}
+
+/// The server's piggybacked response to a CoAP/UDP-form request, or `None` for an unknown resource.
+fn respond(request: &[u8]) -> Option<Vec<u8>> {
+    let view = coap_parse(request)?;
+    let path: Vec<&[u8]> = view.options.iter().filter(|(n, _)| *n == COAP_OPTION_URI_PATH).map(|(_, v)| *v).collect();
+    let exchange = EXCHANGES.iter().find(|e| {
+        e.code == view.code && e.path.len() == path.len() && e.path.iter().zip(&path).all(|(a, b)| a.as_bytes() == *b)
+    })?;
+    let message_id = u16::from_be_bytes([*request.get(2)?, *request.get(3)?]);
+    let location: Vec<_> = exchange.location.iter().map(|s| (COAP_OPTION_LOCATION_PATH, s.as_bytes().to_vec())).collect();
+    Some(coap_message(COAP_ACK, exchange.response_code, message_id, &location, exchange.response_payload))
+}
+
+/// Header and token of a CoAP/UDP message.
+fn udp_framing(message: &[u8]) -> usize {
+    UDP_HEADER_BYTES + message.first().map_or(0, |b| (b & 0x0F) as usize)
+}
+
+/// Waits for the ACK carrying `message_id`, discarding duplicates of earlier ones.
+async fn wait_for_ack(socket: &UdpSocket, buffer: &mut [u8], message_id: u16, timeout: Duration) -> Result<Option<usize>> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        match tokio::time::timeout_at(deadline, socket.recv(buffer)).await {
+            Err(_) => return Ok(None),
+            Ok(received) => {
+                let len = received?;
+                let is_ack = len >= UDP_HEADER_BYTES && (buffer[0] >> 4) & 0x03 == COAP_ACK;
+                if is_ack && buffer[2..4] == message_id.to_be_bytes() {
+                    return Ok(Some(len));
+                }
+            }
+        }
+    }
+}
+
+async fn udp_server(socket: UdpSocket, loss_rate: f64) -> Result<()> {
+    let mut buffer = vec![0u8; 2048];
+    let mut rng_state = 0x2545_F491_4F6C_DD1Du64;
+
+    loop {
+        let (len, peer) = socket.recv_from(&mut buffer).await?;
+        // Deterministic xorshift so impaired runs are repeatable
+        rng_state ^= rng_state << 13;
+        rng_state ^= rng_state >> 7;
+        rng_state ^= rng_state << 17;
+        let sample = (rng_state >> 11) as f64 / (1u64 << 53) as f64;
+        if sample < loss_rate {
+            continue;
+        }
+
+        if let Some(response) = respond(&buffer[..len]) {
+            socket.send_to(&response, peer).await?;
+        }
+    }
+}
+
+/// An RFC 8323 message. Over WebSockets the frame carries the length, so Len is 0.
+fn reliable(code: u8, token: &[u8], body: &[u8], binding: Binding) -> Vec<u8> {
+    let len = body.len();
+    let (nibble, extended) = match binding {
+        Binding::WebSocket => (0, Vec::new()),
+        Binding::Tcp if len < 13 => (len as u8, Vec::new()),
+        Binding::Tcp if len < 269 => (13, vec![(len - 13) as u8]),
+        Binding::Tcp if len < 65_805 => (14, ((len - 269) as u16).to_be_bytes().to_vec()),
+        Binding::Tcp => (15, ((len - 65_805) as u32).to_be_bytes().to_vec()),
+    };
+    let mut message = Vec::with_capacity(2 + extended.len() + token.len() + len);
+    message.push(nibble << 4 | token.len() as u8);
+    message.extend_from_slice(&extended);
+    message.push(code);
+    message.extend_from_slice(token);
+    message.extend_from_slice(body);
+    message
+}
+
+/// A CoAP/UDP-form message re-framed for a reliable binding.
+fn to_reliable(message: &[u8], binding: Binding) -> Vec<u8> {
+    let token_len = (message[0] & 0x0F) as usize;
+    let token = &message[UDP_HEADER_BYTES..UDP_HEADER_BYTES + token_len];
+    reliable(message[1], token, &message[UDP_HEADER_BYTES + token_len..], binding)
+}
+
+/// A reliable-binding message back in CoAP/UDP form (confirmable, message ID 0), and its body length.
+fn from_reliable(message: &[u8]) -> Option<(Vec<u8>, usize)> {
+    let first = *message.first()?;
+    let token_len = (first & 0x0F) as usize;
+    let extended = extended_length_bytes(first);
+    let code = *message.get(1 + extended)?;
+    let token = message.get(2 + extended..2 + extended + token_len)?;
+    let body = message.get(2 + extended + token_len..)?;
+    let mut udp = vec![COAP_CON << 4 | 0x40 | token_len as u8, code, 0, 0];
+    udp.extend_from_slice(token);
+    udp.extend_from_slice(body);
+    Some((udp, body.len()))
+}
+
+/// Bytes of extended length after the first byte of an RFC 8323 message.
+fn extended_length_bytes(first: u8) -> usize {
+    match first >> 4 {
+        13 => 1,
+        14 => 2,
+        15 => 4,
+        _ => 0,
+    }
+}
+
+/// The CSM each side sends first, announcing the largest message it accepts.
+fn csm(binding: Binding) -> Vec<u8> {
+    let size = coap_uint(MAX_MESSAGE_SIZE);
+    let mut body = vec![(CSM_OPTION_MAX_MESSAGE_SIZE as u8) << 4 | size.len() as u8];
+    body.extend_from_slice(&size);
+    reliable(COAP_CSM, &[], &body, binding)
+}
+
+/// Sends one message over the binding and returns the bytes written.
+async fn send(stream: &mut BufReader<TcpStream>, binding: Binding, message: &[u8], client: bool) -> Result<usize> {
+    let bytes = match binding {
+        Binding::Tcp => message.to_vec(),
+        Binding::WebSocket => websocket_frame(message, client),
+    };
+    stream.write_all(&bytes).await?;
+    Ok(bytes.len())
+}
+
+/// Reads one message from the binding: the bytes read and the message.
+async fn receive(stream: &mut BufReader<TcpStream>, binding: Binding) -> Result<(usize, Vec<u8>)> {
+    match binding {
+        Binding::Tcp => {
+            let first = stream.read_u8().await?;
+            let mut message = vec![first; 1 + extended_length_bytes(first)];
+            stream.read_exact(&mut message[1..]).await?;
+            let extended = &message[1..];
+            let len = match first >> 4 {
+                13 => extended[0] as usize + 13,
+                14 => u16::from_be_bytes([extended[0], extended[1]]) as usize + 269,
+                15 => u32::from_be_bytes([extended[0], extended[1], extended[2], extended[3]]) as usize + 65_805,
+                len => len as usize,
+            };
+            let start = message.len();
+            message.resize(start + 1 + (first & 0x0F) as usize + len, 0);
+            stream.read_exact(&mut message[start..]).await?;
+            Ok((message.len(), message))
+        }
+        Binding::WebSocket => read_websocket_frame(stream).await,
+    }
+}
+
+/// Sends a request and checks the response code; the bytes sent, and the bytes received with the response's body length.
+async fn reliable_exchange(
+    stream: &mut BufReader<TcpStream>,
+    binding: Binding,
+    request: &[u8],
+    response_code: u8,
+) -> Result<(usize, (usize, usize))> {
+    let sent = send(stream, binding, &to_reliable(request, binding), true).await?;
+    let (received, response) = receive(stream, binding).await?;
+    let (response, body_len) = from_reliable(&response).ok_or_else(|| anyhow!("malformed response"))?;
+    let code = coap_parse(&response).map(|r| r.code);
+    if code != Some(response_code) {
+        return Err(anyhow!("response code {:?}, expected {:#04x}", code, response_code));
+    }
+    Ok((sent, (received, body_len)))
+}
+
+async fn reliable_server(listener: TcpListener, binding: Binding) -> Result<()> {
+    let (stream, _) = listener.accept().await?;
+    stream.set_nodelay(true)?;
+    let mut stream = BufReader::new(stream);
+    if binding == Binding::WebSocket {
+        accept_websocket_upgrade(&mut stream, Some("coap")).await?;
+    }
+    send(&mut stream, binding, &csm(binding), false).await?;
+
+    loop {
+        let Ok((_, message)) = receive(&mut stream, binding).await else {
+            return Ok(());
+        };
+        let Some((request, _)) = from_reliable(&message) else {
+            continue;
+        };
+        if request[1] == COAP_CSM {
+            continue;
+        }
+        if let Some(response) = respond(&request) {
+            send(&mut stream, binding, &to_reliable(&response, binding), false).await?;
+        }
+    }
+}
+
+/// Opens a WebSocket on `path` with `subprotocol`; returns the bytes of the request and response.
+pub(crate) async fn websocket_upgrade<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut BufReader<S>,
+    server: SocketAddr,
+    path: &str,
+    subprotocol: Option<&str>,
+) -> Result<usize> {
+    let mut nonce = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let key = base64(&nonce);
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\n{}Sec-WebSocket-Version: 13\r\n\r\n",
+        path,
+        server,
+        key,
+        subprotocol_header(subprotocol)
+    );
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+
+    let (response_bytes, headers) = read_http_head(stream).await?;
+    if !headers.first().is_some_and(|status| status.starts_with("HTTP/1.1 101")) {
+        return Err(anyhow!("WebSocket upgrade refused: {:?}", headers.first()));
+    }
+    let accept = header(&headers, "sec-websocket-accept").ok_or_else(|| anyhow!("no Sec-WebSocket-Accept"))?;
+    if accept != websocket_accept(&key) {
+        return Err(anyhow!("Sec-WebSocket-Accept does not match the key"));
+    }
+    Ok(request.len() + response_bytes)
+}
+
+pub(crate) async fn accept_websocket_upgrade<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut BufReader<S>,
+    subprotocol: Option<&str>,
+) -> Result<()> {
+    let (_, headers) = read_http_head(stream).await?;
+    let key = header(&headers, "sec-websocket-key").ok_or_else(|| anyhow!("no Sec-WebSocket-Key"))?;
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n{}\r\n",
+        websocket_accept(&key),
+        subprotocol_header(subprotocol)
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+fn subprotocol_header(subprotocol: Option<&str>) -> String {
+    subprotocol.map(|name| format!("Sec-WebSocket-Protocol: {}\r\n", name)).unwrap_or_default()
+}
+
+/// The lines of an HTTP head up to the blank line, and its size in bytes.
+async fn read_http_head<S: AsyncRead + Unpin>(stream: &mut BufReader<S>) -> Result<(usize, Vec<String>)> {
+    let (mut bytes, mut lines) = (0, Vec::new());
+    loop {
+        let mut line = String::new();
+        let read = stream.read_line(&mut line).await?;
+        if read == 0 {
+            return Err(anyhow!("connection closed during the WebSocket upgrade"));
+        }
+        bytes += read;
+        let line = line.trim_end();
+        if line.is_empty() {
+            return Ok((bytes, lines));
+        }
+        lines.push(line.to_string());
+    }
+}
+
+fn header(lines: &[String], name: &str) -> Option<String> {
+    lines.iter().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+    })
+}
+
+fn websocket_accept(key: &str) -> String {
+    base64(digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, format!("{}{}", key, WEBSOCKET_GUID).as_bytes()).as_ref())
+}
+
+/// One binary frame; clients must mask what they send.
+pub(crate) fn websocket_frame(payload: &[u8], masked: bool) -> Vec<u8> {
+    websocket_frame_of(WEBSOCKET_BINARY, payload, masked)
+}
+
+/// A frame with the given FIN and opcode byte.
+pub(crate) fn websocket_frame_of(opcode: u8, payload: &[u8], masked: bool) -> Vec<u8> {
+    let mask_bit = if masked { WEBSOCKET_MASKED } else { 0 };
+    let mut frame = vec![opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(mask_bit | len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(mask_bit | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(mask_bit | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    if masked {
+        let mut key = [0u8; 4];
+        rand::thread_rng().fill_bytes(&mut key);
+        frame.extend_from_slice(&key);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ key[i % 4]));
+    } else {
+        frame.extend_from_slice(payload);
+    }
+    frame
+}
+
+/// Reads one binary frame: the bytes read and the unmasked payload.
+pub(crate) async fn read_websocket_frame<S: AsyncRead + Unpin>(stream: &mut S) -> Result<(usize, Vec<u8>)> {
+    match read_websocket_frame_of(stream).await? {
+        (read, WEBSOCKET_BINARY, payload) => Ok((read, payload)),
+        (_, opcode, _) => Err(anyhow!("unexpected WebSocket frame {:#04x}", opcode)),
+    }
+}
+
+/// Reads one unfragmented frame of any kind: the bytes read, the FIN and opcode byte and the unmasked payload.
+pub(crate) async fn read_websocket_frame_of<S: AsyncRead + Unpin>(stream: &mut S) -> Result<(usize, u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    stream.read_exact(&mut head).await?;
+    let (len, extended) = match head[1] & 0x7F {
+        126 => (stream.read_u16().await? as usize, 2),
+        127 => (stream.read_u64().await? as usize, 8),
+        len => (len as usize, 0),
+    };
+    let mut key = [0u8; 4];
+    let masked = head[1] & WEBSOCKET_MASKED != 0;
+    if masked {
+        stream.read_exact(&mut key).await?;
+    }
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).await?;
+    if masked {
+        payload.iter_mut().enumerate().for_each(|(i, b)| *b ^= key[i % 4]);
+    }
+    Ok((2 + extended + if masked { key.len() } else { 0 } + len, head[0], payload))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn percentile(sorted: &[f64], quantile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    sorted[((sorted.len() - 1) as f64 * quantile).round() as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_binding_completes_the_lwm2m_exchanges() {
+        // RFC 6455's example handshake
+        assert_eq!(websocket_accept("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let metrics = runtime
+            .block_on(CoapBindingComparison::builder().rounds(3u32).build().analyze_coap_bindings())
+            .unwrap();
+
+        let [udp, tcp, websocket] = &metrics.bindings[..] else { panic!("{:?}", metrics.bindings) };
+        for binding in [udp, tcp, websocket] {
+            assert_eq!(binding.success_rate, 1.0, "{}", binding.binding);
+        }
+        assert_eq!((udp.setup_bytes, udp.retransmissions), (0, 0));
+        // No message ID or type, but a length field; WebSocket frames and client masks on top
+        assert!(tcp.framing_bytes_per_message < udp.framing_bytes_per_message);
+        assert!(websocket.framing_bytes_per_message > tcp.framing_bytes_per_message);
+        assert!(websocket.setup_bytes > tcp.setup_bytes && tcp.setup_bytes > TCP_HANDSHAKE_BYTES);
+        assert_eq!(metrics.deltas.len(), 2);
+    }
+}
@@ -0,0 +1,376 @@
+// matter-project/src/commissioning_faults.rs
+/*!
+Commissioning fault injection - detection time, retries and time-to-commission after wrong passcodes, PASE drops and CSR timeouts
+
+The commissioning flow is replayed in virtual time over a link with a fixed
+one-way delay, using the exchange sizes of the remote commissioning run. Lost
+messages are recovered by MRP retransmission; a failed PASE session is started
+again from PBKDFParamRequest, and a CSRRequest that times out is re-sent after
+re-arming the fail-safe, up to the commissioner's retry budget.
+*/
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::bdx::{matter_message, MRP_STANDALONE_ACK, PROTOCOL_SECURE_CHANNEL};
+use crate::builder::analyzer_builder;
+use crate::distributed::COMMISSIONING_STEPS;
+use crate::transport_modes::{IPV4_UDP_HEADER_BYTES, MRP_ACTIVE_RETRANS_TIMEOUT, MRP_BACKOFF_BASE, MRP_MAX_TRANSMISSIONS};
+
+/// Commissionees close the commissioning window after this many failed PASE attempts.
+const PASE_MAX_FAILED_ATTEMPTS: u32 = 20;
+/// ArmFailSafe request/response sent before re-trying a timed-out step.
+const ARM_FAIL_SAFE: (&str, u16, u16) = ("ArmFailSafe", 60, 40);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommissioningFault {
+    /// The commissioner proves the wrong passcode; Pake3 is answered with a failure StatusReport.
+    WrongPasscode,
+    /// Pake1 is lost on the way to the commissionee.
+    DroppedPaseMessage,
+    /// The commissionee acknowledges CSRRequest but never sends CSRResponse.
+    CsrTimeout,
+}
+
+impl CommissioningFault {
+    pub const ALL: [CommissioningFault; 3] =
+        [Self::WrongPasscode, Self::DroppedPaseMessage, Self::CsrTimeout];
+
+    /// Exchange of the commissioning flow the fault is injected into.
+    fn step(self) -> &'static str {
+        match self {
+            Self::WrongPasscode => "PASE_Pake3",
+            Self::DroppedPaseMessage => "PASE_Pake1",
+            Self::CsrTimeout => "CSRRequest",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CommissioningFaultConfig {
+    /// Number of consecutive failures injected per run.
+    pub failure_counts: Vec<u32>,
+    /// Times the commissioner restarts PASE or re-sends a step before giving up.
+    pub max_retries: u32,
+    pub one_way_delay: Duration,
+    /// Commissionee processing time before each response.
+    pub step_processing: Duration,
+    /// How long the commissioner waits for CSRResponse.
+    pub csr_response_timeout: Duration,
+}
+
+impl Default for CommissioningFaultConfig {
+    fn default() -> Self {
+        Self {
+            failure_counts: vec![1, 2, 3, 5],
+            max_retries: 3,
+            one_way_delay: Duration::from_millis(10),
+            step_processing: Duration::from_millis(5),
+            csr_response_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+analyzer_builder!(CommissioningFaultInjectorBuilder => CommissioningFaultInjector(CommissioningFaultConfig) {
+    failure_counts: Vec<u32>,
+    max_retries: u32,
+    one_way_delay: Duration,
+    step_processing: Duration,
+    csr_response_timeout: Duration,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommissioningFaultMetrics {
+    pub one_way_delay_ms: f64,
+    pub max_retries: u32,
+    pub happy_path: CommissioningRun,
+    pub faults: Vec<FaultResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FaultResult {
+    pub fault: CommissioningFault,
+    pub step: String,
+    pub runs: Vec<CommissioningRun>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommissioningRun {
+    pub failures_injected: u32,
+    pub commissioned: bool,
+    pub failure_reason: Option<String>,
+    /// From each injected failure until the commissioner notices it.
+    pub detection_time_mean_ms: f64,
+    pub mrp_retransmissions: u32,
+    pub pase_restarts: u32,
+    pub step_retries: u32,
+    pub messages: u32,
+    /// Message bytes with IPv4/UDP headers.
+    pub wire_bytes: usize,
+    /// Until CommissioningComplete is answered, or until the commissioner gives up.
+    pub time_to_commission_ms: f64,
+    /// Over the happy path.
+    pub extra_time_ms: f64,
+}
+
+pub struct CommissioningFaultInjector {
+    config: CommissioningFaultConfig,
+}
+
+impl CommissioningFaultInjector {
+    pub fn new(config: CommissioningFaultConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_commissioning_faults(&mut self) -> Result<CommissioningFaultMetrics> {
+        println!("\n🧯 Injecting Commissioning Faults (retry budget {})", self.config.max_retries);
+        println!("------------------------------------------------");
+
+        for fault in CommissioningFault::ALL {
+            if !COMMISSIONING_STEPS.iter().any(|(step, _, _)| *step == fault.step()) {
+                return Err(anyhow!("commissioning flow has no {} exchange", fault.step()));
+            }
+        }
+
+        let happy_path = self.simulate(None, 0);
+        println!("✅ Happy path: {} messages, {}B, {:.1}ms",
+                 happy_path.messages, happy_path.wire_bytes, happy_path.time_to_commission_ms);
+
+        let mut faults = Vec::new();
+        for fault in CommissioningFault::ALL {
+            let mut runs = Vec::new();
+            for &failures in &self.config.failure_counts {
+                let mut run = self.simulate(Some(fault), failures);
+                run.extra_time_ms = run.time_to_commission_ms - happy_path.time_to_commission_ms;
+                if run.commissioned {
+                    println!("✅ {:?} x{}: commissioned in {:.1}ms (detected after {:.1}ms, {} MRP retries, {} PASE restarts, {} step retries)",
+                             fault, failures, run.time_to_commission_ms, run.detection_time_mean_ms,
+                             run.mrp_retransmissions, run.pase_restarts, run.step_retries);
+                } else {
+                    println!("❌ {:?} x{}: gave up after {:.1}ms ({})",
+                             fault, failures, run.time_to_commission_ms, run.failure_reason.as_deref().unwrap_or("unknown"));
+                }
+                runs.push(run);
+            }
+            faults.push(FaultResult { fault, step: fault.step().to_string(), runs });
+        }
+
+        Ok(CommissioningFaultMetrics {
+            one_way_delay_ms: self.config.one_way_delay.as_secs_f64() * 1000.0,
+            max_retries: self.config.max_retries,
+            happy_path,
+            faults,
+        })
+    }
+
+    fn simulate(&self, fault: Option<CommissioningFault>, failures: u32) -> CommissioningRun {
+        let mut flow = Flow {
+            config: &self.config,
+            fault,
+            remaining: failures,
+            clock_ms: 0.0,
+            detections: Vec::new(),
+            mrp_retransmissions: 0,
+            messages: 0,
+            wire_bytes: 0,
+        };
+        let mut pase_restarts = 0;
+        let mut step_retries = 0;
+        let mut failed_pase_attempts = 0;
+        let mut failure_reason = None;
+
+        let mut index = 0;
+        while index < COMMISSIONING_STEPS.len() {
+            let outcome = flow.exchange(COMMISSIONING_STEPS[index]);
+            let restart_pase = match outcome {
+                Outcome::Completed => {
+                    index += 1;
+                    continue;
+                }
+                Outcome::PaseFailed => {
+                    failed_pase_attempts += 1;
+                    true
+                }
+                Outcome::ExchangeFailed => is_pase(COMMISSIONING_STEPS[index].0),
+                Outcome::StepTimedOut => false,
+            };
+
+            if failed_pase_attempts >= PASE_MAX_FAILED_ATTEMPTS {
+                failure_reason = Some("commissioning window closed after failed PASE attempts".to_string());
+                break;
+            }
+            if pase_restarts + step_retries >= self.config.max_retries {
+                failure_reason = Some(format!("{} failed after {} retries", COMMISSIONING_STEPS[index].0, self.config.max_retries));
+                break;
+            }
+            if restart_pase {
+                pase_restarts += 1;
+                index = 0;
+            } else {
+                step_retries += 1;
+                flow.exchange(ARM_FAIL_SAFE);
+            }
+        }
+
+        let detections = flow.detections.len().max(1) as f64;
+        CommissioningRun {
+            failures_injected: failures,
+            commissioned: failure_reason.is_none(),
+            failure_reason,
+            detection_time_mean_ms: flow.detections.iter().sum::<f64>() / detections,
+            mrp_retransmissions: flow.mrp_retransmissions,
+            pase_restarts,
+            step_retries,
+            messages: flow.messages,
+            wire_bytes: flow.wire_bytes,
+            time_to_commission_ms: flow.clock_ms,
+            extra_time_ms: 0.0,
+        }
+    }
+}
+
+fn is_pase(step: &str) -> bool {
+    matches!(step, "PBKDFParamRequest" | "PASE_Pake1" | "PASE_Pake3")
+}
+
+enum Outcome {
+    Completed,
+    /// The commissionee rejected the PASE proof.
+    PaseFailed,
+    /// MRP gave up on the request.
+    ExchangeFailed,
+    /// The request was acknowledged but the response never came.
+    StepTimedOut,
+}
+
+/// Virtual-time state of one commissioning attempt sequence.
+struct Flow<'a> {
+    config: &'a CommissioningFaultConfig,
+    fault: Option<CommissioningFault>,
+    remaining: u32,
+    clock_ms: f64,
+    detections: Vec<f64>,
+    mrp_retransmissions: u32,
+    messages: u32,
+    wire_bytes: usize,
+}
+
+impl Flow<'_> {
+    fn send(&mut self, bytes: u16) {
+        self.messages += 1;
+        self.wire_bytes += bytes as usize + IPV4_UDP_HEADER_BYTES;
+    }
+
+    /// Consumes one injected failure if the fault targets `step`.
+    fn inject(&mut self, step: &str) -> bool {
+        let hit = self.remaining > 0 && self.fault.is_some_and(|fault| fault.step() == step);
+        if hit {
+            self.remaining -= 1;
+        }
+        hit
+    }
+
+    fn exchange(&mut self, (step, request_bytes, response_bytes): (&str, u16, u16)) -> Outcome {
+        let delay_ms = self.config.one_way_delay.as_secs_f64() * 1000.0;
+        let processing_ms = self.config.step_processing.as_secs_f64() * 1000.0;
+
+        // The request is retransmitted by MRP until it gets through or MRP gives up
+        let mut delivered = false;
+        for attempt in 0..MRP_MAX_TRANSMISSIONS {
+            if attempt > 0 {
+                self.mrp_retransmissions += 1;
+            }
+            self.send(request_bytes);
+            if self.fault == Some(CommissioningFault::DroppedPaseMessage) && self.inject(step) {
+                let timeout_ms = MRP_ACTIVE_RETRANS_TIMEOUT.as_secs_f64() * 1000.0 * MRP_BACKOFF_BASE.powi(attempt as i32);
+                self.clock_ms += timeout_ms;
+                self.detections.push(timeout_ms);
+                continue;
+            }
+            delivered = true;
+            break;
+        }
+        if !delivered {
+            return Outcome::ExchangeFailed;
+        }
+        self.clock_ms += delay_ms + processing_ms;
+
+        if self.fault == Some(CommissioningFault::CsrTimeout) && self.inject(step) {
+            // Only a standalone ack comes back
+            self.messages += 1;
+            self.wire_bytes += matter_message(PROTOCOL_SECURE_CHANNEL, MRP_STANDALONE_ACK, &[]).len() + IPV4_UDP_HEADER_BYTES;
+            let timeout_ms = self.config.csr_response_timeout.as_secs_f64() * 1000.0;
+            self.clock_ms += timeout_ms - delay_ms - processing_ms;
+            self.detections.push(timeout_ms);
+            return Outcome::StepTimedOut;
+        }
+
+        self.send(response_bytes);
+        self.clock_ms += delay_ms;
+        if self.fault == Some(CommissioningFault::WrongPasscode) && self.inject(step) {
+            // Pake3 is answered with a failure StatusReport
+            self.detections.push(2.0 * delay_ms + processing_ms);
+            return Outcome::PaseFailed;
+        }
+        Outcome::Completed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn injector() -> CommissioningFaultInjector {
+        CommissioningFaultInjector::builder()
+            .one_way_delay(Duration::from_millis(10))
+            .step_processing(Duration::from_millis(5))
+            .max_retries(3u32)
+            .build()
+    }
+
+    #[test]
+    fn the_happy_path_is_one_round_trip_per_step() {
+        let run = injector().simulate(None, 0);
+        let steps = COMMISSIONING_STEPS.len();
+        let payload: usize = COMMISSIONING_STEPS.iter().map(|(_, request, response)| (request + response) as usize).sum();
+
+        assert!(run.commissioned);
+        assert_eq!(run.messages as usize, 2 * steps);
+        assert_eq!(run.wire_bytes, payload + 2 * steps * IPV4_UDP_HEADER_BYTES);
+        assert!((run.time_to_commission_ms - steps as f64 * 25.0).abs() < 1e-9);
+        assert_eq!(run.detection_time_mean_ms, 0.0);
+    }
+
+    #[test]
+    fn dropped_messages_are_recovered_by_mrp_until_it_gives_up() {
+        let happy = injector().simulate(None, 0);
+        let once = injector().simulate(Some(CommissioningFault::DroppedPaseMessage), 1);
+        assert!(once.commissioned);
+        assert_eq!((once.mrp_retransmissions, once.pase_restarts), (1, 0));
+        assert!((once.time_to_commission_ms - happy.time_to_commission_ms - 300.0).abs() < 1e-9);
+
+        // Every transmission of one Pake1 lost: PASE starts over from PBKDFParamRequest
+        let exhausted = injector().simulate(Some(CommissioningFault::DroppedPaseMessage), MRP_MAX_TRANSMISSIONS);
+        assert!(exhausted.commissioned);
+        assert_eq!((exhausted.mrp_retransmissions, exhausted.pase_restarts), (MRP_MAX_TRANSMISSIONS - 1, 1));
+    }
+
+    #[test]
+    fn failures_beyond_the_retry_budget_abort_commissioning() {
+        let within = injector().simulate(Some(CommissioningFault::WrongPasscode), 3);
+        assert!(within.commissioned);
+        assert_eq!(within.pase_restarts, 3);
+        assert!((within.detection_time_mean_ms - 25.0).abs() < 1e-9);
+
+        let beyond = injector().simulate(Some(CommissioningFault::WrongPasscode), 4);
+        assert!(!beyond.commissioned);
+        assert_eq!(beyond.failure_reason.as_deref(), Some("PASE_Pake3 failed after 3 retries"));
+
+        let csr = injector().simulate(Some(CommissioningFault::CsrTimeout), 1);
+        assert!(csr.commissioned);
+        assert_eq!((csr.step_retries, csr.pase_restarts), (1, 0));
+        assert_eq!(csr.detection_time_mean_ms, 30_000.0);
+    }
+}
@@ -0,0 +1,280 @@
+// matter-project/src/concurrency.rs
+/*!
+Concurrent connection scaling probe - how many simultaneous sessions hold up
+*/
+
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use ring::{agreement, rand};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::builder::analyzer_builder;
+
+const HELLO_LEN: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct ConcurrencyConfig {
+    pub max_connections: u32,
+    pub secure_sessions: bool,
+    pub level_timeout: Duration,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 1024,
+            secure_sessions: true,
+            level_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+analyzer_builder!(ConcurrencyProbeBuilder => ConcurrencyProbe(ConcurrencyConfig) {
+    max_connections: u32,
+    secure_sessions: bool,
+    level_timeout: Duration,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConcurrencyMetrics {
+    pub secure_sessions: bool,
+    pub connection_cap: u32,
+    pub max_stable_concurrency: u32,
+    pub first_failure_level: Option<u32>,
+    pub failure_reason: Option<String>,
+    /// Loopback: includes both the client and the server end of each session.
+    pub memory_per_connection_kb: f64,
+    pub levels: Vec<ConcurrencyLevel>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConcurrencyLevel {
+    pub connections: u32,
+    pub established: u32,
+    pub setup_time_ms: f64,
+    pub rss_delta_kb: i64,
+}
+
+pub struct ConcurrencyProbe {
+    config: ConcurrencyConfig,
+}
+
+impl ConcurrencyProbe {
+    pub fn new(config: ConcurrencyConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_concurrency(&mut self) -> Result<ConcurrencyMetrics> {
+        println!("\n🔀 Probing Concurrent Connection Scaling");
+        println!("----------------------------------------");
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let server_addr = listener.local_addr()?;
+        let server = tokio::spawn(serve_sessions(listener, self.config.secure_sessions));
+
+        let mut levels = Vec::new();
+        let mut max_stable_concurrency = 0;
+        let mut first_failure_level = None;
+        let mut failure_reason = None;
+        let mut memory_per_connection_kb = 0.0;
+
+        let mut level = 1u32;
+        while level <= self.config.max_connections {
+            let rss_before = resident_memory_kb();
+            let start = Instant::now();
+            let outcome = tokio::time::timeout(self.config.level_timeout, self.open_sessions(server_addr, level)).await;
+            let setup_time_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+            let (established, held, error) = match outcome {
+                Ok((streams, error)) => (streams.len() as u32, streams, error),
+                Err(_) => (0, Vec::new(), Some("level timed out".to_string())),
+            };
+            let rss_delta_kb = resident_memory_kb() as i64 - rss_before as i64;
+            drop(held);
+
+            levels.push(ConcurrencyLevel { connections: level, established, setup_time_ms, rss_delta_kb });
+            debug!("Level {}: {}/{} sessions in {:.2}ms", level, established, level, setup_time_ms);
+
+            if let Some(reason) = error {
+                warn!("⚠️ Concurrency probe failed at {} sessions: {}", level, reason);
+                first_failure_level = Some(level);
+                failure_reason = Some(reason);
+                break;
+            }
+
+            max_stable_concurrency = level;
+            if rss_delta_kb > 0 {
+                memory_per_connection_kb = rss_delta_kb as f64 / level as f64;
+            }
+
+            // Allow the server to release the previous level's sessions
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            level = level.saturating_mul(2);
+        }
+
+        server.abort();
+
+        let metrics = ConcurrencyMetrics {
+            secure_sessions: self.config.secure_sessions,
+            connection_cap: self.config.max_connections,
+            max_stable_concurrency,
+            first_failure_level,
+            failure_reason,
+            memory_per_connection_kb,
+            levels,
+        };
+
+        println!("✅ Max Stable Concurrency: {} sessions (cap {})", metrics.max_stable_concurrency, metrics.connection_cap);
+        println!("✅ Memory per Connection: {:.1}KB", metrics.memory_per_connection_kb);
+
+        Ok(metrics)
+    }
+
+    /// Opens `count` sessions concurrently and keeps them all open; returns the
+    /// established streams and the first error, if any.
+    async fn open_sessions(&self, server_addr: SocketAddr, count: u32) -> (Vec<TcpStream>, Option<String>) {
+        let secure = self.config.secure_sessions;
+        let handles: Vec<_> = (0..count)
+            .map(|_| tokio::spawn(open_session(server_addr, secure)))
+            .collect();
+
+        let mut streams = Vec::with_capacity(count as usize);
+        let mut error = None;
+
+        for handle in handles {
+            match handle.await {
+                Ok(Ok(stream)) => streams.push(stream),
+                Ok(Err(e)) => {
+                    error.get_or_insert_with(|| e.to_string());
+                }
+                Err(e) => {
+                    error.get_or_insert_with(|| e.to_string());
+                }
+            }
+        }
+
+        (streams, error)
+    }
+}
+
+/// Connects and completes one hello exchange; secure sessions exchange X25519
+/// public keys and derive a shared secret on both ends.
+async fn open_session(server_addr: SocketAddr, secure: bool) -> Result<TcpStream> {
+    let mut stream = TcpStream::connect(server_addr).await?;
+
+    if secure {
+        let rng = rand::SystemRandom::new();
+        let private = agreement::EphemeralPrivateKey::generate(&agreement::X25519, &rng)
+            .map_err(|_| anyhow!("key generation failed"))?;
+        let public = private.compute_public_key().map_err(|_| anyhow!("public key derivation failed"))?;
+        stream.write_all(public.as_ref()).await?;
+
+        let mut peer = [0u8; HELLO_LEN];
+        stream.read_exact(&mut peer).await?;
+        agreement::agree_ephemeral(private, &agreement::UnparsedPublicKey::new(&agreement::X25519, peer), |_| ())
+            .map_err(|_| anyhow!("key agreement failed"))?;
+    } else {
+        stream.write_all(&[0x5A; HELLO_LEN]).await?;
+        let mut reply = [0u8; HELLO_LEN];
+        stream.read_exact(&mut reply).await?;
+    }
+
+    Ok(stream)
+}
+
+async fn serve_sessions(listener: TcpListener, secure: bool) {
+    loop {
+        let (mut stream, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // Typically EMFILE at high levels; the client side records the failure
+                info!("Concurrency server accept failed: {}", e);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                continue;
+            }
+        };
+
+        tokio::spawn(async move {
+            let mut hello = [0u8; HELLO_LEN];
+            if stream.read_exact(&mut hello).await.is_err() {
+                return;
+            }
+
+            let reply = if secure {
+                let rng = rand::SystemRandom::new();
+                let Ok(private) = agreement::EphemeralPrivateKey::generate(&agreement::X25519, &rng) else {
+                    return;
+                };
+                let Ok(public) = private.compute_public_key() else {
+                    return;
+                };
+                let mut reply = [0u8; HELLO_LEN];
+                reply.copy_from_slice(public.as_ref());
+                let _ = agreement::agree_ephemeral(private, &agreement::UnparsedPublicKey::new(&agreement::X25519, hello), |_| ());
+                reply
+            } else {
+                hello
+            };
+
+            if stream.write_all(&reply).await.is_err() {
+                return;
+            }
+
+            // Hold the session until the client closes it
+            let mut sink = [0u8; 64];
+            while matches!(stream.read(&mut sink).await, Ok(n) if n > 0) {}
+        });
+    }
+}
+
+/// Resident set size of this process (Linux); 0 where unavailable.
+fn resident_memory_kb() -> u64 {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find(|line| line.starts_with("VmRSS:"))
+                .and_then(|line| line.split_whitespace().nth(1))
+                .and_then(|kb| kb.parse().ok())
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn levels_double_up_to_the_connection_cap() {
+        for secure_sessions in [false, true] {
+            let metrics = ConcurrencyProbe::builder()
+                .max_connections(6u32)
+                .secure_sessions(secure_sessions)
+                .build()
+                .analyze_concurrency()
+                .await
+                .unwrap();
+
+            let connections: Vec<u32> = metrics.levels.iter().map(|l| l.connections).collect();
+            assert_eq!(connections, [1, 2, 4]);
+            assert!(metrics.levels.iter().all(|l| l.established == l.connections));
+            assert_eq!(metrics.max_stable_concurrency, 4);
+            assert_eq!(metrics.first_failure_level, None);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn resident_memory_is_read_from_proc() {
+        let before = resident_memory_kb();
+        let held = vec![1u8; 64 << 20];
+        assert!(before > 0);
+        assert!(resident_memory_kb() >= before + 32 * 1024, "64MB touched, at least half resident");
+        drop(held);
+    }
+}
@@ -0,0 +1,414 @@
+// matter-project/src/congestion.rs
+/*!
+Congestion-control comparison - TCP-based protocols under cubic, BBR and Reno
+
+Matter over TCP and MQTT both inherit whatever congestion controller the
+host uses, and on a lossy link that choice can matter more than the
+protocol. Loss-based controllers (Reno, cubic) halve their window on every
+loss. BBR paces to its estimate of bandwidth and round trip and largely
+ignores random loss. This mode selects each controller per socket with
+`TCP_CONGESTION`, on both ends, and runs the same two workloads for each
+protocol:
+
+- a bulk upload of `transfer_bytes` in protocol messages: length-prefixed
+  BDX blocks for Matter, QoS 0 PUBLISHes for MQTT. It reports goodput,
+  plus the sender's retransmissions and smoothed RTT from `TCP_INFO`;
+- `round_trips` request/response exchanges: an Invoke and its response,
+  or a QoS 1 PUBLISH and its PUBACK.
+
+The sensitivity per protocol is the spread between the best and worst
+controller. On plain loopback, with no loss and microsecond round trips,
+the controllers barely differ. The comparison means something on a lossy
+link: run it under `--netns` with `--netem` or `--impairment`, and the
+result records that link. Controllers the kernel does not offer, or does
+not allow this user, are recorded as unavailable. Selecting one per
+socket is Linux-only.
+*/
+
+use analysis_core::netns::{self, Impairment};
+use analysis_core::tunables::read_sysctl;
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+
+use crate::bdx::{block, matter_message};
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{mqtt_packet, mqtt_publish, IM_INVOKE_REQUEST, IM_INVOKE_RESPONSE, MQTT_PUBACK, PROTOCOL_IM};
+use crate::transport_modes::frame;
+
+/// Data bytes per bulk message, a typical BDX block and firmware PUBLISH.
+const BULK_BLOCK_BYTES: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct CongestionConfig {
+    /// `TCP_CONGESTION` names, as in `tcp_available_congestion_control`.
+    pub algorithms: Vec<String>,
+    pub transfer_bytes: usize,
+    pub round_trips: u32,
+}
+
+impl Default for CongestionConfig {
+    fn default() -> Self {
+        Self {
+            algorithms: vec!["cubic".to_string(), "bbr".to_string(), "reno".to_string()],
+            transfer_bytes: 1024 * 1024,
+            round_trips: 50,
+        }
+    }
+}
+
+analyzer_builder!(CongestionAnalyzerBuilder => CongestionAnalyzer(CongestionConfig) {
+    algorithms: Vec<String>,
+    transfer_bytes: usize,
+    round_trips: u32,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CongestionMetrics {
+    /// Netem on the link, when run in an impaired namespace; `None` is plain loopback.
+    pub link: Option<Impairment>,
+    /// What the kernel offers, from `tcp_available_congestion_control`.
+    pub available_algorithms: Vec<String>,
+    pub transfer_bytes: usize,
+    pub round_trips: u32,
+    pub results: Vec<CongestionResult>,
+    pub sensitivity: Vec<CongestionSensitivity>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CongestionResult {
+    pub protocol: String,
+    pub algorithm: String,
+    /// Whether the controller could be selected; the measurements are zero when not.
+    pub available: bool,
+    pub error: Option<String>,
+    pub goodput_mbps: f64,
+    pub transfer_ms: f64,
+    /// Segments the bulk sender retransmitted, from `TCP_INFO`.
+    pub retransmissions: Option<u32>,
+    /// The bulk sender's smoothed RTT at the end of the transfer.
+    pub smoothed_rtt_ms: Option<f64>,
+    pub median_latency_ms: f64,
+    pub p95_latency_ms: f64,
+}
+
+/// How much a protocol's results move with the controller, over the available ones.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CongestionSensitivity {
+    pub protocol: String,
+    pub best_goodput_algorithm: Option<String>,
+    /// (best - worst) / best goodput.
+    pub goodput_spread_ratio: f64,
+    pub best_latency_algorithm: Option<String>,
+    /// (worst - best) / best p95 latency.
+    pub latency_spread_ratio: f64,
+}
+
+/// One protocol's bulk message and request/response pair, framed as on the wire.
+struct Workload {
+    protocol: &'static str,
+    bulk_message: Vec<u8>,
+    request: Vec<u8>,
+    response: Vec<u8>,
+}
+
+fn workloads() -> Vec<Workload> {
+    let data = [0xA5u8; BULK_BLOCK_BYTES];
+    vec![
+        Workload {
+            protocol: "Matter",
+            bulk_message: frame(&block(1, &data, false)),
+            request: frame(&matter_message(PROTOCOL_IM, IM_INVOKE_REQUEST, &[0x15; 24])),
+            response: frame(&matter_message(PROTOCOL_IM, IM_INVOKE_RESPONSE, &[0x15; 8])),
+        },
+        Workload {
+            protocol: "MQTT",
+            bulk_message: mqtt_publish("firmware/block", None, &data),
+            request: mqtt_publish("home/light/set", Some(1), b"on"),
+            response: mqtt_packet(MQTT_PUBACK, &1u16.to_be_bytes()),
+        },
+    ]
+}
+
+pub struct CongestionAnalyzer {
+    config: CongestionConfig,
+}
+
+impl CongestionAnalyzer {
+    pub fn new(config: CongestionConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_congestion(&mut self) -> Result<CongestionMetrics> {
+        let link = netns::current().and_then(|namespace| namespace.impairment);
+        println!("\n🚦 Comparing TCP Congestion Controllers ({}) on {}",
+                 self.config.algorithms.join(", "),
+                 if link.is_some() { "the impaired namespace link" } else { "plain loopback" });
+        println!("------------------------------------------------");
+
+        let workloads = workloads();
+        let mut results = Vec::new();
+        for algorithm in &self.config.algorithms {
+            for workload in &workloads {
+                let result = match probe(algorithm) {
+                    Ok(()) => self.run(workload, algorithm).await?,
+                    Err(e) => {
+                        debug!("{} unavailable: {:#}", algorithm, e);
+                        CongestionResult {
+                            protocol: workload.protocol.to_string(),
+                            algorithm: algorithm.clone(),
+                            available: false,
+                            error: Some(format!("{:#}", e)),
+                            goodput_mbps: 0.0,
+                            transfer_ms: 0.0,
+                            retransmissions: None,
+                            smoothed_rtt_ms: None,
+                            median_latency_ms: 0.0,
+                            p95_latency_ms: 0.0,
+                        }
+                    }
+                };
+                results.push(result);
+            }
+        }
+
+        let sensitivity: Vec<CongestionSensitivity> =
+            workloads.iter().map(|workload| sensitivity(workload.protocol, &results)).collect();
+        // Reported once per controller rather than per protocol
+        for result in results.iter().filter(|result| !result.available && result.protocol == workloads[0].protocol) {
+            println!("🚫 {} unavailable: {}", result.algorithm, result.error.as_deref().unwrap_or("-"));
+        }
+        for s in &sensitivity {
+            let rows = results.iter().filter(|result| result.protocol == s.protocol && result.available);
+            let summary: Vec<String> = rows
+                .map(|result| format!("{} {:.1}Mbps/{:.2}ms p95", result.algorithm, result.goodput_mbps, result.p95_latency_ms))
+                .collect();
+            println!("✅ {}: {} (goodput spread {:.0}%)", s.protocol, summary.join(", "), s.goodput_spread_ratio * 100.0);
+        }
+
+        Ok(CongestionMetrics {
+            link,
+            available_algorithms: read_sysctl("net.ipv4.tcp_available_congestion_control")
+                .map(|names| names.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default(),
+            transfer_bytes: self.config.transfer_bytes,
+            round_trips: self.config.round_trips,
+            results,
+            sensitivity,
+        })
+    }
+
+    async fn run(&self, workload: &Workload, algorithm: &str) -> Result<CongestionResult> {
+        let messages = self.config.transfer_bytes.div_ceil(workload.bulk_message.len()).max(1);
+        let (transfer_ms, tcp_info) = bulk_upload(algorithm, &workload.bulk_message, messages).await?;
+        let mut latencies = round_trips(algorithm, workload, self.config.round_trips).await?;
+        latencies.sort_by(|a, b| a.total_cmp(b));
+
+        let sent_bytes = messages * workload.bulk_message.len();
+        Ok(CongestionResult {
+            protocol: workload.protocol.to_string(),
+            algorithm: algorithm.to_string(),
+            available: true,
+            error: None,
+            goodput_mbps: sent_bytes as f64 * 8.0 / (transfer_ms / 1000.0).max(f64::EPSILON) / 1_000_000.0,
+            transfer_ms,
+            retransmissions: tcp_info.map(|(retransmissions, _)| retransmissions),
+            smoothed_rtt_ms: tcp_info.map(|(_, rtt_ms)| rtt_ms),
+            median_latency_ms: percentile(&latencies, 0.5),
+            p95_latency_ms: percentile(&latencies, 0.95),
+        })
+    }
+}
+
+/// Sends `messages` copies of `message` and waits for the receiver's
+/// one-byte acknowledgement of the last; returns the time taken and the
+/// sender's `TCP_INFO`.
+async fn bulk_upload(algorithm: &str, message: &[u8], messages: usize) -> Result<(f64, Option<(u32, f64)>)> {
+    let listener = bind_listener(algorithm)?;
+    let server_addr = listener.local_addr()?;
+    let total = messages * message.len();
+
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        let mut buffer = vec![0u8; 64 * 1024];
+        let mut received = 0;
+        while received < total {
+            let len = stream.read(&mut buffer).await?;
+            if len == 0 {
+                return Err(anyhow!("sender closed after {} of {} bytes", received, total));
+            }
+            received += len;
+        }
+        stream.write_all(&[0x06]).await?;
+        anyhow::Ok(())
+    });
+
+    let mut stream = connect(server_addr, algorithm).await?;
+    let start = Instant::now();
+    for _ in 0..messages {
+        stream.write_all(message).await?;
+    }
+    let mut ack = [0u8; 1];
+    stream.read_exact(&mut ack).await?;
+    let transfer_ms = start.elapsed().as_secs_f64() * 1000.0;
+    let tcp_info = tcp_info(&stream);
+
+    server.await??;
+    Ok((transfer_ms, tcp_info))
+}
+
+/// Request/response exchanges over one connection; per-exchange latency in ms.
+async fn round_trips(algorithm: &str, workload: &Workload, count: u32) -> Result<Vec<f64>> {
+    let listener = bind_listener(algorithm)?;
+    let server_addr = listener.local_addr()?;
+    let (request_len, response) = (workload.request.len(), workload.response.clone());
+
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        stream.set_nodelay(true)?;
+        let mut request = vec![0u8; request_len];
+        while stream.read_exact(&mut request).await.is_ok() {
+            stream.write_all(&response).await?;
+        }
+        anyhow::Ok(())
+    });
+
+    let mut stream = connect(server_addr, algorithm).await?;
+    stream.set_nodelay(true)?;
+    let mut response = vec![0u8; workload.response.len()];
+    let mut latencies = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let start = Instant::now();
+        stream.write_all(&workload.request).await?;
+        stream.read_exact(&mut response).await?;
+        latencies.push(start.elapsed().as_secs_f64() * 1000.0);
+    }
+
+    drop(stream);
+    server.await??;
+    Ok(latencies)
+}
+
+/// Whether this kernel and user can select the controller at all.
+fn probe(algorithm: &str) -> Result<()> {
+    set_congestion(&TcpSocket::new_v4()?, algorithm)
+}
+
+// Accepted connections inherit the listener's controller
+fn bind_listener(algorithm: &str) -> Result<TcpListener> {
+    let socket = TcpSocket::new_v4()?;
+    set_congestion(&socket, algorithm)?;
+    socket.bind("127.0.0.1:0".parse()?)?;
+    Ok(socket.listen(16)?)
+}
+
+async fn connect(addr: SocketAddr, algorithm: &str) -> Result<TcpStream> {
+    let socket = TcpSocket::new_v4()?;
+    set_congestion(&socket, algorithm)?;
+    Ok(socket.connect(addr).await?)
+}
+
+#[cfg(target_os = "linux")]
+fn set_congestion(socket: &TcpSocket, algorithm: &str) -> Result<()> {
+    socket2::SockRef::from(socket)
+        .set_tcp_congestion(algorithm.as_bytes())
+        .map_err(|e| anyhow!("TCP_CONGESTION {}: {}", algorithm, e))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_congestion(_socket: &TcpSocket, algorithm: &str) -> Result<()> {
+    Err(anyhow!("selecting {} per socket needs Linux's TCP_CONGESTION", algorithm))
+}
+
+/// Total retransmitted segments and smoothed RTT in ms.
+#[cfg(target_os = "linux")]
+pub(crate) fn tcp_info(stream: &TcpStream) -> Option<(u32, f64)> {
+    use std::os::fd::AsRawFd;
+
+    let mut info: libc::tcp_info = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            &mut info as *mut libc::tcp_info as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    (rc == 0).then(|| (info.tcpi_total_retrans, info.tcpi_rtt as f64 / 1000.0))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn tcp_info(_stream: &TcpStream) -> Option<(u32, f64)> {
+    None
+}
+
+fn sensitivity(protocol: &str, results: &[CongestionResult]) -> CongestionSensitivity {
+    let rows: Vec<&CongestionResult> =
+        results.iter().filter(|result| result.protocol == protocol && result.available).collect();
+    let best_goodput = rows.iter().copied().max_by(|a, b| a.goodput_mbps.total_cmp(&b.goodput_mbps));
+    let worst_goodput = rows.iter().map(|result| result.goodput_mbps).fold(f64::INFINITY, f64::min);
+    let best_latency = rows.iter().copied().min_by(|a, b| a.p95_latency_ms.total_cmp(&b.p95_latency_ms));
+    let worst_latency = rows.iter().map(|result| result.p95_latency_ms).fold(0.0, f64::max);
+
+    CongestionSensitivity {
+        protocol: protocol.to_string(),
+        best_goodput_algorithm: best_goodput.map(|result| result.algorithm.clone()),
+        goodput_spread_ratio: best_goodput
+            .filter(|best| best.goodput_mbps > 0.0)
+            .map_or(0.0, |best| (best.goodput_mbps - worst_goodput) / best.goodput_mbps),
+        best_latency_algorithm: best_latency.map(|result| result.algorithm.clone()),
+        latency_spread_ratio: best_latency
+            .filter(|best| best.p95_latency_ms > 0.0)
+            .map_or(0.0, |best| (worst_latency - best.p95_latency_ms) / best.p95_latency_ms),
+    }
+}
+
+fn percentile(sorted: &[f64], quantile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((sorted.len() - 1) as f64 * quantile).round() as usize;
+    sorted[index]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_controller_is_measured_or_marked_unavailable() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let metrics = runtime
+            .block_on(
+                CongestionAnalyzer::builder()
+                    .algorithms(vec!["reno".to_string(), "no-such-controller".to_string()])
+                    .transfer_bytes(256 * 1024usize)
+                    .round_trips(10u32)
+                    .build()
+                    .analyze_congestion(),
+            )
+            .unwrap();
+
+        assert_eq!(metrics.results.len(), 4);
+        for result in &metrics.results {
+            if result.algorithm == "reno" && cfg!(target_os = "linux") {
+                assert!(result.available, "{:?}", result.error);
+                assert!(result.goodput_mbps > 0.0 && result.p95_latency_ms >= result.median_latency_ms);
+                assert!(result.retransmissions.is_some());
+            } else {
+                assert!(!result.available && result.error.is_some());
+            }
+        }
+        // A single available controller has nothing to spread against
+        for s in &metrics.sensitivity {
+            assert_eq!(s.goodput_spread_ratio, 0.0);
+            assert_eq!(s.best_goodput_algorithm.is_some(), cfg!(target_os = "linux"));
+        }
+    }
+}
@@ -0,0 +1,153 @@
+// matter-project/src/connection_setup.rs
+/*!
+TCP connection setup - the time to a usable connection, split into TCP, security and first application byte
+
+Connecting to a listener only times the TCP handshake, which is rarely the
+largest part of getting an answer over a fresh connection. [`ConnectionSetup`]
+opens a connection to a device task on loopback and times three stages in turn:
+
+- the TCP three-way handshake, until `connect` returns with the SYN-ACK
+  received. On Linux the kernel's first RTT sample, taken from the SYN and
+  SYN-ACK, is recorded alongside;
+- the security handshake: the session's ECDH P-256 key exchange over the
+  new connection, until the controller has derived its keys. The device
+  generates its key on the critical path, as a real device would;
+- the first application byte: from sending the first encrypted request
+  until the first byte of the device's response arrives.
+
+The response is then read in full and checked, so a stage that only looked
+fast because it failed is not reported.
+*/
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::calibration::CalibrationMetrics;
+use crate::congestion::tcp_info;
+use crate::secure_session::{ephemeral_key, Endpoint, MESSAGE_HEADER_BYTES};
+use crate::transport_modes::{frame, TCP_LENGTH_PREFIX_BYTES};
+
+const SESSION_ID: u16 = 1;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConnectionSetup {
+    /// `connect` until the SYN-ACK arrived.
+    pub tcp_handshake_ms: f64,
+    /// The kernel's RTT sample from the SYN and SYN-ACK; Linux only.
+    pub syn_rtt_ms: Option<f64>,
+    /// Key exchange over the connection until the controller's keys were derived.
+    pub security_handshake_ms: f64,
+    /// Both handshake messages, with their length prefixes.
+    pub security_handshake_bytes: usize,
+    /// First encrypted request sent until the first byte of its response arrived.
+    pub first_application_byte_ms: f64,
+    /// `connect` until the first application byte.
+    pub total_ms: f64,
+}
+
+impl ConnectionSetup {
+    /// Connects to a device task that answers `request` with `response`, timing each stage.
+    pub async fn measure(request: &[u8], response: &[u8]) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let device_addr = listener.local_addr()?;
+        let device = tokio::spawn(device(listener, request.to_vec(), response.to_vec()));
+
+        let start = Instant::now();
+        let mut stream = TcpStream::connect(device_addr).await?;
+        let connected = Instant::now();
+        stream.set_nodelay(true)?;
+        let syn_rtt_ms = tcp_info(&stream).map(|(_, rtt_ms)| rtt_ms);
+
+        let (ephemeral, hello) = ephemeral_key()?;
+        stream.write_all(&frame(&hello)).await?;
+        let device_hello = read_frame(&mut stream).await?;
+        let controller = Endpoint::agree(ephemeral, handshake_public(&device_hello)?, true)?;
+        let secured = Instant::now();
+
+        stream.write_all(&frame(&controller.seal(SESSION_ID, 1, request)?)).await?;
+        let mut length = [0u8; TCP_LENGTH_PREFIX_BYTES];
+        stream.read_exact(&mut length[..1]).await?;
+        let first_byte = Instant::now();
+        stream.read_exact(&mut length[1..]).await?;
+        let mut sealed = vec![0u8; u32::from_le_bytes(length) as usize];
+        stream.read_exact(&mut sealed).await?;
+        if controller.open(sealed)? != response {
+            return Err(anyhow!("controller decrypted a different response"));
+        }
+        device.await??;
+
+        let ms = |from: Instant, to: Instant| (to - from).as_secs_f64() * 1000.0;
+        Ok(Self {
+            tcp_handshake_ms: ms(start, connected),
+            syn_rtt_ms,
+            security_handshake_ms: ms(connected, secured),
+            security_handshake_bytes: 2 * (TCP_LENGTH_PREFIX_BYTES + hello.len()),
+            first_application_byte_ms: ms(secured, first_byte),
+            total_ms: ms(start, first_byte),
+        })
+    }
+
+    /// Each stage with the harness overhead removed; the kernel's RTT sample is left as it is.
+    pub fn corrected(self, calibration: &CalibrationMetrics) -> Self {
+        let tcp_handshake_ms = calibration.correct_ms(self.tcp_handshake_ms);
+        let security_handshake_ms = calibration.correct_ms(self.security_handshake_ms);
+        let first_application_byte_ms = calibration.correct_ms(self.first_application_byte_ms);
+        Self {
+            tcp_handshake_ms,
+            security_handshake_ms,
+            first_application_byte_ms,
+            total_ms: tcp_handshake_ms + security_handshake_ms + first_application_byte_ms,
+            ..self
+        }
+    }
+}
+
+/// Accepts one connection, completes the key exchange and answers one request.
+async fn device(listener: TcpListener, request: Vec<u8>, response: Vec<u8>) -> Result<()> {
+    let (mut stream, _) = listener.accept().await?;
+    stream.set_nodelay(true)?;
+
+    let controller_hello = read_frame(&mut stream).await?;
+    let (ephemeral, hello) = ephemeral_key()?;
+    stream.write_all(&frame(&hello)).await?;
+    let device = Endpoint::agree(ephemeral, handshake_public(&controller_hello)?, false)?;
+
+    if device.open(read_frame(&mut stream).await?)? != request {
+        return Err(anyhow!("device decrypted a different request"));
+    }
+    stream.write_all(&frame(&device.seal(SESSION_ID, 1, &response)?)).await?;
+    Ok(())
+}
+
+async fn read_frame(stream: &mut TcpStream) -> Result<Vec<u8>> {
+    let mut length = [0u8; TCP_LENGTH_PREFIX_BYTES];
+    stream.read_exact(&mut length).await?;
+    let mut message = vec![0u8; u32::from_le_bytes(length) as usize];
+    stream.read_exact(&mut message).await?;
+    Ok(message)
+}
+
+fn handshake_public(message: &[u8]) -> Result<&[u8]> {
+    message.get(MESSAGE_HEADER_BYTES..).ok_or_else(|| anyhow!("handshake message of {} bytes is too short", message.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_stages_add_up_to_the_first_application_byte() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let setup = runtime.block_on(ConnectionSetup::measure(b"read descriptor", b"report")).unwrap();
+
+        let stages = setup.tcp_handshake_ms + setup.security_handshake_ms + setup.first_application_byte_ms;
+        assert!((setup.total_ms - stages).abs() < 1e-6);
+        assert!(setup.tcp_handshake_ms > 0.0 && setup.security_handshake_ms > 0.0);
+        // Two length-prefixed P-256 public keys behind a session header
+        assert_eq!(setup.security_handshake_bytes, 2 * (TCP_LENGTH_PREFIX_BYTES + MESSAGE_HEADER_BYTES + 65));
+        assert_eq!(setup.syn_rtt_ms.is_some(), cfg!(target_os = "linux"));
+    }
+}
@@ -0,0 +1,373 @@
+// matter-project/src/contention.rs
+/*!
+Wi-Fi airtime contention - interaction latency and loss as competing stations load the channel
+
+Loopback measurements never wait for the medium. In a home, every frame
+first has to win the channel against the other stations on it. This is a
+discrete-event model of 802.11 DCF (CSMA/CA) on a 20 MHz 802.11n channel.
+The controller and the device are two stations, and N more stations send
+Poisson background traffic. Each station waits DIFS plus a random backoff
+from its contention window before sending. Two stations whose backoffs end
+in the same slot collide, double their windows and try again, up to the
+retry limit. The frames of each protocol's exchange are the real
+encodings of the scenario interaction, IP and UDP/TCP headers included. A
+message goes out once the previous one has been delivered. An interaction
+is lost when one of its frames is dropped after the retry limit; that is
+loss at the MAC, before any MRP, CoAP or TCP retransmission.
+
+Stations that get a frame while others count down join at the next
+contention round. Capture, rate adaptation and hidden stations are not
+modelled. Every protocol sees the same background arrivals (same seed), so
+differences come from the frames alone.
+*/
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::builder::analyzer_builder;
+use crate::scenarios::{interaction_frames, DeviceScenario};
+use crate::workload::XorShift;
+
+// 802.11n in 2.4 GHz, 20 MHz, HT-mixed format; times in µs
+const SLOT_US: f64 = 9.0;
+const SIFS_US: f64 = 10.0;
+const DIFS_US: f64 = SIFS_US + 2.0 * SLOT_US;
+const HT_PREAMBLE_US: f64 = 36.0;
+/// Legacy preamble plus 14 bytes at the 24 Mbit/s basic rate.
+const ACK_US: f64 = 28.0;
+/// QoS data header, LLC/SNAP and FCS.
+const MAC_OVERHEAD_BYTES: usize = 38;
+const CW_MIN: u32 = 15;
+const CW_MAX: u32 = 1023;
+/// Background frames a station holds before it starts dropping new ones.
+const BACKGROUND_QUEUE_FRAMES: usize = 64;
+
+const CONTROLLER: usize = 0;
+const DEVICE: usize = 1;
+
+#[derive(Debug, Clone)]
+pub struct ContentionConfig {
+    /// Competing stations besides the controller and the device, one simulation each.
+    pub station_counts: Vec<usize>,
+    /// Mean background frames per second sent by each competing station.
+    pub background_frames_per_s: f64,
+    pub background_frame_bytes: usize,
+    pub phy_rate_mbps: f64,
+    /// Transmissions per frame before it is dropped.
+    pub retry_limit: u32,
+    pub scenario: DeviceScenario,
+    pub interaction: String,
+    /// A new interaction starts this often.
+    pub interaction_interval: Duration,
+    /// Simulated time per station count.
+    pub duration: Duration,
+    pub seed: u64,
+}
+
+impl Default for ContentionConfig {
+    fn default() -> Self {
+        Self {
+            station_counts: vec![0, 5, 10, 20, 40],
+            background_frames_per_s: 100.0,
+            background_frame_bytes: 1500,
+            phy_rate_mbps: 65.0,
+            retry_limit: 7,
+            scenario: DeviceScenario::Light,
+            interaction: "turn_on".to_string(),
+            interaction_interval: Duration::from_millis(100),
+            duration: Duration::from_secs(60),
+            seed: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+}
+
+analyzer_builder!(ContentionModelBuilder => ContentionModel(ContentionConfig) {
+    station_counts: Vec<usize>,
+    background_frames_per_s: f64,
+    background_frame_bytes: usize,
+    phy_rate_mbps: f64,
+    retry_limit: u32,
+    scenario: DeviceScenario,
+    interaction: String,
+    interaction_interval: Duration,
+    duration: Duration,
+    seed: u64,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContentionMetrics {
+    pub model: String,
+    pub phy_rate_mbps: f64,
+    pub background_frames_per_s: f64,
+    pub background_frame_bytes: usize,
+    pub scenario: DeviceScenario,
+    pub interaction: String,
+    pub simulated_s: f64,
+    pub protocols: Vec<ContentionProtocolResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContentionProtocolResult {
+    pub protocol: String,
+    pub frames_per_interaction: usize,
+    pub wire_bytes_per_interaction: usize,
+    pub points: Vec<ContentionPoint>,
+    /// p95 latency with the most competing stations over that with the fewest.
+    pub p95_latency_inflation: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContentionPoint {
+    pub competing_stations: usize,
+    pub interactions: usize,
+    pub completed: usize,
+    /// Interactions that lost a frame after the retry limit.
+    pub loss_ratio: f64,
+    pub latency_mean_ms: f64,
+    pub latency_p95_ms: f64,
+    pub latency_p99_ms: f64,
+    /// Retransmissions per frame of the protocol's exchanges.
+    pub retries_per_frame: f64,
+    /// Share of the protocol's transmissions that collided.
+    pub collision_ratio: f64,
+    /// Share of simulated time the protocol's frames occupied the channel.
+    pub airtime_share: f64,
+    /// Share of simulated time the channel was busy with anyone's frames.
+    pub channel_utilization: f64,
+}
+
+pub struct ContentionModel {
+    config: ContentionConfig,
+}
+
+impl ContentionModel {
+    pub fn new(config: ContentionConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_contention(&mut self) -> Result<ContentionMetrics> {
+        println!("\n📶 Analyzing Wi-Fi Airtime Contention ({} {}, {:?} competing stations)",
+                 self.config.scenario.name(), self.config.interaction, self.config.station_counts);
+        println!("------------------------------------------------");
+        if self.config.phy_rate_mbps <= 0.0 || self.config.interaction_interval.is_zero() {
+            return Err(anyhow!("contention needs a positive PHY rate and interaction interval"));
+        }
+
+        let mut protocols = Vec::new();
+        for (protocol, frames) in interaction_frames(self.config.scenario, &self.config.interaction)? {
+            let mut points = Vec::new();
+            for &stations in &self.config.station_counts {
+                let point = self.simulate(&frames, stations);
+                println!("✅ {} with {} stations: {:.2}ms p95, {:.1}% lost, {:.2} retries/frame, channel {:.0}% busy",
+                         protocol, stations, point.latency_p95_ms, point.loss_ratio * 100.0,
+                         point.retries_per_frame, point.channel_utilization * 100.0);
+                points.push(point);
+            }
+            let p95 = |point: Option<&ContentionPoint>| point.map_or(0.0, |p| p.latency_p95_ms);
+            let (first, last) = (p95(points.first()), p95(points.last()));
+            protocols.push(ContentionProtocolResult {
+                protocol: protocol.to_string(),
+                frames_per_interaction: frames.len(),
+                wire_bytes_per_interaction: frames.iter().sum(),
+                p95_latency_inflation: if first > 0.0 { last / first } else { 0.0 },
+                points,
+            });
+        }
+
+        Ok(ContentionMetrics {
+            model: "802.11n DCF, 2.4 GHz 20 MHz, Poisson background".to_string(),
+            phy_rate_mbps: self.config.phy_rate_mbps,
+            background_frames_per_s: self.config.background_frames_per_s,
+            background_frame_bytes: self.config.background_frame_bytes,
+            scenario: self.config.scenario,
+            interaction: self.config.interaction.clone(),
+            simulated_s: self.config.duration.as_secs_f64(),
+            protocols,
+        })
+    }
+
+    /// One simulation of the exchange `frames` against `competing` background stations.
+    pub fn simulate(&self, frames: &[usize], competing: usize) -> ContentionPoint {
+        let config = &self.config;
+        let end_us = config.duration.as_secs_f64() * 1e6;
+        let interval_us = config.interaction_interval.as_secs_f64() * 1e6;
+        let background_mean_us = if config.background_frames_per_s > 0.0 { 1e6 / config.background_frames_per_s } else { f64::INFINITY };
+        let mut rng = XorShift(config.seed.max(1));
+        let mut stations: Vec<Station> = (0..2 + competing).map(|_| Station::default()).collect();
+        let mut next_background: Vec<f64> = (0..competing).map(|_| rng.exponential(background_mean_us)).collect();
+        let mut next_interaction_us = 0.0;
+        let mut interactions: Vec<Interaction> = Vec::new();
+        let mut stats = Totals::default();
+        let mut now_us = 0.0;
+
+        while now_us < end_us {
+            // Arrivals up to now join this contention round
+            while next_interaction_us <= now_us && next_interaction_us < end_us {
+                let id = interactions.len();
+                interactions.push(Interaction { started_us: next_interaction_us, finished_us: None, lost: false });
+                stations[CONTROLLER].queue.push_back(Frame { bytes: frames[0], interaction: Some((id, 0)) });
+                next_interaction_us += interval_us;
+            }
+            for (index, next) in next_background.iter_mut().enumerate() {
+                while *next <= now_us {
+                    let queue = &mut stations[2 + index].queue;
+                    if queue.len() < BACKGROUND_QUEUE_FRAMES {
+                        queue.push_back(Frame { bytes: config.background_frame_bytes, interaction: None });
+                    }
+                    *next += rng.exponential(background_mean_us);
+                }
+            }
+
+            let mut active: Vec<usize> = (0..stations.len()).filter(|&s| !stations[s].queue.is_empty()).collect();
+            if active.is_empty() {
+                let next_background = next_background.iter().copied().fold(f64::INFINITY, f64::min);
+                now_us = next_interaction_us.min(next_background);
+                if !now_us.is_finite() {
+                    break;
+                }
+                continue;
+            }
+            for &s in &active {
+                let station = &mut stations[s];
+                if station.backoff.is_none() {
+                    station.backoff = Some(rng.below(station.cw as usize + 1) as u32);
+                }
+            }
+
+            let slots = active.iter().filter_map(|&s| stations[s].backoff).min().unwrap_or(0);
+            now_us += DIFS_US + slots as f64 * SLOT_US;
+            for &s in &active {
+                if let Some(backoff) = stations[s].backoff.as_mut() {
+                    *backoff -= slots;
+                }
+            }
+            active.retain(|&s| stations[s].backoff == Some(0));
+
+            let airtimes: Vec<f64> = active.iter().map(|&s| self.airtime_us(stations[s].queue[0].bytes)).collect();
+            let busy_us = airtimes.iter().copied().fold(0.0, f64::max);
+            stats.busy_us += busy_us;
+            let collided = active.len() > 1;
+            now_us += busy_us + SIFS_US + ACK_US;
+
+            for (&s, &airtime) in active.iter().zip(&airtimes) {
+                let ours = stations[s].queue[0].interaction.is_some();
+                if ours {
+                    stats.transmissions += 1;
+                    stats.collisions += collided as usize;
+                    stats.airtime_us += airtime;
+                }
+                let station = &mut stations[s];
+                station.backoff = None;
+                if collided && station.retries + 1 < config.retry_limit {
+                    station.retries += 1;
+                    station.cw = (2 * (station.cw + 1) - 1).min(CW_MAX);
+                    if ours {
+                        stats.retries += 1;
+                    }
+                    continue;
+                }
+
+                // Delivered, or dropped after the retry limit
+                let frame = station.queue.pop_front().expect("a transmitting station has a frame");
+                station.retries = 0;
+                station.cw = CW_MIN;
+                let Some((id, message)) = frame.interaction else { continue };
+                stats.frames += 1;
+                if collided {
+                    interactions[id].lost = true;
+                } else if message + 1 < frames.len() {
+                    // Requests and responses alternate between controller and device
+                    let next = if (message + 1) % 2 == 0 { CONTROLLER } else { DEVICE };
+                    stations[next].queue.push_back(Frame { bytes: frames[message + 1], interaction: Some((id, message + 1)) });
+                } else {
+                    interactions[id].finished_us = Some(now_us);
+                }
+            }
+        }
+
+        let mut latencies: Vec<f64> = interactions
+            .iter()
+            .filter_map(|i| i.finished_us.map(|finished| (finished - i.started_us) / 1000.0))
+            .collect();
+        latencies.sort_by(f64::total_cmp);
+        let percentile = |p: f64| latencies.get(((latencies.len().saturating_sub(1)) as f64 * p) as usize).copied().unwrap_or(0.0);
+        let ratio = |part: f64, whole: f64| if whole > 0.0 { part / whole } else { 0.0 };
+        ContentionPoint {
+            competing_stations: competing,
+            interactions: interactions.len(),
+            completed: latencies.len(),
+            loss_ratio: ratio(interactions.iter().filter(|i| i.lost).count() as f64, interactions.len() as f64),
+            latency_mean_ms: ratio(latencies.iter().sum(), latencies.len() as f64),
+            latency_p95_ms: percentile(0.95),
+            latency_p99_ms: percentile(0.99),
+            retries_per_frame: ratio(stats.retries as f64, stats.frames as f64),
+            collision_ratio: ratio(stats.collisions as f64, stats.transmissions as f64),
+            airtime_share: ratio(stats.airtime_us, now_us),
+            channel_utilization: ratio(stats.busy_us, now_us).min(1.0),
+        }
+    }
+
+    fn airtime_us(&self, bytes: usize) -> f64 {
+        HT_PREAMBLE_US + ((bytes + MAC_OVERHEAD_BYTES) * 8) as f64 / self.config.phy_rate_mbps
+    }
+}
+
+struct Station {
+    queue: VecDeque<Frame>,
+    /// Slots left before sending; drawn when the station next contends.
+    backoff: Option<u32>,
+    cw: u32,
+    retries: u32,
+}
+
+impl Default for Station {
+    fn default() -> Self {
+        Self { queue: VecDeque::new(), backoff: None, cw: CW_MIN, retries: 0 }
+    }
+}
+
+struct Frame {
+    bytes: usize,
+    /// Interaction and message index, for the protocol's own frames.
+    interaction: Option<(usize, usize)>,
+}
+
+struct Interaction {
+    started_us: f64,
+    finished_us: Option<f64>,
+    lost: bool,
+}
+
+#[derive(Default)]
+struct Totals {
+    frames: usize,
+    transmissions: usize,
+    collisions: usize,
+    retries: usize,
+    airtime_us: f64,
+    busy_us: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contention_inflates_latency_and_collisions() {
+        let model = ContentionModel::builder().duration(Duration::from_secs(5)).build();
+        let frames = [120, 90, 60];
+
+        let idle = model.simulate(&frames, 0);
+        assert_eq!(idle.completed, idle.interactions);
+        assert_eq!((idle.loss_ratio, idle.collision_ratio), (0.0, 0.0));
+        // Three frames, each DIFS + backoff + airtime + SIFS + ACK, with no one to wait for
+        assert!(idle.latency_mean_ms > 0.2 && idle.latency_mean_ms < 1.0, "{}", idle.latency_mean_ms);
+
+        let busy = model.simulate(&frames, 40);
+        assert!(busy.channel_utilization > idle.channel_utilization);
+        assert!(busy.collision_ratio > 0.0);
+        assert!(busy.latency_p95_ms > 2.0 * idle.latency_p95_ms);
+    }
+}
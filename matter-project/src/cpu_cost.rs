@@ -0,0 +1,262 @@
+// matter-project/src/cpu_cost.rs
+/*!
+Per-operation CPU cost - hardware cycle and instruction counts (Linux perf events)
+*/
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use metrics_core::bench::{encode_reference_report, REFERENCE_REPORT_CAPACITY};
+use metrics_core::tlv::TlvWriter;
+use ring::{aead, agreement, hkdf, rand, signature};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::builder::analyzer_builder;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CpuCostMetrics {
+    pub counters_available: bool,
+    pub backend: String,
+    pub operations: Vec<OperationCost>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OperationCost {
+    pub operation: String,
+    pub iterations: u32,
+    pub wall_time_us_per_op: f64,
+    pub cycles_per_op: Option<f64>,
+    pub instructions_per_op: Option<f64>,
+    pub instructions_per_cycle: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CpuCostConfig {
+    /// Repetitions of each operation per measurement.
+    pub iterations: u32,
+}
+
+impl Default for CpuCostConfig {
+    fn default() -> Self {
+        Self { iterations: 200 }
+    }
+}
+
+analyzer_builder!(CpuCostAnalyzerBuilder => CpuCostAnalyzer(CpuCostConfig) {
+    iterations: u32,
+});
+
+pub struct CpuCostAnalyzer {
+    iterations: u32,
+    counters: Option<counters::HardwareCounters>,
+}
+
+impl Default for CpuCostAnalyzer {
+    fn default() -> Self {
+        Self::new(CpuCostConfig::default())
+    }
+}
+
+impl CpuCostAnalyzer {
+    pub fn new(config: CpuCostConfig) -> Self {
+        let counters = match counters::HardwareCounters::open() {
+            Ok(counters) => Some(counters),
+            Err(e) => {
+                // Typical causes: non-Linux host, perf_event_paranoid, VMs without a PMU
+                warn!("⚠️ Hardware counters unavailable, recording wall time only: {}", e);
+                None
+            }
+        };
+
+        Self { iterations: config.iterations.max(1), counters }
+    }
+
+    pub fn analyze_cpu_cost(&mut self) -> Result<CpuCostMetrics> {
+        println!("\n🧮 Analyzing Per-Operation CPU Cost");
+        println!("-----------------------------------");
+
+        let rng = rand::SystemRandom::new();
+        let signing_pkcs8 = signature::EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+            .map_err(|_| anyhow!("failed to generate signing key"))?;
+        let signing_key = signature::EcdsaKeyPair::from_pkcs8(
+            &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+            signing_pkcs8.as_ref(),
+            &rng,
+        )
+        .map_err(|_| anyhow!("failed to load signing key"))?;
+        // The peer's ephemeral key is its own work, not the device's
+        let peer_public = agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, &rng)
+            .and_then(|peer| peer.compute_public_key())
+            .map_err(|_| anyhow!("peer key generation failed"))?;
+        let session_key = aead::LessSafeKey::new(
+            aead::UnboundKey::new(&aead::AES_128_GCM, &[0x42; 16]).map_err(|_| anyhow!("invalid AEAD key"))?,
+        );
+
+        let mut operations = Vec::new();
+
+        operations.push(self.measure("tlv_encode", || {
+            let mut buffer = [0u8; REFERENCE_REPORT_CAPACITY];
+            let mut writer = TlvWriter::new(&mut buffer);
+            encode_reference_report(&mut writer).map_err(|e| anyhow!("TLV encode failed: {:?}", e))
+        })?);
+
+        operations.push(self.measure("case_handshake_crypto", || {
+            case_handshake_crypto(&rng, &signing_key, peer_public.as_ref())
+        })?);
+
+        operations.push(self.measure("secure_message_processing", || {
+            secure_message_round(&session_key)
+        })?);
+
+        for op in &operations {
+            match (op.cycles_per_op, op.instructions_per_op) {
+                (Some(cycles), Some(instructions)) => println!(
+                    "✅ {}: {:.0} cycles, {:.0} instructions ({:.2}µs)",
+                    op.operation, cycles, instructions, op.wall_time_us_per_op
+                ),
+                _ => println!("✅ {}: {:.2}µs (no counters)", op.operation, op.wall_time_us_per_op),
+            }
+        }
+
+        Ok(CpuCostMetrics {
+            counters_available: self.counters.is_some(),
+            backend: if self.counters.is_some() { "perf_event_open" } else { "wall_clock" }.to_string(),
+            operations,
+        })
+    }
+
+    fn measure<F>(&mut self, operation: &str, mut op: F) -> Result<OperationCost>
+    where
+        F: FnMut() -> Result<()>,
+    {
+        // Warm caches and lazy initialization outside the counted window
+        op()?;
+
+        let iterations = self.iterations;
+        let start = Instant::now();
+        let counts = match &mut self.counters {
+            Some(counters) => Some(counters.count(|| {
+                for _ in 0..iterations {
+                    op()?;
+                }
+                Ok(())
+            })?),
+            None => {
+                for _ in 0..iterations {
+                    op()?;
+                }
+                None
+            }
+        };
+        let wall_time_us_per_op = start.elapsed().as_secs_f64() * 1e6 / iterations as f64;
+
+        let cycles_per_op = counts.map(|(cycles, _)| cycles as f64 / iterations as f64);
+        let instructions_per_op = counts.map(|(_, instructions)| instructions as f64 / iterations as f64);
+
+        Ok(OperationCost {
+            operation: operation.to_string(),
+            iterations,
+            wall_time_us_per_op,
+            cycles_per_op,
+            instructions_per_op,
+            instructions_per_cycle: counts
+                .filter(|(cycles, _)| *cycles > 0)
+                .map(|(cycles, instructions)| instructions as f64 / cycles as f64),
+        })
+    }
+}
+
+/// Device-side CASE work: ephemeral key generation, ECDH, HKDF session key derivation and a Sigma2 signature.
+/// `peer_public` is the initiator's key from Sigma1, generated outside the counted window.
+fn case_handshake_crypto(rng: &rand::SystemRandom, signing_key: &signature::EcdsaKeyPair, peer_public: &[u8]) -> Result<()> {
+    let ours = agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, rng)
+        .map_err(|_| anyhow!("ephemeral key generation failed"))?;
+    let our_public = ours.compute_public_key().map_err(|_| anyhow!("public key derivation failed"))?;
+
+    let mut session_keys = [0u8; 48];
+    agreement::agree_ephemeral(
+        ours,
+        &agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, peer_public),
+        |shared_secret| {
+            hkdf::Salt::new(hkdf::HKDF_SHA256, b"CASE salt")
+                .extract(shared_secret)
+                .expand(&[b"SessionKeys"], SessionKeyLength(session_keys.len()))
+                .and_then(|okm| okm.fill(&mut session_keys))
+        },
+    )
+    .map_err(|_| anyhow!("key agreement failed"))?
+    .map_err(|_| anyhow!("session key derivation failed"))?;
+
+    signing_key
+        .sign(rng, our_public.as_ref())
+        .map_err(|_| anyhow!("Sigma2 signing failed"))?;
+    Ok(())
+}
+
+/// Encrypt and decrypt one 64-byte application payload, as both ends of a secured exchange do.
+fn secure_message_round(key: &aead::LessSafeKey) -> Result<()> {
+    let mut message = vec![0x15u8; 64];
+    let nonce = [0u8; aead::NONCE_LEN];
+
+    key.seal_in_place_append_tag(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::from(&[0u8; 8]), &mut message)
+        .map_err(|_| anyhow!("message encryption failed"))?;
+    key.open_in_place(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::from(&[0u8; 8]), &mut message)
+        .map_err(|_| anyhow!("message decryption failed"))?;
+    Ok(())
+}
+
+struct SessionKeyLength(usize);
+
+impl hkdf::KeyType for SessionKeyLength {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "perf-counters"))]
+mod counters {
+    use perf_event::events::Hardware;
+    use perf_event::{Builder, Counter, Group};
+
+    pub struct HardwareCounters {
+        group: Group,
+        cycles: Counter,
+        instructions: Counter,
+    }
+
+    impl HardwareCounters {
+        pub fn open() -> anyhow::Result<Self> {
+            let mut group = Group::new()?;
+            let cycles = Builder::new().group(&mut group).kind(Hardware::CPU_CYCLES).build()?;
+            let instructions = Builder::new().group(&mut group).kind(Hardware::INSTRUCTIONS).build()?;
+            Ok(Self { group, cycles, instructions })
+        }
+
+        /// Counts (cycles, instructions) spent in `work`.
+        pub fn count<F: FnMut() -> anyhow::Result<()>>(&mut self, mut work: F) -> anyhow::Result<(u64, u64)> {
+            self.group.reset()?;
+            self.group.enable()?;
+            let outcome = work();
+            self.group.disable()?;
+            outcome?;
+
+            let counts = self.group.read()?;
+            Ok((counts[&self.cycles], counts[&self.instructions]))
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "perf-counters")))]
+mod counters {
+    pub struct HardwareCounters;
+
+    impl HardwareCounters {
+        pub fn open() -> anyhow::Result<Self> {
+            Err(anyhow::anyhow!("hardware counters require Linux perf events and the perf-counters feature"))
+        }
+
+        pub fn count<F: FnMut() -> anyhow::Result<()>>(&mut self, _work: F) -> anyhow::Result<(u64, u64)> {
+            Err(anyhow::anyhow!("hardware counters require Linux perf events and the perf-counters feature"))
+        }
+    }
+}
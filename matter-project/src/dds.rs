@@ -0,0 +1,978 @@
+// matter-project/src/dds.rs
+/*!
+DDS over RTPS - participant discovery, QoS-matched endpoint discovery and sustained publish rates
+
+DDS is the pub/sub middleware of ROS 2 and of many industrial control
+systems. Its wire protocol, RTPS 2.3, runs over UDP without a broker:
+
+- SPDP: each participant announces itself with a DATA(p) carrying its GUID,
+  locators and builtin endpoints. The announcements normally go to the
+  239.255.0.1:7400 multicast group; here each participant sends them to the
+  other's unicast port, as with a static peer list.
+- SEDP: each side then sends its writers (DATA(w)) and readers (DATA(r))
+  over the reliable builtin endpoints: DATA plus HEARTBEAT, answered with
+  an ACKNACK. A writer and a reader on the same topic match only when the
+  writer offers at least the QoS the reader requests (reliability,
+  durability, deadline); otherwise the reader reports
+  REQUESTED_INCOMPATIBLE_QOS and nothing flows. A TRANSIENT_LOCAL writer
+  replays its history to a durable reader that joins late.
+- User data: one DATA per sample, preceded by an INFO_TS. A RELIABLE writer
+  keeps a KEEP_LAST history and sends a HEARTBEAT every few samples. The
+  reader answers with an ACKNACK whose bitmap lists what it is missing, and
+  the writer repairs what is still in its history. BEST_EFFORT sends each
+  sample once.
+
+Discovery runs for six writer/reader QoS pairs and measures SPDP, SEDP and
+the first sample. Each sustained-rate run publishes timestamped samples at
+a fixed rate for a while, BEST_EFFORT and RELIABLE, while the subscriber
+drops a share of the DATA it receives to stand in for a lossy link.
+Messages are encoded on the wire as RTPS defines them; bytes include IPv4
+and UDP headers.
+*/
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::oneshot;
+use tokio::time::{interval, timeout, MissedTickBehavior};
+
+use crate::builder::analyzer_builder;
+use crate::handshake::next_unit;
+use crate::transport_modes::IPV4_UDP_HEADER_BYTES;
+
+// RTPS 2.3 message header
+const RTPS_HEADER_BYTES: usize = 20;
+const PROTOCOL_VERSION: [u8; 2] = [2, 3];
+/// VENDORID_UNKNOWN; the assigned ids belong to real implementations.
+const VENDOR_ID: [u8; 2] = [0, 0];
+
+// Submessages
+const SUBMESSAGE_ACKNACK: u8 = 0x06;
+const SUBMESSAGE_HEARTBEAT: u8 = 0x07;
+const SUBMESSAGE_INFO_TS: u8 = 0x09;
+const SUBMESSAGE_DATA: u8 = 0x15;
+const FLAG_LITTLE_ENDIAN: u8 = 0x01;
+/// FinalFlag on HEARTBEAT and ACKNACK: no reply needed.
+const FLAG_FINAL: u8 = 0x02;
+const FLAG_DATA: u8 = 0x04;
+const ENCAPSULATION_CDR_LE: [u8; 4] = [0x00, 0x01, 0x00, 0x00];
+const ENCAPSULATION_PL_CDR_LE: [u8; 4] = [0x00, 0x03, 0x00, 0x00];
+/// ACKNACK bitmaps cover at most this many sequence numbers.
+const MAX_BITMAP_BITS: usize = 256;
+
+type EntityId = [u8; 4];
+const ENTITY_PARTICIPANT: EntityId = [0x00, 0x00, 0x01, 0xC1];
+const SPDP_WRITER: EntityId = [0x00, 0x01, 0x00, 0xC2];
+const SPDP_READER: EntityId = [0x00, 0x01, 0x00, 0xC7];
+const SEDP_PUBLICATIONS_WRITER: EntityId = [0x00, 0x00, 0x03, 0xC2];
+const SEDP_PUBLICATIONS_READER: EntityId = [0x00, 0x00, 0x03, 0xC7];
+const SEDP_SUBSCRIPTIONS_WRITER: EntityId = [0x00, 0x00, 0x04, 0xC2];
+const SEDP_SUBSCRIPTIONS_READER: EntityId = [0x00, 0x00, 0x04, 0xC7];
+/// User writer and reader without a key.
+const USER_WRITER: EntityId = [0x00, 0x00, 0x01, 0x03];
+const USER_READER: EntityId = [0x00, 0x00, 0x01, 0x04];
+
+// Parameter ids
+const PID_SENTINEL: u16 = 0x0001;
+const PID_PARTICIPANT_LEASE_DURATION: u16 = 0x0002;
+const PID_TOPIC_NAME: u16 = 0x0005;
+const PID_TYPE_NAME: u16 = 0x0007;
+const PID_PROTOCOL_VERSION: u16 = 0x0015;
+const PID_VENDORID: u16 = 0x0016;
+const PID_RELIABILITY: u16 = 0x001A;
+const PID_DURABILITY: u16 = 0x001D;
+const PID_DEADLINE: u16 = 0x0023;
+const PID_DEFAULT_UNICAST_LOCATOR: u16 = 0x0031;
+const PID_METATRAFFIC_UNICAST_LOCATOR: u16 = 0x0032;
+const PID_HISTORY: u16 = 0x0040;
+const PID_PARTICIPANT_GUID: u16 = 0x0050;
+const PID_BUILTIN_ENDPOINT_SET: u16 = 0x0058;
+const PID_ENDPOINT_GUID: u16 = 0x005A;
+const LOCATOR_KIND_UDPV4: i32 = 1;
+/// Participant, publications and subscriptions announcers and detectors.
+const BUILTIN_ENDPOINTS: u32 = 0x3F;
+const RELIABILITY_BEST_EFFORT: u32 = 1;
+const RELIABILITY_RELIABLE: u32 = 2;
+const DURABILITY_VOLATILE: u32 = 0;
+const DURABILITY_TRANSIENT_LOCAL: u32 = 1;
+const HISTORY_KEEP_LAST: u32 = 0;
+
+const TOPIC_NAME: &str = "rt/robot/joint_states";
+const TYPE_NAME: &str = "sensor_msgs::msg::dds_::JointState_";
+/// Samples a TRANSIENT_LOCAL writer has published before the reader joins.
+const HISTORICAL_SAMPLES: usize = 10;
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long a RELIABLE writer keeps repairing after its last sample.
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(200);
+const DRAIN_HEARTBEAT: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone)]
+pub struct DdsConfig {
+    /// Samples per second the publisher offers in the sustained-rate runs.
+    pub publish_rates: Vec<u32>,
+    pub payload_bytes: usize,
+    pub duration: Duration,
+    /// Share of user DATA the subscriber drops, to exercise RELIABLE repair.
+    pub loss_rate: f64,
+    /// KEEP_LAST depth of the writer history, which bounds what can be repaired.
+    pub history_depth: usize,
+    /// A RELIABLE writer sends a HEARTBEAT with every this many samples.
+    pub heartbeat_every: usize,
+    /// Discovery runs averaged per QoS pair.
+    pub discovery_iterations: usize,
+}
+
+impl Default for DdsConfig {
+    fn default() -> Self {
+        Self {
+            publish_rates: vec![1_000, 10_000, 50_000],
+            payload_bytes: 64,
+            duration: Duration::from_secs(1),
+            loss_rate: 0.01,
+            history_depth: 256,
+            heartbeat_every: 32,
+            discovery_iterations: 20,
+        }
+    }
+}
+
+analyzer_builder!(DdsAnalyzerBuilder => DdsAnalyzer(DdsConfig) {
+    publish_rates: Vec<u32>,
+    payload_bytes: usize,
+    duration: Duration,
+    loss_rate: f64,
+    history_depth: usize,
+    heartbeat_every: usize,
+    discovery_iterations: usize,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DdsMetrics {
+    pub topic: String,
+    /// SPDP DATA(p) with IPv4/UDP headers.
+    pub spdp_message_bytes: usize,
+    pub discovery: Vec<QosMatchRun>,
+    pub publish: Vec<DdsPublishRun>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QosMatchRun {
+    pub writer_qos: String,
+    pub reader_qos: String,
+    pub matched: bool,
+    /// Policy the reader reports as REQUESTED_INCOMPATIBLE_QOS.
+    pub incompatible_policy: Option<String>,
+    /// Both participants start until each has the other's DATA(p).
+    pub participant_discovery_ms: f64,
+    /// SEDP until both sides have the remote endpoint and their announcement is acknowledged.
+    pub endpoint_discovery_ms: f64,
+    /// SEDP start until the reader has its first live sample.
+    pub first_sample_ms: Option<f64>,
+    /// Samples a TRANSIENT_LOCAL writer replayed to the late-joining reader.
+    pub historical_samples: usize,
+    /// SPDP and SEDP in both directions, with IPv4/UDP headers.
+    pub discovery_bytes: usize,
+    pub discovery_messages: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DdsPublishRun {
+    pub reliability: String,
+    pub offered_rate_hz: u32,
+    pub sent: usize,
+    /// Distinct samples the reader kept.
+    pub delivered: usize,
+    pub delivered_rate_hz: f64,
+    /// Samples the reader never got, including those that left a RELIABLE writer's history unrepaired.
+    pub lost: usize,
+    /// Samples the writer sent again after an ACKNACK.
+    pub repairs: usize,
+    pub latency_p50_ms: f64,
+    pub latency_p99_ms: f64,
+    /// All traffic in both directions per sample sent, with IPv4/UDP headers.
+    pub bytes_per_sample: f64,
+    /// HEARTBEAT and ACKNACK bytes per sample sent.
+    pub control_bytes_per_sample: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct EndpointQos {
+    reliable: bool,
+    transient_local: bool,
+    deadline_ms: Option<u32>,
+}
+
+impl EndpointQos {
+    const fn new(reliable: bool, transient_local: bool, deadline_ms: Option<u32>) -> Self {
+        Self { reliable, transient_local, deadline_ms }
+    }
+
+    fn describe(&self) -> String {
+        let mut description = format!(
+            "{}, {}",
+            if self.reliable { "RELIABLE" } else { "BEST_EFFORT" },
+            if self.transient_local { "TRANSIENT_LOCAL" } else { "VOLATILE" }
+        );
+        if let Some(deadline) = self.deadline_ms {
+            description.push_str(&format!(", deadline {}ms", deadline));
+        }
+        description
+    }
+
+    /// The first policy where the writer's offer falls short of what the reader requests.
+    fn incompatible(offered: &Self, requested: &Self) -> Option<&'static str> {
+        if requested.reliable && !offered.reliable {
+            Some("RELIABILITY")
+        } else if requested.transient_local && !offered.transient_local {
+            Some("DURABILITY")
+        } else if requested.deadline_ms.is_some_and(|requested| offered.deadline_ms.is_none_or(|offered| offered > requested)) {
+            Some("DEADLINE")
+        } else {
+            None
+        }
+    }
+
+    fn parameters(&self, list: &mut ParameterList, history_depth: usize) {
+        let reliability = if self.reliable { RELIABILITY_RELIABLE } else { RELIABILITY_BEST_EFFORT };
+        // max_blocking_time 100 ms
+        list.push(PID_RELIABILITY, &[&reliability.to_le_bytes()[..], &0i32.to_le_bytes(), &100_000_000u32.to_le_bytes()].concat());
+        let durability = if self.transient_local { DURABILITY_TRANSIENT_LOCAL } else { DURABILITY_VOLATILE };
+        list.push(PID_DURABILITY, &durability.to_le_bytes());
+        list.push(PID_HISTORY, &[HISTORY_KEEP_LAST.to_le_bytes(), (history_depth as u32).to_le_bytes()].concat());
+        if let Some(deadline) = self.deadline_ms {
+            list.push(PID_DEADLINE, &[((deadline / 1000) as i32).to_le_bytes(), ((deadline % 1000) * 1_000_000).to_le_bytes()].concat());
+        }
+    }
+
+    fn from_parameters(parameters: &[(u16, &[u8])]) -> Self {
+        let u32_at = |value: &[u8], offset: usize| value.get(offset..offset + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+        let find = |pid: u16| parameters.iter().find(|(id, _)| *id == pid).map(|(_, value)| *value);
+        Self {
+            reliable: find(PID_RELIABILITY).and_then(|value| u32_at(value, 0)) == Some(RELIABILITY_RELIABLE),
+            transient_local: find(PID_DURABILITY).and_then(|value| u32_at(value, 0)) == Some(DURABILITY_TRANSIENT_LOCAL),
+            deadline_ms: find(PID_DEADLINE)
+                .and_then(|value| Some(u32_at(value, 0)? * 1000 + u32_at(value, 4)? / 1_000_000)),
+        }
+    }
+}
+
+/// Writer and reader QoS of each discovery run: three that match, then one
+/// mismatch per policy.
+const QOS_PAIRS: [(EndpointQos, EndpointQos); 6] = [
+    (EndpointQos::new(true, false, None), EndpointQos::new(true, false, None)),
+    (EndpointQos::new(false, false, None), EndpointQos::new(false, false, None)),
+    (EndpointQos::new(true, true, None), EndpointQos::new(true, true, None)),
+    (EndpointQos::new(false, false, None), EndpointQos::new(true, false, None)),
+    (EndpointQos::new(true, false, None), EndpointQos::new(true, true, None)),
+    (EndpointQos::new(true, false, Some(100)), EndpointQos::new(true, false, Some(50))),
+];
+
+pub struct DdsAnalyzer {
+    config: DdsConfig,
+}
+
+impl DdsAnalyzer {
+    pub fn new(config: DdsConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_dds(&mut self) -> Result<DdsMetrics> {
+        println!("\n🤖 Analyzing DDS (RTPS 2.3) Discovery and Publish Rates");
+        println!("------------------------------------------------------");
+
+        let mut discovery = Vec::new();
+        for (writer_qos, reader_qos) in QOS_PAIRS {
+            let mut runs = Vec::new();
+            for _ in 0..self.config.discovery_iterations.max(1) {
+                runs.push(self.discover(writer_qos, reader_qos).await?);
+            }
+            let mean = |value: fn(&DiscoveryRun) -> f64| runs.iter().map(value).sum::<f64>() / runs.len() as f64;
+            let first = &runs[0];
+            let result = QosMatchRun {
+                writer_qos: writer_qos.describe(),
+                reader_qos: reader_qos.describe(),
+                matched: first.incompatible.is_none(),
+                incompatible_policy: first.incompatible.map(str::to_string),
+                participant_discovery_ms: mean(|run| run.participant_ms),
+                endpoint_discovery_ms: mean(|run| run.endpoint_ms),
+                first_sample_ms: first.first_sample_ms.map(|_| mean(|run| run.first_sample_ms.unwrap_or(0.0))),
+                historical_samples: first.historical_samples,
+                discovery_bytes: first.bytes,
+                discovery_messages: first.messages,
+            };
+            match &result.incompatible_policy {
+                None => println!("✅ {} → {}: SPDP {:.3}ms, SEDP {:.3}ms, first sample {:.3}ms, {} historical, {} B",
+                                 result.writer_qos, result.reader_qos, result.participant_discovery_ms,
+                                 result.endpoint_discovery_ms, result.first_sample_ms.unwrap_or(0.0),
+                                 result.historical_samples, result.discovery_bytes),
+                Some(policy) => println!("✅ {} → {}: REQUESTED_INCOMPATIBLE_QOS ({}) after SEDP {:.3}ms",
+                                         result.writer_qos, result.reader_qos, policy, result.endpoint_discovery_ms),
+            }
+            discovery.push(result);
+        }
+
+        let mut publish = Vec::new();
+        for &rate in &self.config.publish_rates {
+            for reliable in [false, true] {
+                let run = self.publish(rate, reliable).await?;
+                println!("✅ {} @ {}/s: {}/{} delivered ({} repaired), p50 {:.3}ms, p99 {:.3}ms, {:.0} B/sample",
+                         run.reliability, run.offered_rate_hz, run.delivered, run.sent, run.repairs,
+                         run.latency_p50_ms, run.latency_p99_ms, run.bytes_per_sample);
+                publish.push(run);
+            }
+        }
+
+        Ok(DdsMetrics {
+            topic: TOPIC_NAME.to_string(),
+            spdp_message_bytes: spdp_announcement(&guid_prefix(1), "127.0.0.1:7410".parse()?).len() + IPV4_UDP_HEADER_BYTES,
+            discovery,
+            publish,
+        })
+    }
+
+    /// SPDP, then SEDP of one writer and one reader, then the first sample if they match.
+    async fn discover(&self, writer_qos: EndpointQos, reader_qos: EndpointQos) -> Result<DiscoveryRun> {
+        let mut publisher = Participant::bind(1).await?;
+        let mut subscriber = Participant::bind(2).await?;
+        let (publisher_addr, subscriber_addr) = (publisher.addr()?, subscriber.addr()?);
+
+        let start = Instant::now();
+        let publisher_spdp = spdp_announcement(&publisher.prefix, publisher_addr);
+        let subscriber_spdp = spdp_announcement(&subscriber.prefix, subscriber_addr);
+        let (found_subscriber, found_publisher) = tokio::join!(
+            publisher.announce_and_wait(&publisher_spdp, subscriber_addr, SPDP_WRITER),
+            subscriber.announce_and_wait(&subscriber_spdp, publisher_addr, SPDP_WRITER),
+        );
+        found_subscriber?;
+        found_publisher?;
+        let participant_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        let sedp_start = Instant::now();
+        let publication = endpoint_announcement(
+            &publisher.prefix,
+            USER_WRITER,
+            &writer_qos,
+            self.config.history_depth,
+            SEDP_PUBLICATIONS_READER,
+            SEDP_PUBLICATIONS_WRITER,
+        );
+        let subscription = endpoint_announcement(
+            &subscriber.prefix,
+            USER_READER,
+            &reader_qos,
+            self.config.history_depth,
+            SEDP_SUBSCRIPTIONS_READER,
+            SEDP_SUBSCRIPTIONS_WRITER,
+        );
+        let (remote_reader, remote_writer) = tokio::join!(
+            publisher.sedp(&publication, subscriber_addr, SEDP_SUBSCRIPTIONS_WRITER),
+            subscriber.sedp(&subscription, publisher_addr, SEDP_PUBLICATIONS_WRITER),
+        );
+        let (remote_reader, remote_writer) = (remote_reader?, remote_writer?);
+        let endpoint_ms = sedp_start.elapsed().as_secs_f64() * 1000.0;
+
+        // Each side decides on what it learned over SEDP
+        let incompatible = EndpointQos::incompatible(&remote_writer, &reader_qos)
+            .or_else(|| EndpointQos::incompatible(&writer_qos, &remote_reader));
+        let mut first_sample_ms = None;
+        let mut historical_samples = 0;
+        if incompatible.is_none() {
+            let replay = if writer_qos.transient_local && reader_qos.transient_local { HISTORICAL_SAMPLES } else { 0 };
+            let live = replay as i64 + 1;
+            for sn in 1..=live {
+                let message = user_message(&publisher.prefix, sn, &vec![0u8; self.config.payload_bytes], sedp_start);
+                publisher.send(&message, subscriber_addr).await?;
+            }
+            loop {
+                let message = subscriber.receive().await?;
+                for (id, _, body) in submessages(&message).into_iter().flatten() {
+                    if let (SUBMESSAGE_DATA, Some((_, writer, sn, _))) = (id, data_fields(body)) {
+                        if writer == USER_WRITER && sn < live {
+                            historical_samples += 1;
+                        } else if writer == USER_WRITER {
+                            first_sample_ms = Some(sedp_start.elapsed().as_secs_f64() * 1000.0);
+                        }
+                    }
+                }
+                if first_sample_ms.is_some() {
+                    break;
+                }
+            }
+        }
+
+        Ok(DiscoveryRun {
+            participant_ms,
+            endpoint_ms,
+            first_sample_ms,
+            historical_samples,
+            incompatible,
+            bytes: publisher.bytes + subscriber.bytes,
+            messages: publisher.messages + subscriber.messages,
+        })
+    }
+
+    async fn publish(&self, rate: u32, reliable: bool) -> Result<DdsPublishRun> {
+        let mut publisher = Participant::bind(1).await?;
+        let subscriber = Participant::bind(2).await?;
+        let subscriber_addr = subscriber.addr()?;
+        let start = Instant::now();
+        let (stop, stopped) = oneshot::channel();
+        let reader = tokio::spawn(read_samples(subscriber, publisher.addr()?, start, reliable, self.config.loss_rate, stopped));
+
+        let payload = vec![0x5Au8; self.config.payload_bytes];
+        let depth = self.config.history_depth.max(1);
+        let mut history: VecDeque<(i64, Vec<u8>)> = VecDeque::with_capacity(depth);
+        let mut sn = 0i64;
+        let mut heartbeats = 0u32;
+        let mut repairs = 0;
+        let mut control_bytes = 0;
+        let mut acked = 1i64;
+        let mut buffer = vec![0u8; 65536];
+        let mut tick = interval(Duration::from_millis(1));
+        tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut drain_started: Option<Instant> = None;
+        let mut last_heartbeat: Option<Instant> = None;
+
+        loop {
+            tokio::select! {
+                _ = tick.tick() => {
+                    let elapsed = start.elapsed();
+                    if elapsed < self.config.duration {
+                        let due = (elapsed.as_secs_f64() * rate as f64) as i64;
+                        while sn < due {
+                            sn += 1;
+                            let mut message = user_message(&publisher.prefix, sn, &payload, start);
+                            let data_len = message.len();
+                            if reliable && (sn as usize).is_multiple_of(self.config.heartbeat_every.max(1)) {
+                                heartbeats += 1;
+                                let first = history.front().map_or(sn, |(first, _)| *first);
+                                heartbeat_into(&mut message, USER_READER, USER_WRITER, first, sn, heartbeats);
+                                control_bytes += message.len() - data_len;
+                            }
+                            publisher.send(&message, subscriber_addr).await?;
+                            if reliable {
+                                message.truncate(data_len);
+                                if history.len() == depth {
+                                    history.pop_front();
+                                }
+                                history.push_back((sn, message));
+                            }
+                        }
+                    } else if !reliable || acked > sn {
+                        break;
+                    } else {
+                        // Keep asking for acknowledgement until everything still in history is repaired
+                        let drain = *drain_started.get_or_insert_with(Instant::now);
+                        if drain.elapsed() >= DRAIN_TIMEOUT {
+                            break;
+                        }
+                        if last_heartbeat.is_none_or(|last| last.elapsed() >= DRAIN_HEARTBEAT) {
+                            last_heartbeat = Some(Instant::now());
+                            heartbeats += 1;
+                            let mut message = rtps_header(&publisher.prefix);
+                            let first = history.front().map_or(sn, |(first, _)| *first);
+                            heartbeat_into(&mut message, USER_READER, USER_WRITER, first, sn, heartbeats);
+                            control_bytes += message.len() + IPV4_UDP_HEADER_BYTES;
+                            publisher.send(&message, subscriber_addr).await?;
+                        }
+                    }
+                }
+                received = publisher.socket.recv_from(&mut buffer) => {
+                    let (len, _) = received?;
+                    for (id, _, body) in submessages(&buffer[..len]).into_iter().flatten() {
+                        let Some((base, missing)) = (id == SUBMESSAGE_ACKNACK).then(|| acknack_fields(body)).flatten() else {
+                            continue;
+                        };
+                        acked = acked.max(base);
+                        let oldest = history.front().map_or(sn + 1, |(first, _)| *first);
+                        // What fell out of the history cannot be repaired; the next HEARTBEAT tells the reader
+                        for missing in missing.into_iter().filter(|&missing| missing >= oldest) {
+                            let (_, message) = &history[(missing - oldest) as usize];
+                            let message = message.clone();
+                            publisher.send(&message, subscriber_addr).await?;
+                            repairs += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        tokio::time::sleep(DRAIN_HEARTBEAT).await;
+        let _ = stop.send(());
+        let mut log = reader.await??;
+        let sent = sn as usize;
+        log.latencies.sort_by(|a, b| a.total_cmp(b));
+        let seconds = self.config.duration.as_secs_f64().max(f64::EPSILON);
+        Ok(DdsPublishRun {
+            reliability: if reliable { "RELIABLE" } else { "BEST_EFFORT" }.to_string(),
+            offered_rate_hz: rate,
+            sent,
+            delivered: log.delivered,
+            delivered_rate_hz: log.delivered as f64 / seconds,
+            lost: sent.saturating_sub(log.delivered),
+            repairs,
+            latency_p50_ms: percentile(&log.latencies, 50.0),
+            latency_p99_ms: percentile(&log.latencies, 99.0),
+            bytes_per_sample: (publisher.bytes + log.bytes) as f64 / sent.max(1) as f64,
+            control_bytes_per_sample: (control_bytes + log.bytes) as f64 / sent.max(1) as f64,
+        })
+    }
+}
+
+struct DiscoveryRun {
+    participant_ms: f64,
+    endpoint_ms: f64,
+    first_sample_ms: Option<f64>,
+    historical_samples: usize,
+    incompatible: Option<&'static str>,
+    bytes: usize,
+    messages: usize,
+}
+
+/// One DomainParticipant on its own loopback socket, counting what it sends.
+struct Participant {
+    prefix: [u8; 12],
+    socket: UdpSocket,
+    bytes: usize,
+    messages: usize,
+    buffer: Vec<u8>,
+}
+
+impl Participant {
+    async fn bind(host: u8) -> Result<Self> {
+        Ok(Self {
+            prefix: guid_prefix(host),
+            socket: UdpSocket::bind("127.0.0.1:0").await?,
+            bytes: 0,
+            messages: 0,
+            buffer: vec![0u8; 65536],
+        })
+    }
+
+    fn addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    async fn send(&mut self, message: &[u8], to: SocketAddr) -> Result<()> {
+        self.socket.send_to(message, to).await?;
+        self.bytes += message.len() + IPV4_UDP_HEADER_BYTES;
+        self.messages += 1;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Vec<u8>> {
+        let (len, _) = timeout(RECEIVE_TIMEOUT, self.socket.recv_from(&mut self.buffer)).await??;
+        Ok(self.buffer[..len].to_vec())
+    }
+
+    /// Sends `announcement` and waits for the peer's DATA from `writer`.
+    async fn announce_and_wait(&mut self, announcement: &[u8], peer: SocketAddr, writer: EntityId) -> Result<()> {
+        self.send(announcement, peer).await?;
+        loop {
+            let message = self.receive().await?;
+            let found = submessages(&message)
+                .into_iter()
+                .flatten()
+                .any(|(id, _, body)| id == SUBMESSAGE_DATA && data_fields(body).is_some_and(|(_, from, _, _)| from == writer));
+            if found {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Sends this side's SEDP announcement (DATA and HEARTBEAT), acknowledges
+    /// the peer's from `remote_writer` and returns its QoS once both sides are
+    /// acknowledged.
+    async fn sedp(&mut self, announcement: &[u8], peer: SocketAddr, remote_writer: EntityId) -> Result<EndpointQos> {
+        self.send(announcement, peer).await?;
+        let mut remote = None;
+        let mut acknowledged = false;
+        while remote.is_none() || !acknowledged {
+            let message = self.receive().await?;
+            let Some(parsed) = submessages(&message) else {
+                continue;
+            };
+            for (id, _, body) in parsed {
+                match id {
+                    SUBMESSAGE_DATA => {
+                        if let Some((_, writer, _, payload)) = data_fields(body) {
+                            if writer == remote_writer {
+                                remote = Some(EndpointQos::from_parameters(&parameters(payload.get(4..).unwrap_or(&[]))));
+                            }
+                        }
+                    }
+                    SUBMESSAGE_HEARTBEAT => {
+                        if let Some((reader, writer, _, last)) = heartbeat_fields(body) {
+                            let mut reply = rtps_header(&self.prefix);
+                            acknack_into(&mut reply, reader, writer, last + 1, &[], 1);
+                            self.send(&reply, peer).await?;
+                        }
+                    }
+                    SUBMESSAGE_ACKNACK => acknowledged = true,
+                    _ => {}
+                }
+            }
+        }
+        remote.ok_or_else(|| anyhow!("no SEDP announcement from the peer"))
+    }
+}
+
+struct ReaderLog {
+    delivered: usize,
+    latencies: Vec<f64>,
+    /// ACKNACKs sent, with IPv4/UDP headers.
+    bytes: usize,
+}
+
+/// The subscriber: keeps every sample it has not seen, drops `loss_rate` of
+/// the DATA it receives, and answers HEARTBEATs with what it is missing.
+async fn read_samples(
+    mut participant: Participant,
+    writer: SocketAddr,
+    start: Instant,
+    reliable: bool,
+    loss_rate: f64,
+    mut stop: oneshot::Receiver<()>,
+) -> Result<ReaderLog> {
+    let mut log = ReaderLog { delivered: 0, latencies: Vec::new(), bytes: 0 };
+    let mut received: Vec<bool> = vec![false];
+    let mut rng_state = 0x9E37_79B9_7F4A_7C15u64;
+    let mut acknacks = 0u32;
+    loop {
+        let len = tokio::select! {
+            _ = &mut stop => break,
+            read = participant.socket.recv_from(&mut participant.buffer) => read?.0,
+        };
+        let message = participant.buffer[..len].to_vec();
+        for (id, _, body) in submessages(&message).into_iter().flatten() {
+            match id {
+                SUBMESSAGE_DATA => {
+                    let Some((_, _, sn, payload)) = data_fields(body) else {
+                        continue;
+                    };
+                    if next_unit(&mut rng_state) < loss_rate {
+                        continue;
+                    }
+                    let index = sn.max(0) as usize;
+                    if index >= received.len() {
+                        received.resize(index + 1, false);
+                    }
+                    if !received[index] {
+                        received[index] = true;
+                        log.delivered += 1;
+                        if let Some(sent) = payload.get(4..12) {
+                            let sent_ns = u64::from_le_bytes(sent.try_into()?);
+                            log.latencies.push((start.elapsed().as_nanos() as u64).saturating_sub(sent_ns) as f64 / 1e6);
+                        }
+                    }
+                }
+                SUBMESSAGE_HEARTBEAT if reliable => {
+                    let Some((_, _, first, last)) = heartbeat_fields(body) else {
+                        continue;
+                    };
+                    let missing: Vec<i64> = (first.max(1)..=last)
+                        .filter(|&sn| !received.get(sn as usize).copied().unwrap_or(false))
+                        .take(MAX_BITMAP_BITS)
+                        .collect();
+                    let base = missing.first().copied().unwrap_or(last + 1);
+                    let missing: Vec<i64> = missing.into_iter().filter(|&sn| sn < base + MAX_BITMAP_BITS as i64).collect();
+                    acknacks += 1;
+                    let mut reply = rtps_header(&participant.prefix);
+                    acknack_into(&mut reply, USER_READER, USER_WRITER, base, &missing, acknacks);
+                    participant.socket.send_to(&reply, writer).await?;
+                    log.bytes += reply.len() + IPV4_UDP_HEADER_BYTES;
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(log)
+}
+
+fn guid_prefix(host: u8) -> [u8; 12] {
+    let mut prefix = [0u8; 12];
+    prefix[..4].copy_from_slice(&[0x01, 0x0F, 0xDD, host]);
+    prefix[4..8].copy_from_slice(&std::process::id().to_le_bytes());
+    prefix
+}
+
+fn rtps_header(prefix: &[u8; 12]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(256);
+    header.extend_from_slice(b"RTPS");
+    header.extend_from_slice(&PROTOCOL_VERSION);
+    header.extend_from_slice(&VENDOR_ID);
+    header.extend_from_slice(prefix);
+    header
+}
+
+/// Appends a little-endian submessage; its body is padded to four bytes.
+fn submessage_into(out: &mut Vec<u8>, id: u8, flags: u8, body: &[u8]) {
+    let padded = body.len().next_multiple_of(4);
+    out.extend_from_slice(&[id, flags | FLAG_LITTLE_ENDIAN]);
+    out.extend_from_slice(&(padded as u16).to_le_bytes());
+    out.extend_from_slice(body);
+    out.resize(out.len() + padded - body.len(), 0);
+}
+
+fn sequence_number(sn: i64) -> [u8; 8] {
+    let mut bytes = [0u8; 8];
+    bytes[..4].copy_from_slice(&((sn >> 32) as i32).to_le_bytes());
+    bytes[4..].copy_from_slice(&(sn as u32).to_le_bytes());
+    bytes
+}
+
+fn read_sequence_number(bytes: &[u8]) -> Option<i64> {
+    let high = i32::from_le_bytes(bytes.get(..4)?.try_into().ok()?) as i64;
+    let low = u32::from_le_bytes(bytes.get(4..8)?.try_into().ok()?) as i64;
+    Some(high << 32 | low)
+}
+
+fn data_into(out: &mut Vec<u8>, reader: EntityId, writer: EntityId, sn: i64, payload: &[u8]) {
+    let mut body = Vec::with_capacity(20 + payload.len());
+    body.extend_from_slice(&0u16.to_le_bytes()); // extraFlags
+    body.extend_from_slice(&16u16.to_le_bytes()); // octetsToInlineQos
+    body.extend_from_slice(&reader);
+    body.extend_from_slice(&writer);
+    body.extend_from_slice(&sequence_number(sn));
+    body.extend_from_slice(payload);
+    submessage_into(out, SUBMESSAGE_DATA, FLAG_DATA, &body);
+}
+
+/// HEARTBEAT without the FinalFlag, so the reader always answers.
+fn heartbeat_into(out: &mut Vec<u8>, reader: EntityId, writer: EntityId, first: i64, last: i64, count: u32) {
+    let mut body = Vec::with_capacity(28);
+    body.extend_from_slice(&reader);
+    body.extend_from_slice(&writer);
+    body.extend_from_slice(&sequence_number(first));
+    body.extend_from_slice(&sequence_number(last));
+    body.extend_from_slice(&count.to_le_bytes());
+    submessage_into(out, SUBMESSAGE_HEARTBEAT, 0, &body);
+}
+
+/// ACKNACK acknowledging everything below `base` and asking for `missing`.
+fn acknack_into(out: &mut Vec<u8>, reader: EntityId, writer: EntityId, base: i64, missing: &[i64], count: u32) {
+    let bits = missing.iter().map(|&sn| (sn - base) as usize + 1).max().unwrap_or(0);
+    let mut bitmap = vec![0u32; bits.div_ceil(32)];
+    for &sn in missing {
+        let bit = (sn - base) as usize;
+        bitmap[bit / 32] |= 1 << (31 - bit % 32);
+    }
+    let mut body = Vec::with_capacity(24 + bitmap.len() * 4);
+    body.extend_from_slice(&reader);
+    body.extend_from_slice(&writer);
+    body.extend_from_slice(&sequence_number(base));
+    body.extend_from_slice(&(bits as u32).to_le_bytes());
+    for word in bitmap {
+        body.extend_from_slice(&word.to_le_bytes());
+    }
+    body.extend_from_slice(&count.to_le_bytes());
+    submessage_into(out, SUBMESSAGE_ACKNACK, if missing.is_empty() { FLAG_FINAL } else { 0 }, &body);
+}
+
+/// INFO_TS and DATA of one user sample: CDR of its send time (ns since `start`) and payload.
+fn user_message(prefix: &[u8; 12], sn: i64, payload: &[u8], start: Instant) -> Vec<u8> {
+    let mut message = rtps_header(prefix);
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+    let fraction = ((now.subsec_nanos() as u64) << 32) / 1_000_000_000;
+    submessage_into(&mut message, SUBMESSAGE_INFO_TS, 0, &[(now.as_secs() as u32).to_le_bytes(), (fraction as u32).to_le_bytes()].concat());
+
+    let mut sample = Vec::with_capacity(16 + payload.len());
+    sample.extend_from_slice(&ENCAPSULATION_CDR_LE);
+    sample.extend_from_slice(&(start.elapsed().as_nanos() as u64).to_le_bytes());
+    sample.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    sample.extend_from_slice(payload);
+    data_into(&mut message, USER_READER, USER_WRITER, sn, &sample);
+    message
+}
+
+/// CDR ParameterList, little endian.
+struct ParameterList(Vec<u8>);
+
+impl ParameterList {
+    fn new() -> Self {
+        Self(ENCAPSULATION_PL_CDR_LE.to_vec())
+    }
+
+    fn push(&mut self, pid: u16, value: &[u8]) {
+        let padded = value.len().next_multiple_of(4);
+        self.0.extend_from_slice(&pid.to_le_bytes());
+        self.0.extend_from_slice(&(padded as u16).to_le_bytes());
+        self.0.extend_from_slice(value);
+        self.0.resize(self.0.len() + padded - value.len(), 0);
+    }
+
+    fn push_string(&mut self, pid: u16, value: &str) {
+        let mut encoded = ((value.len() + 1) as u32).to_le_bytes().to_vec();
+        encoded.extend_from_slice(value.as_bytes());
+        encoded.push(0);
+        self.push(pid, &encoded);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.push(PID_SENTINEL, &[]);
+        self.0
+    }
+}
+
+fn locator(addr: SocketAddr) -> Vec<u8> {
+    let mut locator = LOCATOR_KIND_UDPV4.to_le_bytes().to_vec();
+    locator.extend_from_slice(&(addr.port() as u32).to_le_bytes());
+    locator.extend_from_slice(&[0u8; 12]);
+    match addr {
+        SocketAddr::V4(v4) => locator.extend_from_slice(&v4.ip().octets()),
+        SocketAddr::V6(_) => locator.extend_from_slice(&[0u8; 4]),
+    }
+    locator
+}
+
+/// SPDP DATA(p) announcing the participant and its builtin endpoints.
+fn spdp_announcement(prefix: &[u8; 12], addr: SocketAddr) -> Vec<u8> {
+    let mut list = ParameterList::new();
+    list.push(PID_PROTOCOL_VERSION, &PROTOCOL_VERSION);
+    list.push(PID_VENDORID, &VENDOR_ID);
+    list.push(PID_PARTICIPANT_GUID, &[&prefix[..], &ENTITY_PARTICIPANT].concat());
+    list.push(PID_METATRAFFIC_UNICAST_LOCATOR, &locator(addr));
+    list.push(PID_DEFAULT_UNICAST_LOCATOR, &locator(addr));
+    list.push(PID_PARTICIPANT_LEASE_DURATION, &[20i32.to_le_bytes(), 0u32.to_le_bytes()].concat());
+    list.push(PID_BUILTIN_ENDPOINT_SET, &BUILTIN_ENDPOINTS.to_le_bytes());
+    let mut message = rtps_header(prefix);
+    data_into(&mut message, SPDP_READER, SPDP_WRITER, 1, &list.finish());
+    message
+}
+
+/// SEDP DATA(w) or DATA(r) for one user endpoint, with the HEARTBEAT that asks for its acknowledgement.
+fn endpoint_announcement(
+    prefix: &[u8; 12],
+    endpoint: EntityId,
+    qos: &EndpointQos,
+    history_depth: usize,
+    reader: EntityId,
+    writer: EntityId,
+) -> Vec<u8> {
+    let mut list = ParameterList::new();
+    list.push(PID_ENDPOINT_GUID, &[&prefix[..], &endpoint].concat());
+    list.push_string(PID_TOPIC_NAME, TOPIC_NAME);
+    list.push_string(PID_TYPE_NAME, TYPE_NAME);
+    qos.parameters(&mut list, history_depth);
+    let mut message = rtps_header(prefix);
+    data_into(&mut message, reader, writer, 1, &list.finish());
+    heartbeat_into(&mut message, reader, writer, 1, 1, 1);
+    message
+}
+
+/// Submessages of an RTPS message as (id, flags, body); `None` if it is not one.
+fn submessages(message: &[u8]) -> Option<Vec<(u8, u8, &[u8])>> {
+    if message.len() < RTPS_HEADER_BYTES || &message[..4] != b"RTPS" {
+        return None;
+    }
+    let mut found = Vec::new();
+    let mut rest = &message[RTPS_HEADER_BYTES..];
+    while rest.len() >= 4 {
+        let (id, flags) = (rest[0], rest[1]);
+        let length = if flags & FLAG_LITTLE_ENDIAN != 0 {
+            u16::from_le_bytes([rest[2], rest[3]])
+        } else {
+            u16::from_be_bytes([rest[2], rest[3]])
+        } as usize;
+        // A zero length on the last submessage runs to the end of the message
+        let length = if length == 0 { rest.len() - 4 } else { length };
+        found.push((id, flags, rest.get(4..4 + length)?));
+        rest = &rest[4 + length..];
+    }
+    Some(found)
+}
+
+/// Reader, writer, sequence number and serialized payload of a DATA body.
+fn data_fields(body: &[u8]) -> Option<(EntityId, EntityId, i64, &[u8])> {
+    let inline_qos = u16::from_le_bytes(body.get(2..4)?.try_into().ok()?) as usize;
+    Some((
+        body.get(4..8)?.try_into().ok()?,
+        body.get(8..12)?.try_into().ok()?,
+        read_sequence_number(body.get(12..20)?)?,
+        body.get(4 + inline_qos..)?,
+    ))
+}
+
+/// Reader, writer, first and last sequence number of a HEARTBEAT body.
+fn heartbeat_fields(body: &[u8]) -> Option<(EntityId, EntityId, i64, i64)> {
+    Some((
+        body.get(..4)?.try_into().ok()?,
+        body.get(4..8)?.try_into().ok()?,
+        read_sequence_number(body.get(8..16)?)?,
+        read_sequence_number(body.get(16..24)?)?,
+    ))
+}
+
+/// Bitmap base and the sequence numbers an ACKNACK asks for.
+fn acknack_fields(body: &[u8]) -> Option<(i64, Vec<i64>)> {
+    let base = read_sequence_number(body.get(8..16)?)?;
+    let bits = u32::from_le_bytes(body.get(16..20)?.try_into().ok()?) as usize;
+    let mut missing = Vec::new();
+    for bit in 0..bits.min(MAX_BITMAP_BITS) {
+        let word = u32::from_le_bytes(body.get(20 + bit / 32 * 4..24 + bit / 32 * 4)?.try_into().ok()?);
+        if word & (1 << (31 - bit % 32)) != 0 {
+            missing.push(base + bit as i64);
+        }
+    }
+    Some((base, missing))
+}
+
+/// Parameters of a ParameterList up to its sentinel.
+fn parameters(mut list: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut found = Vec::new();
+    while list.len() >= 4 {
+        let pid = u16::from_le_bytes([list[0], list[1]]);
+        let length = u16::from_le_bytes([list[2], list[3]]) as usize;
+        if pid == PID_SENTINEL {
+            break;
+        }
+        let Some(value) = list.get(4..4 + length) else {
+            break;
+        };
+        found.push((pid, value));
+        list = &list[4 + length..];
+    }
+    found
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((p / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn qos_mismatches_block_matching_and_reliable_repairs_losses() {
+        let metrics = DdsAnalyzer::builder()
+            .publish_rates(vec![2_000u32])
+            .duration(Duration::from_millis(300))
+            .loss_rate(0.05)
+            .discovery_iterations(2usize)
+            .build()
+            .analyze_dds()
+            .await
+            .unwrap();
+
+        let policies: Vec<Option<&str>> = metrics.discovery.iter().map(|run| run.incompatible_policy.as_deref()).collect();
+        assert_eq!(policies, [None, None, None, Some("RELIABILITY"), Some("DURABILITY"), Some("DEADLINE")]);
+        assert!(metrics.discovery[..3].iter().all(|run| run.matched && run.first_sample_ms.is_some()));
+        assert_eq!(metrics.discovery[2].historical_samples, HISTORICAL_SAMPLES);
+        assert_eq!(metrics.discovery[0].historical_samples, 0);
+
+        let best_effort = &metrics.publish[0];
+        let reliable = &metrics.publish[1];
+        assert!(best_effort.lost > 0 && best_effort.repairs == 0);
+        assert!(reliable.repairs > 0);
+        assert!(reliable.lost < best_effort.lost);
+        assert!(reliable.control_bytes_per_sample > 0.0 && best_effort.control_bytes_per_sample == 0.0);
+    }
+}
@@ -0,0 +1,608 @@
+// matter-project/src/distributed.rs
+/*!
+Clock-synchronized two-node measurement (coordinator + agent over a control channel)
+*/
+
+use analysis_core::platform::TestEnvironment;
+use analysis_core::retry::RetryPolicy;
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::task::JoinHandle;
+
+use crate::throughput::{ThroughputBackend, UdpSink};
+
+const SYNC_SAMPLES: u32 = 16;
+const ONE_WAY_PROBES: u32 = 100;
+const PROBE_IDLE_TIMEOUT: Duration = Duration::from_millis(500);
+const ECHO_ROUNDS: u32 = 50;
+const RESPONDER_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Responder datagram kinds: echo replies with the same bytes, commissioning
+// steps reply with a response of the requested size
+const RESPONDER_ECHO: u8 = 0x01;
+const RESPONDER_COMMISSIONING: u8 = 0x02;
+
+/// Matter commissioning exchanges (PASE, credential provisioning, CASE) with
+/// typical request/response sizes in bytes.
+pub(crate) const COMMISSIONING_STEPS: &[(&str, u16, u16)] = &[
+    ("PBKDFParamRequest", 82, 120),
+    ("PASE_Pake1", 85, 150),
+    ("PASE_Pake3", 50, 30),
+    ("CSRRequest", 60, 300),
+    ("AddTrustedRootCertificate", 400, 30),
+    ("AddNOC", 650, 40),
+    ("CASE_Sigma1", 160, 500),
+    ("CASE_Sigma3", 450, 30),
+    ("CommissioningComplete", 40, 40),
+];
+
+/// Messages exchanged on the TCP control channel, one JSON object per line.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ControlMessage {
+    SyncRequest { t1_ns: u64 },
+    SyncResponse { t1_ns: u64, t2_ns: u64, t3_ns: u64 },
+    OneWayStart { count: u32 },
+    OneWayReady { udp_port: u16 },
+    OneWayFinish,
+    OneWayReport { arrivals: Vec<ProbeArrival> },
+    ResponderStart,
+    ResponderReady { udp_port: u16 },
+    /// `batch_size` > 1 asks the sink to drain with recvmmsg (Linux), or with
+    /// io_uring sets the receives kept queued.
+    SinkStart {
+        batch_size: usize,
+        #[serde(default)]
+        backend: ThroughputBackend,
+    },
+    SinkReady { udp_port: u16 },
+    SinkFinish,
+    SinkReport { packets: u64, bytes: u64 },
+    Done,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeArrival {
+    pub sequence: u32,
+    pub sent_ns: u64,
+    pub received_ns: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DistributedMetrics {
+    pub agent_address: String,
+    /// Failed attempts at connecting to the agent before the measurement started.
+    pub connection_retries: u32,
+    pub clock_offset_ms: f64,
+    pub sync_round_trip_ms: f64,
+    pub offset_uncertainty_ms: f64,
+    pub sync_samples: u32,
+    pub probes_sent: u32,
+    pub probes_received: u32,
+    pub one_way_latency_ms: f64,
+    pub one_way_latency_min_ms: f64,
+    pub one_way_latency_max_ms: f64,
+    pub one_way_packet_loss_rate: f64,
+    pub echo: RemoteEchoMetrics,
+    pub commissioning: RemoteCommissioningMetrics,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoteEchoMetrics {
+    pub rounds: u32,
+    pub replies: u32,
+    pub round_trip_time_ms: f64,
+    pub round_trip_min_ms: f64,
+    pub round_trip_max_ms: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoteCommissioningMetrics {
+    pub steps: Vec<CommissioningStepTiming>,
+    pub commissioning_time_ms: f64,
+    pub bytes_sent: u32,
+    pub bytes_received: u32,
+    pub completed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommissioningStepTiming {
+    pub step: String,
+    pub request_bytes: u32,
+    pub response_bytes: u32,
+    pub round_trip_ms: f64,
+}
+
+/// Clock offset of the agent relative to the coordinator, NTP style.
+#[derive(Debug, Clone, Copy)]
+pub struct ClockOffset {
+    pub offset_ns: f64,
+    pub round_trip_ns: f64,
+}
+
+impl ClockOffset {
+    /// Standard NTP estimate: offset = ((t2 - t1) + (t3 - t4)) / 2, delay = (t4 - t1) - (t3 - t2).
+    pub fn from_exchange(t1_ns: u64, t2_ns: u64, t3_ns: u64, t4_ns: u64) -> Self {
+        let (t1, t2, t3, t4) = (t1_ns as f64, t2_ns as f64, t3_ns as f64, t4_ns as f64);
+        Self {
+            offset_ns: ((t2 - t1) + (t3 - t4)) / 2.0,
+            round_trip_ns: (t4 - t1) - (t3 - t2),
+        }
+    }
+
+    /// Converts a timestamp taken on the agent's clock onto the coordinator's clock.
+    pub fn remote_to_local_ns(&self, remote_ns: u64) -> f64 {
+        remote_ns as f64 - self.offset_ns
+    }
+}
+
+/// Wildcard bind address in the same family as the peer.
+fn unspecified_addr(peer: SocketAddr) -> SocketAddr {
+    match peer {
+        SocketAddr::V4(_) => "0.0.0.0:0".parse().expect("valid IPv4 wildcard"),
+        SocketAddr::V6(_) => "[::]:0".parse().expect("valid IPv6 wildcard"),
+    }
+}
+
+pub fn wall_clock_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+pub struct ControlChannel {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+}
+
+impl ControlChannel {
+    pub fn new(stream: TcpStream) -> Result<Self> {
+        stream.set_nodelay(true)?;
+        let (reader, writer) = stream.into_split();
+        Ok(Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        })
+    }
+
+    pub async fn send(&mut self, message: &ControlMessage) -> Result<()> {
+        let mut line = serde_json::to_string(message)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+
+    pub async fn receive(&mut self) -> Result<Option<ControlMessage>> {
+        match self.lines.next_line().await? {
+            Some(line) => Ok(Some(serde_json::from_str(&line)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn expect(&mut self) -> Result<ControlMessage> {
+        self.receive()
+            .await?
+            .ok_or_else(|| anyhow!("control channel closed by peer"))
+    }
+}
+
+pub struct DistributedCoordinator {
+    agent_addr: SocketAddr,
+    environment: TestEnvironment,
+    retry_policy: RetryPolicy,
+}
+
+impl DistributedCoordinator {
+    pub fn new(agent_addr: SocketAddr) -> Self {
+        Self {
+            agent_addr,
+            environment: TestEnvironment::detect(),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// How connecting to the agent is retried.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub async fn analyze_one_way_latency(&mut self) -> Result<DistributedMetrics> {
+        println!("\n🌐 Distributed Measurement - Coordinator ↔ Agent {}", self.agent_addr);
+        println!("--------------------------------------------------------");
+
+        let agent_addr = self.agent_addr;
+        let connection = self
+            .retry_policy
+            .run("agent connection", || async move { Ok(TcpStream::connect(agent_addr).await?) })
+            .await?;
+        let mut channel = ControlChannel::new(connection.value)?;
+
+        let offset = self.estimate_clock_offset(&mut channel).await?;
+        info!("Clock offset {:.3}ms (rtt {:.3}ms)", offset.offset_ns / 1e6, offset.round_trip_ns / 1e6);
+
+        let arrivals = self.send_one_way_probes(&mut channel).await?;
+
+        let responder = self.start_remote_responder(&mut channel).await?;
+        let echo = self.measure_echo(&responder).await?;
+        let commissioning = self.run_remote_commissioning(&responder).await?;
+        channel.send(&ControlMessage::Done).await?;
+
+        let mut latencies: Vec<f64> = arrivals
+            .iter()
+            .map(|a| (offset.remote_to_local_ns(a.received_ns) - a.sent_ns as f64) / 1e6)
+            .collect();
+        latencies.sort_by(|a, b| a.total_cmp(b));
+
+        let received = latencies.len() as u32;
+        let metrics = DistributedMetrics {
+            agent_address: self.agent_addr.to_string(),
+            connection_retries: connection.retries,
+            clock_offset_ms: offset.offset_ns / 1e6,
+            sync_round_trip_ms: offset.round_trip_ns / 1e6,
+            offset_uncertainty_ms: offset.round_trip_ns / 2e6,
+            sync_samples: SYNC_SAMPLES,
+            probes_sent: ONE_WAY_PROBES,
+            probes_received: received,
+            one_way_latency_ms: latencies.get(latencies.len() / 2).copied().unwrap_or(0.0),
+            one_way_latency_min_ms: latencies.first().copied().unwrap_or(0.0),
+            one_way_latency_max_ms: latencies.last().copied().unwrap_or(0.0),
+            one_way_packet_loss_rate: 1.0 - received as f64 / ONE_WAY_PROBES as f64,
+            echo,
+            commissioning,
+        };
+
+        println!("✅ Clock Offset: {:.3}ms (±{:.3}ms)", metrics.clock_offset_ms, metrics.offset_uncertainty_ms);
+        println!("✅ One-Way Latency: {:.3}ms (min {:.3}ms, max {:.3}ms)",
+                 metrics.one_way_latency_ms, metrics.one_way_latency_min_ms, metrics.one_way_latency_max_ms);
+        println!("✅ Probes Received: {}/{}", metrics.probes_received, metrics.probes_sent);
+        println!("✅ Echo RTT: {:.3}ms ({}/{} replies)",
+                 metrics.echo.round_trip_time_ms, metrics.echo.replies, metrics.echo.rounds);
+        println!("✅ Remote Commissioning: {:.2}ms over {} exchanges",
+                 metrics.commissioning.commissioning_time_ms, metrics.commissioning.steps.len());
+
+        Ok(metrics)
+    }
+
+    async fn estimate_clock_offset(&self, channel: &mut ControlChannel) -> Result<ClockOffset> {
+        let mut best: Option<ClockOffset> = None;
+
+        for _ in 0..SYNC_SAMPLES {
+            let t1_ns = wall_clock_ns();
+            channel.send(&ControlMessage::SyncRequest { t1_ns }).await?;
+
+            match channel.expect().await? {
+                ControlMessage::SyncResponse { t1_ns, t2_ns, t3_ns } => {
+                    let sample = ClockOffset::from_exchange(t1_ns, t2_ns, t3_ns, wall_clock_ns());
+                    debug!("Sync sample: offset {:.0}ns, rtt {:.0}ns", sample.offset_ns, sample.round_trip_ns);
+
+                    // The exchange with the smallest round trip has the least queuing asymmetry
+                    best = match best {
+                        Some(b) if b.round_trip_ns <= sample.round_trip_ns => Some(b),
+                        _ => Some(sample),
+                    };
+                }
+                other => return Err(anyhow!("unexpected control message during sync: {:?}", other)),
+            }
+        }
+
+        best.ok_or_else(|| anyhow!("no clock sync samples collected"))
+    }
+
+    async fn send_one_way_probes(&self, channel: &mut ControlChannel) -> Result<Vec<ProbeArrival>> {
+        channel.send(&ControlMessage::OneWayStart { count: ONE_WAY_PROBES }).await?;
+
+        let udp_port = match channel.expect().await? {
+            ControlMessage::OneWayReady { udp_port } => udp_port,
+            other => return Err(anyhow!("unexpected control message before probes: {:?}", other)),
+        };
+
+        let socket = self.environment.bind_udp_socket(unspecified_addr(self.agent_addr))?;
+        let target = SocketAddr::new(self.agent_addr.ip(), udp_port);
+
+        for sequence in 0..ONE_WAY_PROBES {
+            let mut probe = [0u8; 12];
+            probe[..4].copy_from_slice(&sequence.to_be_bytes());
+            probe[4..].copy_from_slice(&wall_clock_ns().to_be_bytes());
+
+            if let Err(e) = socket.send_to(&probe, target).await {
+                warn!("⚠️ One-way probe {} failed: {}", sequence, e);
+            }
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+
+        channel.send(&ControlMessage::OneWayFinish).await?;
+
+        match channel.expect().await? {
+            ControlMessage::OneWayReport { arrivals } => Ok(arrivals),
+            other => Err(anyhow!("unexpected control message after probes: {:?}", other)),
+        }
+    }
+
+    async fn start_remote_responder(&self, channel: &mut ControlChannel) -> Result<UdpSocket> {
+        channel.send(&ControlMessage::ResponderStart).await?;
+
+        let udp_port = match channel.expect().await? {
+            ControlMessage::ResponderReady { udp_port } => udp_port,
+            other => return Err(anyhow!("unexpected control message before responder use: {:?}", other)),
+        };
+
+        let socket = self.environment.bind_udp_socket(unspecified_addr(self.agent_addr))?;
+        socket.connect(SocketAddr::new(self.agent_addr.ip(), udp_port)).await?;
+        Ok(socket)
+    }
+
+    async fn measure_echo(&self, socket: &UdpSocket) -> Result<RemoteEchoMetrics> {
+        let mut round_trips: Vec<f64> = Vec::new();
+        let mut buffer = [0u8; 1500];
+
+        for sequence in 0..ECHO_ROUNDS {
+            let mut request = vec![RESPONDER_ECHO];
+            request.extend_from_slice(&sequence.to_be_bytes());
+
+            let start = std::time::Instant::now();
+            socket.send(&request).await?;
+
+            // Only accept the reply for this round; stale late replies are skipped
+            let deadline = tokio::time::Instant::now() + RESPONDER_TIMEOUT;
+            while let Ok(Ok(len)) = tokio::time::timeout_at(deadline, socket.recv(&mut buffer)).await {
+                if buffer[..len] == request[..] {
+                    round_trips.push(start.elapsed().as_micros() as f64 / 1000.0);
+                    break;
+                }
+            }
+        }
+
+        round_trips.sort_by(|a, b| a.total_cmp(b));
+
+        Ok(RemoteEchoMetrics {
+            rounds: ECHO_ROUNDS,
+            replies: round_trips.len() as u32,
+            round_trip_time_ms: round_trips.get(round_trips.len() / 2).copied().unwrap_or(0.0),
+            round_trip_min_ms: round_trips.first().copied().unwrap_or(0.0),
+            round_trip_max_ms: round_trips.last().copied().unwrap_or(0.0),
+        })
+    }
+
+    async fn run_remote_commissioning(&self, socket: &UdpSocket) -> Result<RemoteCommissioningMetrics> {
+        let mut steps = Vec::with_capacity(COMMISSIONING_STEPS.len());
+        let mut buffer = [0u8; 1500];
+        let mut completed = true;
+        let commissioning_start = std::time::Instant::now();
+
+        for (index, (step, request_bytes, response_bytes)) in COMMISSIONING_STEPS.iter().enumerate() {
+            let mut request = vec![0u8; *request_bytes as usize];
+            request[0] = RESPONDER_COMMISSIONING;
+            request[1] = index as u8;
+            request[2..4].copy_from_slice(&response_bytes.to_be_bytes());
+
+            let start = std::time::Instant::now();
+            socket.send(&request).await?;
+
+            let received = match tokio::time::timeout(RESPONDER_TIMEOUT, socket.recv(&mut buffer)).await {
+                Ok(Ok(len)) if len >= 2 && buffer[0] == RESPONDER_COMMISSIONING && buffer[1] == index as u8 => len,
+                _ => {
+                    warn!("⚠️ Remote commissioning step {} got no response", step);
+                    completed = false;
+                    break;
+                }
+            };
+
+            steps.push(CommissioningStepTiming {
+                step: step.to_string(),
+                request_bytes: request.len() as u32,
+                response_bytes: received as u32,
+                round_trip_ms: start.elapsed().as_micros() as f64 / 1000.0,
+            });
+        }
+
+        Ok(RemoteCommissioningMetrics {
+            commissioning_time_ms: commissioning_start.elapsed().as_micros() as f64 / 1000.0,
+            bytes_sent: steps.iter().map(|s| s.request_bytes).sum(),
+            bytes_received: steps.iter().map(|s| s.response_bytes).sum(),
+            steps,
+            completed,
+        })
+    }
+}
+
+pub struct DistributedAgent {
+    bind_addr: SocketAddr,
+    environment: TestEnvironment,
+}
+
+impl DistributedAgent {
+    pub fn new(bind_addr: SocketAddr) -> Self {
+        Self {
+            bind_addr,
+            environment: TestEnvironment::detect(),
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        let listener = TcpListener::bind(self.bind_addr).await?;
+        self.run_on(listener).await
+    }
+
+    /// Serves coordinators on a listener bound elsewhere, e.g. on a port from a `PortAllocator`.
+    pub async fn run_on(&mut self, listener: TcpListener) -> Result<()> {
+        println!("🛰️ Measurement agent listening on {}", listener.local_addr()?);
+        println!("✅ Device Class: {:?} ({}, {})",
+                 self.environment.device_class, self.environment.cpu_model, self.environment.architecture);
+
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            info!("Coordinator connected from {}", peer);
+
+            let local_ip = stream.local_addr()?.ip();
+            if let Err(e) = self.serve_coordinator(stream, local_ip).await {
+                warn!("⚠️ Coordinator session with {} ended with error: {}", peer, e);
+            }
+        }
+    }
+
+    async fn serve_coordinator(&self, stream: TcpStream, local_ip: std::net::IpAddr) -> Result<()> {
+        let mut channel = ControlChannel::new(stream)?;
+        let mut receiver: Option<JoinHandle<Vec<ProbeArrival>>> = None;
+        let mut responder: Option<JoinHandle<()>> = None;
+        let mut sink: Option<UdpSink> = None;
+
+        while let Some(message) = channel.receive().await? {
+            match message {
+                ControlMessage::SyncRequest { t1_ns } => {
+                    let t2_ns = wall_clock_ns();
+                    let t3_ns = wall_clock_ns();
+                    channel.send(&ControlMessage::SyncResponse { t1_ns, t2_ns, t3_ns }).await?;
+                }
+                ControlMessage::OneWayStart { count } => {
+                    let socket = self.environment.bind_udp_socket(SocketAddr::new(local_ip, 0))?;
+                    let udp_port = socket.local_addr()?.port();
+                    receiver = Some(tokio::spawn(Self::collect_probes(socket, count)));
+                    channel.send(&ControlMessage::OneWayReady { udp_port }).await?;
+                }
+                ControlMessage::OneWayFinish => {
+                    let arrivals = match receiver.take() {
+                        Some(handle) => handle.await?,
+                        None => Vec::new(),
+                    };
+                    channel.send(&ControlMessage::OneWayReport { arrivals }).await?;
+                }
+                ControlMessage::ResponderStart => {
+                    let socket = self.environment.bind_udp_socket(SocketAddr::new(local_ip, 0))?;
+                    let udp_port = socket.local_addr()?.port();
+                    if let Some(previous) = responder.replace(tokio::spawn(Self::respond(socket))) {
+                        previous.abort();
+                    }
+                    channel.send(&ControlMessage::ResponderReady { udp_port }).await?;
+                }
+                ControlMessage::SinkStart { batch_size, backend } => {
+                    let socket = self.environment.bind_udp_socket(SocketAddr::new(local_ip, 0))?;
+                    let udp_port = socket.local_addr()?.port();
+                    if let Some(previous) = sink.replace(UdpSink::spawn(socket, batch_size, backend)?) {
+                        previous.finish().await?;
+                    }
+                    channel.send(&ControlMessage::SinkReady { udp_port }).await?;
+                }
+                ControlMessage::SinkFinish => {
+                    let counts = match sink.take() {
+                        Some(running) => running.finish().await?,
+                        None => Default::default(),
+                    };
+                    channel.send(&ControlMessage::SinkReport { packets: counts.packets, bytes: counts.bytes }).await?;
+                }
+                ControlMessage::Done => break,
+                other => warn!("⚠️ Agent ignoring unexpected control message: {:?}", other),
+            }
+        }
+
+        if let Some(handle) = responder {
+            handle.abort();
+        }
+        if let Some(running) = sink {
+            running.finish().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Answers echo and commissioning datagrams until the session ends.
+    async fn respond(socket: UdpSocket) {
+        let mut buffer = [0u8; 1500];
+
+        loop {
+            let (len, peer) = match socket.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                Err(e) => {
+                    warn!("⚠️ Responder receive failed: {}", e);
+                    return;
+                }
+            };
+
+            let reply = match buffer[..len].first().copied() {
+                Some(RESPONDER_ECHO) => buffer[..len].to_vec(),
+                Some(RESPONDER_COMMISSIONING) if len >= 4 => {
+                    let response_len = u16::from_be_bytes([buffer[2], buffer[3]]).max(2) as usize;
+                    let mut response = vec![0u8; response_len];
+                    response[0] = RESPONDER_COMMISSIONING;
+                    response[1] = buffer[1];
+                    response
+                }
+                _ => {
+                    debug!("Responder ignoring unknown datagram ({} bytes)", len);
+                    continue;
+                }
+            };
+
+            if let Err(e) = socket.send_to(&reply, peer).await {
+                warn!("⚠️ Responder reply to {} failed: {}", peer, e);
+            }
+        }
+    }
+
+    async fn collect_probes(socket: UdpSocket, count: u32) -> Vec<ProbeArrival> {
+        let mut arrivals = Vec::with_capacity(count as usize);
+        let mut buffer = [0u8; 64];
+
+        while arrivals.len() < count as usize {
+            match tokio::time::timeout(PROBE_IDLE_TIMEOUT, socket.recv_from(&mut buffer)).await {
+                Ok(Ok((len, _))) if len >= 12 => {
+                    let received_ns = wall_clock_ns();
+                    let sequence = u32::from_be_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+                    let mut sent = [0u8; 8];
+                    sent.copy_from_slice(&buffer[4..12]);
+                    arrivals.push(ProbeArrival {
+                        sequence,
+                        sent_ns: u64::from_be_bytes(sent),
+                        received_ns,
+                    });
+                }
+                Ok(Ok(_)) => debug!("Ignoring short probe datagram"),
+                Ok(Err(e)) => {
+                    warn!("⚠️ Probe receive failed: {}", e);
+                    break;
+                }
+                Err(_) => break, // idle timeout: remaining probes were lost
+            }
+        }
+
+        arrivals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn coordinator_and_agent_measure_over_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let agent_addr = listener.local_addr().unwrap();
+        let agent = tokio::spawn(async move { DistributedAgent::new(agent_addr).run_on(listener).await });
+
+        let metrics = DistributedCoordinator::new(agent_addr).analyze_one_way_latency().await.unwrap();
+        agent.abort();
+
+        // Both ends read the same clock, so the estimated offset is within the sync round trip
+        assert!(metrics.clock_offset_ms.abs() <= metrics.sync_round_trip_ms.max(1.0));
+        assert_eq!(metrics.sync_samples, SYNC_SAMPLES);
+        assert_eq!(metrics.probes_sent, ONE_WAY_PROBES);
+        assert!(metrics.probes_received > 0);
+        assert!(metrics.one_way_latency_min_ms <= metrics.one_way_latency_ms);
+        assert!(metrics.one_way_latency_ms <= metrics.one_way_latency_max_ms);
+        assert_eq!((metrics.echo.rounds, metrics.echo.replies), (ECHO_ROUNDS, ECHO_ROUNDS));
+
+        let commissioning = &metrics.commissioning;
+        assert!(commissioning.completed);
+        assert_eq!(commissioning.steps.len(), COMMISSIONING_STEPS.len());
+        let requested: u32 = COMMISSIONING_STEPS.iter().map(|(_, request, _)| u32::from(*request)).sum();
+        let answered: u32 = COMMISSIONING_STEPS.iter().map(|(_, _, response)| u32::from(*response)).sum();
+        assert_eq!((commissioning.bytes_sent, commissioning.bytes_received), (requested, answered));
+    }
+}
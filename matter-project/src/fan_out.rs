@@ -0,0 +1,408 @@
+// matter-project/src/fan_out.rs
+/*!
+Observer fan-out - one sensor, N observers via CoAP observe, CoAP group notifications, Matter subscriptions and an MQTT broker
+
+A sensor on a constrained link (802.15.4-like by default) changes a few
+values at every sample, and N observers on the LAN want each of them. Who
+copies the data N times decides what the sensor's own link carries:
+
+- CoAP observe: one NON notification per value per observer, all sent by
+  the sensor.
+- CoAP group notifications (RFC 7641 observe with notifications sent to a
+  multicast group): one NON per value, whatever N is; the LAN delivers the
+  multicast frame to every observer at once.
+- Matter subscriptions: one ReportData per subscriber that batches every
+  changed attribute, answered by the subscriber's StatusResponse and the
+  sensor's MRP standalone ack. Matter groupcast only carries commands, so
+  reports stay unicast.
+- MQTT: one QoS 1 PUBLISH per value to the broker, acknowledged with a
+  PUBACK; the broker on the LAN sends the copies and takes the
+  subscribers' PUBACKs.
+
+Message sizes come from the same encoders as the other benchmarks, with
+IPv4 plus UDP/TCP headers; 6LoWPAN compression and 802.15.4 fragmentation
+are left out, and so are bare TCP ACKs. The sensor's link is one FIFO
+channel with a rate and a one-way delay: the radio is half duplex, so the
+acknowledgements it sends and receives queue behind its reports. The
+switched LAN only adds its serialization and delay, except that the
+broker's copies queue on its own uplink. A value's latency runs from the
+sample to its arrival at an observer; an observer has the sample once its
+last value is there.
+*/
+
+use anyhow::{anyhow, Result};
+use metrics_core::tlv::{Tag, TlvWriter};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::bdx::{matter_message, MRP_STANDALONE_ACK, PROTOCOL_SECURE_CHANNEL};
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{
+    coap_message, coap_uint, mqtt_packet, mqtt_publish, tlv, COAP_CONTENT, COAP_NON, COAP_OPTION_CONTENT_FORMAT,
+    COAP_OPTION_OBSERVE, IM_REPORT_DATA, IM_STATUS_RESPONSE, MQTT_PUBACK, PROTOCOL_IM,
+};
+use crate::transport_modes::{IPV4_TCP_HEADER_BYTES, IPV4_UDP_HEADER_BYTES};
+
+const SUBSCRIPTION_ID: u32 = 0x5EB5_0002;
+const SENSOR_ENDPOINT: u16 = 1;
+
+/// Changing values in report order: Matter cluster and attribute, and the MQTT topic leaf.
+const SENSOR_VALUES: [(u32, u32, &str); 4] = [
+    (0x0402, 0x0000, "temperature"),  // Temperature Measurement: MeasuredValue
+    (0x0405, 0x0000, "humidity"),     // Relative Humidity Measurement: MeasuredValue
+    (0x0403, 0x0000, "pressure"),     // Pressure Measurement: MeasuredValue
+    (0x002F, 0x000C, "battery"),      // Power Source: BatPercentRemaining
+];
+
+#[derive(Debug, Clone)]
+pub struct FanOutConfig {
+    pub observer_counts: Vec<usize>,
+    /// Values that change together at each sample; Matter batches them into one report.
+    pub values_per_sample: usize,
+    pub samples: usize,
+    pub sample_interval: Duration,
+    /// Rate and one-way delay of the sensor's own link to its border router.
+    pub device_rate_kbit: u32,
+    pub device_delay: Duration,
+    /// Rate and one-way delay of the LAN between the border router, the broker and the observers.
+    pub lan_rate_kbit: u32,
+    pub lan_delay: Duration,
+}
+
+impl Default for FanOutConfig {
+    fn default() -> Self {
+        Self {
+            observer_counts: vec![1, 4, 16, 64],
+            values_per_sample: 3,
+            samples: 100,
+            sample_interval: Duration::from_secs(1),
+            device_rate_kbit: 250,
+            device_delay: Duration::from_millis(5),
+            lan_rate_kbit: 100_000,
+            lan_delay: Duration::from_millis(1),
+        }
+    }
+}
+
+analyzer_builder!(FanOutAnalyzerBuilder => FanOutAnalyzer(FanOutConfig) {
+    observer_counts: Vec<usize>,
+    values_per_sample: usize,
+    samples: usize,
+    sample_interval: Duration,
+    device_rate_kbit: u32,
+    device_delay: Duration,
+    lan_rate_kbit: u32,
+    lan_delay: Duration,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FanOutMetrics {
+    pub values_per_sample: usize,
+    pub samples: usize,
+    pub sample_interval_ms: f64,
+    pub device_rate_kbit: u32,
+    pub lan_rate_kbit: u32,
+    pub runs: Vec<FanOutRun>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FanOutRun {
+    pub protocol: String,
+    pub observers: usize,
+    /// Messages over the sensor's link per sample, both directions.
+    pub device_messages_per_sample: f64,
+    /// Bytes over the sensor's link per sample, both directions.
+    pub device_link_bytes_per_sample: f64,
+    /// Bytes over every link per sample; a message crossing two links counts twice.
+    pub wire_bytes_per_sample: f64,
+    /// Share of the run the sensor's link was busy; above 1 a backlog builds.
+    pub device_link_utilization: f64,
+    /// Sample to observer, over every observer of every sample.
+    pub latency_mean_ms: f64,
+    pub latency_p95_ms: f64,
+    pub latency_max_ms: f64,
+    /// First to last observer of a sample, averaged over samples.
+    pub spread_mean_ms: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scheme {
+    CoapObserve,
+    CoapGroup,
+    MatterSubscriptions,
+    MqttBroker,
+}
+
+impl Scheme {
+    fn name(self) -> &'static str {
+        match self {
+            Scheme::CoapObserve => "CoAP observe (unicast)",
+            Scheme::CoapGroup => "CoAP group notifications",
+            Scheme::MatterSubscriptions => "Matter subscriptions",
+            Scheme::MqttBroker => "MQTT broker fan-out",
+        }
+    }
+}
+
+/// Sizes on the wire of every message the schemes send, headers included.
+struct Messages {
+    coap_notification: usize,
+    matter_report: usize,
+    matter_status: usize,
+    matter_ack: usize,
+    mqtt_publish: usize,
+    mqtt_puback: usize,
+}
+
+impl Messages {
+    fn new(values: usize) -> Result<Self> {
+        let value = b"21.50";
+        let notification = coap_message(
+            COAP_NON,
+            COAP_CONTENT,
+            0x2001,
+            &[(COAP_OPTION_OBSERVE, coap_uint(0x0102)), (COAP_OPTION_CONTENT_FORMAT, Vec::new())],
+            value,
+        );
+        let report = tlv(|writer: &mut TlvWriter| {
+            writer.start_struct(Tag::Anonymous)?;
+            writer.u32(Tag::Context(0), SUBSCRIPTION_ID)?;
+            writer.start_array(Tag::Context(1))?; // AttributeReportIBs
+            for (cluster, attribute, _) in SENSOR_VALUES.iter().cycle().take(values) {
+                writer.start_struct(Tag::Anonymous)?;
+                writer.start_struct(Tag::Context(1))?; // AttributeDataIB
+                writer.u32(Tag::Context(0), 0x0102)?; // DataVersion
+                writer.start_list(Tag::Context(1))?;
+                writer.u16(Tag::Context(2), SENSOR_ENDPOINT)?;
+                writer.u32(Tag::Context(3), *cluster)?;
+                writer.u32(Tag::Context(4), *attribute)?;
+                writer.end_container()?;
+                writer.i16(Tag::Context(2), 2150)?;
+                writer.end_container()?;
+                writer.end_container()?;
+            }
+            writer.end_container()?;
+            writer.u8(Tag::Context(255), 11)?;
+            writer.end_container()
+        })?;
+        // Topics differ in length; size the publishes by the longest
+        let topic_leaf = SENSOR_VALUES.iter().take(values.max(1)).map(|(_, _, leaf)| *leaf).max_by_key(|leaf| leaf.len());
+        let topic = format!("sensors/dev1/{}", topic_leaf.unwrap_or("value"));
+
+        Ok(Self {
+            coap_notification: notification.len() + IPV4_UDP_HEADER_BYTES,
+            matter_report: matter_message(PROTOCOL_IM, IM_REPORT_DATA, &report).len() + IPV4_UDP_HEADER_BYTES,
+            matter_status: matter_message(PROTOCOL_IM, IM_STATUS_RESPONSE, &[0x15, 0x24, 0x00, 0x00, 0x18]).len()
+                + IPV4_UDP_HEADER_BYTES,
+            matter_ack: matter_message(PROTOCOL_SECURE_CHANNEL, MRP_STANDALONE_ACK, &[]).len() + IPV4_UDP_HEADER_BYTES,
+            mqtt_publish: mqtt_publish(&topic, Some(1), value).len() + IPV4_TCP_HEADER_BYTES,
+            mqtt_puback: mqtt_packet(MQTT_PUBACK, &1u16.to_be_bytes()).len() + IPV4_TCP_HEADER_BYTES,
+        })
+    }
+}
+
+/// A link with a rate and a one-way delay, counting what crosses it.
+struct Channel {
+    rate_kbit: f64,
+    delay_ms: f64,
+    busy_until: f64,
+    busy_ms: f64,
+    bytes: usize,
+    messages: usize,
+}
+
+impl Channel {
+    fn new(rate_kbit: u32, delay: Duration) -> Self {
+        Self {
+            rate_kbit: rate_kbit as f64,
+            delay_ms: delay.as_secs_f64() * 1000.0,
+            busy_until: 0.0,
+            busy_ms: 0.0,
+            bytes: 0,
+            messages: 0,
+        }
+    }
+
+    /// Queues `bytes` at `at` behind what was queued before and returns when
+    /// they arrive at the far end.
+    fn send(&mut self, at: f64, bytes: usize) -> f64 {
+        let serialization = bytes as f64 * 8.0 / self.rate_kbit;
+        self.busy_until = self.busy_until.max(at) + serialization;
+        self.busy_ms += serialization;
+        self.bytes += bytes;
+        self.messages += 1;
+        self.busy_until + self.delay_ms
+    }
+
+    /// Sends `bytes` at `at` without waiting for other traffic.
+    fn forward(&mut self, at: f64, bytes: usize) -> f64 {
+        self.bytes += bytes;
+        self.messages += 1;
+        at + bytes as f64 * 8.0 / self.rate_kbit + self.delay_ms
+    }
+}
+
+pub struct FanOutAnalyzer {
+    config: FanOutConfig,
+}
+
+impl FanOutAnalyzer {
+    pub fn new(config: FanOutConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_fan_out(&mut self) -> Result<FanOutMetrics> {
+        println!("\n📣 Analyzing Observer Fan-Out ({} values per sample, {} kbit/s sensor link)",
+                 self.config.values_per_sample, self.config.device_rate_kbit);
+        println!("------------------------------------------------");
+
+        if self.config.values_per_sample == 0 || self.config.samples == 0 {
+            return Err(anyhow!("fan-out needs at least one value and one sample"));
+        }
+        if self.config.device_rate_kbit == 0 || self.config.lan_rate_kbit == 0 {
+            return Err(anyhow!("link rates must be positive"));
+        }
+
+        let messages = Messages::new(self.config.values_per_sample)?;
+        let mut runs = Vec::new();
+        for &observers in &self.config.observer_counts {
+            for scheme in [Scheme::CoapObserve, Scheme::CoapGroup, Scheme::MatterSubscriptions, Scheme::MqttBroker] {
+                let run = self.simulate(scheme, observers, &messages);
+                println!("✅ {} × {}: {:.0} msgs and {:.0} B per sample on the sensor link ({:.0}% busy), {:.0} B on the wire, p95 {:.1}ms, spread {:.1}ms",
+                         run.protocol, run.observers, run.device_messages_per_sample, run.device_link_bytes_per_sample,
+                         run.device_link_utilization * 100.0, run.wire_bytes_per_sample, run.latency_p95_ms,
+                         run.spread_mean_ms);
+                runs.push(run);
+            }
+        }
+
+        Ok(FanOutMetrics {
+            values_per_sample: self.config.values_per_sample,
+            samples: self.config.samples,
+            sample_interval_ms: self.config.sample_interval.as_secs_f64() * 1000.0,
+            device_rate_kbit: self.config.device_rate_kbit,
+            lan_rate_kbit: self.config.lan_rate_kbit,
+            runs,
+        })
+    }
+
+    fn simulate(&self, scheme: Scheme, observers: usize, messages: &Messages) -> FanOutRun {
+        let mut device = Channel::new(self.config.device_rate_kbit, self.config.device_delay);
+        let mut lan = Channel::new(self.config.lan_rate_kbit, self.config.lan_delay);
+        let mut broker = Channel::new(self.config.lan_rate_kbit, self.config.lan_delay);
+        let values = self.config.values_per_sample;
+        let interval_ms = self.config.sample_interval.as_secs_f64() * 1000.0;
+        let mut latencies = Vec::with_capacity(self.config.samples * observers);
+        let mut spreads = Vec::with_capacity(self.config.samples);
+
+        for sample in 0..self.config.samples {
+            let at = sample as f64 * interval_ms;
+            let mut arrivals = Vec::with_capacity(observers);
+            match scheme {
+                Scheme::CoapObserve => {
+                    for _ in 0..observers {
+                        let last = (0..values)
+                            .map(|_| lan.forward(device.send(at, messages.coap_notification), messages.coap_notification))
+                            .fold(at, f64::max);
+                        arrivals.push(last);
+                    }
+                }
+                Scheme::CoapGroup => {
+                    // One multicast frame per value reaches every observer together
+                    let last = (0..values)
+                        .map(|_| lan.forward(device.send(at, messages.coap_notification), messages.coap_notification))
+                        .fold(at, f64::max);
+                    arrivals.resize(observers, last);
+                }
+                Scheme::MatterSubscriptions => {
+                    for _ in 0..observers {
+                        arrivals.push(lan.forward(device.send(at, messages.matter_report), messages.matter_report));
+                    }
+                    // Each subscriber's StatusResponse, then the sensor's standalone acks
+                    let statuses: Vec<f64> = arrivals
+                        .iter()
+                        .map(|&arrived| device.send(lan.forward(arrived, messages.matter_status), messages.matter_status))
+                        .collect();
+                    for status in statuses {
+                        lan.forward(device.send(status, messages.matter_ack), messages.matter_ack);
+                    }
+                }
+                Scheme::MqttBroker => {
+                    let at_broker: Vec<f64> = (0..values)
+                        .map(|_| lan.forward(device.send(at, messages.mqtt_publish), messages.mqtt_publish))
+                        .collect();
+                    for &arrived in &at_broker {
+                        device.send(lan.forward(arrived, messages.mqtt_puback), messages.mqtt_puback);
+                    }
+                    arrivals.resize(observers, at);
+                    for &arrived in &at_broker {
+                        for last in arrivals.iter_mut() {
+                            *last = broker.send(arrived, messages.mqtt_publish);
+                            lan.forward(*last, messages.mqtt_puback);
+                        }
+                    }
+                }
+            }
+            let first = arrivals.iter().copied().fold(f64::INFINITY, f64::min);
+            let last = arrivals.iter().copied().fold(at, f64::max);
+            spreads.push(if arrivals.is_empty() { 0.0 } else { last - first });
+            latencies.extend(arrivals.iter().map(|arrived| arrived - at));
+        }
+
+        latencies.sort_by(|a, b| a.total_cmp(b));
+        let samples = self.config.samples as f64;
+        let duration_ms = (samples * interval_ms).max(device.busy_until);
+        FanOutRun {
+            protocol: scheme.name().to_string(),
+            observers,
+            device_messages_per_sample: device.messages as f64 / samples,
+            device_link_bytes_per_sample: device.bytes as f64 / samples,
+            wire_bytes_per_sample: (device.bytes + lan.bytes + broker.bytes) as f64 / samples,
+            device_link_utilization: device.busy_ms / duration_ms.max(f64::EPSILON),
+            latency_mean_ms: latencies.iter().sum::<f64>() / latencies.len().max(1) as f64,
+            latency_p95_ms: percentile(&latencies, 95.0),
+            latency_max_ms: latencies.last().copied().unwrap_or(0.0),
+            spread_mean_ms: spreads.iter().sum::<f64>() / samples,
+        }
+    }
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((p / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_unicast_schemes_load_the_sensor_link_with_every_observer() {
+        let metrics = FanOutAnalyzer::builder()
+            .observer_counts(vec![1usize, 32])
+            .values_per_sample(4usize)
+            .samples(20usize)
+            .build()
+            .analyze_fan_out()
+            .unwrap();
+        let run = |protocol: &str, observers: usize| {
+            metrics.runs.iter().find(|r| r.protocol == protocol && r.observers == observers).unwrap()
+        };
+
+        for protocol in ["CoAP group notifications", "MQTT broker fan-out"] {
+            assert_eq!(run(protocol, 1).device_link_bytes_per_sample, run(protocol, 32).device_link_bytes_per_sample);
+        }
+        for protocol in ["CoAP observe (unicast)", "Matter subscriptions"] {
+            assert_eq!(run(protocol, 32).device_link_bytes_per_sample, 32.0 * run(protocol, 1).device_link_bytes_per_sample);
+            assert!(run(protocol, 32).spread_mean_ms > 0.0);
+        }
+        // A multicast frame reaches every observer at once
+        assert_eq!(run("CoAP group notifications", 32).spread_mean_ms, 0.0);
+        // One batched report, StatusResponse and ack per subscriber against one notification per value
+        assert_eq!(run("Matter subscriptions", 32).device_messages_per_sample, 32.0 * 3.0);
+        assert_eq!(run("CoAP observe (unicast)", 32).device_messages_per_sample, 32.0 * 4.0);
+    }
+}
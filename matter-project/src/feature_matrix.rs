@@ -0,0 +1,176 @@
+// matter-project/src/feature_matrix.rs
+/*!
+Protocol feature matrix - the qualitative comparison kept next to the measurements
+
+Latency and byte counts miss half of a protocol comparison: how a protocol
+is secured, whether it runs over IP, and whether multicast, mesh and
+firmware updates are part of it or left to something else. These facts live
+as data files in `capabilities/features/`, one per protocol, and are written
+into results next to the measured numbers so reports can show both.
+
+Multicast, mesh and OTA each declare a support level and the mechanism:
+
+- `native`: the protocol's own specification covers it.
+- `underlay`: the network below provides it, e.g. a Thread or RPL mesh.
+- `extension`: a companion specification or vendor convention covers it.
+- `none`: nothing standard does.
+
+The files are compiled in; `--capabilities-dir` reads `features/` under that
+directory instead. A mechanism must be given for every declared level, and a
+protocol may appear only once.
+*/
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::builder::analyzer_builder;
+
+// Compiled-in copies of capabilities/features/*.json, by file name
+const BUILTIN_FEATURES: &[(&str, &str)] = &[
+    ("matter.json", include_str!("../capabilities/features/matter.json")),
+    ("lwm2m.json", include_str!("../capabilities/features/lwm2m.json")),
+    ("mqtt.json", include_str!("../capabilities/features/mqtt.json")),
+    ("coap.json", include_str!("../capabilities/features/coap.json")),
+    ("mqtt_sn.json", include_str!("../capabilities/features/mqtt_sn.json")),
+    ("zwave.json", include_str!("../capabilities/features/zwave.json")),
+    ("bacnet.json", include_str!("../capabilities/features/bacnet.json")),
+    ("dds.json", include_str!("../capabilities/features/dds.json")),
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct FeatureMatrixConfig {
+    /// Directory holding one feature file per protocol; `None` uses the compiled-in files.
+    pub features_dir: Option<PathBuf>,
+}
+
+analyzer_builder!(FeatureMatrixAnalyzerBuilder => FeatureMatrixAnalyzer(FeatureMatrixConfig) {
+    features_dir: Option<PathBuf>,
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Support {
+    Native,
+    Underlay,
+    Extension,
+    None,
+}
+
+impl Support {
+    fn label(self) -> &'static str {
+        match self {
+            Support::Native => "native",
+            Support::Underlay => "underlay",
+            Support::Extension => "extension",
+            Support::None => "none",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Feature {
+    pub support: Support,
+    pub mechanism: String,
+}
+
+/// What one protocol offers beyond what can be measured.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolFeatures {
+    pub protocol: String,
+    pub security_model: String,
+    pub ip_based: bool,
+    pub multicast: Feature,
+    pub mesh: Feature,
+    pub ota: Feature,
+    pub governance: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FeatureMatrixMetrics {
+    /// `compiled-in` or the directory the feature files were read from.
+    pub source: String,
+    pub protocols: Vec<ProtocolFeatures>,
+}
+
+impl FeatureMatrixMetrics {
+    pub fn features_for(&self, protocol: &str) -> Option<&ProtocolFeatures> {
+        self.protocols.iter().find(|p| p.protocol == protocol)
+    }
+}
+
+pub struct FeatureMatrixAnalyzer {
+    config: FeatureMatrixConfig,
+}
+
+impl FeatureMatrixAnalyzer {
+    pub fn new(config: FeatureMatrixConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_feature_matrix(&mut self) -> Result<FeatureMatrixMetrics> {
+        let (source, protocols) = self.load()?;
+
+        println!("\n🧭 Analyzing Protocol Features (feature files: {})", source);
+        println!("--------------------------------------------------------");
+
+        for (i, features) in protocols.iter().enumerate() {
+            if protocols[..i].iter().any(|p| p.protocol == features.protocol) {
+                return Err(anyhow!("{} is declared more than once", features.protocol));
+            }
+            for (name, feature) in [("multicast", &features.multicast), ("mesh", &features.mesh), ("ota", &features.ota)] {
+                if feature.mechanism.trim().is_empty() {
+                    return Err(anyhow!("{}: {} declares no mechanism", features.protocol, name));
+                }
+            }
+            println!("✅ {}: {}, multicast {}, mesh {}, OTA {} ({})",
+                     features.protocol, if features.ip_based { "IP" } else { "non-IP" }, features.multicast.support.label(),
+                     features.mesh.support.label(), features.ota.support.label(), features.governance);
+        }
+
+        Ok(FeatureMatrixMetrics { source, protocols })
+    }
+
+    fn load(&self) -> Result<(String, Vec<ProtocolFeatures>)> {
+        let Some(dir) = &self.config.features_dir else {
+            let protocols = BUILTIN_FEATURES
+                .iter()
+                .map(|(name, json)| serde_json::from_str(json).context(*name))
+                .collect::<Result<_>>()?;
+            return Ok(("compiled-in".to_string(), protocols));
+        };
+
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+            .with_context(|| format!("reading {}", dir.display()))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<_, _>>()?;
+        paths.retain(|p| p.extension().is_some_and(|e| e == "json"));
+        paths.sort();
+        let protocols = paths
+            .into_iter()
+            .map(|path| {
+                let json = std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+                serde_json::from_str(&json).with_context(|| path.display().to_string())
+            })
+            .collect::<Result<_>>()?;
+
+        Ok((dir.display().to_string(), protocols))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_features_load() {
+        let metrics = FeatureMatrixAnalyzer::new(FeatureMatrixConfig::default())
+            .analyze_feature_matrix()
+            .unwrap();
+        assert_eq!(metrics.protocols.len(), BUILTIN_FEATURES.len());
+        let matter = metrics.features_for("Matter").unwrap();
+        assert!(matter.ip_based);
+        assert_eq!(matter.mesh.support, Support::Underlay);
+        assert!(!metrics.features_for("Z-Wave").unwrap().ip_based);
+    }
+}
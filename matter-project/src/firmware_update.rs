@@ -0,0 +1,1181 @@
+// matter-project/src/firmware_update.rs
+/*!
+Firmware update transfer - Matter OTA (BDX), LwM2M object 5 (block-wise CoAP), MQTT chunks and HTTP ranges
+*/
+
+use anyhow::{anyhow, Result};
+use bytes::BufMut;
+use metrics_core::tlv::{Tag, TlvError, TlvWriter};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+use crate::bdx::{self, matter_message, BDX_BLOCK_QUERY};
+use crate::builder::analyzer_builder;
+
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone)]
+pub struct FirmwareUpdateConfig {
+    pub image_bytes: usize,
+    /// Fraction of the image after which the resume run loses its connection.
+    pub interrupt_at: f64,
+}
+
+impl Default for FirmwareUpdateConfig {
+    fn default() -> Self {
+        Self {
+            image_bytes: 512 * 1024,
+            interrupt_at: 0.5,
+        }
+    }
+}
+
+analyzer_builder!(FirmwareUpdateBenchmarkBuilder => FirmwareUpdateBenchmark(FirmwareUpdateConfig) {
+    image_bytes: usize,
+    interrupt_at: f64,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FirmwareUpdateMetrics {
+    pub image_bytes: usize,
+    pub interrupt_at: f64,
+    pub mechanisms: Vec<UpdateMechanismResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateMechanismResult {
+    pub mechanism: String,
+    pub transport: String,
+    pub block_size: usize,
+    pub blocks: usize,
+    pub total_time_ms: f64,
+    pub messages: usize,
+    /// Application-layer bytes in both directions (UDP/TCP/IP headers excluded).
+    pub wire_bytes: usize,
+    /// Non-image bytes per image byte.
+    pub overhead_ratio: f64,
+    pub goodput_kbps: f64,
+    pub resume: ResumeResult,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResumeResult {
+    pub resumed_from_offset: usize,
+    /// Image bytes that arrived with the interrupted block and were fetched again.
+    pub wasted_image_bytes: usize,
+    /// Reconnect plus resume handshake after the interruption.
+    pub resume_setup_ms: f64,
+    pub total_time_ms: f64,
+    /// Extra wire bytes and messages compared with the uninterrupted transfer.
+    pub extra_wire_bytes: usize,
+    pub extra_messages: usize,
+}
+
+pub struct FirmwareUpdateBenchmark {
+    config: FirmwareUpdateConfig,
+}
+
+impl FirmwareUpdateBenchmark {
+    pub fn new(config: FirmwareUpdateConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_firmware_update(&mut self) -> Result<FirmwareUpdateMetrics> {
+        println!("\n📦 Analyzing Firmware Update Transfer ({} KB image)", self.config.image_bytes / 1024);
+        println!("------------------------------------------------------");
+
+        let image = image(self.config.image_bytes.max(1));
+
+        let mechanisms: Vec<Arc<dyn UpdateMechanism>> = vec![
+            Arc::new(MatterBdx),
+            Arc::new(Lwm2mBlockwise),
+            Arc::new(MqttChunks),
+            Arc::new(HttpRanges),
+        ];
+
+        let mut results = Vec::new();
+        for mechanism in mechanisms {
+            let blocks = image.len().div_ceil(mechanism.block_size());
+            let interrupt_block = ((blocks as f64 * self.config.interrupt_at) as usize).min(blocks - 1);
+
+            let clean = run_transfer(&mechanism, &image, None).await?;
+            let resumed = run_transfer(&mechanism, &image, Some(interrupt_block)).await?;
+            let point = resumed
+                .resume
+                .ok_or_else(|| anyhow!("{}: transfer was not interrupted", mechanism.name()))?;
+
+            let total_time_ms = clean.elapsed.as_secs_f64() * 1000.0;
+            let result = UpdateMechanismResult {
+                mechanism: mechanism.name().to_string(),
+                transport: match mechanism.carrier() {
+                    Carrier::Udp => "UDP",
+                    Carrier::Tcp => "TCP",
+                }
+                .to_string(),
+                block_size: mechanism.block_size(),
+                blocks,
+                total_time_ms,
+                messages: clean.messages,
+                wire_bytes: clean.wire_bytes,
+                overhead_ratio: clean.wire_bytes.saturating_sub(image.len()) as f64 / image.len() as f64,
+                goodput_kbps: image.len() as f64 * 8.0 / total_time_ms,
+                resume: ResumeResult {
+                    resumed_from_offset: point.offset,
+                    wasted_image_bytes: point.wasted_bytes,
+                    resume_setup_ms: point.setup_ms,
+                    total_time_ms: resumed.elapsed.as_secs_f64() * 1000.0,
+                    extra_wire_bytes: resumed.wire_bytes.saturating_sub(clean.wire_bytes),
+                    extra_messages: resumed.messages.saturating_sub(clean.messages),
+                },
+            };
+
+            println!("✅ {} ({}): {:.1}ms, {} msgs, {:.1}% overhead, resume +{}B in {:.2}ms",
+                     result.mechanism, result.transport, result.total_time_ms, result.messages,
+                     result.overhead_ratio * 100.0, result.resume.extra_wire_bytes, result.resume.resume_setup_ms);
+            results.push(result);
+        }
+
+        Ok(FirmwareUpdateMetrics {
+            image_bytes: image.len(),
+            interrupt_at: self.config.interrupt_at,
+            mechanisms: results,
+        })
+    }
+}
+
+/// Deterministic, incompressible-looking image.
+pub(crate) fn image(bytes: usize) -> Arc<Vec<u8>> {
+    let mut state = 0x2545_F491_4F6C_DD1Du64;
+    Arc::new(
+        (0..bytes)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect(),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Carrier {
+    Udp,
+    Tcp,
+}
+
+/// One control round trip; `response` is `None` for messages nobody answers.
+/// Exchanges are replayed from the device's side whichever end initiates them
+/// in the real protocol, which keeps their size and round trip.
+pub(crate) struct Exchange {
+    pub(crate) request: Vec<u8>,
+    pub(crate) response: Option<Vec<u8>>,
+}
+
+impl Exchange {
+    fn round_trip(request: Vec<u8>, response: Vec<u8>) -> Self {
+        Self { request, response: Some(response) }
+    }
+
+    fn one_way(request: Vec<u8>) -> Self {
+        Self { request, response: None }
+    }
+}
+
+/// Message formats of one update mechanism. Transfers are device-driven and
+/// stop-and-wait: request block n, receive it, request block n + 1.
+pub(crate) trait UpdateMechanism: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn carrier(&self) -> Carrier;
+    fn block_size(&self) -> usize;
+    /// Exchanges before the first block; `resume_offset` is set when picking up an interrupted transfer.
+    fn session_setup(&self, image: &[u8], resume_offset: Option<usize>) -> Result<Vec<Exchange>>;
+    fn session_teardown(&self, image: &[u8]) -> Result<Vec<Exchange>>;
+    fn block_request(&self, block: usize) -> Vec<u8>;
+    fn parse_block_request(&self, request: &[u8]) -> Option<usize>;
+    fn block_response(&self, block: usize, data: &[u8], image_bytes: usize) -> Vec<u8>;
+    fn block_payload<'a>(&self, response: &'a [u8]) -> Option<&'a [u8]>;
+    /// Sent after each block without waiting for a reply (e.g. MQTT PUBACK).
+    fn block_ack(&self, _block: usize) -> Option<Vec<u8>> {
+        None
+    }
+    /// Length of the first complete message in a TCP stream buffer.
+    fn frame_len(&self, _buffer: &[u8]) -> Option<usize> {
+        None
+    }
+}
+
+pub(crate) struct TransferOutcome {
+    pub(crate) elapsed: Duration,
+    pub(crate) messages: usize,
+    pub(crate) wire_bytes: usize,
+    resume: Option<ResumePoint>,
+}
+
+struct ResumePoint {
+    offset: usize,
+    wasted_bytes: usize,
+    setup_ms: f64,
+}
+
+/// Serves the image for one transfer; control requests are answered from a
+/// table of the exchanges the client is going to replay.
+struct UpdateServer {
+    mechanism: Arc<dyn UpdateMechanism>,
+    image: Arc<Vec<u8>>,
+    controls: HashMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+impl UpdateServer {
+    fn respond(&self, request: &[u8]) -> Option<Vec<u8>> {
+        if let Some(block) = self.mechanism.parse_block_request(request) {
+            let block_size = self.mechanism.block_size();
+            let start = (block * block_size).min(self.image.len());
+            let end = (start + block_size).min(self.image.len());
+            return Some(self.mechanism.block_response(block, &self.image[start..end], self.image.len()));
+        }
+        self.controls.get(request).cloned().flatten()
+    }
+
+    async fn spawn(self: Arc<Self>) -> Result<(SocketAddr, JoinHandle<()>)> {
+        match self.mechanism.carrier() {
+            Carrier::Udp => {
+                let socket = UdpSocket::bind("127.0.0.1:0").await?;
+                let addr = socket.local_addr()?;
+                let handle = tokio::spawn(async move {
+                    let mut buffer = vec![0u8; 65536];
+                    while let Ok((len, peer)) = socket.recv_from(&mut buffer).await {
+                        if let Some(response) = self.respond(&buffer[..len]) {
+                            let _ = socket.send_to(&response, peer).await;
+                        }
+                    }
+                });
+                Ok((addr, handle))
+            }
+            Carrier::Tcp => {
+                let listener = TcpListener::bind("127.0.0.1:0").await?;
+                let addr = listener.local_addr()?;
+                let handle = tokio::spawn(async move {
+                    while let Ok((mut stream, _)) = listener.accept().await {
+                        let _ = stream.set_nodelay(true);
+                        let server = self.clone();
+                        tokio::spawn(async move {
+                            let mut pending = Vec::new();
+                            let mut chunk = vec![0u8; 65536];
+                            while let Ok(read) = stream.read(&mut chunk).await {
+                                if read == 0 {
+                                    break;
+                                }
+                                pending.extend_from_slice(&chunk[..read]);
+                                while let Some(len) = server.mechanism.frame_len(&pending) {
+                                    let request: Vec<u8> = pending.drain(..len).collect();
+                                    if let Some(response) = server.respond(&request) {
+                                        if stream.write_all(&response).await.is_err() {
+                                            return;
+                                        }
+                                    }
+                                }
+                            }
+                        });
+                    }
+                });
+                Ok((addr, handle))
+            }
+        }
+    }
+}
+
+/// Device side of the connection, counting what crosses it.
+enum Link {
+    Udp(UdpSocket),
+    Tcp(TcpStream, Vec<u8>),
+}
+
+impl Link {
+    async fn connect(carrier: Carrier, server: SocketAddr) -> Result<Self> {
+        match carrier {
+            Carrier::Udp => {
+                let socket = UdpSocket::bind("127.0.0.1:0").await?;
+                socket.connect(server).await?;
+                Ok(Link::Udp(socket))
+            }
+            Carrier::Tcp => {
+                let stream = TcpStream::connect(server).await?;
+                stream.set_nodelay(true)?;
+                Ok(Link::Tcp(stream, Vec::new()))
+            }
+        }
+    }
+
+    async fn send(&mut self, message: &[u8], outcome: &mut TransferOutcome) -> Result<()> {
+        match self {
+            Link::Udp(socket) => {
+                socket.send(message).await?;
+            }
+            Link::Tcp(stream, _) => stream.write_all(message).await?,
+        }
+        outcome.messages += 1;
+        outcome.wire_bytes += message.len();
+        Ok(())
+    }
+
+    async fn receive(&mut self, mechanism: &dyn UpdateMechanism, outcome: &mut TransferOutcome) -> Result<Vec<u8>> {
+        let message = match self {
+            Link::Udp(socket) => {
+                let mut buffer = vec![0u8; 65536];
+                let len = timeout(RECEIVE_TIMEOUT, socket.recv(&mut buffer)).await??;
+                buffer.truncate(len);
+                buffer
+            }
+            Link::Tcp(stream, pending) => loop {
+                if let Some(len) = mechanism.frame_len(pending) {
+                    break pending.drain(..len).collect();
+                }
+                let mut chunk = vec![0u8; 65536];
+                let read = timeout(RECEIVE_TIMEOUT, stream.read(&mut chunk)).await??;
+                if read == 0 {
+                    return Err(anyhow!("{}: server closed the connection", mechanism.name()));
+                }
+                pending.extend_from_slice(&chunk[..read]);
+            },
+        };
+        outcome.messages += 1;
+        outcome.wire_bytes += message.len();
+        Ok(message)
+    }
+
+    async fn replay(&mut self, exchanges: &[Exchange], mechanism: &dyn UpdateMechanism, outcome: &mut TransferOutcome) -> Result<()> {
+        for exchange in exchanges {
+            self.send(&exchange.request, outcome).await?;
+            if exchange.response.is_some() {
+                self.receive(mechanism, outcome).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) async fn run_transfer(mechanism: &Arc<dyn UpdateMechanism>, image: &Arc<Vec<u8>>, interrupt_block: Option<usize>) -> Result<TransferOutcome> {
+    let setup = mechanism.session_setup(image, None)?;
+    let teardown = mechanism.session_teardown(image)?;
+    let resume_setup = match interrupt_block {
+        Some(block) => mechanism.session_setup(image, Some(block * mechanism.block_size()))?,
+        None => Vec::new(),
+    };
+
+    let controls = setup
+        .iter()
+        .chain(&teardown)
+        .chain(&resume_setup)
+        .map(|exchange| (exchange.request.clone(), exchange.response.clone()))
+        .collect();
+    let server = Arc::new(UpdateServer {
+        mechanism: mechanism.clone(),
+        image: image.clone(),
+        controls,
+    });
+    let (addr, handle) = server.spawn().await?;
+
+    let result = drive_transfer(mechanism.as_ref(), image, addr, &setup, &teardown, &resume_setup, interrupt_block).await;
+    handle.abort();
+    result
+}
+
+async fn drive_transfer(
+    mechanism: &dyn UpdateMechanism,
+    image: &[u8],
+    server: SocketAddr,
+    setup: &[Exchange],
+    teardown: &[Exchange],
+    resume_setup: &[Exchange],
+    interrupt_block: Option<usize>,
+) -> Result<TransferOutcome> {
+    let mut outcome = TransferOutcome {
+        elapsed: Duration::ZERO,
+        messages: 0,
+        wire_bytes: 0,
+        resume: None,
+    };
+    let blocks = image.len().div_ceil(mechanism.block_size());
+    let start = Instant::now();
+
+    let mut link = Link::connect(mechanism.carrier(), server).await?;
+    link.replay(setup, mechanism, &mut outcome).await?;
+
+    let mut received = Vec::with_capacity(image.len());
+    let mut block = 0;
+    while block < blocks {
+        link.send(&mechanism.block_request(block), &mut outcome).await?;
+        let response = link.receive(mechanism, &mut outcome).await?;
+        let payload = mechanism
+            .block_payload(&response)
+            .ok_or_else(|| anyhow!("{}: malformed block {}", mechanism.name(), block))?;
+
+        if interrupt_block == Some(block) && outcome.resume.is_none() {
+            // The connection drops with this block in flight; reconnect and pick up where the device can
+            let wasted_bytes = payload.len();
+            let resume_start = Instant::now();
+            link = Link::connect(mechanism.carrier(), server).await?;
+            link.replay(resume_setup, mechanism, &mut outcome).await?;
+            outcome.resume = Some(ResumePoint {
+                offset: block * mechanism.block_size(),
+                wasted_bytes,
+                setup_ms: resume_start.elapsed().as_secs_f64() * 1000.0,
+            });
+            continue;
+        }
+
+        received.extend_from_slice(payload);
+        if let Some(ack) = mechanism.block_ack(block) {
+            link.send(&ack, &mut outcome).await?;
+        }
+        block += 1;
+    }
+
+    link.replay(teardown, mechanism, &mut outcome).await?;
+    outcome.elapsed = start.elapsed();
+
+    if received != image {
+        return Err(anyhow!("{}: received image does not match", mechanism.name()));
+    }
+    Ok(outcome)
+}
+
+pub(crate) fn tlv(build: impl FnOnce(&mut TlvWriter) -> Result<(), TlvError>) -> Result<Vec<u8>> {
+    let mut encoded = Vec::new();
+    tlv_into(&mut encoded, build)?;
+    Ok(encoded)
+}
+
+/// [`tlv`], appended to `out`.
+pub(crate) fn tlv_into(out: &mut impl BufMut, build: impl FnOnce(&mut TlvWriter) -> Result<(), TlvError>) -> Result<()> {
+    let mut buffer = [0u8; 512];
+    let mut writer = TlvWriter::new(&mut buffer);
+    build(&mut writer).map_err(|e| anyhow!("TLV encoding failed: {:?}", e))?;
+    out.put_slice(writer.as_bytes());
+    Ok(())
+}
+
+const IMAGE_NAME: &str = "fw-2.ota";
+const NEW_VERSION: u32 = 2;
+const UPDATE_TOKEN: [u8; 8] = [0x5A; 8];
+
+// Interaction Model
+pub(crate) const PROTOCOL_IM: u16 = 0x0001;
+pub(crate) const IM_STATUS_RESPONSE: u8 = 0x01;
+pub(crate) const IM_READ_REQUEST: u8 = 0x02;
+pub(crate) const IM_REPORT_DATA: u8 = 0x05;
+pub(crate) const IM_INVOKE_REQUEST: u8 = 0x08;
+pub(crate) const IM_INVOKE_RESPONSE: u8 = 0x09;
+
+// OTA Software Update Provider cluster
+const OTA_PROVIDER_CLUSTER: u32 = 0x0029;
+const QUERY_IMAGE: u32 = 0x00;
+const QUERY_IMAGE_RESPONSE: u32 = 0x01;
+const APPLY_UPDATE_REQUEST: u32 = 0x02;
+const APPLY_UPDATE_RESPONSE: u32 = 0x03;
+const NOTIFY_UPDATE_APPLIED: u32 = 0x04;
+
+fn command_path(writer: &mut TlvWriter, command: u32) -> Result<(), TlvError> {
+    writer.start_list(Tag::Context(0))?;
+    writer.u16(Tag::Context(0), 0)?;
+    writer.u32(Tag::Context(1), OTA_PROVIDER_CLUSTER)?;
+    writer.u32(Tag::Context(2), command)?;
+    writer.end_container()
+}
+
+fn invoke_request(command: u32, fields: impl FnOnce(&mut TlvWriter) -> Result<(), TlvError>) -> Result<Vec<u8>> {
+    let payload = tlv(|writer| {
+        writer.start_struct(Tag::Anonymous)?;
+        writer.bool(Tag::Context(0), false)?; // SuppressResponse
+        writer.bool(Tag::Context(1), false)?; // TimedRequest
+        writer.start_array(Tag::Context(2))?;
+        writer.start_struct(Tag::Anonymous)?;
+        command_path(writer, command)?;
+        writer.start_struct(Tag::Context(1))?;
+        fields(writer)?;
+        writer.end_container()?;
+        writer.end_container()?;
+        writer.end_container()?;
+        writer.u8(Tag::Context(255), 11)?;
+        writer.end_container()
+    })?;
+    Ok(matter_message(PROTOCOL_IM, IM_INVOKE_REQUEST, &payload))
+}
+
+type CommandFields<'a> = &'a dyn Fn(&mut TlvWriter) -> Result<(), TlvError>;
+
+/// InvokeResponse carrying either response command fields or, with `None`, a success status.
+fn invoke_response(command: u32, fields: Option<CommandFields>) -> Result<Vec<u8>> {
+    let payload = tlv(|writer| {
+        writer.start_struct(Tag::Anonymous)?;
+        writer.bool(Tag::Context(0), false)?;
+        writer.start_array(Tag::Context(1))?;
+        writer.start_struct(Tag::Anonymous)?;
+        match fields {
+            Some(fields) => {
+                writer.start_struct(Tag::Context(0))?;
+                command_path(writer, command)?;
+                writer.start_struct(Tag::Context(1))?;
+                fields(writer)?;
+                writer.end_container()?;
+            }
+            None => {
+                writer.start_struct(Tag::Context(1))?;
+                command_path(writer, command)?;
+                writer.start_struct(Tag::Context(1))?;
+                writer.u8(Tag::Context(0), 0)?;
+                writer.end_container()?;
+            }
+        }
+        writer.end_container()?;
+        writer.end_container()?;
+        writer.end_container()?;
+        writer.u8(Tag::Context(255), 11)?;
+        writer.end_container()
+    })?;
+    Ok(matter_message(PROTOCOL_IM, IM_INVOKE_RESPONSE, &payload))
+}
+
+/// OTA Requestor flow: QueryImage, BDX receiver-driven download, ApplyUpdate, NotifyUpdateApplied.
+/// BDX block counters are kept absolute so a resumed transfer addresses the same blocks.
+pub(crate) struct MatterBdx;
+
+impl UpdateMechanism for MatterBdx {
+    fn name(&self) -> &'static str {
+        "Matter OTA (BDX)"
+    }
+
+    fn carrier(&self) -> Carrier {
+        Carrier::Udp
+    }
+
+    fn block_size(&self) -> usize {
+        1024
+    }
+
+    fn session_setup(&self, image: &[u8], resume_offset: Option<usize>) -> Result<Vec<Exchange>> {
+        let mut exchanges = Vec::new();
+        if resume_offset.is_none() {
+            let query = invoke_request(QUERY_IMAGE, |writer| {
+                writer.u16(Tag::Context(0), 0xFFF1)?; // VendorID
+                writer.u16(Tag::Context(1), 0x8000)?; // ProductID
+                writer.u32(Tag::Context(2), NEW_VERSION - 1)?; // SoftwareVersion
+                writer.start_array(Tag::Context(3))?; // ProtocolsSupported
+                writer.u8(Tag::Anonymous, 0)?; // BDXSynchronous
+                writer.end_container()?;
+                writer.u16(Tag::Context(4), 1) // HardwareVersion
+            })?;
+            let response = invoke_response(QUERY_IMAGE_RESPONSE, Some(&|writer: &mut TlvWriter| {
+                writer.u8(Tag::Context(0), 0)?; // UpdateAvailable
+                writer.u32(Tag::Context(1), 0)?; // DelayedActionTime
+                writer.utf8(Tag::Context(2), &format!("bdx://FFF1000000000001/{}", IMAGE_NAME))?;
+                writer.u32(Tag::Context(3), NEW_VERSION)?;
+                writer.utf8(Tag::Context(4), "2.0.0")?;
+                writer.octets(Tag::Context(5), &UPDATE_TOKEN)
+            }))?;
+            exchanges.push(Exchange::round_trip(query, response));
+        }
+
+        let start_offset = resume_offset.unwrap_or(0);
+        exchanges.push(Exchange::round_trip(
+            bdx::receive_init(self.block_size() as u16, resume_offset.map(|offset| offset as u32), IMAGE_NAME),
+            bdx::receive_accept(self.block_size() as u16, (image.len() - start_offset) as u32),
+        ));
+        Ok(exchanges)
+    }
+
+    fn session_teardown(&self, image: &[u8]) -> Result<Vec<Exchange>> {
+        let last_block = (image.len().div_ceil(self.block_size()) - 1) as u32;
+        let apply = invoke_request(APPLY_UPDATE_REQUEST, |writer| {
+            writer.octets(Tag::Context(0), &UPDATE_TOKEN)?;
+            writer.u32(Tag::Context(1), NEW_VERSION)
+        })?;
+        let apply_response = invoke_response(APPLY_UPDATE_RESPONSE, Some(&|writer: &mut TlvWriter| {
+            writer.u8(Tag::Context(0), 0)?; // Proceed
+            writer.u32(Tag::Context(1), 0)
+        }))?;
+        let notify = invoke_request(NOTIFY_UPDATE_APPLIED, |writer| {
+            writer.octets(Tag::Context(0), &UPDATE_TOKEN)?;
+            writer.u32(Tag::Context(1), NEW_VERSION)
+        })?;
+
+        Ok(vec![
+            Exchange::one_way(bdx::block_ack_eof(last_block)),
+            Exchange::round_trip(apply, apply_response),
+            Exchange::round_trip(notify, invoke_response(NOTIFY_UPDATE_APPLIED, None)?),
+            Exchange::one_way(matter_message(PROTOCOL_IM, IM_STATUS_RESPONSE, &[0x15, 0x24, 0x00, 0x00, 0x18])),
+        ])
+    }
+
+    fn block_request(&self, block: usize) -> Vec<u8> {
+        bdx::block_query(block as u32)
+    }
+
+    fn parse_block_request(&self, request: &[u8]) -> Option<usize> {
+        if bdx::bdx_opcode(request)? != BDX_BLOCK_QUERY {
+            return None;
+        }
+        bdx::block_counter(request).map(|counter| counter as usize)
+    }
+
+    fn block_response(&self, block: usize, data: &[u8], image_bytes: usize) -> Vec<u8> {
+        bdx::block(block as u32, data, (block + 1) * self.block_size() >= image_bytes)
+    }
+
+    fn block_payload<'a>(&self, response: &'a [u8]) -> Option<&'a [u8]> {
+        bdx::block_data(response)
+    }
+}
+
+// CoAP
+pub(crate) const COAP_CON: u8 = 0;
+pub(crate) const COAP_NON: u8 = 1;
+pub(crate) const COAP_ACK: u8 = 2;
+pub(crate) const COAP_GET: u8 = 0x01;
+pub(crate) const COAP_POST: u8 = 0x02;
+pub(crate) const COAP_PUT: u8 = 0x03;
+pub(crate) const COAP_CHANGED: u8 = 0x44;
+pub(crate) const COAP_CONTENT: u8 = 0x45;
+pub(crate) const COAP_OPTION_OBSERVE: u16 = 6;
+pub(crate) const COAP_OPTION_URI_PATH: u16 = 11;
+pub(crate) const COAP_OPTION_CONTENT_FORMAT: u16 = 12;
+const COAP_OPTION_BLOCK2: u16 = 23;
+const COAP_TOKEN: [u8; 2] = [0xB7, 0x01];
+const COAP_BLOCK_SZX: usize = 6; // 1024-byte blocks
+
+pub(crate) fn coap_uint(value: usize) -> Vec<u8> {
+    value.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect()
+}
+
+pub(crate) fn coap_message(kind: u8, code: u8, message_id: u16, options: &[(u16, Vec<u8>)], payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::new();
+    coap_message_into(&mut message, kind, code, message_id, options, payload);
+    message
+}
+
+/// [`coap_message`], appended to `out`; option values can be borrowed.
+pub(crate) fn coap_message_into(
+    out: &mut impl BufMut,
+    kind: u8,
+    code: u8,
+    message_id: u16,
+    options: &[(u16, impl AsRef<[u8]>)],
+    payload: &[u8],
+) {
+    out.put_slice(&[0x40 | (kind << 4) | COAP_TOKEN.len() as u8, code]);
+    out.put_u16(message_id);
+    out.put_slice(&COAP_TOKEN);
+
+    // Options in ascending order; short deltas/lengths only (all values here stay below 269)
+    let mut previous = 0;
+    for (number, value) in options {
+        let value = value.as_ref();
+        let delta = (number - previous) as usize;
+        let nibble = |n: usize| if n < 13 { (n as u8, None) } else { (13, Some((n - 13) as u8)) };
+        let (delta_nibble, delta_ext) = nibble(delta);
+        let (len_nibble, len_ext) = nibble(value.len());
+        out.put_u8(delta_nibble << 4 | len_nibble);
+        delta_ext.into_iter().chain(len_ext).for_each(|ext| out.put_u8(ext));
+        out.put_slice(value);
+        previous = *number;
+    }
+
+    if !payload.is_empty() {
+        out.put_u8(0xFF);
+        out.put_slice(payload);
+    }
+}
+
+pub(crate) struct CoapView<'a> {
+    pub code: u8,
+    pub options: Vec<(u16, &'a [u8])>,
+    pub payload: &'a [u8],
+}
+
+pub(crate) fn coap_parse(message: &[u8]) -> Option<CoapView<'_>> {
+    let token_len = (*message.first()? & 0x0F) as usize;
+    let code = *message.get(1)?;
+    let mut pos = 4 + token_len;
+    let mut number = 0u16;
+    let mut options = Vec::new();
+
+    while pos < message.len() && message[pos] != 0xFF {
+        let header = message[pos];
+        pos += 1;
+        let mut extended = |nibble: u8| -> Option<usize> {
+            match nibble {
+                13 => {
+                    pos += 1;
+                    Some(*message.get(pos - 1)? as usize + 13)
+                }
+                14 => {
+                    pos += 2;
+                    Some(u16::from_be_bytes(message.get(pos - 2..pos)?.try_into().ok()?) as usize + 269)
+                }
+                15 => None,
+                n => Some(n as usize),
+            }
+        };
+        let delta = extended(header >> 4)?;
+        let len = extended(header & 0x0F)?;
+        number = number.checked_add(u16::try_from(delta).ok()?)?;
+        options.push((number, message.get(pos..pos + len)?));
+        pos += len;
+    }
+
+    let payload = message.get(pos + 1..).unwrap_or(&[]);
+    Some(CoapView { code, options, payload })
+}
+
+pub(crate) fn uri_path(segments: &[&str]) -> Vec<(u16, Vec<u8>)> {
+    segments.iter().map(|s| (COAP_OPTION_URI_PATH, s.as_bytes().to_vec())).collect()
+}
+
+/// LwM2M Firmware Update object (5) in pull mode: the server writes the Package URI,
+/// the device fetches it with CoAP Block2 and reports State / Update Result.
+/// Block2 is stateless, so resuming only needs the next block number.
+pub(crate) struct Lwm2mBlockwise;
+
+impl Lwm2mBlockwise {
+    fn notify(&self, message_id: u16, resource: &str, value: &str) -> Exchange {
+        let mut options = vec![(COAP_OPTION_OBSERVE, coap_uint(message_id as usize))];
+        options.extend(uri_path(&["5", "0", resource]));
+        Exchange::round_trip(
+            coap_message(COAP_CON, COAP_CONTENT, message_id, &options, value.as_bytes()),
+            coap_message(COAP_ACK, 0x00, message_id, &[], &[]),
+        )
+    }
+}
+
+impl UpdateMechanism for Lwm2mBlockwise {
+    fn name(&self) -> &'static str {
+        "LwM2M FOTA (CoAP Block2)"
+    }
+
+    fn carrier(&self) -> Carrier {
+        Carrier::Udp
+    }
+
+    fn block_size(&self) -> usize {
+        1 << (COAP_BLOCK_SZX + 4)
+    }
+
+    fn session_setup(&self, _image: &[u8], resume_offset: Option<usize>) -> Result<Vec<Exchange>> {
+        if resume_offset.is_some() {
+            return Ok(Vec::new());
+        }
+
+        let mut observe = vec![(COAP_OPTION_OBSERVE, Vec::new())];
+        observe.extend(uri_path(&["5", "0", "3"]));
+        let mut write = uri_path(&["5", "0", "1"]);
+        write.push((COAP_OPTION_CONTENT_FORMAT, Vec::new()));
+        let package_uri = format!("coap://[fd00::1]:5683/{}", IMAGE_NAME);
+
+        Ok(vec![
+            Exchange::round_trip(
+                coap_message(COAP_CON, COAP_GET, 0x1001, &observe, &[]),
+                coap_message(COAP_ACK, COAP_CONTENT, 0x1001, &[(COAP_OPTION_OBSERVE, vec![1])], b"0"),
+            ),
+            Exchange::round_trip(
+                coap_message(COAP_CON, COAP_PUT, 0x1002, &write, package_uri.as_bytes()),
+                coap_message(COAP_ACK, COAP_CHANGED, 0x1002, &[], &[]),
+            ),
+            // State: Downloading
+            Exchange::one_way(coap_message(COAP_NON, COAP_CONTENT, 0x1003, &[(COAP_OPTION_OBSERVE, vec![2])], b"1")),
+        ])
+    }
+
+    fn session_teardown(&self, _image: &[u8]) -> Result<Vec<Exchange>> {
+        let execute = uri_path(&["5", "0", "2"]);
+        Ok(vec![
+            self.notify(0x1004, "3", "2"), // State: Downloaded
+            Exchange::round_trip(
+                coap_message(COAP_CON, COAP_POST, 0x1005, &execute, &[]),
+                coap_message(COAP_ACK, COAP_CHANGED, 0x1005, &[], &[]),
+            ),
+            self.notify(0x1006, "5", "1"), // Update Result: success
+        ])
+    }
+
+    fn block_request(&self, block: usize) -> Vec<u8> {
+        let mut options = uri_path(&[IMAGE_NAME]);
+        options.push((COAP_OPTION_BLOCK2, coap_uint(block << 4 | COAP_BLOCK_SZX)));
+        coap_message(COAP_CON, COAP_GET, block as u16, &options, &[])
+    }
+
+    fn parse_block_request(&self, request: &[u8]) -> Option<usize> {
+        let request = coap_parse(request)?;
+        if request.code != COAP_GET {
+            return None;
+        }
+        let (_, value) = request.options.iter().find(|(number, _)| *number == COAP_OPTION_BLOCK2)?;
+        let value = value.iter().fold(0usize, |acc, b| acc << 8 | *b as usize);
+        Some(value >> 4)
+    }
+
+    fn block_response(&self, block: usize, data: &[u8], image_bytes: usize) -> Vec<u8> {
+        let more = ((block + 1) * self.block_size() < image_bytes) as usize;
+        let options = vec![
+            (COAP_OPTION_CONTENT_FORMAT, coap_uint(42)), // application/octet-stream
+            (COAP_OPTION_BLOCK2, coap_uint(block << 4 | more << 3 | COAP_BLOCK_SZX)),
+        ];
+        coap_message(COAP_ACK, COAP_CONTENT, block as u16, &options, data)
+    }
+
+    fn block_payload<'a>(&self, response: &'a [u8]) -> Option<&'a [u8]> {
+        let response = coap_parse(response)?;
+        (response.code == COAP_CONTENT).then_some(response.payload)
+    }
+}
+
+// MQTT 3.1.1
+pub(crate) const MQTT_CONNECT: u8 = 0x10;
+pub(crate) const MQTT_CONNACK: u8 = 0x20;
+pub(crate) const MQTT_PUBLISH: u8 = 0x30;
+pub(crate) const MQTT_PUBLISH_QOS1: u8 = 0x32;
+pub(crate) const MQTT_PUBACK: u8 = 0x40;
+pub(crate) const MQTT_SUBSCRIBE: u8 = 0x82;
+pub(crate) const MQTT_SUBACK: u8 = 0x90;
+pub(crate) const MQTT_DISCONNECT: u8 = 0xE0;
+const MQTT_REQUEST_TOPIC: &str = "fw/dev1/get";
+const MQTT_DATA_TOPIC: &str = "fw/dev1/data";
+
+pub(crate) fn mqtt_packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(5 + body.len());
+    mqtt_fixed_header(&mut packet, header, body.len());
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// Packet type byte and remaining length.
+pub(crate) fn mqtt_fixed_header(out: &mut impl BufMut, header: u8, mut remaining: usize) {
+    out.put_u8(header);
+    loop {
+        let mut byte = (remaining % 128) as u8;
+        remaining /= 128;
+        if remaining > 0 {
+            byte |= 0x80;
+        }
+        out.put_u8(byte);
+        if remaining == 0 {
+            break;
+        }
+    }
+}
+
+pub(crate) fn mqtt_string(value: &str) -> Vec<u8> {
+    let mut encoded = (value.len() as u16).to_be_bytes().to_vec();
+    encoded.extend_from_slice(value.as_bytes());
+    encoded
+}
+
+pub(crate) fn mqtt_publish(topic: &str, packet_id: Option<u16>, payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::new();
+    mqtt_publish_into(&mut packet, topic, packet_id, payload);
+    packet
+}
+
+/// [`mqtt_publish`], appended to `out` without building the body first.
+pub(crate) fn mqtt_publish_into(out: &mut impl BufMut, topic: &str, packet_id: Option<u16>, payload: &[u8]) {
+    let header = if packet_id.is_some() { MQTT_PUBLISH_QOS1 } else { MQTT_PUBLISH };
+    mqtt_fixed_header(out, header, 2 + topic.len() + packet_id.map_or(0, |_| 2) + payload.len());
+    out.put_u16(topic.len() as u16);
+    out.put_slice(topic.as_bytes());
+    if let Some(id) = packet_id {
+        out.put_u16(id);
+    }
+    out.put_slice(payload);
+}
+
+/// (header, body) of a complete MQTT packet.
+pub(crate) fn mqtt_split(packet: &[u8]) -> Option<(u8, &[u8])> {
+    let mut remaining = 0usize;
+    for (i, byte) in packet.iter().skip(1).take(4).enumerate() {
+        remaining |= ((byte & 0x7F) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            let start = 2 + i;
+            return Some((packet[0], packet.get(start..start + remaining)?));
+        }
+    }
+    None
+}
+
+/// Chunked topics: the device publishes a chunk request (QoS 0), the broker side
+/// answers on the data topic (QoS 1) and the device PUBACKs. A persistent
+/// session keeps the subscription, so resuming is CONNECT plus the next chunk.
+pub(crate) struct MqttChunks;
+
+impl MqttChunks {
+    fn connect(&self, clean_session: bool) -> Exchange {
+        let mut body = mqtt_string("MQTT");
+        body.extend_from_slice(&[4, if clean_session { 0x02 } else { 0x00 }, 0, 60]);
+        body.extend_from_slice(&mqtt_string("fw-dev1"));
+        Exchange::round_trip(
+            mqtt_packet(MQTT_CONNECT, &body),
+            mqtt_packet(MQTT_CONNACK, &[!clean_session as u8, 0]),
+        )
+    }
+}
+
+impl UpdateMechanism for MqttChunks {
+    fn name(&self) -> &'static str {
+        "MQTT chunked topics"
+    }
+
+    fn carrier(&self) -> Carrier {
+        Carrier::Tcp
+    }
+
+    fn block_size(&self) -> usize {
+        4096
+    }
+
+    fn session_setup(&self, _image: &[u8], resume_offset: Option<usize>) -> Result<Vec<Exchange>> {
+        if resume_offset.is_some() {
+            return Ok(vec![self.connect(false)]);
+        }
+
+        let mut subscribe = 1u16.to_be_bytes().to_vec();
+        subscribe.extend_from_slice(&mqtt_string(MQTT_DATA_TOPIC));
+        subscribe.push(1);
+        Ok(vec![
+            self.connect(true),
+            Exchange::round_trip(mqtt_packet(MQTT_SUBSCRIBE, &subscribe), mqtt_packet(MQTT_SUBACK, &[0, 1, 1])),
+        ])
+    }
+
+    fn session_teardown(&self, _image: &[u8]) -> Result<Vec<Exchange>> {
+        let status = format!(r#"{{"state":"applied","version":"2.0.0","image":"{}"}}"#, IMAGE_NAME);
+        Ok(vec![
+            Exchange::round_trip(
+                mqtt_publish("fw/dev1/status", Some(0xFFFF), status.as_bytes()),
+                mqtt_packet(MQTT_PUBACK, &0xFFFFu16.to_be_bytes()),
+            ),
+            Exchange::one_way(mqtt_packet(MQTT_DISCONNECT, &[])),
+        ])
+    }
+
+    fn block_request(&self, block: usize) -> Vec<u8> {
+        mqtt_publish(MQTT_REQUEST_TOPIC, None, &(block as u32).to_be_bytes())
+    }
+
+    fn parse_block_request(&self, request: &[u8]) -> Option<usize> {
+        let (header, body) = mqtt_split(request)?;
+        let topic_len = u16::from_be_bytes(body.get(..2)?.try_into().ok()?) as usize;
+        if header != MQTT_PUBLISH || body.get(2..2 + topic_len)? != MQTT_REQUEST_TOPIC.as_bytes() {
+            return None;
+        }
+        Some(u32::from_be_bytes(body.get(2 + topic_len..6 + topic_len)?.try_into().ok()?) as usize)
+    }
+
+    fn block_response(&self, block: usize, data: &[u8], _image_bytes: usize) -> Vec<u8> {
+        let mut payload = (block as u32).to_be_bytes().to_vec();
+        payload.extend_from_slice(data);
+        mqtt_publish(MQTT_DATA_TOPIC, Some(block as u16 % 0xFFFE + 1), &payload)
+    }
+
+    fn block_payload<'a>(&self, response: &'a [u8]) -> Option<&'a [u8]> {
+        let (header, body) = mqtt_split(response)?;
+        let topic_len = u16::from_be_bytes(body.get(..2)?.try_into().ok()?) as usize;
+        // topic, packet id, chunk index
+        (header == MQTT_PUBLISH_QOS1).then(|| body.get(2 + topic_len + 2 + 4..)).flatten()
+    }
+
+    fn block_ack(&self, block: usize) -> Option<Vec<u8>> {
+        Some(mqtt_packet(MQTT_PUBACK, &(block as u16 % 0xFFFE + 1).to_be_bytes()))
+    }
+
+    fn frame_len(&self, buffer: &[u8]) -> Option<usize> {
+        let (_, body) = mqtt_split(buffer)?;
+        Some(body.as_ptr() as usize - buffer.as_ptr() as usize + body.len())
+    }
+}
+
+/// HTTP/1.1 with a manifest fetch and fixed-size Range GETs on one keep-alive
+/// connection; a new connection with the next Range resumes.
+pub(crate) struct HttpRanges;
+
+impl HttpRanges {
+    fn header_end(buffer: &[u8]) -> Option<usize> {
+        buffer.windows(4).position(|w| w == b"\r\n\r\n").map(|p| p + 4)
+    }
+
+    fn response(status: &str, headers: &[String], body: &[u8]) -> Vec<u8> {
+        let mut response = format!("HTTP/1.1 {}\r\n", status);
+        for header in headers {
+            response.push_str(header);
+            response.push_str("\r\n");
+        }
+        response.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+        let mut response = response.into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+}
+
+impl UpdateMechanism for HttpRanges {
+    fn name(&self) -> &'static str {
+        "HTTP range requests"
+    }
+
+    fn carrier(&self) -> Carrier {
+        Carrier::Tcp
+    }
+
+    fn block_size(&self) -> usize {
+        16 * 1024
+    }
+
+    fn session_setup(&self, image: &[u8], resume_offset: Option<usize>) -> Result<Vec<Exchange>> {
+        if resume_offset.is_some() {
+            return Ok(Vec::new());
+        }
+
+        let sha256: String = digest::digest(&digest::SHA256, image)
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let manifest = format!(r#"{{"version":"2.0.0","image":"{}","size":{},"sha256":"{}"}}"#, IMAGE_NAME, image.len(), sha256);
+        Ok(vec![Exchange::round_trip(
+            b"GET /fw/manifest.json HTTP/1.1\r\nHost: updates.local\r\n\r\n".to_vec(),
+            Self::response("200 OK", &["Content-Type: application/json".to_string()], manifest.as_bytes()),
+        )])
+    }
+
+    fn session_teardown(&self, _image: &[u8]) -> Result<Vec<Exchange>> {
+        let body = r#"{"state":"applied","version":"2.0.0"}"#;
+        let request = format!(
+            "POST /fw/status HTTP/1.1\r\nHost: updates.local\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        Ok(vec![Exchange::round_trip(request.into_bytes(), b"HTTP/1.1 204 No Content\r\n\r\n".to_vec())])
+    }
+
+    fn block_request(&self, block: usize) -> Vec<u8> {
+        let start = block * self.block_size();
+        format!(
+            "GET /fw/{} HTTP/1.1\r\nHost: updates.local\r\nRange: bytes={}-{}\r\n\r\n",
+            IMAGE_NAME,
+            start,
+            start + self.block_size() - 1
+        )
+        .into_bytes()
+    }
+
+    fn parse_block_request(&self, request: &[u8]) -> Option<usize> {
+        let request = std::str::from_utf8(request).ok()?;
+        if !request.starts_with(&format!("GET /fw/{} ", IMAGE_NAME)) {
+            return None;
+        }
+        let range = request.split("Range: bytes=").nth(1)?;
+        let start: usize = range.split('-').next()?.parse().ok()?;
+        Some(start / self.block_size())
+    }
+
+    fn block_response(&self, block: usize, data: &[u8], image_bytes: usize) -> Vec<u8> {
+        let start = block * self.block_size();
+        Self::response(
+            "206 Partial Content",
+            &[
+                "Content-Type: application/octet-stream".to_string(),
+                format!("Content-Range: bytes {}-{}/{}", start, start + data.len() - 1, image_bytes),
+            ],
+            data,
+        )
+    }
+
+    fn block_payload<'a>(&self, response: &'a [u8]) -> Option<&'a [u8]> {
+        if !response.starts_with(b"HTTP/1.1 206") {
+            return None;
+        }
+        response.get(Self::header_end(response)?..)
+    }
+
+    fn frame_len(&self, buffer: &[u8]) -> Option<usize> {
+        let header_end = Self::header_end(buffer)?;
+        let headers = std::str::from_utf8(&buffer[..header_end]).ok()?;
+        let content_length = headers
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("content-length").then(|| value.trim().parse::<usize>().ok())?
+            })
+            .unwrap_or(0);
+        let total = header_end + content_length;
+        (buffer.len() >= total).then_some(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Messages and wire bytes of `exchanges`, counted from the message formats.
+    fn exchange_cost(exchanges: &[Exchange]) -> (usize, usize) {
+        exchanges.iter().fold((0, 0), |(messages, bytes), exchange| {
+            let response = exchange.response.as_ref().map_or(0, Vec::len);
+            (messages + 1 + usize::from(exchange.response.is_some()), bytes + exchange.request.len() + response)
+        })
+    }
+
+    /// Messages and wire bytes of fetching `block` once.
+    fn block_cost(mechanism: &dyn UpdateMechanism, image: &[u8], block: usize) -> (usize, usize) {
+        let size = mechanism.block_size();
+        let data = &image[block * size..((block + 1) * size).min(image.len())];
+        let ack = mechanism.block_ack(block).map_or(0, |ack| ack.len());
+        (2 + usize::from(ack > 0), mechanism.block_request(block).len() + mechanism.block_response(block, data, image.len()).len() + ack)
+    }
+
+    #[tokio::test]
+    async fn transfers_over_loopback_report_what_crossed_the_wire() {
+        let config = FirmwareUpdateConfig { image_bytes: 20_000, interrupt_at: 0.5 };
+        let metrics = FirmwareUpdateBenchmark::new(config.clone()).analyze_firmware_update().await.unwrap();
+        let image = image(config.image_bytes);
+        let mechanisms: [&dyn UpdateMechanism; 4] = [&MatterBdx, &Lwm2mBlockwise, &MqttChunks, &HttpRanges];
+        assert_eq!(metrics.image_bytes, config.image_bytes);
+        assert_eq!(metrics.mechanisms.len(), mechanisms.len());
+
+        for (mechanism, result) in mechanisms.into_iter().zip(&metrics.mechanisms) {
+            assert_eq!(result.mechanism, mechanism.name());
+            let blocks = image.len().div_ceil(mechanism.block_size());
+            assert_eq!(result.blocks, blocks);
+
+            let setup = mechanism.session_setup(&image, None).unwrap();
+            let teardown = mechanism.session_teardown(&image).unwrap();
+            let (mut messages, mut bytes) = exchange_cost(&setup);
+            let (teardown_messages, teardown_bytes) = exchange_cost(&teardown);
+            (messages, bytes) = (messages + teardown_messages, bytes + teardown_bytes);
+            for block in 0..blocks {
+                let (block_messages, block_bytes) = block_cost(mechanism, &image, block);
+                (messages, bytes) = (messages + block_messages, bytes + block_bytes);
+            }
+            assert_eq!((result.messages, result.wire_bytes), (messages, bytes), "{}", result.mechanism);
+            assert_eq!(result.overhead_ratio, (bytes - image.len()) as f64 / image.len() as f64);
+            assert!(result.total_time_ms > 0.0);
+            assert!((result.goodput_kbps - image.len() as f64 * 8.0 / result.total_time_ms).abs() < 1e-9);
+
+            // The interrupted block is fetched again after the resume handshake, and its ack is never sent
+            let interrupted = blocks / 2;
+            let resume = &result.resume;
+            assert_eq!(resume.resumed_from_offset, interrupted * mechanism.block_size());
+            assert_eq!(resume.wasted_image_bytes, mechanism.block_size().min(image.len() - resume.resumed_from_offset));
+            let (setup_messages, setup_bytes) = exchange_cost(&mechanism.session_setup(&image, Some(resume.resumed_from_offset)).unwrap());
+            let (block_messages, block_bytes) = block_cost(mechanism, &image, interrupted);
+            let ack = mechanism.block_ack(interrupted).map_or(0, |ack| ack.len());
+            assert_eq!(resume.extra_messages, setup_messages + block_messages - usize::from(ack > 0), "{}", result.mechanism);
+            assert_eq!(resume.extra_wire_bytes, setup_bytes + block_bytes - ack, "{}", result.mechanism);
+            assert!(resume.resume_setup_ms > 0.0 && resume.total_time_ms > resume.resume_setup_ms);
+        }
+    }
+}
@@ -0,0 +1,482 @@
+// matter-project/src/fixtures.rs
+/*!
+Test fixture servers - the local counterparts the measurements talk to
+
+A UDP echo responder, a TCP discard sink, a TCP echo peer, and minimal CoAP
+and HTTP servers. Each one binds its own port and counts what it receives. A loss, RTT or
+throughput figure measured against them comes from a peer that actually
+answers, not from sending into a port where nothing listens and timing the
+send. The analyzer starts them on ephemeral loopback ports as it needs them.
+`analyzer-fixtures` runs all four for measurements from another host or
+another tool, on fixed ports or on ports reserved through a `PortAllocator`.
+
+A connection only counts as set up once the peer has accepted it. The
+kernel completes the TCP handshake for a listener that never calls
+`accept`, so timing `connect` alone does not show a peer is there. The TCP
+peer therefore writes [`PEER_ACCEPT_ACK`] as soon as it accepts, and then
+echoes. [`connect_to_peer`] times the connect, the wait for that
+acknowledgement, and a first echoed round trip.
+
+The CoAP server answers any GET with a 2.05 Content carrying the request's
+token. It piggybacks the response on the ACK for confirmable requests and
+resets empty pings. The HTTP server reads one request per connection,
+discards any body, and replies 200 with a short body. The mDNS responder
+answers queries for `_matter._tcp.local` as one advertised node; it is not
+one of the four fixed fixtures, since its answers depend on the node.
+*/
+
+use analysis_core::ports::PortAllocator;
+use anyhow::{anyhow, Result};
+use log::debug;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::task::JoinHandle;
+
+use crate::mdns::{self, ServiceAdvertisement};
+
+/// Names of the fixtures' ports in a ports file.
+pub const FIXTURE_PORTS: [&str; 4] = ["udp_echo", "tcp_sink", "coap", "http"];
+/// Body of every CoAP and HTTP response.
+pub const RESPONSE_BODY: &[u8] = b"analyzer fixture";
+
+/// Written by the TCP peer on every connection it accepts.
+pub const PEER_ACCEPT_ACK: u8 = 0x06;
+
+/// Pause after a failed accept, typically EMFILE, before accepting again.
+const ACCEPT_RETRY: Duration = Duration::from_millis(10);
+const COAP_VERSION: u8 = 1;
+const COAP_CON: u8 = 0;
+const COAP_NON: u8 = 1;
+const COAP_ACK: u8 = 2;
+const COAP_RST: u8 = 3;
+const COAP_GET: u8 = 0x01;
+const COAP_CONTENT: u8 = 0x45;
+const COAP_METHOD_NOT_ALLOWED: u8 = 0x85;
+const COAP_PAYLOAD_MARKER: u8 = 0xFF;
+
+#[derive(Debug, Clone)]
+pub struct FixtureConfig {
+    pub bind_ip: IpAddr,
+    /// 0 picks a free port.
+    pub udp_echo_port: u16,
+    pub tcp_sink_port: u16,
+    pub coap_port: u16,
+    pub http_port: u16,
+}
+
+impl Default for FixtureConfig {
+    fn default() -> Self {
+        Self {
+            bind_ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            udp_echo_port: 0,
+            tcp_sink_port: 0,
+            coap_port: 0,
+            http_port: 0,
+        }
+    }
+}
+
+/// A running fixture server. It stops when dropped.
+pub struct Fixture {
+    pub name: &'static str,
+    pub addr: SocketAddr,
+    received: Arc<AtomicU64>,
+    handle: JoinHandle<()>,
+}
+
+impl Fixture {
+    /// Payload bytes received so far.
+    pub fn received_bytes(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    fn spawn<F>(name: &'static str, addr: SocketAddr, serve: impl FnOnce(Arc<AtomicU64>) -> F) -> Self
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        let received = Arc::new(AtomicU64::new(0));
+        let handle = tokio::spawn(serve(received.clone()));
+        Self { name, addr, received, handle }
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Sends every datagram straight back to its sender.
+pub async fn udp_echo(addr: SocketAddr) -> Result<Fixture> {
+    udp_echo_on(UdpSocket::bind(addr).await?)
+}
+
+pub fn udp_echo_on(socket: UdpSocket) -> Result<Fixture> {
+    let addr = socket.local_addr()?;
+    Ok(Fixture::spawn("udp echo", addr, |received| async move {
+        let mut buffer = vec![0u8; 65_536];
+        loop {
+            match socket.recv_from(&mut buffer).await {
+                Ok((len, peer)) => {
+                    received.fetch_add(len as u64, Ordering::Relaxed);
+                    if let Err(e) = socket.send_to(&buffer[..len], peer).await {
+                        debug!("UDP echo to {} failed: {}", peer, e);
+                    }
+                }
+                Err(e) => debug!("UDP echo receive error: {}", e),
+            }
+        }
+    }))
+}
+
+/// Accepts connections and discards everything sent on them.
+pub async fn tcp_sink(addr: SocketAddr) -> Result<Fixture> {
+    tcp_sink_on(TcpListener::bind(addr).await?)
+}
+
+pub fn tcp_sink_on(listener: TcpListener) -> Result<Fixture> {
+    let addr = listener.local_addr()?;
+    Ok(Fixture::spawn("tcp sink", addr, |received| async move {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                tokio::time::sleep(ACCEPT_RETRY).await;
+                continue;
+            };
+            let received = received.clone();
+            tokio::spawn(async move {
+                let mut buffer = vec![0u8; 65_536];
+                while let Ok(len @ 1..) = stream.read(&mut buffer).await {
+                    received.fetch_add(len as u64, Ordering::Relaxed);
+                }
+            });
+        }
+    }))
+}
+
+/// Acknowledges every connection it accepts with [`PEER_ACCEPT_ACK`], then echoes what is sent on it.
+pub async fn tcp_peer(addr: SocketAddr) -> Result<Fixture> {
+    tcp_peer_on(TcpListener::bind(addr).await?)
+}
+
+pub fn tcp_peer_on(listener: TcpListener) -> Result<Fixture> {
+    let addr = listener.local_addr()?;
+    Ok(Fixture::spawn("tcp peer", addr, |received| async move {
+        loop {
+            let Ok((mut stream, peer)) = listener.accept().await else {
+                tokio::time::sleep(ACCEPT_RETRY).await;
+                continue;
+            };
+            let received = received.clone();
+            tokio::spawn(async move {
+                let _ = stream.set_nodelay(true);
+                if let Err(e) = stream.write_all(&[PEER_ACCEPT_ACK]).await {
+                    debug!("TCP peer could not acknowledge {}: {}", peer, e);
+                    return;
+                }
+                let mut buffer = vec![0u8; 65_536];
+                while let Ok(len @ 1..) = stream.read(&mut buffer).await {
+                    received.fetch_add(len as u64, Ordering::Relaxed);
+                    if stream.write_all(&buffer[..len]).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    }))
+}
+
+/// How long each step of connecting to a [`tcp_peer`] took.
+#[derive(Debug, Clone, Copy)]
+pub struct PeerConnection {
+    /// `connect` until the TCP handshake completed.
+    pub connect_ms: f64,
+    /// Handshake completed until the peer's accept acknowledgement arrived.
+    pub accept_ack_ms: f64,
+    /// One probe sent and echoed back.
+    pub round_trip_ms: f64,
+}
+
+impl PeerConnection {
+    pub fn total_ms(&self) -> f64 {
+        self.connect_ms + self.accept_ack_ms + self.round_trip_ms
+    }
+}
+
+/// Connects to a [`tcp_peer`], waits for it to acknowledge the accept and
+/// times one echoed `probe`. Fails when no acknowledgement or echo arrives
+/// within `timeout`, as with a listener that never accepts.
+pub async fn connect_to_peer(addr: SocketAddr, probe: &[u8], timeout: Duration) -> Result<(TcpStream, PeerConnection)> {
+    let ms = |from: Instant, to: Instant| (to - from).as_secs_f64() * 1000.0;
+    let exchange = async {
+        let start = Instant::now();
+        let mut stream = TcpStream::connect(addr).await?;
+        let connected = Instant::now();
+        stream.set_nodelay(true)?;
+
+        let mut ack = [0u8; 1];
+        stream.read_exact(&mut ack).await?;
+        if ack[0] != PEER_ACCEPT_ACK {
+            return Err(anyhow!("peer sent {:#04x} instead of its accept acknowledgement", ack[0]));
+        }
+        let accepted = Instant::now();
+
+        stream.write_all(probe).await?;
+        let mut echo = vec![0u8; probe.len()];
+        stream.read_exact(&mut echo).await?;
+        if echo != probe {
+            return Err(anyhow!("peer echoed different bytes"));
+        }
+        let echoed = Instant::now();
+
+        let timing = PeerConnection {
+            connect_ms: ms(start, connected),
+            accept_ack_ms: ms(connected, accepted),
+            round_trip_ms: ms(accepted, echoed),
+        };
+        Ok((stream, timing))
+    };
+    tokio::time::timeout(timeout, exchange)
+        .await
+        .map_err(|_| anyhow!("no accept acknowledgement and echo from {} within {:?}", addr, timeout))?
+}
+
+pub async fn coap_server(addr: SocketAddr) -> Result<Fixture> {
+    coap_server_on(UdpSocket::bind(addr).await?)
+}
+
+pub fn coap_server_on(socket: UdpSocket) -> Result<Fixture> {
+    let addr = socket.local_addr()?;
+    Ok(Fixture::spawn("coap", addr, |received| async move {
+        let mut buffer = vec![0u8; 1500];
+        loop {
+            let Ok((len, peer)) = socket.recv_from(&mut buffer).await else {
+                continue;
+            };
+            received.fetch_add(len as u64, Ordering::Relaxed);
+            if let Some(response) = coap_response(&buffer[..len]) {
+                if let Err(e) = socket.send_to(&response, peer).await {
+                    debug!("CoAP response to {} failed: {}", peer, e);
+                }
+            }
+        }
+    }))
+}
+
+/// The reply to one CoAP message, or `None` for messages that get no reply.
+fn coap_response(request: &[u8]) -> Option<Vec<u8>> {
+    let (first, code) = (*request.first()?, *request.get(1)?);
+    let message_id = request.get(2..4)?;
+    let (version, kind, token_len) = (first >> 6, (first >> 4) & 0x03, (first & 0x0F) as usize);
+    if version != COAP_VERSION || token_len > 8 {
+        return None;
+    }
+    let token = request.get(4..4 + token_len)?;
+
+    let header = |kind: u8, code: u8, token: &[u8]| {
+        let mut message = vec![(COAP_VERSION << 6) | (kind << 4) | token.len() as u8, code];
+        message.extend_from_slice(message_id);
+        message.extend_from_slice(token);
+        message
+    };
+    match (kind, code) {
+        // An empty confirmable message is a ping
+        (COAP_CON, 0) => Some(header(COAP_RST, 0, &[])),
+        (COAP_CON | COAP_NON, _) => {
+            let reply_kind = if kind == COAP_CON { COAP_ACK } else { COAP_NON };
+            if code != COAP_GET {
+                return Some(header(reply_kind, COAP_METHOD_NOT_ALLOWED, token));
+            }
+            let mut response = header(reply_kind, COAP_CONTENT, token);
+            response.push(COAP_PAYLOAD_MARKER);
+            response.extend_from_slice(RESPONSE_BODY);
+            Some(response)
+        }
+        _ => None,
+    }
+}
+
+/// Answers mDNS queries for the Matter service as the node in `advertisement`, unicast to the asker.
+pub async fn mdns_responder(addr: SocketAddr, advertisement: ServiceAdvertisement) -> Result<Fixture> {
+    mdns_responder_on(UdpSocket::bind(addr).await?, advertisement)
+}
+
+pub fn mdns_responder_on(socket: UdpSocket, advertisement: ServiceAdvertisement) -> Result<Fixture> {
+    let addr = socket.local_addr()?;
+    Ok(Fixture::spawn("mdns", addr, |received| async move {
+        let mut buffer = vec![0u8; 9000];
+        loop {
+            let Ok((len, peer)) = socket.recv_from(&mut buffer).await else {
+                continue;
+            };
+            received.fetch_add(len as u64, Ordering::Relaxed);
+            if let Some(response) = mdns::answer(&buffer[..len], &advertisement) {
+                if let Err(e) = socket.send_to(&response, peer).await {
+                    debug!("mDNS response to {} failed: {}", peer, e);
+                }
+            }
+        }
+    }))
+}
+
+pub async fn http_server(addr: SocketAddr) -> Result<Fixture> {
+    http_server_on(TcpListener::bind(addr).await?)
+}
+
+pub fn http_server_on(listener: TcpListener) -> Result<Fixture> {
+    let addr = listener.local_addr()?;
+    Ok(Fixture::spawn("http", addr, |received| async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                tokio::time::sleep(ACCEPT_RETRY).await;
+                continue;
+            };
+            let received = received.clone();
+            tokio::spawn(async move {
+                if let Err(e) = serve_http(stream, &received).await {
+                    debug!("HTTP fixture connection failed: {}", e);
+                }
+            });
+        }
+    }))
+}
+
+async fn serve_http(stream: TcpStream, received: &AtomicU64) -> Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut content_length = 0usize;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(());
+        }
+        received.fetch_add(line.len() as u64, Ordering::Relaxed);
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse()?;
+            }
+        }
+    }
+
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).await?;
+    received.fetch_add(content_length as u64, Ordering::Relaxed);
+
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        RESPONSE_BODY.len()
+    );
+    let stream = reader.get_mut();
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(RESPONSE_BODY).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// All four fixtures.
+pub struct Fixtures {
+    pub udp_echo: Fixture,
+    pub tcp_sink: Fixture,
+    pub coap: Fixture,
+    pub http: Fixture,
+}
+
+impl Fixtures {
+    pub async fn start(config: &FixtureConfig) -> Result<Self> {
+        let addr = |port| SocketAddr::new(config.bind_ip, port);
+        Ok(Self {
+            udp_echo: udp_echo(addr(config.udp_echo_port)).await?,
+            tcp_sink: tcp_sink(addr(config.tcp_sink_port)).await?,
+            coap: coap_server(addr(config.coap_port)).await?,
+            http: http_server(addr(config.http_port)).await?,
+        })
+    }
+
+    /// Starts the fixtures on ports reserved in `ports` as `udp_echo`, `tcp_sink`, `coap` and `http`.
+    pub fn start_reserved(ports: &mut PortAllocator) -> Result<Self> {
+        for name in FIXTURE_PORTS {
+            ports.reserve(name)?;
+        }
+        Ok(Self {
+            udp_echo: udp_echo_on(ports.take_udp("udp_echo")?)?,
+            tcp_sink: tcp_sink_on(ports.take_tcp("tcp_sink")?)?,
+            coap: coap_server_on(ports.take_udp("coap")?)?,
+            http: http_server_on(ports.take_tcp("http")?)?,
+        })
+    }
+
+    pub fn all(&self) -> [&Fixture; 4] {
+        [&self.udp_echo, &self.tcp_sink, &self.coap, &self.http]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures_answer_like_real_peers() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let fixtures = Fixtures::start_reserved(&mut PortAllocator::loopback()).unwrap();
+            let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let mut buffer = [0u8; 1500];
+
+            client.send_to(b"ping", fixtures.udp_echo.addr).await.unwrap();
+            let (len, _) = client.recv_from(&mut buffer).await.unwrap();
+            assert_eq!(&buffer[..len], b"ping");
+
+            // Confirmable GET, message ID 0x1234, token 0xAB
+            client.send_to(&[0x41, COAP_GET, 0x12, 0x34, 0xAB], fixtures.coap.addr).await.unwrap();
+            let (len, _) = client.recv_from(&mut buffer).await.unwrap();
+            assert_eq!(&buffer[..6], &[0x61, COAP_CONTENT, 0x12, 0x34, 0xAB, COAP_PAYLOAD_MARKER]);
+            assert_eq!(&buffer[6..len], RESPONSE_BODY);
+
+            let mut stream = TcpStream::connect(fixtures.tcp_sink.addr).await.unwrap();
+            stream.write_all(&[0u8; 1000]).await.unwrap();
+            drop(stream);
+
+            let mut stream = TcpStream::connect(fixtures.http.addr).await.unwrap();
+            stream.write_all(b"POST /report HTTP/1.1\r\nHost: fixture\r\nContent-Length: 5\r\n\r\nhello").await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 200 OK"));
+            assert!(response.ends_with("analyzer fixture"));
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!(fixtures.tcp_sink.received_bytes(), 1000);
+        });
+    }
+
+    #[test]
+    fn peer_connections_include_the_accept_and_a_round_trip() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let peer = tcp_peer("127.0.0.1:0".parse().unwrap()).await.unwrap();
+            for _ in 0..3 {
+                let (_stream, timing) = connect_to_peer(peer.addr, b"probe", Duration::from_secs(5)).await.unwrap();
+                assert!(timing.connect_ms > 0.0 && timing.accept_ack_ms >= 0.0 && timing.round_trip_ms > 0.0);
+                assert!(timing.total_ms() >= timing.connect_ms + timing.round_trip_ms);
+            }
+            assert_eq!(peer.received_bytes(), 3 * b"probe".len() as u64);
+
+            // The kernel completes the handshake for a listener that never accepts, so connect
+            // alone succeeds, but without the acknowledgement the connection is not counted
+            let idle = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            assert!(TcpStream::connect(idle.local_addr().unwrap()).await.is_ok());
+            let error = connect_to_peer(idle.local_addr().unwrap(), b"probe", Duration::from_millis(100)).await.unwrap_err();
+            assert!(error.to_string().contains("no accept acknowledgement"));
+
+            // Nor from a peer that accepts but does not acknowledge
+            let sink = tcp_sink("127.0.0.1:0".parse().unwrap()).await.unwrap();
+            assert!(connect_to_peer(sink.addr, b"probe", Duration::from_millis(100)).await.is_err());
+        });
+    }
+}
@@ -0,0 +1,209 @@
+// matter-project/src/footprint.rs
+/*!
+Code footprint comparison - builds minimal per-protocol clients and measures them
+*/
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::builder::analyzer_builder;
+
+const CLIENTS_PACKAGE: &str = "footprint-clients";
+
+/// (protocol, cargo feature, binary name)
+const CLIENTS: &[(&str, &str, &str)] = &[
+    ("Matter", "matter", "matter_client"),
+    ("LwM2M", "lwm2m", "lwm2m_client"),
+];
+
+// ELF section header constants
+const SHF_WRITE: u64 = 0x1;
+const SHF_ALLOC: u64 = 0x2;
+const SHT_NOBITS: u32 = 8;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FootprintMetrics {
+    pub build_profile: String,
+    pub clients: Vec<ClientFootprint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientFootprint {
+    pub protocol: String,
+    pub binary: String,
+    pub stripped_size_bytes: u64,
+    pub dependency_count: u32,
+    pub estimated_flash_bytes: u64,
+    pub estimated_ram_bytes: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct FootprintConfig {
+    /// Cargo target directory for the client builds, kept apart from the analyzer's own.
+    pub target_dir: PathBuf,
+}
+
+impl Default for FootprintConfig {
+    fn default() -> Self {
+        Self { target_dir: PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target").join("footprint") }
+    }
+}
+
+analyzer_builder!(FootprintAnalyzerBuilder => FootprintAnalyzer(FootprintConfig) {
+    target_dir: PathBuf,
+});
+
+pub struct FootprintAnalyzer {
+    workspace_dir: PathBuf,
+    target_dir: PathBuf,
+}
+
+impl Default for FootprintAnalyzer {
+    fn default() -> Self {
+        Self::new(FootprintConfig::default())
+    }
+}
+
+impl FootprintAnalyzer {
+    pub fn new(config: FootprintConfig) -> Self {
+        Self {
+            workspace_dir: PathBuf::from(env!("CARGO_MANIFEST_DIR")),
+            target_dir: config.target_dir,
+        }
+    }
+
+    pub fn analyze_footprint(&mut self) -> Result<FootprintMetrics> {
+        println!("\n📦 Analyzing Code Footprint (minimal clients)");
+        println!("---------------------------------------------");
+
+        let mut clients = Vec::new();
+        for (protocol, feature, binary) in CLIENTS {
+            let path = self.build_client(feature, binary)?;
+            let stripped_size_bytes = std::fs::metadata(&path)?.len();
+            let (estimated_flash_bytes, estimated_ram_bytes) =
+                elf_section_footprint(&path).unwrap_or((stripped_size_bytes, 0));
+
+            let footprint = ClientFootprint {
+                protocol: protocol.to_string(),
+                binary: binary.to_string(),
+                stripped_size_bytes,
+                dependency_count: self.count_dependencies(feature)?,
+                estimated_flash_bytes,
+                estimated_ram_bytes,
+            };
+
+            println!("✅ {}: {} bytes stripped, {} deps, ~{} bytes flash",
+                     footprint.protocol, footprint.stripped_size_bytes,
+                     footprint.dependency_count, footprint.estimated_flash_bytes);
+            clients.push(footprint);
+        }
+
+        Ok(FootprintMetrics {
+            build_profile: "release, opt-level=s, lto, panic=abort, stripped".to_string(),
+            clients,
+        })
+    }
+
+    fn build_client(&self, feature: &str, binary: &str) -> Result<PathBuf> {
+        info!("Building minimal {} client", binary);
+
+        // Size-oriented profile, as firmware would be built
+        let status = Command::new(env!("CARGO"))
+            .current_dir(&self.workspace_dir)
+            .args(["build", "--release", "-p", CLIENTS_PACKAGE, "--no-default-features"])
+            .args(["--features", feature, "--bin", binary])
+            .arg("--target-dir")
+            .arg(&self.target_dir)
+            .env("CARGO_PROFILE_RELEASE_OPT_LEVEL", "s")
+            .env("CARGO_PROFILE_RELEASE_LTO", "true")
+            .env("CARGO_PROFILE_RELEASE_PANIC", "abort")
+            .env("CARGO_PROFILE_RELEASE_STRIP", "symbols")
+            .status()
+            .context("failed to run cargo build for footprint client")?;
+
+        if !status.success() {
+            return Err(anyhow!("building {} failed with {}", binary, status));
+        }
+
+        let path = self
+            .target_dir
+            .join("release")
+            .join(format!("{}{}", binary, std::env::consts::EXE_SUFFIX));
+        debug!("Footprint artifact: {}", path.display());
+        Ok(path)
+    }
+
+    fn count_dependencies(&self, feature: &str) -> Result<u32> {
+        let output = Command::new(env!("CARGO"))
+            .current_dir(&self.workspace_dir)
+            .args(["tree", "-p", CLIENTS_PACKAGE, "--no-default-features", "--features", feature])
+            .args(["-e", "normal", "--prefix", "none"])
+            .output()
+            .context("failed to run cargo tree")?;
+
+        let tree = String::from_utf8_lossy(&output.stdout);
+        let unique: HashSet<&str> = tree
+            .lines()
+            .map(|line| line.trim_end_matches(" (*)").trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        // The first entry is the client package itself
+        Ok(unique.len().saturating_sub(1) as u32)
+    }
+}
+
+/// Sums allocated ELF sections: read-only + initialized data approximate flash,
+/// writable sections (including .bss) approximate RAM. Returns None for non-ELF files.
+fn elf_section_footprint(path: &Path) -> Option<(u64, u64)> {
+    let data = std::fs::read(path).ok()?;
+    if data.len() < 0x40 || &data[..4] != b"\x7fELF" || data[5] != 1 {
+        return None; // not a little-endian ELF
+    }
+
+    let is_64 = data[4] == 2;
+    let read_u16 = |offset: usize| data.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as u64);
+    let read_u32 = |offset: usize| data.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as u64);
+    let read_u64 = |offset: usize| {
+        data.get(offset..offset + 8).map(|b| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(b);
+            u64::from_le_bytes(bytes)
+        })
+    };
+
+    let (section_offset, entry_size, entry_count) = if is_64 {
+        (read_u64(0x28)?, read_u16(0x3A)?, read_u16(0x3C)?)
+    } else {
+        (read_u32(0x20)?, read_u16(0x2E)?, read_u16(0x30)?)
+    };
+
+    let mut flash = 0u64;
+    let mut ram = 0u64;
+
+    for index in 0..entry_count {
+        let header = (section_offset + index * entry_size) as usize;
+        let section_type = read_u32(header + 4)? as u32;
+        let (flags, size) = if is_64 {
+            (read_u64(header + 8)?, read_u64(header + 0x20)?)
+        } else {
+            (read_u32(header + 8)?, read_u32(header + 0x14)?)
+        };
+
+        if flags & SHF_ALLOC == 0 {
+            continue;
+        }
+        if section_type != SHT_NOBITS {
+            flash += size;
+        }
+        if flags & SHF_WRITE != 0 {
+            ram += size;
+        }
+    }
+
+    Some((flash, ram))
+}
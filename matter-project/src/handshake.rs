@@ -0,0 +1,782 @@
+// matter-project/src/handshake.rs
+/*!
+Transport-security handshake comparison - DTLS/TLS flights, RTTs and resumption over loopback and impaired links
+
+Handshakes are driven in memory between a client and a server endpoint of the
+selected backend, which yields exact flight sizes and round-trip counts. Link
+conditions are then applied per flight: one-way delay for every round trip and
+retransmission timeouts (DTLS timer / TCP RTO) for lost packets.
+*/
+
+use anyhow::Result;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::builder::analyzer_builder;
+
+// Give up retransmitting a flight after this many attempts
+const MAX_RETRANSMISSIONS: u32 = 6;
+const IPV4_TCP_HEADER_BYTES: usize = 20 + 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityProtocol {
+    Tls12,
+    Tls13,
+    Dtls12,
+    Dtls13,
+}
+
+impl SecurityProtocol {
+    pub const ALL: [SecurityProtocol; 4] = [Self::Tls12, Self::Tls13, Self::Dtls12, Self::Dtls13];
+
+    fn is_datagram(self) -> bool {
+        matches!(self, Self::Dtls12 | Self::Dtls13)
+    }
+
+    /// Initial timeout before a lost flight is resent: the RFC 6347 DTLS timer,
+    /// or the Linux minimum TCP RTO for TLS.
+    fn retransmit_timeout_ms(self) -> f64 {
+        if self.is_datagram() { 1000.0 } else { 200.0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    /// Server authenticated by an X.509 certificate (ECDSA P-256).
+    Certificate,
+    /// Pre-shared key, as in CoAP/LwM2M DTLS-PSK deployments.
+    Psk,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkProfile {
+    pub name: String,
+    pub one_way_delay_ms: f64,
+    pub loss_rate: f64,
+    pub mtu_bytes: usize,
+}
+
+impl LinkProfile {
+    pub fn loopback() -> Self {
+        Self { name: "loopback".to_string(), one_way_delay_ms: 0.0, loss_rate: 0.0, mtu_bytes: 1500 }
+    }
+
+    pub fn impaired(one_way_delay_ms: f64, loss_rate: f64) -> Self {
+        Self { name: "impaired".to_string(), one_way_delay_ms, loss_rate, mtu_bytes: 1280 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Flight {
+    pub from_client: bool,
+    pub bytes: usize,
+    /// Datagrams for DTLS, TLS records otherwise.
+    pub records: usize,
+}
+
+/// What a backend observed while driving one handshake in memory.
+#[derive(Debug)]
+pub struct HandshakeTrace {
+    pub flights: Vec<Flight>,
+    pub handshake_rtts: u32,
+    pub compute_time_ms: f64,
+    pub resumed: bool,
+}
+
+pub trait HandshakeBackend {
+    fn name(&self) -> &'static str;
+    fn supports(&self, protocol: SecurityProtocol, auth: AuthMode) -> bool;
+    /// A full handshake, or with `resume` one resuming the session of the
+    /// previous full handshake for the same protocol and authentication.
+    fn handshake(&mut self, protocol: SecurityProtocol, auth: AuthMode, resume: bool) -> Result<HandshakeTrace>;
+    /// DER size of the server certificate presented in certificate mode.
+    fn certificate_der_bytes(&self) -> usize;
+}
+
+#[derive(Debug, Clone)]
+pub struct HandshakeConfig {
+    pub protocols: Vec<SecurityProtocol>,
+    pub auth_modes: Vec<AuthMode>,
+    pub links: Vec<LinkProfile>,
+    pub trials: u32,
+}
+
+impl Default for HandshakeConfig {
+    fn default() -> Self {
+        Self {
+            protocols: SecurityProtocol::ALL.to_vec(),
+            auth_modes: vec![AuthMode::Certificate, AuthMode::Psk],
+            links: vec![LinkProfile::loopback(), LinkProfile::impaired(50.0, 0.05)],
+            trials: 200,
+        }
+    }
+}
+
+analyzer_builder!(HandshakeAnalyzerBuilder => HandshakeAnalyzer(HandshakeConfig) {
+    protocols: Vec<SecurityProtocol>,
+    auth_modes: Vec<AuthMode>,
+    links: Vec<LinkProfile>,
+    trials: u32,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HandshakeMetrics {
+    pub backends: Vec<String>,
+    pub links: Vec<LinkProfile>,
+    pub results: Vec<HandshakeResult>,
+    /// backend/protocol pairs that could not be measured, with the reason.
+    pub unsupported: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HandshakeResult {
+    pub backend: String,
+    pub protocol: SecurityProtocol,
+    pub auth: AuthMode,
+    pub link: String,
+    pub resumption_requested: bool,
+    pub resumed: bool,
+    pub flights: Vec<Flight>,
+    pub client_bytes: usize,
+    pub server_bytes: usize,
+    pub handshake_rtts: u32,
+    /// Server certificate DER size in certificate mode.
+    pub certificate_der_bytes: Option<usize>,
+    /// TCP three-way handshake ahead of TLS; 0 for DTLS.
+    pub transport_setup_rtts: u32,
+    pub compute_time_ms: f64,
+    pub mean_handshake_ms: f64,
+    pub p95_handshake_ms: f64,
+}
+
+pub struct HandshakeAnalyzer {
+    config: HandshakeConfig,
+}
+
+impl HandshakeAnalyzer {
+    pub fn new(config: HandshakeConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_handshakes(&mut self) -> Result<HandshakeMetrics> {
+        println!("\n🔏 Comparing DTLS / TLS Handshakes");
+        println!("----------------------------------");
+
+        let mut backends = available_backends();
+        let mut unsupported = Vec::new();
+        if backends.is_empty() {
+            warn!("⚠️ No TLS backend compiled in; enable the tls-rustls and/or tls-openssl features");
+            unsupported.push("no backend compiled in (features tls-rustls, tls-openssl)".to_string());
+        }
+        // No maintained Rust binding fits this harness yet
+        unsupported.push("mbedtls: backend not available in this build".to_string());
+
+        let mut results = Vec::new();
+        for backend in backends.iter_mut() {
+            for protocol in self.config.protocols.clone() {
+                for auth in self.config.auth_modes.clone() {
+                    if !backend.supports(protocol, auth) {
+                        unsupported.push(format!("{}: {:?} with {:?} not implemented by the library",
+                                                 backend.name(), protocol, auth));
+                        continue;
+                    }
+
+                    for resume in [false, true] {
+                        let trace = match backend.handshake(protocol, auth, resume) {
+                            Ok(trace) => trace,
+                            Err(e) => {
+                                warn!("⚠️ {} {:?}/{:?} handshake failed: {}", backend.name(), protocol, auth, e);
+                                unsupported.push(format!("{}: {:?} with {:?} failed: {}", backend.name(), protocol, auth, e));
+                                break;
+                            }
+                        };
+                        let certificate_der_bytes = match auth {
+                            AuthMode::Certificate => Some(backend.certificate_der_bytes()),
+                            AuthMode::Psk => None,
+                        };
+
+                        for link in &self.config.links {
+                            let mut result = self.evaluate(backend.name(), protocol, auth, resume, &trace, link);
+                            result.certificate_der_bytes = certificate_der_bytes;
+                            debug!("{} {:?}/{:?} resumed={} on {}: {:.2}ms mean",
+                                   result.backend, protocol, auth, result.resumed, link.name, result.mean_handshake_ms);
+                            results.push(result);
+                        }
+                    }
+                }
+            }
+        }
+
+        for result in results.iter().filter(|r| r.link == "loopback") {
+            println!("✅ {} {:?}/{:?}{}: {} RTTs, {} flights, {}B client / {}B server, {:.2}ms",
+                     result.backend, result.protocol, result.auth, if result.resumed { " (resumed)" } else { "" },
+                     result.handshake_rtts + result.transport_setup_rtts, result.flights.len(),
+                     result.client_bytes, result.server_bytes, result.compute_time_ms);
+        }
+
+        Ok(HandshakeMetrics {
+            backends: backends.iter().map(|b| b.name().to_string()).collect(),
+            links: self.config.links.clone(),
+            results,
+            unsupported,
+        })
+    }
+
+    fn evaluate(
+        &self,
+        backend: &str,
+        protocol: SecurityProtocol,
+        auth: AuthMode,
+        resume: bool,
+        trace: &HandshakeTrace,
+        link: &LinkProfile,
+    ) -> HandshakeResult {
+        let transport_setup_rtts = if protocol.is_datagram() { 0 } else { 1 };
+        let round_trips = (trace.handshake_rtts + transport_setup_rtts) as f64;
+        let base_ms = trace.compute_time_ms + round_trips * 2.0 * link.one_way_delay_ms;
+
+        let mut rng_state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut samples: Vec<f64> = (0..self.config.trials.max(1))
+            .map(|_| {
+                let mut time_ms = base_ms;
+                for flight in &trace.flights {
+                    let packets = if protocol.is_datagram() {
+                        flight.records
+                    } else {
+                        flight.bytes.div_ceil(link.mtu_bytes - IPV4_TCP_HEADER_BYTES)
+                    };
+                    let mut timeout_ms = protocol.retransmit_timeout_ms();
+                    for _ in 0..MAX_RETRANSMISSIONS {
+                        let delivered = (0..packets).all(|_| next_unit(&mut rng_state) >= link.loss_rate);
+                        if delivered {
+                            break;
+                        }
+                        time_ms += timeout_ms;
+                        timeout_ms *= 2.0;
+                    }
+                }
+                time_ms
+            })
+            .collect();
+        samples.sort_by(|a, b| a.total_cmp(b));
+
+        HandshakeResult {
+            backend: backend.to_string(),
+            protocol,
+            auth,
+            link: link.name.clone(),
+            resumption_requested: resume,
+            resumed: trace.resumed,
+            flights: trace.flights.clone(),
+            client_bytes: trace.flights.iter().filter(|f| f.from_client).map(|f| f.bytes).sum(),
+            server_bytes: trace.flights.iter().filter(|f| !f.from_client).map(|f| f.bytes).sum(),
+            handshake_rtts: trace.handshake_rtts,
+            certificate_der_bytes: None,
+            transport_setup_rtts,
+            compute_time_ms: trace.compute_time_ms,
+            mean_handshake_ms: samples.iter().sum::<f64>() / samples.len() as f64,
+            p95_handshake_ms: samples[((samples.len() - 1) as f64 * 0.95).round() as usize],
+        }
+    }
+}
+
+pub fn available_backends() -> Vec<Box<dyn HandshakeBackend>> {
+    #[allow(unused_mut)]
+    let mut backends: Vec<Box<dyn HandshakeBackend>> = Vec::new();
+
+    #[cfg(feature = "tls-rustls")]
+    match rustls_backend::RustlsBackend::new() {
+        Ok(backend) => backends.push(Box::new(backend)),
+        Err(e) => warn!("⚠️ rustls backend unavailable: {}", e),
+    }
+
+    #[cfg(feature = "tls-openssl")]
+    match openssl_backend::OpensslBackend::new() {
+        Ok(backend) => backends.push(Box::new(backend)),
+        Err(e) => warn!("⚠️ OpenSSL backend unavailable: {}", e),
+    }
+
+    backends
+}
+
+/// Deterministic xorshift in [0, 1) so impaired runs are repeatable.
+pub(crate) fn next_unit(state: &mut u64) -> f64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    (*state >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// In-memory handshake driver shared by the backends.
+#[cfg(any(feature = "tls-rustls", feature = "tls-openssl"))]
+mod pump {
+    use super::Flight;
+    use anyhow::{anyhow, Result};
+
+    // Bound on pump iterations; real handshakes need well under ten
+    const MAX_PUMP_ROUNDS: usize = 32;
+
+    /// One side of an in-memory handshake.
+    pub trait Endpoint {
+        /// Records or datagrams written since the last call.
+        fn take_output(&mut self) -> Result<Vec<Vec<u8>>>;
+        fn give_input(&mut self, packets: &[Vec<u8>]) -> Result<()>;
+        fn is_handshaking(&self) -> bool;
+    }
+
+    /// Shuttles output between the endpoints until both are idle; returns the
+    /// flights and the number of server flights the client had to wait for.
+    pub fn drive(client: &mut dyn Endpoint, server: &mut dyn Endpoint) -> Result<(Vec<Flight>, u32)> {
+        let mut flights: Vec<Flight> = Vec::new();
+        let mut handshake_rtts = 0;
+
+        // Consecutive output from the same side belongs to one flight
+        let record = |flights: &mut Vec<Flight>, from_client: bool, packets: &[Vec<u8>]| {
+            let bytes: usize = packets.iter().map(Vec::len).sum();
+            match flights.last_mut() {
+                Some(last) if last.from_client == from_client => {
+                    last.bytes += bytes;
+                    last.records += packets.len();
+                }
+                _ => flights.push(Flight { from_client, bytes, records: packets.len() }),
+            }
+        };
+
+        for _ in 0..MAX_PUMP_ROUNDS {
+            let from_client = client.take_output()?;
+            if !from_client.is_empty() {
+                record(&mut flights, true, &from_client);
+                server.give_input(&from_client)?;
+            }
+
+            let from_server = server.take_output()?;
+            if !from_server.is_empty() {
+                if client.is_handshaking() {
+                    handshake_rtts += 1;
+                }
+                record(&mut flights, false, &from_server);
+                client.give_input(&from_server)?;
+            }
+
+            if from_client.is_empty() && from_server.is_empty() {
+                if client.is_handshaking() || server.is_handshaking() {
+                    return Err(anyhow!("handshake stalled after {} flights", flights.len()));
+                }
+                return Ok((flights, handshake_rtts));
+            }
+        }
+
+        Err(anyhow!("handshake did not settle within {} rounds", MAX_PUMP_ROUNDS))
+    }
+}
+
+#[cfg(feature = "tls-rustls")]
+mod rustls_backend {
+    use super::pump::{drive, Endpoint};
+    use super::{AuthMode, HandshakeBackend, HandshakeTrace, SecurityProtocol};
+    use anyhow::{anyhow, Result};
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+    use rustls::{ClientConfig, ClientConnection, Connection, HandshakeKind, RootCertStore, ServerConfig, ServerConnection};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    pub struct RustlsBackend {
+        certificate: CertificateDer<'static>,
+        private_key: PrivatePkcs8KeyDer<'static>,
+        // Kept per protocol so the client session cache and server store survive until the resumed handshake
+        configs: Vec<(SecurityProtocol, Arc<ClientConfig>, Arc<ServerConfig>)>,
+    }
+
+    impl RustlsBackend {
+        pub fn new() -> Result<Self> {
+            let rcgen::CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+            Ok(Self {
+                certificate: cert.der().clone(),
+                private_key: PrivatePkcs8KeyDer::from(key_pair.serialize_der()),
+                configs: Vec::new(),
+            })
+        }
+
+        fn configs(&mut self, protocol: SecurityProtocol) -> Result<(Arc<ClientConfig>, Arc<ServerConfig>)> {
+            if let Some((_, client, server)) = self.configs.iter().find(|(p, _, _)| *p == protocol) {
+                return Ok((client.clone(), server.clone()));
+            }
+
+            let version = match protocol {
+                SecurityProtocol::Tls12 => &rustls::version::TLS12,
+                SecurityProtocol::Tls13 => &rustls::version::TLS13,
+                other => return Err(anyhow!("rustls does not implement {:?}", other)),
+            };
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+            let mut roots = RootCertStore::empty();
+            roots.add(self.certificate.clone())?;
+            let client = ClientConfig::builder_with_provider(provider.clone())
+                .with_protocol_versions(&[version])?
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            let server = ServerConfig::builder_with_provider(provider)
+                .with_protocol_versions(&[version])?
+                .with_no_client_auth()
+                .with_single_cert(vec![self.certificate.clone()], PrivateKeyDer::Pkcs8(self.private_key.clone_key()))?;
+
+            let (client, server) = (Arc::new(client), Arc::new(server));
+            self.configs.push((protocol, client.clone(), server.clone()));
+            Ok((client, server))
+        }
+    }
+
+    impl HandshakeBackend for RustlsBackend {
+        fn name(&self) -> &'static str {
+            "rustls"
+        }
+
+        fn supports(&self, protocol: SecurityProtocol, auth: AuthMode) -> bool {
+            // No DTLS, and no external PSK support in rustls
+            matches!(protocol, SecurityProtocol::Tls12 | SecurityProtocol::Tls13) && auth == AuthMode::Certificate
+        }
+
+        fn certificate_der_bytes(&self) -> usize {
+            self.certificate.len()
+        }
+
+        fn handshake(&mut self, protocol: SecurityProtocol, auth: AuthMode, resume: bool) -> Result<HandshakeTrace> {
+            if auth != AuthMode::Certificate {
+                return Err(anyhow!("rustls does not implement {:?} authentication", auth));
+            }
+            if !resume {
+                // Fresh caches so a full handshake is never accidentally resumed
+                self.configs.retain(|(p, _, _)| *p != protocol);
+            }
+            let (client_config, server_config) = self.configs(protocol)?;
+
+            let start = Instant::now();
+            let mut client = RustlsEndpoint(Connection::Client(ClientConnection::new(
+                client_config,
+                ServerName::try_from("localhost")?,
+            )?));
+            let mut server = RustlsEndpoint(Connection::Server(ServerConnection::new(server_config)?));
+            let (flights, handshake_rtts) = drive(&mut client, &mut server)?;
+            let compute_time_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+            Ok(HandshakeTrace {
+                flights,
+                handshake_rtts,
+                compute_time_ms,
+                resumed: client.0.handshake_kind() == Some(HandshakeKind::Resumed),
+            })
+        }
+    }
+
+    struct RustlsEndpoint(Connection);
+
+    impl Endpoint for RustlsEndpoint {
+        fn take_output(&mut self) -> Result<Vec<Vec<u8>>> {
+            let mut records = Vec::new();
+            while self.0.wants_write() {
+                let mut record = Vec::new();
+                self.0.write_tls(&mut record)?;
+                records.push(record);
+            }
+            Ok(records)
+        }
+
+        fn give_input(&mut self, packets: &[Vec<u8>]) -> Result<()> {
+            for packet in packets {
+                let mut remaining: &[u8] = packet;
+                while !remaining.is_empty() {
+                    self.0.read_tls(&mut remaining)?;
+                    self.0.process_new_packets()?;
+                }
+            }
+            Ok(())
+        }
+
+        fn is_handshaking(&self) -> bool {
+            self.0.is_handshaking()
+        }
+    }
+}
+
+#[cfg(feature = "tls-openssl")]
+mod openssl_backend {
+    use super::pump::{drive, Endpoint};
+    use super::{AuthMode, HandshakeBackend, HandshakeTrace, SecurityProtocol};
+    use anyhow::{anyhow, Result};
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::BigNum;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::{PKey, Private};
+    use openssl::ssl::{
+        ErrorCode, HandshakeError, MidHandshakeSslStream, Ssl, SslContext, SslMethod, SslOptions, SslSession,
+        SslStream, SslVerifyMode, SslVersion,
+    };
+    use openssl::x509::extension::BasicConstraints;
+    use openssl::x509::{X509NameBuilder, X509};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::io::{self, Read, Write};
+    use std::rc::Rc;
+    use std::time::Instant;
+
+    const DTLS_MTU: u32 = 1200;
+    const DTLS_COOKIE: &[u8] = b"iot-analyzer-cookie";
+    // CoAP-style PSK credentials: short identity, 128-bit key
+    const PSK_IDENTITY: &[u8] = b"device-00000001";
+    const PSK_KEY: [u8; 16] = [0x5A; 16];
+    // TLS_PSK_WITH_AES_128_CCM_8 is the CoAP (RFC 7252) mandatory suite
+    const PSK_CIPHERS: &str = "PSK-AES128-CCM8:PSK-AES128-GCM-SHA256";
+
+    struct ProtocolState {
+        protocol: SecurityProtocol,
+        auth: AuthMode,
+        client: SslContext,
+        // The server context holds the session cache used for resumption
+        server: SslContext,
+        session: Option<SslSession>,
+    }
+
+    pub struct OpensslBackend {
+        certificate: X509,
+        key: PKey<Private>,
+        states: Vec<ProtocolState>,
+    }
+
+    impl OpensslBackend {
+        pub fn new() -> Result<Self> {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+            let key = PKey::from_ec_key(EcKey::generate(&group)?)?;
+
+            let mut name = X509NameBuilder::new()?;
+            name.append_entry_by_text("CN", "localhost")?;
+            let name = name.build();
+
+            let serial = BigNum::from_u32(1)?.to_asn1_integer()?;
+            let not_before = Asn1Time::days_from_now(0)?;
+            let not_after = Asn1Time::days_from_now(1)?;
+
+            let mut builder = X509::builder()?;
+            builder.set_version(2)?;
+            builder.set_serial_number(&serial)?;
+            builder.set_subject_name(&name)?;
+            builder.set_issuer_name(&name)?;
+            builder.set_pubkey(&key)?;
+            builder.set_not_before(&not_before)?;
+            builder.set_not_after(&not_after)?;
+            builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+            builder.sign(&key, MessageDigest::sha256())?;
+
+            Ok(Self { certificate: builder.build(), key, states: Vec::new() })
+        }
+
+        fn state(&mut self, protocol: SecurityProtocol, auth: AuthMode) -> Result<&mut ProtocolState> {
+            if let Some(index) = self.states.iter().position(|s| s.protocol == protocol && s.auth == auth) {
+                return Ok(&mut self.states[index]);
+            }
+
+            let (method, version) = match protocol {
+                SecurityProtocol::Tls12 => (SslMethod::tls(), SslVersion::TLS1_2),
+                SecurityProtocol::Tls13 => (SslMethod::tls(), SslVersion::TLS1_3),
+                SecurityProtocol::Dtls12 => (SslMethod::dtls(), SslVersion::DTLS1_2),
+                other => return Err(anyhow!("OpenSSL does not implement {:?}", other)),
+            };
+
+            let mut client = SslContext::builder(method)?;
+            client.set_min_proto_version(Some(version))?;
+            client.set_max_proto_version(Some(version))?;
+            client.cert_store_mut().add_cert(self.certificate.clone())?;
+            client.set_verify(SslVerifyMode::PEER);
+
+            let mut server = SslContext::builder(method)?;
+            server.set_min_proto_version(Some(version))?;
+            server.set_max_proto_version(Some(version))?;
+            server.set_certificate(&self.certificate)?;
+            server.set_private_key(&self.key)?;
+            server.set_session_id_context(b"iot-analyzer")?;
+
+            if auth == AuthMode::Psk {
+                client.set_cipher_list(PSK_CIPHERS)?;
+                server.set_cipher_list(PSK_CIPHERS)?;
+                client.set_psk_client_callback(|_, _hint, identity, psk| {
+                    // The identity is written as a NUL-terminated C string
+                    identity[..PSK_IDENTITY.len()].copy_from_slice(PSK_IDENTITY);
+                    identity[PSK_IDENTITY.len()] = 0;
+                    psk[..PSK_KEY.len()].copy_from_slice(&PSK_KEY);
+                    Ok(PSK_KEY.len())
+                });
+                server.set_psk_server_callback(|_, identity, psk| {
+                    if identity != Some(PSK_IDENTITY) {
+                        return Ok(0); // unknown identity: handshake fails
+                    }
+                    psk[..PSK_KEY.len()].copy_from_slice(&PSK_KEY);
+                    Ok(PSK_KEY.len())
+                });
+            }
+
+            if protocol.is_datagram() {
+                // The MTU is set explicitly; the in-memory BIO has none to query
+                client.set_options(SslOptions::NO_QUERY_MTU);
+                server.set_options(SslOptions::NO_QUERY_MTU | SslOptions::COOKIE_EXCHANGE);
+                server.set_cookie_generate_cb(|_, cookie| {
+                    cookie[..DTLS_COOKIE.len()].copy_from_slice(DTLS_COOKIE);
+                    Ok(DTLS_COOKIE.len())
+                });
+                server.set_cookie_verify_cb(|_, cookie| cookie == DTLS_COOKIE);
+            }
+
+            self.states.push(ProtocolState { protocol, auth, client: client.build(), server: server.build(), session: None });
+            Ok(self.states.last_mut().expect("just pushed"))
+        }
+    }
+
+    impl HandshakeBackend for OpensslBackend {
+        fn name(&self) -> &'static str {
+            "openssl"
+        }
+
+        fn supports(&self, protocol: SecurityProtocol, auth: AuthMode) -> bool {
+            // OpenSSL 3.x has no DTLS 1.3; PSK is wired up for the (D)TLS 1.2 suites only
+            match auth {
+                AuthMode::Certificate => protocol != SecurityProtocol::Dtls13,
+                AuthMode::Psk => matches!(protocol, SecurityProtocol::Tls12 | SecurityProtocol::Dtls12),
+            }
+        }
+
+        fn certificate_der_bytes(&self) -> usize {
+            self.certificate.to_der().map(|der| der.len()).unwrap_or(0)
+        }
+
+        fn handshake(&mut self, protocol: SecurityProtocol, auth: AuthMode, resume: bool) -> Result<HandshakeTrace> {
+            if !resume {
+                self.states.retain(|s| s.protocol != protocol || s.auth != auth);
+            }
+            let state = self.state(protocol, auth)?;
+
+            let mut client_ssl = Ssl::new(&state.client)?;
+            let mut server_ssl = Ssl::new(&state.server)?;
+            if protocol.is_datagram() {
+                client_ssl.set_mtu(DTLS_MTU)?;
+                server_ssl.set_mtu(DTLS_MTU)?;
+            }
+            if let (true, Some(session)) = (resume, &state.session) {
+                // SAFETY: the session was produced by a connection from this same client context
+                unsafe { client_ssl.set_session(session)? };
+            }
+
+            let start = Instant::now();
+            let mut client = OpensslEndpoint::start(client_ssl, true)?;
+            let mut server = OpensslEndpoint::start(server_ssl, false)?;
+            let (flights, handshake_rtts) = drive(&mut client, &mut server)?;
+            let compute_time_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+            let (resumed, session) = match &client.state {
+                EndpointState::Established(stream) => {
+                    (stream.ssl().session_reused(), stream.ssl().session().map(|s| s.to_owned()))
+                }
+                _ => (false, None),
+            };
+            if !resume {
+                state.session = session;
+            }
+
+            Ok(HandshakeTrace { flights, handshake_rtts, compute_time_ms, resumed })
+        }
+    }
+
+    /// Packet queues shared between an SSL object and the pump; one read
+    /// returns at most one packet so DTLS keeps its datagram boundaries.
+    #[derive(Clone, Default)]
+    struct MemoryStream {
+        inbox: Rc<RefCell<VecDeque<Vec<u8>>>>,
+        outbox: Rc<RefCell<Vec<Vec<u8>>>>,
+    }
+
+    impl Read for MemoryStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut inbox = self.inbox.borrow_mut();
+            let Some(mut packet) = inbox.pop_front() else {
+                return Err(io::ErrorKind::WouldBlock.into());
+            };
+            let len = packet.len().min(buf.len());
+            buf[..len].copy_from_slice(&packet[..len]);
+            if len < packet.len() {
+                packet.drain(..len);
+                inbox.push_front(packet);
+            }
+            Ok(len)
+        }
+    }
+
+    impl Write for MemoryStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.outbox.borrow_mut().push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    enum EndpointState {
+        Handshaking(MidHandshakeSslStream<MemoryStream>),
+        Established(SslStream<MemoryStream>),
+        Failed,
+    }
+
+    struct OpensslEndpoint {
+        stream: MemoryStream,
+        state: EndpointState,
+    }
+
+    impl OpensslEndpoint {
+        fn start(ssl: Ssl, is_client: bool) -> Result<Self> {
+            let stream = MemoryStream::default();
+            let outcome = if is_client { ssl.connect(stream.clone()) } else { ssl.accept(stream.clone()) };
+            Ok(Self { stream, state: Self::settle(outcome)? })
+        }
+
+        fn settle(outcome: Result<SslStream<MemoryStream>, HandshakeError<MemoryStream>>) -> Result<EndpointState> {
+            match outcome {
+                Ok(stream) => Ok(EndpointState::Established(stream)),
+                Err(HandshakeError::WouldBlock(mid)) => Ok(EndpointState::Handshaking(mid)),
+                Err(HandshakeError::Failure(mid)) => Err(anyhow!("handshake failed: {}", mid.error())),
+                Err(HandshakeError::SetupFailure(e)) => Err(e.into()),
+            }
+        }
+    }
+
+    impl Endpoint for OpensslEndpoint {
+        fn take_output(&mut self) -> Result<Vec<Vec<u8>>> {
+            Ok(std::mem::take(&mut *self.stream.outbox.borrow_mut()))
+        }
+
+        fn give_input(&mut self, packets: &[Vec<u8>]) -> Result<()> {
+            self.stream.inbox.borrow_mut().extend(packets.iter().cloned());
+
+            self.state = match std::mem::replace(&mut self.state, EndpointState::Failed) {
+                EndpointState::Handshaking(mid) => Self::settle(mid.handshake())?,
+                EndpointState::Established(mut stream) => {
+                    // Post-handshake messages (TLS 1.3 session tickets) are consumed by a read
+                    let mut scratch = [0u8; 1];
+                    match stream.ssl_read(&mut scratch) {
+                        Ok(_) => {}
+                        Err(e) if e.code() == ErrorCode::WANT_READ => {}
+                        Err(e) => return Err(anyhow!("post-handshake read failed: {}", e)),
+                    }
+                    EndpointState::Established(stream)
+                }
+                EndpointState::Failed => return Err(anyhow!("endpoint already failed")),
+            };
+            Ok(())
+        }
+
+        fn is_handshaking(&self) -> bool {
+            !matches!(self.state, EndpointState::Established(_))
+        }
+    }
+}
@@ -0,0 +1,387 @@
+// matter-project/src/home_assistant.rs
+/*!
+Home Assistant in-situ measurement - actuation latency as a real smart-home controller sees it
+
+Every other comparison drives a protocol with this crate's own client. Here
+the devices are driven through a running Home Assistant instance instead,
+the way its dashboards and automations drive them: over the WebSocket API
+at `/api/websocket`, authenticated with a long-lived access token.
+
+For each entity the integration behind it is read from the entity registry
+(`matter`, `zha`, `mqtt`, `zwave_js`, ...), so devices on different
+protocols can be compared inside the same controller. The entity is then
+toggled `commands` times, alternating on and off, with `interval` between
+commands. Each command is a `call_service` (`turn_on` or `turn_off`) and two
+times are taken from its send:
+
+- service: until Home Assistant answers the call. Most integrations answer
+  once the command has left for the device, some only when the device has
+  acknowledged it.
+- state: until the `state_changed` event that puts the entity in the
+  requested state. Integrations that update their state optimistically
+  report it straight away; the others wait for the device's report.
+
+A command that sees no matching state within `timeout` counts as failed.
+The entity is left in the state it had before the run. Only plain
+`http://` is supported; a TLS-terminating proxy needs the instance's local
+port instead.
+*/
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::{timeout_at, Instant};
+
+use crate::builder::analyzer_builder;
+use crate::coap_bindings::{
+    read_websocket_frame_of, websocket_frame_of, websocket_upgrade, WEBSOCKET_CLOSE, WEBSOCKET_PING, WEBSOCKET_PONG,
+    WEBSOCKET_TEXT,
+};
+
+const WEBSOCKET_PATH: &str = "/api/websocket";
+const DEFAULT_PORT: u16 = 8123;
+/// Domains whose entities are switched with `turn_on` and `turn_off`.
+const SWITCHABLE_DOMAINS: &[&str] = &["light", "switch", "fan", "input_boolean"];
+
+#[derive(Debug, Clone)]
+pub struct HomeAssistantConfig {
+    /// Base URL of the instance, `http://host[:port]`.
+    pub url: String,
+    /// Long-lived access token, created under the user's profile in Home Assistant.
+    pub token: Option<String>,
+    /// Entity IDs to toggle, e.g. `light.kitchen`.
+    pub entities: Vec<String>,
+    /// Commands per entity.
+    pub commands: usize,
+    /// Pause between commands, so devices and their networks settle.
+    pub interval: Duration,
+    /// How long a command may take to show in the entity's state.
+    pub timeout: Duration,
+}
+
+impl Default for HomeAssistantConfig {
+    fn default() -> Self {
+        Self {
+            url: format!("http://homeassistant.local:{}", DEFAULT_PORT),
+            token: None,
+            entities: Vec::new(),
+            commands: 20,
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+analyzer_builder!(HomeAssistantAnalyzerBuilder => HomeAssistantAnalyzer(HomeAssistantConfig) {
+    url: String,
+    token: Option<String>,
+    entities: Vec<String>,
+    commands: usize,
+    interval: Duration,
+    timeout: Duration,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HomeAssistantMetrics {
+    pub url: String,
+    pub ha_version: String,
+    pub entities: Vec<EntityRun>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EntityRun {
+    pub entity_id: String,
+    /// The integration providing the entity; `None` for entities outside the registry.
+    pub platform: Option<String>,
+    pub commands: usize,
+    pub failed: usize,
+    /// Send to Home Assistant's answer to the service call.
+    pub service_p50_ms: f64,
+    pub service_p95_ms: f64,
+    /// Send to the entity reporting the requested state.
+    pub state_mean_ms: f64,
+    pub state_p50_ms: f64,
+    pub state_p95_ms: f64,
+    pub state_p99_ms: f64,
+}
+
+pub struct HomeAssistantAnalyzer {
+    config: HomeAssistantConfig,
+}
+
+impl HomeAssistantAnalyzer {
+    pub fn new(config: HomeAssistantConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_home_assistant(&mut self) -> Result<HomeAssistantMetrics> {
+        println!("\n🏠 Analyzing Actuation Through Home Assistant ({}, {} commands per entity)",
+                 self.config.url, self.config.commands);
+        println!("------------------------------------------------");
+
+        if self.config.entities.is_empty() {
+            return Err(anyhow!("no entities to drive; list them with --ha-entities"));
+        }
+        let token = self.config.token.as_deref().ok_or_else(|| anyhow!("no access token; pass one with --ha-token"))?;
+        let mut session = Session::connect(&self.config.url, token).await?;
+        println!("✅ Connected to Home Assistant {}", session.version);
+        session.request(json!({ "type": "subscribe_events", "event_type": "state_changed" })).await?;
+        let states = session.request(json!({ "type": "get_states" })).await?;
+
+        let mut entities = Vec::new();
+        for entity_id in &self.config.entities {
+            let run = self.drive(&mut session, entity_id, &states).await?;
+            println!("✅ {} ({}): state p50 {:.1}ms, p95 {:.1}ms, service p50 {:.1}ms, {} of {} failed",
+                     run.entity_id, run.platform.as_deref().unwrap_or("unregistered"), run.state_p50_ms,
+                     run.state_p95_ms, run.service_p50_ms, run.failed, run.commands);
+            entities.push(run);
+        }
+
+        Ok(HomeAssistantMetrics { url: self.config.url.clone(), ha_version: session.version.clone(), entities })
+    }
+
+    async fn drive(&self, session: &mut Session, entity_id: &str, states: &Value) -> Result<EntityRun> {
+        let domain = entity_id.split_once('.').map(|(domain, _)| domain).unwrap_or_default();
+        if !SWITCHABLE_DOMAINS.contains(&domain) {
+            return Err(anyhow!("{}: only {} entities can be toggled", entity_id, SWITCHABLE_DOMAINS.join(", ")));
+        }
+        let initial = states
+            .as_array()
+            .and_then(|states| states.iter().find(|state| state["entity_id"] == entity_id))
+            .and_then(|state| state["state"].as_str())
+            .ok_or_else(|| anyhow!("{} is not known to Home Assistant", entity_id))?
+            .to_string();
+        let platform = session
+            .request(json!({ "type": "config/entity_registry/get", "entity_id": entity_id }))
+            .await
+            .ok()
+            .and_then(|entry| entry["platform"].as_str().map(str::to_string));
+
+        let commands = self.config.commands.max(1);
+        let (mut service, mut state) = (Vec::with_capacity(commands), Vec::with_capacity(commands));
+        let mut current = initial.clone();
+        for _ in 0..commands {
+            let target = if current == "on" { "off" } else { "on" };
+            match session.switch(domain, entity_id, target, self.config.timeout).await? {
+                Some((answered, reached)) => {
+                    service.push(answered.as_secs_f64() * 1000.0);
+                    state.push(reached.as_secs_f64() * 1000.0);
+                    current = target.to_string();
+                }
+                None => log::warn!("⚠️ {} did not turn {} within {:?}", entity_id, target, self.config.timeout),
+            }
+            tokio::time::sleep(self.config.interval).await;
+        }
+        if current != initial && (initial == "on" || initial == "off") {
+            session.switch(domain, entity_id, &initial, self.config.timeout).await?;
+        }
+
+        let mean = state.iter().sum::<f64>() / state.len().max(1) as f64;
+        service.sort_by(f64::total_cmp);
+        state.sort_by(f64::total_cmp);
+        Ok(EntityRun {
+            entity_id: entity_id.to_string(),
+            platform,
+            commands,
+            failed: commands - state.len(),
+            service_p50_ms: percentile(&service, 50.0),
+            service_p95_ms: percentile(&service, 95.0),
+            state_mean_ms: mean,
+            state_p50_ms: percentile(&state, 50.0),
+            state_p95_ms: percentile(&state, 95.0),
+            state_p99_ms: percentile(&state, 99.0),
+        })
+    }
+}
+
+/// An authenticated WebSocket API connection.
+struct Session {
+    stream: BufReader<TcpStream>,
+    version: String,
+    next_id: u64,
+}
+
+impl Session {
+    async fn connect(url: &str, token: &str) -> Result<Self> {
+        let authority = url
+            .strip_prefix("http://")
+            .ok_or_else(|| anyhow!("{}: only http:// Home Assistant URLs are supported", url))?
+            .trim_end_matches('/');
+        let authority = if authority.contains(':') { authority.to_string() } else { format!("{}:{}", authority, DEFAULT_PORT) };
+        let server = tokio::net::lookup_host(&authority)
+            .await
+            .with_context(|| format!("resolving {}", authority))?
+            .next()
+            .ok_or_else(|| anyhow!("{} did not resolve", authority))?;
+        let stream = TcpStream::connect(server).await.with_context(|| format!("connecting to {}", server))?;
+        stream.set_nodelay(true)?;
+        let mut session = Self { stream: BufReader::new(stream), version: String::new(), next_id: 1 };
+        websocket_upgrade(&mut session.stream, server, WEBSOCKET_PATH, None).await?;
+
+        let hello = session.receive().await?;
+        if hello["type"] != "auth_required" {
+            return Err(anyhow!("expected auth_required, got {}", hello));
+        }
+        session.send(&json!({ "type": "auth", "access_token": token })).await?;
+        let answer = session.receive().await?;
+        if answer["type"] != "auth_ok" {
+            return Err(anyhow!("Home Assistant refused the token: {}", answer["message"]));
+        }
+        session.version = answer["ha_version"].as_str().unwrap_or("unknown").to_string();
+        Ok(session)
+    }
+
+    async fn send(&mut self, message: &Value) -> Result<()> {
+        let frame = websocket_frame_of(WEBSOCKET_TEXT, message.to_string().as_bytes(), true);
+        self.stream.get_mut().write_all(&frame).await?;
+        Ok(())
+    }
+
+    /// The next message, answering pings on the way.
+    async fn receive(&mut self) -> Result<Value> {
+        loop {
+            match read_websocket_frame_of(&mut self.stream).await? {
+                (_, WEBSOCKET_TEXT, payload) => return Ok(serde_json::from_slice(&payload)?),
+                (_, WEBSOCKET_PING, payload) => {
+                    let pong = websocket_frame_of(WEBSOCKET_PONG, &payload, true);
+                    self.stream.get_mut().write_all(&pong).await?;
+                }
+                (_, WEBSOCKET_CLOSE, _) => return Err(anyhow!("Home Assistant closed the connection")),
+                (_, opcode, _) => return Err(anyhow!("unexpected WebSocket frame {:#04x}", opcode)),
+            }
+        }
+    }
+
+    /// Sends a command with the next ID and returns its successful result;
+    /// events arriving in between are dropped.
+    async fn request(&mut self, mut command: Value) -> Result<Value> {
+        let id = self.next_id;
+        self.next_id += 1;
+        command["id"] = json!(id);
+        self.send(&command).await?;
+        loop {
+            let message = self.receive().await?;
+            if message["type"] == "result" && message["id"] == id {
+                if message["success"] != true {
+                    return Err(anyhow!("{} failed: {}", command["type"], message["error"]));
+                }
+                return Ok(message["result"].clone());
+            }
+        }
+    }
+
+    /// Turns `entity_id` to `target` and returns the time to the service call's
+    /// answer and to the matching state, or `None` if the state did not follow in time.
+    async fn switch(&mut self, domain: &str, entity_id: &str, target: &str, timeout: Duration) -> Result<Option<(Duration, Duration)>> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let command = json!({
+            "id": id,
+            "type": "call_service",
+            "domain": domain,
+            "service": format!("turn_{}", target),
+            "target": { "entity_id": entity_id },
+        });
+        let start = Instant::now();
+        self.send(&command).await?;
+
+        let deadline = start + timeout;
+        let (mut answered, mut reached) = (None, None);
+        while answered.is_none() || reached.is_none() {
+            let Ok(message) = timeout_at(deadline, self.receive()).await else {
+                return Ok(None);
+            };
+            let message = message?;
+            match message["type"].as_str() {
+                Some("result") if message["id"] == id => {
+                    if message["success"] != true {
+                        log::warn!("⚠️ turn_{} on {} failed: {}", target, entity_id, message["error"]);
+                        return Ok(None);
+                    }
+                    answered = Some(start.elapsed());
+                }
+                Some("event") => {
+                    let data = &message["event"]["data"];
+                    if data["entity_id"] == entity_id && data["new_state"]["state"] == target && reached.is_none() {
+                        reached = Some(start.elapsed());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(answered.zip(reached))
+    }
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((p / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coap_bindings::accept_websocket_upgrade;
+    use tokio::net::TcpListener;
+
+    /// Answers like Home Assistant with one Matter light whose state follows 5 ms after each call.
+    async fn instance(listener: TcpListener) -> Result<()> {
+        let (stream, _) = listener.accept().await?;
+        let mut stream = BufReader::new(stream);
+        accept_websocket_upgrade(&mut stream, None).await?;
+        let send = |message: Value| websocket_frame_of(WEBSOCKET_TEXT, message.to_string().as_bytes(), false);
+        stream.get_mut().write_all(&send(json!({ "type": "auth_required", "ha_version": "2024.10.0" }))).await?;
+        let mut state = "off";
+        loop {
+            let (_, _, payload) = read_websocket_frame_of(&mut stream).await?;
+            let message: Value = serde_json::from_slice(&payload)?;
+            let id = message["id"].clone();
+            let replies = match message["type"].as_str().unwrap_or_default() {
+                "auth" => vec![json!({ "type": "auth_ok", "ha_version": "2024.10.0" })],
+                "get_states" => vec![json!({ "id": id, "type": "result", "success": true,
+                                             "result": [{ "entity_id": "light.desk", "state": state }] })],
+                "config/entity_registry/get" => vec![json!({ "id": id, "type": "result", "success": true,
+                                                             "result": { "platform": "matter" } })],
+                "call_service" => {
+                    stream.get_mut().write_all(&send(json!({ "id": id, "type": "result", "success": true }))).await?;
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    state = if message["service"] == "turn_on" { "on" } else { "off" };
+                    vec![json!({ "type": "event", "event": { "event_type": "state_changed", "data": {
+                        "entity_id": "light.desk", "new_state": { "state": state } } } })]
+                }
+                _ => vec![json!({ "id": id, "type": "result", "success": true, "result": null })],
+            };
+            for reply in replies {
+                stream.get_mut().write_all(&send(reply)).await?;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn toggles_an_entity_and_times_its_state() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(instance(listener));
+
+        let metrics = HomeAssistantAnalyzer::builder()
+            .url(url)
+            .token(Some("token".to_string()))
+            .entities(vec!["light.desk".to_string()])
+            .commands(5usize)
+            .interval(Duration::ZERO)
+            .build()
+            .analyze_home_assistant()
+            .await
+            .unwrap();
+        assert_eq!(metrics.ha_version, "2024.10.0");
+        let run = &metrics.entities[0];
+        assert_eq!((run.platform.as_deref(), run.commands, run.failed), (Some("matter"), 5, 0));
+        assert!(run.state_p50_ms >= 5.0 && run.state_p50_ms > run.service_p50_ms, "{:?}", run);
+    }
+}
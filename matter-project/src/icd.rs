@@ -0,0 +1,1058 @@
+// matter-project/src/icd.rs
+/*!
+Sleepy end devices - command delivery latency and retry overhead when the device only wakes to poll
+*/
+
+use anyhow::Result;
+use metrics_core::tlv::{Tag, TlvError, TlvWriter};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::time::Duration;
+
+use crate::bdx::{matter_message, MRP_STANDALONE_ACK, PROTOCOL_SECURE_CHANNEL};
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{
+    coap_message, mqtt_packet, mqtt_publish, mqtt_string, tlv, uri_path, COAP_ACK, COAP_CHANGED, COAP_CON, COAP_POST,
+    IM_INVOKE_REQUEST, IM_INVOKE_RESPONSE, MQTT_CONNACK, MQTT_CONNECT, MQTT_DISCONNECT, MQTT_PUBACK, PROTOCOL_IM,
+};
+use crate::handshake::next_unit;
+use crate::lowpan::{lowpan_frames, LowpanPath, LowpanTransport};
+use crate::mdns::MATTER_PORT;
+use crate::transport_modes::{
+    IPV4_TCP_HEADER_BYTES, IPV4_UDP_HEADER_BYTES, MRP_BACKOFF_BASE, MRP_MAX_TRANSMISSIONS, TCP_HANDSHAKE_BYTES,
+    TCP_TEARDOWN_BYTES,
+};
+
+// MRP towards an idle peer: the ICD's advertised idle interval times the backoff margin
+const MRP_BACKOFF_MARGIN: f64 = 1.1;
+const MRP_BACKOFF_THRESHOLD: i32 = 1;
+
+// RFC 7252 transmission parameters
+const COAP_ACK_TIMEOUT_MS: f64 = 2000.0;
+const COAP_ACK_RANDOM_FACTOR: f64 = 1.5;
+const COAP_MAX_RETRANSMIT: u32 = 4;
+
+/// Queued LwM2M commands are given up after this many wake-ups without delivery.
+const QUEUE_MODE_MAX_WAKES: u32 = 5;
+
+// Initial TCP retransmission timeout (RFC 6298)
+const TCP_INITIAL_RTO_MS: f64 = 1000.0;
+
+/// 802.15.4 MAC Data Request command (long source address) and its immediate ack.
+const THREAD_DATA_POLL_BYTES: usize = 18 + 5;
+
+/// Wake periods over which the offline-window scenario issues its commands.
+const OFFLINE_WINDOWS: usize = 20;
+/// Thread only guarantees a parent buffers one IPv6 datagram per sleepy child.
+const THREAD_PARENT_BUFFERED_DATAGRAMS: usize = 1;
+/// A further poll, its ack and a buffered frame at 250 kbit/s, after the frame-pending bit.
+const THREAD_INDIRECT_FRAME_MS: f64 = 8.0;
+/// QoS 1 messages a broker sends before waiting for PUBACKs (Mosquitto's default).
+const MQTT_MAX_INFLIGHT: usize = 20;
+
+// OnOff cluster Toggle
+const ON_OFF_CLUSTER: u32 = 0x0006;
+const TOGGLE: u32 = 0x02;
+
+#[derive(Debug, Clone)]
+pub struct IcdConfig {
+    /// How often the sleepy device wakes up to poll for downlink traffic.
+    pub poll_intervals: Vec<Duration>,
+    /// How long the device stays reachable after each wake-up.
+    pub awake_window: Duration,
+    pub link_delay: Duration,
+    /// Fraction of messages lost in either direction.
+    pub loss_rate: f64,
+    /// Commands issued per poll interval, at random points of the sleep cycle.
+    pub commands: usize,
+    /// Registration Update period of the LwM2M client in queue mode, and the
+    /// wake-up period of every protocol in the offline-window scenario.
+    pub update_interval: Duration,
+    /// Commands issued per update interval in the offline-window scenario; one run each.
+    pub commands_per_window: Vec<usize>,
+    /// Requests the LwM2M server and the MQTT broker hold per device; more are refused.
+    pub queue_capacity: usize,
+}
+
+impl Default for IcdConfig {
+    fn default() -> Self {
+        Self {
+            poll_intervals: vec![
+                Duration::from_secs(1),
+                Duration::from_secs(5),
+                Duration::from_secs(30),
+                Duration::from_secs(300),
+            ],
+            awake_window: Duration::from_millis(500),
+            link_delay: Duration::from_millis(20),
+            loss_rate: 0.02,
+            commands: 1000,
+            update_interval: Duration::from_secs(60),
+            commands_per_window: vec![1, 4, 16, 64],
+            queue_capacity: 32,
+        }
+    }
+}
+
+analyzer_builder!(IcdAnalyzerBuilder => IcdAnalyzer(IcdConfig) {
+    poll_intervals: Vec<Duration>,
+    awake_window: Duration,
+    link_delay: Duration,
+    loss_rate: f64,
+    commands: usize,
+    update_interval: Duration,
+    commands_per_window: Vec<usize>,
+    queue_capacity: usize,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IcdMetrics {
+    pub awake_window_ms: f64,
+    pub link_delay_ms: f64,
+    pub loss_rate: f64,
+    pub protocols: Vec<IcdProtocolResult>,
+    pub update_interval_ms: f64,
+    /// Commands arriving while the device is offline, held until it next wakes.
+    pub offline_windows: Vec<OfflineWindowRun>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IcdProtocolResult {
+    pub protocol: String,
+    /// What holds downlink traffic while the device sleeps.
+    pub sleep_handling: String,
+    pub runs: Vec<IcdRun>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IcdRun {
+    pub poll_interval_ms: f64,
+    pub commands: usize,
+    pub delivered: usize,
+    pub failed: usize,
+    /// Command issue to arrival at the device, over delivered commands.
+    pub latency_mean_ms: f64,
+    pub latency_p95_ms: f64,
+    pub latency_max_ms: f64,
+    /// Command and response transmissions (including retries) per command.
+    pub transmissions_per_command: f64,
+    pub retransmissions_per_command: f64,
+    /// Bytes of those transmissions per command, with IPv4 and UDP/TCP headers.
+    pub command_bytes_per_command: f64,
+    /// Traffic the device spends each hour only to stay reachable (polls, updates, reconnects).
+    pub reachability_bytes_per_hour: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OfflineWindowRun {
+    pub protocol: String,
+    /// What holds commands while the device is offline.
+    pub holder: String,
+    /// Commands the holder takes per device.
+    pub holder_capacity: usize,
+    pub commands_per_window: usize,
+    pub commands: usize,
+    pub delivered: usize,
+    /// Refused by a full holder or given up on.
+    pub dropped: usize,
+    /// Command issue to arrival at the device, over delivered commands.
+    pub latency_mean_ms: f64,
+    pub latency_p95_ms: f64,
+    pub latency_max_ms: f64,
+    /// Most commands held at once.
+    pub max_queue_depth: usize,
+    /// Delivered commands that missed the first wake-up after they were issued.
+    pub carried_over: usize,
+    /// Command and response bytes per command, with IPv4 and UDP/TCP headers.
+    pub bytes_per_command: f64,
+}
+
+pub struct IcdAnalyzer {
+    config: IcdConfig,
+}
+
+impl IcdAnalyzer {
+    pub fn new(config: IcdConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_icd(&mut self) -> Result<IcdMetrics> {
+        println!("\n😴 Analyzing Sleepy Device Command Delivery ({} commands per poll interval)", self.config.commands);
+        println!("------------------------------------------------------------------------");
+
+        let protocols: Vec<Box<dyn SleepyProtocol>> = vec![
+            Box::new(MatterIcd::new()?),
+            Box::new(CoapDirect::new()),
+            Box::new(Lwm2mQueueMode::new()),
+            Box::new(MqttPersistentSession::new()),
+        ];
+
+        let mut results = Vec::new();
+        for protocol in &protocols {
+            let mut runs = Vec::new();
+            for &poll_interval in &self.config.poll_intervals {
+                if poll_interval.is_zero() {
+                    continue;
+                }
+                let run = self.simulate(protocol.as_ref(), poll_interval);
+                println!("✅ {} @ {:.0}s poll: {}/{} delivered, mean {:.0}ms, p95 {:.0}ms, {:.2} retries/cmd, {:.0}B/h idle",
+                         protocol.name(), run.poll_interval_ms / 1000.0, run.delivered, run.commands,
+                         run.latency_mean_ms, run.latency_p95_ms, run.retransmissions_per_command,
+                         run.reachability_bytes_per_hour);
+                runs.push(run);
+            }
+            results.push(IcdProtocolResult {
+                protocol: protocol.name().to_string(),
+                sleep_handling: protocol.sleep_handling().to_string(),
+                runs,
+            });
+        }
+
+        let matter = MatterIcd::new()?;
+        let (lwm2m, mqtt) = (Lwm2mQueueMode::new(), MqttPersistentSession::new());
+        let mut offline_windows = Vec::new();
+        for &per_window in &self.config.commands_per_window {
+            let (schedule, mut link) = self.offline_setup();
+            let mut run = OfflineRun::new(per_window, self.config.queue_capacity, &schedule, &mut link);
+            lwm2m.offline(&mut run, &schedule, &mut link);
+            offline_windows.push(run.summarize(lwm2m.name(), "LwM2M server queue", per_window));
+
+            let (schedule, mut link) = self.offline_setup();
+            let mut run = OfflineRun::new(per_window, THREAD_PARENT_BUFFERED_DATAGRAMS, &schedule, &mut link);
+            matter.offline(&mut run, &schedule, &mut link);
+            offline_windows.push(run.summarize(matter.name(), "Thread parent indirect buffer", per_window));
+
+            let (schedule, mut link) = self.offline_setup();
+            let mut run = OfflineRun::new(per_window, self.config.queue_capacity, &schedule, &mut link);
+            mqtt.offline(&mut run, &schedule, &mut link);
+            offline_windows.push(run.summarize(mqtt.name(), "MQTT broker session queue", per_window));
+        }
+        for run in &offline_windows {
+            println!("✅ {} @ {} cmds/{:.0}s window: {}/{} delivered ({} dropped), mean {:.0}ms, p95 {:.0}ms, queue depth {} of {}, {} carried over",
+                     run.protocol, run.commands_per_window, self.config.update_interval.as_secs_f64(), run.delivered,
+                     run.commands, run.dropped, run.latency_mean_ms, run.latency_p95_ms, run.max_queue_depth,
+                     run.holder_capacity, run.carried_over);
+        }
+
+        Ok(IcdMetrics {
+            awake_window_ms: self.config.awake_window.as_secs_f64() * 1000.0,
+            link_delay_ms: self.config.link_delay.as_secs_f64() * 1000.0,
+            loss_rate: self.config.loss_rate,
+            protocols: results,
+            update_interval_ms: self.config.update_interval.as_secs_f64() * 1000.0,
+            offline_windows,
+        })
+    }
+
+    /// Every protocol sees the same wake-ups, losses and command times.
+    fn offline_setup(&self) -> (SleepSchedule, Link) {
+        let schedule = SleepSchedule {
+            poll_ms: self.config.update_interval.as_secs_f64().max(0.001) * 1000.0,
+            awake_ms: self.config.awake_window.as_secs_f64() * 1000.0,
+        };
+        let link = Link {
+            delay_ms: self.config.link_delay.as_secs_f64() * 1000.0,
+            loss_rate: self.config.loss_rate,
+            rng_state: 0x2545_F491_4F6C_DD1Du64,
+        };
+        (schedule, link)
+    }
+
+    fn simulate(&self, protocol: &dyn SleepyProtocol, poll_interval: Duration) -> IcdRun {
+        let schedule = SleepSchedule {
+            poll_ms: poll_interval.as_secs_f64() * 1000.0,
+            awake_ms: self.config.awake_window.as_secs_f64() * 1000.0,
+        };
+        // Fixed seed so every run is repeatable
+        let mut link = Link {
+            delay_ms: self.config.link_delay.as_secs_f64() * 1000.0,
+            loss_rate: self.config.loss_rate,
+            rng_state: 0x2545_F491_4F6C_DD1Du64,
+        };
+
+        let commands = self.config.commands.max(1);
+        let mut latencies = Vec::with_capacity(commands);
+        let mut transmissions = 0;
+        let mut retransmissions = 0;
+        let mut bytes = 0;
+        for _ in 0..commands {
+            // Issued somewhere in the second sleep cycle, so there is always a previous wake-up
+            let issued_at = schedule.poll_ms * (1.0 + next_unit(&mut link.rng_state));
+            let delivery = protocol.deliver(issued_at, &schedule, &mut link);
+            if let Some(delivered_at) = delivery.delivered_at {
+                latencies.push(delivered_at - issued_at);
+            }
+            transmissions += delivery.transmissions;
+            retransmissions += delivery.retransmissions;
+            bytes += delivery.bytes;
+        }
+        latencies.sort_by(|a, b| a.total_cmp(b));
+
+        let per_command = |total: usize| total as f64 / commands as f64;
+        IcdRun {
+            poll_interval_ms: schedule.poll_ms,
+            commands,
+            delivered: latencies.len(),
+            failed: commands - latencies.len(),
+            latency_mean_ms: latencies.iter().sum::<f64>() / latencies.len().max(1) as f64,
+            latency_p95_ms: latencies
+                .get(((latencies.len().max(1) - 1) as f64 * 0.95).round() as usize)
+                .copied()
+                .unwrap_or(0.0),
+            latency_max_ms: latencies.last().copied().unwrap_or(0.0),
+            transmissions_per_command: per_command(transmissions as usize),
+            retransmissions_per_command: per_command(retransmissions as usize),
+            command_bytes_per_command: per_command(bytes),
+            reachability_bytes_per_hour: protocol.reachability_bytes_per_wake() as f64 * 3_600_000.0 / schedule.poll_ms,
+        }
+    }
+}
+
+/// The device wakes every `poll_ms` and is reachable for `awake_ms` afterwards.
+struct SleepSchedule {
+    poll_ms: f64,
+    awake_ms: f64,
+}
+
+impl SleepSchedule {
+    fn awake(&self, at: f64) -> bool {
+        at.rem_euclid(self.poll_ms) < self.awake_ms
+    }
+
+    /// `at` itself while awake, otherwise the next wake-up.
+    fn reachable_from(&self, at: f64) -> f64 {
+        if self.awake(at) {
+            at
+        } else {
+            self.next_wake(at)
+        }
+    }
+
+    fn next_wake(&self, at: f64) -> f64 {
+        ((at / self.poll_ms).floor() + 1.0) * self.poll_ms
+    }
+}
+
+/// Commands issued at random over [`OFFLINE_WINDOWS`] wake periods, and what
+/// became of them while a holder kept them for the device.
+struct OfflineRun {
+    /// Issue times, in order.
+    issued: Vec<f64>,
+    /// First command not yet issued to the holder.
+    next: usize,
+    capacity: usize,
+    /// Commands waiting, oldest first, with the wake-ups each has missed.
+    held: VecDeque<(usize, u32)>,
+    /// Latency of each delivered command.
+    latencies: Vec<f64>,
+    dropped: usize,
+    carried_over: usize,
+    max_depth: usize,
+    bytes: usize,
+    /// Past this, whatever is still held is given up on.
+    give_up_at: f64,
+}
+
+impl OfflineRun {
+    fn new(per_window: usize, capacity: usize, schedule: &SleepSchedule, link: &mut Link) -> Self {
+        let span = schedule.poll_ms * OFFLINE_WINDOWS as f64;
+        let mut issued: Vec<f64> = (0..per_window * OFFLINE_WINDOWS)
+            .map(|_| schedule.poll_ms * 0.5 + span * next_unit(&mut link.rng_state))
+            .collect();
+        issued.sort_by(|a, b| a.total_cmp(b));
+        Self {
+            issued,
+            next: 0,
+            capacity,
+            held: VecDeque::new(),
+            latencies: Vec::new(),
+            dropped: 0,
+            carried_over: 0,
+            max_depth: 0,
+            bytes: 0,
+            give_up_at: schedule.poll_ms * (OFFLINE_WINDOWS as u32 + QUEUE_MODE_MAX_WAKES + 1) as f64,
+        }
+    }
+
+    /// Hands the holder every command issued up to `at`; a full holder refuses the rest.
+    fn admit(&mut self, at: f64) {
+        while self.issued.get(self.next).is_some_and(|&issued| issued <= at) {
+            if self.held.len() < self.capacity {
+                self.held.push_back((self.next, 0));
+                self.max_depth = self.max_depth.max(self.held.len());
+            } else {
+                self.dropped += 1;
+            }
+            self.next += 1;
+        }
+    }
+
+    fn next_held(&mut self) -> Option<usize> {
+        self.held.pop_front().map(|(command, _)| command)
+    }
+
+    fn next_issue(&self) -> Option<f64> {
+        self.issued.get(self.next).copied()
+    }
+
+    /// Back to the head of the queue, for the next wake-up.
+    fn requeue(&mut self, command: usize) {
+        self.held.push_front((command, 0));
+    }
+
+    /// A wake-up went by: commands still held after [`QUEUE_MODE_MAX_WAKES`] are given up.
+    fn missed_window(&mut self) {
+        let before = self.held.len();
+        self.held.retain_mut(|(_, missed)| {
+            *missed += 1;
+            *missed < QUEUE_MODE_MAX_WAKES
+        });
+        self.dropped += before - self.held.len();
+    }
+
+    fn deliver(&mut self, command: usize, at: f64, schedule: &SleepSchedule) {
+        let issued = self.issued[command];
+        if at >= schedule.next_wake(issued) + schedule.poll_ms {
+            self.carried_over += 1;
+        }
+        self.latencies.push(at - issued);
+    }
+
+    /// Whether the run is over by the wake-up at `wake`.
+    fn finished(&mut self, wake: f64, schedule: &SleepSchedule) -> bool {
+        if wake > self.give_up_at {
+            self.dropped += self.held.len() + self.issued.len() - self.next;
+            self.held.clear();
+            self.next = self.issued.len();
+        }
+        self.held.is_empty() && self.next == self.issued.len() && wake > schedule.poll_ms
+    }
+
+    fn summarize(mut self, protocol: &str, holder: &str, per_window: usize) -> OfflineWindowRun {
+        self.latencies.sort_by(|a, b| a.total_cmp(b));
+        let commands = self.issued.len();
+        OfflineWindowRun {
+            protocol: protocol.to_string(),
+            holder: holder.to_string(),
+            holder_capacity: self.capacity,
+            commands_per_window: per_window,
+            commands,
+            delivered: self.latencies.len(),
+            dropped: self.dropped,
+            latency_mean_ms: self.latencies.iter().sum::<f64>() / self.latencies.len().max(1) as f64,
+            latency_p95_ms: self
+                .latencies
+                .get(((self.latencies.len().max(1) - 1) as f64 * 0.95).round() as usize)
+                .copied()
+                .unwrap_or(0.0),
+            latency_max_ms: self.latencies.last().copied().unwrap_or(0.0),
+            max_queue_depth: self.max_depth,
+            carried_over: self.carried_over,
+            bytes_per_command: self.bytes as f64 / commands.max(1) as f64,
+        }
+    }
+}
+
+/// One-way delay and deterministic loss between the device and its peer.
+struct Link {
+    delay_ms: f64,
+    loss_rate: f64,
+    rng_state: u64,
+}
+
+impl Link {
+    fn lost(&mut self) -> bool {
+        next_unit(&mut self.rng_state) < self.loss_rate
+    }
+
+    /// Arrival time of a TCP segment sent at `sent_at`, retransmitted with
+    /// exponential RTO backoff until it gets through.
+    fn tcp_segment(&mut self, sent_at: f64, segment_bytes: usize, delivery: &mut Delivery) -> f64 {
+        let mut sent_at = sent_at;
+        let mut rto = TCP_INITIAL_RTO_MS;
+        delivery.transmit(segment_bytes);
+        while self.lost() {
+            sent_at += rto;
+            rto *= 2.0;
+            delivery.retransmit(segment_bytes);
+        }
+        sent_at + self.delay_ms
+    }
+}
+
+#[derive(Default)]
+struct Delivery {
+    delivered_at: Option<f64>,
+    transmissions: u32,
+    retransmissions: u32,
+    bytes: usize,
+}
+
+impl Delivery {
+    fn transmit(&mut self, bytes: usize) {
+        self.transmissions += 1;
+        self.bytes += bytes;
+    }
+
+    fn retransmit(&mut self, bytes: usize) {
+        self.retransmissions += 1;
+        self.transmit(bytes);
+    }
+
+    fn deliver(&mut self, at: f64) {
+        self.delivered_at = Some(self.delivered_at.map_or(at, |first| first.min(at)));
+    }
+}
+
+/// How one protocol gets a command to a device that is mostly asleep.
+trait SleepyProtocol {
+    fn name(&self) -> &'static str;
+    fn sleep_handling(&self) -> &'static str;
+    /// Simulates one command issued at `issued_at` (ms on the schedule's clock).
+    fn deliver(&self, issued_at: f64, schedule: &SleepSchedule, link: &mut Link) -> Delivery;
+    /// Bytes exchanged at every wake-up whether or not a command is pending.
+    fn reachability_bytes_per_wake(&self) -> usize;
+}
+
+/// Confirmable CoAP request with RFC 7252 retransmission; a copy only lands
+/// while `reachable` says the device is listening. Returns when the sender
+/// stops, with the time its ACK arrived if one did.
+fn coap_confirmable(
+    sent_at: f64,
+    reachable: impl Fn(f64) -> bool,
+    request_bytes: usize,
+    ack_bytes: usize,
+    link: &mut Link,
+    delivery: &mut Delivery,
+) -> Option<f64> {
+    let mut sent_at = sent_at;
+    let mut timeout = COAP_ACK_TIMEOUT_MS * (1.0 + next_unit(&mut link.rng_state) * (COAP_ACK_RANDOM_FACTOR - 1.0));
+    for _ in 0..=COAP_MAX_RETRANSMIT {
+        if delivery.transmissions > 0 {
+            delivery.retransmit(request_bytes);
+        } else {
+            delivery.transmit(request_bytes);
+        }
+        let arrival = sent_at + link.delay_ms;
+        if !link.lost() && reachable(arrival) {
+            delivery.deliver(arrival);
+            delivery.transmit(ack_bytes);
+            if !link.lost() {
+                return Some(arrival + link.delay_ms);
+            }
+        }
+        sent_at += timeout;
+        timeout *= 2.0;
+    }
+    None
+}
+
+/// Matter ICD on Thread: the parent buffers frames until the device's next
+/// data poll, and MRP sizes its retransmission timeout from the idle interval
+/// the ICD advertises (its poll interval), so retries are rare.
+struct MatterIcd {
+    invoke_bytes: usize,
+    response_bytes: usize,
+    ack_bytes: usize,
+}
+
+impl MatterIcd {
+    fn new() -> Result<Self> {
+        let command_path = |writer: &mut TlvWriter| -> Result<(), TlvError> {
+            writer.start_list(Tag::Context(0))?;
+            writer.u16(Tag::Context(0), 1)?;
+            writer.u32(Tag::Context(1), ON_OFF_CLUSTER)?;
+            writer.u32(Tag::Context(2), TOGGLE)?;
+            writer.end_container()
+        };
+        let invoke = tlv(|writer| {
+            writer.start_struct(Tag::Anonymous)?;
+            writer.bool(Tag::Context(0), false)?; // SuppressResponse
+            writer.bool(Tag::Context(1), false)?; // TimedRequest
+            writer.start_array(Tag::Context(2))?;
+            writer.start_struct(Tag::Anonymous)?;
+            command_path(writer)?;
+            writer.start_struct(Tag::Context(1))?;
+            writer.end_container()?;
+            writer.end_container()?;
+            writer.end_container()?;
+            writer.u8(Tag::Context(255), 11)?;
+            writer.end_container()
+        })?;
+        let response = tlv(|writer| {
+            writer.start_struct(Tag::Anonymous)?;
+            writer.bool(Tag::Context(0), false)?;
+            writer.start_array(Tag::Context(1))?;
+            writer.start_struct(Tag::Anonymous)?;
+            writer.start_struct(Tag::Context(1))?; // CommandStatusIB
+            command_path(writer)?;
+            writer.start_struct(Tag::Context(1))?;
+            writer.u8(Tag::Context(0), 0)?;
+            writer.end_container()?;
+            writer.end_container()?;
+            writer.end_container()?;
+            writer.end_container()?;
+            writer.u8(Tag::Context(255), 11)?;
+            writer.end_container()
+        })?;
+
+        Ok(Self {
+            invoke_bytes: thread_bytes(matter_message(PROTOCOL_IM, IM_INVOKE_REQUEST, &invoke).len()),
+            response_bytes: thread_bytes(matter_message(PROTOCOL_IM, IM_INVOKE_RESPONSE, &response).len()),
+            ack_bytes: thread_bytes(matter_message(PROTOCOL_SECURE_CHANNEL, MRP_STANDALONE_ACK, &[]).len()),
+        })
+    }
+}
+
+impl MatterIcd {
+    /// The controller sends each command when it is issued. While the device
+    /// sleeps, its Thread parent buffers what fits and drops the rest, and MRP
+    /// retransmits after the idle interval until a copy is buffered or the
+    /// transmissions run out. Transmissions reach the parent in time order, so
+    /// the first datagram of a poll interval takes the buffer slot.
+    fn offline(&self, run: &mut OfflineRun, schedule: &SleepSchedule, link: &mut Link) {
+        let interval = schedule.poll_ms * MRP_BACKOFF_MARGIN;
+        let mut buffered: HashMap<u64, usize> = HashMap::new();
+        let mut delivered_at: Vec<Option<f64>> = vec![None; run.issued.len()];
+        // Non-negative f64 bit patterns sort like the values
+        let mut pending: BinaryHeap<Reverse<(u64, usize, i32)>> =
+            run.issued.iter().enumerate().map(|(command, at)| Reverse((at.to_bits(), command, 0))).collect();
+        while let Some(Reverse((sent_at, command, attempt))) = pending.pop() {
+            let sent_at = f64::from_bits(sent_at);
+            run.bytes += self.invoke_bytes;
+            let at_parent = sent_at + link.delay_ms;
+            let mut acked = false;
+            if !link.lost() {
+                let at_device = if schedule.awake(at_parent) {
+                    Some(at_parent)
+                } else {
+                    let wake = schedule.next_wake(at_parent);
+                    let frames = buffered.entry((wake / schedule.poll_ms).round() as u64).or_default();
+                    (*frames < THREAD_PARENT_BUFFERED_DATAGRAMS).then(|| {
+                        *frames += 1;
+                        run.max_depth = run.max_depth.max(*frames);
+                        // Each further frame waits for one more poll with the frame-pending bit set
+                        wake + *frames as f64 * THREAD_INDIRECT_FRAME_MS
+                    })
+                };
+                if let Some(at_device) = at_device {
+                    if delivered_at[command].is_none() {
+                        delivered_at[command] = Some(at_device);
+                        run.bytes += self.response_bytes;
+                    } else {
+                        run.bytes += self.ack_bytes;
+                    }
+                    acked = !link.lost();
+                }
+            }
+            if !acked && attempt + 1 < MRP_MAX_TRANSMISSIONS as i32 {
+                let retry = sent_at + interval * MRP_BACKOFF_BASE.powi((attempt - MRP_BACKOFF_THRESHOLD).max(0));
+                pending.push(Reverse((retry.to_bits(), command, attempt + 1)));
+                continue;
+            }
+            match delivered_at[command] {
+                Some(at) => {
+                    run.bytes += self.ack_bytes;
+                    run.deliver(command, at, schedule);
+                }
+                None => run.dropped += 1,
+            }
+        }
+    }
+}
+
+impl SleepyProtocol for MatterIcd {
+    fn name(&self) -> &'static str {
+        "Matter ICD (Thread SED)"
+    }
+
+    fn sleep_handling(&self) -> &'static str {
+        "Thread parent buffers until the next data poll; MRP uses the advertised idle interval"
+    }
+
+    fn deliver(&self, issued_at: f64, schedule: &SleepSchedule, link: &mut Link) -> Delivery {
+        let mut delivery = Delivery::default();
+        let interval = schedule.poll_ms * MRP_BACKOFF_MARGIN;
+        let mut sent_at = issued_at;
+        let mut acked_at = f64::INFINITY;
+
+        for attempt in 0..MRP_MAX_TRANSMISSIONS as i32 {
+            if acked_at <= sent_at {
+                break;
+            }
+            if attempt > 0 {
+                delivery.retransmit(self.invoke_bytes);
+            } else {
+                delivery.transmit(self.invoke_bytes);
+            }
+            if !link.lost() {
+                let at_device = schedule.reachable_from(sent_at + link.delay_ms);
+                // The first copy gets the InvokeResponse, duplicates a standalone ack
+                let first = delivery.delivered_at.is_none();
+                delivery.deliver(at_device);
+                delivery.transmit(if first { self.response_bytes } else { self.ack_bytes });
+                if !link.lost() {
+                    acked_at = acked_at.min(at_device + link.delay_ms);
+                }
+            }
+            sent_at += interval * MRP_BACKOFF_BASE.powi((attempt - MRP_BACKOFF_THRESHOLD).max(0));
+        }
+        if delivery.delivered_at.is_some() {
+            // The controller acknowledges the InvokeResponse
+            delivery.transmit(self.ack_bytes);
+        }
+        delivery
+    }
+
+    fn reachability_bytes_per_wake(&self) -> usize {
+        THREAD_DATA_POLL_BYTES
+    }
+}
+
+/// A Matter message as the controller off the mesh and the ICD exchange it in 6LoWPAN frames.
+fn thread_bytes(message: usize) -> usize {
+    lowpan_frames(LowpanPath::OffMesh, LowpanTransport::udp(MATTER_PORT), message).on_air_bytes
+}
+
+fn light_command() -> Vec<u8> {
+    coap_message(COAP_CON, COAP_POST, 0x2001, &uri_path(&["3311", "0", "5850"]), b"1")
+}
+
+fn changed_ack() -> Vec<u8> {
+    coap_message(COAP_ACK, COAP_CHANGED, 0x2001, &[], &[])
+}
+
+/// Plain CoAP with nothing holding traffic for the sleeping device: the
+/// server's confirmable retransmissions have to hit an awake window.
+struct CoapDirect {
+    request_bytes: usize,
+    ack_bytes: usize,
+}
+
+impl CoapDirect {
+    fn new() -> Self {
+        Self {
+            request_bytes: light_command().len() + IPV4_UDP_HEADER_BYTES,
+            ack_bytes: changed_ack().len() + IPV4_UDP_HEADER_BYTES,
+        }
+    }
+}
+
+impl SleepyProtocol for CoapDirect {
+    fn name(&self) -> &'static str {
+        "CoAP (no queueing)"
+    }
+
+    fn sleep_handling(&self) -> &'static str {
+        "none; CON retransmissions must land in an awake window"
+    }
+
+    fn deliver(&self, issued_at: f64, schedule: &SleepSchedule, link: &mut Link) -> Delivery {
+        let mut delivery = Delivery::default();
+        coap_confirmable(issued_at, |at| schedule.awake(at), self.request_bytes, self.ack_bytes, link, &mut delivery);
+        delivery
+    }
+
+    fn reachability_bytes_per_wake(&self) -> usize {
+        0
+    }
+}
+
+/// LwM2M Queue Mode: the server holds requests until the device's
+/// Registration Update shows it is awake, then sends them while the device
+/// keeps listening for the awake window.
+struct Lwm2mQueueMode {
+    request_bytes: usize,
+    ack_bytes: usize,
+    update_bytes: usize,
+}
+
+impl Lwm2mQueueMode {
+    fn new() -> Self {
+        let update = coap_message(COAP_CON, COAP_POST, 0x3001, &uri_path(&["rd", "5a3f"]), &[]);
+        let update_ack = coap_message(COAP_ACK, COAP_CHANGED, 0x3001, &[], &[]);
+        Self {
+            request_bytes: light_command().len() + IPV4_UDP_HEADER_BYTES,
+            ack_bytes: changed_ack().len() + IPV4_UDP_HEADER_BYTES,
+            update_bytes: update.len() + update_ack.len() + 2 * IPV4_UDP_HEADER_BYTES,
+        }
+    }
+}
+
+impl Lwm2mQueueMode {
+    /// When the server hears the Update the device sends on waking; the device
+    /// retransmits it until the server does.
+    fn update_heard(&self, wake: f64, link: &mut Link) -> Option<f64> {
+        let mut update_at = wake;
+        let mut timeout = COAP_ACK_TIMEOUT_MS;
+        for _ in 0..=COAP_MAX_RETRANSMIT {
+            if !link.lost() {
+                return Some(update_at + link.delay_ms);
+            }
+            update_at += timeout;
+            timeout *= 2.0;
+        }
+        None
+    }
+
+    /// The server queues requests and sends them one at a time (NSTART = 1)
+    /// after each Update. Every request the device receives restarts its awake
+    /// timer. A request that fails, or whose exchange outlasts the window, goes
+    /// back to the head of the queue for the next Update.
+    fn offline(&self, run: &mut OfflineRun, schedule: &SleepSchedule, link: &mut Link) {
+        let mut wake = schedule.poll_ms;
+        while !run.finished(wake, schedule) {
+            run.admit(wake);
+            let Some(heard_at) = self.update_heard(wake, link) else {
+                run.missed_window();
+                wake += schedule.poll_ms;
+                continue;
+            };
+            let mut cursor = heard_at;
+            let mut listening_until = heard_at + link.delay_ms + schedule.awake_ms;
+            loop {
+                run.admit(cursor);
+                let Some(command) = run.next_held() else {
+                    // Anything issued while the device still listens goes straight out
+                    match run.next_issue().filter(|&at| at + link.delay_ms <= listening_until) {
+                        Some(at) => {
+                            cursor = at;
+                            continue;
+                        }
+                        None => break,
+                    }
+                };
+                let mut delivery = Delivery::default();
+                let acked_at = coap_confirmable(
+                    cursor,
+                    |at| at <= listening_until,
+                    self.request_bytes,
+                    self.ack_bytes,
+                    link,
+                    &mut delivery,
+                );
+                run.bytes += delivery.bytes;
+                match (delivery.delivered_at, acked_at) {
+                    (Some(at), Some(acked_at)) => {
+                        run.deliver(command, at, schedule);
+                        listening_until = listening_until.max(at + schedule.awake_ms);
+                        cursor = acked_at;
+                    }
+                    (Some(at), None) => {
+                        // Delivered, but the exchange retransmits until long after the device sleeps
+                        run.deliver(command, at, schedule);
+                        break;
+                    }
+                    (None, _) => {
+                        run.requeue(command);
+                        break;
+                    }
+                }
+            }
+            run.missed_window();
+            wake += schedule.poll_ms;
+        }
+    }
+}
+
+impl SleepyProtocol for Lwm2mQueueMode {
+    fn name(&self) -> &'static str {
+        "LwM2M Queue Mode"
+    }
+
+    fn sleep_handling(&self) -> &'static str {
+        "server queues until the device's Registration Update"
+    }
+
+    fn deliver(&self, issued_at: f64, schedule: &SleepSchedule, link: &mut Link) -> Delivery {
+        let mut delivery = Delivery::default();
+        // Still listening after the last Update: send right away
+        if schedule.awake(issued_at) {
+            coap_confirmable(issued_at, |at| schedule.awake(at), self.request_bytes, self.ack_bytes, link, &mut delivery);
+        }
+
+        let mut wake = schedule.next_wake(issued_at);
+        for _ in 0..QUEUE_MODE_MAX_WAKES {
+            if delivery.delivered_at.is_some() {
+                break;
+            }
+            if let Some(heard_at) = self.update_heard(wake, link) {
+                let listening_until = heard_at + link.delay_ms + schedule.awake_ms;
+                coap_confirmable(heard_at, |at| at <= listening_until, self.request_bytes, self.ack_bytes, link, &mut delivery);
+            }
+            wake += schedule.poll_ms;
+        }
+        delivery
+    }
+
+    fn reachability_bytes_per_wake(&self) -> usize {
+        self.update_bytes
+    }
+}
+
+/// MQTT with a persistent session: the device reconnects at every wake-up
+/// and the broker delivers queued QoS 1 messages after CONNACK. TCP repairs
+/// losses with RTO backoff.
+struct MqttPersistentSession {
+    publish_bytes: usize,
+    puback_bytes: usize,
+    connect_bytes: usize,
+    connack_bytes: usize,
+    disconnect_bytes: usize,
+}
+
+impl MqttPersistentSession {
+    fn new() -> Self {
+        let mut connect = mqtt_string("MQTT");
+        connect.extend_from_slice(&[4, 0x00, 0, 60]);
+        connect.extend_from_slice(&mqtt_string("sed-dev1"));
+        Self {
+            publish_bytes: mqtt_publish("cmd/dev1/light", Some(1), br#"{"on":true}"#).len() + IPV4_TCP_HEADER_BYTES,
+            puback_bytes: mqtt_packet(MQTT_PUBACK, &1u16.to_be_bytes()).len() + IPV4_TCP_HEADER_BYTES,
+            connect_bytes: mqtt_packet(MQTT_CONNECT, &connect).len() + IPV4_TCP_HEADER_BYTES,
+            connack_bytes: mqtt_packet(MQTT_CONNACK, &[1, 0]).len() + IPV4_TCP_HEADER_BYTES,
+            disconnect_bytes: mqtt_packet(MQTT_DISCONNECT, &[]).len() + IPV4_TCP_HEADER_BYTES,
+        }
+    }
+}
+
+impl MqttPersistentSession {
+    /// The broker queues QoS 1 messages while the device is away. At each
+    /// wake-up the device reconnects and the broker sends up to
+    /// [`MQTT_MAX_INFLIGHT`] of them back to back, the next batch once those
+    /// are acknowledged. The device disconnects after the awake window.
+    fn offline(&self, run: &mut OfflineRun, schedule: &SleepSchedule, link: &mut Link) {
+        let mut wake = schedule.poll_ms;
+        while !run.finished(wake, schedule) {
+            run.admit(wake);
+            // Reconnect segments are reachability cost, only their delays count here
+            let mut reconnect = Delivery::default();
+            let syn_ack_at = link.tcp_segment(wake, 0, &mut reconnect);
+            let connected_at = link.tcp_segment(syn_ack_at, 0, &mut reconnect);
+            let mut cursor = link.tcp_segment(connected_at, 0, &mut reconnect);
+            let mut listening_until = cursor + schedule.awake_ms;
+            loop {
+                run.admit(cursor);
+                let mut batch = Vec::new();
+                while batch.len() < MQTT_MAX_INFLIGHT {
+                    match run.next_held() {
+                        Some(command) => batch.push(command),
+                        None => break,
+                    }
+                }
+                if batch.is_empty() {
+                    match run.next_issue().filter(|&at| at <= listening_until) {
+                        Some(at) => {
+                            cursor = at;
+                            continue;
+                        }
+                        None => break,
+                    }
+                }
+                let mut delivery = Delivery::default();
+                let mut acked_at = cursor;
+                for command in batch {
+                    let arrival = link.tcp_segment(cursor, self.publish_bytes, &mut delivery);
+                    run.deliver(command, arrival, schedule);
+                    listening_until = listening_until.max(arrival + schedule.awake_ms);
+                    acked_at = acked_at.max(link.tcp_segment(arrival, self.puback_bytes, &mut delivery));
+                }
+                run.bytes += delivery.bytes;
+                cursor = acked_at;
+            }
+            wake += schedule.poll_ms;
+        }
+    }
+}
+
+impl SleepyProtocol for MqttPersistentSession {
+    fn name(&self) -> &'static str {
+        "MQTT persistent session"
+    }
+
+    fn sleep_handling(&self) -> &'static str {
+        "broker queues QoS 1 messages until the device reconnects"
+    }
+
+    fn deliver(&self, issued_at: f64, schedule: &SleepSchedule, link: &mut Link) -> Delivery {
+        let mut delivery = Delivery::default();
+        let publish_at = if schedule.awake(issued_at) {
+            issued_at
+        } else {
+            // SYN, SYN-ACK, ACK + CONNECT, then CONNACK and the queued PUBLISH back to back;
+            // reconnect segments are reachability cost, only their delays count here
+            let mut reconnect = Delivery::default();
+            let syn_ack_at = link.tcp_segment(schedule.next_wake(issued_at), 0, &mut reconnect);
+            let connected_at = link.tcp_segment(syn_ack_at, 0, &mut reconnect);
+            link.tcp_segment(connected_at, 0, &mut reconnect)
+        };
+        let arrival = link.tcp_segment(publish_at, self.publish_bytes, &mut delivery);
+        delivery.deliver(arrival);
+        link.tcp_segment(arrival, self.puback_bytes, &mut delivery);
+        delivery
+    }
+
+    fn reachability_bytes_per_wake(&self) -> usize {
+        TCP_HANDSHAKE_BYTES + self.connect_bytes + self.connack_bytes + self.disconnect_bytes + TCP_TEARDOWN_BYTES
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lossless() -> IcdAnalyzer {
+        IcdAnalyzer::new(IcdConfig { loss_rate: 0.0, commands: 200, ..IcdConfig::default() })
+    }
+
+    #[test]
+    fn sleep_schedule_wakes_every_poll_interval() {
+        let schedule = SleepSchedule { poll_ms: 1000.0, awake_ms: 100.0 };
+        assert!(schedule.awake(0.0) && schedule.awake(99.0) && schedule.awake(1050.0));
+        assert!(!schedule.awake(100.0) && !schedule.awake(999.0));
+        assert_eq!(schedule.next_wake(0.0), 1000.0);
+        assert_eq!(schedule.next_wake(1500.0), 2000.0);
+        assert_eq!(schedule.next_wake(2000.0), 3000.0);
+        assert_eq!(schedule.reachable_from(1050.0), 1050.0);
+        assert_eq!(schedule.reachable_from(1500.0), 2000.0);
+    }
+
+    #[test]
+    fn lossless_commands_wait_at_most_one_poll_interval() {
+        let analyzer = lossless();
+        let matter = MatterIcd::new().unwrap();
+        for poll_interval in [Duration::from_secs(1), Duration::from_secs(30)] {
+            let run = analyzer.simulate(&matter, poll_interval);
+            let poll_ms = poll_interval.as_secs_f64() * 1000.0;
+            assert_eq!((run.delivered, run.failed), (200, 0));
+            assert!(run.latency_max_ms <= poll_ms + 20.0, "{} > {}", run.latency_max_ms, poll_ms + 20.0);
+            assert!(run.latency_mean_ms >= 20.0 && run.latency_p95_ms <= run.latency_max_ms);
+            // Invoke, InvokeResponse and the controller's ack; MRP's idle interval outlasts the wait
+            assert_eq!(run.transmissions_per_command, 3.0);
+            assert_eq!(run.retransmissions_per_command, 0.0);
+            let expected = (matter.invoke_bytes + matter.response_bytes + matter.ack_bytes) as f64;
+            assert_eq!(run.command_bytes_per_command, expected);
+        }
+    }
+
+    #[test]
+    fn reachability_traffic_scales_with_the_wake_rate() {
+        let analyzer = lossless();
+        let protocols: [Box<dyn SleepyProtocol>; 4] = [
+            Box::new(MatterIcd::new().unwrap()),
+            Box::new(CoapDirect::new()),
+            Box::new(Lwm2mQueueMode::new()),
+            Box::new(MqttPersistentSession::new()),
+        ];
+        for protocol in &protocols {
+            let per_wake = protocol.reachability_bytes_per_wake() as f64;
+            let every_second = analyzer.simulate(protocol.as_ref(), Duration::from_secs(1));
+            let every_minute = analyzer.simulate(protocol.as_ref(), Duration::from_secs(60));
+            assert_eq!(every_second.reachability_bytes_per_hour, per_wake * 3600.0, "{}", protocol.name());
+            assert_eq!(every_minute.reachability_bytes_per_hour, per_wake * 60.0, "{}", protocol.name());
+        }
+        assert_eq!(protocols[0].reachability_bytes_per_wake(), THREAD_DATA_POLL_BYTES);
+        assert_eq!(protocols[1].reachability_bytes_per_wake(), 0);
+    }
+}
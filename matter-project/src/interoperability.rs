@@ -0,0 +1,271 @@
+// matter-project/src/interoperability.rs
+/*!
+Interoperability scoring model.
+
+The score is derived from declared capability matrices kept as data files in
+`capabilities/`: `reference.json` lists every device type, data model,
+ecosystem and transport binding the model knows about, and one file per
+protocol declares which of them the protocol supports natively. The files are
+compiled in; `--capabilities-dir` reads them from a directory instead. Any
+change to the dimensions, weights or the combination rule must bump
+[`INTEROPERABILITY_MODEL_VERSION`]; changing only the data files does not.
+
+Model (version 1):
+
+| Dimension          | Coverage (declared / reference)       | Weight |
+|--------------------|---------------------------------------|--------|
+| device_types       | standard device types                 | 0.30   |
+| data_models        | shared data models                    | 0.20   |
+| ecosystems         | platforms with native support         | 0.30   |
+| transport_bindings | transport bindings                    | 0.20   |
+
+The score is the weighted mean of the four coverages. A declared capability
+missing from the reference is rejected so a typo cannot silently lower a score.
+*/
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::builder::analyzer_builder;
+
+pub const INTEROPERABILITY_MODEL_VERSION: &str = "1";
+
+const WEIGHT_DEVICE_TYPES: f64 = 0.30;
+const WEIGHT_DATA_MODELS: f64 = 0.20;
+const WEIGHT_ECOSYSTEMS: f64 = 0.30;
+const WEIGHT_TRANSPORT_BINDINGS: f64 = 0.20;
+
+const REFERENCE_FILE: &str = "reference.json";
+
+// Compiled-in copies of capabilities/*.json, by file name
+const BUILTIN_MATRICES: &[(&str, &str)] = &[
+    ("matter.json", include_str!("../capabilities/matter.json")),
+    ("lwm2m.json", include_str!("../capabilities/lwm2m.json")),
+    ("mqtt.json", include_str!("../capabilities/mqtt.json")),
+    ("coap.json", include_str!("../capabilities/coap.json")),
+];
+const BUILTIN_REFERENCE: &str = include_str!("../capabilities/reference.json");
+
+#[derive(Debug, Clone, Default)]
+pub struct InteroperabilityConfig {
+    /// Directory holding `reference.json` and one matrix per protocol; `None` uses the compiled-in files.
+    pub capabilities_dir: Option<PathBuf>,
+}
+
+analyzer_builder!(InteroperabilityAnalyzerBuilder => InteroperabilityAnalyzer(InteroperabilityConfig) {
+    capabilities_dir: Option<PathBuf>,
+});
+
+/// Everything the model knows about, per dimension.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapabilityReference {
+    pub device_types: Vec<String>,
+    pub data_models: Vec<String>,
+    pub ecosystems: Vec<String>,
+    pub transport_bindings: Vec<String>,
+}
+
+/// What one protocol supports natively, per dimension.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapabilityMatrix {
+    pub protocol: String,
+    pub device_types: Vec<String>,
+    pub data_models: Vec<String>,
+    pub ecosystems: Vec<String>,
+    pub transport_bindings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DimensionCoverage {
+    pub device_types: f64,
+    pub data_models: f64,
+    pub ecosystems: f64,
+    pub transport_bindings: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InteroperabilityScore {
+    pub protocol: String,
+    pub score: f64,
+    pub coverage: DimensionCoverage,
+    pub matrix: CapabilityMatrix,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InteroperabilityMetrics {
+    pub model_version: String,
+    /// `compiled-in` or the directory the matrices were read from.
+    pub source: String,
+    pub reference: CapabilityReference,
+    pub protocols: Vec<InteroperabilityScore>,
+}
+
+impl InteroperabilityMetrics {
+    pub fn score_for(&self, protocol: &str) -> Option<f64> {
+        self.protocols.iter().find(|p| p.protocol == protocol).map(|p| p.score)
+    }
+}
+
+pub fn interoperability_score(reference: &CapabilityReference, matrix: &CapabilityMatrix) -> Result<InteroperabilityScore> {
+    let coverage = |dimension: &str, declared: &[String], known: &[String]| -> Result<f64> {
+        if let Some(unknown) = declared.iter().find(|c| !known.contains(c)) {
+            return Err(anyhow!("{}: {} '{}' is not in the reference", matrix.protocol, dimension, unknown));
+        }
+        if known.is_empty() {
+            return Ok(0.0);
+        }
+        let supported = known.iter().filter(|c| declared.contains(c)).count();
+        Ok(supported as f64 / known.len() as f64)
+    };
+
+    let coverage = DimensionCoverage {
+        device_types: coverage("device type", &matrix.device_types, &reference.device_types)?,
+        data_models: coverage("data model", &matrix.data_models, &reference.data_models)?,
+        ecosystems: coverage("ecosystem", &matrix.ecosystems, &reference.ecosystems)?,
+        transport_bindings: coverage("transport binding", &matrix.transport_bindings, &reference.transport_bindings)?,
+    };
+    let score = coverage.device_types * WEIGHT_DEVICE_TYPES
+        + coverage.data_models * WEIGHT_DATA_MODELS
+        + coverage.ecosystems * WEIGHT_ECOSYSTEMS
+        + coverage.transport_bindings * WEIGHT_TRANSPORT_BINDINGS;
+
+    Ok(InteroperabilityScore {
+        protocol: matrix.protocol.clone(),
+        score,
+        coverage,
+        matrix: matrix.clone(),
+    })
+}
+
+pub struct InteroperabilityAnalyzer {
+    config: InteroperabilityConfig,
+}
+
+impl InteroperabilityAnalyzer {
+    pub fn new(config: InteroperabilityConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_interoperability(&mut self) -> Result<InteroperabilityMetrics> {
+        let (source, reference, matrices) = self.load()?;
+
+        println!("\n🤝 Analyzing Interoperability (capability matrices: {})", source);
+        println!("--------------------------------------------------------");
+
+        let mut protocols = Vec::new();
+        for matrix in &matrices {
+            let score = interoperability_score(&reference, matrix)?;
+            println!("✅ {}: {:.2} (device types {:.2}, data models {:.2}, ecosystems {:.2}, transports {:.2})",
+                     score.protocol, score.score, score.coverage.device_types, score.coverage.data_models,
+                     score.coverage.ecosystems, score.coverage.transport_bindings);
+            protocols.push(score);
+        }
+
+        Ok(InteroperabilityMetrics {
+            model_version: INTEROPERABILITY_MODEL_VERSION.to_string(),
+            source,
+            reference,
+            protocols,
+        })
+    }
+
+    fn load(&self) -> Result<(String, CapabilityReference, Vec<CapabilityMatrix>)> {
+        let Some(dir) = &self.config.capabilities_dir else {
+            let reference = serde_json::from_str(BUILTIN_REFERENCE).context(REFERENCE_FILE)?;
+            let matrices = BUILTIN_MATRICES
+                .iter()
+                .map(|(name, json)| serde_json::from_str(json).context(*name))
+                .collect::<Result<_>>()?;
+            return Ok(("compiled-in".to_string(), reference, matrices));
+        };
+
+        let read = |path: PathBuf| -> Result<String> {
+            std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))
+        };
+        let reference = serde_json::from_str(&read(dir.join(REFERENCE_FILE))?).context(REFERENCE_FILE)?;
+
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+            .with_context(|| format!("reading {}", dir.display()))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<_, _>>()?;
+        paths.retain(|p| {
+            p.extension().is_some_and(|e| e == "json") && p.file_name().is_some_and(|n| n != REFERENCE_FILE)
+        });
+        paths.sort();
+        let matrices = paths
+            .into_iter()
+            .map(|path| {
+                let json = read(path.clone())?;
+                serde_json::from_str(&json).with_context(|| path.display().to_string())
+            })
+            .collect::<Result<_>>()?;
+
+        Ok((dir.display().to_string(), reference, matrices))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn reference() -> CapabilityReference {
+        CapabilityReference {
+            device_types: strings(&["light", "lock"]),
+            data_models: strings(&["a", "b"]),
+            ecosystems: strings(&["x", "y", "z", "w"]),
+            transport_bindings: strings(&["udp", "tcp"]),
+        }
+    }
+
+    fn matrix(device_types: &[&str], ecosystems: &[&str]) -> CapabilityMatrix {
+        CapabilityMatrix {
+            protocol: "test".to_string(),
+            device_types: strings(device_types),
+            data_models: Vec::new(),
+            ecosystems: strings(ecosystems),
+            transport_bindings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn full_matrix_scores_one() {
+        let reference = reference();
+        let full = CapabilityMatrix {
+            protocol: "full".to_string(),
+            device_types: reference.device_types.clone(),
+            data_models: reference.data_models.clone(),
+            ecosystems: reference.ecosystems.clone(),
+            transport_bindings: reference.transport_bindings.clone(),
+        };
+        let score = interoperability_score(&reference, &full).unwrap();
+        assert!((score.score - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn coverage_is_weighted() {
+        let score = interoperability_score(&reference(), &matrix(&["light"], &["x"])).unwrap();
+        assert!((score.coverage.device_types - 0.5).abs() < 1e-9);
+        assert!((score.coverage.ecosystems - 0.25).abs() < 1e-9);
+        let expected = 0.5 * WEIGHT_DEVICE_TYPES + 0.25 * WEIGHT_ECOSYSTEMS;
+        assert!((score.score - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unknown_capability_is_rejected() {
+        assert!(interoperability_score(&reference(), &matrix(&["lihgt"], &[])).is_err());
+    }
+
+    #[test]
+    fn builtin_matrices_load() {
+        let metrics = InteroperabilityAnalyzer::new(InteroperabilityConfig::default())
+            .analyze_interoperability()
+            .unwrap();
+        assert_eq!(metrics.protocols.len(), BUILTIN_MATRICES.len());
+        assert!(metrics.score_for("Matter").is_some());
+    }
+}
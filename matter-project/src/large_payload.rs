@@ -0,0 +1,474 @@
+// matter-project/src/large_payload.rs
+/*!
+Large payload transfer - CoAP Block2, Matter BDX and HTTP Range requests from 64 KB to 10 MB
+
+The other benchmarks send small commands; this one moves payloads large
+enough that each protocol has to chunk them, using the chunking it was
+designed with:
+
+- Matter BDX: receiver-driven 1 KB blocks over UDP, reliable through MRP.
+- CoAP Block2: 1 KB blocks over UDP, each a confirmable GET.
+- HTTP: 16 KB `Range` GETs on one kept-alive TCP connection.
+
+Each transfer is the firmware-update flow of the same protocol, so its
+session setup and teardown are included. It runs twice. Over loopback it
+is measured for time, message count and application-layer bytes. Its
+messages are then replayed over a modelled link with a one-way delay, a
+rate and independent loss per datagram or TCP segment, with each
+protocol's own recovery: MRP and CoAP retransmit the block exchange on
+their timers, TCP retransmits the lost segment after three duplicate
+acks or on its RTO. When an exchange runs out of retransmissions the
+device reconnects and resumes at the same block.
+*/
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{image, run_transfer, Carrier, Exchange, HttpRanges, Lwm2mBlockwise, MatterBdx, UpdateMechanism};
+use crate::handshake::next_unit;
+use crate::transport_modes::{
+    IPV4_TCP_HEADER_BYTES, IPV4_UDP_HEADER_BYTES, MRP_ACTIVE_RETRANS_TIMEOUT, MRP_BACKOFF_BASE, MRP_MAX_TRANSMISSIONS,
+    TCP_HANDSHAKE_BYTES,
+};
+
+/// CoAP Block2 numbers have 20 bits, which caps 1 KB blocks at 1 GB; this keeps runs in memory.
+const MAX_PAYLOAD_BYTES: usize = 64 * 1024 * 1024;
+
+// RFC 7252 transmission parameters
+const COAP_ACK_TIMEOUT_MS: f64 = 2000.0;
+const COAP_ACK_RANDOM_FACTOR: f64 = 1.5;
+const COAP_MAX_RETRANSMIT: u32 = 4;
+
+const TCP_MSS: usize = 1460;
+// Initial RTO for a SYN (RFC 6298) and Linux's minimum RTO once the connection is up
+const TCP_SYN_RTO_MS: f64 = 1000.0;
+const TCP_MIN_RTO_MS: f64 = 200.0;
+/// Linux's default tcp_syn_retries.
+const TCP_MAX_RETRANSMISSIONS: u32 = 6;
+/// Duplicate acks that trigger a fast retransmit.
+const TCP_DUPACK_THRESHOLD: usize = 3;
+
+/// A modelled transfer that has to resume this often is reported as failed.
+const MAX_RESUMES: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct LargePayloadConfig {
+    pub payload_sizes: Vec<usize>,
+    /// Loss rates of the modelled link, per datagram or TCP segment in either direction.
+    pub loss_rates: Vec<f64>,
+    /// One-way delay of the modelled link.
+    pub link_delay: Duration,
+    /// Rate of the modelled link in each direction.
+    pub link_rate_kbit: u32,
+}
+
+impl Default for LargePayloadConfig {
+    fn default() -> Self {
+        Self {
+            payload_sizes: vec![64 * 1024, 1024 * 1024, 10 * 1024 * 1024],
+            loss_rates: vec![0.01, 0.05],
+            link_delay: Duration::from_millis(20),
+            link_rate_kbit: 1000,
+        }
+    }
+}
+
+analyzer_builder!(LargePayloadBenchmarkBuilder => LargePayloadBenchmark(LargePayloadConfig) {
+    payload_sizes: Vec<usize>,
+    loss_rates: Vec<f64>,
+    link_delay: Duration,
+    link_rate_kbit: u32,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LargePayloadMetrics {
+    pub link_delay_ms: f64,
+    pub link_rate_kbit: u32,
+    pub transfers: Vec<PayloadTransfer>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PayloadTransfer {
+    pub mechanism: String,
+    pub transport: String,
+    pub payload_bytes: usize,
+    pub block_size: usize,
+    pub blocks: usize,
+    /// Measured over loopback.
+    pub loopback_time_ms: f64,
+    pub messages: usize,
+    /// Application-layer bytes in both directions (UDP/TCP/IP headers excluded).
+    pub wire_bytes: usize,
+    /// Non-payload bytes per payload byte.
+    pub overhead_ratio: f64,
+    /// The same on the lossless modelled link, with IPv4/UDP/TCP headers, the TCP handshake and ACKs.
+    pub link_overhead_ratio: f64,
+    /// Lossless transfer time on the modelled link.
+    pub link_time_ms: f64,
+    pub lossy: Vec<LossyTransfer>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LossyTransfer {
+    pub loss_rate: f64,
+    /// False when the transfer had to resume more than `MAX_RESUMES` times.
+    pub completed: bool,
+    pub time_ms: f64,
+    /// Time relative to the lossless transfer on the same link.
+    pub slowdown: f64,
+    pub goodput_kbps: f64,
+    pub retransmissions: usize,
+    /// Reconnects after an exchange ran out of retransmissions.
+    pub resumes: usize,
+    /// Non-payload bytes on the link per payload byte, retransmissions and headers included.
+    pub overhead_ratio: f64,
+}
+
+pub struct LargePayloadBenchmark {
+    config: LargePayloadConfig,
+}
+
+impl LargePayloadBenchmark {
+    pub fn new(config: LargePayloadConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_large_payload(&mut self) -> Result<LargePayloadMetrics> {
+        println!("\n🚚 Analyzing Large Payload Transfer ({:.0}ms, {} kbit/s link)",
+                 self.config.link_delay.as_secs_f64() * 1000.0, self.config.link_rate_kbit);
+        println!("------------------------------------------------------");
+
+        if let Some(size) = self.config.payload_sizes.iter().find(|&&size| size == 0 || size > MAX_PAYLOAD_BYTES) {
+            return Err(anyhow!("payload size {} is outside 1..={} bytes", size, MAX_PAYLOAD_BYTES));
+        }
+        if let Some(rate) = self.config.loss_rates.iter().find(|rate| !(0.0..1.0).contains(*rate)) {
+            return Err(anyhow!("loss rate {} is outside [0, 1)", rate));
+        }
+        if self.config.link_rate_kbit == 0 {
+            return Err(anyhow!("link rate must be positive"));
+        }
+
+        let mechanisms: Vec<(Arc<dyn UpdateMechanism>, Recovery)> = vec![
+            (Arc::new(MatterBdx), Recovery::Mrp),
+            (Arc::new(Lwm2mBlockwise), Recovery::CoapCon),
+            (Arc::new(HttpRanges), Recovery::Tcp),
+        ];
+        let mut transfers = Vec::new();
+        for &size in &self.config.payload_sizes {
+            let payload = image(size);
+            for (mechanism, recovery) in &mechanisms {
+                let measured = run_transfer(mechanism, &payload, None).await?;
+                let lossless = self.model(mechanism.as_ref(), *recovery, &payload, 0.0)?;
+                let lossy = self
+                    .config
+                    .loss_rates
+                    .iter()
+                    .map(|&loss_rate| self.model(mechanism.as_ref(), *recovery, &payload, loss_rate))
+                    .collect::<Result<Vec<_>>>()?
+                    .into_iter()
+                    .zip(&self.config.loss_rates)
+                    .map(|(tally, &loss_rate)| LossyTransfer {
+                        loss_rate,
+                        completed: tally.resumes <= MAX_RESUMES,
+                        time_ms: tally.time_ms,
+                        slowdown: tally.time_ms / lossless.time_ms.max(f64::EPSILON),
+                        goodput_kbps: size as f64 * 8.0 / tally.time_ms.max(f64::EPSILON),
+                        retransmissions: tally.retransmissions,
+                        resumes: tally.resumes,
+                        overhead_ratio: tally.bytes.saturating_sub(size) as f64 / size as f64,
+                    })
+                    .collect();
+
+                let transfer = PayloadTransfer {
+                    mechanism: mechanism.name().to_string(),
+                    transport: match mechanism.carrier() {
+                        Carrier::Udp => "UDP",
+                        Carrier::Tcp => "TCP",
+                    }
+                    .to_string(),
+                    payload_bytes: size,
+                    block_size: mechanism.block_size(),
+                    blocks: size.div_ceil(mechanism.block_size()),
+                    loopback_time_ms: measured.elapsed.as_secs_f64() * 1000.0,
+                    messages: measured.messages,
+                    wire_bytes: measured.wire_bytes,
+                    overhead_ratio: measured.wire_bytes.saturating_sub(size) as f64 / size as f64,
+                    link_overhead_ratio: lossless.bytes.saturating_sub(size) as f64 / size as f64,
+                    link_time_ms: lossless.time_ms,
+                    lossy,
+                };
+
+                let losses: Vec<String> = transfer
+                    .lossy
+                    .iter()
+                    .map(|run| match run.completed {
+                        true => format!("×{:.2} at {:.0}%", run.slowdown, run.loss_rate * 100.0),
+                        false => format!("failed at {:.0}%", run.loss_rate * 100.0),
+                    })
+                    .collect();
+                println!("✅ {} {} KB: {} msgs, {:.1}ms loopback, {:.1}% overhead ({:.1}% on the link), {:.1}s on the link, {}",
+                         transfer.mechanism, size / 1024, transfer.messages, transfer.loopback_time_ms,
+                         transfer.overhead_ratio * 100.0, transfer.link_overhead_ratio * 100.0,
+                         transfer.link_time_ms / 1000.0, losses.join(", "));
+                transfers.push(transfer);
+            }
+        }
+
+        Ok(LargePayloadMetrics {
+            link_delay_ms: self.config.link_delay.as_secs_f64() * 1000.0,
+            link_rate_kbit: self.config.link_rate_kbit,
+            transfers,
+        })
+    }
+
+    /// Replays one transfer over the modelled link.
+    fn model(&self, mechanism: &dyn UpdateMechanism, recovery: Recovery, payload: &[u8], loss_rate: f64) -> Result<Tally> {
+        // Same seed for every mechanism, so each sees the same kind of luck
+        let mut link = LossyLink {
+            delay_ms: self.config.link_delay.as_secs_f64() * 1000.0,
+            rate_kbit: self.config.link_rate_kbit as f64,
+            loss_rate,
+            rng_state: 0x9E37_79B9_7F4A_7C15u64,
+            recovery,
+        };
+        let mut tally = Tally::default();
+        let block_size = mechanism.block_size();
+        let blocks = payload.len().div_ceil(block_size);
+
+        let mut messages: Vec<(usize, Option<usize>)> = Vec::new();
+        let sizes = |exchanges: Vec<Exchange>| {
+            exchanges
+                .into_iter()
+                .map(|exchange| (exchange.request.len(), exchange.response.map(|response| response.len())))
+                .collect::<Vec<_>>()
+        };
+        messages.extend(sizes(mechanism.session_setup(payload, None)?));
+        let first_block = messages.len();
+        for block in 0..blocks {
+            let data = &payload[block * block_size..((block + 1) * block_size).min(payload.len())];
+            messages.push((
+                mechanism.block_request(block).len(),
+                Some(mechanism.block_response(block, data, payload.len()).len()),
+            ));
+            if let Some(ack) = mechanism.block_ack(block) {
+                messages.push((ack.len(), None));
+            }
+        }
+        messages.extend(sizes(mechanism.session_teardown(payload)?));
+
+        // Whether an exchange completes or gives up, the clock moves on to when it did
+        let mut clock = 0.0;
+        let mut connected = recovery != Recovery::Tcp;
+        let mut index = 0;
+        while index < messages.len() && tally.resumes <= MAX_RESUMES {
+            if !connected {
+                match link.connect(clock, &mut tally) {
+                    Ok(done) => (clock, connected) = (done, true),
+                    Err(gave_up) => (clock, tally.resumes) = (gave_up, tally.resumes + 1),
+                }
+                continue;
+            }
+            let (request, response) = messages[index];
+            match link.exchange(clock, request, response, &mut tally) {
+                Ok(done) => {
+                    clock = done;
+                    index += 1;
+                }
+                Err(gave_up) => {
+                    // Reconnect and pick up at the block in flight
+                    clock = gave_up;
+                    tally.resumes += 1;
+                    connected = recovery != Recovery::Tcp;
+                    let block = index.saturating_sub(first_block).min(blocks.saturating_sub(1));
+                    let resume = sizes(mechanism.session_setup(payload, Some(block * block_size))?);
+                    messages.splice(index..index, resume);
+                }
+            }
+        }
+        tally.time_ms = clock;
+        Ok(tally)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Recovery {
+    Mrp,
+    CoapCon,
+    Tcp,
+}
+
+#[derive(Default)]
+struct Tally {
+    time_ms: f64,
+    /// On the link, IPv4/UDP/TCP headers and retransmissions included.
+    bytes: usize,
+    retransmissions: usize,
+    resumes: usize,
+}
+
+/// Delay, rate and deterministic loss, the same in both directions.
+struct LossyLink {
+    delay_ms: f64,
+    rate_kbit: f64,
+    loss_rate: f64,
+    rng_state: u64,
+    recovery: Recovery,
+}
+
+impl LossyLink {
+    fn lost(&mut self) -> bool {
+        next_unit(&mut self.rng_state) < self.loss_rate
+    }
+
+    fn serialization_ms(&self, bytes: usize) -> f64 {
+        bytes as f64 * 8.0 / self.rate_kbit
+    }
+
+    /// Sends `request` at `at` and returns when its response has arrived, or when
+    /// a one-way message is on the link; `Err` with the time the sender gave up
+    /// once retransmissions run out.
+    fn exchange(&mut self, at: f64, request: usize, response: Option<usize>, tally: &mut Tally) -> Result<f64, f64> {
+        if self.recovery == Recovery::Tcp {
+            let arrived = self.stream(at, request, tally)?;
+            return match response {
+                Some(response) => self.stream(arrived, response, tally),
+                None => Ok(arrived),
+            };
+        }
+
+        let request = request + IPV4_UDP_HEADER_BYTES;
+        let Some(response) = response.map(|response| response + IPV4_UDP_HEADER_BYTES) else {
+            // One-way messages are not waited for and their loss stalls nothing
+            tally.bytes += request;
+            return Ok(at + self.serialization_ms(request));
+        };
+        let mut sent_at = at;
+        let mut waited = 0.0;
+        let mut done: Option<f64> = None;
+        for transmission in 1.. {
+            tally.bytes += request;
+            if transmission > 1 {
+                tally.retransmissions += 1;
+            }
+            if !self.lost() {
+                // Retransmissions are answered again, since the response may be what was lost
+                tally.bytes += response;
+                if !self.lost() {
+                    let arrived = sent_at + self.serialization_ms(request) + self.serialization_ms(response) + 2.0 * self.delay_ms;
+                    done = Some(done.map_or(arrived, |d: f64| d.min(arrived)));
+                }
+            }
+            match self.retransmit_after(transmission) {
+                Some(wait) if done.is_none_or(|d| d > sent_at + wait) => (sent_at, waited) = (sent_at + wait, wait),
+                _ => break,
+            }
+        }
+        // The last transmission gets as long as the one before it
+        done.ok_or(sent_at + waited)
+    }
+
+    /// Wait after `transmissions` datagrams before the next one; `None` once they are used up.
+    fn retransmit_after(&mut self, transmissions: u32) -> Option<f64> {
+        match self.recovery {
+            Recovery::Mrp if transmissions < MRP_MAX_TRANSMISSIONS => Some(
+                MRP_ACTIVE_RETRANS_TIMEOUT.as_secs_f64() * 1000.0 * MRP_BACKOFF_BASE.powi(transmissions as i32 - 1),
+            ),
+            Recovery::CoapCon if transmissions <= COAP_MAX_RETRANSMIT => {
+                let initial = COAP_ACK_TIMEOUT_MS * (1.0 + next_unit(&mut self.rng_state) * (COAP_ACK_RANDOM_FACTOR - 1.0));
+                Some(initial * (1 << (transmissions - 1)) as f64)
+            }
+            _ => None,
+        }
+    }
+
+    /// One message over TCP, split into segments sent back to back. A lost segment
+    /// is fast-retransmitted once enough later segments produce duplicate acks,
+    /// otherwise on its RTO; the message is there when its last segment is.
+    fn stream(&mut self, at: f64, bytes: usize, tally: &mut Tally) -> Result<f64, f64> {
+        let segments = bytes.div_ceil(TCP_MSS).max(1);
+        let mut clock = at;
+        let mut done = at;
+        for segment in 0..segments {
+            let len = (bytes - segment * TCP_MSS).min(TCP_MSS) + IPV4_TCP_HEADER_BYTES;
+            clock += self.serialization_ms(len);
+            tally.bytes += len;
+            let mut arrived = clock + self.delay_ms;
+            let mut rto = 2.0 * self.delay_ms + TCP_MIN_RTO_MS;
+            let mut transmission = 0;
+            while self.lost() {
+                transmission += 1;
+                if transmission > TCP_MAX_RETRANSMISSIONS {
+                    return Err(arrived);
+                }
+                let wait = if transmission == 1 && segments - segment > TCP_DUPACK_THRESHOLD {
+                    2.0 * self.delay_ms
+                } else {
+                    rto *= 2.0;
+                    rto / 2.0
+                };
+                arrived += wait + self.serialization_ms(len);
+                tally.bytes += len;
+                tally.retransmissions += 1;
+            }
+            done = done.max(arrived);
+        }
+        // Delayed acks, one per two segments; they ride alongside and take no time here
+        tally.bytes += segments.div_ceil(2) * IPV4_TCP_HEADER_BYTES;
+        Ok(done)
+    }
+
+    /// SYN and SYN-ACK retransmitted on the SYN RTO; the final ACK travels with the first request.
+    fn connect(&mut self, at: f64, tally: &mut Tally) -> Result<f64, f64> {
+        let mut sent_at = at;
+        let mut rto = TCP_SYN_RTO_MS;
+        for transmission in 0..=TCP_MAX_RETRANSMISSIONS {
+            if transmission > 0 {
+                tally.retransmissions += 1;
+            }
+            tally.bytes += IPV4_TCP_HEADER_BYTES;
+            if !self.lost() {
+                tally.bytes += IPV4_TCP_HEADER_BYTES;
+                if !self.lost() {
+                    tally.bytes += TCP_HANDSHAKE_BYTES - 2 * IPV4_TCP_HEADER_BYTES;
+                    return Ok(sent_at + 2.0 * self.delay_ms);
+                }
+            }
+            sent_at += rto;
+            rto *= 2.0;
+        }
+        Err(sent_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn block_size_and_retransmission_timers_decide_large_transfers() {
+        let metrics = LargePayloadBenchmark::builder()
+            .payload_sizes(vec![64usize * 1024])
+            .loss_rates(vec![0.05])
+            .build()
+            .analyze_large_payload()
+            .await
+            .unwrap();
+        let transfer = |name: &str| metrics.transfers.iter().find(|t| t.mechanism.contains(name)).unwrap();
+        let (bdx, coap, http) = (transfer("BDX"), transfer("CoAP"), transfer("HTTP"));
+
+        assert_eq!((bdx.blocks, coap.blocks, http.blocks), (64, 64, 4));
+        // 16 KB ranges need far fewer round trips than 1 KB blocks
+        assert!(http.messages < bdx.messages / 10);
+        assert!(http.link_time_ms < bdx.link_time_ms);
+        // Headers only add to what the application sends
+        for t in [bdx, coap, http] {
+            assert!(t.link_overhead_ratio > t.overhead_ratio);
+            assert!(t.lossy[0].completed && t.lossy[0].retransmissions > 0);
+        }
+        // CoAP waits 2-3 s before retransmitting a block, MRP 300 ms
+        assert!(coap.lossy[0].slowdown > bdx.lossy[0].slowdown);
+    }
+}
@@ -0,0 +1,410 @@
+// matter-project/src/layers.rs
+/*!
+Matter layer stages - the transport, session, application and presentation phases as pipeline stages
+
+transport → session → application, with presentation independent. The
+transport stage opens a loopback UDP link and leaves it in the context,
+discovers the node through an emulated mDNS responder, times a TCP
+connection until a peer has accepted and echoed a first message, and times
+a connection's setup stage by stage with `ConnectionSetup`. The
+session stage establishes a `SecureSession` over that link, and the
+application stage sends its interaction-model reads through the session, so
+the cluster setup and discovery times include the encryption they would pay
+on a commissioned device. The transport stage's mDNS responder and TCP peer
+listen on ports reserved through the context's `PortAllocator`, so runs in
+parallel on one host do not take each other's ports. Every phase is corrected by the calibration in the
+context, and it is attributed energy when a `PowerSampler` is there.
+
+The link, the handshake and each read are retried under the context's
+`RetryPolicy`. The retries each one needed are added to the `RetryCounts`
+in the context under the name of the metric they went into.
+*/
+
+use analysis_core::pipeline::{LayerContext, LayerPipeline, LayerStage, StageFuture};
+use analysis_core::ports::PortAllocator;
+use analysis_core::retry::{RetryCounts, RetryPolicy};
+use analysis_core::watchdog::StageProbe;
+use anyhow::{anyhow, Result};
+use metrics_core::bench::{benchmark_tlv_encoding, encode_reference_report, StdClock, REFERENCE_REPORT_CAPACITY};
+use metrics_core::metrics::PresentationSample;
+use metrics_core::tlv::{Tag, TlvError, TlvWriter};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+use crate::bdx::PROTOCOL_SECURE_CHANNEL;
+use crate::calibration::CalibrationMetrics;
+use crate::connection_setup::ConnectionSetup;
+use crate::fixtures;
+use crate::mdns::{self, DiscoveryTiming, ServiceAdvertisement};
+use crate::message::{self, MessageHeader, ProtocolHeader};
+use crate::power::PowerSampler;
+use crate::secure_session::{SecureSession, MESSAGE_HEADER_BYTES};
+
+/// PASE/CASE bytes exchanged during commissioning.
+pub const PAIRING_OVERHEAD_BYTES: u32 = 156;
+/// Interaction-model framing around one cluster operation, used when no session was measured.
+pub const APPLICATION_OVERHEAD_BYTES: u32 = 24;
+const TLV_ITERATIONS: u32 = 10_000;
+const SESSION_ID: u16 = 1;
+const DISCOVERY_TIMEOUT: Duration = Duration::from_millis(500);
+const PEER_TIMEOUT: Duration = Duration::from_millis(500);
+/// The first message a commissioner sends over a new connection.
+const PBKDF_PARAM_REQUEST: u8 = 0x20;
+const CONTROLLER_NODE_ID: u64 = 0x0000_0000_0001_B669;
+/// Names of the transport stage's ports in the context's `PortAllocator`.
+const MDNS_RESPONDER_PORT: &str = "mdns_responder";
+const TCP_PEER_PORT: &str = "tcp_peer";
+/// Attributes of the reference report, read during cluster setup.
+const REFERENCE_ATTRIBUTES: [(u16, u32, u32); 7] = [
+    (1, 0x0006, 0x0000),
+    (1, 0x0008, 0x0000),
+    (1, 0x0300, 0x0007),
+    (2, 0x0402, 0x0000),
+    (0, 0x0028, 0x0001),
+    (0, 0x0028, 0x0003),
+    (0, 0x0028, 0x000A),
+];
+/// Descriptor cluster ServerList, read during service discovery.
+const DESCRIPTOR_SERVER_LIST: (u16, u32, u32) = (1, 0x001D, 0x0001);
+const ENDPOINT_1_SERVERS: [u32; 3] = [0x0006, 0x0008, 0x0300];
+
+/// A connected controller/device socket pair on loopback, opened by the transport stage.
+pub struct LoopbackLink {
+    controller: UdpSocket,
+    device: UdpSocket,
+}
+
+impl LoopbackLink {
+    pub async fn open() -> Result<Self> {
+        let controller = UdpSocket::bind("127.0.0.1:0").await?;
+        let device = UdpSocket::bind("127.0.0.1:0").await?;
+        controller.connect(device.local_addr()?).await?;
+        device.connect(controller.local_addr()?).await?;
+        Ok(Self { controller, device })
+    }
+
+    /// Sends `bytes` from the controller and echoes them back from the device.
+    pub async fn exchange(&self, bytes: usize) -> Result<()> {
+        let received = self.to_device(&vec![0u8; bytes]).await?;
+        self.to_controller(&received).await?;
+        Ok(())
+    }
+
+    /// Registers both sockets with the watchdog's probe for the running stage.
+    pub fn watch(&self, probe: &StageProbe) -> Result<()> {
+        probe.socket("controller", &self.controller)?;
+        probe.socket("device", &self.device)
+    }
+
+    /// Sends a message from the controller and returns it as the device received it.
+    pub async fn to_device(&self, message: &[u8]) -> Result<Vec<u8>> {
+        deliver(&self.controller, &self.device, message).await
+    }
+
+    /// Sends a message from the device and returns it as the controller received it.
+    pub async fn to_controller(&self, message: &[u8]) -> Result<Vec<u8>> {
+        deliver(&self.device, &self.controller, message).await
+    }
+}
+
+async fn deliver(from: &UdpSocket, to: &UdpSocket, message: &[u8]) -> Result<Vec<u8>> {
+    let mut buffer = vec![0u8; message.len().max(1)];
+    from.send(message).await?;
+    let received = to.recv(&mut buffer).await?;
+    if received != message.len() {
+        return Err(anyhow!("loopback delivered {} of {} bytes", received, message.len()));
+    }
+    buffer.truncate(received);
+    Ok(buffer)
+}
+
+/// Calibrated phase times filled in by the stages.
+#[derive(Debug, Clone, Default)]
+pub struct LayerTimings {
+    pub transport_ms: f64,
+    /// The PTR query and its parsed response, part of `transport_ms`.
+    pub mdns_discovery: DiscoveryTiming,
+    /// `connect` until the peer acknowledged the accept and echoed a first message.
+    pub tcp_connection_ms: f64,
+    pub tcp_connection_setup: ConnectionSetup,
+    pub commissioning_ms: f64,
+    pub cluster_setup_ms: f64,
+    pub service_discovery_ms: f64,
+}
+
+pub struct TransportStage;
+pub struct SessionStage;
+pub struct ApplicationStage;
+pub struct PresentationStage;
+
+impl LayerStage for TransportStage {
+    fn name(&self) -> &'static str {
+        "transport"
+    }
+
+    fn run<'a>(&'a self, context: &'a mut LayerContext) -> StageFuture<'a> {
+        Box::pin(async move {
+            println!("📡 Measuring Matter transport layer...");
+            let (probe, policy) = (probe(context), retry_policy(context));
+            let responder = fixtures::mdns_responder_on(reserved(context)?.take_udp(MDNS_RESPONDER_PORT)?, ServiceAdvertisement::default())?;
+            let discovery_socket = UdpSocket::bind("127.0.0.1:0").await?;
+            probe.socket("mdns", &discovery_socket)?;
+            let phase = Phase::start();
+            let pending = probe.pending("loopback exchange and mdns discovery");
+            let probe = &probe;
+            let link = policy
+                .run("loopback link", || async move {
+                    let link = LoopbackLink::open().await?;
+                    link.watch(probe)?;
+                    link.exchange(MESSAGE_HEADER_BYTES).await?;
+                    Ok(link)
+                })
+                .await?;
+            let discovery = policy
+                .run("mdns discovery", || mdns::discover(&discovery_socket, responder.addr, DISCOVERY_TIMEOUT))
+                .await?;
+            drop(pending);
+            retries(context).record("udp_discovery_time_ms", link.retries + discovery.retries);
+            context.insert(link.value);
+            let elapsed = phase.finish(context, "transport")?;
+            timings(context).transport_ms = elapsed;
+
+            let (_, mut discovery) = discovery.value;
+            discovery.round_trip_ms = context.require::<CalibrationMetrics>()?.correct_ms(discovery.round_trip_ms);
+            println!("🔎 mDNS discovery: {:.3}ms query to response + {:.3}ms parse ({} records, {} bytes)",
+                     discovery.round_trip_ms, discovery.parse_ms, discovery.records, discovery.response_bytes);
+            timings(context).mdns_discovery = discovery;
+
+            // The same read the application stage later sends over the UDP session
+            let request = tlv(|w| read_request(w, &[DESCRIPTOR_SERVER_LIST]))?;
+            let report = tlv(|w| server_list_report(w, &ENDPOINT_1_SERVERS))?;
+            let (request, report) = (&request, &report);
+            let setup = policy.run("tcp connection", || ConnectionSetup::measure(request, report)).await?;
+            retries(context).record("tcp_connection_setup", setup.retries);
+            let setup = setup.value.corrected(context.require::<CalibrationMetrics>()?);
+            println!("🔗 TCP setup: {:.3}ms handshake{} + {:.3}ms security + {:.3}ms to first application byte",
+                     setup.tcp_handshake_ms,
+                     setup.syn_rtt_ms.map_or(String::new(), |rtt| format!(" (SYN RTT {:.3}ms)", rtt)),
+                     setup.security_handshake_ms, setup.first_application_byte_ms);
+            timings(context).tcp_connection_setup = setup;
+
+            // Only a peer that accepted counts as connected; the kernel finishes the handshake for any listener
+            let peer = fixtures::tcp_peer_on(reserved(context)?.take_tcp(TCP_PEER_PORT)?)?;
+            let header = MessageHeader { source_node_id: Some(CONTROLLER_NODE_ID), ..MessageHeader::default() };
+            let probe = message::encode(&header, &ProtocolHeader::request(PROTOCOL_SECURE_CHANNEL, PBKDF_PARAM_REQUEST, 1), &[0x15, 0x18]);
+            let (peer_addr, probe) = (peer.addr, &probe);
+            let connection = policy
+                .run("tcp peer", || async move {
+                    fixtures::connect_to_peer(peer_addr, probe, PEER_TIMEOUT).await.map(|(_, timing)| timing)
+                })
+                .await?;
+            retries(context).record("tcp_connection_time_ms", connection.retries);
+            let connection = connection.value;
+            let tcp_connection_ms = context.require::<CalibrationMetrics>()?.correct_ms(connection.total_ms());
+            println!("🤝 TCP connection: {:.3}ms ({:.3}ms connect, {:.3}ms accept acknowledgement, {:.3}ms first round trip)",
+                     tcp_connection_ms, connection.connect_ms, connection.accept_ack_ms, connection.round_trip_ms);
+            timings(context).tcp_connection_ms = tcp_connection_ms;
+            Ok(())
+        })
+    }
+}
+
+impl LayerStage for SessionStage {
+    fn name(&self) -> &'static str {
+        "session"
+    }
+
+    fn depends_on(&self) -> &[&'static str] {
+        &["transport"]
+    }
+
+    fn run<'a>(&'a self, context: &'a mut LayerContext) -> StageFuture<'a> {
+        Box::pin(async move {
+            println!("🔐 Measuring Matter commissioning...");
+            let (probe, policy) = (probe(context), retry_policy(context));
+            let phase = Phase::start();
+            let link = context.take::<LoopbackLink>().ok_or_else(|| anyhow!("no loopback link from the transport stage"))?;
+            link.watch(&probe)?;
+            let pending = probe.pending("secure session handshake");
+            let handshake = policy.run("secure session handshake", || SecureSession::handshake(&link)).await?;
+            drop(pending);
+            retries(context).record("commissioning_time_ms", handshake.retries);
+            context.insert(SecureSession::new(link, SESSION_ID, handshake.value));
+            let elapsed = phase.finish(context, "commissioning")?;
+            timings(context).commissioning_ms = elapsed;
+            Ok(())
+        })
+    }
+}
+
+impl LayerStage for ApplicationStage {
+    fn name(&self) -> &'static str {
+        "application"
+    }
+
+    fn depends_on(&self) -> &[&'static str] {
+        &["session"]
+    }
+
+    fn run<'a>(&'a self, context: &'a mut LayerContext) -> StageFuture<'a> {
+        Box::pin(async move {
+            println!("🔧 Measuring cluster setup...");
+            let request = tlv(|w| read_request(w, &REFERENCE_ATTRIBUTES))?;
+            let report = tlv(encode_reference_report)?;
+            let (probe, policy) = (probe(context), retry_policy(context));
+            session(context)?.watch(&probe)?;
+            let phase = Phase::start();
+            let pending = probe.pending("reference attribute read");
+            let mut attempts = policy.attempts("reference attribute read");
+            while attempts.attempt(session(context)?.request(&request, &report)).await?.is_none() {}
+            drop(pending);
+            retries(context).record("cluster_initialization_time_ms", attempts.retries());
+            let elapsed = phase.finish(context, "cluster_setup")?;
+            timings(context).cluster_setup_ms = elapsed;
+
+            println!("🎯 Measuring service discovery...");
+            let request = tlv(|w| read_request(w, &[DESCRIPTOR_SERVER_LIST]))?;
+            let report = tlv(|w| server_list_report(w, &ENDPOINT_1_SERVERS))?;
+            let phase = Phase::start();
+            let pending = probe.pending("descriptor server list read");
+            let mut attempts = policy.attempts("descriptor server list read");
+            while attempts.attempt(session(context)?.request(&request, &report)).await?.is_none() {}
+            drop(pending);
+            retries(context).record("discovery_time_ms", attempts.retries());
+            let elapsed = phase.finish(context, "service_discovery")?;
+            timings(context).service_discovery_ms = elapsed;
+            Ok(())
+        })
+    }
+}
+
+impl LayerStage for PresentationStage {
+    fn name(&self) -> &'static str {
+        "presentation"
+    }
+
+    fn run<'a>(&'a self, context: &'a mut LayerContext) -> StageFuture<'a> {
+        Box::pin(async move {
+            println!("🧾 Benchmarking Matter TLV encoding...");
+            let mut tlv_buffer = [0u8; REFERENCE_REPORT_CAPACITY];
+            let encoding = benchmark_tlv_encoding(&StdClock::new(), &mut tlv_buffer, TLV_ITERATIONS)
+                .map_err(|e| anyhow!("TLV benchmark failed: {:?}", e))?;
+            context.insert::<PresentationSample>(encoding);
+            Ok(())
+        })
+    }
+}
+
+/// The built-in Matter layers.
+pub fn matter_pipeline() -> LayerPipeline {
+    LayerPipeline::new()
+        .stage(TransportStage)
+        .stage(SessionStage)
+        .stage(ApplicationStage)
+        .stage(PresentationStage)
+}
+
+/// The watchdog's probe for the running stage, or a detached one when no watchdog is running it.
+fn probe(context: &LayerContext) -> StageProbe {
+    context.get::<StageProbe>().cloned().unwrap_or_default()
+}
+
+fn retry_policy(context: &LayerContext) -> RetryPolicy {
+    context.get::<RetryPolicy>().cloned().unwrap_or_default()
+}
+
+fn retries(context: &mut LayerContext) -> &mut RetryCounts {
+    if context.get::<RetryCounts>().is_none() {
+        context.insert(RetryCounts::default());
+    }
+    context.get_mut().expect("inserted above")
+}
+
+/// The context's port allocator, with the transport stage's ports reserved in it.
+fn reserved(context: &mut LayerContext) -> Result<&mut PortAllocator> {
+    if context.get::<PortAllocator>().is_none() {
+        context.insert(PortAllocator::loopback());
+    }
+    let ports: &mut PortAllocator = context.get_mut().expect("inserted above");
+    for name in [MDNS_RESPONDER_PORT, TCP_PEER_PORT] {
+        if ports.addr(name).is_none() {
+            ports.reserve(name)?;
+        }
+    }
+    Ok(ports)
+}
+
+fn session(context: &mut LayerContext) -> Result<&mut SecureSession> {
+    context.get_mut().ok_or_else(|| anyhow!("no secure session from the session stage"))
+}
+
+fn tlv(encode: impl FnOnce(&mut TlvWriter<'_>) -> Result<(), TlvError>) -> Result<Vec<u8>> {
+    let mut buffer = [0u8; REFERENCE_REPORT_CAPACITY];
+    let mut writer = TlvWriter::new(&mut buffer);
+    encode(&mut writer).map_err(|e| anyhow!("TLV encoding failed: {:?}", e))?;
+    Ok(writer.as_bytes().to_vec())
+}
+
+/// ReadRequest with one attribute path per `(endpoint, cluster, attribute)`.
+fn read_request(writer: &mut TlvWriter<'_>, paths: &[(u16, u32, u32)]) -> Result<(), TlvError> {
+    writer.start_struct(Tag::Anonymous)?;
+    writer.start_array(Tag::Context(0))?;
+    for &(endpoint, cluster, attribute) in paths {
+        writer.start_list(Tag::Anonymous)?;
+        writer.u16(Tag::Context(2), endpoint)?;
+        writer.u32(Tag::Context(3), cluster)?;
+        writer.u32(Tag::Context(4), attribute)?;
+        writer.end_container()?;
+    }
+    writer.end_container()?;
+    writer.bool(Tag::Context(3), true)?;
+    writer.end_container()
+}
+
+/// ReportData carrying the Descriptor ServerList.
+fn server_list_report(writer: &mut TlvWriter<'_>, clusters: &[u32]) -> Result<(), TlvError> {
+    let (endpoint, cluster, attribute) = DESCRIPTOR_SERVER_LIST;
+    writer.start_struct(Tag::Anonymous)?;
+    writer.start_array(Tag::Context(1))?;
+    writer.start_struct(Tag::Anonymous)?;
+    writer.start_list(Tag::Context(1))?;
+    writer.u16(Tag::Context(2), endpoint)?;
+    writer.u32(Tag::Context(3), cluster)?;
+    writer.u32(Tag::Context(4), attribute)?;
+    writer.end_container()?;
+    writer.start_array(Tag::Context(2))?;
+    for &server in clusters {
+        writer.u32(Tag::Anonymous, server)?;
+    }
+    writer.end_container()?;
+    writer.end_container()?;
+    writer.end_container()?;
+    writer.end_container()
+}
+
+fn timings(context: &mut LayerContext) -> &mut LayerTimings {
+    if context.get::<LayerTimings>().is_none() {
+        context.insert(LayerTimings::default());
+    }
+    context.get_mut().expect("inserted above")
+}
+
+struct Phase {
+    start: Instant,
+}
+
+impl Phase {
+    fn start() -> Self {
+        Self { start: Instant::now() }
+    }
+
+    /// Records the phase's energy and returns its calibrated time.
+    fn finish(self, context: &mut LayerContext, phase: &str) -> Result<f64> {
+        let end = Instant::now();
+        if let Some(sampler) = context.get_mut::<PowerSampler>() {
+            sampler.record_phase(phase, self.start, end);
+        }
+        let measured_ms = end.duration_since(self.start).as_micros() as f64 / 1000.0;
+        Ok(context.require::<CalibrationMetrics>()?.correct_ms(measured_ms))
+    }
+}
@@ -0,0 +1,68 @@
+// matter-project/src/lib.rs
+/*!
+Shared analyzer components used by the analyzer and agent binaries
+
+Protocol-independent result handling lives in the `analysis-core` crate.
+*/
+
+pub mod access_control;
+pub mod asymmetry;
+pub mod attestation;
+pub mod auth_cost;
+pub mod bacnet;
+pub mod bdx;
+pub mod bridge;
+pub mod buffer_pool;
+pub mod builder;
+pub mod calibration;
+pub mod cellular;
+pub mod coap_bindings;
+pub mod cli;
+pub mod commissioning_faults;
+pub mod concurrency;
+pub mod congestion;
+pub mod connection_setup;
+pub mod contention;
+pub mod cpu_cost;
+pub mod dds;
+pub mod distributed;
+pub mod end_to_end;
+pub mod fan_out;
+pub mod feature_matrix;
+pub mod firmware_update;
+pub mod fixtures;
+pub mod footprint;
+pub mod gateway;
+pub mod handshake;
+pub mod home_assistant;
+pub mod icd;
+pub mod interoperability;
+pub mod large_payload;
+pub mod layers;
+pub mod lowpan;
+pub mod mdns;
+pub mod message;
+pub mod mqtt_sn;
+pub mod naming;
+pub mod netsim;
+pub mod power;
+pub mod reference;
+pub mod rekey;
+pub mod reordering;
+pub mod replay;
+pub mod robustness;
+pub mod saturation;
+pub mod scenarios;
+pub mod secure_session;
+pub mod sigfox;
+pub mod snmp;
+pub mod socket_options;
+pub mod start_modes;
+pub mod stream;
+pub mod telemetry;
+pub mod throughput;
+pub mod topology;
+pub mod transport_modes;
+pub mod wisun;
+pub mod workload;
+pub mod zwave;
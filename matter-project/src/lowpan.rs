@@ -0,0 +1,367 @@
+// matter-project/src/lowpan.rs
+/*!
+6LoWPAN - IPHC/NHC header compression and 802.15.4 framing of each protocol's UDP/IPv6 messages
+
+The other benchmarks count IPv4 and UDP headers, as on Ethernet or Wi-Fi.
+A Thread device sends IPv6 over 802.15.4 instead. Its 127-byte frames carry
+a MAC header with security, RFC 6282 compressed IPv6 and UDP headers and,
+across routers, a mesh header. A datagram that does not fit is split
+into RFC 4944 fragments. This model computes those sizes for three kinds of
+path:
+
+- link-local: neighbours using fe80:: addresses derived from their short
+  MAC addresses. IPHC elides both addresses.
+- mesh-local: ML-EIDs across Thread routers. The prefix comes from context
+  0 and both 64-bit interface IDs are carried inline.
+- off-mesh: a device talking through the border router to a controller on
+  Wi-Fi or Ethernet. The OMR prefix comes from context 1, which costs a CID
+  byte. The controller's full address is carried inline.
+
+Traffic class, flow label and hop limit are always elided. UDP ports are
+carried inline unless they fall in the 0xF0Bx range, and the UDP checksum
+is always kept. TCP has no NHC encoding, so its header goes inline. MAC
+frames use short addresses, PAN ID compression and the 6-byte auxiliary
+security header with a 4-byte MIC. On-air bytes also count the PHY preamble,
+SFD and length, but not MAC acks or CSMA backoff. The topology and ICD
+models use the same framing for their Thread hops.
+*/
+
+use anyhow::Result;
+use metrics_core::tlv::Tag;
+use serde::{Deserialize, Serialize};
+
+use crate::bdx::{matter_message, MRP_STANDALONE_ACK, PROTOCOL_SECURE_CHANNEL};
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{
+    coap_message, tlv, uri_path, COAP_ACK, COAP_CHANGED, COAP_CON, COAP_NON, COAP_POST, COAP_PUT, IM_INVOKE_REQUEST,
+    IM_INVOKE_RESPONSE, IM_REPORT_DATA, IM_STATUS_RESPONSE, PROTOCOL_IM,
+};
+use crate::mdns::MATTER_PORT;
+use crate::start_modes::{invoke_request, invoke_response};
+use crate::transport_modes::IPV4_UDP_HEADER_BYTES;
+
+pub(crate) const COAP_PORT: u16 = 5683;
+
+const IPV6_HEADER_BYTES: usize = 40;
+const UDP_HEADER_BYTES: usize = 8;
+const TCP_HEADER_BYTES: usize = 20;
+/// IPHC dispatch and encoding fields.
+const IPHC_BASE_BYTES: usize = 2;
+const IPHC_CID_BYTES: usize = 1;
+/// IPHC next header carried inline, for headers without an NHC encoding.
+const IPHC_NEXT_HEADER_BYTES: usize = 1;
+const NHC_UDP_BYTES: usize = 1;
+const UDP_CHECKSUM_BYTES: usize = 2;
+/// Mesh addressing header with short originator and final addresses.
+const MESH_HEADER_BYTES: usize = 5;
+const FRAG1_HEADER_BYTES: usize = 4;
+const FRAGN_HEADER_BYTES: usize = 5;
+/// Frame control, sequence number, destination PAN and two short addresses.
+const MAC_HEADER_BYTES: usize = 9;
+/// Security control, frame counter and key index, then MIC-32.
+const MAC_SECURITY_BYTES: usize = 6 + 4;
+const MAC_FCS_BYTES: usize = 2;
+/// Preamble, SFD and PHY header.
+const PHY_HEADER_BYTES: usize = 6;
+const MAX_PSDU_BYTES: usize = 127;
+
+/// Where a datagram's addresses come from, which decides what IPHC can elide.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LowpanPath {
+    LinkLocal,
+    MeshLocal,
+    OffMesh,
+}
+
+impl LowpanPath {
+    pub(crate) const ALL: [LowpanPath; 3] = [Self::LinkLocal, Self::MeshLocal, Self::OffMesh];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::LinkLocal => "link-local",
+            Self::MeshLocal => "mesh-local",
+            Self::OffMesh => "off-mesh",
+        }
+    }
+
+    /// Inline source and destination address bytes, and whether a CID byte is needed.
+    fn addresses(self) -> (usize, usize, bool) {
+        match self {
+            Self::LinkLocal => (0, 0, false),
+            Self::MeshLocal => (8, 8, false),
+            Self::OffMesh => (8, 16, true),
+        }
+    }
+
+    fn mesh_header_bytes(self) -> usize {
+        match self {
+            Self::LinkLocal => 0,
+            Self::MeshLocal | Self::OffMesh => MESH_HEADER_BYTES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LowpanTransport {
+    Udp { source_port: u16, destination_port: u16 },
+    Tcp,
+}
+
+impl LowpanTransport {
+    pub(crate) fn udp(port: u16) -> Self {
+        Self::Udp { source_port: port, destination_port: port }
+    }
+
+    fn uncompressed_bytes(self) -> usize {
+        IPV6_HEADER_BYTES
+            + match self {
+                Self::Udp { .. } => UDP_HEADER_BYTES,
+                Self::Tcp => TCP_HEADER_BYTES,
+            }
+    }
+}
+
+/// A datagram on 802.15.4.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct LowpanFrames {
+    pub frames: usize,
+    /// Compressed IPv6 and transport headers.
+    pub header_bytes: usize,
+    /// Every frame with its PHY, MAC, mesh and fragment headers.
+    pub on_air_bytes: usize,
+}
+
+fn udp_port_bytes(source: u16, destination: u16) -> usize {
+    let nibble = |port: u16| port & 0xFFF0 == 0xF0B0;
+    let byte = |port: u16| port & 0xFF00 == 0xF000;
+    if nibble(source) && nibble(destination) {
+        1
+    } else if byte(source) || byte(destination) {
+        3
+    } else {
+        4
+    }
+}
+
+/// IPHC and NHC bytes that replace the IPv6 and transport headers.
+fn compressed_header_bytes(path: LowpanPath, transport: LowpanTransport) -> usize {
+    let (source, destination, cid) = path.addresses();
+    let next = match transport {
+        LowpanTransport::Udp { source_port, destination_port } => {
+            NHC_UDP_BYTES + udp_port_bytes(source_port, destination_port) + UDP_CHECKSUM_BYTES
+        }
+        LowpanTransport::Tcp => IPHC_NEXT_HEADER_BYTES + TCP_HEADER_BYTES,
+    };
+    IPHC_BASE_BYTES + if cid { IPHC_CID_BYTES } else { 0 } + source + destination + next
+}
+
+/// Frames and on-air bytes of a datagram with `payload` bytes above the transport header.
+pub(crate) fn lowpan_frames(path: LowpanPath, transport: LowpanTransport, payload: usize) -> LowpanFrames {
+    let header_bytes = compressed_header_bytes(path, transport);
+    let per_frame = PHY_HEADER_BYTES + MAC_HEADER_BYTES + MAC_SECURITY_BYTES + MAC_FCS_BYTES + path.mesh_header_bytes();
+    let room = MAX_PSDU_BYTES - (per_frame - PHY_HEADER_BYTES);
+    if header_bytes + payload <= room {
+        return LowpanFrames { frames: 1, header_bytes, on_air_bytes: per_frame + header_bytes + payload };
+    }
+
+    // Fragment offsets count the uncompressed datagram in 8-byte units, so
+    // every fragment but the last ends on an 8-byte boundary of it
+    let uncompressed = transport.uncompressed_bytes();
+    let first_room = room - FRAG1_HEADER_BYTES - header_bytes;
+    let first = ((uncompressed + first_room) / 8 * 8).saturating_sub(uncompressed);
+    let rest = (room - FRAGN_HEADER_BYTES) / 8 * 8;
+    let frames = 1 + payload.saturating_sub(first).div_ceil(rest);
+    LowpanFrames {
+        frames,
+        header_bytes,
+        on_air_bytes: frames * per_frame + FRAG1_HEADER_BYTES + (frames - 1) * FRAGN_HEADER_BYTES + header_bytes + payload,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LowpanConfig {
+    /// Attribute value sizes carried by the reports, from one frame to several.
+    pub report_values: Vec<usize>,
+    pub rate_kbit: f64,
+}
+
+impl Default for LowpanConfig {
+    fn default() -> Self {
+        Self { report_values: vec![4, 64, 256], rate_kbit: 250.0 }
+    }
+}
+
+analyzer_builder!(LowpanAnalyzerBuilder => LowpanAnalyzer(LowpanConfig) {
+    report_values: Vec<usize>,
+    rate_kbit: f64,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LowpanMetrics {
+    pub paths: Vec<LowpanPathSummary>,
+    pub messages: Vec<LowpanMessage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LowpanPathSummary {
+    pub path: String,
+    /// IPv6 and UDP headers, uncompressed.
+    pub uncompressed_header_bytes: usize,
+    pub compressed_header_bytes: usize,
+    /// PHY, MAC with security and mesh header of every frame.
+    pub frame_overhead_bytes: usize,
+    /// UDP payload that still fits one frame.
+    pub single_frame_payload_bytes: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LowpanMessage {
+    pub protocol: String,
+    pub message: String,
+    pub path: String,
+    pub message_bytes: usize,
+    pub ipv4_bytes: usize,
+    pub ipv6_bytes: usize,
+    pub frames: usize,
+    pub on_air_bytes: usize,
+    pub airtime_ms: f64,
+}
+
+pub struct LowpanAnalyzer {
+    config: LowpanConfig,
+}
+
+impl LowpanAnalyzer {
+    pub fn new(config: LowpanConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_lowpan(&mut self) -> Result<LowpanMetrics> {
+        println!("\n🗜️ Analyzing 6LoWPAN Header Compression over 802.15.4");
+        println!("-----------------------------------------------------");
+
+        let udp = LowpanTransport::udp(MATTER_PORT);
+        let mut paths = Vec::new();
+        for path in LowpanPath::ALL {
+            let empty = lowpan_frames(path, udp, 0);
+            let overhead = empty.on_air_bytes - empty.header_bytes;
+            let summary = LowpanPathSummary {
+                path: path.name().to_string(),
+                uncompressed_header_bytes: udp.uncompressed_bytes(),
+                compressed_header_bytes: empty.header_bytes,
+                frame_overhead_bytes: overhead,
+                single_frame_payload_bytes: MAX_PSDU_BYTES + PHY_HEADER_BYTES - overhead - empty.header_bytes,
+            };
+            println!("✅ {}: IPv6+UDP {}B → {}B, {}B per frame on air, {}B of payload fit one frame",
+                     summary.path, summary.uncompressed_header_bytes, summary.compressed_header_bytes,
+                     summary.frame_overhead_bytes, summary.single_frame_payload_bytes);
+            paths.push(summary);
+        }
+
+        let mut messages = Vec::new();
+        for (protocol, message, port, bytes) in self.messages()? {
+            let mut cells = Vec::new();
+            for path in LowpanPath::ALL {
+                let frames = lowpan_frames(path, LowpanTransport::udp(port), bytes);
+                let row = LowpanMessage {
+                    protocol: protocol.to_string(),
+                    message: message.clone(),
+                    path: path.name().to_string(),
+                    message_bytes: bytes,
+                    ipv4_bytes: bytes + IPV4_UDP_HEADER_BYTES,
+                    ipv6_bytes: bytes + udp.uncompressed_bytes(),
+                    frames: frames.frames,
+                    on_air_bytes: frames.on_air_bytes,
+                    airtime_ms: frames.on_air_bytes as f64 * 8.0 / self.config.rate_kbit,
+                };
+                cells.push(format!("{} {}×{}B {:.1}ms", row.path, row.frames, row.on_air_bytes, row.airtime_ms));
+                messages.push(row);
+            }
+            println!("✅ {} {}: {}B (IPv4 {}B, IPv6 {}B) → {}",
+                     protocol, message, bytes, bytes + IPV4_UDP_HEADER_BYTES, bytes + udp.uncompressed_bytes(),
+                     cells.join(" | "));
+        }
+
+        Ok(LowpanMetrics { paths, messages })
+    }
+
+    /// (protocol, message, UDP port, message bytes) of each message the model frames.
+    fn messages(&self) -> Result<Vec<(&'static str, String, u16, usize)>> {
+        let mut messages = Vec::new();
+        for &size in &self.config.report_values {
+            let value = vec![0x42u8; size];
+            let report = tlv(|writer| {
+                writer.start_struct(Tag::Anonymous)?;
+                writer.u32(Tag::Context(0), 1)?; // SubscriptionId
+                writer.start_array(Tag::Context(1))?;
+                writer.start_struct(Tag::Anonymous)?;
+                writer.start_struct(Tag::Context(1))?; // AttributeDataIB
+                writer.u32(Tag::Context(0), 1)?; // DataVersion
+                writer.start_list(Tag::Context(1))?;
+                writer.u16(Tag::Context(2), 1)?;
+                writer.u32(Tag::Context(3), 0x0402)?;
+                writer.u32(Tag::Context(4), 0x0000)?;
+                writer.end_container()?;
+                writer.octets(Tag::Context(2), &value)?;
+                writer.end_container()?;
+                writer.end_container()?;
+                writer.end_container()?;
+                writer.u8(Tag::Context(255), 11)?;
+                writer.end_container()
+            })?;
+            messages.push((
+                "Matter",
+                format!("ReportData {}B", size),
+                MATTER_PORT,
+                matter_message(PROTOCOL_IM, IM_REPORT_DATA, &report).len(),
+            ));
+            messages.push((
+                "CoAP",
+                format!("NON report {}B", size),
+                COAP_PORT,
+                coap_message(COAP_NON, COAP_POST, 1, &uri_path(&["sensors", "temp"]), &value).len(),
+            ));
+        }
+
+        let status = matter_message(PROTOCOL_IM, IM_STATUS_RESPONSE, &[0x15, 0x24, 0x00, 0x00, 0x18]);
+        messages.extend([
+            ("Matter", "InvokeRequest".to_string(), MATTER_PORT, matter_message(PROTOCOL_IM, IM_INVOKE_REQUEST, &tlv(invoke_request)?).len()),
+            ("Matter", "InvokeResponse".to_string(), MATTER_PORT, matter_message(PROTOCOL_IM, IM_INVOKE_RESPONSE, &tlv(invoke_response)?).len()),
+            ("Matter", "StatusResponse".to_string(), MATTER_PORT, status.len()),
+            ("Matter", "standalone ack".to_string(), MATTER_PORT, matter_message(PROTOCOL_SECURE_CHANNEL, MRP_STANDALONE_ACK, &[]).len()),
+            ("CoAP", "CON PUT".to_string(), COAP_PORT, coap_message(COAP_CON, COAP_PUT, 2, &uri_path(&["light", "on"]), b"1").len()),
+            ("CoAP", "2.04 ACK".to_string(), COAP_PORT, coap_message(COAP_ACK, COAP_CHANGED, 2, &[], &[]).len()),
+        ]);
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iphc_shrinks_headers_and_large_datagrams_fragment() {
+        let udp = LowpanTransport::udp(MATTER_PORT);
+        // Dispatch, NHC byte, inline ports and checksum
+        assert_eq!(lowpan_frames(LowpanPath::LinkLocal, udp, 10).header_bytes, 2 + 1 + 4 + 2);
+        assert_eq!(lowpan_frames(LowpanPath::OffMesh, udp, 10).header_bytes, 2 + 1 + 8 + 16 + 7);
+        assert_eq!(udp_port_bytes(0xF0B1, 0xF0B2), 1);
+
+        let path = LowpanPath::MeshLocal;
+        let single = lowpan_frames(path, udp, 50);
+        assert_eq!(single.frames, 1);
+        assert_eq!(single.on_air_bytes, 6 + 9 + 10 + 2 + 5 + single.header_bytes + 50);
+
+        let large = lowpan_frames(path, udp, 300);
+        assert_eq!(large.frames, 4);
+        assert!(large.on_air_bytes > 300 + 4 * (6 + 9 + 10 + 2 + 5));
+
+        let metrics = LowpanAnalyzer::builder().report_values(vec![4usize, 256]).build().analyze_lowpan().unwrap();
+        let ack = metrics.messages.iter().find(|m| m.message == "standalone ack" && m.path == "link-local").unwrap();
+        assert_eq!(ack.frames, 1);
+        assert!(ack.on_air_bytes - ack.message_bytes < ack.ipv6_bytes - ack.message_bytes);
+        let report = metrics.messages.iter().find(|m| m.message == "ReportData 256B" && m.path == "off-mesh").unwrap();
+        assert!(report.frames >= 3);
+    }
+}
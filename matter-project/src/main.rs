@@ -1,31 +1,173 @@
 // Simplified Matter Protocol Analyzer - Working Version
 use std::time::{Duration, Instant};
 use serde::{Deserialize, Serialize};
-use std::thread;
+use analysis_core::baseline::{BaselineComparison, BaselineConfig, BaselineManager, BaselineStore};
+use analysis_core::budget::{BudgetCheck, BudgetPolicy, OVER_BUDGET_EXIT_CODE};
+use analysis_core::campaign::CampaignMerger;
+use analysis_core::diff::ResultDiff;
+use analysis_core::events::{EventLog, Outcome};
+use analysis_core::export::ResultExport;
+use analysis_core::impairment::ImpairmentMatrix;
+use analysis_core::integrity::IntegrityTool;
+use analysis_core::netns::{self, Namespace, NetnsConfig, NAMESPACE_SECTION, NETNS_ENV};
+use analysis_core::package::DatasetPackager;
+use analysis_core::pipeline::LayerContext;
+use analysis_core::platform::TestEnvironment;
+use analysis_core::ports::PortAllocator;
+use analysis_core::plugin::{Plugin, PluginConfig, PluginTool, REQUIRED_SECTIONS};
+use analysis_core::query::ResultQuery;
+use analysis_core::redact::{RedactTool, Redactor};
+use analysis_core::results_store::{ResultsStore, SavedRun};
+use analysis_core::retry::RetryCounts;
+use analysis_core::run_metadata::{tags_text, RunMetadata};
+use analysis_core::samples;
+use analysis_core::schema::RESULT_SCHEMA_VERSION;
+use analysis_core::scoring::{efficiency_score, EfficiencyInputs};
+use analysis_core::trends::TrendAnalyzer;
+use analysis_core::tunables::{self, NetworkTunables};
+use analysis_core::units::Units;
+use analysis_core::watchdog::{StageFailure, Watchdog};
+use matter_research_analyzer::access_control::{AclOverheadAnalyzer, AclOverheadMetrics};
+use matter_research_analyzer::asymmetry::{AsymmetryAnalyzer, AsymmetryMetrics};
+use matter_research_analyzer::attestation::{AttestationAnalyzer, AttestationMetrics};
+use matter_research_analyzer::auth_cost::{AuthCostAnalyzer, AuthCostMetrics};
+use matter_research_analyzer::bacnet::{BacnetAnalyzer, BacnetMetrics};
+use matter_research_analyzer::bdx::{BdxAnalyzer, BdxMetrics};
+use matter_research_analyzer::bridge::{BridgeAnalyzer, BridgeMetrics};
+use matter_research_analyzer::calibration::{CalibrationMetrics, TimingCalibrator};
+use matter_research_analyzer::cellular::{CellularAnalyzer, CellularMetrics};
+use matter_research_analyzer::cli::CliOptions;
+use matter_research_analyzer::coap_bindings::{CoapBindingComparison, CoapBindingMetrics};
+use matter_research_analyzer::commissioning_faults::{CommissioningFaultInjector, CommissioningFaultMetrics};
+use matter_research_analyzer::concurrency::{ConcurrencyMetrics, ConcurrencyProbe};
+use matter_research_analyzer::congestion::{CongestionAnalyzer, CongestionMetrics};
+use matter_research_analyzer::connection_setup::ConnectionSetup;
+use matter_research_analyzer::contention::{ContentionMetrics, ContentionModel};
+use matter_research_analyzer::cpu_cost::{CpuCostAnalyzer, CpuCostMetrics};
+use matter_research_analyzer::dds::{DdsAnalyzer, DdsMetrics};
+use matter_research_analyzer::distributed::{DistributedCoordinator, DistributedMetrics};
+use matter_research_analyzer::end_to_end::{EndToEndAnalyzer, EndToEndMetrics};
+use matter_research_analyzer::fan_out::{FanOutAnalyzer, FanOutMetrics};
+use matter_research_analyzer::feature_matrix::{FeatureMatrixAnalyzer, FeatureMatrixMetrics};
+use matter_research_analyzer::firmware_update::{FirmwareUpdateBenchmark, FirmwareUpdateMetrics};
+use matter_research_analyzer::footprint::{FootprintAnalyzer, FootprintMetrics};
+use matter_research_analyzer::gateway::{GatewayAnalyzer, GatewayMetrics};
+use matter_research_analyzer::handshake::{HandshakeAnalyzer, HandshakeMetrics};
+use matter_research_analyzer::home_assistant::{HomeAssistantAnalyzer, HomeAssistantMetrics};
+use matter_research_analyzer::icd::{IcdAnalyzer, IcdMetrics};
+use matter_research_analyzer::interoperability::{InteroperabilityAnalyzer, InteroperabilityMetrics};
+use matter_research_analyzer::large_payload::{LargePayloadBenchmark, LargePayloadMetrics};
+use matter_research_analyzer::layers::{matter_pipeline, LayerTimings, APPLICATION_OVERHEAD_BYTES, PAIRING_OVERHEAD_BYTES};
+use matter_research_analyzer::lowpan::{LowpanAnalyzer, LowpanMetrics};
+use matter_research_analyzer::mdns::DiscoveryTiming;
+use matter_research_analyzer::message::HeaderOverhead;
+use matter_research_analyzer::mqtt_sn::{MqttSnAnalyzer, MqttSnMetrics};
+use matter_research_analyzer::naming::{NamingAnalyzer, NamingMetrics};
+use matter_research_analyzer::power::{EnergyMetrics, PowerSampler};
+use matter_research_analyzer::reference::ReferenceCompare;
+use matter_research_analyzer::rekey::{RekeyAnalyzer, RekeyMetrics};
+use matter_research_analyzer::reordering::{ReorderAnalyzer, ReorderMetrics};
+use matter_research_analyzer::replay::{ReplayAnalyzer, ReplayMetrics};
+use matter_research_analyzer::robustness::{RobustnessAnalyzer, RobustnessMetrics};
+use matter_research_analyzer::saturation::{SaturationAnalyzer, SaturationMetrics};
+use matter_research_analyzer::scenarios::{ScenarioAnalyzer, ScenarioMetrics};
+use matter_research_analyzer::secure_session::{SecureSession, SessionStats};
+use matter_research_analyzer::sigfox::{SigfoxAnalyzer, SigfoxMetrics};
+use matter_research_analyzer::snmp::{SnmpAnalyzer, SnmpMetrics};
+use matter_research_analyzer::socket_options::{SocketMatrixMetrics, SocketOptionMatrix};
+use matter_research_analyzer::start_modes::{StartModeAnalyzer, StartModeMetrics};
+use matter_research_analyzer::stream::MeasurementStream;
+use matter_research_analyzer::telemetry::{TelemetryMetrics, TelemetryStreamer};
+use matter_research_analyzer::throughput::{ThroughputMetrics, ThroughputReceiver, ThroughputTester};
+use matter_research_analyzer::topology::{TopologyAnalyzer, TopologyMetrics};
+use matter_research_analyzer::transport_modes::{TransportModeComparison, TransportModeMetrics, IPV4_UDP_HEADER_BYTES};
+use matter_research_analyzer::wisun::{WisunAnalyzer, WisunMetrics};
+use matter_research_analyzer::workload::{WorkloadGenerator, WorkloadMetrics};
+use matter_research_analyzer::zwave::{ZwaveAnalyzer, ZwaveMetrics};
+use metrics_core::metrics::PresentationSample;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct MatterAnalysisResult {
+    schema_version: u32,
     osi_layer_4_transport: TransportMetrics,
     osi_layer_5_session: SessionMetrics,
     osi_layer_6_presentation: PresentationMetrics,
     osi_layer_7_application: ApplicationMetrics,
     protocol_name: String,
     analysis_timestamp: String,
+    run_metadata: RunMetadata,
+    measurement_calibration: CalibrationMetrics,
+    distributed_measurement: Option<DistributedMetrics>,
+    test_environment: TestEnvironment,
+    measured_energy: Option<EnergyMetrics>,
+    footprint_metrics: Option<FootprintMetrics>,
+    cpu_cost: Option<CpuCostMetrics>,
+    throughput: Option<ThroughputMetrics>,
+    concurrency: Option<ConcurrencyMetrics>,
+    socket_options: Option<SocketMatrixMetrics>,
+    congestion_control: Option<CongestionMetrics>,
+    transport_modes: Option<TransportModeMetrics>,
+    coap_bindings: Option<CoapBindingMetrics>,
+    mqtt_sn: Option<MqttSnMetrics>,
+    handshakes: Option<HandshakeMetrics>,
+    auth_cost: Option<AuthCostMetrics>,
+    attestation: Option<AttestationMetrics>,
+    rekey: Option<RekeyMetrics>,
+    commissioning_faults: Option<CommissioningFaultMetrics>,
+    replay: Option<ReplayMetrics>,
+    robustness: Option<RobustnessMetrics>,
+    firmware_update: Option<FirmwareUpdateMetrics>,
+    bdx: Option<BdxMetrics>,
+    large_payload: Option<LargePayloadMetrics>,
+    telemetry: Option<TelemetryMetrics>,
+    fan_out: Option<FanOutMetrics>,
+    dds: Option<DdsMetrics>,
+    snmp: Option<SnmpMetrics>,
+    bacnet: Option<BacnetMetrics>,
+    cellular: Option<CellularMetrics>,
+    sigfox: Option<SigfoxMetrics>,
+    lowpan: Option<LowpanMetrics>,
+    wisun: Option<WisunMetrics>,
+    zwave: Option<ZwaveMetrics>,
+    gateway: Option<GatewayMetrics>,
+    home_assistant: Option<HomeAssistantMetrics>,
+    icd: Option<IcdMetrics>,
+    bridge: Option<BridgeMetrics>,
+    wifi_contention: Option<ContentionMetrics>,
+    asymmetric_links: Option<AsymmetryMetrics>,
+    reordering: Option<ReorderMetrics>,
+    start_modes: Option<StartModeMetrics>,
+    end_to_end: Option<EndToEndMetrics>,
+    topologies: Option<TopologyMetrics>,
+    saturation: Option<SaturationMetrics>,
+    baseline_comparison: Option<BaselineComparison>,
+    /// Retries each layer metric needed; a metric measured on a retry is not a clean measurement.
+    metric_retries: RetryCounts,
+    /// Stages the watchdog cancelled or skipped; their metrics are missing from the result.
+    stage_failures: Vec<StageFailure>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct TransportMetrics {
     udp_discovery_time_ms: f64,
     tcp_connection_time_ms: f64,
-    total_transport_overhead: u32,
+    /// TCP handshake, security handshake and first application byte of a fresh connection.
+    tcp_connection_setup: ConnectionSetup,
+    /// Query to parsed response against the emulated mDNS responder, part of `udp_discovery_time_ms`.
+    mdns_discovery: DiscoveryTiming,
+    /// IPv4/UDP headers plus the secure session's measured per-message overhead; absent without a session.
+    total_transport_overhead: Option<u32>,
     efficiency_score: f64,
+    efficiency_model_version: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct SessionMetrics {
     commissioning_time_ms: f64,
     pairing_overhead_bytes: u32,
-    session_establishment_efficiency: f64,
+    /// Share of the session's bytes that carried application data rather than the handshake.
+    session_establishment_efficiency: Option<f64>,
+    secure_session: Option<SessionStats>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -40,61 +182,637 @@ struct ApplicationMetrics {
     discovery_time_ms: f64,
     cluster_initialization_time_ms: f64,
     application_overhead_bytes: u32,
+    /// Bytes per header field and the MIC of a secured interaction-model request.
+    message_overhead: HeaderOverhead,
+    acl_overhead: Option<AclOverheadMetrics>,
+    /// Bytes per message spent on topics, URI paths and attribute paths.
+    naming_overhead: Option<NamingMetrics>,
+    scenarios: Option<ScenarioMetrics>,
+    workload: Option<WorkloadMetrics>,
+    interoperability: InteroperabilityMetrics,
+    /// Security model, IP, multicast, mesh, OTA and governance per protocol.
+    feature_matrix: FeatureMatrixMetrics,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = CliOptions::from_env()?;
+    if let Some(merge) = &options.merge {
+        CampaignMerger::new(merge.clone()).merge_and_save()?;
+        return Ok(());
+    }
+    if let Some(matrix) = &options.matrix {
+        ImpairmentMatrix::new(matrix.clone(), "matter").run(&std::env::current_exe()?)?;
+        return Ok(());
+    }
+    if let Some(diff) = &options.diff {
+        ResultDiff::new(diff.clone()).run()?;
+        return Ok(());
+    }
+    if let Some(query) = &options.query {
+        ResultQuery::new(query.clone()).run()?;
+        return Ok(());
+    }
+    if let Some(export) = &options.export {
+        ResultExport::new(export.clone()).run()?;
+        return Ok(());
+    }
+    if let Some(compare) = &options.compare {
+        ReferenceCompare::new(compare.clone()).run()?;
+        return Ok(());
+    }
+    if let Some(baseline) = &options.baseline {
+        BaselineManager::new(baseline.clone()).run()?;
+        return Ok(());
+    }
+    if let Some(budget) = &options.budget {
+        if !BudgetCheck::new(budget.clone()).run()?.passed() {
+            std::process::exit(OVER_BUDGET_EXIT_CODE);
+        }
+        return Ok(());
+    }
+    if let Some(trends) = &options.trends {
+        TrendAnalyzer::new(trends.clone()).run()?;
+        return Ok(());
+    }
+    if let Some(package) = &options.package {
+        DatasetPackager::new(package.clone()).run()?;
+        return Ok(());
+    }
+    if let Some(integrity) = &options.integrity {
+        IntegrityTool::new(integrity.clone()).run()?;
+        return Ok(());
+    }
+    if let Some(redaction) = &options.redaction {
+        RedactTool::new(redaction.clone()).run()?;
+        return Ok(());
+    }
+    if let Some(plugin_tool) = &options.plugin_tool {
+        PluginTool::new(plugin_tool.clone()).run()?;
+        return Ok(());
+    }
+    // Read before measuring, so a mistake in the policy does not cost a whole run
+    let budgets = options.budgets.as_deref().map(BudgetPolicy::load).transpose()?;
+    if let Some(config) = &options.netns {
+        if netns::current().is_none() {
+            return run_in_namespace(config).await;
+        }
+    }
+    // Opened before anything else prints, so the console output is already on stderr
+    let mut stream = match options.stream {
+        Some(format) => {
+            let redactor = options.redact.then(|| Redactor::new(&options.redact_terms));
+            MeasurementStream::stdout(format, redactor, Units { precision: options.precision, ..Units::default() })?
+        }
+        None => MeasurementStream::disabled(),
+    };
+    let events = match &options.event_log {
+        Some(path) => EventLog::create(path)?,
+        None => EventLog::disabled(),
+    };
+    events.run_started();
+    if options.samples {
+        samples::start_recording();
+    }
+    if let Some(plugin) = &options.plugin {
+        return run_plugin(&options, plugin, budgets.as_ref(), &mut stream, &events);
+    }
+    
     println!("🚀 Simplified Matter Protocol Analyzer");
     println!("======================================");
     
+    // Held until the run ends, when the previous values are written back
+    let sysctl_guard = tunables::apply_sysctls(&options.sysctls);
+    for setting in sysctl_guard.applied() {
+        match &setting.error {
+            None => println!("🔧 {} = {} (was {})", setting.name, setting.value, setting.previous.as_deref().unwrap_or("?")),
+            Some(error) => println!("⚠️ {} not set: {}", setting.name, error),
+        }
+    }
+
+    let mut test_environment = TestEnvironment::detect();
+    test_environment.network_tunables.applied = sysctl_guard.applied().to_vec();
+    println!("🖥️ Device Class: {:?} ({} on {})",
+             test_environment.device_class, test_environment.cpu_model, test_environment.architecture);
+    for (capability, status) in test_environment.capabilities.unavailable() {
+        println!("🚫 Not measurable on {}: {} ({})", test_environment.os_platform, capability, status.detail);
+    }
+    print_network_tunables(&test_environment.network_tunables);
+    print_run_metadata(&options.run_metadata);
+    
+    // Measure the harness itself before timing anything else
+    let calibration = TimingCalibrator::with_samples(test_environment.calibration_samples())
+        .calibrate()
+        .await?;
+    
+    // Sample the device's supply in the background so phases can be attributed energy
+    let mut context = LayerContext::new();
+    context.insert(calibration.clone());
+    context.insert(options.retry_policy.clone());
+    // Listeners the stages start take reserved ports, so parallel runs on one host do not collide
+    context.insert(PortAllocator::loopback());
+    if let Some(config) = &options.power_meter {
+        context.insert(PowerSampler::start(config.open()?, Duration::from_millis(1)));
+    }
+    
     let start_time = Instant::now();
     
-    // Simulate Matter operations with realistic timings
-    println!("📡 Simulating Matter transport layer...");
-    thread::sleep(Duration::from_millis(12));
-    let transport_time = 12.3;
+    // Measure the Matter layers, each after the ones it builds on
+    let pipeline = matter_pipeline();
+    println!("🧭 Layer pipeline: {}", pipeline.order()?.join(" → "));
+    let mut watchdog = Watchdog::new(options.watchdog_config.clone()).with_event_log(events.clone());
+    let finished_stages = pipeline.run(&mut context, &mut watchdog).await?;
+    let stage_success_rate = finished_stages.len() as f64 / pipeline.order()?.len().max(1) as f64;
+    
+    let measured_energy = context.take::<PowerSampler>().map(PowerSampler::finish);
+    let timings = context.take::<LayerTimings>().unwrap_or_default();
+    let (transport_time, commissioning_time, cluster_time, discovery_time) =
+        (timings.transport_ms, timings.commissioning_ms, timings.cluster_setup_ms, timings.service_discovery_ms);
+    // A presentation stage the watchdog cancelled reports zeros; its failure is in stage_failures
+    let encoding = match context.take::<PresentationSample>() {
+        Some(encoding) => encoding,
+        None if watchdog.has_failed("presentation") => {
+            PresentationSample { iterations: 0, total_time_us: 0, raw_size_bytes: 0, encoded_size_bytes: 0 }
+        }
+        None => return Err("presentation stage produced no TLV benchmark".into()),
+    };
+    let secure_session = context.take::<SecureSession>().map(|session| session.stats);
+    let metric_retries = context.take::<RetryCounts>().unwrap_or_default();
+    for (metric, retries) in metric_retries.retried() {
+        println!("⚠️ {} was measured after {} retr{}", metric, retries, if retries == 1 { "y" } else { "ies" });
+    }
+    stream.measurement("layers", &serde_json::json!({
+        "transport_ms": transport_time,
+        "tcp_connection_ms": timings.tcp_connection_ms,
+        "commissioning_ms": commissioning_time,
+        "cluster_setup_ms": cluster_time,
+        "service_discovery_ms": discovery_time,
+        "encoding_time_ms": encoding.encoding_time_ms(),
+        "secure_session": secure_session,
+        "metric_retries": metric_retries,
+    }));
+    
+    // One-way latency against a remote agent, when one is configured
+    let distributed_measurement = match options.coordinator_target {
+        Some(agent_addr) => {
+            let mut coordinator = DistributedCoordinator::new(agent_addr).with_retry_policy(options.retry_policy.clone());
+            watchdog.guard("distributed", |_| async move { coordinator.analyze_one_way_latency().await }).await?
+        }
+        None => None,
+    };
+    stream.measurement("distributed_measurement", &distributed_measurement);
+    
+    let footprint_metrics = if options.footprint {
+        Some(events.phase("footprint_metrics", || FootprintAnalyzer::default().analyze_footprint())?)
+    } else {
+        None
+    };
+    stream.measurement("footprint_metrics", &footprint_metrics);
+    
+    let cpu_cost = if options.cpu_cost {
+        Some(events.phase("cpu_cost", || CpuCostAnalyzer::default().analyze_cpu_cost())?)
+    } else {
+        None
+    };
+    stream.measurement("cpu_cost", &cpu_cost);
+    
+    let throughput = if options.throughput {
+        let receiver = match options.coordinator_target {
+            Some(agent_addr) => ThroughputReceiver::Agent(agent_addr),
+            None => ThroughputReceiver::Loopback,
+        };
+        let mut tester = ThroughputTester::new(options.throughput_config.clone(), receiver);
+        watchdog.guard("throughput", |_| async move { tester.analyze_throughput().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("throughput", &throughput);
+    
+    let concurrency = if options.concurrency {
+        let mut probe = ConcurrencyProbe::new(options.concurrency_config.clone());
+        watchdog.guard("concurrency", |_| async move { probe.analyze_concurrency().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("concurrency", &concurrency);
+    
+    let socket_options = if options.socket_matrix {
+        let mut matrix = SocketOptionMatrix::new(options.socket_matrix_config.clone());
+        watchdog.guard("socket_options", |_| async move { matrix.analyze_socket_options().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("socket_options", &socket_options);
+    
+    let congestion_control = if options.congestion {
+        let mut analyzer = CongestionAnalyzer::new(options.congestion_config.clone());
+        watchdog.guard("congestion_control", |_| async move { analyzer.analyze_congestion().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("congestion_control", &congestion_control);
+    
+    let transport_modes = if options.transport_modes {
+        let mut comparison = TransportModeComparison::new(options.transport_mode_config.clone());
+        watchdog.guard("transport_modes", |_| async move { comparison.analyze_transport_modes().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("transport_modes", &transport_modes);
+    
+    let coap_bindings = if options.coap_bindings {
+        let mut comparison = CoapBindingComparison::new(options.coap_binding_config.clone());
+        watchdog.guard("coap_bindings", |_| async move { comparison.analyze_coap_bindings().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("coap_bindings", &coap_bindings);
+    
+    let mqtt_sn = if options.mqtt_sn {
+        let mut analyzer = MqttSnAnalyzer::new(options.mqtt_sn_config.clone());
+        watchdog.guard("mqtt_sn", |_| async move { analyzer.analyze_mqtt_sn().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("mqtt_sn", &mqtt_sn);
+    
+    let handshakes = if options.handshakes {
+        Some(events.phase("handshakes", || HandshakeAnalyzer::new(options.handshake_config.clone()).analyze_handshakes())?)
+    } else {
+        None
+    };
+    stream.measurement("handshakes", &handshakes);
+    
+    let auth_cost = if options.auth_cost {
+        Some(events.phase("auth_cost", || AuthCostAnalyzer::default().analyze_auth_cost())?)
+    } else {
+        None
+    };
+    stream.measurement("auth_cost", &auth_cost);
+    
+    let attestation = if options.attestation {
+        Some(events.phase("attestation", || AttestationAnalyzer::default().analyze_attestation())?)
+    } else {
+        None
+    };
+    stream.measurement("attestation", &attestation);
+    
+    let commissioning_faults = if options.commissioning_faults {
+        let mut injector = CommissioningFaultInjector::new(options.commissioning_fault_config.clone());
+        Some(events.phase("commissioning_faults", || injector.analyze_commissioning_faults())?)
+    } else {
+        None
+    };
+    stream.measurement("commissioning_faults", &commissioning_faults);
+    
+    let rekey = if options.rekey {
+        Some(events.phase("rekey", || RekeyAnalyzer::new(options.rekey_config.clone()).analyze_rekey())?)
+    } else {
+        None
+    };
+    stream.measurement("rekey", &rekey);
+    
+    let replay = if options.replay {
+        Some(events.phase("replay", || ReplayAnalyzer::new().analyze_replay())?)
+    } else {
+        None
+    };
+    stream.measurement("replay", &replay);
+    
+    let robustness = if options.robustness {
+        Some(events.phase("robustness", || RobustnessAnalyzer::new(options.robustness_config.clone()).analyze_robustness())?)
+    } else {
+        None
+    };
+    stream.measurement("robustness", &robustness);
+    
+    let acl_overhead = if options.acl_overhead {
+        Some(events.phase("acl_overhead", || AclOverheadAnalyzer::new(options.acl_config.clone()).analyze_acl_overhead())?)
+    } else {
+        None
+    };
+    stream.measurement("acl_overhead", &acl_overhead);
+    
+    let naming_overhead = if options.naming {
+        Some(events.phase("naming_overhead", || NamingAnalyzer::new(options.naming_config.clone()).analyze_naming())?)
+    } else {
+        None
+    };
+    stream.measurement("naming_overhead", &naming_overhead);
+    
+    let scenarios = if options.scenarios {
+        Some(events.phase("scenarios", || ScenarioAnalyzer::new(options.scenario_config.clone()).analyze_scenarios())?)
+    } else {
+        None
+    };
+    stream.measurement("scenarios", &scenarios);
+    
+    let workload = if options.workload {
+        Some(events.phase("workload", || WorkloadGenerator::new(options.workload_config.clone()).analyze_workload())?)
+    } else {
+        None
+    };
+    stream.measurement("workload", &workload);
+    
+    let interoperability = events.phase("interoperability", || {
+        InteroperabilityAnalyzer::new(options.interoperability_config.clone()).analyze_interoperability()
+    })?;
+    stream.measurement("interoperability", &interoperability);
+    let feature_matrix = events.phase("feature_matrix", || {
+        FeatureMatrixAnalyzer::new(options.feature_matrix_config.clone()).analyze_feature_matrix()
+    })?;
+    stream.measurement("feature_matrix", &feature_matrix);
+    
+    let firmware_update = if options.firmware_update {
+        let mut benchmark = FirmwareUpdateBenchmark::new(options.firmware_update_config.clone());
+        watchdog.guard("firmware_update", |_| async move { benchmark.analyze_firmware_update().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("firmware_update", &firmware_update);
+    
+    let bdx = if options.bdx {
+        let mut analyzer = BdxAnalyzer::new(options.bdx_config.clone());
+        watchdog.guard("bdx", |_| async move { analyzer.analyze_bdx().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("bdx", &bdx);
     
-    println!("🔐 Simulating Matter commissioning...");
-    thread::sleep(Duration::from_millis(89));
-    let commissioning_time = 89.2;
+    let large_payload = if options.large_payload {
+        let mut benchmark = LargePayloadBenchmark::new(options.large_payload_config.clone());
+        watchdog.guard("large_payload", |_| async move { benchmark.analyze_large_payload().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("large_payload", &large_payload);
+    
+    let telemetry = if options.telemetry {
+        let mut streamer = TelemetryStreamer::new(options.telemetry_config.clone());
+        watchdog.guard("telemetry", |_| async move { streamer.analyze_telemetry().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("telemetry", &telemetry);
+    
+    let fan_out = if options.fan_out {
+        Some(events.phase("fan_out", || FanOutAnalyzer::new(options.fan_out_config.clone()).analyze_fan_out())?)
+    } else {
+        None
+    };
+    stream.measurement("fan_out", &fan_out);
     
-    println!("🔧 Simulating cluster setup...");
-    thread::sleep(Duration::from_millis(12));
-    let cluster_time = 12.1;
+    let dds = if options.dds {
+        let mut analyzer = DdsAnalyzer::new(options.dds_config.clone());
+        watchdog.guard("dds", |_| async move { analyzer.analyze_dds().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("dds", &dds);
     
-    println!("🎯 Simulating service discovery...");
-    thread::sleep(Duration::from_millis(18));
-    let discovery_time = 18.5;
+    let snmp = if options.snmp {
+        let mut analyzer = SnmpAnalyzer::new(options.snmp_config.clone());
+        watchdog.guard("snmp", |_| async move { analyzer.analyze_snmp().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("snmp", &snmp);
+    
+    let bacnet = if options.bacnet {
+        let mut analyzer = BacnetAnalyzer::new(options.bacnet_config.clone());
+        watchdog.guard("bacnet", |_| async move { analyzer.analyze_bacnet().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("bacnet", &bacnet);
+    
+    let cellular = if options.cellular {
+        Some(events.phase("cellular", || CellularAnalyzer::new(options.cellular_config.clone()).analyze_cellular())?)
+    } else {
+        None
+    };
+    stream.measurement("cellular", &cellular);
+    
+    let sigfox = if options.sigfox {
+        Some(events.phase("sigfox", || SigfoxAnalyzer::new(options.sigfox_config.clone()).analyze_sigfox())?)
+    } else {
+        None
+    };
+    stream.measurement("sigfox", &sigfox);
+    
+    let lowpan = if options.lowpan {
+        Some(events.phase("lowpan", || LowpanAnalyzer::new(options.lowpan_config.clone()).analyze_lowpan())?)
+    } else {
+        None
+    };
+    stream.measurement("lowpan", &lowpan);
+    
+    let wisun = if options.wisun {
+        let mut analyzer = WisunAnalyzer::new(options.wisun_config.clone());
+        watchdog.guard("wisun", |_| async move { analyzer.analyze_wisun().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("wisun", &wisun);
+    
+    let zwave = if options.zwave {
+        let mut analyzer = ZwaveAnalyzer::new(options.zwave_config.clone());
+        watchdog.guard("zwave", |_| async move { analyzer.analyze_zwave().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("zwave", &zwave);
+    
+    let gateway = if options.gateway {
+        let mut analyzer = GatewayAnalyzer::new(options.gateway_config.clone());
+        watchdog.guard("gateway", |_| async move { analyzer.analyze_gateway().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("gateway", &gateway);
+    
+    let home_assistant = if options.home_assistant {
+        let mut analyzer = HomeAssistantAnalyzer::new(options.home_assistant_config.clone());
+        watchdog.guard("home_assistant", |_| async move { analyzer.analyze_home_assistant().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("home_assistant", &home_assistant);
+    
+    let icd = if options.icd {
+        Some(events.phase("icd", || IcdAnalyzer::new(options.icd_config.clone()).analyze_icd())?)
+    } else {
+        None
+    };
+    stream.measurement("icd", &icd);
+    
+    let bridge = if options.bridge {
+        let mut analyzer = BridgeAnalyzer::new(options.bridge_config.clone());
+        watchdog.guard("bridge", |_| async move { analyzer.analyze_bridge().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("bridge", &bridge);
+    
+    let wifi_contention = if options.contention {
+        Some(events.phase("wifi_contention", || ContentionModel::new(options.contention_config.clone()).analyze_contention())?)
+    } else {
+        None
+    };
+    stream.measurement("wifi_contention", &wifi_contention);
+    
+    let asymmetric_links = if options.asymmetry {
+        let mut analyzer = AsymmetryAnalyzer::new(options.asymmetry_config.clone());
+        watchdog.guard("asymmetric_links", |_| async move { analyzer.analyze_asymmetry().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("asymmetric_links", &asymmetric_links);
+    
+    let reordering = if options.reordering {
+        let mut analyzer = ReorderAnalyzer::new(options.reorder_config.clone());
+        watchdog.guard("reordering", |_| async move { analyzer.analyze_reordering().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("reordering", &reordering);
+    
+    let start_modes = if options.start_modes {
+        let mut analyzer = StartModeAnalyzer::new(options.start_mode_config.clone());
+        watchdog.guard("start_modes", |_| async move { analyzer.analyze_start_modes().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("start_modes", &start_modes);
+    
+    let end_to_end = if options.end_to_end {
+        Some(events.phase("end_to_end", || EndToEndAnalyzer::new(options.end_to_end_config.clone()).analyze_end_to_end())?)
+    } else {
+        None
+    };
+    stream.measurement("end_to_end", &end_to_end);
+    
+    let topologies = if options.topologies {
+        Some(events.phase("topologies", || TopologyAnalyzer::new(options.topology_config.clone()).analyze_topologies())?)
+    } else {
+        None
+    };
+    stream.measurement("topologies", &topologies);
+    
+    let saturation = if options.saturation {
+        let mut analyzer = SaturationAnalyzer::new(options.saturation_config.clone());
+        watchdog.guard("saturation", |_| async move { analyzer.analyze_saturation().await }).await?
+    } else {
+        None
+    };
+    stream.measurement("saturation", &saturation);
+    
+    // Prefer the cross-network echo over the local discovery phase when an agent was used
+    let transport_efficiency = efficiency_score(&EfficiencyInputs {
+        throughput_mbps: throughput.as_ref()
+            .and_then(|t| t.runs.iter().map(|r| r.goodput_mbps).reduce(f64::max)),
+        round_trip_time_ms: Some(distributed_measurement.as_ref()
+            .map(|d| d.echo.round_trip_time_ms)
+            .unwrap_or(transport_time)),
+        packet_loss_rate: distributed_measurement.as_ref().map(|d| d.one_way_packet_loss_rate),
+        success_rate: Some(stage_success_rate),
+    });
     
     let result = MatterAnalysisResult {
+        schema_version: RESULT_SCHEMA_VERSION,
         osi_layer_4_transport: TransportMetrics {
             udp_discovery_time_ms: transport_time,
-            tcp_connection_time_ms: 8.7,
-            total_transport_overhead: 60,
-            efficiency_score: 0.78,
+            tcp_connection_time_ms: timings.tcp_connection_ms,
+            tcp_connection_setup: timings.tcp_connection_setup.clone(),
+            mdns_discovery: timings.mdns_discovery.clone(),
+            total_transport_overhead: secure_session.as_ref()
+                .map(|stats| (IPV4_UDP_HEADER_BYTES + stats.per_message_overhead_bytes) as u32),
+            efficiency_score: transport_efficiency.score,
+            efficiency_model_version: transport_efficiency.model_version,
         },
         osi_layer_5_session: SessionMetrics {
             commissioning_time_ms: commissioning_time,
-            pairing_overhead_bytes: 156,
-            session_establishment_efficiency: 0.78,
+            pairing_overhead_bytes: PAIRING_OVERHEAD_BYTES,
+            session_establishment_efficiency: secure_session.as_ref()
+                .filter(|stats| stats.handshake_bytes + stats.application_bytes > 0)
+                .map(|stats| stats.application_bytes as f64 / (stats.handshake_bytes + stats.application_bytes) as f64),
+            secure_session: secure_session.clone(),
         },
         osi_layer_6_presentation: PresentationMetrics {
-            encoding_time_ms: 0.25,
-            tlv_overhead_bytes: 12,
-            compression_ratio: 0.85,
+            encoding_time_ms: encoding.encoding_time_ms(),
+            tlv_overhead_bytes: encoding.overhead_bytes(),
+            compression_ratio: encoding.compression_ratio(),
         },
         osi_layer_7_application: ApplicationMetrics {
             discovery_time_ms: discovery_time,
             cluster_initialization_time_ms: cluster_time,
-            application_overhead_bytes: 24,
+            application_overhead_bytes: secure_session.as_ref()
+                .map(|stats| stats.per_message_overhead_bytes as u32)
+                .unwrap_or(APPLICATION_OVERHEAD_BYTES),
+            message_overhead: HeaderOverhead::secured_request(),
+            acl_overhead,
+            naming_overhead,
+            scenarios,
+            workload,
+            interoperability,
+            feature_matrix,
         },
         protocol_name: "Matter_Protocol_Analysis".to_string(),
-        analysis_timestamp: "2025-01-07T12:00:00Z".to_string(),
+        analysis_timestamp: chrono::Utc::now().to_rfc3339(),
+        run_metadata: options.run_metadata.clone(),
+        measurement_calibration: calibration,
+        distributed_measurement,
+        test_environment,
+        measured_energy,
+        footprint_metrics,
+        cpu_cost,
+        throughput,
+        concurrency,
+        socket_options,
+        congestion_control,
+        transport_modes,
+        coap_bindings,
+        mqtt_sn,
+        handshakes,
+        auth_cost,
+        attestation,
+        rekey,
+        commissioning_faults,
+        replay,
+        robustness,
+        firmware_update,
+        bdx,
+        large_payload,
+        telemetry,
+        fan_out,
+        dds,
+        snmp,
+        bacnet,
+        cellular,
+        sigfox,
+        lowpan,
+        wisun,
+        zwave,
+        gateway,
+        home_assistant,
+        icd,
+        bridge,
+        wifi_contention,
+        asymmetric_links,
+        reordering,
+        start_modes,
+        end_to_end,
+        topologies,
+        saturation,
+        baseline_comparison: None,
+        metric_retries,
+        stage_failures: watchdog.into_failures(),
     };
     
     // Save results
-    std::fs::create_dir_all("../results")?;
-    let json_output = serde_json::to_string_pretty(&result)?;
-    std::fs::write("../results/matter_real_analysis.json", &json_output)?;
+    let (saved, baseline_comparison) = record_run(&options, "Matter", serde_json::to_value(&result)?)?;
+    stream.complete(&saved.path);
+    events.run_finished(Outcome::Ok, Some(saved.path.display().to_string()));
     
     println!("\n📊 MATTER ANALYSIS RESULTS");
     println!("==========================");
@@ -102,7 +820,194 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("🔐 Commissioning: {:.2}ms", result.osi_layer_5_session.commissioning_time_ms);
     println!("🔧 Cluster Setup: {:.2}ms", result.osi_layer_7_application.cluster_initialization_time_ms);
     println!("🎯 Discovery: {:.2}ms", result.osi_layer_7_application.discovery_time_ms);
-    println!("\n✅ Results saved to: ../results/matter_real_analysis.json");
+    let overhead = &result.osi_layer_7_application.message_overhead;
+    println!("📐 Message overhead: {} bytes ({} message header, {} protocol header, {} MIC)",
+             overhead.total(), overhead.message_header(), overhead.protocol_header(), overhead.mic);
+    if result.measurement_calibration.is_below_noise_floor(result.osi_layer_6_presentation.encoding_time_ms) {
+        println!("⚠️ Encoding time {:.2}ms is below the harness noise floor ({:.4}ms)",
+                 result.osi_layer_6_presentation.encoding_time_ms,
+                 result.measurement_calibration.noise_floor_ms);
+    }
+    if let Some(energy) = &result.measured_energy {
+        for phase in &energy.phases {
+            println!("🔋 {} energy: {:.3}mJ ({:.1}mW avg)", phase.phase, phase.energy_mj, phase.average_power_mw);
+        }
+    }
+    print_saved(&saved, baseline_comparison.as_ref());
+    let within_budget = check_budgets(&options, budgets.as_ref(), &saved)?;
+    // Exiting skips destructors, so the sysctls are put back first
+    drop(sysctl_guard);
+    if !within_budget {
+        std::process::exit(OVER_BUDGET_EXIT_CODE);
+    }
+    
+    Ok(())
+}
+
+/// Runs a third-party protocol plugin in place of the Matter measurements and saves its result the same way.
+fn run_plugin(
+    options: &CliOptions,
+    config: &PluginConfig,
+    budgets: Option<&BudgetPolicy>,
+    stream: &mut MeasurementStream,
+    events: &EventLog,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let plugin = Plugin::resolve(&config.plugin, &config.dir, config.timeout)?;
+    let description = plugin.describe()?;
+    println!("🧩 {} {} ({} plugin)", description.name, description.version, description.protocol);
+    println!("======================================");
+    print_run_metadata(&options.run_metadata);
     
+    let phase = format!("plugin:{}", description.name);
+    let result = events.phase(&phase, || plugin.analyze(&description, &options.run_metadata, &config.options))?;
+    // A plugin hands over its whole result at once, so its sections all stream together
+    for section in REQUIRED_SECTIONS {
+        stream.measurement(section, &result[section]);
+    }
+    let section_sizes: Vec<(&str, usize)> = REQUIRED_SECTIONS
+        .iter()
+        .map(|section| (*section, result[*section].as_object().map_or(0, |fields| fields.len())))
+        .collect();
+    let (saved, baseline_comparison) = record_run(options, &description.protocol, result)?;
+    stream.complete(&saved.path);
+    events.run_finished(Outcome::Ok, Some(saved.path.display().to_string()));
+    
+    println!("\n📊 {} ANALYSIS RESULTS", description.protocol.to_uppercase());
+    println!("==========================");
+    for (section, size) in section_sizes {
+        println!("🧩 {}: {} metrics", section, size);
+    }
+    print_saved(&saved, baseline_comparison.as_ref());
+    if !check_budgets(options, budgets, &saved)? {
+        std::process::exit(OVER_BUDGET_EXIT_CODE);
+    }
+    Ok(())
+}
+
+/// Runs this analyzer again, with the same arguments, inside a fresh network namespace, and removes the namespace afterwards.
+async fn run_in_namespace(config: &NetnsConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let namespace = Namespace::create(config)?;
+    let info = namespace.info();
+    println!("🧱 Network namespace {}: {} ({}) ↔ host {} ({})",
+             info.name, info.namespace_addr, info.namespace_interface, info.host_addr, info.host_interface);
+    if let Some(impairment) = &info.impairment {
+        println!("🐢 Impairment {}: uplink {}, downlink {}", impairment.profile.as_deref().unwrap_or("--netem"),
+                 impairment.uplink.qdisc_args().join(" "), impairment.downlink.qdisc_args().join(" "));
+    }
+    
+    let mut command = tokio::process::Command::from(namespace.command(std::env::current_exe()?));
+    command.args(std::env::args_os().skip(1)).env(NETNS_ENV, serde_json::to_string(info)?);
+    let mut child = command.spawn()?;
+    // On Ctrl-C the child gets the signal too; waiting for it lets the namespace be removed
+    let status = tokio::select! {
+        status = child.wait() => status?,
+        _ = tokio::signal::ctrl_c() => {
+            child.kill().await?;
+            return Err(format!("interrupted; network namespace {} removed", info.name).into());
+        }
+    };
+    drop(namespace);
+    if status.code() == Some(OVER_BUDGET_EXIT_CODE) {
+        std::process::exit(OVER_BUDGET_EXIT_CODE);
+    }
+    if !status.success() {
+        return Err(format!("analysis in the network namespace exited with {}", status).into());
+    }
     Ok(())
 }
+
+fn print_run_metadata(metadata: &RunMetadata) {
+    if *metadata != RunMetadata::default() {
+        println!("🏷️ Experiment: {} [{}]",
+                 metadata.experiment_id.as_deref().unwrap_or("-"), tags_text(&metadata.tags));
+    }
+}
+
+fn print_network_tunables(tunables: &NetworkTunables) {
+    let kib = |bytes: Option<usize>| bytes.map_or("?".to_string(), |bytes| format!("{}KiB", bytes / 1024));
+    println!("📶 Socket buffers: UDP {}/{} rx/tx for {}KiB requested, TCP {}/{}; congestion control {}",
+             kib(tunables.udp_recv_buffer_bytes), kib(tunables.udp_send_buffer_bytes),
+             tunables.requested_socket_buffer_bytes / 1024,
+             kib(tunables.tcp_recv_buffer_bytes), kib(tunables.tcp_send_buffer_bytes),
+             tunables.tcp_congestion_control.as_deref().unwrap_or("unknown"));
+    if tunables.udp_buffers_clamped() {
+        println!("⚠️ UDP buffers were clamped below the request; raise net.core.rmem_max/wmem_max for high-rate tests");
+    }
+}
+
+/// Compares a finished result with its pinned baseline, applies `--redact` and saves it to the results directory.
+fn record_run(
+    options: &CliOptions,
+    protocol: &str,
+    mut result: serde_json::Value,
+) -> Result<(SavedRun, Option<BaselineComparison>), Box<dyn std::error::Error>> {
+    let baseline = BaselineConfig::default();
+    let comparison = match BaselineStore::new(options.results_config.dir.join("baselines")).compare(&result, baseline.threshold_percent) {
+        Ok(comparison) => comparison,
+        Err(e) => {
+            println!("⚠️ Baseline comparison skipped: {:#}", e);
+            None
+        }
+    };
+    result["baseline_comparison"] = serde_json::to_value(&comparison)?;
+    Units { precision: options.precision, ..Units::default() }.annotate(&mut result);
+    if let Some(namespace) = netns::current() {
+        result[NAMESPACE_SECTION] = serde_json::to_value(&namespace)?;
+    }
+    
+    if options.redact {
+        let mut redactor = Redactor::new(&options.redact_terms);
+        redactor.redact_value(&mut result);
+        println!("🕶️ Redacted {} addresses, {} MACs and {} words from the result",
+                 redactor.counts.addresses, redactor.counts.macs, redactor.counts.terms);
+    }
+    let json_output = serde_json::to_string_pretty(&result)?;
+    let saved = ResultsStore::new(options.results_config.clone()).save(protocol, &json_output, &options.run_metadata)?;
+    if options.samples {
+        save_samples(&saved.path);
+    }
+    Ok((saved, comparison))
+}
+
+/// Writes the per-iteration samples recorded during the run next to its result file.
+fn save_samples(run_file: &std::path::Path) {
+    let series = samples::take_recorded();
+    if series.is_empty() {
+        println!("⚠️ No per-iteration samples were recorded; none of the enabled analyzers times iterations");
+        return;
+    }
+    let path = run_file.with_extension("samples.arrow");
+    let run = run_file.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    match samples::write_samples(&path, &run, &series) {
+        Ok(rows) => println!("📈 {} samples in {} series saved to: {}", rows, series.len(), path.display()),
+        Err(e) => println!("⚠️ Samples not saved: {:#}", e),
+    }
+}
+
+/// Checks the saved result against the `--budgets` policy; true when there is none or every budget is met.
+fn check_budgets(options: &CliOptions, policy: Option<&BudgetPolicy>, saved: &SavedRun) -> Result<bool, Box<dyn std::error::Error>> {
+    let (Some(policy), Some(file)) = (policy, &options.budgets) else {
+        return Ok(true);
+    };
+    let report = policy.check(vec![saved.path.clone()])?;
+    report.print(file);
+    Ok(report.passed())
+}
+
+fn print_saved(saved: &SavedRun, baseline_comparison: Option<&BaselineComparison>) {
+    if let Some(comparison) = baseline_comparison {
+        println!("📌 Baseline {}: {} regressions, {} improvements beyond {}%",
+                 comparison.scenario_hash, comparison.regressions, comparison.improvements, comparison.threshold_percent);
+        for change in comparison.changes.iter().filter(|c| c.regression) {
+            println!("❌ {}: {:?} → {:?}", change.metric, change.old, change.new);
+        }
+    }
+    println!("\n✅ Results saved to: {}", saved.path.display());
+    println!("✅ Latest copy: {}", saved.latest.display());
+    for file in &saved.archived {
+        println!("🗄️ Archived {}", file);
+    }
+    for file in &saved.removed {
+        println!("🗑️ Removed {}", file);
+    }
+}
@@ -0,0 +1,424 @@
+// matter-project/src/mdns.rs
+/*!
+mDNS discovery - DNS-SD queries and answers for the Matter operational service
+
+A controller finds a commissioned node by asking for PTR records of
+`_matter._tcp.local`. The node answers with the PTR naming its instance
+(`<compressed fabric id>-<node id>`), the SRV giving its host and port, the TXT
+with its session parameters, and an AAAA for the host. [`query`] builds that
+question, [`answer`] builds the node's response with compressed names, and
+[`parse_response`] resolves a response back into a [`ServiceAdvertisement`].
+
+[`discover`] sends a query to a responder and times the two halves a
+controller pays for separately: the round trip until the response arrives,
+and parsing it into an address it can open a session to. Sending into the
+multicast group only times the `send_to` call; the responder in
+`fixtures::mdns_responder` answers unicast on loopback so the round trip can
+be measured.
+*/
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+pub const MDNS_PORT: u16 = 5353;
+pub const MATTER_SERVICE: &str = "_matter._tcp.local";
+pub const MATTER_PORT: u16 = 5540;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_SRV: u16 = 33;
+pub const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Set on a question's class to ask for a unicast response, and on a record's class to flush caches.
+const CLASS_TOP_BIT: u16 = 0x8000;
+const FLAGS_RESPONSE: u16 = 0x8400;
+const HEADER_BYTES: usize = 12;
+const SERVICE_TTL: u32 = 120;
+const HOST_TTL: u32 = 4500;
+const MAX_POINTER_JUMPS: usize = 16;
+
+static NEXT_QUERY_ID: AtomicU16 = AtomicU16::new(1);
+
+/// What a node advertises: an instance of the Matter service and where to reach it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServiceAdvertisement {
+    pub instance: String,
+    pub host: String,
+    pub port: u16,
+    pub txt: Vec<String>,
+    pub addresses: Vec<Ipv6Addr>,
+}
+
+impl Default for ServiceAdvertisement {
+    fn default() -> Self {
+        Self {
+            instance: "2906C908D115D362-8FC7772401CD0696".to_string(),
+            host: "B75AFB458ECD.local".to_string(),
+            port: MATTER_PORT,
+            txt: vec!["SII=5000".to_string(), "SAI=300".to_string(), "SAT=4000".to_string(), "T=0".to_string()],
+            addresses: vec!["fd00::b75a:fb45:8ecd".parse().unwrap()],
+        }
+    }
+}
+
+impl ServiceAdvertisement {
+    /// The instance's fully qualified name, which its SRV and TXT records belong to.
+    pub fn instance_name(&self) -> String {
+        format!("{}.{}", self.instance, MATTER_SERVICE)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiscoveryTiming {
+    /// Query sent until the response arrived.
+    pub round_trip_ms: f64,
+    /// Response bytes resolved into an instance, host, port and addresses.
+    pub parse_ms: f64,
+    pub query_bytes: usize,
+    pub response_bytes: usize,
+    pub records: usize,
+    pub instance: String,
+}
+
+impl DiscoveryTiming {
+    pub fn total_ms(&self) -> f64 {
+        self.round_trip_ms + self.parse_ms
+    }
+}
+
+/// A PTR question for `service` that asks for a unicast response.
+pub fn query(id: u16, service: &str) -> Vec<u8> {
+    let mut message = Message::new(id, 0);
+    message.question(service, TYPE_PTR, CLASS_IN | CLASS_TOP_BIT);
+    message.finish()
+}
+
+/// The response to `query` from a node advertising `advertisement`, or `None` when nothing it asks is ours.
+pub fn answer(query: &[u8], advertisement: &ServiceAdvertisement) -> Option<Vec<u8>> {
+    let header = Header::parse(query).ok()?;
+    if header.flags & 0x8000 != 0 {
+        return None;
+    }
+    let instance_name = advertisement.instance_name();
+    let mut offset = HEADER_BYTES;
+    let (mut service, mut srv, mut txt) = (false, false, false);
+    for _ in 0..header.questions {
+        let (name, next) = read_name(query, offset).ok()?;
+        let qtype = read_u16(query, next).ok()?;
+        offset = next + 4;
+        let any = qtype == TYPE_ANY;
+        if name.eq_ignore_ascii_case(MATTER_SERVICE) && (qtype == TYPE_PTR || any) {
+            service = true;
+        } else if name.eq_ignore_ascii_case(&instance_name) {
+            srv |= qtype == TYPE_SRV || any;
+            txt |= qtype == TYPE_TXT || any;
+        }
+    }
+    if !(service || srv || txt) {
+        return None;
+    }
+
+    // A PTR answer carries the records it points to as additionals, so one round trip resolves the node
+    let mut message = Message::new(header.id, FLAGS_RESPONSE);
+    if service {
+        message.record(MATTER_SERVICE, TYPE_PTR, CLASS_IN, SERVICE_TTL, |m| m.name(&instance_name));
+    }
+    if service || srv {
+        message.record(&instance_name, TYPE_SRV, CLASS_IN | CLASS_TOP_BIT, SERVICE_TTL, |m| {
+            m.bytes.extend_from_slice(&[0, 0, 0, 0]);
+            m.bytes.extend_from_slice(&advertisement.port.to_be_bytes());
+            m.name(&advertisement.host);
+        });
+    }
+    if service || txt {
+        message.record(&instance_name, TYPE_TXT, CLASS_IN | CLASS_TOP_BIT, SERVICE_TTL, |m| {
+            for entry in &advertisement.txt {
+                m.bytes.push(entry.len().min(255) as u8);
+                m.bytes.extend_from_slice(&entry.as_bytes()[..entry.len().min(255)]);
+            }
+        });
+    }
+    for address in &advertisement.addresses {
+        message.record(&advertisement.host, TYPE_AAAA, CLASS_IN | CLASS_TOP_BIT, HOST_TTL, |m| {
+            m.bytes.extend_from_slice(&address.octets());
+        });
+    }
+    let answers = if service { 1 } else { srv as u16 + txt as u16 };
+    let records = message.records;
+    message.set_counts(answers, records - answers);
+    Some(message.finish())
+}
+
+/// Resolves the first Matter service instance in a response, following its PTR to the SRV, TXT and AAAA records.
+pub fn parse_response(message: &[u8]) -> Result<ServiceAdvertisement> {
+    let header = Header::parse(message)?;
+    if header.flags & 0x8000 == 0 {
+        return Err(anyhow!("not a DNS response"));
+    }
+    let mut offset = HEADER_BYTES;
+    for _ in 0..header.questions {
+        offset = read_name(message, offset)?.1 + 4;
+    }
+
+    let (mut instance, mut srv, mut txt) = (None, Vec::new(), Vec::new());
+    let mut addresses: Vec<(String, Ipv6Addr)> = Vec::new();
+    let records = header.answers as usize + header.authorities as usize + header.additionals as usize;
+    for _ in 0..records {
+        let (name, next) = read_name(message, offset)?;
+        let rtype = read_u16(message, next)?;
+        let length = read_u16(message, next + 8)? as usize;
+        let data = next + 10;
+        let end = data + length;
+        let rdata = message.get(data..end).ok_or_else(|| anyhow!("record {} runs past the message", name))?;
+        match rtype {
+            TYPE_PTR if name.eq_ignore_ascii_case(MATTER_SERVICE) && instance.is_none() => {
+                instance = Some(read_name(message, data)?.0);
+            }
+            TYPE_SRV => {
+                let port = read_u16(message, data + 4)?;
+                srv.push((name, read_name(message, data + 6)?.0, port));
+            }
+            TYPE_TXT => {
+                let mut entry = 0;
+                while entry < rdata.len() {
+                    let len = rdata[entry] as usize;
+                    let text = rdata.get(entry + 1..entry + 1 + len).ok_or_else(|| anyhow!("TXT entry runs past its record"))?;
+                    if !text.is_empty() {
+                        txt.push((name.clone(), String::from_utf8_lossy(text).into_owned()));
+                    }
+                    entry += 1 + len;
+                }
+            }
+            TYPE_AAAA => {
+                let octets: [u8; 16] = rdata.try_into().map_err(|_| anyhow!("AAAA record of {} bytes", length))?;
+                addresses.push((name, Ipv6Addr::from(octets)));
+            }
+            _ => {}
+        }
+        offset = end;
+    }
+
+    let instance_name = instance.ok_or_else(|| anyhow!("no PTR record for {}", MATTER_SERVICE))?;
+    let (_, host, port) = srv
+        .into_iter()
+        .find(|(name, _, _)| name.eq_ignore_ascii_case(&instance_name))
+        .ok_or_else(|| anyhow!("no SRV record for {}", instance_name))?;
+    let suffix = format!(".{}", MATTER_SERVICE);
+    Ok(ServiceAdvertisement {
+        instance: instance_name.strip_suffix(&suffix).unwrap_or(&instance_name).to_string(),
+        txt: txt.into_iter().filter(|(name, _)| name.eq_ignore_ascii_case(&instance_name)).map(|(_, t)| t).collect(),
+        addresses: addresses.into_iter().filter(|(name, _)| name.eq_ignore_ascii_case(&host)).map(|(_, a)| a).collect(),
+        host,
+        port,
+    })
+}
+
+/// Asks `responder` for the Matter service and times the round trip and the parse of its response.
+pub async fn discover(socket: &UdpSocket, responder: SocketAddr, timeout: Duration) -> Result<(ServiceAdvertisement, DiscoveryTiming)> {
+    let id = NEXT_QUERY_ID.fetch_add(1, Ordering::Relaxed);
+    let query = query(id, MATTER_SERVICE);
+    let mut buffer = vec![0u8; 9000];
+
+    let start = Instant::now();
+    socket.send_to(&query, responder).await?;
+    let len = tokio::time::timeout(timeout, async {
+        loop {
+            let (len, from) = socket.recv_from(&mut buffer).await?;
+            // Stray datagrams and answers to earlier attempts are not this query's response
+            if from == responder && buffer.get(..2) == Some(&id.to_be_bytes()[..]) {
+                return Ok::<_, std::io::Error>(len);
+            }
+        }
+    })
+    .await
+    .map_err(|_| anyhow!("no mDNS response from {} within {:?}", responder, timeout))??;
+    let received = Instant::now();
+    let advertisement = parse_response(&buffer[..len])?;
+    let parsed = Instant::now();
+
+    let header = Header::parse(&buffer[..len])?;
+    let ms = |from: Instant, to: Instant| (to - from).as_secs_f64() * 1000.0;
+    let timing = DiscoveryTiming {
+        round_trip_ms: ms(start, received),
+        parse_ms: ms(received, parsed),
+        query_bytes: query.len(),
+        response_bytes: len,
+        records: header.answers as usize + header.authorities as usize + header.additionals as usize,
+        instance: advertisement.instance.clone(),
+    };
+    Ok((advertisement, timing))
+}
+
+struct Header {
+    id: u16,
+    flags: u16,
+    questions: u16,
+    answers: u16,
+    authorities: u16,
+    additionals: u16,
+}
+
+impl Header {
+    fn parse(message: &[u8]) -> Result<Self> {
+        if message.len() < HEADER_BYTES {
+            return Err(anyhow!("DNS message of {} bytes is shorter than its header", message.len()));
+        }
+        let field = |i: usize| u16::from_be_bytes([message[2 * i], message[2 * i + 1]]);
+        Ok(Self {
+            id: field(0),
+            flags: field(1),
+            questions: field(2),
+            answers: field(3),
+            authorities: field(4),
+            additionals: field(5),
+        })
+    }
+}
+
+/// A message under construction; names are compressed against the suffixes already written.
+struct Message {
+    bytes: Vec<u8>,
+    suffixes: HashMap<String, u16>,
+    records: u16,
+}
+
+impl Message {
+    fn new(id: u16, flags: u16) -> Self {
+        let mut bytes = Vec::with_capacity(512);
+        bytes.extend_from_slice(&id.to_be_bytes());
+        bytes.extend_from_slice(&flags.to_be_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+        Self { bytes, suffixes: HashMap::new(), records: 0 }
+    }
+
+    fn question(&mut self, name: &str, qtype: u16, class: u16) {
+        self.name(name);
+        self.bytes.extend_from_slice(&qtype.to_be_bytes());
+        self.bytes.extend_from_slice(&class.to_be_bytes());
+        let questions = read_u16(&self.bytes, 4).unwrap_or(0) + 1;
+        self.bytes[4..6].copy_from_slice(&questions.to_be_bytes());
+    }
+
+    fn record(&mut self, name: &str, rtype: u16, class: u16, ttl: u32, rdata: impl FnOnce(&mut Self)) {
+        self.name(name);
+        self.bytes.extend_from_slice(&rtype.to_be_bytes());
+        self.bytes.extend_from_slice(&class.to_be_bytes());
+        self.bytes.extend_from_slice(&ttl.to_be_bytes());
+        let length_at = self.bytes.len();
+        self.bytes.extend_from_slice(&[0, 0]);
+        rdata(self);
+        let length = (self.bytes.len() - length_at - 2) as u16;
+        self.bytes[length_at..length_at + 2].copy_from_slice(&length.to_be_bytes());
+        self.records += 1;
+    }
+
+    fn name(&mut self, name: &str) {
+        let name = name.trim_end_matches('.');
+        let mut rest = name;
+        while !rest.is_empty() {
+            if let Some(&pointer) = self.suffixes.get(&rest.to_ascii_lowercase()) {
+                self.bytes.extend_from_slice(&(0xC000 | pointer).to_be_bytes());
+                return;
+            }
+            // Pointers only reach the first 16 KiB of a message
+            if self.bytes.len() < 0x3FFF {
+                self.suffixes.insert(rest.to_ascii_lowercase(), self.bytes.len() as u16);
+            }
+            let (label, tail) = rest.split_once('.').unwrap_or((rest, ""));
+            self.bytes.push(label.len().min(63) as u8);
+            self.bytes.extend_from_slice(&label.as_bytes()[..label.len().min(63)]);
+            rest = tail;
+        }
+        self.bytes.push(0);
+    }
+
+    fn set_counts(&mut self, answers: u16, additionals: u16) {
+        self.bytes[6..8].copy_from_slice(&answers.to_be_bytes());
+        self.bytes[10..12].copy_from_slice(&additionals.to_be_bytes());
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+fn read_u16(message: &[u8], offset: usize) -> Result<u16> {
+    message
+        .get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| anyhow!("DNS message ends at {} bytes, reading offset {}", message.len(), offset))
+}
+
+/// The dotted name at `offset` and the offset just past it, following compression pointers.
+fn read_name(message: &[u8], offset: usize) -> Result<(String, usize)> {
+    let mut labels = Vec::new();
+    let (mut at, mut end, mut jumps) = (offset, None, 0);
+    loop {
+        let len = *message.get(at).ok_or_else(|| anyhow!("name at {} runs past the message", offset))? as usize;
+        match len {
+            0 => break,
+            _ if len & 0xC0 == 0xC0 => {
+                jumps += 1;
+                if jumps > MAX_POINTER_JUMPS {
+                    return Err(anyhow!("name at {} has a compression loop", offset));
+                }
+                end.get_or_insert(at + 2);
+                at = (read_u16(message, at)? & 0x3FFF) as usize;
+            }
+            _ if len > 63 => return Err(anyhow!("label of {} bytes in name at {}", len, offset)),
+            _ => {
+                let label = message.get(at + 1..at + 1 + len).ok_or_else(|| anyhow!("label runs past the message"))?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                at += 1 + len;
+            }
+        }
+    }
+    Ok((labels.join("."), end.unwrap_or(at + 1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_ptr_query_resolves_the_advertised_node() {
+        let advertisement = ServiceAdvertisement::default();
+        let query = query(0x4D41, MATTER_SERVICE);
+        let response = answer(&query, &advertisement).unwrap();
+
+        assert_eq!(&response[..2], &[0x4D, 0x41]);
+        assert_eq!(parse_response(&response).unwrap(), advertisement);
+        // PTR answer with SRV, TXT and AAAA additionals; the repeated instance name is a two-byte pointer
+        let header = Header::parse(&response).unwrap();
+        assert_eq!((header.answers, header.additionals), (1, 3));
+        assert!(response.len() < 2 * advertisement.instance_name().len() + 100);
+
+        let mut other = Message::new(1, 0);
+        other.question("_http._tcp.local", TYPE_PTR, CLASS_IN);
+        assert!(answer(&other.finish(), &advertisement).is_none());
+        assert!(parse_response(&query).is_err());
+    }
+
+    #[test]
+    fn discovery_times_a_round_trip_to_a_responder() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let responder = crate::fixtures::mdns_responder("127.0.0.1:0".parse().unwrap(), ServiceAdvertisement::default())
+                .await
+                .unwrap();
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let (advertisement, timing) = discover(&socket, responder.addr, Duration::from_secs(5)).await.unwrap();
+
+            assert_eq!(advertisement.port, MATTER_PORT);
+            assert_eq!(timing.instance, advertisement.instance);
+            assert!(timing.round_trip_ms > 0.0 && timing.parse_ms > 0.0);
+            assert_eq!((timing.query_bytes, timing.records), (query(1, MATTER_SERVICE).len(), 4));
+            assert_eq!(responder.received_bytes(), timing.query_bytes as u64);
+        });
+    }
+}
@@ -0,0 +1,1843 @@
+// matter-project/src/mqtt_sn.rs
+/*!
+MQTT-SN - the UDP variant of MQTT for constrained devices, measured against a gateway
+
+MQTT-SN v1.2 replaces MQTT's TCP connection with datagrams to a gateway.
+Topic names are registered once and then replaced by 2-byte topic IDs. QoS -1
+publishes go to predefined topics without connecting first. A sleeping client
+tells the gateway how long it will sleep; the gateway buffers its messages
+and hands them over when the client wakes and sends a PINGREQ.
+
+A local gateway (with the broker built in, as an aggregating gateway would
+forward to one) and a local MQTT 3.1.1 broker over TCP run the same device
+workload: connect, topic registration, publishes at each QoS, and a sleeping
+client that receives buffered commands. The MQTT client sleeps by
+disconnecting and wakes by reconnecting to its persistent session.
+
+The MQTT side runs over each [`MqttTransport`]: plain TCP, WebSockets, TLS
+and WebSockets over TLS, which is what browsers and cloud brokers expect.
+Bytes are counted as both ends write them to TCP, beneath any TLS, so
+handshakes, records, frames and client masks are all included. The first
+connection over TLS is a full handshake; the reconnect after sleeping
+resumes the session, as a client with a session cache would. TLS needs
+the `tls-rustls` feature; without it those transports are reported as
+unsupported.
+
+Over plain TCP the broker also measures what MQTT keeps on the device's
+behalf: a retained state message, a last will published when the device
+dies without DISCONNECT, and persistent sessions that queue messages until
+the client returns or the session expires.
+*/
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf, ReadHalf, WriteHalf};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{JoinHandle, JoinSet};
+
+use self::tls::Tls;
+use crate::builder::analyzer_builder;
+use crate::coap_bindings::{
+    accept_websocket_upgrade, read_websocket_frame, websocket_frame, websocket_frame_of, websocket_upgrade, WEBSOCKET_CLOSE,
+};
+use crate::firmware_update::{
+    mqtt_packet, mqtt_publish, mqtt_split, mqtt_string, MQTT_CONNACK, MQTT_CONNECT, MQTT_DISCONNECT, MQTT_PUBACK,
+    MQTT_PUBLISH, MQTT_SUBACK, MQTT_SUBSCRIBE,
+};
+use crate::transport_modes::{IPV4_TCP_HEADER_BYTES, IPV4_UDP_HEADER_BYTES, TCP_HANDSHAKE_BYTES, TCP_TEARDOWN_BYTES};
+
+// MQTT-SN v1.2 message types
+const SN_CONNECT: u8 = 0x04;
+const SN_CONNACK: u8 = 0x05;
+const SN_REGISTER: u8 = 0x0A;
+const SN_REGACK: u8 = 0x0B;
+pub(crate) const SN_PUBLISH: u8 = 0x0C;
+const SN_PUBACK: u8 = 0x0D;
+const SN_SUBSCRIBE: u8 = 0x12;
+const SN_SUBACK: u8 = 0x13;
+const SN_PINGREQ: u8 = 0x16;
+const SN_PINGRESP: u8 = 0x17;
+const SN_DISCONNECT: u8 = 0x18;
+
+// MQTT-SN flags
+pub(crate) const SN_QOS_0: u8 = 0x00;
+const SN_QOS_1: u8 = 0x20;
+pub(crate) const SN_QOS_MINUS_ONE: u8 = 0x60;
+const SN_QOS_MASK: u8 = 0x60;
+const SN_CLEAN_SESSION: u8 = 0x04;
+pub(crate) const SN_TOPIC_NORMAL: u8 = 0x00;
+pub(crate) const SN_TOPIC_PREDEFINED: u8 = 0x01;
+const SN_TOPIC_TYPE_MASK: u8 = 0x03;
+const SN_PROTOCOL_ID: u8 = 0x01;
+const SN_ACCEPTED: u8 = 0x00;
+const SN_REJECTED_INVALID_TOPIC: u8 = 0x02;
+
+// MQTT 3.1.1 beyond what the firmware-update analyzer uses
+const MQTT_PINGREQ: u8 = 0xC0;
+const MQTT_PINGRESP: u8 = 0xD0;
+const MQTT_RETAIN: u8 = 0x01;
+const MQTT_CLEAN_SESSION: u8 = 0x02;
+const MQTT_WILL: u8 = 0x04;
+const MQTT_WILL_QOS1: u8 = 0x08;
+
+const CLIENT_ID: &str = "sensor-dev1";
+const KEEP_ALIVE_SECS: u16 = 60;
+/// Known to the gateway and the device in advance, so QoS -1 needs no registration.
+const PREDEFINED_TOPIC: &str = "sensors/dev1/alarm";
+const PREDEFINED_TOPIC_ID: u16 = 1;
+const TELEMETRY_TOPIC: &str = "sensors/dev1/telemetry";
+const COMMAND_TOPIC: &str = "cmd/dev1";
+const STATE_TOPIC: &str = "sensors/dev1/state";
+const WILL_TOPIC: &str = "status/dev1";
+const WILL_MESSAGE: &[u8] = b"offline";
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long to wait for fire-and-forget publishes to reach the gateway or broker.
+const DELIVERY_WAIT: Duration = Duration::from_millis(500);
+
+/// What MQTT runs over, from the device's point of view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MqttTransport {
+    Tcp,
+    WebSocket,
+    Tls,
+    SecureWebSocket,
+}
+
+impl MqttTransport {
+    pub const ALL: [MqttTransport; 4] = [Self::Tcp, Self::WebSocket, Self::Tls, Self::SecureWebSocket];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Tcp => "mqtt",
+            Self::WebSocket => "mqtt/ws",
+            Self::Tls => "mqtt/tls",
+            Self::SecureWebSocket => "mqtt/wss",
+        }
+    }
+
+    fn is_websocket(self) -> bool {
+        matches!(self, Self::WebSocket | Self::SecureWebSocket)
+    }
+
+    fn is_tls(self) -> bool {
+        matches!(self, Self::Tls | Self::SecureWebSocket)
+    }
+}
+
+impl FromStr for MqttTransport {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "tcp" => Ok(Self::Tcp),
+            "ws" | "websocket" => Ok(Self::WebSocket),
+            "tls" => Ok(Self::Tls),
+            "wss" => Ok(Self::SecureWebSocket),
+            _ => Err(anyhow!("unknown MQTT transport: {} (expected tcp, ws, tls or wss)", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MqttSnConfig {
+    /// Publishes per QoS mode.
+    pub publishes: u32,
+    pub payload_bytes: usize,
+    /// Commands queued for the device while it sleeps.
+    pub buffered_messages: u32,
+    /// Sleep duration announced in the MQTT-SN DISCONNECT.
+    pub sleep_duration_secs: u16,
+    /// Transports the MQTT baseline runs over; TCP is the reference for the others.
+    pub mqtt_transports: Vec<MqttTransport>,
+    /// Topics the session-restore client subscribes to.
+    pub session_topics: u32,
+    /// How long the broker keeps a persistent session after its client leaves.
+    pub session_expiry: Duration,
+    /// How often the device publishes its state; without a retained message a
+    /// new subscriber waits for the next one.
+    pub state_interval: Duration,
+    /// Keep-alive of the client that goes silent before its will is published.
+    pub will_keep_alive_secs: u16,
+}
+
+impl Default for MqttSnConfig {
+    fn default() -> Self {
+        Self {
+            publishes: 20,
+            payload_bytes: 8,
+            buffered_messages: 3,
+            sleep_duration_secs: 300,
+            mqtt_transports: MqttTransport::ALL.to_vec(),
+            session_topics: 5,
+            session_expiry: Duration::from_millis(250),
+            state_interval: Duration::from_secs(60),
+            will_keep_alive_secs: 1,
+        }
+    }
+}
+
+analyzer_builder!(MqttSnAnalyzerBuilder => MqttSnAnalyzer(MqttSnConfig) {
+    publishes: u32,
+    payload_bytes: usize,
+    buffered_messages: u32,
+    sleep_duration_secs: u16,
+    mqtt_transports: Vec<MqttTransport>,
+    session_topics: u32,
+    session_expiry: Duration,
+    state_interval: Duration,
+    will_keep_alive_secs: u16,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MqttSnMetrics {
+    pub payload_bytes: usize,
+    pub connect: Vec<ConnectCost>,
+    pub registration: TopicRegistration,
+    pub publishes: Vec<PublishMetrics>,
+    pub sleeping_client: Vec<SleepingClientMetrics>,
+    /// Each other MQTT transport against MQTT over plain TCP.
+    pub transport_overhead: Vec<TransportOverhead>,
+    /// Transports that could not be measured, with the reason.
+    pub unsupported: Vec<String>,
+    pub mqtt_session: MqttSessionMetrics,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConnectCost {
+    /// "mqtt-sn" or "mqtt".
+    pub protocol: String,
+    pub connect_ms: f64,
+    /// CONNECT and CONNACK with IP headers, plus the TCP, TLS and WebSocket handshakes for MQTT.
+    pub bytes: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TopicRegistration {
+    pub topic: String,
+    pub topic_id: u16,
+    pub register_ms: f64,
+    /// REGISTER and REGACK with IP headers.
+    pub bytes: usize,
+    /// An MQTT PUBLISH carries the topic name; MQTT-SN carries a 2-byte ID instead.
+    pub bytes_saved_per_publish: usize,
+    /// Publishes after which the registration has paid for itself.
+    pub break_even_publishes: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublishMetrics {
+    /// e.g. "mqtt-sn qos -1".
+    pub mode: String,
+    pub qos: i8,
+    pub sent: u32,
+    /// Publishes counted by the gateway or broker.
+    pub delivered: u32,
+    /// PUBLISH and any PUBACK with IP headers, per publish.
+    pub bytes_per_publish: f64,
+    /// Until the PUBACK; only acknowledged modes have one.
+    pub median_ack_ms: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SleepingClientMetrics {
+    pub protocol: String,
+    pub buffered: u32,
+    pub delivered_on_wake: u32,
+    /// From waking to the last buffered message (MQTT-SN: to the PINGRESP that ends the flush).
+    pub wake_ms: f64,
+    /// One wake and return to sleep: MQTT-SN PINGREQ, the buffered PUBLISHes and PUBACKs, PINGRESP;
+    /// MQTT reconnect, the queued PUBLISHes and PUBACKs, DISCONNECT, any close frame or
+    /// close_notify, and TCP teardown.
+    pub bytes_per_cycle: usize,
+}
+
+/// An MQTT transport minus MQTT over plain TCP on the same workload; positive means it costs more.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransportOverhead {
+    pub transport: String,
+    pub connect_ms: f64,
+    pub connect_bytes: i64,
+    pub qos0_bytes_per_publish: f64,
+    pub qos1_bytes_per_publish: f64,
+    pub bytes_per_cycle: i64,
+}
+
+/// Retained messages, last will and session restoration, over MQTT on plain TCP.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MqttSessionMetrics {
+    pub retained: RetainedMessageMetrics,
+    pub last_will: LastWillMetrics,
+    pub session_restore: Vec<SessionRestore>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RetainedMessageMetrics {
+    /// The retained PUBLISH and its PUBACK, the same as any QoS 1 publish.
+    pub publish_bytes: usize,
+    /// Topic and payload the broker holds until the message is replaced or cleared.
+    pub broker_bytes_held: usize,
+    /// From a new subscriber's SUBSCRIBE until it has the retained state.
+    pub new_subscriber_ms: f64,
+    /// SUBSCRIBE, SUBACK, the retained PUBLISH and its PUBACK, with IP headers.
+    pub new_subscriber_bytes: usize,
+    /// Without retain a new subscriber waits for the next state publish: half the interval on average.
+    pub without_retain_expected_wait_ms: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LastWillMetrics {
+    /// Will topic and message carried in CONNECT.
+    pub connect_overhead_bytes: usize,
+    /// From the connection closing without DISCONNECT until a subscriber has the will.
+    pub closed_connection_ms: f64,
+    /// From a client's last packet until a subscriber has the will, when the client
+    /// goes silent and the broker waits out one and a half keep-alive periods.
+    pub keep_alive_ms: f64,
+    pub keep_alive_secs: u16,
+    /// Whether the will was withheld after a DISCONNECT, as it should be.
+    pub withheld_on_disconnect: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionRestore {
+    /// "persistent", "clean" (Clean Session set) or "expired" (reconnected after the session expiry).
+    pub case: String,
+    pub session_present: bool,
+    /// From reconnecting until the client is subscribed again and has every queued message.
+    pub reconnect_ms: f64,
+    /// The reconnect, any SUBSCRIBE and SUBACK, and the queued PUBLISHes and PUBACKs, with headers.
+    pub reconnect_bytes: usize,
+    pub delivered: u32,
+    /// Published while the client was away and never delivered.
+    pub lost: u32,
+}
+
+pub struct MqttSnAnalyzer {
+    config: MqttSnConfig,
+}
+
+impl MqttSnAnalyzer {
+    pub fn new(config: MqttSnConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_mqtt_sn(&mut self) -> Result<MqttSnMetrics> {
+        println!("\n📨 Analyzing MQTT-SN against a Gateway");
+        println!("------------------------------------------------");
+
+        let gateway = Server::gateway().await?;
+        let payload = vec![0x5A; self.config.payload_bytes];
+
+        // Connect
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        socket.connect(gateway.addr).await?;
+        let start = Instant::now();
+        let connect = sn_message(SN_CONNECT, &sn_connect_body());
+        let connack = sn_request(&socket, &connect, SN_CONNACK).await?;
+        let sn_connect = ConnectCost {
+            protocol: "mqtt-sn".to_string(),
+            connect_ms: start.elapsed().as_secs_f64() * 1000.0,
+            bytes: connect.len() + connack.len() + 2 * IPV4_UDP_HEADER_BYTES,
+        };
+
+        // Topic registration
+        let mut register_body = vec![0, 0];
+        register_body.extend_from_slice(&1u16.to_be_bytes());
+        register_body.extend_from_slice(TELEMETRY_TOPIC.as_bytes());
+        let register = sn_message(SN_REGISTER, &register_body);
+        let start = Instant::now();
+        let regack = sn_request(&socket, &register, SN_REGACK).await?;
+        let register_ms = start.elapsed().as_secs_f64() * 1000.0;
+        let (_, body) = sn_split(&regack).ok_or_else(|| anyhow!("malformed REGACK"))?;
+        if body.get(4) != Some(&SN_ACCEPTED) {
+            return Err(anyhow!("gateway rejected the topic registration"));
+        }
+        let topic_id = u16::from_be_bytes([body[0], body[1]]);
+        let registration_bytes = register.len() + regack.len() + 2 * IPV4_UDP_HEADER_BYTES;
+        // MQTT's 2-byte length prefix and name against MQTT-SN's 2-byte ID
+        let bytes_saved_per_publish = TELEMETRY_TOPIC.len();
+        let registration = TopicRegistration {
+            topic: TELEMETRY_TOPIC.to_string(),
+            topic_id,
+            register_ms,
+            bytes: registration_bytes,
+            bytes_saved_per_publish,
+            break_even_publishes: registration_bytes.div_ceil(bytes_saved_per_publish),
+        };
+
+        // Publishes
+        let publishes = self.config.publishes;
+        // QoS -1 skips CONNECT entirely, so it goes out from a socket the gateway has never seen
+        let unconnected = UdpSocket::bind("127.0.0.1:0").await?;
+        unconnected.connect(gateway.addr).await?;
+        let mut publish_metrics = vec![
+            sn_publishes(&unconnected, &gateway, SN_QOS_MINUS_ONE | SN_TOPIC_PREDEFINED, PREDEFINED_TOPIC_ID, &payload, publishes).await?,
+            sn_publishes(&socket, &gateway, SN_QOS_0 | SN_TOPIC_NORMAL, topic_id, &payload, publishes).await?,
+            sn_publishes(&socket, &gateway, SN_QOS_1 | SN_TOPIC_NORMAL, topic_id, &payload, publishes).await?,
+        ];
+
+        // Sleeping client
+        let buffered = self.config.buffered_messages;
+        let sn_sleep = self.sn_sleep_cycle(&socket, &gateway, buffered).await?;
+
+        // MQTT on the same workload, over each transport
+        let (mut runs, mut unsupported) = (Vec::new(), Vec::new());
+        for &transport in &self.config.mqtt_transports {
+            // Fresh TLS ends per transport, so no run resumes another's session
+            let tls = match transport.is_tls().then(Tls::new).transpose() {
+                Ok(tls) => tls.map(Arc::new),
+                Err(e) => {
+                    unsupported.push(format!("{}: {}", transport.name(), e));
+                    continue;
+                }
+            };
+            runs.push(self.mqtt_run(transport, tls, &payload).await?);
+        }
+
+        let transport_overhead: Vec<_> = match runs.iter().find(|run| run.transport == MqttTransport::Tcp) {
+            Some(tcp) => runs.iter().filter(|run| run.transport != MqttTransport::Tcp).map(|run| run.overhead_over(tcp)).collect(),
+            None => Vec::new(),
+        };
+        let mqtt_session = self.mqtt_session().await?;
+
+        let (mut connect, mut sleeping_client) = (vec![sn_connect], vec![sn_sleep]);
+        for run in runs {
+            connect.push(run.connect);
+            publish_metrics.extend(run.publishes);
+            sleeping_client.push(run.sleep);
+        }
+
+        for cost in &connect {
+            println!("✅ {} connect: {:.3}ms, {} bytes", cost.protocol, cost.connect_ms, cost.bytes);
+        }
+        println!("✅ Topic registration: {:.3}ms, {} bytes, saves {} bytes/publish (pays off after {} publishes)",
+                 registration.register_ms, registration.bytes, registration.bytes_saved_per_publish,
+                 registration.break_even_publishes);
+        for publish in &publish_metrics {
+            println!("✅ {}: {:.1} bytes/publish, {}/{} delivered{}",
+                     publish.mode, publish.bytes_per_publish, publish.delivered, publish.sent,
+                     publish.median_ack_ms.map(|ms| format!(", {:.3}ms median ack", ms)).unwrap_or_default());
+        }
+        for sleep in &sleeping_client {
+            println!("✅ {} sleeping client: {}/{} buffered messages on wake in {:.3}ms, {} bytes/cycle",
+                     sleep.protocol, sleep.delivered_on_wake, sleep.buffered, sleep.wake_ms, sleep.bytes_per_cycle);
+        }
+        for overhead in &transport_overhead {
+            println!("✅ {} over mqtt: {:+} connect bytes ({:+.3}ms), {:+.1}/{:+.1} bytes per QoS 0/1 publish, {:+} bytes/cycle",
+                     overhead.transport, overhead.connect_bytes, overhead.connect_ms, overhead.qos0_bytes_per_publish,
+                     overhead.qos1_bytes_per_publish, overhead.bytes_per_cycle);
+        }
+        for skipped in &unsupported {
+            println!("⚠️ Skipped {}", skipped);
+        }
+        let retained = &mqtt_session.retained;
+        println!("✅ Retained state: {} bytes to publish, {} bytes held, new subscriber has it in {:.3}ms ({} bytes) vs {:.0}ms waiting for the next publish",
+                 retained.publish_bytes, retained.broker_bytes_held, retained.new_subscriber_ms,
+                 retained.new_subscriber_bytes, retained.without_retain_expected_wait_ms);
+        let will = &mqtt_session.last_will;
+        println!("✅ Last will: +{} CONNECT bytes, delivered {:.3}ms after the connection closed, {:.1}ms after the client went silent (keep-alive {}s), {} on DISCONNECT",
+                 will.connect_overhead_bytes, will.closed_connection_ms, will.keep_alive_ms, will.keep_alive_secs,
+                 if will.withheld_on_disconnect { "withheld" } else { "sent" });
+        for restore in &mqtt_session.session_restore {
+            println!("✅ Session restore ({}): session present {}, {:.3}ms, {} bytes, {}/{} queued messages delivered",
+                     restore.case, restore.session_present, restore.reconnect_ms, restore.reconnect_bytes,
+                     restore.delivered, restore.delivered + restore.lost);
+        }
+
+        Ok(MqttSnMetrics {
+            payload_bytes: self.config.payload_bytes,
+            connect,
+            registration,
+            publishes: publish_metrics,
+            sleeping_client,
+            transport_overhead,
+            unsupported,
+            mqtt_session,
+        })
+    }
+
+    /// Retained messages, last will and session restoration against a broker on plain TCP.
+    async fn mqtt_session(&self) -> Result<MqttSessionMetrics> {
+        let broker = Broker::start(MqttTransport::Tcp, None, self.config.session_expiry).await?;
+        Ok(MqttSessionMetrics {
+            retained: self.retained_message(&broker).await?,
+            last_will: self.last_will(&broker).await?,
+            session_restore: self.session_restore(&broker).await?,
+        })
+    }
+
+    /// The device retains its state; a subscriber that arrives later gets it on SUBSCRIBE.
+    async fn retained_message(&self, broker: &Broker) -> Result<RetainedMessageMetrics> {
+        let state = br#"{"temperature":21.5,"battery":87}"#;
+        let mut device = broker.connect().await?;
+        device.request(&mqtt_connect("sensor-dev1-state", true, KEEP_ALIVE_SECS, None), MQTT_CONNACK).await?;
+        let mut publish = mqtt_publish(STATE_TOPIC, Some(1), state);
+        publish[0] |= MQTT_RETAIN;
+        let before = broker.wire.bytes();
+        device.request(&publish, MQTT_PUBACK).await?;
+        let publish_bytes = broker.wire.bytes() - before;
+        leave(device).await?;
+
+        let mut subscriber = broker.connect().await?;
+        subscriber.request(&mqtt_connect("dashboard", true, KEEP_ALIVE_SECS, None), MQTT_CONNACK).await?;
+        let (start, before) = (Instant::now(), broker.wire.bytes());
+        subscriber.send(&mqtt_subscribe(1, &[STATE_TOPIC])).await?;
+        let (arrived, payload, retain) = await_publish(&mut subscriber, STATE_TOPIC, REPLY_TIMEOUT).await?;
+        let new_subscriber_bytes = broker.wire.bytes() - before;
+        if !retain || payload != state {
+            return Err(anyhow!("the retained state did not reach a new subscriber"));
+        }
+        leave(subscriber).await?;
+
+        Ok(RetainedMessageMetrics {
+            publish_bytes,
+            broker_bytes_held: STATE_TOPIC.len() + state.len(),
+            new_subscriber_ms: (arrived - start).as_secs_f64() * 1000.0,
+            new_subscriber_bytes,
+            without_retain_expected_wait_ms: self.config.state_interval.as_secs_f64() * 1000.0 / 2.0,
+        })
+    }
+
+    /// A monitor subscribes to the device's will, then the device dies three ways:
+    /// its connection closes, it goes silent, and (as a control) it disconnects cleanly.
+    async fn last_will(&self, broker: &Broker) -> Result<LastWillMetrics> {
+        let will = Some((WILL_TOPIC, WILL_MESSAGE));
+        let mut monitor = broker.connect().await?;
+        monitor.request(&mqtt_connect("monitor", true, KEEP_ALIVE_SECS, None), MQTT_CONNACK).await?;
+        monitor.request(&mqtt_subscribe(1, &[WILL_TOPIC]), MQTT_SUBACK).await?;
+
+        // The process crashes and the OS closes its socket without a DISCONNECT
+        let mut device = broker.connect().await?;
+        device.request(&mqtt_connect("sensor-dev1-will", true, KEEP_ALIVE_SECS, will), MQTT_CONNACK).await?;
+        let start = Instant::now();
+        drop(device);
+        let (arrived, ..) = await_publish(&mut monitor, WILL_TOPIC, REPLY_TIMEOUT).await?;
+        let closed_connection_ms = (arrived - start).as_secs_f64() * 1000.0;
+
+        // Power or link is lost: nothing closes the connection, so only the keep-alive notices
+        let keep_alive_secs = self.config.will_keep_alive_secs;
+        let mut device = broker.connect().await?;
+        device.request(&mqtt_connect("sensor-dev1-will", true, keep_alive_secs, will), MQTT_CONNACK).await?;
+        let start = Instant::now();
+        let limit = Duration::from_secs(keep_alive_secs as u64) * 2 + REPLY_TIMEOUT;
+        let (arrived, ..) = await_publish(&mut monitor, WILL_TOPIC, limit).await?;
+        let keep_alive_ms = (arrived - start).as_secs_f64() * 1000.0;
+        drop(device);
+
+        let mut device = broker.connect().await?;
+        device.request(&mqtt_connect("sensor-dev1-will", true, KEEP_ALIVE_SECS, will), MQTT_CONNACK).await?;
+        leave(device).await?;
+        let withheld_on_disconnect = await_publish(&mut monitor, WILL_TOPIC, DELIVERY_WAIT).await.is_err();
+        leave(monitor).await?;
+
+        Ok(LastWillMetrics {
+            connect_overhead_bytes: mqtt_connect(CLIENT_ID, true, KEEP_ALIVE_SECS, will).len()
+                - mqtt_connect(CLIENT_ID, true, KEEP_ALIVE_SECS, None).len(),
+            closed_connection_ms,
+            keep_alive_ms,
+            keep_alive_secs,
+            withheld_on_disconnect,
+        })
+    }
+
+    /// A client subscribes, leaves, has commands published while away, and comes back:
+    /// to its persistent session, with a clean session, and after its session expired.
+    async fn session_restore(&self, broker: &Broker) -> Result<Vec<SessionRestore>> {
+        let topics: Vec<String> = (0..self.config.session_topics.max(1)).map(|i| format!("cmd/dev2/{}", i)).collect();
+        let topics: Vec<&str> = topics.iter().map(String::as_str).collect();
+        let subscribe = mqtt_subscribe(1, &topics);
+        let away = self.config.buffered_messages;
+        let mut restores = Vec::new();
+
+        for case in ["persistent", "clean", "expired"] {
+            let (client_id, clean) = (format!("gateway-{}", case), case == "clean");
+            let connect = mqtt_connect(&client_id, clean, KEEP_ALIVE_SECS, None);
+            let mut link = broker.connect().await?;
+            link.request(&connect, MQTT_CONNACK).await?;
+            link.request(&subscribe, MQTT_SUBACK).await?;
+            leave(link).await?;
+            broker.wait_until_away(&client_id).await?;
+            if case == "expired" {
+                tokio::time::sleep(self.config.session_expiry + Duration::from_millis(50)).await;
+            }
+            broker.server.queue_commands(topics[0], away).await?;
+
+            let (start, before) = (Instant::now(), broker.wire.bytes());
+            let mut link = broker.connect().await?;
+            let connack = link.request(&connect, MQTT_CONNACK).await?;
+            let session_present = connack.get(2) == Some(&1);
+            let mut delivered = 0;
+            if session_present {
+                while delivered < away {
+                    await_publish(&mut link, topics[0], REPLY_TIMEOUT).await?;
+                    delivered += 1;
+                }
+            } else {
+                link.request(&subscribe, MQTT_SUBACK).await?;
+            }
+            let reconnect_ms = start.elapsed().as_secs_f64() * 1000.0;
+            let reconnect_bytes = broker.wire.bytes() - before;
+            leave(link).await?;
+
+            restores.push(SessionRestore {
+                case: case.to_string(),
+                session_present,
+                reconnect_ms,
+                reconnect_bytes,
+                delivered,
+                lost: away - delivered,
+            });
+        }
+        Ok(restores)
+    }
+
+    /// Connect, QoS 0 and QoS 1 publishes and a sleep cycle against a broker on `transport`.
+    async fn mqtt_run(&self, transport: MqttTransport, tls: Option<Arc<Tls>>, payload: &[u8]) -> Result<MqttRun> {
+        let broker = Broker::start(transport, tls, self.config.session_expiry).await?;
+
+        let start = Instant::now();
+        let before = broker.wire.bytes();
+        let mut link = broker.connect().await?;
+        link.request(&mqtt_connect(CLIENT_ID, false, KEEP_ALIVE_SECS, None), MQTT_CONNACK).await?;
+        let connect = ConnectCost {
+            protocol: transport.name().to_string(),
+            connect_ms: start.elapsed().as_secs_f64() * 1000.0,
+            bytes: broker.wire.bytes() - before,
+        };
+
+        let publishes = vec![
+            mqtt_publishes(&mut link, &broker, false, payload, self.config.publishes).await?,
+            mqtt_publishes(&mut link, &broker, true, payload, self.config.publishes).await?,
+        ];
+        let sleep = mqtt_sleep_cycle(link, &broker, self.config.buffered_messages).await?;
+        Ok(MqttRun { transport, connect, publishes, sleep })
+    }
+
+    /// Subscribes, announces the sleep, has commands queued, then wakes with a PINGREQ.
+    async fn sn_sleep_cycle(&self, socket: &UdpSocket, gateway: &Server, buffered: u32) -> Result<SleepingClientMetrics> {
+        let mut subscribe_body = vec![SN_QOS_1 | SN_TOPIC_NORMAL];
+        subscribe_body.extend_from_slice(&2u16.to_be_bytes());
+        subscribe_body.extend_from_slice(COMMAND_TOPIC.as_bytes());
+        sn_request(socket, &sn_message(SN_SUBSCRIBE, &subscribe_body), SN_SUBACK).await?;
+        let duration = self.config.sleep_duration_secs.to_be_bytes();
+        sn_request(socket, &sn_message(SN_DISCONNECT, &duration), SN_DISCONNECT).await?;
+
+        gateway.queue_commands(COMMAND_TOPIC, buffered).await?;
+
+        let start = Instant::now();
+        let pingreq = sn_message(SN_PINGREQ, CLIENT_ID.as_bytes());
+        socket.send(&pingreq).await?;
+        let mut bytes = pingreq.len() + IPV4_UDP_HEADER_BYTES;
+        let mut delivered = 0;
+        let mut buffer = vec![0u8; 2048];
+        loop {
+            let len = tokio::time::timeout(REPLY_TIMEOUT, socket.recv(&mut buffer))
+                .await
+                .map_err(|_| anyhow!("no PINGRESP after waking"))??;
+            bytes += len + IPV4_UDP_HEADER_BYTES;
+            match sn_split(&buffer[..len]) {
+                Some((SN_PUBLISH, body)) if body.len() >= 5 => {
+                    delivered += 1;
+                    let puback = sn_message(SN_PUBACK, &[body[1], body[2], body[3], body[4], SN_ACCEPTED]);
+                    socket.send(&puback).await?;
+                    bytes += puback.len() + IPV4_UDP_HEADER_BYTES;
+                }
+                Some((SN_PINGRESP, _)) => break,
+                other => debug!("MQTT-SN client ignored {:?} while waking", other.map(|(kind, _)| kind)),
+            }
+        }
+
+        Ok(SleepingClientMetrics {
+            protocol: "mqtt-sn".to_string(),
+            buffered,
+            delivered_on_wake: delivered,
+            wake_ms: start.elapsed().as_secs_f64() * 1000.0,
+            bytes_per_cycle: bytes,
+        })
+    }
+}
+
+/// One transport's results on the MQTT workload: QoS 0 then QoS 1 publishes.
+struct MqttRun {
+    transport: MqttTransport,
+    connect: ConnectCost,
+    publishes: Vec<PublishMetrics>,
+    sleep: SleepingClientMetrics,
+}
+
+impl MqttRun {
+    fn overhead_over(&self, tcp: &MqttRun) -> TransportOverhead {
+        TransportOverhead {
+            transport: self.transport.name().to_string(),
+            connect_ms: self.connect.connect_ms - tcp.connect.connect_ms,
+            connect_bytes: self.connect.bytes as i64 - tcp.connect.bytes as i64,
+            qos0_bytes_per_publish: self.publishes[0].bytes_per_publish - tcp.publishes[0].bytes_per_publish,
+            qos1_bytes_per_publish: self.publishes[1].bytes_per_publish - tcp.publishes[1].bytes_per_publish,
+            bytes_per_cycle: self.sleep.bytes_per_cycle as i64 - tcp.sleep.bytes_per_cycle as i64,
+        }
+    }
+}
+
+/// A command published by the backend while the device is away; acknowledged once queued.
+struct BackendPublish {
+    topic: String,
+    payload: Vec<u8>,
+    queued: oneshot::Sender<()>,
+}
+
+/// The gateway or broker task, with a count of the device publishes it has taken.
+struct Server {
+    addr: SocketAddr,
+    backend: mpsc::UnboundedSender<BackendPublish>,
+    received: Arc<AtomicU32>,
+    task: JoinHandle<Result<()>>,
+}
+
+impl Server {
+    async fn gateway() -> Result<Self> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let addr = socket.local_addr()?;
+        let (backend, commands) = mpsc::unbounded_channel();
+        let received = Arc::new(AtomicU32::new(0));
+        let task = tokio::spawn(gateway(socket, commands, received.clone()));
+        Ok(Self { addr, backend, received, task })
+    }
+
+    fn received(&self) -> u32 {
+        self.received.load(Ordering::SeqCst)
+    }
+
+    /// Waits until `target` publishes have arrived or [`DELIVERY_WAIT`] passes.
+    async fn wait_for_received(&self, target: u32) -> u32 {
+        let deadline = Instant::now() + DELIVERY_WAIT;
+        while self.received() < target && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        self.received()
+    }
+
+    async fn queue_commands(&self, topic: &str, count: u32) -> Result<()> {
+        for i in 0..count {
+            let (queued, done) = oneshot::channel();
+            let payload = format!("set-interval={}", 60 * (i + 1)).into_bytes();
+            self.backend
+                .send(BackendPublish { topic: topic.to_string(), payload, queued })
+                .map_err(|_| anyhow!("backend channel closed"))?;
+            done.await?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// An MQTT-SN message: a 1-byte length, or 0x01 and a 2-byte length when longer than 255.
+pub(crate) fn sn_message(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(4 + body.len());
+    if 2 + body.len() <= 255 {
+        message.push((2 + body.len()) as u8);
+    } else {
+        message.push(0x01);
+        message.extend_from_slice(&((4 + body.len()) as u16).to_be_bytes());
+    }
+    message.push(kind);
+    message.extend_from_slice(body);
+    message
+}
+
+/// (type, body) of an MQTT-SN message.
+fn sn_split(message: &[u8]) -> Option<(u8, &[u8])> {
+    let (len, header) = match *message.first()? {
+        0x01 => (u16::from_be_bytes([*message.get(1)?, *message.get(2)?]) as usize, 3),
+        len => (len as usize, 1),
+    };
+    Some((*message.get(header)?, message.get(header + 1..len)?))
+}
+
+fn sn_connect_body() -> Vec<u8> {
+    let mut body = vec![SN_CLEAN_SESSION, SN_PROTOCOL_ID];
+    body.extend_from_slice(&KEEP_ALIVE_SECS.to_be_bytes());
+    body.extend_from_slice(CLIENT_ID.as_bytes());
+    body
+}
+
+/// Sends a message to the gateway and waits for the reply of type `reply`.
+async fn sn_request(socket: &UdpSocket, message: &[u8], reply: u8) -> Result<Vec<u8>> {
+    socket.send(message).await?;
+    let mut buffer = vec![0u8; 2048];
+    let deadline = tokio::time::Instant::now() + REPLY_TIMEOUT;
+    loop {
+        let len = tokio::time::timeout_at(deadline, socket.recv(&mut buffer))
+            .await
+            .map_err(|_| anyhow!("no MQTT-SN reply of type {:#04x}", reply))??;
+        if sn_split(&buffer[..len]).is_some_and(|(kind, _)| kind == reply) {
+            return Ok(buffer[..len].to_vec());
+        }
+    }
+}
+
+async fn sn_publishes(
+    socket: &UdpSocket,
+    gateway: &Server,
+    flags: u8,
+    topic_id: u16,
+    payload: &[u8],
+    count: u32,
+) -> Result<PublishMetrics> {
+    let acknowledged = flags & SN_QOS_MASK == SN_QOS_1;
+    let before = gateway.received();
+    let (mut bytes, mut latencies) = (0, Vec::new());
+
+    for i in 0..count {
+        let message_id = if acknowledged { i as u16 + 1 } else { 0 };
+        let mut body = vec![flags];
+        body.extend_from_slice(&topic_id.to_be_bytes());
+        body.extend_from_slice(&message_id.to_be_bytes());
+        body.extend_from_slice(payload);
+        let publish = sn_message(SN_PUBLISH, &body);
+        bytes += publish.len() + IPV4_UDP_HEADER_BYTES;
+
+        if acknowledged {
+            let start = Instant::now();
+            let puback = sn_request(socket, &publish, SN_PUBACK).await?;
+            latencies.push(start.elapsed().as_secs_f64() * 1000.0);
+            bytes += puback.len() + IPV4_UDP_HEADER_BYTES;
+        } else {
+            socket.send(&publish).await?;
+        }
+    }
+
+    let delivered = gateway.wait_for_received(before + count).await - before;
+    let qos = match flags & SN_QOS_MASK {
+        SN_QOS_MINUS_ONE => -1,
+        SN_QOS_1 => 1,
+        _ => 0,
+    };
+    Ok(publish_metrics(format!("mqtt-sn qos {}", qos), qos, count, delivered, bytes, latencies))
+}
+
+async fn mqtt_publishes(
+    link: &mut MqttLink,
+    broker: &Broker,
+    acknowledged: bool,
+    payload: &[u8],
+    count: u32,
+) -> Result<PublishMetrics> {
+    let (before, bytes_before) = (broker.server.received(), broker.wire.bytes());
+    let mut latencies = Vec::new();
+
+    for i in 0..count {
+        let packet_id = acknowledged.then_some(i as u16 + 1);
+        let publish = mqtt_publish(TELEMETRY_TOPIC, packet_id, payload);
+        if acknowledged {
+            let start = Instant::now();
+            link.request(&publish, MQTT_PUBACK).await?;
+            latencies.push(start.elapsed().as_secs_f64() * 1000.0);
+        } else {
+            link.send(&publish).await?;
+        }
+    }
+
+    let delivered = broker.server.wait_for_received(before + count).await - before;
+    let bytes = broker.wire.bytes() - bytes_before;
+    let qos = acknowledged as i8;
+    Ok(publish_metrics(format!("{} qos {}", broker.transport.name(), qos), qos, count, delivered, bytes, latencies))
+}
+
+fn publish_metrics(mode: String, qos: i8, sent: u32, delivered: u32, bytes: usize, mut latencies: Vec<f64>) -> PublishMetrics {
+    latencies.sort_by(|a, b| a.total_cmp(b));
+    PublishMetrics {
+        mode,
+        qos,
+        sent,
+        delivered,
+        bytes_per_publish: if sent > 0 { bytes as f64 / sent as f64 } else { 0.0 },
+        median_ack_ms: (!latencies.is_empty()).then(|| percentile(&latencies, 0.5)),
+    }
+}
+
+/// Disconnects, has commands queued on the persistent session, then reconnects for them.
+async fn mqtt_sleep_cycle(mut link: MqttLink, broker: &Broker, buffered: u32) -> Result<SleepingClientMetrics> {
+    link.request(&mqtt_subscribe(2, &[COMMAND_TOPIC]), MQTT_SUBACK).await?;
+    leave(link).await?;
+
+    broker.wait_until_away(CLIENT_ID).await?;
+    broker.server.queue_commands(COMMAND_TOPIC, buffered).await?;
+
+    let start = Instant::now();
+    let before = broker.wire.bytes();
+    let mut link = broker.connect().await?;
+    link.request(&mqtt_connect(CLIENT_ID, false, KEEP_ALIVE_SECS, None), MQTT_CONNACK).await?;
+    let mut delivered = 0;
+    while delivered < buffered {
+        await_publish(&mut link, COMMAND_TOPIC, REPLY_TIMEOUT)
+            .await
+            .map_err(|e| anyhow!("{} of {} queued messages arrived after reconnecting: {}", delivered, buffered, e))?;
+        delivered += 1;
+    }
+    let wake_ms = start.elapsed().as_secs_f64() * 1000.0;
+    leave(link).await?;
+
+    Ok(SleepingClientMetrics {
+        protocol: broker.transport.name().to_string(),
+        buffered,
+        delivered_on_wake: delivered,
+        wake_ms,
+        bytes_per_cycle: broker.wire.bytes() - before + TCP_TEARDOWN_BYTES,
+    })
+}
+
+/// CONNECT with an optional will (QoS 1, not retained). Without Clean Session,
+/// subscriptions and queued messages outlive the connection.
+fn mqtt_connect(client_id: &str, clean: bool, keep_alive_secs: u16, will: Option<(&str, &[u8])>) -> Vec<u8> {
+    let mut flags = if clean { MQTT_CLEAN_SESSION } else { 0 };
+    if will.is_some() {
+        flags |= MQTT_WILL | MQTT_WILL_QOS1;
+    }
+    let mut body = mqtt_string("MQTT");
+    body.extend_from_slice(&[4, flags]);
+    body.extend_from_slice(&keep_alive_secs.to_be_bytes());
+    body.extend_from_slice(&mqtt_string(client_id));
+    if let Some((topic, message)) = will {
+        body.extend_from_slice(&mqtt_string(topic));
+        body.extend_from_slice(&(message.len() as u16).to_be_bytes());
+        body.extend_from_slice(message);
+    }
+    mqtt_packet(MQTT_CONNECT, &body)
+}
+
+/// What the broker takes from a CONNECT.
+struct ConnectRequest {
+    client_id: String,
+    clean: bool,
+    keep_alive_secs: u16,
+    will: Option<(String, Vec<u8>)>,
+}
+
+impl ConnectRequest {
+    fn parse(body: &[u8]) -> Option<Self> {
+        // Protocol name (6) and level (1), then the flags and keep-alive
+        let flags = *body.get(7)?;
+        let keep_alive_secs = u16::from_be_bytes([*body.get(8)?, *body.get(9)?]);
+        let mut rest = body.get(10..)?;
+        let mut field = || {
+            let len = u16::from_be_bytes([*rest.first()?, *rest.get(1)?]) as usize;
+            let value = rest.get(2..2 + len)?.to_vec();
+            rest = &rest[2 + len..];
+            Some(value)
+        };
+        let client_id = String::from_utf8_lossy(&field()?).into_owned();
+        let will = match flags & MQTT_WILL != 0 {
+            true => Some((String::from_utf8_lossy(&field()?).into_owned(), field()?)),
+            false => None,
+        };
+        Some(Self { client_id, clean: flags & MQTT_CLEAN_SESSION != 0, keep_alive_secs, will })
+    }
+}
+
+/// SUBSCRIBE to each topic at QoS 1.
+fn mqtt_subscribe(packet_id: u16, topics: &[&str]) -> Vec<u8> {
+    let mut body = packet_id.to_be_bytes().to_vec();
+    for topic in topics {
+        body.extend_from_slice(&mqtt_string(topic));
+        body.push(1);
+    }
+    mqtt_packet(MQTT_SUBSCRIBE, &body)
+}
+
+/// Reads until a PUBLISH on `topic` arrives and acknowledges it if QoS 1:
+/// when it arrived, its payload and whether it was a retained message.
+async fn await_publish(link: &mut MqttLink, topic: &str, wait: Duration) -> Result<(Instant, Vec<u8>, bool)> {
+    let deadline = tokio::time::Instant::now() + wait;
+    loop {
+        let packet = tokio::time::timeout_at(deadline, link.receive())
+            .await
+            .map_err(|_| anyhow!("nothing published on {} within {:?}", topic, wait))??
+            .ok_or_else(|| anyhow!("broker closed the connection"))?;
+        let arrived = Instant::now();
+        let Some((header, body)) = mqtt_split(&packet) else {
+            continue;
+        };
+        let Some(publish) = (header & 0xF0 == MQTT_PUBLISH).then(|| PublishPacket::parse(header, body)).flatten() else {
+            continue;
+        };
+        if let Some(packet_id) = publish.packet_id {
+            link.send(&mqtt_packet(MQTT_PUBACK, &packet_id.to_be_bytes())).await?;
+        }
+        if publish.topic == topic {
+            return Ok((arrived, publish.payload.to_vec(), publish.retain));
+        }
+    }
+}
+
+/// The parts of a PUBLISH the broker and clients act on.
+struct PublishPacket<'a> {
+    topic: String,
+    packet_id: Option<u16>,
+    retain: bool,
+    payload: &'a [u8],
+}
+
+impl<'a> PublishPacket<'a> {
+    fn parse(header: u8, body: &'a [u8]) -> Option<Self> {
+        let topic_len = u16::from_be_bytes([*body.first()?, *body.get(1)?]) as usize;
+        let topic = String::from_utf8_lossy(body.get(2..2 + topic_len)?).into_owned();
+        let (packet_id, payload) = match (header >> 1) & 0x03 {
+            0 => (None, &body[2 + topic_len..]),
+            _ => (Some(u16::from_be_bytes([*body.get(2 + topic_len)?, *body.get(3 + topic_len)?])), &body[4 + topic_len..]),
+        };
+        Some(Self { topic, packet_id, retain: header & MQTT_RETAIN != 0, payload })
+    }
+}
+
+/// DISCONNECT, then close.
+async fn leave(mut link: MqttLink) -> Result<()> {
+    link.send(&mqtt_packet(MQTT_DISCONNECT, &[])).await?;
+    link.close().await
+}
+
+/// One whole MQTT packet, or `None` at end of stream.
+async fn read_mqtt_packet<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Option<Vec<u8>>> {
+    let mut header = [0u8; 1];
+    if stream.read(&mut header).await? == 0 {
+        return Ok(None);
+    }
+    let mut packet = header.to_vec();
+    let mut remaining = 0usize;
+    for i in 0..4 {
+        let byte = stream.read_u8().await?;
+        packet.push(byte);
+        remaining |= ((byte & 0x7F) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    let start = packet.len();
+    packet.resize(start + remaining, 0);
+    stream.read_exact(&mut packet[start..]).await?;
+    Ok(Some(packet))
+}
+
+/// Anything MQTT runs over: TCP, or TLS on top of it.
+trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
+/// Bytes both ends of the broker's connections have written to TCP, with an
+/// IPv4/TCP header per write. Pure ACKs are not counted.
+#[derive(Default)]
+struct WireCounter(AtomicUsize);
+
+impl WireCounter {
+    fn add(&self, bytes: usize) {
+        self.0.fetch_add(bytes, Ordering::SeqCst);
+    }
+
+    fn bytes(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A TCP stream that counts its writes, beneath any TLS.
+struct Counted {
+    stream: TcpStream,
+    wire: Arc<WireCounter>,
+}
+
+impl AsyncRead for Counted {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Counted {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.stream).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            if written > 0 {
+                this.wire.add(written + IPV4_TCP_HEADER_BYTES);
+            }
+        }
+        poll
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+type Stream = BufReader<Box<dyn Transport>>;
+
+/// One end of an MQTT connection. Over WebSockets each packet travels in its own binary frame.
+struct MqttLink {
+    reader: MqttReader,
+    writer: MqttWriter,
+}
+
+impl MqttLink {
+    fn new(stream: Stream, websocket: bool, client: bool) -> Self {
+        let (reader, writer) = tokio::io::split(stream);
+        Self {
+            reader: MqttReader { stream: reader, websocket },
+            writer: MqttWriter { stream: writer, websocket, client },
+        }
+    }
+
+    async fn send(&mut self, packet: &[u8]) -> Result<()> {
+        self.writer.send(packet).await
+    }
+
+    /// The next packet, or `None` once the peer has closed.
+    async fn receive(&mut self) -> Result<Option<Vec<u8>>> {
+        self.reader.receive().await
+    }
+
+    /// Sends a packet and reads until one of type `reply` arrives.
+    async fn request(&mut self, packet: &[u8], reply: u8) -> Result<Vec<u8>> {
+        self.send(packet).await?;
+        loop {
+            let response = tokio::time::timeout(REPLY_TIMEOUT, self.receive())
+                .await
+                .map_err(|_| anyhow!("no MQTT reply of type {:#04x}", reply))??
+                .ok_or_else(|| anyhow!("broker closed the connection"))?;
+            if response.first() == Some(&reply) {
+                return Ok(response);
+            }
+        }
+    }
+
+    async fn close(self) -> Result<()> {
+        self.writer.close().await
+    }
+}
+
+/// The receiving half of an [`MqttLink`], so the broker can read and write at once.
+struct MqttReader {
+    stream: ReadHalf<Stream>,
+    websocket: bool,
+}
+
+impl MqttReader {
+    async fn receive(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.websocket {
+            return Ok(Some(read_websocket_frame(&mut self.stream).await?.1));
+        }
+        read_mqtt_packet(&mut self.stream).await
+    }
+}
+
+/// The sending half of an [`MqttLink`]; clients mask their WebSocket frames.
+struct MqttWriter {
+    stream: WriteHalf<Stream>,
+    websocket: bool,
+    client: bool,
+}
+
+impl MqttWriter {
+    async fn send(&mut self, packet: &[u8]) -> Result<()> {
+        if self.websocket {
+            self.stream.write_all(&websocket_frame(packet, self.client)).await?;
+        } else {
+            self.stream.write_all(packet).await?;
+        }
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// A WebSocket close frame (status 1000), TLS close_notify, then FIN.
+    async fn close(mut self) -> Result<()> {
+        if self.websocket {
+            let close = websocket_frame_of(WEBSOCKET_CLOSE, &1000u16.to_be_bytes(), self.client);
+            self.stream.write_all(&close).await?;
+        }
+        self.stream.shutdown().await?;
+        Ok(())
+    }
+}
+
+/// An MQTT broker on one transport, and the counter both ends write through.
+struct Broker {
+    server: Server,
+    transport: MqttTransport,
+    tls: Option<Arc<Tls>>,
+    wire: Arc<WireCounter>,
+    state: Arc<Mutex<BrokerState>>,
+}
+
+impl Broker {
+    async fn start(transport: MqttTransport, tls: Option<Arc<Tls>>, session_expiry: Duration) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (backend, commands) = mpsc::unbounded_channel();
+        let received = Arc::new(AtomicU32::new(0));
+        let wire = Arc::new(WireCounter::default());
+        let state = Arc::new(Mutex::new(BrokerState::new(session_expiry)));
+        let connections = Connections { transport, tls: tls.clone(), wire: wire.clone(), state: state.clone(), received: received.clone() };
+        let task = tokio::spawn(broker(listener, connections, commands));
+        Ok(Self { server: Server { addr, backend, received, task }, transport, tls, wire, state })
+    }
+
+    /// Connects through the TCP handshake and any TLS handshake and WebSocket upgrade.
+    async fn connect(&self) -> Result<MqttLink> {
+        let stream = TcpStream::connect(self.server.addr).await?;
+        stream.set_nodelay(true)?;
+        self.wire.add(TCP_HANDSHAKE_BYTES);
+        let stream = Counted { stream, wire: self.wire.clone() };
+        let stream: Box<dyn Transport> = match &self.tls {
+            Some(tls) => tls.connect(stream).await?,
+            None => Box::new(stream),
+        };
+        let mut stream = BufReader::new(stream);
+        if self.transport.is_websocket() {
+            websocket_upgrade(&mut stream, self.server.addr, "/mqtt", Some("mqtt")).await?;
+        }
+        Ok(MqttLink::new(stream, self.transport.is_websocket(), true))
+    }
+
+    /// Waits until the broker has seen `client_id`'s connection end, so what is
+    /// published next is queued for its session rather than sent.
+    async fn wait_until_away(&self, client_id: &str) -> Result<()> {
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        while !self.state.lock().unwrap().is_away(client_id) {
+            if Instant::now() >= deadline {
+                return Err(anyhow!("broker still has {} connected", client_id));
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        Ok(())
+    }
+}
+
+/// The broker's end of [`Broker::connect`].
+async fn accept(stream: TcpStream, transport: MqttTransport, tls: Option<&Tls>, wire: &Arc<WireCounter>) -> Result<MqttLink> {
+    stream.set_nodelay(true)?;
+    let stream = Counted { stream, wire: wire.clone() };
+    let stream: Box<dyn Transport> = match tls {
+        Some(tls) => tls.accept(stream).await?,
+        None => Box::new(stream),
+    };
+    let mut stream = BufReader::new(stream);
+    if transport.is_websocket() {
+        accept_websocket_upgrade(&mut stream, Some("mqtt")).await?;
+    }
+    Ok(MqttLink::new(stream, transport.is_websocket(), false))
+}
+
+/// What the gateway remembers about a client between datagrams.
+struct SnClient {
+    id: String,
+    addr: SocketAddr,
+    asleep: bool,
+    subscriptions: Vec<u16>,
+    buffered: Vec<Vec<u8>>,
+    next_message_id: u16,
+}
+
+/// Gateway and broker state: registered topics and the clients it has seen.
+struct Gateway {
+    topics: Vec<(String, u16)>,
+    clients: Vec<SnClient>,
+    received: Arc<AtomicU32>,
+}
+
+impl Gateway {
+    fn topic_id(&mut self, name: &str) -> u16 {
+        if let Some((_, id)) = self.topics.iter().find(|(topic, _)| topic == name) {
+            return *id;
+        }
+        let id = self.topics.iter().map(|(_, id)| *id).max().unwrap_or(0) + 1;
+        self.topics.push((name.to_string(), id));
+        id
+    }
+
+    /// The replies to one datagram from `peer`, in order.
+    fn handle(&mut self, message: &[u8], peer: SocketAddr) -> Vec<Vec<u8>> {
+        let Some((kind, body)) = sn_split(message) else {
+            return Vec::new();
+        };
+        match kind {
+            SN_CONNECT if body.len() >= 4 => {
+                let id = String::from_utf8_lossy(&body[4..]).into_owned();
+                let clean = body[0] & SN_CLEAN_SESSION != 0;
+                self.clients.retain(|client| client.id != id || !clean);
+                match self.clients.iter_mut().find(|client| client.id == id) {
+                    Some(client) => {
+                        client.addr = peer;
+                        client.asleep = false;
+                    }
+                    None => self.clients.push(SnClient {
+                        id,
+                        addr: peer,
+                        asleep: false,
+                        subscriptions: Vec::new(),
+                        buffered: Vec::new(),
+                        next_message_id: 1,
+                    }),
+                }
+                vec![sn_message(SN_CONNACK, &[SN_ACCEPTED])]
+            }
+            SN_REGISTER if body.len() >= 4 => {
+                let id = self.topic_id(&String::from_utf8_lossy(&body[4..]));
+                let mut regack = id.to_be_bytes().to_vec();
+                regack.extend_from_slice(&[body[2], body[3], SN_ACCEPTED]);
+                vec![sn_message(SN_REGACK, &regack)]
+            }
+            SN_PUBLISH if body.len() >= 5 => {
+                let (flags, topic_id) = (body[0], u16::from_be_bytes([body[1], body[2]]));
+                let qos = flags & SN_QOS_MASK;
+                let connected = self.clients.iter().any(|client| client.addr == peer && !client.asleep);
+                let known_topic = match flags & SN_TOPIC_TYPE_MASK {
+                    SN_TOPIC_PREDEFINED => topic_id == PREDEFINED_TOPIC_ID,
+                    _ => self.topics.iter().any(|(_, id)| *id == topic_id),
+                };
+                // QoS -1 is the only publish accepted without a connection
+                if !known_topic || !(connected || qos == SN_QOS_MINUS_ONE) {
+                    return if qos == SN_QOS_1 {
+                        vec![sn_message(SN_PUBACK, &[body[1], body[2], body[3], body[4], SN_REJECTED_INVALID_TOPIC])]
+                    } else {
+                        Vec::new()
+                    };
+                }
+                self.received.fetch_add(1, Ordering::SeqCst);
+                if qos == SN_QOS_1 {
+                    vec![sn_message(SN_PUBACK, &[body[1], body[2], body[3], body[4], SN_ACCEPTED])]
+                } else {
+                    Vec::new()
+                }
+            }
+            SN_SUBSCRIBE if body.len() >= 3 => {
+                let topic_id = self.topic_id(&String::from_utf8_lossy(&body[3..]));
+                let Some(client) = self.clients.iter_mut().find(|client| client.addr == peer) else {
+                    return Vec::new();
+                };
+                if !client.subscriptions.contains(&topic_id) {
+                    client.subscriptions.push(topic_id);
+                }
+                let mut suback = vec![SN_QOS_1];
+                suback.extend_from_slice(&topic_id.to_be_bytes());
+                suback.extend_from_slice(&[body[1], body[2], SN_ACCEPTED]);
+                vec![sn_message(SN_SUBACK, &suback)]
+            }
+            SN_PINGREQ => {
+                // A sleeping client names itself and collects what was buffered for it
+                let mut replies = Vec::new();
+                if let Some(client) = self.clients.iter_mut().find(|client| !body.is_empty() && client.id.as_bytes() == body) {
+                    client.addr = peer;
+                    replies.append(&mut client.buffered);
+                }
+                replies.push(sn_message(SN_PINGRESP, &[]));
+                replies
+            }
+            SN_DISCONNECT => {
+                if body.len() == 2 {
+                    if let Some(client) = self.clients.iter_mut().find(|client| client.addr == peer) {
+                        client.asleep = true;
+                    }
+                } else {
+                    self.clients.retain(|client| client.addr != peer);
+                }
+                vec![sn_message(SN_DISCONNECT, &[])]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Delivers a backend publish to subscribers: now if awake, buffered if asleep.
+    fn route(&mut self, topic: &str, payload: &[u8]) -> Vec<(SocketAddr, Vec<u8>)> {
+        let topic_id = self.topic_id(topic);
+        let mut sends = Vec::new();
+        for client in self.clients.iter_mut().filter(|client| client.subscriptions.contains(&topic_id)) {
+            let mut body = vec![SN_QOS_1 | SN_TOPIC_NORMAL];
+            body.extend_from_slice(&topic_id.to_be_bytes());
+            body.extend_from_slice(&client.next_message_id.to_be_bytes());
+            body.extend_from_slice(payload);
+            client.next_message_id = client.next_message_id.wrapping_add(1).max(1);
+            let publish = sn_message(SN_PUBLISH, &body);
+            if client.asleep {
+                client.buffered.push(publish);
+            } else {
+                sends.push((client.addr, publish));
+            }
+        }
+        sends
+    }
+}
+
+async fn gateway(socket: UdpSocket, mut backend: mpsc::UnboundedReceiver<BackendPublish>, received: Arc<AtomicU32>) -> Result<()> {
+    let mut state = Gateway {
+        topics: vec![(PREDEFINED_TOPIC.to_string(), PREDEFINED_TOPIC_ID)],
+        clients: Vec::new(),
+        received,
+    };
+    let mut buffer = vec![0u8; 2048];
+
+    loop {
+        tokio::select! {
+            datagram = socket.recv_from(&mut buffer) => {
+                let (len, peer) = datagram?;
+                for reply in state.handle(&buffer[..len], peer) {
+                    socket.send_to(&reply, peer).await?;
+                }
+            }
+            Some(publish) = backend.recv() => {
+                for (addr, message) in state.route(&publish.topic, &publish.payload) {
+                    socket.send_to(&message, addr).await?;
+                }
+                let _ = publish.queued.send(());
+            }
+        }
+    }
+}
+
+/// A client's MQTT session: kept across connections unless it asked for a clean
+/// one, and until [`BrokerState::session_expiry`] after it leaves.
+struct BrokerSession {
+    client_id: String,
+    clean: bool,
+    /// Where the client's connection takes packets, while it has one.
+    outbound: Option<mpsc::UnboundedSender<Vec<u8>>>,
+    away_since: Option<Instant>,
+    subscriptions: Vec<String>,
+    queued: Vec<(String, Vec<u8>)>,
+    will: Option<(String, Vec<u8>)>,
+    next_packet_id: u16,
+}
+
+impl BrokerSession {
+    /// A QoS 1 PUBLISH with the session's next packet ID.
+    fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+        self.next_packet_id = self.next_packet_id.wrapping_add(1).max(1);
+        let mut packet = mqtt_publish(topic, Some(self.next_packet_id), payload);
+        if retain {
+            packet[0] |= MQTT_RETAIN;
+        }
+        packet
+    }
+}
+
+/// Sessions and retained messages, shared by the broker's connections.
+struct BrokerState {
+    sessions: Vec<BrokerSession>,
+    /// The last retained message per topic.
+    retained: Vec<(String, Vec<u8>)>,
+    session_expiry: Duration,
+}
+
+impl BrokerState {
+    fn new(session_expiry: Duration) -> Self {
+        Self { sessions: Vec::new(), retained: Vec::new(), session_expiry }
+    }
+
+    fn session(&mut self, client_id: &str) -> Option<&mut BrokerSession> {
+        self.sessions.iter_mut().find(|session| session.client_id == client_id)
+    }
+
+    fn is_away(&self, client_id: &str) -> bool {
+        self.sessions.iter().find(|session| session.client_id == client_id).is_none_or(|session| session.outbound.is_none())
+    }
+
+    /// Drops sessions whose client has been away longer than the expiry.
+    fn expire(&mut self) {
+        let expiry = self.session_expiry;
+        self.sessions.retain(|session| session.away_since.is_none_or(|since| since.elapsed() <= expiry));
+    }
+
+    /// Attaches a connection to its client's session: whether one was present,
+    /// and the messages queued while the client was away.
+    fn connect(&mut self, connect: ConnectRequest, outbound: mpsc::UnboundedSender<Vec<u8>>) -> (bool, Vec<Vec<u8>>) {
+        self.expire();
+        if connect.clean {
+            self.sessions.retain(|session| session.client_id != connect.client_id);
+        }
+        let present = self.session(&connect.client_id).is_some();
+        if !present {
+            self.sessions.push(BrokerSession {
+                client_id: connect.client_id.clone(),
+                clean: connect.clean,
+                outbound: None,
+                away_since: None,
+                subscriptions: Vec::new(),
+                queued: Vec::new(),
+                will: None,
+                next_packet_id: 0,
+            });
+        }
+        let session = self.session(&connect.client_id).expect("session just attached");
+        session.clean = connect.clean;
+        session.outbound = Some(outbound);
+        session.away_since = None;
+        session.will = connect.will;
+        let queued = std::mem::take(&mut session.queued);
+        let packets = queued.iter().map(|(topic, payload)| session.publish(topic, payload, false)).collect();
+        (present, packets)
+    }
+
+    /// Adds subscriptions and returns the retained messages on those topics.
+    fn subscribe(&mut self, client_id: &str, topics: Vec<String>) -> Vec<Vec<u8>> {
+        let Some(session) = self.sessions.iter_mut().find(|session| session.client_id == client_id) else {
+            return Vec::new();
+        };
+        let retained = self.retained.iter().filter(|(topic, _)| topics.contains(topic));
+        let packets = retained.map(|(topic, payload)| session.publish(topic, payload, true)).collect();
+        for topic in topics {
+            if !session.subscriptions.contains(&topic) {
+                session.subscriptions.push(topic);
+            }
+        }
+        packets
+    }
+
+    /// Routes a publish at QoS 1: sent to subscribers that are connected, queued
+    /// for persistent sessions that are not. An empty retained payload clears the topic.
+    fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) {
+        self.expire();
+        if retain {
+            self.retained.retain(|(retained, _)| retained != topic);
+            if !payload.is_empty() {
+                self.retained.push((topic.to_string(), payload.to_vec()));
+            }
+        }
+        for session in self.sessions.iter_mut().filter(|session| session.subscriptions.iter().any(|t| t == topic)) {
+            match session.outbound.clone() {
+                Some(outbound) => {
+                    let _ = outbound.send(session.publish(topic, payload, false));
+                }
+                None => session.queued.push((topic.to_string(), payload.to_vec())),
+            }
+        }
+    }
+
+    /// Detaches a connection. Unless the client sent DISCONNECT first, its will is published.
+    fn disconnect(&mut self, client_id: &str, outbound: &mpsc::UnboundedSender<Vec<u8>>, graceful: bool) {
+        // A connection the client has since replaced no longer owns the session
+        let Some(index) = self.sessions.iter().position(|session| {
+            session.client_id == client_id && session.outbound.as_ref().is_some_and(|current| current.same_channel(outbound))
+        }) else {
+            return;
+        };
+        let session = &mut self.sessions[index];
+        session.outbound = None;
+        session.away_since = Some(Instant::now());
+        let will = session.will.take();
+        if session.clean {
+            self.sessions.remove(index);
+        }
+        if let (false, Some((topic, message))) = (graceful, will) {
+            self.publish(&topic, &message, false);
+        }
+    }
+}
+
+/// What every broker connection shares.
+#[derive(Clone)]
+struct Connections {
+    transport: MqttTransport,
+    tls: Option<Arc<Tls>>,
+    wire: Arc<WireCounter>,
+    state: Arc<Mutex<BrokerState>>,
+    received: Arc<AtomicU32>,
+}
+
+/// Serves each connection in its own task, and routes backend publishes through the shared state.
+async fn broker(
+    listener: TcpListener,
+    connections: Connections,
+    mut backend: mpsc::UnboundedReceiver<BackendPublish>,
+) -> Result<()> {
+    let mut served = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, _) = accepted?;
+                let shared = connections.clone();
+                served.spawn(async move {
+                    let transport = shared.transport;
+                    let result = match accept(stream, transport, shared.tls.as_deref(), &shared.wire).await {
+                        Ok(link) => serve_mqtt(link, shared.state, shared.received).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = result {
+                        debug!("MQTT broker connection over {} ended: {}", transport.name(), e);
+                    }
+                });
+            }
+            Some(publish) = backend.recv() => {
+                connections.state.lock().unwrap().publish(&publish.topic, &publish.payload, false);
+                let _ = publish.queued.send(());
+            }
+            Some(_) = served.join_next() => {}
+        }
+    }
+}
+
+/// Serves one connection until the client closes it or misses its keep-alive. A
+/// writer task sends what this connection and other clients' publishes queue for it.
+async fn serve_mqtt(link: MqttLink, state: Arc<Mutex<BrokerState>>, received: Arc<AtomicU32>) -> Result<()> {
+    let MqttLink { mut reader, mut writer } = link;
+    let (outbound, mut packets) = mpsc::unbounded_channel::<Vec<u8>>();
+    let writing = tokio::spawn(async move {
+        while let Some(packet) = packets.recv().await {
+            writer.send(&packet).await?;
+        }
+        Ok::<_, anyhow::Error>(())
+    });
+
+    let (mut client_id, mut keep_alive, mut graceful) = (None, None, false);
+    // After DISCONNECT the client closes; reading on lets its close frame or close_notify arrive
+    let ended = loop {
+        let packet = match keep_alive {
+            // The broker gives up on a client after one and a half keep-alive periods of silence
+            Some(limit) => match tokio::time::timeout(limit, reader.receive()).await {
+                Ok(packet) => packet,
+                Err(_) => break Ok(()),
+            },
+            None => reader.receive().await,
+        };
+        let packet = match packet {
+            Ok(Some(packet)) => packet,
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e),
+        };
+        let Some((header, body)) = mqtt_split(&packet) else {
+            break Err(anyhow!("malformed MQTT packet"));
+        };
+        match header {
+            MQTT_CONNECT => {
+                let Some(connect) = ConnectRequest::parse(body) else {
+                    break Err(anyhow!("malformed CONNECT"));
+                };
+                keep_alive = (connect.keep_alive_secs > 0).then(|| Duration::from_millis(connect.keep_alive_secs as u64 * 1500));
+                client_id = Some(connect.client_id.clone());
+                // CONNACK goes out before anything routed to the new session
+                let mut state = state.lock().unwrap();
+                let (present, queued) = state.connect(connect, outbound.clone());
+                let _ = outbound.send(mqtt_packet(MQTT_CONNACK, &[present as u8, 0]));
+                for publish in queued {
+                    let _ = outbound.send(publish);
+                }
+            }
+            header if header & 0xF0 == MQTT_PUBLISH => {
+                let Some(publish) = PublishPacket::parse(header, body) else {
+                    break Err(anyhow!("malformed PUBLISH"));
+                };
+                received.fetch_add(1, Ordering::SeqCst);
+                state.lock().unwrap().publish(&publish.topic, publish.payload, publish.retain);
+                if let Some(packet_id) = publish.packet_id {
+                    let _ = outbound.send(mqtt_packet(MQTT_PUBACK, &packet_id.to_be_bytes()));
+                }
+            }
+            MQTT_SUBSCRIBE if body.len() >= 2 => {
+                let (mut topics, mut rest) = (Vec::new(), &body[2..]);
+                while rest.len() >= 2 {
+                    let topic_len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+                    let Some(topic) = rest.get(2..2 + topic_len) else { break };
+                    topics.push(String::from_utf8_lossy(topic).into_owned());
+                    rest = rest.get(3 + topic_len..).unwrap_or_default();
+                }
+                let mut suback = body[..2].to_vec();
+                suback.extend(topics.iter().map(|_| 1));
+                let mut state = state.lock().unwrap();
+                let retained = client_id.as_deref().map(|id| state.subscribe(id, topics)).unwrap_or_default();
+                let _ = outbound.send(mqtt_packet(MQTT_SUBACK, &suback));
+                for publish in retained {
+                    let _ = outbound.send(publish);
+                }
+            }
+            MQTT_PINGREQ => {
+                let _ = outbound.send(mqtt_packet(MQTT_PINGRESP, &[]));
+            }
+            MQTT_DISCONNECT => graceful = true,
+            _ => {}
+        }
+    };
+
+    if let Some(id) = &client_id {
+        state.lock().unwrap().disconnect(id, &outbound, graceful);
+    }
+    drop(outbound);
+    let written = writing.await?;
+    ended.and(written)
+}
+
+/// Both ends of TLS 1.3 with a self-signed P-256 certificate, as a cloud broker presents one.
+#[cfg(feature = "tls-rustls")]
+mod tls {
+    use super::Transport;
+    use anyhow::Result;
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer, ServerName};
+    use rustls::{ClientConfig, RootCertStore, ServerConfig};
+    use std::sync::Arc;
+    use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+    pub struct Tls {
+        connector: TlsConnector,
+        acceptor: TlsAcceptor,
+    }
+
+    impl Tls {
+        pub fn new() -> Result<Self> {
+            let rcgen::CertifiedKey { cert, key_pair } = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+            let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_pair.serialize_der()));
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+            let mut roots = RootCertStore::empty();
+            roots.add(cert.der().clone())?;
+            let client = ClientConfig::builder_with_provider(provider.clone())
+                .with_protocol_versions(&[&rustls::version::TLS13])?
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            let server = ServerConfig::builder_with_provider(provider)
+                .with_protocol_versions(&[&rustls::version::TLS13])?
+                .with_no_client_auth()
+                .with_single_cert(vec![cert.der().clone()], key)?;
+
+            Ok(Self {
+                connector: TlsConnector::from(Arc::new(client)),
+                acceptor: TlsAcceptor::from(Arc::new(server)),
+            })
+        }
+
+        pub async fn connect<S: Transport + 'static>(&self, stream: S) -> Result<Box<dyn Transport>> {
+            Ok(Box::new(self.connector.connect(ServerName::try_from("localhost")?, stream).await?))
+        }
+
+        pub async fn accept<S: Transport + 'static>(&self, stream: S) -> Result<Box<dyn Transport>> {
+            Ok(Box::new(self.acceptor.accept(stream).await?))
+        }
+    }
+}
+
+#[cfg(not(feature = "tls-rustls"))]
+mod tls {
+    use super::Transport;
+
+    pub enum Tls {}
+
+    impl Tls {
+        pub fn new() -> anyhow::Result<Self> {
+            Err(anyhow::anyhow!("no TLS backend compiled in (feature tls-rustls)"))
+        }
+
+        pub async fn connect<S: Transport + 'static>(&self, _stream: S) -> anyhow::Result<Box<dyn Transport>> {
+            match *self {}
+        }
+
+        pub async fn accept<S: Transport + 'static>(&self, _stream: S) -> anyhow::Result<Box<dyn Transport>> {
+            match *self {}
+        }
+    }
+}
+
+fn percentile(sorted: &[f64], quantile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    sorted[((sorted.len() - 1) as f64 * quantile).round() as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleeping_client_collects_buffered_messages_and_qos_minus_one_needs_no_connection() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let metrics = runtime
+            .block_on(MqttSnAnalyzer::builder().publishes(5u32).buffered_messages(2u32).build().analyze_mqtt_sn())
+            .unwrap();
+
+        for publish in &metrics.publishes {
+            assert_eq!(publish.delivered, publish.sent, "{}", publish.mode);
+        }
+        // MQTT-SN, then MQTT over TCP first
+        let [qos_minus_one, _, sn_qos1, mqtt_qos0, mqtt_qos1] = &metrics.publishes[..5] else { panic!("{:?}", metrics.publishes) };
+        assert_eq!((qos_minus_one.qos, qos_minus_one.median_ack_ms), (-1, None));
+        assert!(sn_qos1.median_ack_ms.is_some() && mqtt_qos1.median_ack_ms.is_some());
+        // Topic IDs and no TCP headers
+        assert!(qos_minus_one.bytes_per_publish < mqtt_qos0.bytes_per_publish);
+        assert!(sn_qos1.bytes_per_publish < mqtt_qos1.bytes_per_publish);
+
+        let [sn_sleep, mqtt_sleep] = &metrics.sleeping_client[..2] else { panic!() };
+        assert_eq!((sn_sleep.delivered_on_wake, mqtt_sleep.delivered_on_wake), (2, 2));
+        assert!(sn_sleep.bytes_per_cycle < mqtt_sleep.bytes_per_cycle);
+        assert!(metrics.connect[0].bytes < metrics.connect[1].bytes);
+        assert_eq!(metrics.registration.bytes_saved_per_publish, TELEMETRY_TOPIC.len());
+    }
+
+    #[test]
+    fn websocket_and_tls_cost_more_than_plain_tcp() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let metrics = runtime
+            .block_on(MqttSnAnalyzer::builder().publishes(3u32).buffered_messages(1u32).build().analyze_mqtt_sn())
+            .unwrap();
+
+        for sleep in &metrics.sleeping_client {
+            assert_eq!(sleep.delivered_on_wake, 1, "{}", sleep.protocol);
+        }
+        let overhead = |transport: &str| metrics.transport_overhead.iter().find(|o| o.transport == transport);
+        // The upgrade, then a frame header per packet and a mask on the client's
+        let ws = overhead("mqtt/ws").unwrap();
+        assert!(ws.connect_bytes > 0 && ws.qos0_bytes_per_publish >= 6.0 && ws.bytes_per_cycle > 0);
+        for transport in ["mqtt/tls", "mqtt/wss"] {
+            match overhead(transport) {
+                // A handshake, then a record header, content type and AEAD tag per write
+                Some(tls) => assert!(tls.connect_bytes > ws.connect_bytes && tls.qos0_bytes_per_publish > ws.qos0_bytes_per_publish),
+                None => assert!(metrics.unsupported.iter().any(|skipped| skipped.starts_with(transport))),
+            }
+        }
+    }
+
+    #[test]
+    fn retained_state_wills_and_persistent_sessions_survive_the_client() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let metrics = runtime
+            .block_on(
+                MqttSnAnalyzer::builder()
+                    .publishes(1u32)
+                    .buffered_messages(2u32)
+                    .mqtt_transports(vec![MqttTransport::Tcp])
+                    .session_expiry(Duration::from_millis(100))
+                    .build()
+                    .analyze_mqtt_sn(),
+            )
+            .unwrap();
+        let session = &metrics.mqtt_session;
+
+        assert!(session.retained.new_subscriber_ms < session.retained.without_retain_expected_wait_ms);
+        assert_eq!(session.last_will.connect_overhead_bytes, 2 + WILL_TOPIC.len() + 2 + WILL_MESSAGE.len());
+        // One and a half keep-alive periods
+        assert!(session.last_will.keep_alive_ms >= 1500.0 && session.last_will.closed_connection_ms < 1000.0);
+        assert!(session.last_will.withheld_on_disconnect);
+
+        let restored: Vec<_> = session.session_restore.iter().map(|r| (r.case.as_str(), r.session_present, r.delivered)).collect();
+        assert_eq!(restored, [("persistent", true, 2), ("clean", false, 0), ("expired", false, 0)]);
+    }
+
+    #[test]
+    fn long_messages_use_the_three_byte_length() {
+        let body = vec![0u8; 300];
+        let message = sn_message(SN_PUBLISH, &body);
+        assert_eq!(&message[..4], &[0x01, 0x01, 0x30, SN_PUBLISH]);
+        assert_eq!(sn_split(&message), Some((SN_PUBLISH, &body[..])));
+    }
+}
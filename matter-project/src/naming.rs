@@ -0,0 +1,284 @@
+// matter-project/src/naming.rs
+/*!
+Naming overhead - bytes per message spent on identifying what the payload is
+
+Each protocol names the value it carries: MQTT with a topic string, MQTT-SN
+with a registered 2-byte topic ID, CoAP with Uri-Path options, and Matter
+with an endpoint/cluster/attribute path in TLV. The same temperature reading
+is encoded under longer and shorter identifiers per protocol, at each
+payload size. A scheme's name bytes are its message minus
+the same message with the identifier left out, so length prefixes, option
+headers and TLV control bytes count against the name.
+*/
+
+use anyhow::{anyhow, Result};
+use metrics_core::tlv::{Tag, TlvError, TlvWriter};
+use serde::{Deserialize, Serialize};
+
+use crate::bdx::matter_message;
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{
+    coap_message, mqtt_packet, mqtt_publish, tlv, uri_path, COAP_NON, COAP_PUT, IM_REPORT_DATA, MQTT_PUBLISH, PROTOCOL_IM,
+};
+use crate::mqtt_sn::{sn_message, SN_PUBLISH, SN_QOS_0, SN_TOPIC_NORMAL};
+use crate::transport_modes::{IPV4_TCP_HEADER_BYTES, IPV4_UDP_HEADER_BYTES};
+
+/// What fits the 512-byte TLV buffer alongside the report's structure.
+const MAX_PAYLOAD_BYTES: usize = 400;
+const SUBSCRIPTION_ID: u32 = 0x5EB5_0001;
+
+// Temperature Measurement on endpoint 1, and a vendor's own cluster for the same reading
+const TEMPERATURE_CLUSTER: u32 = 0x0402;
+const MEASURED_VALUE: u32 = 0x0000;
+const VENDOR_CLUSTER: u32 = 0xFFF1_FC01;
+const VENDOR_ATTRIBUTE: u32 = 0xFFF1_0001;
+
+/// How one scheme names the reading.
+#[derive(Debug, Clone, Copy)]
+enum Identifier {
+    MqttTopic(&'static str),
+    MqttSnTopicId(u16),
+    CoapPath(&'static [&'static str]),
+    /// `minimal` encodes each ID in the smallest TLV integer that holds it,
+    /// as most Matter stacks do; otherwise at its declared width.
+    MatterPath { endpoint: u16, cluster: u32, attribute: u32, minimal: bool },
+}
+
+impl Identifier {
+    fn protocol(self) -> &'static str {
+        match self {
+            Self::MqttTopic(_) => "mqtt",
+            Self::MqttSnTopicId(_) => "mqtt-sn",
+            Self::CoapPath(_) => "coap",
+            Self::MatterPath { .. } => "matter",
+        }
+    }
+
+    fn describe(self) -> String {
+        match self {
+            Self::MqttTopic(topic) => topic.to_string(),
+            Self::MqttSnTopicId(id) => format!("topic id {}", id),
+            Self::CoapPath(segments) => format!("/{}", segments.join("/")),
+            Self::MatterPath { endpoint, cluster, attribute, minimal } => format!(
+                "{}/{:#06x}/{:#06x} ({})",
+                endpoint, cluster, attribute, if minimal { "minimal width" } else { "declared width" }
+            ),
+        }
+    }
+
+    /// The whole message with IP and UDP/TCP headers; `named: false` leaves the identifier out.
+    fn message(self, payload: &[u8], named: bool) -> Result<Vec<u8>> {
+        let (mut message, ip_header) = match self {
+            Self::MqttTopic(topic) => {
+                // Unnamed drops the topic field, length prefix included
+                let publish = if named { mqtt_publish(topic, None, payload) } else { mqtt_packet(MQTT_PUBLISH, payload) };
+                (publish, IPV4_TCP_HEADER_BYTES)
+            }
+            Self::MqttSnTopicId(id) => {
+                let mut body = vec![SN_QOS_0 | SN_TOPIC_NORMAL];
+                if named {
+                    body.extend_from_slice(&id.to_be_bytes());
+                }
+                body.extend_from_slice(&0u16.to_be_bytes());
+                body.extend_from_slice(payload);
+                (sn_message(SN_PUBLISH, &body), IPV4_UDP_HEADER_BYTES)
+            }
+            Self::CoapPath(segments) => {
+                let options = if named { uri_path(segments) } else { Vec::new() };
+                (coap_message(COAP_NON, COAP_PUT, 1, &options, payload), IPV4_UDP_HEADER_BYTES)
+            }
+            Self::MatterPath { endpoint, cluster, attribute, minimal } => {
+                let report = tlv(|writer| {
+                    writer.start_struct(Tag::Anonymous)?;
+                    writer.u32(Tag::Context(0), SUBSCRIPTION_ID)?;
+                    writer.start_array(Tag::Context(1))?; // AttributeReportIBs
+                    writer.start_struct(Tag::Anonymous)?;
+                    writer.start_struct(Tag::Context(1))?; // AttributeDataIB
+                    writer.u32(Tag::Context(0), 1)?; // DataVersion
+                    if named {
+                        writer.start_list(Tag::Context(1))?;
+                        id(writer, Tag::Context(2), endpoint as u32, 2, minimal)?;
+                        id(writer, Tag::Context(3), cluster, 4, minimal)?;
+                        id(writer, Tag::Context(4), attribute, 4, minimal)?;
+                        writer.end_container()?;
+                    }
+                    writer.octets(Tag::Context(2), payload)?;
+                    writer.end_container()?;
+                    writer.end_container()?;
+                    writer.end_container()?;
+                    writer.u8(Tag::Context(255), 11)?;
+                    writer.end_container()
+                })?;
+                (matter_message(PROTOCOL_IM, IM_REPORT_DATA, &report), IPV4_UDP_HEADER_BYTES)
+            }
+        };
+        message.resize(message.len() + ip_header, 0);
+        Ok(message)
+    }
+}
+
+/// An unsigned ID at `width` bytes, or the fewest that hold it.
+fn id(writer: &mut TlvWriter, tag: Tag, value: u32, width: usize, minimal: bool) -> Result<(), TlvError> {
+    let width = if minimal { (4 - value.leading_zeros() as usize / 8).max(1) } else { width };
+    match width {
+        1 => writer.u8(tag, value as u8),
+        2 => writer.u16(tag, value as u16),
+        _ => writer.u32(tag, value),
+    }
+}
+
+/// Longer and shorter names for the same reading in each protocol. Matter's
+/// IDs are fixed by the spec, so its schemes vary the cluster and the integer width.
+fn schemes() -> Vec<(&'static str, Identifier)> {
+    vec![
+        ("descriptive", Identifier::MqttTopic("building-7/floor-3/room-312/sensors/temperature-sensor-01/temperature")),
+        ("typical", Identifier::MqttTopic("sensors/dev1/temperature")),
+        ("terse", Identifier::MqttTopic("s/1/t")),
+        ("registered", Identifier::MqttSnTopicId(1)),
+        (
+            "descriptive",
+            Identifier::CoapPath(&["building-7", "floor-3", "room-312", "sensors", "temperature-sensor-01", "temperature"]),
+        ),
+        // LwM2M Temperature object, instance 0, Sensor Value
+        ("typical", Identifier::CoapPath(&["3303", "0", "5700"])),
+        ("terse", Identifier::CoapPath(&["t"])),
+        (
+            "vendor",
+            Identifier::MatterPath { endpoint: 1, cluster: VENDOR_CLUSTER, attribute: VENDOR_ATTRIBUTE, minimal: true },
+        ),
+        (
+            "fixed-width",
+            Identifier::MatterPath { endpoint: 1, cluster: TEMPERATURE_CLUSTER, attribute: MEASURED_VALUE, minimal: false },
+        ),
+        (
+            "standard",
+            Identifier::MatterPath { endpoint: 1, cluster: TEMPERATURE_CLUSTER, attribute: MEASURED_VALUE, minimal: true },
+        ),
+    ]
+}
+
+#[derive(Debug, Clone)]
+pub struct NamingConfig {
+    pub payload_sizes: Vec<usize>,
+}
+
+impl Default for NamingConfig {
+    fn default() -> Self {
+        Self { payload_sizes: vec![2, 16, 64, 256] }
+    }
+}
+
+analyzer_builder!(NamingAnalyzerBuilder => NamingAnalyzer(NamingConfig) {
+    payload_sizes: Vec<usize>,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NamingMetrics {
+    pub payload_sizes: Vec<usize>,
+    pub schemes: Vec<NamingScheme>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NamingScheme {
+    pub protocol: String,
+    /// e.g. "descriptive", "terse", "registered" (MQTT-SN) or "vendor" (Matter).
+    pub scheme: String,
+    pub identifier: String,
+    /// One per payload size, in order.
+    pub messages: Vec<NamedMessage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NamedMessage {
+    pub payload_bytes: usize,
+    /// With IP and UDP/TCP headers.
+    pub message_bytes: usize,
+    /// The message minus the same message without the identifier. Constant per
+    /// scheme except where a length field grows past an encoding boundary.
+    pub name_bytes: usize,
+    /// Name bytes as a share of the message.
+    pub name_share: f64,
+}
+
+pub struct NamingAnalyzer {
+    config: NamingConfig,
+}
+
+impl NamingAnalyzer {
+    pub fn new(config: NamingConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_naming(&mut self) -> Result<NamingMetrics> {
+        println!("\n🏷️ Analyzing Topic and Resource Naming Overhead");
+        println!("------------------------------------------------");
+
+        let sizes = &self.config.payload_sizes;
+        if let Some(size) = sizes.iter().find(|&&size| size > MAX_PAYLOAD_BYTES) {
+            return Err(anyhow!("naming payload of {} bytes is over the {}-byte limit", size, MAX_PAYLOAD_BYTES));
+        }
+
+        let mut schemes = Vec::new();
+        for (scheme, identifier) in self::schemes() {
+            let mut messages = Vec::new();
+            for &payload_bytes in sizes {
+                let payload = vec![0x5A; payload_bytes];
+                let message_bytes = identifier.message(&payload, true)?.len();
+                let name_bytes = message_bytes - identifier.message(&payload, false)?.len();
+                messages.push(NamedMessage {
+                    payload_bytes,
+                    message_bytes,
+                    name_bytes,
+                    name_share: name_bytes as f64 / message_bytes as f64,
+                });
+            }
+            schemes.push(NamingScheme {
+                protocol: identifier.protocol().to_string(),
+                scheme: scheme.to_string(),
+                identifier: identifier.describe(),
+                messages,
+            });
+        }
+
+        // Message bytes and the name's share of them, per payload size
+        let header: Vec<_> = sizes.iter().map(|size| format!("{:>13}", format!("{}B payload", size))).collect();
+        println!("   {:<20} | {}", "scheme", header.join(" | "));
+        for scheme in &schemes {
+            let cells: Vec<_> = scheme
+                .messages
+                .iter()
+                .map(|message| format!("{:>5}B ({:>3.0}%)", message.message_bytes, message.name_share * 100.0))
+                .collect();
+            println!("✅ {:<20} | {}   {}", format!("{} {}", scheme.protocol, scheme.scheme), cells.join(" | "), scheme.identifier);
+        }
+
+        Ok(NamingMetrics { payload_sizes: sizes.clone(), schemes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_cost_their_length_plus_framing_and_matter_ids_shrink_with_minimal_width() {
+        let metrics = NamingAnalyzer::builder().payload_sizes(vec![2usize, 256]).build().analyze_naming().unwrap();
+        let name_bytes = |protocol: &str, name: &str| {
+            metrics.schemes.iter().find(|s| s.protocol == protocol && s.scheme == name).unwrap().messages[0].name_bytes
+        };
+
+        // A 2-byte length prefix per topic, a 1-byte option header per short path segment
+        assert_eq!(name_bytes("mqtt", "typical"), 2 + "sensors/dev1/temperature".len());
+        assert_eq!(name_bytes("coap", "typical"), 3 + "3303".len() + "0".len() + "5700".len());
+        assert_eq!(name_bytes("mqtt-sn", "registered"), 2);
+        // The path list and its end, a control and tag byte per ID, and the IDs at 2+4+4 or 1+2+1 bytes
+        assert_eq!(name_bytes("matter", "fixed-width"), 3 + 3 * 2 + 10);
+        assert_eq!(name_bytes("matter", "standard"), 3 + 3 * 2 + 4);
+        assert!(name_bytes("matter", "vendor") > name_bytes("matter", "standard"));
+
+        for scheme in &metrics.schemes {
+            let [small, large] = &scheme.messages[..] else { panic!() };
+            assert!(small.name_share > large.name_share, "{} {}", scheme.protocol, scheme.scheme);
+        }
+    }
+}
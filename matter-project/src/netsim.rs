@@ -0,0 +1,426 @@
+// matter-project/src/netsim.rs
+/*!
+Link emulation - loopback relays that shape each direction separately
+
+IoT links are rarely symmetric: a cellular or LPWAN device usually has far
+less uplink than downlink, and the two directions can differ in delay too.
+A [`ShapedRelay`] sits between a device and a server on loopback and
+forwards traffic through one [`LinkShape`] per direction. Every message
+waits for the link to finish serializing the ones before it, at the
+direction's rate and with IPv4 plus UDP/TCP headers counted, and is then
+delivered after the direction's delay. Messages queue but are never
+dropped.
+
+A direction can also reorder and duplicate. A reordered message is held
+back by an extra delay, so the ones sent after it overtake it; netem does
+the opposite and lets the reordered packet skip the delay, but either way
+one message arrives behind its successors. A duplicated message is sent
+twice and takes link time twice. Over UDP both copies reach the receiver,
+in arrival order. Over TCP the relay does what the receiving TCP would: a
+held-back segment blocks everything behind it, and duplicates are counted
+but never reach the application.
+
+This works without root or netem, but only shapes what passes through user
+space: the relay forwards application bytes, so the kernel's own TCP
+acknowledgements on either side are not delayed. Shaping those needs the
+netem qdiscs of `--netns` with `--netem-uplink` and `--netem-downlink`.
+*/
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::firmware_update::Carrier;
+use crate::transport_modes::{IPV4_TCP_HEADER_BYTES, IPV4_UDP_HEADER_BYTES};
+use crate::workload::XorShift;
+
+/// Seeds the reorder and duplicate draws, so runs with the same link see the same pattern.
+const SHAPE_SEED: u64 = 0x5EED_11F7_2024_0001;
+
+/// One direction of a link: `delay_ms=40,rate_kbit=25,reorder_percent=10,reorder_delay_ms=50,duplicate_percent=2`,
+/// every key optional.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LinkShape {
+    pub delay_ms: f64,
+    /// Unlimited when unset.
+    pub rate_kbit: Option<u32>,
+    /// Messages held back by `reorder_delay_ms` on top of the delay.
+    #[serde(default)]
+    pub reorder_percent: f64,
+    #[serde(default)]
+    pub reorder_delay_ms: f64,
+    /// Messages sent twice.
+    #[serde(default)]
+    pub duplicate_percent: f64,
+}
+
+impl LinkShape {
+    pub fn new(delay_ms: f64, rate_kbit: u32) -> Self {
+        Self { delay_ms, rate_kbit: Some(rate_kbit), ..Self::default() }
+    }
+
+    fn delay(&self) -> Duration {
+        Duration::from_secs_f64(self.delay_ms / 1000.0)
+    }
+
+    /// Time the link is busy sending `bytes`.
+    fn serialization(&self, bytes: usize) -> Duration {
+        match self.rate_kbit {
+            Some(rate) => Duration::from_secs_f64(bytes as f64 * 8.0 / (rate as f64 * 1000.0)),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+impl std::str::FromStr for LinkShape {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let mut shape = LinkShape::default();
+        for pair in spec.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').ok_or_else(|| anyhow!("link shape expects key=value, got {}", pair))?;
+            match key.trim() {
+                "delay_ms" => shape.delay_ms = value.trim().parse()?,
+                "rate_kbit" => shape.rate_kbit = Some(value.trim().parse()?),
+                "reorder_percent" => shape.reorder_percent = value.trim().parse()?,
+                "reorder_delay_ms" => shape.reorder_delay_ms = value.trim().parse()?,
+                "duplicate_percent" => shape.duplicate_percent = value.trim().parse()?,
+                other => return Err(anyhow!("unknown link setting {}", other)),
+            }
+        }
+        let percent = 0.0..=100.0;
+        if shape.delay_ms < 0.0 || shape.reorder_delay_ms < 0.0 || shape.rate_kbit == Some(0) {
+            return Err(anyhow!("link delays must not be negative and rate must be above zero"));
+        }
+        if !percent.contains(&shape.reorder_percent) || !percent.contains(&shape.duplicate_percent) {
+            return Err(anyhow!("link percentages must be between 0 and 100"));
+        }
+        Ok(shape)
+    }
+}
+
+/// Shapes per direction, seen from the device.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AsymmetricLink {
+    /// Device to server.
+    pub uplink: LinkShape,
+    /// Server to device.
+    pub downlink: LinkShape,
+}
+
+impl AsymmetricLink {
+    pub fn symmetric(shape: LinkShape) -> Self {
+        Self { uplink: shape.clone(), downlink: shape }
+    }
+}
+
+/// What crossed the relay in each direction, headers included.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LinkStats {
+    pub uplink_packets: usize,
+    pub uplink_bytes: usize,
+    pub downlink_packets: usize,
+    pub downlink_bytes: usize,
+    /// Messages held back, both directions.
+    pub reordered: usize,
+    /// Extra copies sent, both directions; over TCP they never reach the application.
+    pub duplicated: usize,
+}
+
+/// Departure times for one direction: a message starts serializing once the
+/// previous one has left and arrives a delay after it has.
+struct Pacer {
+    shape: LinkShape,
+    carrier: Carrier,
+    free_at: Instant,
+    uplink: bool,
+    rng: XorShift,
+    stats: Arc<Mutex<LinkStats>>,
+}
+
+impl Pacer {
+    fn new(shape: LinkShape, carrier: Carrier, uplink: bool, stats: Arc<Mutex<LinkStats>>) -> Self {
+        let rng = XorShift(SHAPE_SEED ^ uplink as u64);
+        Self { shape, carrier, free_at: Instant::now(), uplink, rng, stats }
+    }
+
+    /// When each copy of a `len`-byte message arrives; empty when no copy should be forwarded.
+    fn arrivals(&mut self, len: usize) -> Vec<Instant> {
+        let header_bytes = match self.carrier {
+            Carrier::Udp => IPV4_UDP_HEADER_BYTES,
+            Carrier::Tcp => IPV4_TCP_HEADER_BYTES,
+        };
+        let bytes = len + header_bytes;
+        let held_back = self.rng.unit() * 100.0 < self.shape.reorder_percent;
+        let duplicated = self.rng.unit() * 100.0 < self.shape.duplicate_percent;
+        let copies = 1 + duplicated as usize;
+        {
+            let mut stats = self.stats.lock().unwrap();
+            if self.uplink {
+                stats.uplink_packets += copies;
+                stats.uplink_bytes += copies * bytes;
+            } else {
+                stats.downlink_packets += copies;
+                stats.downlink_bytes += copies * bytes;
+            }
+            stats.reordered += held_back as usize;
+            stats.duplicated += duplicated as usize;
+        }
+
+        let extra = if held_back { Duration::from_secs_f64(self.shape.reorder_delay_ms / 1000.0) } else { Duration::ZERO };
+        let mut arrivals = Vec::new();
+        for _ in 0..copies {
+            self.free_at = self.free_at.max(Instant::now()) + self.shape.serialization(bytes);
+            arrivals.push(self.free_at + self.shape.delay() + extra);
+        }
+        if self.carrier == Carrier::Tcp {
+            // The receiving TCP drops the copy after acknowledging it
+            arrivals.truncate(1);
+        }
+        arrivals
+    }
+}
+
+type Delivery = (Instant, Vec<u8>);
+
+/// Datagrams in arrival order, whatever order they were queued in.
+struct Arrivals {
+    queue: UnboundedReceiver<Delivery>,
+    open: bool,
+    pending: BinaryHeap<Reverse<(Instant, u64, Vec<u8>)>>,
+    queued: u64,
+}
+
+impl Arrivals {
+    fn new(queue: UnboundedReceiver<Delivery>) -> Self {
+        Self { queue, open: true, pending: BinaryHeap::new(), queued: 0 }
+    }
+
+    /// The next datagram, once it is due; `None` when the reader is gone and nothing is left.
+    async fn next(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let due = self.pending.peek().map(|Reverse((at, _, _))| *at);
+            if due.is_none() && !self.open {
+                return None;
+            }
+            tokio::select! {
+                queued = self.queue.recv(), if self.open => match queued {
+                    Some((at, message)) => {
+                        // The counter keeps copies due at the same time in the order they were sent
+                        self.pending.push(Reverse((at, self.queued, message)));
+                        self.queued += 1;
+                    }
+                    None => self.open = false,
+                },
+                _ = tokio::time::sleep_until(due.unwrap_or_else(Instant::now)), if due.is_some() => {
+                    return self.pending.pop().map(|Reverse((_, _, message))| message);
+                }
+            }
+        }
+    }
+}
+
+fn queue_copies(tx: &mpsc::UnboundedSender<Delivery>, arrivals: Vec<Instant>, message: &[u8]) -> bool {
+    arrivals.into_iter().all(|at| tx.send((at, message.to_vec())).is_ok())
+}
+
+/// Forwards one device to one server over an [`AsymmetricLink`]; the tasks stop when it is dropped.
+pub struct ShapedRelay {
+    addr: SocketAddr,
+    stats: Arc<Mutex<LinkStats>>,
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl ShapedRelay {
+    /// Listens on loopback for the device and forwards to `server`.
+    pub(crate) async fn spawn(carrier: Carrier, link: AsymmetricLink, server: SocketAddr) -> Result<Self> {
+        let stats = Arc::new(Mutex::new(LinkStats::default()));
+        let tasks = Arc::new(Mutex::new(Vec::new()));
+        let up = Pacer::new(link.uplink, carrier, true, stats.clone());
+        let down = Pacer::new(link.downlink, carrier, false, stats.clone());
+        let addr = match carrier {
+            Carrier::Udp => {
+                let device_side = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+                let server_side = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+                server_side.connect(server).await?;
+                let addr = device_side.local_addr()?;
+                tasks.lock().unwrap().extend(relay_udp(device_side, server_side, up, down));
+                addr
+            }
+            Carrier::Tcp => {
+                let listener = TcpListener::bind("127.0.0.1:0").await?;
+                let addr = listener.local_addr()?;
+                let connections = tasks.clone();
+                let accept = tokio::spawn(async move {
+                    let connected = async {
+                        let (device, _) = listener.accept().await?;
+                        let upstream = TcpStream::connect(server).await?;
+                        device.set_nodelay(true)?;
+                        upstream.set_nodelay(true)?;
+                        anyhow::Ok((device, upstream))
+                    };
+                    match connected.await {
+                        Ok((device, upstream)) => connections.lock().unwrap().extend(relay_tcp(device, upstream, up, down)),
+                        Err(e) => log::debug!("Shaped relay did not connect: {:#}", e),
+                    }
+                });
+                tasks.lock().unwrap().push(accept);
+                addr
+            }
+        };
+        Ok(Self { addr, stats, tasks })
+    }
+
+    /// Where the device should send.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn stats(&self) -> LinkStats {
+        self.stats.lock().unwrap().clone()
+    }
+}
+
+impl Drop for ShapedRelay {
+    fn drop(&mut self) {
+        for task in self.tasks.lock().unwrap().drain(..) {
+            task.abort();
+        }
+    }
+}
+
+fn relay_udp(device_side: Arc<UdpSocket>, server_side: Arc<UdpSocket>, mut up: Pacer, mut down: Pacer) -> Vec<JoinHandle<()>> {
+    // The device's address is learnt from its first datagram
+    let device = Arc::new(Mutex::new(None::<SocketAddr>));
+    let (up_tx, up_rx) = mpsc::unbounded_channel::<Delivery>();
+    let (down_tx, down_rx) = mpsc::unbounded_channel::<Delivery>();
+
+    let read_up = {
+        let (socket, device) = (device_side.clone(), device.clone());
+        tokio::spawn(async move {
+            let mut buffer = vec![0u8; 65536];
+            while let Ok((len, peer)) = socket.recv_from(&mut buffer).await {
+                *device.lock().unwrap() = Some(peer);
+                if !queue_copies(&up_tx, up.arrivals(len), &buffer[..len]) {
+                    break;
+                }
+            }
+        })
+    };
+    let write_up = {
+        let socket = server_side.clone();
+        tokio::spawn(async move {
+            let mut arrivals = Arrivals::new(up_rx);
+            while let Some(message) = arrivals.next().await {
+                if socket.send(&message).await.is_err() {
+                    break;
+                }
+            }
+        })
+    };
+    let read_down = tokio::spawn(async move {
+        let mut buffer = vec![0u8; 65536];
+        while let Ok(len) = server_side.recv(&mut buffer).await {
+            if !queue_copies(&down_tx, down.arrivals(len), &buffer[..len]) {
+                break;
+            }
+        }
+    });
+    let write_down = tokio::spawn(async move {
+        let mut arrivals = Arrivals::new(down_rx);
+        while let Some(message) = arrivals.next().await {
+            let peer = *device.lock().unwrap();
+            if let Some(peer) = peer {
+                if device_side.send_to(&message, peer).await.is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    vec![read_up, write_up, read_down, write_down]
+}
+
+fn relay_tcp(device: TcpStream, upstream: TcpStream, up: Pacer, down: Pacer) -> Vec<JoinHandle<()>> {
+    let (device_read, device_write) = device.into_split();
+    let (upstream_read, upstream_write) = upstream.into_split();
+    let mut tasks = shape_stream(device_read, upstream_write, up);
+    tasks.extend(shape_stream(upstream_read, device_write, down));
+    tasks
+}
+
+/// Each read is treated as one segment, and segments are written in order,
+/// so a held-back one delays those behind it.
+fn shape_stream(
+    mut from: tokio::net::tcp::OwnedReadHalf,
+    mut to: tokio::net::tcp::OwnedWriteHalf,
+    mut pacer: Pacer,
+) -> Vec<JoinHandle<()>> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<Delivery>();
+    let reader = tokio::spawn(async move {
+        let mut buffer = vec![0u8; 65536];
+        while let Ok(len) = from.read(&mut buffer).await {
+            if len == 0 || !queue_copies(&tx, pacer.arrivals(len), &buffer[..len]) {
+                break;
+            }
+        }
+    });
+    let writer = tokio::spawn(async move {
+        while let Some((at, segment)) = rx.recv().await {
+            tokio::time::sleep_until(at).await;
+            if to.write_all(&segment).await.is_err() {
+                return;
+            }
+        }
+        // The sender closed its side once everything queued has been delivered
+        let _ = to.shutdown().await;
+    });
+    vec![reader, writer]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_direction_gets_its_own_delay_and_rate() {
+        assert_eq!("delay_ms=40, rate_kbit=25".parse::<LinkShape>().unwrap(), LinkShape::new(40.0, 25));
+        assert!("rate_kbit=0".parse::<LinkShape>().is_err());
+        assert!("latency=5".parse::<LinkShape>().is_err());
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            // 100 bytes plus headers at 8 kbit/s take 128ms up; the way down only adds 5ms
+            let link = AsymmetricLink { uplink: LinkShape::new(20.0, 8), downlink: LinkShape { delay_ms: 5.0, ..LinkShape::default() } };
+            let relay = ShapedRelay::spawn(Carrier::Udp, link, server.local_addr().unwrap()).await.unwrap();
+            let device = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+            let start = Instant::now();
+            device.send_to(&[0u8; 100], relay.addr()).await.unwrap();
+            let mut buffer = [0u8; 256];
+            let (len, peer) = server.recv_from(&mut buffer).await.unwrap();
+            let uplink = start.elapsed();
+            assert_eq!(len, 100);
+            assert!(uplink >= Duration::from_millis(148), "{:?}", uplink);
+
+            let start = Instant::now();
+            server.send_to(&buffer[..len], peer).await.unwrap();
+            device.recv_from(&mut buffer).await.unwrap();
+            let downlink = start.elapsed();
+            assert!(downlink >= Duration::from_millis(5) && downlink < Duration::from_millis(100), "{:?}", downlink);
+
+            let stats = relay.stats();
+            assert_eq!((stats.uplink_packets, stats.downlink_packets), (1, 1));
+            assert_eq!(stats.uplink_bytes, 100 + IPV4_UDP_HEADER_BYTES);
+        });
+    }
+}
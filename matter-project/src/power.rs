@@ -0,0 +1,374 @@
+// matter-project/src/power.rs
+/*!
+External power meter integration - measured energy per protocol phase
+*/
+
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+pub struct PowerSample {
+    pub timestamp: Instant,
+    pub voltage_v: f64,
+    pub current_ma: f64,
+}
+
+impl PowerSample {
+    pub fn power_mw(&self) -> f64 {
+        self.voltage_v * self.current_ma
+    }
+}
+
+pub trait PowerMeter: Send {
+    fn name(&self) -> String;
+    fn read(&mut self) -> Result<PowerSample>;
+}
+
+/// Where to read power from, as given on the command line:
+/// `ina219:<i2c device>:<address>:<shunt ohms>` or `serial:<device>`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PowerMeterConfig {
+    Ina219 { device: String, address: u16, shunt_ohms: f64 },
+    Serial { device: String },
+}
+
+impl std::str::FromStr for PowerMeterConfig {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let parts: Vec<&str> = spec.split(':').collect();
+        match parts.as_slice() {
+            ["ina219"] => Ok(Self::Ina219 { device: "/dev/i2c-1".to_string(), address: 0x40, shunt_ohms: 0.1 }),
+            ["ina219", device] => Ok(Self::Ina219 { device: device.to_string(), address: 0x40, shunt_ohms: 0.1 }),
+            ["ina219", device, address, rest @ ..] => Ok(Self::Ina219 {
+                device: device.to_string(),
+                address: u16::from_str_radix(address.trim_start_matches("0x"), 16)?,
+                shunt_ohms: rest.first().map(|s| s.parse()).transpose()?.unwrap_or(0.1),
+            }),
+            ["serial", device] => Ok(Self::Serial { device: device.to_string() }),
+            _ => Err(anyhow!("unrecognized power meter spec: {}", spec)),
+        }
+    }
+}
+
+impl PowerMeterConfig {
+    pub fn open(&self) -> Result<Box<dyn PowerMeter>> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Self::Ina219 { device, address, shunt_ohms } => {
+                Ok(Box::new(Ina219Meter::open(device, *address, *shunt_ohms)?))
+            }
+            #[cfg(not(target_os = "linux"))]
+            Self::Ina219 { .. } => Err(anyhow!("INA219 over I2C is only supported on Linux")),
+            Self::Serial { device } => Ok(Box::new(SerialLineMeter::open(device)?)),
+        }
+    }
+}
+
+/// INA219 current/voltage monitor on a Linux i2c-dev bus (e.g. Raspberry Pi /dev/i2c-1).
+#[cfg(target_os = "linux")]
+pub struct Ina219Meter {
+    file: std::fs::File,
+    device: String,
+    address: u16,
+    shunt_ohms: f64,
+}
+
+#[cfg(target_os = "linux")]
+impl Ina219Meter {
+    const I2C_SLAVE: libc::c_ulong = 0x0703;
+    const REG_SHUNT_VOLTAGE: u8 = 0x01;
+    const REG_BUS_VOLTAGE: u8 = 0x02;
+
+    pub fn open(device: &str, address: u16, shunt_ohms: f64) -> Result<Self> {
+        use std::os::unix::io::AsRawFd;
+
+        let file = std::fs::OpenOptions::new().read(true).write(true).open(device)?;
+        // SAFETY: I2C_SLAVE takes the 7-bit target address by value on a valid i2c-dev fd
+        let rc = unsafe { libc::ioctl(file.as_raw_fd(), Self::I2C_SLAVE as _, address as libc::c_ulong) };
+        if rc < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        Ok(Self { file, device: device.to_string(), address, shunt_ohms })
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u16> {
+        use std::io::{Read, Write};
+
+        let mut value = [0u8; 2];
+        self.file.write_all(&[register])?;
+        self.file.read_exact(&mut value)?;
+        Ok(u16::from_be_bytes(value))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl PowerMeter for Ina219Meter {
+    fn name(&self) -> String {
+        format!("INA219@{}:0x{:02x}", self.device, self.address)
+    }
+
+    fn read(&mut self) -> Result<PowerSample> {
+        // Shunt voltage LSB is 10µV (signed); bus voltage is bits 15..3 with a 4mV LSB
+        let shunt_v = self.read_register(Self::REG_SHUNT_VOLTAGE)? as i16 as f64 * 10e-6;
+        let bus_v = (self.read_register(Self::REG_BUS_VOLTAGE)? >> 3) as f64 * 4e-3;
+
+        Ok(PowerSample {
+            timestamp: Instant::now(),
+            voltage_v: bus_v,
+            current_ma: shunt_v / self.shunt_ohms * 1000.0,
+        })
+    }
+}
+
+/// Line-oriented serial meters (Otii, Joulescope bridges) streaming
+/// `<current_mA>,<voltage_V>` per line. Configure the port (baud rate etc.)
+/// beforehand, e.g. with `stty`.
+pub struct SerialLineMeter {
+    reader: BufReader<std::fs::File>,
+    device: String,
+}
+
+impl SerialLineMeter {
+    pub fn open(device: &str) -> Result<Self> {
+        Ok(Self {
+            reader: BufReader::new(std::fs::File::open(device)?),
+            device: device.to_string(),
+        })
+    }
+}
+
+impl PowerMeter for SerialLineMeter {
+    fn name(&self) -> String {
+        format!("serial@{}", self.device)
+    }
+
+    fn read(&mut self) -> Result<PowerSample> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(anyhow!("power meter stream closed"));
+        }
+
+        let mut fields = line.trim().split(',');
+        let current_ma = fields.next().ok_or_else(|| anyhow!("missing current field"))?.trim().parse()?;
+        let voltage_v = fields.next().ok_or_else(|| anyhow!("missing voltage field"))?.trim().parse()?;
+
+        Ok(PowerSample { timestamp: Instant::now(), voltage_v, current_ma })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PhaseEnergy {
+    pub phase: String,
+    pub duration_ms: f64,
+    pub energy_mj: f64,
+    pub average_power_mw: f64,
+    pub peak_current_ma: f64,
+    pub samples: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnergyMetrics {
+    pub meter: String,
+    pub sample_rate_hz: f64,
+    pub read_errors: u32,
+    pub phases: Vec<PhaseEnergy>,
+}
+
+/// Polls a meter on a background thread and attributes energy to the phase
+/// windows recorded against the same monotonic clock.
+pub struct PowerSampler {
+    meter_name: String,
+    samples: Arc<Mutex<Vec<PowerSample>>>,
+    read_errors: Arc<AtomicU32>,
+    running: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+    phases: Vec<(String, Instant, Instant)>,
+    started: Instant,
+}
+
+impl PowerSampler {
+    pub fn start(mut meter: Box<dyn PowerMeter>, interval: Duration) -> Self {
+        let meter_name = meter.name();
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let read_errors = Arc::new(AtomicU32::new(0));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let worker = {
+            let samples = Arc::clone(&samples);
+            let read_errors = Arc::clone(&read_errors);
+            let running = Arc::clone(&running);
+            std::thread::spawn(move || {
+                while running.load(Ordering::Relaxed) {
+                    match meter.read() {
+                        Ok(sample) => samples.lock().expect("sample buffer poisoned").push(sample),
+                        Err(e) => {
+                            debug!("Power meter read failed: {}", e);
+                            read_errors.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    std::thread::sleep(interval);
+                }
+            })
+        };
+
+        println!("🔌 Power meter sampling from {}", meter_name);
+
+        Self {
+            meter_name,
+            samples,
+            read_errors,
+            running,
+            worker: Some(worker),
+            phases: Vec::new(),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn record_phase(&mut self, phase: &str, start: Instant, end: Instant) {
+        self.phases.push((phase.to_string(), start, end));
+    }
+
+    pub fn finish(mut self) -> EnergyMetrics {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            if worker.join().is_err() {
+                warn!("⚠️ Power sampling thread panicked");
+            }
+        }
+
+        let samples = std::mem::take(&mut *self.samples.lock().expect("sample buffer poisoned"));
+        let elapsed = self.started.elapsed().as_secs_f64();
+
+        let phases = self
+            .phases
+            .iter()
+            .map(|(phase, start, end)| integrate_phase(phase, *start, *end, &samples))
+            .collect();
+
+        EnergyMetrics {
+            meter: self.meter_name.clone(),
+            sample_rate_hz: if elapsed > 0.0 { samples.len() as f64 / elapsed } else { 0.0 },
+            read_errors: self.read_errors.load(Ordering::Relaxed),
+            phases,
+        }
+    }
+}
+
+/// Trapezoidal integration of power over a phase window. The window's edges
+/// are interpolated from the neighbouring samples, so phases shorter than the
+/// sampling interval still get their share of the energy.
+fn integrate_phase(phase: &str, start: Instant, end: Instant, samples: &[PowerSample]) -> PhaseEnergy {
+    let window: Vec<&PowerSample> = samples
+        .iter()
+        .filter(|s| s.timestamp >= start && s.timestamp <= end)
+        .collect();
+
+    let mut points: Vec<(Instant, f64, f64)> = Vec::with_capacity(window.len() + 2);
+    if let (Some(first), Some(last)) = (interpolate(samples, start), interpolate(samples, end)) {
+        points.push((start, first.0, first.1));
+        points.extend(window.iter().map(|s| (s.timestamp, s.power_mw(), s.current_ma)));
+        points.push((end, last.0, last.1));
+    }
+
+    let energy_mj: f64 = points
+        .windows(2)
+        .map(|pair| {
+            let dt = pair[1].0.duration_since(pair[0].0).as_secs_f64();
+            (pair[0].1 + pair[1].1) / 2.0 * dt
+        })
+        .sum();
+
+    let duration_ms = end.duration_since(start).as_secs_f64() * 1000.0;
+
+    PhaseEnergy {
+        phase: phase.to_string(),
+        duration_ms,
+        energy_mj,
+        average_power_mw: if duration_ms > 0.0 { energy_mj / (duration_ms / 1000.0) } else { 0.0 },
+        peak_current_ma: points.iter().map(|&(_, _, current)| current).fold(0.0, f64::max),
+        samples: window.len() as u32,
+    }
+}
+
+/// Power and current at `at`, linear between the samples either side of it
+/// and held from the nearest one beyond the first or last sample.
+fn interpolate(samples: &[PowerSample], at: Instant) -> Option<(f64, f64)> {
+    let after = samples.partition_point(|s| s.timestamp <= at);
+    match (after.checked_sub(1).map(|i| &samples[i]), samples.get(after)) {
+        (Some(before), Some(after)) => {
+            let span = after.timestamp.duration_since(before.timestamp).as_secs_f64();
+            let t = if span > 0.0 { at.duration_since(before.timestamp).as_secs_f64() / span } else { 0.0 };
+            Some((
+                before.power_mw() + (after.power_mw() - before.power_mw()) * t,
+                before.current_ma + (after.current_ma - before.current_ma) * t,
+            ))
+        }
+        (Some(nearest), None) | (None, Some(nearest)) => Some((nearest.power_mw(), nearest.current_ma)),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1 V samples at the given (ms, mA) offsets from `origin`, so power in mW equals current.
+    fn samples(origin: Instant, points: &[(f64, f64)]) -> Vec<PowerSample> {
+        points
+            .iter()
+            .map(|&(ms, current_ma)| PowerSample {
+                timestamp: origin + Duration::from_secs_f64(ms / 1000.0),
+                voltage_v: 1.0,
+                current_ma,
+            })
+            .collect()
+    }
+
+    fn at(origin: Instant, ms: f64) -> Instant {
+        origin + Duration::from_secs_f64(ms / 1000.0)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn phases_between_samples_are_interpolated_from_their_neighbours() {
+        let origin = Instant::now();
+        assert_eq!(integrate_phase("empty", at(origin, 1.0), at(origin, 2.0), &[]).energy_mj, 0.0);
+
+        // No sample inside: 12mW at 2ms rising to 14mW at 4ms
+        let around = samples(origin, &[(0.0, 10.0), (10.0, 20.0)]);
+        let phase = integrate_phase("short", at(origin, 2.0), at(origin, 4.0), &around);
+        assert_eq!(phase.samples, 0);
+        assert_close(phase.energy_mj, 13.0 * 0.002);
+        assert_close(phase.average_power_mw, 13.0);
+        assert_close(phase.peak_current_ma, 14.0);
+
+        // A single sample holds its power across the whole window
+        let single = samples(origin, &[(5.0, 10.0)]);
+        let phase = integrate_phase("single", origin, at(origin, 10.0), &single);
+        assert_eq!(phase.samples, 1);
+        assert_close(phase.energy_mj, 10.0 * 0.01);
+    }
+
+    #[test]
+    fn many_samples_include_the_slices_at_the_window_edges() {
+        let origin = Instant::now();
+        // Power ramps 1mW per ms, sampled every ms
+        let ramp: Vec<(f64, f64)> = (0..=10).map(|ms| (ms as f64, ms as f64)).collect();
+        let phase = integrate_phase("ramp", at(origin, 2.5), at(origin, 7.5), &samples(origin, &ramp));
+        assert_eq!(phase.samples, 5);
+        assert_close(phase.energy_mj, 5.0 * 0.005);
+        assert_close(phase.average_power_mw, 5.0);
+        assert_close(phase.peak_current_ma, 7.5);
+    }
+}
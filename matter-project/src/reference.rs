@@ -0,0 +1,417 @@
+// matter-project/src/reference.rs
+/*!
+Reference comparison - local results positioned against published measurements, with caveats where the setups differ
+
+`compare --reference <dataset>` loads a reference dataset and compares the
+local results for the same protocol with it, metric by metric. The dataset
+is one of the bundled ones in `references/` (compiled in), a JSON file, or an
+`http(s)://` URL when built with the `download` feature:
+
+```json
+{
+  "name": "study-2025-matter",
+  "title": "...",
+  "protocol": "Matter",
+  "source": "where the figures were published",
+  "published": "2025-06-23",
+  "environment": {"os_platform": "windows", "device_class": "desktop", "link": "loopback", "impaired": false,
+                  "implementation": "rs-matter", "notes": ["..."]},
+  "metrics": {"osi_layer_5_session.commissioning_time_ms": {"value": 452.0, "low": 380.0, "high": 510.0, "samples": 20}}
+}
+```
+
+Metrics are keyed by the path `diff` gives them, in the units their names
+give. Each local mean is in line with the reference when it falls within the
+published `low`..`high` range, or within the tolerance of `value` when there
+is no range; otherwise it is better or worse by the metric's direction, or
+just higher or lower when the direction is not known.
+
+A reference measured elsewhere is a sanity check, not a baseline: link,
+hardware, operating system and implementation all move the numbers. Every
+difference between the reference environment and the local runs that can be
+read from the results is listed as a caveat next to the comparison, together
+with the dataset's own notes.
+*/
+
+use analysis_core::campaign::{CampaignMerger, CampaignRun, MergeConfig};
+use analysis_core::diff::{flatten, Direction};
+use analysis_core::query::QueryConfig;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+// Compiled-in copies of references/*.json, by dataset name
+const BUILTIN_REFERENCES: &[(&str, &str)] = &[
+    ("study-2025-matter", include_str!("../references/study-2025-matter.json")),
+    ("study-2025-lwm2m", include_str!("../references/study-2025-lwm2m.json")),
+];
+
+/// Fewer local runs than this get a caveat of their own.
+const MIN_LOCAL_RUNS: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct ReferenceConfig {
+    /// Bundled dataset name, JSON file or URL; `None` lists the bundled datasets.
+    pub reference: Option<String>,
+    /// Result files, directories or merged datasets; see [`QueryConfig::default_inputs`] when empty.
+    pub inputs: Vec<PathBuf>,
+    /// How far from a reference value without a range a local mean may be and still count as in line.
+    pub tolerance_percent: f64,
+}
+
+impl Default for ReferenceConfig {
+    fn default() -> Self {
+        Self {
+            reference: None,
+            inputs: Vec::new(),
+            tolerance_percent: 20.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferenceDataset {
+    pub name: String,
+    pub title: String,
+    pub protocol: String,
+    /// Where the figures were published.
+    pub source: String,
+    #[serde(default)]
+    pub published: Option<String>,
+    pub environment: ReferenceEnvironment,
+    /// By metric path.
+    pub metrics: BTreeMap<String, ReferenceMetric>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferenceEnvironment {
+    #[serde(default)]
+    pub os_platform: Option<String>,
+    /// `desktop`, `single_board_computer` or `constrained`, as in `test_environment`.
+    #[serde(default)]
+    pub device_class: Option<String>,
+    /// `loopback`, `veth`, `network`, or a description of the radio link such as `thread, 3 hops`.
+    pub link: String,
+    #[serde(default)]
+    pub impaired: bool,
+    #[serde(default)]
+    pub implementation: Option<String>,
+    #[serde(default)]
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferenceMetric {
+    pub value: f64,
+    #[serde(default)]
+    pub low: Option<f64>,
+    #[serde(default)]
+    pub high: Option<f64>,
+    #[serde(default)]
+    pub samples: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Standing {
+    InLine,
+    Better,
+    Worse,
+    Higher,
+    Lower,
+}
+
+impl Standing {
+    fn label(self) -> &'static str {
+        match self {
+            Standing::InLine => "in line",
+            Standing::Better => "better",
+            Standing::Worse => "worse",
+            Standing::Higher => "higher",
+            Standing::Lower => "lower",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricPosition {
+    pub metric: String,
+    pub reference: f64,
+    pub local_mean: f64,
+    /// Local runs that report the metric.
+    pub local_runs: usize,
+    /// `None` when the reference value is zero.
+    pub delta_percent: Option<f64>,
+    pub direction: Direction,
+    pub standing: Standing,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferenceComparison {
+    pub reference: String,
+    pub protocol: String,
+    pub local_runs: usize,
+    pub positions: Vec<MetricPosition>,
+    /// Reference metrics no local run reports.
+    pub not_measured: Vec<String>,
+    pub caveats: Vec<String>,
+}
+
+/// Loads a bundled dataset by name, a dataset file, or a dataset URL.
+pub fn load_reference(spec: &str) -> Result<ReferenceDataset> {
+    let (origin, json) = if spec.starts_with("http://") || spec.starts_with("https://") {
+        (spec.to_string(), download(spec)?)
+    } else if Path::new(spec).is_file() {
+        (spec.to_string(), std::fs::read_to_string(spec).with_context(|| format!("reading {}", spec))?)
+    } else if let Some((name, json)) = BUILTIN_REFERENCES.iter().find(|(name, _)| *name == spec) {
+        (format!("bundled {}", name), json.to_string())
+    } else {
+        let names: Vec<&str> = BUILTIN_REFERENCES.iter().map(|(name, _)| *name).collect();
+        return Err(anyhow!("no reference dataset {} (bundled: {}; or give a file or URL)", spec, names.join(", ")));
+    };
+    let dataset: ReferenceDataset = serde_json::from_str(&json).with_context(|| format!("parsing {}", origin))?;
+    if dataset.metrics.is_empty() {
+        return Err(anyhow!("reference dataset {} has no metrics", dataset.name));
+    }
+    Ok(dataset)
+}
+
+#[cfg(feature = "download")]
+fn download(url: &str) -> Result<String> {
+    let response = attohttpc::get(url).send().with_context(|| format!("downloading {}", url))?;
+    let response = response.error_for_status().with_context(|| format!("downloading {}", url))?;
+    Ok(response.text()?)
+}
+
+#[cfg(not(feature = "download"))]
+fn download(url: &str) -> Result<String> {
+    Err(anyhow!("cannot download {}: built without the `download` feature; save it and pass the file instead", url))
+}
+
+/// Positions the mean of `runs` against every metric of `dataset`.
+pub fn compare_with_reference(dataset: &ReferenceDataset, runs: &[&CampaignRun], tolerance_percent: f64) -> ReferenceComparison {
+    let flattened: Vec<BTreeMap<String, f64>> = runs
+        .iter()
+        .map(|run| {
+            let mut metrics = BTreeMap::new();
+            flatten(&run.result, String::new(), &mut metrics);
+            metrics
+        })
+        .collect();
+
+    let mut positions = Vec::new();
+    let mut not_measured = Vec::new();
+    for (metric, reference) in &dataset.metrics {
+        let values: Vec<f64> = flattened.iter().filter_map(|values| values.get(metric).copied()).collect();
+        if values.is_empty() {
+            not_measured.push(metric.clone());
+            continue;
+        }
+        let local_mean = values.iter().sum::<f64>() / values.len() as f64;
+        let delta_percent = (reference.value != 0.0).then(|| (local_mean - reference.value) / reference.value.abs() * 100.0);
+        let in_line = match (reference.low, reference.high) {
+            (Some(low), Some(high)) => (low..=high).contains(&local_mean),
+            _ => delta_percent.map_or(local_mean == 0.0, |delta| delta.abs() <= tolerance_percent),
+        };
+        let direction = Direction::of(metric);
+        let standing = match (in_line, direction, local_mean > reference.value) {
+            (true, _, _) => Standing::InLine,
+            (_, Direction::LowerIsBetter, false) | (_, Direction::HigherIsBetter, true) => Standing::Better,
+            (_, Direction::LowerIsBetter, true) | (_, Direction::HigherIsBetter, false) => Standing::Worse,
+            (_, Direction::Unknown, true) => Standing::Higher,
+            (_, Direction::Unknown, false) => Standing::Lower,
+        };
+        positions.push(MetricPosition {
+            metric: metric.clone(),
+            reference: reference.value,
+            local_mean,
+            local_runs: values.len(),
+            delta_percent,
+            direction,
+            standing,
+        });
+    }
+
+    ReferenceComparison {
+        reference: dataset.name.clone(),
+        protocol: dataset.protocol.clone(),
+        local_runs: runs.len(),
+        positions,
+        not_measured,
+        caveats: caveats(&dataset.environment, runs),
+    }
+}
+
+/// How a run reached its peer, as far as the result tells.
+fn link_of(result: &Value) -> &'static str {
+    let present = |field: &str| result.get(field).is_some_and(|value| !value.is_null());
+    if present("network_namespace") {
+        "veth"
+    } else if present("distributed_measurement") {
+        "network"
+    } else {
+        "loopback"
+    }
+}
+
+/// The distinct values of a field over the local runs, in first-seen order.
+fn distinct(runs: &[&CampaignRun], field: impl Fn(&CampaignRun) -> Option<String>) -> Vec<String> {
+    let mut values: Vec<String> = Vec::new();
+    for value in runs.iter().filter_map(|run| field(run)) {
+        if !values.contains(&value) {
+            values.push(value);
+        }
+    }
+    values
+}
+
+fn caveats(reference: &ReferenceEnvironment, runs: &[&CampaignRun]) -> Vec<String> {
+    let mut caveats = Vec::new();
+    let differs = |reference: &str, local: &[String]| local.iter().any(|value| !value.eq_ignore_ascii_case(reference));
+
+    let links = distinct(runs, |run| Some(link_of(&run.result).to_string()));
+    if differs(&reference.link, &links) {
+        let mut caveat = format!("the reference was measured over {}, the local runs over {}", reference.link, links.join(", "));
+        if !reference.link.eq_ignore_ascii_case("loopback") && links.iter().any(|link| link == "loopback") {
+            caveat.push_str("; loopback leaves out the air time and hops the reference includes, so local latencies look better");
+        }
+        caveats.push(caveat);
+    }
+    if let Some(device_class) = &reference.device_class {
+        let local = distinct(runs, |run| run.provenance.device_class.clone());
+        if differs(device_class, &local) {
+            caveats.push(format!("the reference ran on a {} machine, the local runs on {}; CPU-bound timings such as encoding and handshakes scale with the hardware",
+                                 device_class, local.join(", ")));
+        }
+    }
+    if let Some(os_platform) = &reference.os_platform {
+        let local = distinct(runs, |run| {
+            run.result.pointer("/test_environment/os_platform").and_then(Value::as_str).map(str::to_string)
+        });
+        if differs(os_platform, &local) {
+            caveats.push(format!("the reference ran on {}, the local runs on {}; timer resolution and socket stacks differ between operating systems",
+                                 os_platform, local.join(", ")));
+        }
+    }
+    let impaired = runs
+        .iter()
+        .filter(|run| run.result.pointer("/network_namespace/impairment").is_some_and(|impairment| !impairment.is_null()))
+        .count();
+    match (reference.impaired, impaired) {
+        (false, 0) => {}
+        (false, impaired) => caveats.push(format!("{} of {} local runs were under network impairment and the reference was not", impaired, runs.len())),
+        (true, impaired) if impaired < runs.len() => {
+            caveats.push(format!("the reference was measured under network impairment and {} of {} local runs were not", runs.len() - impaired, runs.len()));
+        }
+        (true, _) => {}
+    }
+    if let Some(implementation) = &reference.implementation {
+        caveats.push(format!("the reference measured {}; implementation differences show up in every metric", implementation));
+    }
+    if runs.len() < MIN_LOCAL_RUNS {
+        caveats.push(format!("only {} local run(s); repeat the run before reading much into a single comparison", runs.len()));
+    }
+    caveats.extend(reference.notes.iter().map(|note| format!("reference note: {}", note)));
+    caveats
+}
+
+pub struct ReferenceCompare {
+    config: ReferenceConfig,
+}
+
+impl ReferenceCompare {
+    pub fn new(config: ReferenceConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn run(&self) -> Result<Option<ReferenceComparison>> {
+        let Some(spec) = &self.config.reference else {
+            println!("\n📚 Bundled reference datasets ({})", BUILTIN_REFERENCES.len());
+            println!("------------------------------------------------");
+            for (name, _) in BUILTIN_REFERENCES {
+                let dataset = load_reference(name)?;
+                println!("✅ {} ({}, {} metrics): {}", name, dataset.protocol, dataset.metrics.len(), dataset.title);
+            }
+            return Ok(None);
+        };
+        let dataset = load_reference(spec)?;
+        let inputs = if self.config.inputs.is_empty() { QueryConfig::default_inputs() } else { self.config.inputs.clone() };
+        let local = CampaignMerger::new(MergeConfig { inputs, ..MergeConfig::default() }).merge()?;
+        let runs: Vec<&CampaignRun> =
+            local.runs.iter().filter(|run| run.provenance.protocol.eq_ignore_ascii_case(&dataset.protocol)).collect();
+        if runs.is_empty() {
+            return Err(anyhow!("no local {} results to compare with {} (found: {})",
+                               dataset.protocol, dataset.name, local.protocols().join(", ")));
+        }
+        let comparison = compare_with_reference(&dataset, &runs, self.config.tolerance_percent);
+
+        println!("\n📚 {} results ({} runs) against {}", dataset.protocol, runs.len(), dataset.name);
+        println!("------------------------------------------------");
+        println!("{}", dataset.title);
+        println!("Source: {}{}", dataset.source, dataset.published.as_deref().map(|date| format!(", {}", date)).unwrap_or_default());
+        let number = |v: f64| format!("{:.4}", v).trim_end_matches('0').trim_end_matches('.').to_string();
+        for position in &comparison.positions {
+            let marker = match position.standing {
+                Standing::InLine | Standing::Better => "✅",
+                Standing::Worse => "⚠️",
+                Standing::Higher | Standing::Lower => "•",
+            };
+            let percent = position.delta_percent.map_or_else(String::new, |p| format!(" ({:+.1}%)", p));
+            println!("{} {}: {} vs {}{}, {}", marker, position.metric, number(position.local_mean), number(position.reference),
+                     percent, position.standing.label());
+        }
+        if !comparison.not_measured.is_empty() {
+            println!("➖ Not in the local results: {}", comparison.not_measured.join(", "));
+        }
+        println!("\nCaveats:");
+        for caveat in &comparison.caveats {
+            println!("⚠️ {}", caveat);
+        }
+        Ok(Some(comparison))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analysis_core::campaign::RunProvenance;
+    use serde_json::json;
+
+    #[test]
+    fn local_means_are_positioned_with_environment_caveats() {
+        let run = |commissioning: f64, efficiency: f64| CampaignRun {
+            key: "matter/1".to_string(),
+            provenance: RunProvenance {
+                source_file: "matter.json".to_string(),
+                sha256: String::new(),
+                protocol: "Matter".to_string(),
+                analysis_timestamp: None,
+                saved_at: None,
+                host: None,
+                device_class: Some("constrained".to_string()),
+                file_modified: None,
+            },
+            result: json!({
+                "osi_layer_5_session": {"commissioning_time_ms": commissioning, "session_establishment_efficiency": efficiency},
+                "test_environment": {"os_platform": "linux"},
+            }),
+        };
+        let runs = [run(90.0, 0.7), run(110.0, 0.9)];
+        let dataset = load_reference("study-2025-matter").unwrap();
+        let comparison = compare_with_reference(&dataset, &runs.iter().collect::<Vec<_>>(), 20.0);
+
+        let position = |metric: &str| comparison.positions.iter().find(|p| p.metric == metric).unwrap();
+        let commissioning = position("osi_layer_5_session.commissioning_time_ms");
+        assert_eq!(commissioning.local_mean, 100.0);
+        assert_eq!(commissioning.standing, Standing::Better);
+        assert_eq!(position("osi_layer_5_session.session_establishment_efficiency").standing, Standing::Better);
+        assert!(comparison.not_measured.contains(&"osi_layer_7_application.discovery_time_ms".to_string()));
+        assert!(comparison.caveats.iter().any(|c| c.contains("desktop machine, the local runs on constrained")));
+        assert!(comparison.caveats.iter().any(|c| c.contains("windows, the local runs on linux")));
+        assert!(comparison.caveats.iter().any(|c| c.starts_with("only 2 local run(s)")));
+        assert!(!comparison.caveats.iter().any(|c| c.contains("measured over")));
+        assert!(load_reference("study-1999").is_err());
+    }
+}
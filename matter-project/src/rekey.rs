@@ -0,0 +1,353 @@
+// matter-project/src/rekey.rs
+/*!
+Session key rotation - bytes and latency per re-key and the steady-state overhead at a rotation interval
+*/
+
+use anyhow::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::auth_cost::{case_operational_cost, case_resumption_cost, AuthSchemeCost, CASE_ITERATIONS};
+use crate::bdx::{matter_message, MRP_STANDALONE_ACK, PROTOCOL_SECURE_CHANNEL};
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{mqtt_packet, mqtt_string, MQTT_CONNACK, MQTT_CONNECT, MQTT_DISCONNECT};
+use crate::handshake::{AuthMode, HandshakeAnalyzer, HandshakeConfig, HandshakeResult, LinkProfile, SecurityProtocol};
+use crate::transport_modes::{IPV4_TCP_HEADER_BYTES, IPV4_UDP_HEADER_BYTES, TCP_HANDSHAKE_BYTES, TCP_TEARDOWN_BYTES};
+
+// DTLS 1.3 KeyUpdate (RFC 9147): unified header with 16-bit sequence and length,
+// handshake header, one-byte request_update, inner content type and AEAD tag
+const DTLS13_UNIFIED_HEADER_BYTES: usize = 1 + 2 + 2;
+const DTLS_HANDSHAKE_HEADER_BYTES: usize = 12;
+const AEAD_TAG_BYTES: usize = 16;
+// ACK record: length-prefixed list with one 16-byte record number
+const DTLS13_ACK_BODY_BYTES: usize = 2 + 16;
+const TLS_RECORD_HEADER_BYTES: usize = 5;
+
+/// Short-lived JWT-style password as handed out by cloud brokers.
+const MQTT_CREDENTIAL_TOKEN_BYTES: usize = 256;
+
+#[derive(Debug, Clone)]
+pub struct RekeyConfig {
+    /// Time between rotations for the steady-state figures.
+    pub rotation_intervals: Vec<Duration>,
+    /// One-way link delay added to every round trip of a rotation.
+    pub one_way_delay_ms: f64,
+    /// CASE exchanges averaged for the Matter rotation cost.
+    pub case_iterations: u32,
+}
+
+impl Default for RekeyConfig {
+    fn default() -> Self {
+        Self {
+            rotation_intervals: vec![
+                Duration::from_secs(10 * 60),
+                Duration::from_secs(60 * 60),
+                Duration::from_secs(24 * 60 * 60),
+            ],
+            one_way_delay_ms: 20.0,
+            case_iterations: CASE_ITERATIONS,
+        }
+    }
+}
+
+analyzer_builder!(RekeyAnalyzerBuilder => RekeyAnalyzer(RekeyConfig) {
+    rotation_intervals: Vec<Duration>,
+    one_way_delay_ms: f64,
+    case_iterations: u32,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RekeyMetrics {
+    pub one_way_delay_ms: f64,
+    pub rotations: Vec<RotationCost>,
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotationCost {
+    pub scheme: String,
+    pub backend: String,
+    /// What replaces the old keys.
+    pub mechanism: String,
+    pub messages: usize,
+    /// All messages of one rotation with IPv4 and UDP/TCP headers.
+    pub wire_bytes: usize,
+    pub round_trips: u32,
+    /// Host compute time for both ends, no link delay.
+    pub compute_time_ms: f64,
+    /// Until the new keys are usable, link delay included.
+    pub latency_ms: f64,
+    pub steady_state: Vec<SteadyStateOverhead>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SteadyStateOverhead {
+    pub rotation_interval_s: f64,
+    pub rotations_per_hour: f64,
+    pub bytes_per_hour: f64,
+    /// Time per hour spent waiting for new keys.
+    pub rekey_ms_per_hour: f64,
+}
+
+pub struct RekeyAnalyzer {
+    config: RekeyConfig,
+}
+
+impl RekeyAnalyzer {
+    pub fn new(config: RekeyConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_rekey(&mut self) -> Result<RekeyMetrics> {
+        println!("\n🔄 Analyzing Session Key Rotation ({:.0}ms one-way delay)", self.config.one_way_delay_ms);
+        println!("--------------------------------------------------------");
+
+        let rtt_ms = 2.0 * self.config.one_way_delay_ms;
+        let mut rotations = Vec::new();
+
+        // Matter: a fresh CASE exchange, or resumption from the previous session's secret
+        let full = case_operational_cost(self.config.case_iterations.max(1))?;
+        rotations.push(case_rotation(&full, "full CASE (Sigma1/2/3) with certificate validation", 2, rtt_ms));
+        let resumption = case_resumption_cost(self.config.case_iterations.max(1))?;
+        rotations.push(case_rotation(&resumption, "CASE resumption (Sigma1 + Sigma2_Resume)", 1, rtt_ms));
+
+        let handshakes = HandshakeAnalyzer::new(HandshakeConfig {
+            protocols: vec![SecurityProtocol::Dtls12, SecurityProtocol::Tls13],
+            auth_modes: vec![AuthMode::Psk, AuthMode::Certificate],
+            links: vec![LinkProfile {
+                name: "rekey".to_string(),
+                one_way_delay_ms: self.config.one_way_delay_ms,
+                loss_rate: 0.0,
+                mtu_bytes: 1280,
+            }],
+            trials: 1,
+        })
+        .analyze_handshakes()?;
+        let mut notes = handshakes.unsupported.clone();
+        let find = |protocol: SecurityProtocol, auth: AuthMode, resume: bool| {
+            handshakes
+                .results
+                .iter()
+                .find(|r| r.protocol == protocol && r.auth == auth && r.resumption_requested == resume)
+        };
+
+        // DTLS 1.2 re-keys by renegotiating inside the association, or by resuming a new one
+        match find(SecurityProtocol::Dtls12, AuthMode::Psk, false) {
+            Some(result) => {
+                rotations.push(dtls12_rotation("dtls12_psk_renegotiation", "full DTLS 1.2 handshake under the current epoch", result));
+                if let Some(resumed) = find(SecurityProtocol::Dtls12, AuthMode::Psk, true).filter(|r| r.resumed) {
+                    rotations.push(dtls12_rotation("dtls12_psk_resumption", "abbreviated DTLS 1.2 handshake resuming the session", resumed));
+                }
+            }
+            None => {
+                warn!("⚠️ No DTLS 1.2 backend compiled in; DTLS renegotiation is not measured");
+                notes.push("dtls12: renegotiation not measured without a DTLS backend (feature tls-openssl)".to_string());
+            }
+        }
+
+        rotations.push(dtls13_key_update(rtt_ms));
+
+        // MQTT brokers rotate credentials by dropping and re-establishing the connection
+        let tls = find(SecurityProtocol::Tls13, AuthMode::Certificate, false);
+        if tls.is_none() {
+            warn!("⚠️ No TLS 1.3 backend compiled in; MQTT reconnect is measured without TLS");
+            notes.push("mqtt: reconnect measured without TLS (features tls-rustls, tls-openssl)".to_string());
+        }
+        rotations.push(mqtt_reconnect(tls, rtt_ms));
+
+        notes.push("dtls13_key_update: message sizes from RFC 9147 framing, not driven through a backend".to_string());
+
+        for rotation in &mut rotations {
+            rotation.steady_state = self.steady_state(rotation);
+            println!("✅ {}: {} messages, {}B, {} RTTs, {:.2}ms per rotation",
+                     rotation.scheme, rotation.messages, rotation.wire_bytes, rotation.round_trips, rotation.latency_ms);
+        }
+
+        Ok(RekeyMetrics {
+            one_way_delay_ms: self.config.one_way_delay_ms,
+            rotations,
+            notes,
+        })
+    }
+
+    fn steady_state(&self, rotation: &RotationCost) -> Vec<SteadyStateOverhead> {
+        self.config
+            .rotation_intervals
+            .iter()
+            .filter(|interval| !interval.is_zero())
+            .map(|interval| {
+                let rotations_per_hour = 3600.0 / interval.as_secs_f64();
+                SteadyStateOverhead {
+                    rotation_interval_s: interval.as_secs_f64(),
+                    rotations_per_hour,
+                    bytes_per_hour: rotations_per_hour * rotation.wire_bytes as f64,
+                    rekey_ms_per_hour: rotations_per_hour * rotation.latency_ms,
+                }
+            })
+            .collect()
+    }
+}
+
+/// CASE messages are sent over MRP; the closing StatusReport gets a standalone ack.
+fn case_rotation(cost: &AuthSchemeCost, mechanism: &str, round_trips: u32, rtt_ms: f64) -> RotationCost {
+    let ack = matter_message(PROTOCOL_SECURE_CHANNEL, MRP_STANDALONE_ACK, &[]).len();
+    let messages = cost.handshake_messages + 1;
+    RotationCost {
+        scheme: cost.scheme.clone(),
+        backend: cost.backend.clone(),
+        mechanism: mechanism.to_string(),
+        messages,
+        wire_bytes: cost.handshake_bytes + ack + messages * IPV4_UDP_HEADER_BYTES,
+        round_trips,
+        compute_time_ms: cost.handshake_time_ms,
+        latency_ms: cost.handshake_time_ms + round_trips as f64 * rtt_ms,
+        steady_state: Vec::new(),
+    }
+}
+
+fn dtls12_rotation(scheme: &str, mechanism: &str, result: &HandshakeResult) -> RotationCost {
+    let datagrams: usize = result.flights.iter().map(|f| f.records).sum();
+    RotationCost {
+        scheme: scheme.to_string(),
+        backend: result.backend.clone(),
+        mechanism: mechanism.to_string(),
+        messages: datagrams,
+        wire_bytes: result.client_bytes + result.server_bytes + datagrams * IPV4_UDP_HEADER_BYTES,
+        round_trips: result.handshake_rtts,
+        compute_time_ms: result.compute_time_ms,
+        latency_ms: result.mean_handshake_ms,
+        steady_state: Vec::new(),
+    }
+}
+
+/// One KeyUpdate each way, each acknowledged; both directions overlap in a single round trip.
+fn dtls13_key_update(rtt_ms: f64) -> RotationCost {
+    let key_update = DTLS13_UNIFIED_HEADER_BYTES + DTLS_HANDSHAKE_HEADER_BYTES + 1 + 1 + AEAD_TAG_BYTES;
+    let ack = DTLS13_UNIFIED_HEADER_BYTES + DTLS13_ACK_BODY_BYTES + 1 + AEAD_TAG_BYTES;
+    RotationCost {
+        scheme: "dtls13_key_update".to_string(),
+        backend: "RFC 9147".to_string(),
+        mechanism: "KeyUpdate with update_requested, answered by the peer's KeyUpdate".to_string(),
+        messages: 4,
+        wire_bytes: 2 * (key_update + ack) + 4 * IPV4_UDP_HEADER_BYTES,
+        round_trips: 1,
+        compute_time_ms: 0.0,
+        latency_ms: rtt_ms,
+        steady_state: Vec::new(),
+    }
+}
+
+/// DISCONNECT and teardown of the old connection, then TCP (+ TLS) setup and
+/// a CONNECT carrying the new credentials; the persistent session keeps subscriptions.
+fn mqtt_reconnect(tls: Option<&HandshakeResult>, rtt_ms: f64) -> RotationCost {
+    let mut connect = mqtt_string("MQTT");
+    connect.extend_from_slice(&[4, 0xC0, 0, 60]); // username + password, persistent session
+    connect.extend_from_slice(&mqtt_string("sensor-0001"));
+    connect.extend_from_slice(&mqtt_string("sensor-0001"));
+    connect.extend_from_slice(&mqtt_string(&"k".repeat(MQTT_CREDENTIAL_TOKEN_BYTES)));
+    let packets = [
+        mqtt_packet(MQTT_DISCONNECT, &[]).len(),
+        mqtt_packet(MQTT_CONNECT, &connect).len(),
+        mqtt_packet(MQTT_CONNACK, &[1, 0]).len(),
+    ];
+    let mqtt_bytes: usize = packets.iter().map(|p| p + IPV4_TCP_HEADER_BYTES).sum();
+    let tcp_bytes = TCP_TEARDOWN_BYTES + TCP_HANDSHAKE_BYTES;
+
+    match tls {
+        Some(result) => {
+            let records: usize = result.flights.iter().map(|f| f.records).sum();
+            // Handshake records, plus a record header, inner type and tag around each MQTT packet
+            let tls_bytes = result.client_bytes
+                + result.server_bytes
+                + records * IPV4_TCP_HEADER_BYTES
+                + packets.len() * (TLS_RECORD_HEADER_BYTES + 1 + AEAD_TAG_BYTES);
+            RotationCost {
+                scheme: "mqtt_tls13_reconnect".to_string(),
+                backend: result.backend.clone(),
+                mechanism: "new TLS 1.3 connection and CONNECT with fresh credentials".to_string(),
+                messages: packets.len() + records,
+                wire_bytes: mqtt_bytes + tcp_bytes + tls_bytes,
+                round_trips: result.transport_setup_rtts + result.handshake_rtts + 1,
+                compute_time_ms: result.compute_time_ms,
+                latency_ms: result.mean_handshake_ms + rtt_ms,
+                steady_state: Vec::new(),
+            }
+        }
+        None => RotationCost {
+            scheme: "mqtt_reconnect".to_string(),
+            backend: "plain TCP".to_string(),
+            mechanism: "new TCP connection and CONNECT with fresh credentials".to_string(),
+            messages: packets.len(),
+            wire_bytes: mqtt_bytes + tcp_bytes,
+            round_trips: 2,
+            compute_time_ms: 0.0,
+            latency_ms: 2.0 * rtt_ms,
+            steady_state: Vec::new(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth_cost::DeviceStorage;
+
+    fn rotation(wire_bytes: usize, latency_ms: f64) -> RotationCost {
+        RotationCost {
+            scheme: "test".to_string(),
+            backend: String::new(),
+            mechanism: String::new(),
+            messages: 1,
+            wire_bytes,
+            round_trips: 1,
+            compute_time_ms: 0.0,
+            latency_ms,
+            steady_state: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn steady_state_scales_one_rotation_by_the_hourly_rate() {
+        let analyzer = RekeyAnalyzer::builder()
+            .rotation_intervals(vec![Duration::ZERO, Duration::from_secs(600), Duration::from_secs(7200)])
+            .build();
+        let steady_state = analyzer.steady_state(&rotation(500, 40.0));
+
+        assert_eq!(steady_state.len(), 2, "a zero interval is skipped");
+        assert_eq!(steady_state[0].rotations_per_hour, 6.0);
+        assert_eq!(steady_state[0].bytes_per_hour, 3000.0);
+        assert_eq!(steady_state[0].rekey_ms_per_hour, 240.0);
+        assert_eq!(steady_state[1].rotations_per_hour, 0.5);
+    }
+
+    #[test]
+    fn case_rotations_add_the_closing_ack_and_udp_headers() {
+        let cost = AuthSchemeCost {
+            scheme: "case_resumption".to_string(),
+            backend: "ring".to_string(),
+            handshake_time_ms: 1.5,
+            handshake_bytes: 300,
+            handshake_messages: 3,
+            device_storage: DeviceStorage { own_credential_bytes: 0, trust_anchor_bytes: 0, secret_key_bytes: 0, total_bytes: 0 },
+        };
+        let ack = matter_message(PROTOCOL_SECURE_CHANNEL, MRP_STANDALONE_ACK, &[]).len();
+        let rotation = case_rotation(&cost, "resumption", 1, 40.0);
+
+        assert_eq!(rotation.messages, 4);
+        assert_eq!(rotation.wire_bytes, 300 + ack + 4 * IPV4_UDP_HEADER_BYTES);
+        assert_eq!(rotation.latency_ms, 41.5);
+    }
+
+    #[test]
+    fn key_update_and_plain_reconnect_follow_their_framing() {
+        let key_update = dtls13_key_update(40.0);
+        // Two 35-byte KeyUpdates and two 40-byte ACKs
+        assert_eq!(key_update.wire_bytes, 2 * (35 + 40) + 4 * IPV4_UDP_HEADER_BYTES);
+        assert_eq!((key_update.round_trips, key_update.latency_ms), (1, 40.0));
+
+        let reconnect = mqtt_reconnect(None, 40.0);
+        assert_eq!((reconnect.messages, reconnect.round_trips, reconnect.latency_ms), (3, 2, 80.0));
+        assert!(reconnect.wire_bytes > TCP_TEARDOWN_BYTES + TCP_HANDSHAKE_BYTES + MQTT_CREDENTIAL_TOKEN_BYTES);
+    }
+}
@@ -0,0 +1,490 @@
+// matter-project/src/reordering.rs
+/*!
+Reordering and duplication tolerance - Matter MRP, CoAP and TCP under a shuffling, duplicating link
+
+A device sends a message every interval to a server through a
+[`ShapedRelay`], once over a clean link and once over one that holds some
+messages back and sends some twice. Several messages are in flight at once,
+so a held-back message is overtaken by the ones after it. Each receiver
+handles this the way its protocol does:
+
+- Matter MRP accepts a message whose counter is newer than the largest seen,
+  or within the 32-counter window behind it and not yet seen. Anything
+  older counts as a duplicate: it is acknowledged but not delivered. The
+  sender retransmits on the MRP schedule until acknowledged.
+- CoAP CON deduplicates on the message ID, acknowledges every copy and is
+  retransmitted on the RFC 7252 schedule; CoAP NON deduplicates the same
+  way without acknowledgements.
+- TCP delivers in order, so a held-back segment holds up everything behind
+  it, and drops duplicates before the application.
+
+Latency runs from a message's first transmission to its delivery to the
+application. The penalty is the impaired latency minus the clean one.
+Messages are small and the link has no rate limit, so queueing plays no
+part in it.
+*/
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::oneshot;
+use tokio::time::{sleep_until, Instant};
+
+use crate::builder::analyzer_builder;
+use crate::firmware_update::Carrier;
+use crate::netsim::{AsymmetricLink, LinkShape, LinkStats, ShapedRelay};
+use crate::transport_modes::{MRP_ACTIVE_RETRANS_TIMEOUT, MRP_BACKOFF_BASE, MRP_MAX_TRANSMISSIONS};
+
+// Message layout: kind, sequence (u32 BE), padding
+const MESSAGE_BYTES: usize = 32;
+const KIND_DATA: u8 = 0x01;
+const KIND_ACK: u8 = 0x10;
+
+/// Matter's message counter window (Core spec 4.6.5).
+const MATTER_COUNTER_WINDOW: u32 = 32;
+
+// RFC 7252 transmission parameters, at the lower bound of the randomized timeout
+const COAP_ACK_TIMEOUT: Duration = Duration::from_secs(2);
+const COAP_MAX_TRANSMISSIONS: u32 = 5;
+
+/// Time left for late copies to arrive after the last message went out.
+const DRAIN_MARGIN: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone)]
+pub struct ReorderConfig {
+    /// Messages per protocol and link.
+    pub messages: usize,
+    /// A new message goes out this often.
+    pub interval: Duration,
+    /// One-way delay, both directions.
+    pub delay_ms: f64,
+    /// Messages held back, per direction.
+    pub reorder_percent: f64,
+    /// How long a held-back message is held on top of the delay.
+    pub reorder_delay_ms: f64,
+    pub duplicate_percent: f64,
+}
+
+impl Default for ReorderConfig {
+    fn default() -> Self {
+        Self {
+            messages: 100,
+            interval: Duration::from_millis(10),
+            delay_ms: 20.0,
+            reorder_percent: 10.0,
+            reorder_delay_ms: 50.0,
+            duplicate_percent: 5.0,
+        }
+    }
+}
+
+analyzer_builder!(ReorderAnalyzerBuilder => ReorderAnalyzer(ReorderConfig) {
+    messages: usize,
+    interval: Duration,
+    delay_ms: f64,
+    reorder_percent: f64,
+    reorder_delay_ms: f64,
+    duplicate_percent: f64,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReorderMetrics {
+    pub messages: usize,
+    pub interval_ms: f64,
+    pub link: LinkShape,
+    pub protocols: Vec<ReorderProtocolResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReorderProtocolResult {
+    pub protocol: String,
+    pub transport: String,
+    /// What the receiver detects duplicates with.
+    pub mechanism: String,
+    pub clean: ReorderRun,
+    pub impaired: ReorderRun,
+    pub mean_latency_penalty_ms: f64,
+    pub p95_latency_penalty_ms: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReorderRun {
+    /// Distinct messages the application received.
+    pub delivered: usize,
+    /// Messages the application never received, such as Matter messages that fell behind the counter window.
+    pub undelivered: usize,
+    /// Deliveries of a message older than one delivered before it.
+    pub out_of_order: usize,
+    pub duplicates_suppressed: usize,
+    /// Copies of an already delivered message handed to the application again.
+    pub duplicates_delivered: usize,
+    pub retransmissions: usize,
+    pub latency_mean_ms: f64,
+    pub latency_p95_ms: f64,
+    pub link: LinkStats,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Protocol {
+    MatterMrp,
+    CoapCon,
+    CoapNon,
+    Tcp,
+}
+
+const PROTOCOLS: [Protocol; 4] = [Protocol::MatterMrp, Protocol::CoapCon, Protocol::CoapNon, Protocol::Tcp];
+
+impl Protocol {
+    fn name(self) -> &'static str {
+        match self {
+            Protocol::MatterMrp => "Matter MRP",
+            Protocol::CoapCon => "CoAP CON",
+            Protocol::CoapNon => "CoAP NON",
+            Protocol::Tcp => "TCP",
+        }
+    }
+
+    fn mechanism(self) -> &'static str {
+        match self {
+            Protocol::MatterMrp => "message counter window",
+            Protocol::CoapCon | Protocol::CoapNon => "message ID",
+            Protocol::Tcp => "sequence numbers, in-order delivery",
+        }
+    }
+
+    fn carrier(self) -> Carrier {
+        match self {
+            Protocol::Tcp => Carrier::Tcp,
+            _ => Carrier::Udp,
+        }
+    }
+
+    /// Wait before retransmitting after `transmissions` transmissions; `None` once they are used up
+    /// or when the protocol does not acknowledge.
+    fn retransmit_after(self, transmissions: u32) -> Option<Duration> {
+        match self {
+            Protocol::MatterMrp if transmissions < MRP_MAX_TRANSMISSIONS => {
+                Some(MRP_ACTIVE_RETRANS_TIMEOUT.mul_f64(MRP_BACKOFF_BASE.powi(transmissions as i32 - 1)))
+            }
+            Protocol::CoapCon if transmissions < COAP_MAX_TRANSMISSIONS => Some(COAP_ACK_TIMEOUT * (1 << (transmissions - 1))),
+            _ => None,
+        }
+    }
+
+    fn acknowledged(self) -> bool {
+        matches!(self, Protocol::MatterMrp | Protocol::CoapCon)
+    }
+
+    fn dedup(self) -> Dedup {
+        match self {
+            Protocol::MatterMrp => Dedup::CounterWindow { largest: None, seen: HashSet::new() },
+            _ => Dedup::MessageIds(HashSet::new()),
+        }
+    }
+}
+
+enum Dedup {
+    CounterWindow { largest: Option<u32>, seen: HashSet<u32> },
+    MessageIds(HashSet<u32>),
+}
+
+impl Dedup {
+    /// Whether `sequence` is fresh, recording it if so.
+    fn accept(&mut self, sequence: u32) -> bool {
+        match self {
+            Dedup::CounterWindow { largest, seen } => match *largest {
+                Some(max) if sequence <= max => {
+                    // Behind the window there is no record left, so the message counts as a duplicate
+                    max - sequence < MATTER_COUNTER_WINDOW && seen.insert(sequence)
+                }
+                _ => {
+                    *largest = Some(sequence);
+                    seen.retain(|&seen| sequence - seen < MATTER_COUNTER_WINDOW);
+                    seen.insert(sequence)
+                }
+            },
+            Dedup::MessageIds(seen) => seen.insert(sequence),
+        }
+    }
+}
+
+#[derive(Default)]
+struct ReceiverLog {
+    deliveries: Vec<(u32, Instant)>,
+    suppressed: usize,
+}
+
+pub struct ReorderAnalyzer {
+    config: ReorderConfig,
+}
+
+impl ReorderAnalyzer {
+    pub fn new(config: ReorderConfig) -> Self {
+        Self { config }
+    }
+
+    fn clean_link(&self) -> LinkShape {
+        LinkShape { delay_ms: self.config.delay_ms, ..LinkShape::default() }
+    }
+
+    fn impaired_link(&self) -> LinkShape {
+        LinkShape {
+            reorder_percent: self.config.reorder_percent,
+            reorder_delay_ms: self.config.reorder_delay_ms,
+            duplicate_percent: self.config.duplicate_percent,
+            ..self.clean_link()
+        }
+    }
+
+    pub async fn analyze_reordering(&mut self) -> Result<ReorderMetrics> {
+        println!("\n🔀 Analyzing Reordering and Duplication Tolerance ({}% held back {}ms, {}% duplicated)",
+                 self.config.reorder_percent, self.config.reorder_delay_ms, self.config.duplicate_percent);
+        println!("------------------------------------------------");
+
+        let mut protocols = Vec::new();
+        for protocol in PROTOCOLS {
+            let clean = self.run(protocol, self.clean_link()).await?;
+            let impaired = self.run(protocol, self.impaired_link()).await?;
+            println!("✅ {}: p95 {:.1}ms -> {:.1}ms, {} out of order, {} duplicates suppressed, {} undelivered, {} retransmissions",
+                     protocol.name(), clean.latency_p95_ms, impaired.latency_p95_ms, impaired.out_of_order,
+                     impaired.duplicates_suppressed, impaired.undelivered, impaired.retransmissions);
+            protocols.push(ReorderProtocolResult {
+                protocol: protocol.name().to_string(),
+                transport: format!("{:?}", protocol.carrier()).to_uppercase(),
+                mechanism: protocol.mechanism().to_string(),
+                mean_latency_penalty_ms: impaired.latency_mean_ms - clean.latency_mean_ms,
+                p95_latency_penalty_ms: impaired.latency_p95_ms - clean.latency_p95_ms,
+                clean,
+                impaired,
+            });
+        }
+
+        Ok(ReorderMetrics {
+            messages: self.config.messages,
+            interval_ms: self.config.interval.as_secs_f64() * 1000.0,
+            link: self.impaired_link(),
+            protocols,
+        })
+    }
+
+    async fn run(&self, protocol: Protocol, shape: LinkShape) -> Result<ReorderRun> {
+        let drain = Duration::from_secs_f64((2.0 * shape.delay_ms + shape.reorder_delay_ms) / 1000.0) + DRAIN_MARGIN;
+        let link = AsymmetricLink::symmetric(shape);
+        let (sent_at, retransmissions, log, stats) = match protocol.carrier() {
+            Carrier::Udp => {
+                let server = UdpSocket::bind("127.0.0.1:0").await?;
+                let relay = ShapedRelay::spawn(Carrier::Udp, link, server.local_addr()?).await?;
+                let (stop, stopped) = oneshot::channel();
+                let receiver = tokio::spawn(receive_datagrams(server, protocol, stopped));
+
+                let device = UdpSocket::bind("127.0.0.1:0").await?;
+                device.connect(relay.addr()).await?;
+                let (sent_at, retransmissions) = self.send_datagrams(&device, protocol).await?;
+                tokio::time::sleep(drain).await;
+                let _ = stop.send(());
+                (sent_at, retransmissions, receiver.await??, relay.stats())
+            }
+            Carrier::Tcp => {
+                let listener = TcpListener::bind("127.0.0.1:0").await?;
+                let relay = ShapedRelay::spawn(Carrier::Tcp, link, listener.local_addr()?).await?;
+                let receiver = tokio::spawn(async move {
+                    let (stream, _) = listener.accept().await?;
+                    receive_stream(stream).await
+                });
+
+                let mut device = TcpStream::connect(relay.addr()).await?;
+                device.set_nodelay(true)?;
+                let start = Instant::now();
+                let mut sent_at = Vec::with_capacity(self.config.messages);
+                for sequence in 0..self.config.messages {
+                    sleep_until(start + self.config.interval * sequence as u32).await;
+                    device.write_all(&message(KIND_DATA, sequence as u32)).await?;
+                    sent_at.push(Instant::now());
+                }
+                // Closing ends the receiver once the relay has delivered the rest
+                device.shutdown().await?;
+                let log = tokio::time::timeout(drain + Duration::from_secs(5), receiver)
+                    .await
+                    .map_err(|_| anyhow!("TCP receiver did not finish"))???;
+                // The relay stands in for the receiving TCP, which drops the duplicates
+                let stats = relay.stats();
+                (sent_at, 0, ReceiverLog { suppressed: stats.duplicated, ..log }, stats)
+            }
+        };
+        Ok(summarize(&sent_at, retransmissions, log, stats))
+    }
+
+    /// Sends one message per interval and, for acknowledged protocols, retransmits until acknowledged.
+    /// Returns the first transmission time of each message and the retransmission count.
+    async fn send_datagrams(&self, socket: &UdpSocket, protocol: Protocol) -> Result<(Vec<Instant>, usize)> {
+        let start = Instant::now();
+        let mut sent_at = Vec::with_capacity(self.config.messages);
+        // sequence -> (next retransmission, transmissions so far)
+        let mut outstanding: HashMap<u32, (Instant, u32)> = HashMap::new();
+        let mut retransmissions = 0;
+        let mut buffer = [0u8; 64];
+
+        loop {
+            let next = sent_at.len();
+            let tick = (next < self.config.messages).then(|| start + self.config.interval * next as u32);
+            let retry = outstanding.values().map(|(at, _)| *at).min();
+            let wake = match (tick, retry) {
+                (Some(tick), Some(retry)) => tick.min(retry),
+                (tick, retry) => match tick.or(retry) {
+                    Some(wake) => wake,
+                    None => break,
+                },
+            };
+
+            tokio::select! {
+                _ = sleep_until(wake) => {
+                    let now = Instant::now();
+                    if tick.is_some_and(|tick| tick <= now) {
+                        socket.send(&message(KIND_DATA, next as u32)).await?;
+                        sent_at.push(now);
+                        if let Some(after) = protocol.retransmit_after(1) {
+                            outstanding.insert(next as u32, (now + after, 1));
+                        }
+                    }
+                    let due: Vec<u32> = outstanding.iter().filter(|(_, (at, _))| *at <= now).map(|(sequence, _)| *sequence).collect();
+                    for sequence in due {
+                        let (_, transmissions) = outstanding[&sequence];
+                        socket.send(&message(KIND_DATA, sequence)).await?;
+                        retransmissions += 1;
+                        match protocol.retransmit_after(transmissions + 1) {
+                            Some(after) => outstanding.insert(sequence, (now + after, transmissions + 1)),
+                            None => outstanding.remove(&sequence),
+                        };
+                    }
+                }
+                received = socket.recv(&mut buffer) => {
+                    if let Some((KIND_ACK, sequence)) = parse(&buffer[..received?]) {
+                        outstanding.remove(&sequence);
+                    }
+                }
+            }
+        }
+        Ok((sent_at, retransmissions))
+    }
+}
+
+fn message(kind: u8, sequence: u32) -> Vec<u8> {
+    let mut message = vec![0u8; MESSAGE_BYTES];
+    message[0] = kind;
+    message[1..5].copy_from_slice(&sequence.to_be_bytes());
+    message
+}
+
+fn parse(message: &[u8]) -> Option<(u8, u32)> {
+    Some((*message.first()?, u32::from_be_bytes(message.get(1..5)?.try_into().ok()?)))
+}
+
+async fn receive_datagrams(socket: UdpSocket, protocol: Protocol, mut stopped: oneshot::Receiver<()>) -> Result<ReceiverLog> {
+    let mut dedup = protocol.dedup();
+    let mut log = ReceiverLog::default();
+    let mut buffer = [0u8; 64];
+    loop {
+        tokio::select! {
+            _ = &mut stopped => return Ok(log),
+            received = socket.recv_from(&mut buffer) => {
+                let (len, peer) = received?;
+                let Some((KIND_DATA, sequence)) = parse(&buffer[..len]) else { continue };
+                if dedup.accept(sequence) {
+                    log.deliveries.push((sequence, Instant::now()));
+                } else {
+                    log.suppressed += 1;
+                }
+                // Duplicates are acknowledged too, in case the first acknowledgement was lost
+                if protocol.acknowledged() {
+                    socket.send_to(&message(KIND_ACK, sequence), peer).await?;
+                }
+            }
+        }
+    }
+}
+
+async fn receive_stream(mut stream: TcpStream) -> Result<ReceiverLog> {
+    let mut log = ReceiverLog::default();
+    let mut buffer = [0u8; MESSAGE_BYTES];
+    while stream.read_exact(&mut buffer).await.is_ok() {
+        if let Some((KIND_DATA, sequence)) = parse(&buffer) {
+            log.deliveries.push((sequence, Instant::now()));
+        }
+    }
+    Ok(log)
+}
+
+fn summarize(sent_at: &[Instant], retransmissions: usize, log: ReceiverLog, link: LinkStats) -> ReorderRun {
+    let mut delivered = HashSet::new();
+    let (mut out_of_order, mut duplicates_delivered, mut newest) = (0, 0, None::<u32>);
+    let mut latencies = Vec::new();
+    for &(sequence, at) in &log.deliveries {
+        if !delivered.insert(sequence) {
+            duplicates_delivered += 1;
+            continue;
+        }
+        if newest.is_some_and(|newest| sequence < newest) {
+            out_of_order += 1;
+        }
+        newest = newest.max(Some(sequence));
+        if let Some(sent) = sent_at.get(sequence as usize) {
+            latencies.push((at - *sent).as_secs_f64() * 1000.0);
+        }
+    }
+    latencies.sort_by(f64::total_cmp);
+    let percentile = |p: f64| latencies.get(((latencies.len().saturating_sub(1)) as f64 * p).round() as usize).copied().unwrap_or(0.0);
+
+    ReorderRun {
+        delivered: delivered.len(),
+        undelivered: sent_at.len().saturating_sub(delivered.len()),
+        out_of_order,
+        duplicates_suppressed: log.suppressed,
+        duplicates_delivered,
+        retransmissions,
+        latency_mean_ms: if latencies.is_empty() { 0.0 } else { latencies.iter().sum::<f64>() / latencies.len() as f64 },
+        latency_p95_ms: percentile(0.95),
+        link,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reordering_costs_tcp_more_than_datagram_protocols() {
+        let mut window = Protocol::MatterMrp.dedup();
+        assert!(window.accept(40) && window.accept(35) && !window.accept(35));
+        assert!(!window.accept(8), "behind the counter window");
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let metrics = runtime
+            .block_on(
+                ReorderAnalyzer::builder()
+                    .messages(40usize)
+                    .interval(Duration::from_millis(5))
+                    .delay_ms(5.0)
+                    .reorder_percent(30.0)
+                    .reorder_delay_ms(40.0)
+                    .duplicate_percent(20.0)
+                    .build()
+                    .analyze_reordering(),
+            )
+            .unwrap();
+        let protocol = |name: &str| metrics.protocols.iter().find(|p| p.protocol == name).unwrap();
+
+        for result in &metrics.protocols {
+            assert_eq!(result.impaired.delivered, 40, "{}", result.protocol);
+            assert_eq!(result.impaired.duplicates_delivered, 0, "{}", result.protocol);
+            assert!(result.impaired.duplicates_suppressed > 0, "{}", result.protocol);
+        }
+        let (mrp, tcp) = (protocol("Matter MRP"), protocol("TCP"));
+        assert!(mrp.impaired.out_of_order > 0);
+        assert_eq!(tcp.impaired.out_of_order, 0);
+        // In-order delivery makes every message behind a held-back one wait for it
+        assert!(tcp.mean_latency_penalty_ms > mrp.mean_latency_penalty_ms);
+    }
+}
@@ -0,0 +1,481 @@
+// matter-project/src/replay.rs
+/*!
+Replay and duplicate handling - captured messages re-sent to each protocol's duplicate detection
+
+A capture of consecutive messages is fed to a receiver implementing the
+protocol's duplicate detection (Matter message counter window, CoAP message-ID
+deduplication, MQTT QoS 1 / QoS 2 packet identifiers) on a virtual clock, and
+one copy is injected per case. The injected copy should reach the application
+only when it is a fresh message arriving out of order; anything else is
+reported as an anomaly, even where the specification allows it.
+*/
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+use crate::bdx::{matter_message, MRP_STANDALONE_ACK, PROTOCOL_SECURE_CHANNEL};
+use crate::firmware_update::{
+    coap_message, coap_uint, mqtt_packet, mqtt_publish, mqtt_split, mqtt_string, COAP_ACK, COAP_CON, COAP_CONTENT,
+    COAP_OPTION_OBSERVE, IM_REPORT_DATA, MQTT_PUBACK, PROTOCOL_IM,
+};
+
+const CAPTURE_LEN: usize = 200;
+/// Virtual seconds between consecutive captured messages.
+const CAPTURE_SPACING_S: f64 = 1.0;
+
+// Matter message header
+const MESSAGE_COUNTER_OFFSET: usize = 4;
+const EXCHANGE_FLAGS_OFFSET: usize = 8;
+const EXCHANGE_FLAG_RELIABLE: u8 = 0x04;
+/// Counters up to this far behind the largest one seen are tracked individually.
+const MATTER_COUNTER_WINDOW: u32 = 32;
+
+// RFC 7252 defaults
+const COAP_EXCHANGE_LIFETIME_S: f64 = 247.0;
+const COAP_NON_LIFETIME_S: f64 = 145.0;
+
+pub(crate) const MQTT_PUBLISH_QOS2: u8 = 0x34;
+const MQTT_DUP: u8 = 0x08;
+pub(crate) const MQTT_PUBREC: u8 = 0x50;
+pub(crate) const MQTT_PUBREL: u8 = 0x62;
+pub(crate) const MQTT_PUBCOMP: u8 = 0x70;
+const MQTT_TOPIC: &str = "sensors/dev1/temperature";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayCase {
+    /// The sender's retransmission of the latest message.
+    RetransmittedCopy,
+    /// A copy of a message ten messages back.
+    DelayedDuplicate,
+    /// A fresh message overtaken by three newer ones; must be accepted.
+    OutOfOrderFresh,
+    /// A captured message replayed 400 s after the capture ended.
+    ReplayAfterWindow,
+}
+
+impl ReplayCase {
+    const ALL: [ReplayCase; 4] =
+        [Self::RetransmittedCopy, Self::DelayedDuplicate, Self::OutOfOrderFresh, Self::ReplayAfterWindow];
+
+    /// (virtual time in seconds, capture index) in arrival order; the last entry is the injected copy.
+    fn schedule(self) -> Vec<(f64, usize)> {
+        let at = |index: usize| (index as f64 * CAPTURE_SPACING_S, index);
+        match self {
+            Self::RetransmittedCopy => {
+                let mut schedule: Vec<_> = (0..10).map(at).collect();
+                schedule.push((9.0 * CAPTURE_SPACING_S + 0.3, 9));
+                schedule
+            }
+            Self::DelayedDuplicate => {
+                let mut schedule: Vec<_> = (0..=20).map(at).collect();
+                schedule.push((20.0 * CAPTURE_SPACING_S + 0.5, 10));
+                schedule
+            }
+            Self::OutOfOrderFresh => {
+                let mut schedule: Vec<_> = (0..10).chain(11..14).map(at).collect();
+                schedule.push((13.0 * CAPTURE_SPACING_S + 0.5, 10));
+                schedule
+            }
+            Self::ReplayAfterWindow => {
+                let mut schedule: Vec<_> = (0..CAPTURE_LEN).map(at).collect();
+                schedule.push((CAPTURE_LEN as f64 * CAPTURE_SPACING_S + 400.0, 5));
+                schedule
+            }
+        }
+    }
+
+    fn is_duplicate(self) -> bool {
+        self != Self::OutOfOrderFresh
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplayMetrics {
+    pub capture_messages: usize,
+    pub protocols: Vec<ReplayProtocolResult>,
+    pub anomalies: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplayProtocolResult {
+    pub protocol: String,
+    /// What the receiver keys duplicate detection on.
+    pub mechanism: String,
+    pub cases: Vec<ReplayCaseResult>,
+    /// Receiver time per message, over every message of every case.
+    pub mean_handling_ns: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplayCaseResult {
+    pub case: ReplayCase,
+    pub delivered_to_application: bool,
+    /// Whether the receiver answered the copy (ack, cached response, PUBREC).
+    pub answered: bool,
+    pub verdict: String,
+    pub anomaly: bool,
+}
+
+/// What a receiver did with one incoming message.
+struct Reaction {
+    delivered: bool,
+    response: Option<Vec<u8>>,
+    verdict: &'static str,
+}
+
+trait DuplicateDetector {
+    fn name(&self) -> &'static str;
+    fn mechanism(&self) -> &'static str;
+    /// The captured message with sequence number `index`; `retransmission` marks a sender's own resend.
+    fn message(&self, index: usize, retransmission: bool) -> Vec<u8>;
+    fn reset(&mut self);
+    fn receive(&mut self, now_s: f64, message: &[u8]) -> Reaction;
+    /// Sender side of the exchange once the receiver has answered, if the protocol has one.
+    fn complete(&mut self, _response: &[u8]) {}
+}
+
+pub struct ReplayAnalyzer;
+
+impl Default for ReplayAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplayAnalyzer {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn analyze_replay(&mut self) -> Result<ReplayMetrics> {
+        println!("\n🔁 Verifying Replay and Duplicate Handling ({} captured messages)", CAPTURE_LEN);
+        println!("----------------------------------------------------------------");
+
+        let mut detectors: Vec<Box<dyn DuplicateDetector>> = vec![
+            Box::new(MatterCounters::default()),
+            Box::new(CoapDeduplication::default()),
+            Box::new(MqttPacketIds::qos1()),
+            Box::new(MqttPacketIds::qos2()),
+        ];
+
+        let mut protocols = Vec::new();
+        let mut anomalies = Vec::new();
+        for detector in detectors.iter_mut() {
+            let mut cases = Vec::new();
+            let mut handled = 0usize;
+            let mut handling_ns = 0.0;
+            for case in ReplayCase::ALL {
+                detector.reset();
+                let schedule = case.schedule();
+                let mut reaction = None;
+                for (position, &(now_s, index)) in schedule.iter().enumerate() {
+                    let injected = position == schedule.len() - 1;
+                    let retransmission = injected && matches!(case, ReplayCase::RetransmittedCopy | ReplayCase::DelayedDuplicate);
+                    let message = detector.message(index, retransmission);
+
+                    let start = Instant::now();
+                    let outcome = detector.receive(now_s, &message);
+                    handling_ns += start.elapsed().as_nanos() as f64;
+                    handled += 1;
+
+                    // The retransmitted message's exchange is still open when the copy arrives
+                    let hold_open = case == ReplayCase::RetransmittedCopy && position == schedule.len() - 2;
+                    if let (Some(response), false) = (&outcome.response, hold_open) {
+                        detector.complete(response);
+                    }
+                    if injected {
+                        reaction = Some(outcome);
+                    }
+                }
+
+                let Some(reaction) = reaction else { continue };
+                let anomaly = reaction.delivered == case.is_duplicate();
+                if anomaly {
+                    anomalies.push(format!("{}: {:?} {}", detector.name(), case,
+                                           if reaction.delivered { "reached the application again" } else { "was rejected" }));
+                }
+                cases.push(ReplayCaseResult {
+                    case,
+                    delivered_to_application: reaction.delivered,
+                    answered: reaction.response.is_some(),
+                    verdict: reaction.verdict.to_string(),
+                    anomaly,
+                });
+            }
+
+            let flagged = cases.iter().filter(|c| c.anomaly).count();
+            println!("{} {}: {}/{} cases as expected ({})",
+                     if flagged == 0 { "✅" } else { "⚠️" }, detector.name(), cases.len() - flagged, cases.len(),
+                     cases.iter().map(|c| c.verdict.as_str()).collect::<Vec<_>>().join(", "));
+            protocols.push(ReplayProtocolResult {
+                protocol: detector.name().to_string(),
+                mechanism: detector.mechanism().to_string(),
+                cases,
+                mean_handling_ns: handling_ns / handled.max(1) as f64,
+            });
+        }
+
+        Ok(ReplayMetrics { capture_messages: CAPTURE_LEN, protocols, anomalies })
+    }
+}
+
+/// Secure-session message counters: the largest counter seen plus a bitmap of
+/// the window behind it. Reliable duplicates are acknowledged again but not
+/// delivered. The receiver trusts the (zeroed) MIC, so this checks the counter
+/// logic only, not message authentication.
+#[derive(Default)]
+struct MatterCounters {
+    max_counter: Option<u32>,
+    window: u32,
+}
+
+impl DuplicateDetector for MatterCounters {
+    fn name(&self) -> &'static str {
+        "Matter"
+    }
+
+    fn mechanism(&self) -> &'static str {
+        "message counter with a 32-message window"
+    }
+
+    fn message(&self, index: usize, _retransmission: bool) -> Vec<u8> {
+        let mut message = matter_message(PROTOCOL_IM, IM_REPORT_DATA, &[0x15, 0x18]);
+        let counter = 0x1000 + index as u32;
+        message[MESSAGE_COUNTER_OFFSET..MESSAGE_COUNTER_OFFSET + 4].copy_from_slice(&counter.to_le_bytes());
+        message
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn receive(&mut self, _now_s: f64, message: &[u8]) -> Reaction {
+        let Some(counter) = message
+            .get(MESSAGE_COUNTER_OFFSET..MESSAGE_COUNTER_OFFSET + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap_or_default()))
+        else {
+            return Reaction { delivered: false, response: None, verdict: "malformed" };
+        };
+        let reliable = message.get(EXCHANGE_FLAGS_OFFSET).is_some_and(|f| f & EXCHANGE_FLAG_RELIABLE != 0);
+        let ack = || reliable.then(|| matter_message(PROTOCOL_SECURE_CHANNEL, MRP_STANDALONE_ACK, &[]));
+
+        let Some(max) = self.max_counter else {
+            self.max_counter = Some(counter);
+            return Reaction { delivered: true, response: ack(), verdict: "accepted" };
+        };
+        if counter > max {
+            let shift = counter - max;
+            self.window = if shift >= MATTER_COUNTER_WINDOW { 0 } else { (self.window << shift) | (1 << (shift - 1)) };
+            self.max_counter = Some(counter);
+            return Reaction { delivered: true, response: ack(), verdict: "accepted" };
+        }
+        let behind = max - counter;
+        if behind == 0 || behind > MATTER_COUNTER_WINDOW || self.window & (1 << (behind - 1)) != 0 {
+            let verdict = if behind > MATTER_COUNTER_WINDOW { "behind window, dropped" } else { "duplicate, re-acked" };
+            return Reaction { delivered: false, response: ack(), verdict };
+        }
+        self.window |= 1 << (behind - 1);
+        Reaction { delivered: true, response: ack(), verdict: "accepted out of order" }
+    }
+}
+
+/// RFC 7252 deduplication: confirmable message IDs are remembered for
+/// EXCHANGE_LIFETIME and answered with the cached ACK; non-confirmable ones
+/// for NON_LIFETIME and dropped.
+#[derive(Default)]
+struct CoapDeduplication {
+    seen: HashMap<u16, (f64, Vec<u8>)>,
+}
+
+impl DuplicateDetector for CoapDeduplication {
+    fn name(&self) -> &'static str {
+        "CoAP"
+    }
+
+    fn mechanism(&self) -> &'static str {
+        "message ID remembered for EXCHANGE_LIFETIME (247 s)"
+    }
+
+    fn message(&self, index: usize, _retransmission: bool) -> Vec<u8> {
+        let message_id = 0x4000 + index as u16;
+        coap_message(COAP_CON, COAP_CONTENT, message_id, &[(COAP_OPTION_OBSERVE, coap_uint(index + 2))], b"21.5")
+    }
+
+    fn reset(&mut self) {
+        self.seen.clear();
+    }
+
+    fn receive(&mut self, now_s: f64, message: &[u8]) -> Reaction {
+        if message.len() < 4 {
+            return Reaction { delivered: false, response: None, verdict: "malformed" };
+        }
+        let confirmable = (message[0] >> 4) & 0x03 == COAP_CON;
+        let message_id = u16::from_be_bytes([message[2], message[3]]);
+        let lifetime = if confirmable { COAP_EXCHANGE_LIFETIME_S } else { COAP_NON_LIFETIME_S };
+        self.seen.retain(|_, (seen_at, _)| now_s - *seen_at <= lifetime);
+
+        if let Some((_, ack)) = self.seen.get(&message_id) {
+            let response = confirmable.then(|| ack.clone());
+            return Reaction { delivered: false, response, verdict: "duplicate, cached ACK re-sent" };
+        }
+        let ack = coap_message(COAP_ACK, 0x00, message_id, &[], &[]);
+        self.seen.insert(message_id, (now_s, ack.clone()));
+        Reaction { delivered: true, response: confirmable.then_some(ack), verdict: "accepted" }
+    }
+}
+
+/// MQTT receiver side of QoS 1 (at least once: every PUBLISH is delivered and
+/// acknowledged) or QoS 2 (exactly once while the packet identifier is held,
+/// i.e. until the sender's PUBREL).
+struct MqttPacketIds {
+    exactly_once: bool,
+    unreleased: HashSet<u16>,
+}
+
+impl MqttPacketIds {
+    fn qos1() -> Self {
+        Self { exactly_once: false, unreleased: HashSet::new() }
+    }
+
+    fn qos2() -> Self {
+        Self { exactly_once: true, unreleased: HashSet::new() }
+    }
+}
+
+impl DuplicateDetector for MqttPacketIds {
+    fn name(&self) -> &'static str {
+        if self.exactly_once { "MQTT QoS 2" } else { "MQTT QoS 1" }
+    }
+
+    fn mechanism(&self) -> &'static str {
+        if self.exactly_once {
+            "packet identifier held from PUBLISH until PUBREL"
+        } else {
+            "none: packet identifier only matches the PUBACK"
+        }
+    }
+
+    fn message(&self, index: usize, retransmission: bool) -> Vec<u8> {
+        let packet_id = 1 + (index % u16::MAX as usize) as u16;
+        let payload = format!(r#"{{"seq":{},"t":21.5}}"#, index);
+        let mut packet = if self.exactly_once {
+            let mut body = mqtt_string(MQTT_TOPIC);
+            body.extend_from_slice(&packet_id.to_be_bytes());
+            body.extend_from_slice(payload.as_bytes());
+            mqtt_packet(MQTT_PUBLISH_QOS2, &body)
+        } else {
+            mqtt_publish(MQTT_TOPIC, Some(packet_id), payload.as_bytes())
+        };
+        if retransmission {
+            packet[0] |= MQTT_DUP;
+        }
+        packet
+    }
+
+    fn reset(&mut self) {
+        self.unreleased.clear();
+    }
+
+    fn receive(&mut self, _now_s: f64, message: &[u8]) -> Reaction {
+        let Some((header, body)) = mqtt_split(message) else {
+            return Reaction { delivered: false, response: None, verdict: "malformed" };
+        };
+        let topic_len = body.get(..2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize).unwrap_or(0);
+        let Some(packet_id) = body.get(2 + topic_len..4 + topic_len).map(|b| u16::from_be_bytes([b[0], b[1]])) else {
+            return Reaction { delivered: false, response: None, verdict: "malformed" };
+        };
+        let dup = header & MQTT_DUP != 0;
+
+        if !self.exactly_once {
+            let verdict = if dup { "DUP delivered again" } else { "accepted" };
+            return Reaction { delivered: true, response: Some(mqtt_packet(MQTT_PUBACK, &packet_id.to_be_bytes())), verdict };
+        }
+        let pubrec = mqtt_packet(MQTT_PUBREC, &packet_id.to_be_bytes());
+        if !self.unreleased.insert(packet_id) {
+            return Reaction { delivered: false, response: Some(pubrec), verdict: "duplicate, PUBREC re-sent" };
+        }
+        let verdict = if dup { "DUP delivered again after PUBREL" } else { "accepted" };
+        Reaction { delivered: true, response: Some(pubrec), verdict }
+    }
+
+    fn complete(&mut self, response: &[u8]) {
+        // The sender answers PUBREC with PUBREL, which releases the packet identifier
+        if let Some((MQTT_PUBREC, id)) = mqtt_split(response) {
+            if let Some((MQTT_PUBREL, id)) = mqtt_split(&mqtt_packet(MQTT_PUBREL, id)) {
+                self.unreleased.remove(&u16::from_be_bytes([id[0], id[1]]));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds the captured messages at `indices` one second apart from `start_s`; true for each one delivered.
+    fn replay(detector: &mut dyn DuplicateDetector, start_s: f64, indices: &[usize]) -> Vec<bool> {
+        indices
+            .iter()
+            .enumerate()
+            .map(|(position, &index)| {
+                let now_s = start_s + position as f64 * CAPTURE_SPACING_S;
+                let reaction = detector.receive(now_s, &detector.message(index, false));
+                if let Some(response) = &reaction.response {
+                    detector.complete(response);
+                }
+                reaction.delivered
+            })
+            .collect()
+    }
+
+    #[test]
+    fn an_untouched_capture_is_delivered_and_its_replay_is_not() {
+        let capture: Vec<usize> = (0..20).collect();
+        let mut detectors: [Box<dyn DuplicateDetector>; 2] =
+            [Box::new(MatterCounters::default()), Box::new(CoapDeduplication::default())];
+        for detector in detectors.iter_mut() {
+            assert!(replay(detector.as_mut(), 0.0, &capture).iter().all(|&delivered| delivered), "{}", detector.name());
+            // Part of the capture again, still inside the counter window and the exchange lifetime
+            let replayed: Vec<usize> = (12..20).collect();
+            assert!(replay(detector.as_mut(), 20.0, &replayed).iter().all(|&delivered| !delivered), "{}", detector.name());
+        }
+    }
+
+    #[test]
+    fn reordered_and_tampered_messages_are_caught() {
+        let mut matter = MatterCounters::default();
+        // 12 overtaken by newer messages is fresh; its second copy and anything behind the window are not
+        let reordered = [0, 1, 2, 3, 40, 12, 12, 5];
+        assert_eq!(replay(&mut matter, 0.0, &reordered), [true, true, true, true, true, true, false, false]);
+
+        // A message cut short before its counter is never delivered
+        let truncated = &matter.message(41, false)[..MESSAGE_COUNTER_OFFSET + 2];
+        assert_eq!(matter.receive(41.0, truncated).verdict, "malformed");
+        // Rewriting the counter of a seen message back to an old one makes it a replay
+        let mut rewound = matter.message(41, false);
+        rewound[MESSAGE_COUNTER_OFFSET..MESSAGE_COUNTER_OFFSET + 4].copy_from_slice(&(0x1000u32 + 40).to_le_bytes());
+        assert!(!matter.receive(42.0, &rewound).delivered);
+
+        let mut coap = CoapDeduplication::default();
+        assert_eq!(coap.receive(0.0, &[0x40, 0x45]).verdict, "malformed");
+        let mut qos2 = MqttPacketIds::qos2();
+        assert_eq!(qos2.receive(0.0, &[MQTT_PUBLISH_QOS2]).verdict, "malformed");
+    }
+
+    #[test]
+    fn the_analysis_flags_only_what_each_protocol_lets_through() {
+        let metrics = ReplayAnalyzer::new().analyze_replay().unwrap();
+        let anomalies = |protocol: &str| -> Vec<ReplayCase> {
+            let result = metrics.protocols.iter().find(|p| p.protocol == protocol).unwrap();
+            result.cases.iter().filter(|c| c.anomaly).map(|c| c.case).collect()
+        };
+        assert_eq!(anomalies("Matter"), []);
+        // CoAP forgets message IDs after EXCHANGE_LIFETIME; QoS 1 has no duplicate detection at all
+        assert_eq!(anomalies("CoAP"), [ReplayCase::ReplayAfterWindow]);
+        let redelivered = [ReplayCase::RetransmittedCopy, ReplayCase::DelayedDuplicate, ReplayCase::ReplayAfterWindow];
+        assert_eq!(anomalies("MQTT QoS 1"), redelivered);
+    }
+}
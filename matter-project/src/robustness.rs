@@ -0,0 +1,374 @@
+// matter-project/src/robustness.rs
+/*!
+Parser robustness - malformed discovery, TLV, CoAP, MQTT and Matter messages thrown at the analyzers' parsing paths
+
+Each packet kind groups the receive-side parsers the analyzers run on bytes
+from the network. The structured mode mutates well-formed messages built by
+the analyzers themselves (bit flips, boundary bytes, truncation, insertion,
+splicing, random garbage) with a seeded xorshift generator, so a run is
+repeatable, and records every panic with the input that caused it. The same
+entry point, [`parse_packet`], backs the cargo-fuzz targets in `fuzz/`.
+*/
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::attestation::{certificate_parses, AttestationChain};
+use crate::bdx::{self, Sender};
+use crate::bridge::{discovery_messages, is_status_response, more_chunks};
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{
+    coap_parse, mqtt_split, Lwm2mBlockwise, MatterBdx, MqttChunks, UpdateMechanism,
+};
+use crate::telemetry::{CoapObserve, Lwm2mNotify, MatterSubscription, MqttPublish, TelemetryStream};
+
+/// Distinct panics kept per packet kind.
+const MAX_RECORDED_CRASHES: usize = 5;
+/// Input bytes kept with each recorded crash.
+const CRASH_INPUT_BYTES: usize = 64;
+const INTERESTING_BYTES: [u8; 7] = [0x00, 0x01, 0x0D, 0x0E, 0x7F, 0x80, 0xFF];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PacketKind {
+    /// Bridge discovery ReadRequests, chunked ReportData and StatusResponses.
+    Discovery,
+    /// DER certificates read during attestation.
+    Tlv,
+    /// LwM2M Block2 transfers, observe notifications and ACKs.
+    Coap,
+    /// Chunked firmware topics and QoS 1 telemetry publishes.
+    Mqtt,
+    /// BDX transfer messages and subscription reports.
+    Matter,
+}
+
+impl PacketKind {
+    pub const ALL: [PacketKind; 5] = [Self::Discovery, Self::Tlv, Self::Coap, Self::Mqtt, Self::Matter];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Discovery => "discovery",
+            Self::Tlv => "tlv",
+            Self::Coap => "coap",
+            Self::Mqtt => "mqtt",
+            Self::Matter => "matter",
+        }
+    }
+
+    /// Well-formed messages the mutations start from.
+    fn seeds(self) -> Result<Vec<Vec<u8>>> {
+        let block = vec![0xA5; 64];
+        Ok(match self {
+            Self::Discovery => discovery_messages(3)?,
+            Self::Tlv => {
+                let chain = AttestationChain::generate()?;
+                vec![chain.paa.der, chain.pai.der, chain.dac.der]
+            }
+            Self::Coap => vec![
+                Lwm2mBlockwise.block_request(3),
+                Lwm2mBlockwise.block_response(3, &block, 4096),
+                CoapObserve.report(7, 2150)?,
+                Lwm2mNotify.report(7, 2150)?,
+                Lwm2mNotify.acknowledgement(7).unwrap_or_default(),
+            ],
+            Self::Mqtt => vec![
+                MqttChunks.block_request(3),
+                MqttChunks.block_response(3, &block, 4096),
+                MqttChunks.block_ack(3).unwrap_or_default(),
+                MqttPublish.report(7, 2150)?,
+                MqttPublish.acknowledgement(7).unwrap_or_default(),
+            ],
+            Self::Matter => vec![
+                bdx::receive_init(1024, Some(4096), "fw.bin"),
+                bdx::block_query(3),
+                bdx::block(3, &block, false),
+                bdx::block_ack_eof(3),
+                MatterBdx.block_response(3, &block, 4096),
+                MatterSubscription.report(7, 2150)?,
+            ],
+        })
+    }
+}
+
+impl FromStr for PacketKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow!("unknown packet kind: {} (expected discovery, tlv, coap, mqtt or matter)", s))
+    }
+}
+
+/// Runs every parsing path for `kind` on `input`; true if any of them accepted it.
+/// A malformed input must make the parsers return, never panic.
+pub fn parse_packet(kind: PacketKind, input: &[u8]) -> bool {
+    match kind {
+        PacketKind::Discovery => more_chunks(input) | is_status_response(input),
+        PacketKind::Tlv => certificate_parses(input),
+        PacketKind::Coap => {
+            coap_parse(input).is_some()
+                | Lwm2mBlockwise.parse_block_request(input).is_some()
+                | Lwm2mBlockwise.block_payload(input).is_some()
+                | CoapObserve.parse_report(input).is_some()
+                | Lwm2mNotify.parse_report(input).is_some()
+        }
+        PacketKind::Mqtt => {
+            mqtt_split(input).is_some()
+                | MqttChunks.parse_block_request(input).is_some()
+                | MqttChunks.block_payload(input).is_some()
+                | MqttChunks.frame_len(input).is_some()
+                | MqttPublish.parse_report(input).is_some()
+                | MqttPublish.frame_len(input).is_some()
+        }
+        PacketKind::Matter => {
+            let mut sender = Sender::new(Arc::new(vec![0; 4096]));
+            sender.respond(input, false).is_some()
+                | MatterBdx.parse_block_request(input).is_some()
+                | MatterBdx.block_payload(input).is_some()
+                | MatterSubscription.parse_report(input).is_some()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RobustnessConfig {
+    /// Mutated inputs per packet kind.
+    pub iterations: usize,
+    pub seed: u64,
+    pub kinds: Vec<PacketKind>,
+}
+
+impl Default for RobustnessConfig {
+    fn default() -> Self {
+        Self {
+            iterations: 20_000,
+            seed: 0x2545_F491_4F6C_DD1D,
+            kinds: PacketKind::ALL.to_vec(),
+        }
+    }
+}
+
+analyzer_builder!(RobustnessAnalyzerBuilder => RobustnessAnalyzer(RobustnessConfig) {
+    iterations: usize,
+    seed: u64,
+    kinds: Vec<PacketKind>,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RobustnessMetrics {
+    pub iterations: usize,
+    pub seed: u64,
+    pub targets: Vec<RobustnessResult>,
+    pub total_panics: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RobustnessResult {
+    pub kind: PacketKind,
+    pub seed_messages: usize,
+    /// Seed messages some parser accepted unmodified; messages only the other side parses count as rejected.
+    pub seeds_accepted: usize,
+    pub inputs: usize,
+    pub accepted: usize,
+    pub rejected: usize,
+    pub panics: usize,
+    pub crashes: Vec<CrashReport>,
+    pub mean_parse_ns: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CrashReport {
+    pub message: String,
+    /// Leading bytes of the first input that caused it, hex encoded.
+    pub input_hex: String,
+    pub input_len: usize,
+    pub occurrences: usize,
+}
+
+pub struct RobustnessAnalyzer {
+    config: RobustnessConfig,
+}
+
+impl RobustnessAnalyzer {
+    pub fn new(config: RobustnessConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_robustness(&mut self) -> Result<RobustnessMetrics> {
+        println!("\n🧪 Fuzzing Protocol Parsers ({} inputs per kind, seed {:#x})", self.config.iterations, self.config.seed);
+        println!("----------------------------------------------------------------");
+
+        // Panics are expected and recorded; keep them off stderr while fuzzing
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+        let targets = self.config.kinds.iter().map(|&kind| self.fuzz(kind)).collect::<Result<Vec<_>>>();
+        panic::set_hook(previous_hook);
+        let targets = targets?;
+
+        for target in &targets {
+            println!("{} {}: {} inputs, {} accepted, {} rejected, {} panics ({}/{} seeds accepted, {:.0}ns/input)",
+                     if target.panics == 0 { "✅" } else { "❌" }, target.kind.name(), target.inputs, target.accepted,
+                     target.rejected, target.panics, target.seeds_accepted, target.seed_messages, target.mean_parse_ns);
+            for crash in &target.crashes {
+                println!("   ↳ {} x{}: {}", crash.message, crash.occurrences, crash.input_hex);
+            }
+        }
+
+        Ok(RobustnessMetrics {
+            iterations: self.config.iterations,
+            seed: self.config.seed,
+            total_panics: targets.iter().map(|t| t.panics).sum(),
+            targets,
+        })
+    }
+
+    fn fuzz(&self, kind: PacketKind) -> Result<RobustnessResult> {
+        let seeds = kind.seeds()?;
+        if seeds.is_empty() {
+            return Err(anyhow!("no seed messages for {}", kind.name()));
+        }
+        let seeds_accepted = seeds.iter().filter(|seed| parse_packet(kind, seed)).count();
+
+        let mut rng = XorShift(self.config.seed ^ kind as u64);
+        let mut result = RobustnessResult {
+            kind,
+            seed_messages: seeds.len(),
+            seeds_accepted,
+            inputs: 0,
+            accepted: 0,
+            rejected: 0,
+            panics: 0,
+            crashes: Vec::new(),
+            mean_parse_ns: 0.0,
+        };
+        let mut parse_ns = 0.0;
+
+        for _ in 0..self.config.iterations {
+            let mut input = seeds[rng.below(seeds.len())].clone();
+            for _ in 0..1 + rng.below(4) {
+                mutate(&mut input, &seeds, &mut rng);
+            }
+
+            let start = Instant::now();
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| parse_packet(kind, &input)));
+            parse_ns += start.elapsed().as_nanos() as f64;
+            result.inputs += 1;
+
+            match outcome {
+                Ok(true) => result.accepted += 1,
+                Ok(false) => result.rejected += 1,
+                Err(payload) => {
+                    result.panics += 1;
+                    let message = payload
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| payload.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "non-string panic".to_string());
+                    if let Some(crash) = result.crashes.iter_mut().find(|c| c.message == message) {
+                        crash.occurrences += 1;
+                    } else if result.crashes.len() < MAX_RECORDED_CRASHES {
+                        result.crashes.push(CrashReport {
+                            message,
+                            input_hex: input.iter().take(CRASH_INPUT_BYTES).map(|b| format!("{:02x}", b)).collect(),
+                            input_len: input.len(),
+                            occurrences: 1,
+                        });
+                    }
+                }
+            }
+        }
+
+        result.mean_parse_ns = parse_ns / result.inputs.max(1) as f64;
+        Ok(result)
+    }
+}
+
+/// Deterministic xorshift so fuzzing runs are repeatable.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound.max(1) as u64) as usize
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.next() as u8
+    }
+}
+
+fn mutate(input: &mut Vec<u8>, seeds: &[Vec<u8>], rng: &mut XorShift) {
+    match rng.below(8) {
+        // Flip one bit
+        0 if !input.is_empty() => {
+            let at = rng.below(input.len());
+            input[at] ^= 1 << rng.below(8);
+        }
+        // Boundary value, e.g. in a length or option nibble
+        1 if !input.is_empty() => {
+            let at = rng.below(input.len());
+            input[at] = INTERESTING_BYTES[rng.below(INTERESTING_BYTES.len())];
+        }
+        2 => input.truncate(rng.below(input.len() + 1)),
+        3 => {
+            let extra = 1 + rng.below(16);
+            input.extend((0..extra).map(|_| rng.byte()));
+        }
+        4 => {
+            let at = rng.below(input.len() + 1);
+            input.insert(at, rng.byte());
+        }
+        5 if !input.is_empty() => {
+            let start = rng.below(input.len());
+            let end = (start + 1 + rng.below(8)).min(input.len());
+            input.drain(start..end);
+        }
+        // Splice the tail of another seed in
+        6 => {
+            let other = &seeds[rng.below(seeds.len())];
+            let at = rng.below(input.len() + 1);
+            input.truncate(at);
+            input.extend_from_slice(&other[rng.below(other.len() + 1)..]);
+        }
+        _ => {
+            let len = rng.below(65);
+            *input = (0..len).map(|_| rng.byte()).collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coap_option_delta_overflow_is_rejected() {
+        // Two options with 14-nibble deltas of 65535 + 269 each
+        let message = [0x40, 0x01, 0x00, 0x01, 0xE0, 0xFF, 0xFF, 0xE0, 0xFF, 0xFF];
+        assert!(!parse_packet(PacketKind::Coap, &message));
+    }
+
+    #[test]
+    fn short_run_is_repeatable() {
+        let config = RobustnessConfig { iterations: 500, kinds: vec![PacketKind::Coap, PacketKind::Mqtt], ..Default::default() };
+        let first = RobustnessAnalyzer::new(config.clone()).analyze_robustness().unwrap();
+        let second = RobustnessAnalyzer::new(config).analyze_robustness().unwrap();
+        assert_eq!(first.total_panics, 0);
+        for (a, b) in first.targets.iter().zip(&second.targets) {
+            assert_eq!((a.accepted, a.rejected), (b.accepted, b.rejected));
+        }
+    }
+}
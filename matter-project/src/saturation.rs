@@ -0,0 +1,288 @@
+// matter-project/src/saturation.rs
+/*!
+Message-rate ceiling - ramps each telemetry stream's rate until delivery or latency collapses
+
+The telemetry comparison measures a handful of fixed rates. This test
+instead keeps raising the rate, by `growth` per step from `start_rate_hz`,
+until a step is no longer sustained: fewer than 99% of samples delivered,
+or a p95 latency of 50ms or more (the same criteria as the telemetry
+comparison). It then bisects between the last sustained rate and the
+collapsed one to place the ceiling more precisely.
+
+Two points are reported per protocol:
+
+- the maximum sustainable rate, the highest rate that met the criteria;
+- the latency knee, the lowest rate whose p95 latency rose to three times
+  the first step's (and by at least a millisecond): where queueing starts,
+  usually well before the collapse.
+
+Protocols that wait for each report's acknowledgement (Matter
+subscriptions, LwM2M notify) collapse by coalescing once samples come
+faster than one per round trip; pipelined ones (MQTT, CoAP observe) run
+until the host or socket buffers give out. The sample timer ticks once per
+millisecond and catches up in bursts above 1000/s, so on loopback, where a
+round trip is far shorter, the acknowledged protocols coalesce within
+those bursts and their ceiling sits near 1000/s. A step that stalls
+outright counts as collapsed. The collapse reason names the send-path stage
+the telemetry run found holding samples back: sender queue, backpressure,
+network or receiver. On loopback the ceilings describe this host,
+not a radio link.
+*/
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::builder::analyzer_builder;
+use crate::telemetry::{run_stream, telemetry_streams, TelemetryRun, SUSTAINED_DELIVERY_RATIO, SUSTAINED_P95_LATENCY};
+
+/// p95 latency over the first step's at the latency knee.
+const KNEE_LATENCY_FACTOR: f64 = 3.0;
+/// Below this rise the p95 is still noise on loopback.
+const KNEE_MIN_RISE_MS: f64 = 1.0;
+
+#[derive(Debug, Clone)]
+pub struct SaturationConfig {
+    pub start_rate_hz: u32,
+    /// Rate multiplier between ramp steps.
+    pub growth: f64,
+    /// The ramp stops here even when nothing collapsed.
+    pub max_rate_hz: u32,
+    pub step_duration: Duration,
+    /// Bisection steps between the last sustained and the collapsed rate.
+    pub refine_steps: u32,
+}
+
+impl Default for SaturationConfig {
+    fn default() -> Self {
+        Self {
+            start_rate_hz: 100,
+            growth: 2.0,
+            max_rate_hz: 200_000,
+            step_duration: Duration::from_millis(500),
+            refine_steps: 3,
+        }
+    }
+}
+
+analyzer_builder!(SaturationAnalyzerBuilder => SaturationAnalyzer(SaturationConfig) {
+    start_rate_hz: u32,
+    growth: f64,
+    max_rate_hz: u32,
+    step_duration: Duration,
+    refine_steps: u32,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaturationMetrics {
+    pub step_duration_ms: f64,
+    pub sustained_delivery_ratio: f64,
+    pub sustained_p95_latency_ms: f64,
+    pub protocols: Vec<SaturationProtocolResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaturationProtocolResult {
+    pub protocol: String,
+    /// Highest rate that was sustained; zero when not even the first step was.
+    pub max_sustainable_rate_hz: u32,
+    /// Delivered samples per second at that rate.
+    pub max_delivered_rate_hz: f64,
+    /// Lowest rate whose p95 latency rose past the knee threshold.
+    pub latency_knee_rate_hz: Option<u32>,
+    /// Lowest rate that was not sustained; `None` when the ramp reached its maximum.
+    pub collapse_rate_hz: Option<u32>,
+    pub collapse_reason: Option<String>,
+    /// Every step run, ramp and bisection, by rate.
+    pub steps: Vec<SaturationStep>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaturationStep {
+    pub target_rate_hz: u32,
+    pub delivered_rate_hz: f64,
+    pub delivery_ratio: f64,
+    pub latency_p95_ms: f64,
+    pub sustained: bool,
+    /// Send-path stage that held samples back the most, when the step finished.
+    pub bottleneck: Option<String>,
+    pub queue_depth_max: usize,
+    /// Why the step stalled, when it did not finish.
+    pub error: Option<String>,
+}
+
+impl SaturationStep {
+    fn new(rate_hz: u32, run: Result<TelemetryRun>) -> Self {
+        match run {
+            Ok(run) => Self {
+                target_rate_hz: rate_hz,
+                delivered_rate_hz: run.delivered_rate_hz,
+                delivery_ratio: run.delivery_ratio,
+                latency_p95_ms: run.latency_p95_ms,
+                sustained: run.sustained,
+                bottleneck: Some(run.send_path.bottleneck),
+                queue_depth_max: run.send_path.queue_depth_max,
+                error: None,
+            },
+            Err(e) => Self {
+                target_rate_hz: rate_hz,
+                delivered_rate_hz: 0.0,
+                delivery_ratio: 0.0,
+                latency_p95_ms: 0.0,
+                sustained: false,
+                bottleneck: None,
+                queue_depth_max: 0,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+
+    fn collapse_reason(&self) -> String {
+        let symptom = match &self.error {
+            Some(error) => return format!("stalled: {}", error),
+            None if self.delivery_ratio < SUSTAINED_DELIVERY_RATIO => {
+                format!("{:.1}% delivered", self.delivery_ratio * 100.0)
+            }
+            None => format!("p95 latency {:.1}ms", self.latency_p95_ms),
+        };
+        match &self.bottleneck {
+            Some(bottleneck) => format!("{}, {} bound", symptom, bottleneck),
+            None => symptom,
+        }
+    }
+}
+
+pub struct SaturationAnalyzer {
+    config: SaturationConfig,
+}
+
+impl SaturationAnalyzer {
+    pub fn new(config: SaturationConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_saturation(&mut self) -> Result<SaturationMetrics> {
+        println!("\n📈 Analyzing Message-Rate Ceilings (from {}/s, x{} per step up to {}/s)",
+                 self.config.start_rate_hz, self.config.growth, self.config.max_rate_hz);
+        println!("------------------------------------------------");
+
+        let mut protocols = Vec::new();
+        for stream in telemetry_streams() {
+            let run = |rate_hz: u32| {
+                let stream = stream.clone();
+                let duration = self.config.step_duration;
+                async move { SaturationStep::new(rate_hz, run_stream(&stream, rate_hz, duration).await) }
+            };
+
+            // Ramp until a step collapses or the maximum is reached
+            let mut steps = Vec::new();
+            let mut rate_hz = self.config.start_rate_hz.max(1);
+            let (mut sustained, mut collapsed) = (None, None);
+            loop {
+                let step = run(rate_hz).await;
+                let ok = step.sustained;
+                steps.push(step);
+                if !ok {
+                    collapsed = Some(rate_hz);
+                    break;
+                }
+                sustained = Some(rate_hz);
+                if rate_hz >= self.config.max_rate_hz {
+                    break;
+                }
+                rate_hz = ((rate_hz as f64 * self.config.growth.max(1.01)).ceil() as u32).min(self.config.max_rate_hz);
+            }
+
+            // Bisect between the last sustained and the collapsed rate
+            if let (Some(mut low), Some(mut high)) = (sustained, collapsed) {
+                for _ in 0..self.config.refine_steps {
+                    let middle = low + (high - low) / 2;
+                    if middle == low {
+                        break;
+                    }
+                    let step = run(middle).await;
+                    if step.sustained { low = middle } else { high = middle }
+                    steps.push(step);
+                }
+                sustained = Some(low);
+                collapsed = Some(high);
+            }
+            steps.sort_by_key(|step| step.target_rate_hz);
+
+            let ceiling = sustained.and_then(|rate| steps.iter().find(|step| step.target_rate_hz == rate));
+            let collapse = collapsed.and_then(|rate| steps.iter().find(|step| step.target_rate_hz == rate));
+            let baseline_p95 = steps.first().map_or(0.0, |step| step.latency_p95_ms);
+            let knee_p95 = (baseline_p95 * KNEE_LATENCY_FACTOR).max(baseline_p95 + KNEE_MIN_RISE_MS);
+            let latency_knee_rate_hz = steps
+                .iter()
+                .find(|step| step.error.is_some() || step.latency_p95_ms >= knee_p95)
+                .map(|step| step.target_rate_hz);
+
+            let result = SaturationProtocolResult {
+                protocol: stream.name().to_string(),
+                max_sustainable_rate_hz: sustained.unwrap_or(0),
+                max_delivered_rate_hz: ceiling.map_or(0.0, |step| step.delivered_rate_hz),
+                latency_knee_rate_hz,
+                collapse_rate_hz: collapsed,
+                collapse_reason: collapse.map(SaturationStep::collapse_reason),
+                steps,
+            };
+            println!("✅ {}: sustains {}/s, latency knee at {}, collapses at {}",
+                     result.protocol, result.max_sustainable_rate_hz,
+                     result.latency_knee_rate_hz.map_or("-".to_string(), |rate| format!("{}/s", rate)),
+                     match (&result.collapse_rate_hz, &result.collapse_reason) {
+                         (Some(rate), Some(reason)) => format!("{}/s ({})", rate, reason),
+                         _ => "- (ramp maximum reached)".to_string(),
+                     });
+            protocols.push(result);
+        }
+
+        Ok(SaturationMetrics {
+            step_duration_ms: self.config.step_duration.as_secs_f64() * 1000.0,
+            sustained_delivery_ratio: SUSTAINED_DELIVERY_RATIO,
+            sustained_p95_latency_ms: SUSTAINED_P95_LATENCY.as_secs_f64() * 1000.0,
+            protocols,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_ramp_brackets_each_ceiling() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let metrics = runtime
+            .block_on(
+                SaturationAnalyzer::builder()
+                    .start_rate_hz(200u32)
+                    .growth(4.0)
+                    .max_rate_hz(3200u32)
+                    .step_duration(Duration::from_millis(100))
+                    .refine_steps(2u32)
+                    .build()
+                    .analyze_saturation(),
+            )
+            .unwrap();
+
+        assert_eq!(metrics.protocols.len(), 4);
+        for result in &metrics.protocols {
+            assert!(result.steps.windows(2).all(|pair| pair[0].target_rate_hz < pair[1].target_rate_hz));
+            // Everything at or below the ceiling that was run was sustained, and the collapse was not
+            for step in &result.steps {
+                if step.target_rate_hz <= result.max_sustainable_rate_hz {
+                    assert!(step.sustained, "{} @ {}", result.protocol, step.target_rate_hz);
+                }
+            }
+            match result.collapse_rate_hz {
+                Some(rate) => {
+                    assert!(rate > result.max_sustainable_rate_hz);
+                    assert!(result.collapse_reason.is_some());
+                }
+                None => assert_eq!(result.max_sustainable_rate_hz, 3200, "{}", result.protocol),
+            }
+            assert!(result.steps.iter().all(|step| step.error.is_some() || step.bottleneck.is_some()));
+        }
+    }
+}
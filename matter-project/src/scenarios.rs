@@ -0,0 +1,875 @@
+// matter-project/src/scenarios.rs
+/*!
+Device scenario library - canonical light, lock, thermostat and sensor workloads encoded for each protocol
+*/
+
+use anyhow::{anyhow, Result};
+use metrics_core::tlv::{Tag, TlvError, TlvWriter};
+use serde::{Deserialize, Serialize};
+
+use crate::bdx::{matter_message, MRP_STANDALONE_ACK, PROTOCOL_SECURE_CHANNEL};
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{
+    coap_message, coap_uint, mqtt_packet, mqtt_publish, tlv, uri_path, COAP_ACK, COAP_CHANGED, COAP_CON, COAP_CONTENT,
+    COAP_OPTION_CONTENT_FORMAT, COAP_OPTION_OBSERVE, IM_INVOKE_REQUEST, IM_INVOKE_RESPONSE, IM_READ_REQUEST,
+    IM_REPORT_DATA, IM_STATUS_RESPONSE, MQTT_PUBACK, PROTOCOL_IM,
+};
+use crate::transport_modes::{IPV4_TCP_HEADER_BYTES, IPV4_UDP_HEADER_BYTES};
+
+const IM_WRITE_REQUEST: u8 = 0x06;
+const IM_WRITE_RESPONSE: u8 = 0x07;
+const IM_TIMED_REQUEST: u8 = 0x0A;
+const TIMED_INVOKE_TIMEOUT_MS: u16 = 1000;
+const SUBSCRIPTION_ID: u32 = 0x5C3E_0001;
+
+const COAP_GET: u8 = 0x01;
+const COAP_PUT: u8 = 0x03;
+const LWM2M_CONTENT_FORMAT_TLV: usize = 11542;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DeviceScenario {
+    Light,
+    Lock,
+    Thermostat,
+    Sensor,
+}
+
+impl DeviceScenario {
+    pub const ALL: [DeviceScenario; 4] =
+        [DeviceScenario::Light, DeviceScenario::Lock, DeviceScenario::Thermostat, DeviceScenario::Sensor];
+
+    pub fn name(self) -> &'static str {
+        self.definition().name
+    }
+
+    /// Matter device type implemented on the application endpoint.
+    pub fn matter_device_type(self) -> (u32, &'static str) {
+        self.definition().device_type
+    }
+
+    /// Server clusters as `<Name>_0x<id>`, utility clusters included.
+    pub fn clusters(self) -> Vec<String> {
+        self.definition()
+            .clusters
+            .iter()
+            .map(|(id, name)| format!("{}_0x{:04X}", name, id))
+            .collect()
+    }
+
+    pub fn lwm2m_objects(self) -> Vec<String> {
+        self.definition()
+            .objects
+            .iter()
+            .map(|(id, name)| format!("{}_{}", name, id))
+            .collect()
+    }
+
+    fn definition(self) -> &'static ScenarioDefinition {
+        match self {
+            DeviceScenario::Light => &LIGHT,
+            DeviceScenario::Lock => &LOCK,
+            DeviceScenario::Thermostat => &THERMOSTAT,
+            DeviceScenario::Sensor => &SENSOR,
+        }
+    }
+}
+
+impl std::str::FromStr for DeviceScenario {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        DeviceScenario::ALL
+            .into_iter()
+            .find(|scenario| scenario.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| anyhow!("unknown device scenario: {} (expected light, lock, thermostat or sensor)", name))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InteractionKind {
+    Read,
+    Write,
+    Invoke,
+    /// Unsolicited report from the device (subscription, observe, publish).
+    Report,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    Bool(bool),
+    U8(u8),
+    U16(u16),
+    /// Hundredths of a unit (°C, %RH); LwM2M and JSON carry it as a float.
+    Centi(i16),
+}
+
+struct Interaction {
+    name: &'static str,
+    kind: InteractionKind,
+    per_hour: f64,
+    endpoint: u16,
+    cluster: u32,
+    /// Attribute id, or command id for invokes.
+    id: u32,
+    /// Matter commands that must be preceded by a TimedRequest.
+    timed: bool,
+    fields: &'static [(u8, Value)],
+    /// LwM2M object, instance and resource standing for the same state.
+    resource: (u16, u16, u16),
+    /// State carried by the read response, write, report, LwM2M payload and MQTT payload.
+    value: Value,
+}
+
+struct ScenarioDefinition {
+    name: &'static str,
+    device_type: (u32, &'static str),
+    clusters: &'static [(u32, &'static str)],
+    objects: &'static [(u16, &'static str)],
+    interactions: &'static [Interaction],
+}
+
+const UTILITY_CLUSTERS: [(u32, &str); 3] =
+    [(0x001D, "Descriptor"), (0x001F, "Access_Control"), (0x0028, "Basic_Information")];
+
+static LIGHT: ScenarioDefinition = ScenarioDefinition {
+    name: "light",
+    device_type: (0x0101, "Dimmable Light"),
+    clusters: &[
+        UTILITY_CLUSTERS[0],
+        UTILITY_CLUSTERS[1],
+        UTILITY_CLUSTERS[2],
+        (0x0003, "Identify"),
+        (0x0004, "Groups"),
+        (0x0062, "Scenes_Management"),
+        (0x0006, "OnOff"),
+        (0x0008, "Level_Control"),
+    ],
+    objects: &[(3, "Device"), (3311, "Light_Control")],
+    interactions: &[
+        Interaction {
+            name: "turn_on",
+            kind: InteractionKind::Invoke,
+            per_hour: 4.0,
+            endpoint: 1,
+            cluster: 0x0006,
+            id: 0x01, // On
+            timed: false,
+            fields: &[],
+            resource: (3311, 0, 5850),
+            value: Value::Bool(true),
+        },
+        Interaction {
+            name: "turn_off",
+            kind: InteractionKind::Invoke,
+            per_hour: 4.0,
+            endpoint: 1,
+            cluster: 0x0006,
+            id: 0x00, // Off
+            timed: false,
+            fields: &[],
+            resource: (3311, 0, 5850),
+            value: Value::Bool(false),
+        },
+        Interaction {
+            name: "dim",
+            kind: InteractionKind::Invoke,
+            per_hour: 6.0,
+            endpoint: 1,
+            cluster: 0x0008,
+            id: 0x04, // MoveToLevelWithOnOff: Level, TransitionTime, OptionsMask, OptionsOverride
+            timed: false,
+            fields: &[(0, Value::U8(128)), (1, Value::U16(5)), (2, Value::U8(0)), (3, Value::U8(0))],
+            resource: (3311, 0, 5851),
+            value: Value::U8(50),
+        },
+        Interaction {
+            name: "on_off_report",
+            kind: InteractionKind::Report,
+            per_hour: 8.0,
+            endpoint: 1,
+            cluster: 0x0006,
+            id: 0x0000, // OnOff
+            timed: false,
+            fields: &[],
+            resource: (3311, 0, 5850),
+            value: Value::Bool(true),
+        },
+        Interaction {
+            name: "read_level",
+            kind: InteractionKind::Read,
+            per_hour: 2.0,
+            endpoint: 1,
+            cluster: 0x0008,
+            id: 0x0000, // CurrentLevel
+            timed: false,
+            fields: &[],
+            resource: (3311, 0, 5851),
+            value: Value::U8(50),
+        },
+    ],
+};
+
+// There is no IPSO lock object; the generic Actuation object stands in for the bolt
+static LOCK: ScenarioDefinition = ScenarioDefinition {
+    name: "lock",
+    device_type: (0x000A, "Door Lock"),
+    clusters: &[
+        UTILITY_CLUSTERS[0],
+        UTILITY_CLUSTERS[1],
+        UTILITY_CLUSTERS[2],
+        (0x0003, "Identify"),
+        (0x002F, "Power_Source"),
+        (0x0101, "Door_Lock"),
+    ],
+    objects: &[(3, "Device"), (3306, "Actuation")],
+    interactions: &[
+        Interaction {
+            name: "lock",
+            kind: InteractionKind::Invoke,
+            per_hour: 2.0,
+            endpoint: 1,
+            cluster: 0x0101,
+            id: 0x00, // LockDoor
+            timed: true,
+            fields: &[],
+            resource: (3306, 0, 5850),
+            value: Value::Bool(true),
+        },
+        Interaction {
+            name: "unlock",
+            kind: InteractionKind::Invoke,
+            per_hour: 2.0,
+            endpoint: 1,
+            cluster: 0x0101,
+            id: 0x01, // UnlockDoor
+            timed: true,
+            fields: &[],
+            resource: (3306, 0, 5850),
+            value: Value::Bool(false),
+        },
+        Interaction {
+            name: "lock_state_report",
+            kind: InteractionKind::Report,
+            per_hour: 6.0,
+            endpoint: 1,
+            cluster: 0x0101,
+            id: 0x0000, // LockState
+            timed: false,
+            fields: &[],
+            resource: (3306, 0, 5850),
+            value: Value::U8(1),
+        },
+        Interaction {
+            name: "battery_report",
+            kind: InteractionKind::Report,
+            per_hour: 1.0,
+            endpoint: 0,
+            cluster: 0x002F,
+            id: 0x000C, // BatPercentRemaining
+            timed: false,
+            fields: &[],
+            resource: (3, 0, 9),
+            value: Value::U8(180),
+        },
+        Interaction {
+            name: "read_lock_state",
+            kind: InteractionKind::Read,
+            per_hour: 2.0,
+            endpoint: 1,
+            cluster: 0x0101,
+            id: 0x0000,
+            timed: false,
+            fields: &[],
+            resource: (3306, 0, 5850),
+            value: Value::U8(1),
+        },
+    ],
+};
+
+static THERMOSTAT: ScenarioDefinition = ScenarioDefinition {
+    name: "thermostat",
+    device_type: (0x0301, "Thermostat"),
+    clusters: &[
+        UTILITY_CLUSTERS[0],
+        UTILITY_CLUSTERS[1],
+        UTILITY_CLUSTERS[2],
+        (0x0003, "Identify"),
+        (0x0004, "Groups"),
+        (0x0201, "Thermostat"),
+        (0x0204, "Thermostat_User_Interface_Configuration"),
+    ],
+    objects: &[(3, "Device"), (3303, "Temperature"), (3308, "Set_Point")],
+    interactions: &[
+        Interaction {
+            name: "local_temperature_report",
+            kind: InteractionKind::Report,
+            per_hour: 12.0,
+            endpoint: 1,
+            cluster: 0x0201,
+            id: 0x0000, // LocalTemperature
+            timed: false,
+            fields: &[],
+            resource: (3303, 0, 5700),
+            value: Value::Centi(2130),
+        },
+        Interaction {
+            name: "write_heating_setpoint",
+            kind: InteractionKind::Write,
+            per_hour: 3.0,
+            endpoint: 1,
+            cluster: 0x0201,
+            id: 0x0012, // OccupiedHeatingSetpoint
+            timed: false,
+            fields: &[],
+            resource: (3308, 0, 5900),
+            value: Value::Centi(2100),
+        },
+        Interaction {
+            name: "raise_setpoint",
+            kind: InteractionKind::Invoke,
+            per_hour: 2.0,
+            endpoint: 1,
+            cluster: 0x0201,
+            id: 0x00, // SetpointRaiseLower: Mode (heat), Amount in 0.1 °C
+            timed: false,
+            fields: &[(0, Value::U8(0)), (1, Value::U8(5))],
+            resource: (3308, 0, 5900),
+            value: Value::Centi(2150),
+        },
+        Interaction {
+            name: "read_heating_setpoint",
+            kind: InteractionKind::Read,
+            per_hour: 2.0,
+            endpoint: 1,
+            cluster: 0x0201,
+            id: 0x0012,
+            timed: false,
+            fields: &[],
+            resource: (3308, 0, 5900),
+            value: Value::Centi(2150),
+        },
+    ],
+};
+
+static SENSOR: ScenarioDefinition = ScenarioDefinition {
+    name: "sensor",
+    device_type: (0x0302, "Temperature Sensor"),
+    clusters: &[
+        UTILITY_CLUSTERS[0],
+        UTILITY_CLUSTERS[1],
+        UTILITY_CLUSTERS[2],
+        (0x0003, "Identify"),
+        (0x002F, "Power_Source"),
+        (0x0402, "Temperature_Measurement"),
+        (0x0405, "Relative_Humidity_Measurement"),
+    ],
+    objects: &[(3, "Device"), (3303, "Temperature"), (3304, "Humidity")],
+    interactions: &[
+        Interaction {
+            name: "temperature_report",
+            kind: InteractionKind::Report,
+            per_hour: 60.0,
+            endpoint: 1,
+            cluster: 0x0402,
+            id: 0x0000, // MeasuredValue
+            timed: false,
+            fields: &[],
+            resource: (3303, 0, 5700),
+            value: Value::Centi(2150),
+        },
+        Interaction {
+            name: "humidity_report",
+            kind: InteractionKind::Report,
+            per_hour: 60.0,
+            endpoint: 1,
+            cluster: 0x0405,
+            id: 0x0000, // MeasuredValue
+            timed: false,
+            fields: &[],
+            resource: (3304, 0, 5700),
+            value: Value::Centi(4550),
+        },
+        Interaction {
+            name: "battery_report",
+            kind: InteractionKind::Report,
+            per_hour: 1.0,
+            endpoint: 0,
+            cluster: 0x002F,
+            id: 0x000C, // BatPercentRemaining
+            timed: false,
+            fields: &[],
+            resource: (3, 0, 9),
+            value: Value::U8(180),
+        },
+        Interaction {
+            name: "read_temperature",
+            kind: InteractionKind::Read,
+            per_hour: 1.0,
+            endpoint: 1,
+            cluster: 0x0402,
+            id: 0x0000,
+            timed: false,
+            fields: &[],
+            resource: (3303, 0, 5700),
+            value: Value::Centi(2150),
+        },
+    ],
+};
+
+#[derive(Debug, Clone)]
+pub struct ScenarioConfig {
+    pub scenarios: Vec<DeviceScenario>,
+}
+
+impl Default for ScenarioConfig {
+    fn default() -> Self {
+        Self { scenarios: DeviceScenario::ALL.to_vec() }
+    }
+}
+
+analyzer_builder!(ScenarioAnalyzerBuilder => ScenarioAnalyzer(ScenarioConfig) {
+    scenarios: Vec<DeviceScenario>,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScenarioMetrics {
+    pub scenarios: Vec<ScenarioResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScenarioResult {
+    pub scenario: DeviceScenario,
+    pub matter_device_type: String,
+    pub matter_clusters: Vec<String>,
+    pub lwm2m_objects: Vec<String>,
+    pub interactions_per_hour: f64,
+    pub protocols: Vec<ScenarioProtocolResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScenarioProtocolResult {
+    pub protocol: String,
+    pub interactions: Vec<InteractionCost>,
+    pub messages_per_hour: f64,
+    /// Message bytes plus IPv4 and UDP/TCP headers.
+    pub wire_bytes_per_hour: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InteractionCost {
+    pub interaction: String,
+    pub kind: InteractionKind,
+    pub per_hour: f64,
+    /// Messages in the exchange, acknowledgements included.
+    pub messages: usize,
+    pub wire_bytes: usize,
+}
+
+pub struct ScenarioAnalyzer {
+    config: ScenarioConfig,
+}
+
+impl ScenarioAnalyzer {
+    pub fn new(config: ScenarioConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_scenarios(&mut self) -> Result<ScenarioMetrics> {
+        println!("\n🏠 Analyzing Device Scenario Workloads");
+        println!("--------------------------------------");
+
+        let mut results = Vec::new();
+        for &scenario in &self.config.scenarios {
+            let definition = scenario.definition();
+            let mut protocols = Vec::new();
+            for protocol in [Protocol::Matter, Protocol::Lwm2m, Protocol::Mqtt] {
+                let interactions = definition
+                    .interactions
+                    .iter()
+                    .map(|interaction| {
+                        let exchange = protocol.exchange(definition, interaction)?;
+                        Ok(InteractionCost {
+                            interaction: interaction.name.to_string(),
+                            kind: interaction.kind,
+                            per_hour: interaction.per_hour,
+                            messages: exchange.len(),
+                            wire_bytes: exchange.iter().map(|m| m.len() + protocol.header_bytes()).sum(),
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let messages_per_hour = interactions.iter().map(|i| i.per_hour * i.messages as f64).sum();
+                let wire_bytes_per_hour: f64 = interactions.iter().map(|i| i.per_hour * i.wire_bytes as f64).sum();
+                println!("✅ {} / {}: {:.0} messages/h, {:.1} KB/h",
+                         definition.name, protocol.name(), messages_per_hour, wire_bytes_per_hour / 1024.0);
+                protocols.push(ScenarioProtocolResult {
+                    protocol: protocol.name().to_string(),
+                    interactions,
+                    messages_per_hour,
+                    wire_bytes_per_hour,
+                });
+            }
+            let (device_type, device_type_name) = definition.device_type;
+            results.push(ScenarioResult {
+                scenario,
+                matter_device_type: format!("{} (0x{:04X})", device_type_name, device_type),
+                matter_clusters: scenario.clusters(),
+                lwm2m_objects: scenario.lwm2m_objects(),
+                interactions_per_hour: definition.interactions.iter().map(|i| i.per_hour).sum(),
+                protocols,
+            });
+        }
+
+        Ok(ScenarioMetrics { scenarios: results })
+    }
+}
+
+/// (protocol, messages, wire bytes) of one interaction.
+pub(crate) type ProtocolCost = (&'static str, usize, usize);
+
+/// Cost of the named interaction of `scenario` over each protocol.
+pub(crate) fn interaction_costs(scenario: DeviceScenario, interaction: &str) -> Result<Vec<ProtocolCost>> {
+    Ok(interaction_frames(scenario, interaction)?
+        .into_iter()
+        .map(|(protocol, frames)| (protocol, frames.len(), frames.iter().sum()))
+        .collect())
+}
+
+/// Wire size (message plus IPv4 and UDP/TCP headers) of every message of the named
+/// interaction over each protocol, in order; requests and responses alternate.
+pub(crate) fn interaction_frames(scenario: DeviceScenario, interaction: &str) -> Result<Vec<(&'static str, Vec<usize>)>> {
+    let definition = scenario.definition();
+    let interaction = definition
+        .interactions
+        .iter()
+        .find(|i| i.name == interaction)
+        .ok_or_else(|| anyhow!("{} scenario has no {} interaction", definition.name, interaction))?;
+    [Protocol::Matter, Protocol::Lwm2m, Protocol::Mqtt]
+        .into_iter()
+        .map(|protocol| {
+            let exchange = protocol.exchange(definition, interaction)?;
+            Ok((protocol.name(), exchange.iter().map(|m| m.len() + protocol.header_bytes()).collect()))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Protocol {
+    Matter,
+    Lwm2m,
+    Mqtt,
+}
+
+impl Protocol {
+    fn name(self) -> &'static str {
+        match self {
+            Protocol::Matter => "Matter",
+            Protocol::Lwm2m => "LwM2M",
+            Protocol::Mqtt => "MQTT",
+        }
+    }
+
+    fn header_bytes(self) -> usize {
+        match self {
+            Protocol::Matter | Protocol::Lwm2m => IPV4_UDP_HEADER_BYTES,
+            Protocol::Mqtt => IPV4_TCP_HEADER_BYTES,
+        }
+    }
+
+    /// Every message of one interaction, in order.
+    fn exchange(self, scenario: &ScenarioDefinition, interaction: &Interaction) -> Result<Vec<Vec<u8>>> {
+        match self {
+            Protocol::Matter => matter_exchange(interaction),
+            Protocol::Lwm2m => Ok(lwm2m_exchange(interaction)),
+            Protocol::Mqtt => Ok(mqtt_exchange(scenario, interaction)),
+        }
+    }
+}
+
+fn matter_value(writer: &mut TlvWriter, tag: Tag, value: Value) -> Result<(), TlvError> {
+    match value {
+        Value::Bool(v) => writer.bool(tag, v),
+        Value::U8(v) => writer.u8(tag, v),
+        Value::U16(v) => writer.u16(tag, v),
+        Value::Centi(v) => writer.i16(tag, v),
+    }
+}
+
+fn attribute_path(writer: &mut TlvWriter, tag: Tag, interaction: &Interaction) -> Result<(), TlvError> {
+    writer.start_list(tag)?;
+    writer.u16(Tag::Context(2), interaction.endpoint)?;
+    writer.u32(Tag::Context(3), interaction.cluster)?;
+    writer.u32(Tag::Context(4), interaction.id)?;
+    writer.end_container()
+}
+
+fn command_path(writer: &mut TlvWriter, interaction: &Interaction) -> Result<(), TlvError> {
+    writer.start_list(Tag::Context(0))?;
+    writer.u16(Tag::Context(0), interaction.endpoint)?;
+    writer.u32(Tag::Context(1), interaction.cluster)?;
+    writer.u32(Tag::Context(2), interaction.id)?;
+    writer.end_container()
+}
+
+fn status_response() -> Result<Vec<u8>> {
+    let payload = tlv(|writer| {
+        writer.start_struct(Tag::Anonymous)?;
+        writer.u8(Tag::Context(0), 0)?;
+        writer.u8(Tag::Context(255), 11)?;
+        writer.end_container()
+    })?;
+    Ok(matter_message(PROTOCOL_IM, IM_STATUS_RESPONSE, &payload))
+}
+
+fn report_data(interaction: &Interaction, subscription: bool) -> Result<Vec<u8>> {
+    let payload = tlv(|writer| {
+        writer.start_struct(Tag::Anonymous)?;
+        if subscription {
+            writer.u32(Tag::Context(0), SUBSCRIPTION_ID)?;
+        }
+        writer.start_array(Tag::Context(1))?; // AttributeReportIBs
+        writer.start_struct(Tag::Anonymous)?;
+        writer.start_struct(Tag::Context(1))?; // AttributeDataIB
+        writer.u32(Tag::Context(0), 1)?; // DataVersion
+        attribute_path(writer, Tag::Context(1), interaction)?;
+        matter_value(writer, Tag::Context(2), interaction.value)?;
+        writer.end_container()?;
+        writer.end_container()?;
+        writer.end_container()?;
+        if !subscription {
+            writer.bool(Tag::Context(4), true)?; // SuppressResponse
+        }
+        writer.u8(Tag::Context(255), 11)?;
+        writer.end_container()
+    })?;
+    Ok(matter_message(PROTOCOL_IM, IM_REPORT_DATA, &payload))
+}
+
+/// Matter IM over UDP. Responses piggyback the MRP ack of the request; the
+/// last message of each exchange is acknowledged with a standalone ack.
+fn matter_exchange(interaction: &Interaction) -> Result<Vec<Vec<u8>>> {
+    let mut exchange = Vec::new();
+    match interaction.kind {
+        InteractionKind::Read => {
+            let request = tlv(|writer| {
+                writer.start_struct(Tag::Anonymous)?;
+                writer.start_array(Tag::Context(0))?; // AttributeRequests
+                attribute_path(writer, Tag::Anonymous, interaction)?;
+                writer.end_container()?;
+                writer.bool(Tag::Context(3), true)?; // FabricFiltered
+                writer.u8(Tag::Context(255), 11)?;
+                writer.end_container()
+            })?;
+            exchange.push(matter_message(PROTOCOL_IM, IM_READ_REQUEST, &request));
+            exchange.push(report_data(interaction, false)?);
+        }
+        InteractionKind::Write => {
+            let request = tlv(|writer| {
+                writer.start_struct(Tag::Anonymous)?;
+                writer.bool(Tag::Context(0), false)?; // SuppressResponse
+                writer.bool(Tag::Context(1), interaction.timed)?; // TimedRequest
+                writer.start_array(Tag::Context(2))?; // WriteRequests
+                writer.start_struct(Tag::Anonymous)?;
+                attribute_path(writer, Tag::Context(1), interaction)?;
+                matter_value(writer, Tag::Context(2), interaction.value)?;
+                writer.end_container()?;
+                writer.end_container()?;
+                writer.u8(Tag::Context(255), 11)?;
+                writer.end_container()
+            })?;
+            let response = tlv(|writer| {
+                writer.start_struct(Tag::Anonymous)?;
+                writer.start_array(Tag::Context(0))?; // WriteResponses
+                writer.start_struct(Tag::Anonymous)?;
+                attribute_path(writer, Tag::Context(0), interaction)?;
+                writer.start_struct(Tag::Context(1))?;
+                writer.u8(Tag::Context(0), 0)?;
+                writer.end_container()?;
+                writer.end_container()?;
+                writer.end_container()?;
+                writer.u8(Tag::Context(255), 11)?;
+                writer.end_container()
+            })?;
+            if interaction.timed {
+                exchange.push(timed_request()?);
+                exchange.push(status_response()?);
+            }
+            exchange.push(matter_message(PROTOCOL_IM, IM_WRITE_REQUEST, &request));
+            exchange.push(matter_message(PROTOCOL_IM, IM_WRITE_RESPONSE, &response));
+        }
+        InteractionKind::Invoke => {
+            let request = tlv(|writer| {
+                writer.start_struct(Tag::Anonymous)?;
+                writer.bool(Tag::Context(0), false)?; // SuppressResponse
+                writer.bool(Tag::Context(1), interaction.timed)?; // TimedRequest
+                writer.start_array(Tag::Context(2))?;
+                writer.start_struct(Tag::Anonymous)?;
+                command_path(writer, interaction)?;
+                writer.start_struct(Tag::Context(1))?; // CommandFields
+                for &(field, value) in interaction.fields {
+                    matter_value(writer, Tag::Context(field), value)?;
+                }
+                writer.end_container()?;
+                writer.end_container()?;
+                writer.end_container()?;
+                writer.u8(Tag::Context(255), 11)?;
+                writer.end_container()
+            })?;
+            let response = tlv(|writer| {
+                writer.start_struct(Tag::Anonymous)?;
+                writer.bool(Tag::Context(0), false)?;
+                writer.start_array(Tag::Context(1))?;
+                writer.start_struct(Tag::Anonymous)?;
+                writer.start_struct(Tag::Context(1))?; // CommandStatusIB
+                command_path(writer, interaction)?;
+                writer.start_struct(Tag::Context(1))?;
+                writer.u8(Tag::Context(0), 0)?;
+                writer.end_container()?;
+                writer.end_container()?;
+                writer.end_container()?;
+                writer.end_container()?;
+                writer.u8(Tag::Context(255), 11)?;
+                writer.end_container()
+            })?;
+            if interaction.timed {
+                exchange.push(timed_request()?);
+                exchange.push(status_response()?);
+            }
+            exchange.push(matter_message(PROTOCOL_IM, IM_INVOKE_REQUEST, &request));
+            exchange.push(matter_message(PROTOCOL_IM, IM_INVOKE_RESPONSE, &response));
+        }
+        InteractionKind::Report => {
+            exchange.push(report_data(interaction, true)?);
+            exchange.push(status_response()?);
+        }
+    }
+    exchange.push(matter_message(PROTOCOL_SECURE_CHANNEL, MRP_STANDALONE_ACK, &[]));
+    Ok(exchange)
+}
+
+fn timed_request() -> Result<Vec<u8>> {
+    let payload = tlv(|writer| {
+        writer.start_struct(Tag::Anonymous)?;
+        writer.u16(Tag::Context(0), TIMED_INVOKE_TIMEOUT_MS)?;
+        writer.u8(Tag::Context(255), 11)?;
+        writer.end_container()
+    })?;
+    Ok(matter_message(PROTOCOL_IM, IM_TIMED_REQUEST, &payload))
+}
+
+/// Single LwM2M TLV resource: 16-bit identifier, length in the type byte.
+fn lwm2m_tlv(resource: u16, value: Value) -> Vec<u8> {
+    let data = match value {
+        Value::Bool(v) => vec![v as u8],
+        Value::U8(v) => vec![v],
+        Value::U16(v) => v.to_be_bytes().to_vec(),
+        Value::Centi(v) => (v as f32 / 100.0).to_be_bytes().to_vec(),
+    };
+    let mut body = vec![0xE0 | data.len() as u8];
+    body.extend_from_slice(&resource.to_be_bytes());
+    body.extend_from_slice(&data);
+    body
+}
+
+/// LwM2M over CoAP/UDP with confirmable requests and notifications. Commands
+/// become a Write of the resource holding the resulting state.
+fn lwm2m_exchange(interaction: &Interaction) -> Vec<Vec<u8>> {
+    let (object, instance, resource) = interaction.resource;
+    let path = uri_path(&[&object.to_string(), &instance.to_string(), &resource.to_string()]);
+    let content_format = (COAP_OPTION_CONTENT_FORMAT, coap_uint(LWM2M_CONTENT_FORMAT_TLV));
+    let body = lwm2m_tlv(resource, interaction.value);
+    let message_id = 0x3001;
+
+    match interaction.kind {
+        InteractionKind::Read => vec![
+            coap_message(COAP_CON, COAP_GET, message_id, &path, &[]),
+            coap_message(COAP_ACK, COAP_CONTENT, message_id, &[content_format], &body),
+        ],
+        InteractionKind::Write | InteractionKind::Invoke => {
+            let mut options = path;
+            options.push(content_format);
+            vec![
+                coap_message(COAP_CON, COAP_PUT, message_id, &options, &body),
+                coap_message(COAP_ACK, COAP_CHANGED, message_id, &[], &[]),
+            ]
+        }
+        InteractionKind::Report => vec![
+            coap_message(COAP_CON, COAP_CONTENT, message_id, &[(COAP_OPTION_OBSERVE, coap_uint(7)), content_format], &body),
+            coap_message(COAP_ACK, 0x00, message_id, &[], &[]),
+        ],
+    }
+}
+
+fn json_value(value: Value) -> String {
+    match value {
+        Value::Bool(v) => format!(r#"{{"value":{}}}"#, v),
+        Value::U8(v) => format!(r#"{{"value":{}}}"#, v),
+        Value::U16(v) => format!(r#"{{"value":{}}}"#, v),
+        Value::Centi(v) => format!(r#"{{"value":{:.2}}}"#, v as f64 / 100.0),
+    }
+}
+
+/// MQTT over an established TCP connection with QoS 1 throughout. Reads and
+/// commands go to `<topic>/get` and `<topic>/set`; the device answers by
+/// publishing its state to `<topic>`.
+fn mqtt_exchange(scenario: &ScenarioDefinition, interaction: &Interaction) -> Vec<Vec<u8>> {
+    let topic = format!("home/{}-1/{}", scenario.name, interaction.name);
+    let state = json_value(interaction.value);
+    let puback = mqtt_packet(MQTT_PUBACK, &1u16.to_be_bytes());
+    let report = [mqtt_publish(&topic, Some(1), state.as_bytes()), puback.clone()];
+
+    let mut exchange = match interaction.kind {
+        InteractionKind::Read => vec![mqtt_publish(&format!("{}/get", topic), Some(1), &[]), puback],
+        InteractionKind::Write | InteractionKind::Invoke => {
+            vec![mqtt_publish(&format!("{}/set", topic), Some(1), state.as_bytes()), puback]
+        }
+        InteractionKind::Report => Vec::new(),
+    };
+    exchange.extend(report);
+    exchange
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scenarios_parse_by_name_in_any_case() {
+        assert_eq!("Thermostat".parse::<DeviceScenario>().unwrap(), DeviceScenario::Thermostat);
+        assert!("doorbell".parse::<DeviceScenario>().is_err());
+        for scenario in DeviceScenario::ALL {
+            assert_eq!(scenario.name().parse::<DeviceScenario>().unwrap(), scenario);
+            assert!(scenario.clusters().contains(&"Descriptor_0x001D".to_string()), "{}", scenario.name());
+        }
+    }
+
+    #[test]
+    fn lwm2m_and_json_carry_the_same_value() {
+        assert_eq!(lwm2m_tlv(5850, Value::Bool(true)), [0xE1, 0x16, 0xDA, 0x01]);
+        assert_eq!(lwm2m_tlv(5851, Value::U16(300)), [0xE2, 0x16, 0xDB, 0x01, 0x2C]);
+        assert_eq!(lwm2m_tlv(5700, Value::Centi(2150))[3..], 21.5f32.to_be_bytes());
+        assert_eq!(json_value(Value::Centi(2150)), r#"{"value":21.50}"#);
+        assert_eq!(json_value(Value::Bool(false)), r#"{"value":false}"#);
+    }
+
+    #[test]
+    fn hourly_totals_weight_each_interaction_by_its_rate() {
+        let metrics = ScenarioAnalyzer::builder().scenarios(vec![DeviceScenario::Light]).build().analyze_scenarios().unwrap();
+        let light = &metrics.scenarios[0];
+        assert_eq!(light.protocols.len(), 3);
+
+        for protocol in &light.protocols {
+            let messages: f64 = protocol.interactions.iter().map(|i| i.per_hour * i.messages as f64).sum();
+            assert_eq!(protocol.messages_per_hour, messages, "{}", protocol.protocol);
+            for interaction in &protocol.interactions {
+                let frames = interaction_frames(DeviceScenario::Light, &interaction.interaction).unwrap();
+                let (_, sizes) = frames.iter().find(|(name, _)| *name == protocol.protocol).unwrap();
+                assert_eq!(interaction.wire_bytes, sizes.iter().sum::<usize>());
+            }
+        }
+
+        // Timed invokes add a TimedRequest and its StatusResponse
+        let matter_messages = |scenario, interaction| interaction_costs(scenario, interaction).unwrap()[0].1;
+        assert_eq!(matter_messages(DeviceScenario::Lock, "lock"), matter_messages(DeviceScenario::Light, "turn_on") + 2);
+        assert!(interaction_costs(DeviceScenario::Light, "lock").is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use crate::attestation::AttestationChain;
 use std::time::Instant;
 use tokio::time::{sleep, Duration};
 
@@ -32,6 +33,10 @@ impl SessionAnalyzer {
         
         let commissioning_time = start.elapsed().as_millis() as f64;
         
+        // Size of the device attestation certificate a commissionee presents
+        let attestation = AttestationChain::generate()?;
+        attestation.validate()?;
+        
         let metrics = SessionMetrics {
             session_type: "Matter_Commissioning".to_string(),
             commissioning_time_ms: commissioning_time,
@@ -40,7 +45,7 @@ impl SessionAnalyzer {
             session_overhead_bytes: 342,
             session_efficiency: 0.65,
             security_level: "High".to_string(),
-            certificate_size_bytes: 350,
+            certificate_size_bytes: attestation.dac.der.len() as u32,
         };
         
         println!("✅ Total Commissioning Time: {:.2}ms", commissioning_time);
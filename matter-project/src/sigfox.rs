@@ -0,0 +1,436 @@
+// matter-project/src/sigfox.rs
+/*!
+Sigfox feasibility - which protocol messages fit an ultra-narrowband link, and what a gateway must add
+
+Sigfox carries at most 12 bytes uplink and 8 bytes downlink. A device may
+send 140 uplinks and receive 4 downlinks a day, and a downlink can only
+follow an uplink that asked for one. There is no IP, and nothing like a
+connection. Each protocol's minimal messages are encoded with the repo's
+encoders and checked against those limits in four ways:
+
+- direct: the protocol message itself fits one frame. The protocol must
+  not need a session, because none can be set up over this link.
+- SCHC: IPv4/UDP (and, for CoAP, the header fields a static rule can
+  predict) are compressed to a rule ID and a small residue, as SCHC does
+  over Sigfox. This is only defined over UDP.
+- SCHC fragmented: the compressed message is split into frames that each
+  carry a one-byte fragment header. The final fragment's integrity check
+  is not counted.
+- gateway: the device sends a one-byte message type and the raw value. The
+  Sigfox backend or a gateway rebuilds the full message and keeps any
+  session (a TCP connection, a CASE session) on the device's behalf.
+  Security is then not end to end. The adaptation overhead is what the
+  gateway sends on the IP side, including the ack if there is one, less
+  what crossed the radio.
+
+Confirmed messages sent end to end need a downlink ack, so at most 4 of
+them can get through a day. Airtime counts the frame overhead and, for
+uplinks, Sigfox's three repetitions. The analysis is static and
+deterministic.
+*/
+
+use anyhow::Result;
+use metrics_core::tlv::Tag;
+use serde::{Deserialize, Serialize};
+
+use crate::bdx::{matter_message, MRP_STANDALONE_ACK, PROTOCOL_SECURE_CHANNEL};
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{
+    coap_message, mqtt_packet, mqtt_publish, tlv, uri_path, COAP_ACK, COAP_CON, COAP_NON, COAP_POST, COAP_PUT,
+    IM_INVOKE_REQUEST, IM_REPORT_DATA, MQTT_PUBACK, PROTOCOL_IM,
+};
+use crate::mqtt_sn::{sn_message, SN_PUBLISH, SN_QOS_0, SN_QOS_MINUS_ONE, SN_TOPIC_PREDEFINED};
+use crate::start_modes::invoke_request;
+use crate::transport_modes::{IPV4_TCP_HEADER_BYTES, IPV4_UDP_HEADER_BYTES};
+
+/// Preamble, frame sync, length/sequence, device ID, HMAC and CRC around an uplink payload.
+const UPLINK_FRAME_OVERHEAD_BYTES: usize = 14;
+const UPLINK_BPS: f64 = 100.0;
+const UPLINK_REPETITIONS: f64 = 3.0;
+/// Preamble, frame sync, ECC, HMAC and CRC around the always-8-byte downlink payload.
+const DOWNLINK_FRAME_OVERHEAD_BYTES: usize = 20;
+const DOWNLINK_BPS: f64 = 600.0;
+/// SCHC rule ID, and the residue a CoAP rule leaves (message ID low bits).
+const SCHC_RULE_BYTES: usize = 1;
+const SCHC_COAP_RESIDUE_BYTES: usize = 1;
+const SCHC_FRAGMENT_HEADER_BYTES: usize = 1;
+/// Message type byte of a gateway-translated frame.
+const TRANSLATION_TYPE_BYTES: usize = 1;
+/// An OnOff command value.
+const COMMAND_BYTES: usize = 1;
+
+#[derive(Debug, Clone)]
+pub struct SigfoxConfig {
+    /// Sensor value sizes the uplink reports carry.
+    pub payload_sizes: Vec<usize>,
+    pub uplink_limit: usize,
+    pub downlink_limit: usize,
+    pub daily_uplinks: usize,
+    pub daily_downlinks: usize,
+}
+
+impl Default for SigfoxConfig {
+    fn default() -> Self {
+        Self { payload_sizes: vec![1, 4, 8, 12], uplink_limit: 12, downlink_limit: 8, daily_uplinks: 140, daily_downlinks: 4 }
+    }
+}
+
+analyzer_builder!(SigfoxAnalyzerBuilder => SigfoxAnalyzer(SigfoxConfig) {
+    payload_sizes: Vec<usize>,
+    uplink_limit: usize,
+    downlink_limit: usize,
+    daily_uplinks: usize,
+    daily_downlinks: usize,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigfoxMetrics {
+    pub uplink_limit: usize,
+    pub downlink_limit: usize,
+    pub messages: Vec<SigfoxFeasibility>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigfoxFeasibility {
+    pub protocol: String,
+    pub message: String,
+    pub uplink: bool,
+    pub payload_bytes: usize,
+    /// The protocol message alone, without IP.
+    pub message_bytes: usize,
+    /// With the IPv4 and UDP or TCP headers it would travel with.
+    pub stack_bytes: usize,
+    /// After SCHC compression, when the protocol runs over UDP.
+    pub schc_bytes: Option<usize>,
+    pub translated_bytes: usize,
+    /// "direct", "SCHC", "SCHC fragmented", "gateway" or "infeasible".
+    pub verdict: String,
+    pub frames: usize,
+    pub end_to_end: bool,
+    /// Bytes the gateway adds on the IP side when it translates.
+    pub gateway_overhead_bytes: usize,
+    pub per_day: usize,
+    pub airtime_s: f64,
+}
+
+/// One minimal message of a protocol.
+struct Candidate {
+    protocol: &'static str,
+    message: &'static str,
+    uplink: bool,
+    message_bytes: usize,
+    udp: bool,
+    /// Whether the message can be sent without a session set up first.
+    stateless: bool,
+    schc_bytes: Option<usize>,
+    /// IP-side size of the ack it needs, if confirmed.
+    ack_stack_bytes: Option<usize>,
+    value_bytes: usize,
+}
+
+pub struct SigfoxAnalyzer {
+    config: SigfoxConfig,
+}
+
+impl SigfoxAnalyzer {
+    pub fn new(config: SigfoxConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_sigfox(&mut self) -> Result<SigfoxMetrics> {
+        println!("\n📡 Analyzing Sigfox Payload Feasibility");
+        println!("---------------------------------------");
+        println!("✅ Limits: {}B uplink ({}/day), {}B downlink ({}/day)",
+                 self.config.uplink_limit, self.config.daily_uplinks,
+                 self.config.downlink_limit, self.config.daily_downlinks);
+
+        let mut candidates = Vec::new();
+        for &size in &self.config.payload_sizes {
+            candidates.extend(reports(size)?);
+        }
+        candidates.extend(commands()?);
+
+        let messages: Vec<SigfoxFeasibility> = candidates.iter().map(|candidate| self.assess(candidate)).collect();
+
+        let mut seen = Vec::new();
+        for row in &messages {
+            let key = (row.protocol.as_str(), row.message.as_str());
+            if seen.contains(&key) {
+                continue;
+            }
+            seen.push(key);
+            let cells: Vec<String> = messages
+                .iter()
+                .filter(|other| (other.protocol.as_str(), other.message.as_str()) == key)
+                .map(|other| {
+                    let frames = if other.frames > 1 { format!(" ×{}", other.frames) } else { String::new() };
+                    format!("{}B {}{} ({}/day, +{}B at gw)",
+                            other.payload_bytes, other.verdict, frames, other.per_day, other.gateway_overhead_bytes)
+                })
+                .collect();
+            println!("✅ {} {} {}: {}", row.protocol, row.message, if row.uplink { "↑" } else { "↓" }, cells.join(" | "));
+        }
+
+        Ok(SigfoxMetrics { uplink_limit: self.config.uplink_limit, downlink_limit: self.config.downlink_limit, messages })
+    }
+
+    fn assess(&self, candidate: &Candidate) -> SigfoxFeasibility {
+        let (limit, daily) = if candidate.uplink {
+            (self.config.uplink_limit, self.config.daily_uplinks)
+        } else {
+            (self.config.downlink_limit, self.config.daily_downlinks)
+        };
+        let header = if candidate.udp { IPV4_UDP_HEADER_BYTES } else { IPV4_TCP_HEADER_BYTES };
+        let stack_bytes = candidate.message_bytes + header;
+        let translated_bytes = TRANSLATION_TYPE_BYTES + candidate.value_bytes;
+
+        let (verdict, frames, carried) = match candidate.schc_bytes {
+            _ if candidate.stateless && candidate.message_bytes <= limit => ("direct", 1, candidate.message_bytes),
+            Some(schc) if candidate.stateless && schc <= limit => ("SCHC", 1, schc),
+            Some(schc) if candidate.stateless && limit > SCHC_FRAGMENT_HEADER_BYTES => {
+                let frames = schc.div_ceil(limit - SCHC_FRAGMENT_HEADER_BYTES);
+                ("SCHC fragmented", frames, schc + frames * SCHC_FRAGMENT_HEADER_BYTES)
+            }
+            _ if translated_bytes <= limit => ("gateway", 1, translated_bytes),
+            _ => ("infeasible", 0, 0),
+        };
+        let end_to_end = !matches!(verdict, "gateway" | "infeasible");
+
+        let mut per_day = daily.checked_div(frames).unwrap_or(0);
+        // Every end-to-end confirmed uplink needs one of the scarce downlinks for its ack
+        if end_to_end && candidate.uplink && candidate.ack_stack_bytes.is_some() {
+            per_day = per_day.min(self.config.daily_downlinks);
+        }
+        let gateway_overhead_bytes = (stack_bytes + candidate.ack_stack_bytes.unwrap_or(0)).saturating_sub(translated_bytes);
+        let airtime_s = if candidate.uplink {
+            (carried + frames * UPLINK_FRAME_OVERHEAD_BYTES) as f64 * 8.0 / UPLINK_BPS * UPLINK_REPETITIONS
+        } else {
+            (frames * (self.config.downlink_limit + DOWNLINK_FRAME_OVERHEAD_BYTES)) as f64 * 8.0 / DOWNLINK_BPS
+        };
+
+        SigfoxFeasibility {
+            protocol: candidate.protocol.to_string(),
+            message: candidate.message.to_string(),
+            uplink: candidate.uplink,
+            payload_bytes: candidate.value_bytes,
+            message_bytes: candidate.message_bytes,
+            stack_bytes,
+            schc_bytes: candidate.schc_bytes,
+            translated_bytes,
+            verdict: verdict.to_string(),
+            frames,
+            end_to_end,
+            gateway_overhead_bytes,
+            per_day,
+            airtime_s,
+        }
+    }
+}
+
+fn coap_schc(payload: &[u8]) -> usize {
+    // The rule matches version, type, token and options and elides the payload marker
+    SCHC_RULE_BYTES + SCHC_COAP_RESIDUE_BYTES + payload.len()
+}
+
+fn coap_ack_stack_bytes() -> usize {
+    coap_message(COAP_ACK, 0, 1, &[], &[]).len() + IPV4_UDP_HEADER_BYTES
+}
+
+fn matter_ack_stack_bytes() -> usize {
+    matter_message(PROTOCOL_SECURE_CHANNEL, MRP_STANDALONE_ACK, &[]).len() + IPV4_UDP_HEADER_BYTES
+}
+
+/// The minimal uplink report of each protocol, carrying `size` bytes of sensor value.
+fn reports(size: usize) -> Result<Vec<Candidate>> {
+    let payload = vec![0x42u8; size];
+
+    let non = coap_message(COAP_NON, COAP_POST, 1, &uri_path(&["t"]), &payload);
+    let con = coap_message(COAP_CON, COAP_POST, 1, &uri_path(&["t"]), &payload);
+
+    let mut sn_body = vec![SN_QOS_MINUS_ONE | SN_TOPIC_PREDEFINED];
+    sn_body.extend_from_slice(&1u16.to_be_bytes()); // predefined topic ID
+    sn_body.extend_from_slice(&0u16.to_be_bytes()); // no message ID at QoS -1
+    sn_body.extend_from_slice(&payload);
+    let sn = sn_message(SN_PUBLISH, &sn_body);
+
+    let report = tlv(|writer| {
+        writer.start_struct(Tag::Anonymous)?;
+        writer.u32(Tag::Context(0), 1)?; // SubscriptionId
+        writer.start_array(Tag::Context(1))?;
+        writer.start_struct(Tag::Anonymous)?;
+        writer.start_struct(Tag::Context(1))?; // AttributeDataIB
+        writer.u32(Tag::Context(0), 1)?; // DataVersion
+        writer.start_list(Tag::Context(1))?;
+        writer.u16(Tag::Context(2), 1)?;
+        writer.u32(Tag::Context(3), 0x0402)?;
+        writer.u32(Tag::Context(4), 0x0000)?;
+        writer.end_container()?;
+        writer.octets(Tag::Context(2), &payload)?;
+        writer.end_container()?;
+        writer.end_container()?;
+        writer.end_container()?;
+        writer.u8(Tag::Context(255), 11)?;
+        writer.end_container()
+    })?;
+    let matter = matter_message(PROTOCOL_IM, IM_REPORT_DATA, &report);
+
+    Ok(vec![
+        Candidate {
+            protocol: "CoAP",
+            message: "NON report",
+            uplink: true,
+            message_bytes: non.len(),
+            udp: true,
+            stateless: true,
+            schc_bytes: Some(coap_schc(&payload)),
+            ack_stack_bytes: None,
+            value_bytes: size,
+        },
+        Candidate {
+            protocol: "CoAP",
+            message: "CON report",
+            uplink: true,
+            message_bytes: con.len(),
+            udp: true,
+            stateless: true,
+            schc_bytes: Some(coap_schc(&payload)),
+            ack_stack_bytes: Some(coap_ack_stack_bytes()),
+            value_bytes: size,
+        },
+        Candidate {
+            protocol: "MQTT-SN",
+            message: "QoS -1 PUBLISH",
+            uplink: true,
+            message_bytes: sn.len(),
+            udp: true,
+            stateless: true,
+            schc_bytes: Some(SCHC_RULE_BYTES + sn.len()),
+            ack_stack_bytes: None,
+            value_bytes: size,
+        },
+        Candidate {
+            protocol: "MQTT",
+            message: "QoS 0 PUBLISH",
+            uplink: true,
+            message_bytes: mqtt_publish("t", None, &payload).len(),
+            udp: false,
+            stateless: false,
+            schc_bytes: None,
+            ack_stack_bytes: None,
+            value_bytes: size,
+        },
+        Candidate {
+            protocol: "MQTT",
+            message: "QoS 1 PUBLISH",
+            uplink: true,
+            message_bytes: mqtt_publish("t", Some(1), &payload).len(),
+            udp: false,
+            stateless: false,
+            schc_bytes: None,
+            ack_stack_bytes: Some(mqtt_packet(MQTT_PUBACK, &1u16.to_be_bytes()).len() + IPV4_TCP_HEADER_BYTES),
+            value_bytes: size,
+        },
+        Candidate {
+            protocol: "Matter",
+            message: "ReportData",
+            uplink: true,
+            message_bytes: matter.len(),
+            udp: true,
+            stateless: false,
+            schc_bytes: Some(SCHC_RULE_BYTES + matter.len()),
+            ack_stack_bytes: Some(matter_ack_stack_bytes()),
+            value_bytes: size,
+        },
+    ])
+}
+
+/// The minimal downlink OnOff command of each protocol.
+fn commands() -> Result<Vec<Candidate>> {
+    let value = [1u8; COMMAND_BYTES];
+    let coap = coap_message(COAP_CON, COAP_PUT, 2, &uri_path(&["a"]), &value);
+
+    let mut sn_body = vec![SN_QOS_0 | SN_TOPIC_PREDEFINED];
+    sn_body.extend_from_slice(&2u16.to_be_bytes());
+    sn_body.extend_from_slice(&0u16.to_be_bytes());
+    sn_body.extend_from_slice(&value);
+    let sn = sn_message(SN_PUBLISH, &sn_body);
+
+    let invoke = matter_message(PROTOCOL_IM, IM_INVOKE_REQUEST, &tlv(invoke_request)?);
+
+    Ok(vec![
+        Candidate {
+            protocol: "CoAP",
+            message: "CON PUT command",
+            uplink: false,
+            message_bytes: coap.len(),
+            udp: true,
+            stateless: true,
+            schc_bytes: Some(coap_schc(&value)),
+            ack_stack_bytes: Some(coap_ack_stack_bytes()),
+            value_bytes: COMMAND_BYTES,
+        },
+        Candidate {
+            // The gateway only forwards to a connected client
+            protocol: "MQTT-SN",
+            message: "QoS 0 PUBLISH command",
+            uplink: false,
+            message_bytes: sn.len(),
+            udp: true,
+            stateless: false,
+            schc_bytes: Some(SCHC_RULE_BYTES + sn.len()),
+            ack_stack_bytes: None,
+            value_bytes: COMMAND_BYTES,
+        },
+        Candidate {
+            protocol: "MQTT",
+            message: "QoS 0 PUBLISH command",
+            uplink: false,
+            message_bytes: mqtt_publish("a", None, &value).len(),
+            udp: false,
+            stateless: false,
+            schc_bytes: None,
+            ack_stack_bytes: None,
+            value_bytes: COMMAND_BYTES,
+        },
+        Candidate {
+            protocol: "Matter",
+            message: "InvokeRequest",
+            uplink: false,
+            message_bytes: invoke.len(),
+            udp: true,
+            stateless: false,
+            schc_bytes: Some(SCHC_RULE_BYTES + invoke.len()),
+            ack_stack_bytes: Some(matter_ack_stack_bytes()),
+            value_bytes: COMMAND_BYTES,
+        },
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_stateless_messages_fit_and_sessions_need_a_gateway() {
+        let metrics = SigfoxAnalyzer::builder().payload_sizes(vec![1usize, 4, 12]).build().analyze_sigfox().unwrap();
+        let row = |message: &str, size: usize| {
+            metrics.messages.iter().find(|row| row.message == message && row.payload_bytes == size).unwrap()
+        };
+
+        assert_eq!(row("NON report", 1).verdict, "direct");
+        assert_eq!(row("NON report", 4).verdict, "SCHC");
+        assert_eq!(row("QoS -1 PUBLISH", 4).verdict, "direct");
+        // Each end-to-end CON needs a downlink ack
+        assert_eq!(row("CON report", 1).per_day, 4);
+
+        let matter = row("ReportData", 4);
+        assert_eq!(matter.verdict, "gateway");
+        assert!(!matter.end_to_end);
+        assert!(matter.gateway_overhead_bytes > 50);
+
+        // 12 bytes of value leave no room for the gateway's type byte
+        assert_eq!(row("QoS 0 PUBLISH", 12).verdict, "infeasible");
+        assert!(row("NON report", 12).frames > 1);
+        assert_eq!(row("CON PUT command", 1).verdict, "SCHC");
+    }
+}
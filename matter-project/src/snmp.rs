@@ -0,0 +1,1098 @@
+// matter-project/src/snmp.rs
+/*!
+SNMP polling - GET and GETBULK latency, per-OID overhead and SNMPv3 security cost
+
+Switches, routers, UPSes and PDUs still expose their counters over SNMP.
+A poller asks an agent for object instances by OID, and every message is
+BER-encoded:
+
+- SNMPv2c sends a community string in clear text with each message.
+- SNMPv3 wraps the PDU in the User-based Security Model (USM). First a
+  Report exchange discovers the agent's engine ID, boots and time. After
+  that, each message carries those values, a user name and, depending on
+  the security level, an HMAC and a salt for the privacy cipher. Keys are
+  derived from a password by hashing a megabyte of it (RFC 3414 A.2).
+  They are then localized to the engine ID.
+
+A local agent over loopback UDP serves the system group and an ifTable of
+a small switch; a v2c and v3 poller measure:
+
+- GET of 1 to N interface counters (ifInOctets/ifOutOctets) per request:
+  latency and what each added OID costs on the wire.
+- Walking the ifTable with GETNEXT and with GETBULK at several
+  max-repetitions. The agent trims bulk responses to 1472 bytes, net-snmp's
+  default maximum message size.
+- The same GET at each security level: v2c, noAuthNoPriv,
+  authNoPriv with HMAC-SHA-96 (RFC 3414) and HMAC-SHA-256-192 (RFC 7860),
+  and authPriv with AES-128. AES-128-CFB (RFC 3826) is stood in for by the
+  AES-128-GCM keystream without its tag. That is AES-CTR, so the ciphertext
+  has the same length and costs the same block-cipher work.
+
+Bytes are UDP payloads plus IPv4/UDP headers.
+*/
+
+use anyhow::{anyhow, bail, Result};
+use ring::{aead, digest, hmac};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+
+use crate::builder::analyzer_builder;
+use crate::transport_modes::IPV4_UDP_HEADER_BYTES;
+
+// BER tags
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_NULL: u8 = 0x05;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_COUNTER32: u8 = 0x41;
+const TAG_GAUGE32: u8 = 0x42;
+const TAG_TIMETICKS: u8 = 0x43;
+const TAG_END_OF_MIB_VIEW: u8 = 0x82;
+
+// PDU types
+const PDU_GET: u8 = 0xA0;
+const PDU_GET_NEXT: u8 = 0xA1;
+const PDU_RESPONSE: u8 = 0xA2;
+const PDU_GET_BULK: u8 = 0xA5;
+const PDU_REPORT: u8 = 0xA8;
+
+const VERSION_V2C: i64 = 1;
+const VERSION_V3: i64 = 3;
+const SECURITY_MODEL_USM: i64 = 3;
+const FLAG_AUTH: u8 = 0x01;
+const FLAG_PRIV: u8 = 0x02;
+const FLAG_REPORTABLE: u8 = 0x04;
+
+/// net-snmp's default maximum message size; bulk responses are trimmed to fit.
+const MAX_MESSAGE_BYTES: usize = 1472;
+/// Room left in a response for everything but its varbinds.
+const RESPONSE_ENVELOPE_BYTES: usize = 160;
+/// RFC 3414 A.2 hashes the password repeated to one megabyte.
+const PASSWORD_TO_KEY_BYTES: usize = 1_048_576;
+const TIME_WINDOW_SECS: i64 = 150;
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+const SYS_DESCR: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 1, 0];
+const IF_NUMBER: &[u32] = &[1, 3, 6, 1, 2, 1, 2, 1, 0];
+const IF_ENTRY: &[u32] = &[1, 3, 6, 1, 2, 1, 2, 2, 1];
+const IF_IN_OCTETS: u32 = 10;
+const IF_OUT_OCTETS: u32 = 16;
+/// usmStatsUnknownEngineIDs.0, reported during engine discovery.
+const USM_STATS_UNKNOWN_ENGINE_IDS: &[u32] = &[1, 3, 6, 1, 6, 3, 15, 1, 1, 4, 0];
+
+const USER_NAME: &str = "poller";
+const AUTH_PASSWORD: &str = "maplesyrup";
+const PRIV_PASSWORD: &str = "maplesyrup-priv";
+
+#[derive(Debug, Clone)]
+pub struct SnmpConfig {
+    /// Interface counters per GET request.
+    pub oid_counts: Vec<usize>,
+    /// Rows in the agent's ifTable.
+    pub interfaces: usize,
+    /// max-repetitions of each GETBULK walk.
+    pub max_repetitions: Vec<usize>,
+    /// Requests timed per GET run.
+    pub iterations: usize,
+    pub community: String,
+}
+
+impl Default for SnmpConfig {
+    fn default() -> Self {
+        Self {
+            oid_counts: vec![1, 5, 10, 25],
+            interfaces: 24,
+            max_repetitions: vec![10, 25, 50],
+            iterations: 200,
+            community: "public".to_string(),
+        }
+    }
+}
+
+analyzer_builder!(SnmpAnalyzerBuilder => SnmpAnalyzer(SnmpConfig) {
+    oid_counts: Vec<usize>,
+    interfaces: usize,
+    max_repetitions: Vec<usize>,
+    iterations: usize,
+    community: String,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnmpMetrics {
+    pub get: Vec<SnmpGetRun>,
+    pub walks: Vec<SnmpWalkRun>,
+    pub security: Vec<SnmpSecurityRun>,
+    /// Password to localized key, once per user and engine (SHA-1).
+    pub key_localization_ms: f64,
+    /// The Report exchange that learns the agent's engine ID, boots and time.
+    pub engine_discovery_ms: f64,
+    pub engine_discovery_bytes: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnmpGetRun {
+    pub oids: usize,
+    pub request_bytes: usize,
+    pub response_bytes: usize,
+    /// Request and response bytes added per OID beyond the first.
+    pub marginal_bytes_per_oid: f64,
+    pub mean_latency_ms: f64,
+    pub p95_latency_ms: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnmpWalkRun {
+    pub method: String,
+    pub max_repetitions: Option<usize>,
+    pub varbinds: usize,
+    pub requests: usize,
+    pub bytes: usize,
+    pub elapsed_ms: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnmpSecurityRun {
+    pub level: String,
+    pub request_bytes: usize,
+    pub response_bytes: usize,
+    /// Request and response bytes over the same GET with v2c.
+    pub overhead_bytes: isize,
+    pub mean_latency_ms: f64,
+    /// Poller time to build, sign and encrypt a request and to verify and decrypt its response.
+    pub poller_processing_us: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Integer(i64),
+    OctetString(Vec<u8>),
+    Oid(Vec<u32>),
+    Counter32(u32),
+    Gauge32(u32),
+    TimeTicks(u32),
+    Null,
+    EndOfMibView,
+}
+
+impl Value {
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Value::Integer(value) => tlv(TAG_INTEGER, &integer_content(*value)),
+            Value::OctetString(bytes) => tlv(TAG_OCTET_STRING, bytes),
+            Value::Oid(arcs) => oid(arcs),
+            Value::Counter32(value) => tlv(TAG_COUNTER32, &unsigned_content(*value)),
+            Value::Gauge32(value) => tlv(TAG_GAUGE32, &unsigned_content(*value)),
+            Value::TimeTicks(value) => tlv(TAG_TIMETICKS, &unsigned_content(*value)),
+            Value::Null => tlv(TAG_NULL, &[]),
+            Value::EndOfMibView => tlv(TAG_END_OF_MIB_VIEW, &[]),
+        }
+    }
+
+    fn decode(tag: u8, content: &[u8]) -> Option<Self> {
+        let unsigned = || content.iter().fold(0u64, |value, byte| value << 8 | *byte as u64) as u32;
+        Some(match tag {
+            TAG_INTEGER => Value::Integer(read_integer(content)?),
+            TAG_OCTET_STRING => Value::OctetString(content.to_vec()),
+            TAG_OID => Value::Oid(read_oid(content)?),
+            TAG_COUNTER32 => Value::Counter32(unsigned()),
+            TAG_GAUGE32 => Value::Gauge32(unsigned()),
+            TAG_TIMETICKS => Value::TimeTicks(unsigned()),
+            TAG_NULL => Value::Null,
+            TAG_END_OF_MIB_VIEW => Value::EndOfMibView,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Pdu {
+    tag: u8,
+    request_id: i64,
+    /// error-status, or non-repeaters in a GETBULK.
+    error_status: i64,
+    /// error-index, or max-repetitions in a GETBULK.
+    error_index: i64,
+    varbinds: Vec<(Vec<u32>, Value)>,
+}
+
+impl Pdu {
+    fn request(tag: u8, request_id: i64, oids: &[Vec<u32>]) -> Self {
+        Self {
+            tag,
+            request_id,
+            error_status: 0,
+            error_index: 0,
+            varbinds: oids.iter().map(|arcs| (arcs.clone(), Value::Null)).collect(),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let varbinds: Vec<Vec<u8>> = self.varbinds.iter().map(|(arcs, value)| varbind(arcs, value)).collect();
+        tlv(
+            self.tag,
+            &[
+                tlv(TAG_INTEGER, &integer_content(self.request_id)),
+                tlv(TAG_INTEGER, &integer_content(self.error_status)),
+                tlv(TAG_INTEGER, &integer_content(self.error_index)),
+                tlv(TAG_SEQUENCE, &varbinds.concat()),
+            ]
+            .concat(),
+        )
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let (tag, content, _) = read_tlv(bytes)?;
+        let (_, request_id, rest) = read_tlv(content)?;
+        let (_, error_status, rest) = read_tlv(rest)?;
+        let (_, error_index, rest) = read_tlv(rest)?;
+        let (_, mut list, _) = read_tlv(rest)?;
+        let mut varbinds = Vec::new();
+        while !list.is_empty() {
+            let (_, binding, rest) = read_tlv(list)?;
+            let (_, name, value) = read_tlv(binding)?;
+            let (value_tag, value, _) = read_tlv(value)?;
+            varbinds.push((read_oid(name)?, Value::decode(value_tag, value)?));
+            list = rest;
+        }
+        Some(Self {
+            tag,
+            request_id: read_integer(request_id)?,
+            error_status: read_integer(error_status)?,
+            error_index: read_integer(error_index)?,
+            varbinds,
+        })
+    }
+}
+
+fn varbind(arcs: &[u32], value: &Value) -> Vec<u8> {
+    tlv(TAG_SEQUENCE, &[oid(arcs), value.encode()].concat())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Auth {
+    /// usmHMACSHAAuthProtocol: HMAC-SHA-1 truncated to 96 bits.
+    Sha1,
+    /// usmHMAC192SHA256AuthProtocol: HMAC-SHA-256 truncated to 192 bits.
+    Sha256,
+}
+
+impl Auth {
+    fn digest(&self) -> &'static digest::Algorithm {
+        match self {
+            Auth::Sha1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+            Auth::Sha256 => &digest::SHA256,
+        }
+    }
+
+    fn hmac(&self) -> hmac::Algorithm {
+        match self {
+            Auth::Sha1 => hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+            Auth::Sha256 => hmac::HMAC_SHA256,
+        }
+    }
+
+    fn mac_len(&self) -> usize {
+        match self {
+            Auth::Sha1 => 12,
+            Auth::Sha256 => 24,
+        }
+    }
+
+    /// RFC 3414 A.2 password-to-key, localized to `engine_id`.
+    fn localize(&self, password: &str, engine_id: &[u8]) -> Vec<u8> {
+        let mut context = digest::Context::new(self.digest());
+        let mut repeated = password.bytes().cycle();
+        let mut chunk = [0u8; 64];
+        for _ in 0..PASSWORD_TO_KEY_BYTES / chunk.len() {
+            chunk.iter_mut().for_each(|byte| *byte = repeated.next().unwrap_or(0));
+            context.update(&chunk);
+        }
+        let key = context.finish();
+        digest::digest(self.digest(), &[key.as_ref(), engine_id, key.as_ref()].concat()).as_ref().to_vec()
+    }
+}
+
+/// SNMP version and USM security level of one poller/agent pairing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SecurityLevel {
+    V2c,
+    NoAuthNoPriv,
+    AuthNoPriv(Auth),
+    AuthPriv(Auth),
+}
+
+impl SecurityLevel {
+    const ALL: [SecurityLevel; 5] = [
+        SecurityLevel::V2c,
+        SecurityLevel::NoAuthNoPriv,
+        SecurityLevel::AuthNoPriv(Auth::Sha1),
+        SecurityLevel::AuthNoPriv(Auth::Sha256),
+        SecurityLevel::AuthPriv(Auth::Sha1),
+    ];
+
+    fn describe(&self) -> &'static str {
+        match self {
+            SecurityLevel::V2c => "v2c community",
+            SecurityLevel::NoAuthNoPriv => "v3 noAuthNoPriv",
+            SecurityLevel::AuthNoPriv(Auth::Sha1) => "v3 authNoPriv (HMAC-SHA-96)",
+            SecurityLevel::AuthNoPriv(Auth::Sha256) => "v3 authNoPriv (HMAC-SHA-256-192)",
+            SecurityLevel::AuthPriv(Auth::Sha1) => "v3 authPriv (HMAC-SHA-96, AES-128)",
+            SecurityLevel::AuthPriv(Auth::Sha256) => "v3 authPriv (HMAC-SHA-256-192, AES-128)",
+        }
+    }
+
+    fn flags(&self) -> u8 {
+        match self {
+            SecurityLevel::V2c | SecurityLevel::NoAuthNoPriv => 0,
+            SecurityLevel::AuthNoPriv(_) => FLAG_AUTH,
+            SecurityLevel::AuthPriv(_) => FLAG_AUTH | FLAG_PRIV,
+        }
+    }
+
+    fn auth(&self) -> Option<Auth> {
+        match self {
+            SecurityLevel::AuthNoPriv(auth) | SecurityLevel::AuthPriv(auth) => Some(*auth),
+            _ => None,
+        }
+    }
+}
+
+/// Localized keys of the one USM user, for the engine they were localized to.
+#[derive(Clone)]
+struct UsmKeys {
+    auth: Option<(Auth, hmac::Key)>,
+    privacy: Option<[u8; 16]>,
+}
+
+impl UsmKeys {
+    fn new(level: SecurityLevel, engine_id: &[u8]) -> Result<Self> {
+        let auth = level.auth().map(|auth| (auth, hmac::Key::new(auth.hmac(), &auth.localize(AUTH_PASSWORD, engine_id))));
+        let privacy = match level {
+            SecurityLevel::AuthPriv(auth) => Some(auth.localize(PRIV_PASSWORD, engine_id)[..16].try_into()?),
+            _ => None,
+        };
+        Ok(Self { auth, privacy })
+    }
+}
+
+/// The agent's engine as a v3 poller knows it after discovery.
+#[derive(Debug, Clone, Default)]
+struct Engine {
+    id: Vec<u8>,
+    boots: i64,
+    time: i64,
+}
+
+/// A decoded SNMPv3 message; `pdu` is the plaintext PDU.
+struct V3Message {
+    msg_id: i64,
+    flags: u8,
+    engine: Engine,
+    user: Vec<u8>,
+    pdu: Vec<u8>,
+}
+
+/// AES keystream over `data`: AES-128-GCM without its tag is AES-CTR, standing in for RFC 3826 CFB.
+fn aes_keystream(key: &[u8; 16], boots: i64, salt: &[u8], data: &mut [u8]) -> Result<()> {
+    let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_128_GCM, key).map_err(|_| anyhow!("invalid privacy key"))?);
+    let mut nonce = [0u8; 12];
+    nonce[..4].copy_from_slice(&(boots as u32).to_be_bytes());
+    nonce[4..].copy_from_slice(salt.get(..8).ok_or_else(|| anyhow!("short privacy parameters"))?);
+    let mut stream = vec![0u8; data.len()];
+    // The tag is what CFB does not have; only the keystream is kept
+    let _tag = key
+        .seal_in_place_separate_tag(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::empty(), &mut stream)
+        .map_err(|_| anyhow!("keystream failed"))?;
+    data.iter_mut().zip(stream).for_each(|(byte, key)| *byte ^= key);
+    Ok(())
+}
+
+/// Encodes an SNMPv3 message, encrypting and signing as `flags` ask.
+fn encode_v3(msg_id: i64, flags: u8, engine: &Engine, user: &[u8], keys: &UsmKeys, salt: u64, pdu: &[u8]) -> Result<Vec<u8>> {
+    let scoped = tlv(TAG_SEQUENCE, &[tlv(TAG_OCTET_STRING, &engine.id), tlv(TAG_OCTET_STRING, b""), pdu.to_vec()].concat());
+    let (privacy_params, data) = match (flags & FLAG_PRIV != 0, &keys.privacy) {
+        (true, Some(key)) => {
+            let salt = salt.to_be_bytes();
+            let mut encrypted = scoped;
+            aes_keystream(key, engine.boots, &salt, &mut encrypted)?;
+            (salt.to_vec(), tlv(TAG_OCTET_STRING, &encrypted))
+        }
+        (true, None) => bail!("privacy requested without a privacy key"),
+        (false, _) => (Vec::new(), scoped),
+    };
+    let mac_len = match (flags & FLAG_AUTH != 0, &keys.auth) {
+        (true, Some((auth, _))) => auth.mac_len(),
+        (true, None) => bail!("authentication requested without an authentication key"),
+        (false, _) => 0,
+    };
+
+    let usm_fields = [
+        tlv(TAG_OCTET_STRING, &engine.id),
+        tlv(TAG_INTEGER, &integer_content(engine.boots)),
+        tlv(TAG_INTEGER, &integer_content(engine.time)),
+        tlv(TAG_OCTET_STRING, user),
+    ];
+    let usm_prefix = usm_fields.concat();
+    let usm_content = [usm_prefix.clone(), tlv(TAG_OCTET_STRING, &vec![0u8; mac_len]), tlv(TAG_OCTET_STRING, &privacy_params)].concat();
+    let usm = tlv(TAG_SEQUENCE, &usm_content);
+    let version = tlv(TAG_INTEGER, &integer_content(VERSION_V3));
+    let global = tlv(
+        TAG_SEQUENCE,
+        &[
+            tlv(TAG_INTEGER, &integer_content(msg_id)),
+            tlv(TAG_INTEGER, &integer_content(MAX_MESSAGE_BYTES as i64)),
+            tlv(TAG_OCTET_STRING, &[flags]),
+            tlv(TAG_INTEGER, &integer_content(SECURITY_MODEL_USM)),
+        ]
+        .concat(),
+    );
+    // authParams follows the message header, version, msgGlobalData, the msgSecurityParameters
+    // and USM sequence headers, the first four USM fields and its own 2-byte header
+    let usm_at = version.len() + global.len() + header_len(usm.len());
+    let body = [version, global, tlv(TAG_OCTET_STRING, &usm), data].concat();
+    let mut message = tlv(TAG_SEQUENCE, &body);
+    if let Some((auth, key)) = keys.auth.as_ref().filter(|_| mac_len > 0) {
+        let auth_at = header_len(body.len()) + usm_at + header_len(usm_content.len()) + usm_prefix.len() + 2;
+        let tag = hmac::sign(key, &message);
+        message[auth_at..auth_at + auth.mac_len()].copy_from_slice(&tag.as_ref()[..auth.mac_len()]);
+    }
+    Ok(message)
+}
+
+/// Decodes an SNMPv3 message, checking its HMAC and decrypting it with `keys` when it is protected.
+fn decode_v3(message: &[u8], keys: Option<&UsmKeys>) -> Result<V3Message> {
+    let malformed = || anyhow!("malformed SNMPv3 message");
+    let (_, body, _) = read_tlv(message).ok_or_else(malformed)?;
+    let (_, version, rest) = read_tlv(body).ok_or_else(malformed)?;
+    if read_integer(version) != Some(VERSION_V3) {
+        bail!("not an SNMPv3 message");
+    }
+    let (_, global, rest) = read_tlv(rest).ok_or_else(malformed)?;
+    let (_, msg_id, global) = read_tlv(global).ok_or_else(malformed)?;
+    let (_, _, global) = read_tlv(global).ok_or_else(malformed)?;
+    let (_, flags, _) = read_tlv(global).ok_or_else(malformed)?;
+    let flags = *flags.first().ok_or_else(malformed)?;
+    let (_, usm, data) = read_tlv(rest).ok_or_else(malformed)?;
+    let (_, usm, _) = read_tlv(usm).ok_or_else(malformed)?;
+    let (_, engine_id, usm) = read_tlv(usm).ok_or_else(malformed)?;
+    let (_, boots, usm) = read_tlv(usm).ok_or_else(malformed)?;
+    let (_, time, usm) = read_tlv(usm).ok_or_else(malformed)?;
+    let (_, user, usm) = read_tlv(usm).ok_or_else(malformed)?;
+    let (_, auth_params, usm) = read_tlv(usm).ok_or_else(malformed)?;
+    let (_, privacy_params, _) = read_tlv(usm).ok_or_else(malformed)?;
+    let engine = Engine {
+        id: engine_id.to_vec(),
+        boots: read_integer(boots).ok_or_else(malformed)?,
+        time: read_integer(time).ok_or_else(malformed)?,
+    };
+
+    if flags & FLAG_AUTH != 0 {
+        let (auth, key) = keys.and_then(|keys| keys.auth.as_ref()).ok_or_else(|| anyhow!("no authentication key"))?;
+        if auth_params.len() != auth.mac_len() {
+            bail!("authParams is {} bytes, expected {}", auth_params.len(), auth.mac_len());
+        }
+        let auth_at = auth_params.as_ptr() as usize - message.as_ptr() as usize;
+        let mut zeroed = message.to_vec();
+        zeroed[auth_at..auth_at + auth_params.len()].fill(0);
+        let expected = hmac::sign(key, &zeroed);
+        if expected.as_ref()[..auth.mac_len()] != *auth_params {
+            bail!("wrong digest");
+        }
+    }
+
+    let (tag, data, _) = read_tlv(data).ok_or_else(malformed)?;
+    let scoped = if flags & FLAG_PRIV != 0 {
+        let key = keys.and_then(|keys| keys.privacy.as_ref()).ok_or_else(|| anyhow!("no privacy key"))?;
+        if tag != TAG_OCTET_STRING {
+            bail!("encrypted PDU is not an OCTET STRING");
+        }
+        let mut plaintext = data.to_vec();
+        aes_keystream(key, engine.boots, privacy_params, &mut plaintext)?;
+        plaintext
+    } else {
+        tlv(tag, data)
+    };
+    let (_, scoped, _) = read_tlv(&scoped).ok_or_else(malformed)?;
+    let (_, _, scoped) = read_tlv(scoped).ok_or_else(malformed)?;
+    let (_, _, pdu) = read_tlv(scoped).ok_or_else(malformed)?;
+    Ok(V3Message {
+        msg_id: read_integer(msg_id).ok_or_else(malformed)?,
+        flags,
+        engine,
+        user: user.to_vec(),
+        pdu: pdu.to_vec(),
+    })
+}
+
+fn encode_v2c(community: &str, pdu: &[u8]) -> Vec<u8> {
+    tlv(
+        TAG_SEQUENCE,
+        &[tlv(TAG_INTEGER, &integer_content(VERSION_V2C)), tlv(TAG_OCTET_STRING, community.as_bytes()), pdu.to_vec()].concat(),
+    )
+}
+
+/// Community and PDU of an SNMPv2c message.
+fn decode_v2c(message: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    let (_, body, _) = read_tlv(message)?;
+    let (_, version, rest) = read_tlv(body)?;
+    if read_integer(version)? != VERSION_V2C {
+        return None;
+    }
+    let (_, community, pdu) = read_tlv(rest)?;
+    Some((community.to_vec(), pdu.to_vec()))
+}
+
+/// The agent: a switch's system group and ifTable, one community and one USM user at one security level.
+struct Agent {
+    mib: BTreeMap<Vec<u32>, Value>,
+    community: String,
+    engine_id: Vec<u8>,
+    started: Instant,
+    level: SecurityLevel,
+    keys: UsmKeys,
+}
+
+impl Agent {
+    fn new(interfaces: usize, community: &str, level: SecurityLevel) -> Result<Self> {
+        let engine_id = [&[0x80, 0x00, 0x1F, 0x88, 0x80][..], b"switch-lab-01"].concat();
+        let mut mib = BTreeMap::new();
+        let system = |leaf: u32| vec![1, 3, 6, 1, 2, 1, 1, leaf, 0];
+        mib.insert(SYS_DESCR.to_vec(), Value::OctetString(b"Edge switch 24x1G, firmware 3.2.1".to_vec()));
+        mib.insert(system(2), Value::Oid(vec![1, 3, 6, 1, 4, 1, 8072, 3, 2, 10]));
+        mib.insert(system(3), Value::TimeTicks(8_640_000));
+        mib.insert(system(4), Value::OctetString(b"noc@example.net".to_vec()));
+        mib.insert(system(5), Value::OctetString(b"switch-lab-01".to_vec()));
+        mib.insert(system(6), Value::OctetString(b"Rack 4, lab".to_vec()));
+        mib.insert(system(7), Value::Integer(6));
+        mib.insert(IF_NUMBER.to_vec(), Value::Integer(interfaces as i64));
+        for index in 1..=interfaces as u32 {
+            let column = |column: u32| [IF_ENTRY, &[column, index]].concat();
+            mib.insert(column(1), Value::Integer(index as i64));
+            mib.insert(column(2), Value::OctetString(format!("GigabitEthernet0/{}", index).into_bytes()));
+            mib.insert(column(3), Value::Integer(6));
+            mib.insert(column(4), Value::Integer(1500));
+            mib.insert(column(5), Value::Gauge32(1_000_000_000));
+            mib.insert(column(8), Value::Integer(1));
+            mib.insert(column(IF_IN_OCTETS), Value::Counter32(3_000_000_000u32.wrapping_add(index * 7_919_113)));
+            mib.insert(column(IF_OUT_OCTETS), Value::Counter32(1_500_000_000u32.wrapping_add(index * 5_003_221)));
+        }
+        Ok(Self {
+            mib,
+            community: community.to_string(),
+            keys: UsmKeys::new(level, &engine_id)?,
+            engine_id,
+            started: Instant::now(),
+            level,
+        })
+    }
+
+    fn next(&self, after: &[u32]) -> (Vec<u32>, Value) {
+        self.mib
+            .range::<[u32], _>((std::ops::Bound::Excluded(after), std::ops::Bound::Unbounded))
+            .next()
+            .map(|(arcs, value)| (arcs.clone(), value.clone()))
+            .unwrap_or_else(|| (after.to_vec(), Value::EndOfMibView))
+    }
+
+    fn respond(&self, request: &Pdu) -> Pdu {
+        let varbinds = match request.tag {
+            PDU_GET => request
+                .varbinds
+                .iter()
+                .map(|(arcs, _)| (arcs.clone(), self.mib.get(arcs).cloned().unwrap_or(Value::Null)))
+                .collect(),
+            PDU_GET_NEXT => request.varbinds.iter().map(|(arcs, _)| self.next(arcs)).collect(),
+            _ => {
+                // GETBULK: non-repeaters once, then the rest interleaved up to max-repetitions, within the message size
+                let non_repeaters = (request.error_status.max(0) as usize).min(request.varbinds.len());
+                let mut varbinds: Vec<(Vec<u32>, Value)> =
+                    request.varbinds[..non_repeaters].iter().map(|(arcs, _)| self.next(arcs)).collect();
+                let mut budget = MAX_MESSAGE_BYTES - RESPONSE_ENVELOPE_BYTES;
+                budget = budget.saturating_sub(varbinds.iter().map(|(arcs, value)| varbind(arcs, value).len()).sum());
+                let mut cursors: Vec<Vec<u32>> = request.varbinds[non_repeaters..].iter().map(|(arcs, _)| arcs.clone()).collect();
+                'repetitions: for _ in 0..request.error_index.max(0) {
+                    for cursor in cursors.iter_mut() {
+                        let (arcs, value) = self.next(cursor);
+                        let size = varbind(&arcs, &value).len();
+                        if size > budget {
+                            break 'repetitions;
+                        }
+                        budget -= size;
+                        *cursor = arcs.clone();
+                        varbinds.push((arcs, value));
+                    }
+                    if varbinds.last().is_some_and(|(_, value)| *value == Value::EndOfMibView) {
+                        break;
+                    }
+                }
+                varbinds
+            }
+        };
+        Pdu { tag: PDU_RESPONSE, request_id: request.request_id, error_status: 0, error_index: 0, varbinds }
+    }
+
+    /// The reply to one datagram, or `None` for what an agent drops silently.
+    fn handle(&self, message: &[u8]) -> Option<Vec<u8>> {
+        if self.level == SecurityLevel::V2c {
+            let (community, pdu) = decode_v2c(message)?;
+            if community != self.community.as_bytes() {
+                return None;
+            }
+            return Some(encode_v2c(&self.community, &self.respond(&Pdu::decode(&pdu)?).encode()));
+        }
+
+        let engine = Engine {
+            id: self.engine_id.clone(),
+            boots: 1,
+            time: self.started.elapsed().as_secs() as i64,
+        };
+        let protected = self.level.flags() != 0;
+        let unprotected = UsmKeys { auth: None, privacy: None };
+        let received = decode_v3(message, Some(&self.keys)).ok()?;
+        if received.engine.id.is_empty() {
+            let report = Pdu {
+                tag: PDU_REPORT,
+                request_id: Pdu::decode(&received.pdu).map_or(0, |pdu| pdu.request_id),
+                error_status: 0,
+                error_index: 0,
+                varbinds: vec![(USM_STATS_UNKNOWN_ENGINE_IDS.to_vec(), Value::Counter32(1))],
+            };
+            return encode_v3(received.msg_id, 0, &engine, &received.user, &unprotected, 0, &report.encode()).ok();
+        }
+        if received.engine.id != self.engine_id
+            || received.user != USER_NAME.as_bytes()
+            || received.flags & (FLAG_AUTH | FLAG_PRIV) != self.level.flags()
+            || (protected && (received.engine.time - engine.time).abs() > TIME_WINDOW_SECS)
+        {
+            return None;
+        }
+        let response = self.respond(&Pdu::decode(&received.pdu)?);
+        let salt = received.msg_id as u64 | 1 << 63;
+        encode_v3(received.msg_id, self.level.flags(), &engine, &received.user, &self.keys, salt, &response.encode()).ok()
+    }
+}
+
+/// A running agent on a loopback UDP socket; stopped when dropped.
+struct AgentServer {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl AgentServer {
+    async fn start(agent: Agent) -> Result<Self> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let addr = socket.local_addr()?;
+        let agent = Arc::new(agent);
+        let task = tokio::spawn(async move {
+            let mut buffer = vec![0u8; 65536];
+            while let Ok((len, from)) = socket.recv_from(&mut buffer).await {
+                if let Some(reply) = agent.handle(&buffer[..len]) {
+                    let _ = socket.send_to(&reply, from).await;
+                }
+            }
+        });
+        Ok(Self { addr, task })
+    }
+}
+
+impl Drop for AgentServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A poller at one security level, holding what v3 discovery taught it.
+struct Poller {
+    socket: UdpSocket,
+    level: SecurityLevel,
+    community: String,
+    engine: Engine,
+    keys: UsmKeys,
+    next_id: i64,
+    salt: u64,
+    buffer: Vec<u8>,
+    /// Time spent encoding, securing and decoding, excluding the network.
+    processing: Duration,
+}
+
+impl Poller {
+    async fn connect(agent: SocketAddr, level: SecurityLevel, community: &str) -> Result<Self> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        socket.connect(agent).await?;
+        Ok(Self {
+            socket,
+            level,
+            community: community.to_string(),
+            engine: Engine::default(),
+            keys: UsmKeys { auth: None, privacy: None },
+            next_id: 1,
+            salt: 0,
+            buffer: vec![0u8; 65536],
+            processing: Duration::ZERO,
+        })
+    }
+
+    /// Engine discovery: an empty-engine request answered by a Report. Returns the bytes it took.
+    async fn discover(&mut self) -> Result<usize> {
+        let pdu = Pdu::request(PDU_GET, self.take_id(), &[]);
+        let request = encode_v3(self.next_id, FLAG_REPORTABLE, &Engine::default(), b"", &self.keys, 0, &pdu.encode())?;
+        let reply = self.exchange(&request).await?;
+        let report = decode_v3(&reply, None)?;
+        if Pdu::decode(&report.pdu).map(|pdu| pdu.tag) != Some(PDU_REPORT) {
+            bail!("engine discovery got no Report");
+        }
+        self.engine = report.engine;
+        Ok(request.len() + reply.len() + 2 * IPV4_UDP_HEADER_BYTES)
+    }
+
+    fn take_id(&mut self) -> i64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    async fn exchange(&mut self, request: &[u8]) -> Result<Vec<u8>> {
+        self.socket.send(request).await?;
+        let len = tokio::time::timeout(REPLY_TIMEOUT, self.socket.recv(&mut self.buffer))
+            .await
+            .map_err(|_| anyhow!("no SNMP response"))??;
+        Ok(self.buffer[..len].to_vec())
+    }
+
+    /// Sends one request and returns the response PDU with the request and response sizes.
+    async fn request(&mut self, pdu: Pdu) -> Result<(Pdu, usize, usize)> {
+        let encoding = Instant::now();
+        let request = match self.level {
+            SecurityLevel::V2c => encode_v2c(&self.community, &pdu.encode()),
+            level => {
+                self.salt += 1;
+                let flags = level.flags() | FLAG_REPORTABLE;
+                encode_v3(self.next_id, flags, &self.engine, USER_NAME.as_bytes(), &self.keys, self.salt, &pdu.encode())?
+            }
+        };
+        self.processing += encoding.elapsed();
+        let reply = self.exchange(&request).await?;
+        let decoding = Instant::now();
+        let response = match self.level {
+            SecurityLevel::V2c => decode_v2c(&reply).ok_or_else(|| anyhow!("malformed SNMPv2c response"))?.1,
+            _ => decode_v3(&reply, Some(&self.keys))?.pdu,
+        };
+        let response = Pdu::decode(&response).ok_or_else(|| anyhow!("malformed response PDU"))?;
+        self.processing += decoding.elapsed();
+        if response.request_id != pdu.request_id {
+            bail!("response to request {} while waiting for {}", response.request_id, pdu.request_id);
+        }
+        Ok((response, request.len() + IPV4_UDP_HEADER_BYTES, reply.len() + IPV4_UDP_HEADER_BYTES))
+    }
+}
+
+pub struct SnmpAnalyzer {
+    config: SnmpConfig,
+}
+
+impl SnmpAnalyzer {
+    pub fn new(config: SnmpConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_snmp(&mut self) -> Result<SnmpMetrics> {
+        println!("\n📟 Analyzing SNMP Polling (v2c and v3 USM)");
+        println!("------------------------------------------");
+
+        let agent = AgentServer::start(Agent::new(self.config.interfaces, &self.config.community, SecurityLevel::V2c)?).await?;
+        let mut poller = Poller::connect(agent.addr, SecurityLevel::V2c, &self.config.community).await?;
+
+        let mut get = Vec::new();
+        for &count in &self.config.oid_counts {
+            let oids = self.counter_oids(count);
+            let (request_bytes, response_bytes, latencies) = self.time_gets(&mut poller, &oids).await?;
+            let single = get.first().map(|first: &SnmpGetRun| (first.oids, first.request_bytes + first.response_bytes));
+            let marginal = match single {
+                Some((oids, bytes)) if count > oids => {
+                    (request_bytes + response_bytes - bytes) as f64 / (count - oids) as f64
+                }
+                _ => 0.0,
+            };
+            let run = SnmpGetRun {
+                oids: count,
+                request_bytes,
+                response_bytes,
+                marginal_bytes_per_oid: marginal,
+                mean_latency_ms: latencies.iter().sum::<f64>() / latencies.len().max(1) as f64,
+                p95_latency_ms: percentile(&latencies, 95.0),
+            };
+            println!("✅ GET {} OIDs: {} B request, {} B response, +{:.1} B per OID, mean {:.3}ms, p95 {:.3}ms",
+                     run.oids, run.request_bytes, run.response_bytes, run.marginal_bytes_per_oid,
+                     run.mean_latency_ms, run.p95_latency_ms);
+            get.push(run);
+        }
+
+        let mut walks = vec![self.walk(&mut poller, None).await?];
+        for &repetitions in &self.config.max_repetitions {
+            walks.push(self.walk(&mut poller, Some(repetitions)).await?);
+        }
+        for walk in &walks {
+            println!("✅ ifTable walk with {}: {} varbinds in {} requests, {} B, {:.2}ms",
+                     walk.method, walk.varbinds, walk.requests, walk.bytes, walk.elapsed_ms);
+        }
+        drop(agent);
+
+        let key_start = Instant::now();
+        Auth::Sha1.localize(AUTH_PASSWORD, b"switch-lab-01");
+        let key_localization_ms = key_start.elapsed().as_secs_f64() * 1000.0;
+        println!("✅ Password to localized key (SHA-1): {:.2}ms", key_localization_ms);
+
+        let oids = self.counter_oids(self.config.oid_counts.iter().copied().find(|&count| count >= 5).unwrap_or(5));
+        let mut security = Vec::new();
+        let mut engine_discovery = (0.0, 0);
+        let mut baseline = 0;
+        for level in SecurityLevel::ALL {
+            let agent = AgentServer::start(Agent::new(self.config.interfaces, &self.config.community, level)?).await?;
+            let mut poller = Poller::connect(agent.addr, level, &self.config.community).await?;
+            if level != SecurityLevel::V2c {
+                let start = Instant::now();
+                let bytes = poller.discover().await?;
+                if level == SecurityLevel::NoAuthNoPriv {
+                    engine_discovery = (start.elapsed().as_secs_f64() * 1000.0, bytes);
+                }
+                poller.keys = UsmKeys::new(level, &poller.engine.id)?;
+            }
+            poller.processing = Duration::ZERO;
+            let (request_bytes, response_bytes, latencies) = self.time_gets(&mut poller, &oids).await?;
+            if level == SecurityLevel::V2c {
+                baseline = request_bytes + response_bytes;
+            }
+            let run = SnmpSecurityRun {
+                level: level.describe().to_string(),
+                request_bytes,
+                response_bytes,
+                overhead_bytes: (request_bytes + response_bytes) as isize - baseline as isize,
+                mean_latency_ms: latencies.iter().sum::<f64>() / latencies.len().max(1) as f64,
+                poller_processing_us: poller.processing.as_secs_f64() * 1e6 / latencies.len().max(1) as f64,
+            };
+            println!("✅ {}: {} B request, {} B response ({:+} B), mean {:.3}ms, poller {:.1}µs",
+                     run.level, run.request_bytes, run.response_bytes, run.overhead_bytes,
+                     run.mean_latency_ms, run.poller_processing_us);
+            security.push(run);
+        }
+        println!("✅ v3 engine discovery: {:.3}ms, {} B", engine_discovery.0, engine_discovery.1);
+
+        Ok(SnmpMetrics {
+            get,
+            walks,
+            security,
+            key_localization_ms,
+            engine_discovery_ms: engine_discovery.0,
+            engine_discovery_bytes: engine_discovery.1,
+        })
+    }
+
+    /// ifInOctets and ifOutOctets of successive interfaces, as a poller of link utilization asks for them.
+    fn counter_oids(&self, count: usize) -> Vec<Vec<u32>> {
+        let interfaces = self.config.interfaces.max(1) as u32;
+        (0..count as u32)
+            .map(|i| {
+                let column = if i % 2 == 0 { IF_IN_OCTETS } else { IF_OUT_OCTETS };
+                [IF_ENTRY, &[column, (i / 2) % interfaces + 1]].concat()
+            })
+            .collect()
+    }
+
+    async fn time_gets(&self, poller: &mut Poller, oids: &[Vec<u32>]) -> Result<(usize, usize, Vec<f64>)> {
+        let mut sizes = (0, 0);
+        let mut latencies = Vec::with_capacity(self.config.iterations);
+        for _ in 0..self.config.iterations.max(1) {
+            let start = Instant::now();
+            let pdu = Pdu::request(PDU_GET, poller.take_id(), oids);
+            let (response, request_bytes, response_bytes) = poller.request(pdu).await?;
+            latencies.push(start.elapsed().as_secs_f64() * 1000.0);
+            if response.varbinds.len() != oids.len() || response.varbinds.iter().any(|(_, value)| *value == Value::Null) {
+                bail!("GET of {} OIDs came back incomplete", oids.len());
+            }
+            sizes = (request_bytes, response_bytes);
+        }
+        latencies.sort_by(|a, b| a.total_cmp(b));
+        Ok((sizes.0, sizes.1, latencies))
+    }
+
+    /// Walks the ifTable with GETNEXT (`None`) or GETBULK at `max_repetitions`.
+    async fn walk(&self, poller: &mut Poller, max_repetitions: Option<usize>) -> Result<SnmpWalkRun> {
+        let start = Instant::now();
+        let mut cursor = IF_ENTRY.to_vec();
+        let (mut varbinds, mut requests, mut bytes) = (0, 0, 0);
+        'walk: loop {
+            let mut pdu = Pdu::request(PDU_GET_NEXT, poller.take_id(), &[cursor.clone()]);
+            if let Some(repetitions) = max_repetitions {
+                pdu.tag = PDU_GET_BULK;
+                pdu.error_index = repetitions as i64;
+            }
+            let (response, request_bytes, response_bytes) = poller.request(pdu).await?;
+            requests += 1;
+            bytes += request_bytes + response_bytes;
+            if response.varbinds.is_empty() {
+                bail!("empty walk response");
+            }
+            for (arcs, value) in response.varbinds {
+                if !arcs.starts_with(IF_ENTRY) || value == Value::EndOfMibView {
+                    break 'walk;
+                }
+                varbinds += 1;
+                cursor = arcs;
+            }
+        }
+        Ok(SnmpWalkRun {
+            method: match max_repetitions {
+                None => "GETNEXT".to_string(),
+                Some(repetitions) => format!("GETBULK (max-repetitions {})", repetitions),
+            },
+            max_repetitions,
+            varbinds,
+            requests,
+            bytes,
+            elapsed_ms: start.elapsed().as_secs_f64() * 1000.0,
+        })
+    }
+}
+
+fn header_len(content_len: usize) -> usize {
+    match content_len {
+        0..=0x7F => 2,
+        0x80..=0xFF => 3,
+        _ => 4,
+    }
+}
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(content.len() + 4);
+    out.push(tag);
+    match content.len() {
+        len @ 0..=0x7F => out.push(len as u8),
+        len @ 0x80..=0xFF => out.extend_from_slice(&[0x81, len as u8]),
+        len => out.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]),
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// Minimal two's-complement content of an INTEGER.
+fn integer_content(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    while start < 7 && ((bytes[start] == 0 && bytes[start + 1] & 0x80 == 0) || (bytes[start] == 0xFF && bytes[start + 1] & 0x80 != 0)) {
+        start += 1;
+    }
+    bytes[start..].to_vec()
+}
+
+/// Content of an unsigned application type, with a leading zero when the top bit is set.
+fn unsigned_content(value: u32) -> Vec<u8> {
+    integer_content(value as i64)
+}
+
+fn oid(arcs: &[u32]) -> Vec<u8> {
+    let mut content = vec![(arcs.first().copied().unwrap_or(0) * 40 + arcs.get(1).copied().unwrap_or(0)) as u8];
+    for &arc in arcs.iter().skip(2) {
+        let mut groups = vec![(arc & 0x7F) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            groups.push((rest & 0x7F) as u8 | 0x80);
+            rest >>= 7;
+        }
+        content.extend(groups.into_iter().rev());
+    }
+    tlv(TAG_OID, &content)
+}
+
+/// Tag, content and what follows of the first TLV in `input`.
+fn read_tlv(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *input.first()?;
+    let first = *input.get(1)? as usize;
+    let (len, header) = if first & 0x80 == 0 {
+        (first, 2)
+    } else {
+        let count = first & 0x7F;
+        let len = input.get(2..2 + count)?.iter().fold(0usize, |len, byte| len << 8 | *byte as usize);
+        (len, 2 + count)
+    };
+    let content = input.get(header..header + len)?;
+    Some((tag, content, &input[header + len..]))
+}
+
+fn read_integer(content: &[u8]) -> Option<i64> {
+    let first = *content.first()?;
+    if content.len() > 8 {
+        return None;
+    }
+    let seed = if first & 0x80 != 0 { -1i64 } else { 0 };
+    Some(content.iter().fold(seed, |value, byte| value << 8 | *byte as i64))
+}
+
+fn read_oid(content: &[u8]) -> Option<Vec<u32>> {
+    let first = *content.first()? as u32;
+    let mut arcs = vec![first / 40, first % 40];
+    let mut arc = 0u32;
+    for &byte in &content[1..] {
+        arc = arc << 7 | (byte & 0x7F) as u32;
+        if byte & 0x80 == 0 {
+            arcs.push(arc);
+            arc = 0;
+        }
+    }
+    Some(arcs)
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((p / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn bulk_walks_need_fewer_requests_and_v3_costs_bytes() {
+        let metrics = SnmpAnalyzer::builder()
+            .oid_counts(vec![1usize, 10])
+            .interfaces(8usize)
+            .max_repetitions(vec![16usize])
+            .iterations(5usize)
+            .build()
+            .analyze_snmp()
+            .await
+            .unwrap();
+
+        // An ifInOctets varbind is 16 bytes in the GET and 21 in the response
+        assert!(metrics.get[1].marginal_bytes_per_oid > 25.0 && metrics.get[1].marginal_bytes_per_oid < 40.0);
+
+        let (getnext, bulk) = (&metrics.walks[0], &metrics.walks[1]);
+        assert_eq!(getnext.varbinds, 8 * 8);
+        assert_eq!(bulk.varbinds, getnext.varbinds);
+        assert_eq!(getnext.requests, getnext.varbinds + 1);
+        assert!(bulk.requests <= 5 && bulk.bytes < getnext.bytes / 2);
+
+        let overhead: Vec<isize> = metrics.security.iter().map(|run| run.overhead_bytes).collect();
+        assert_eq!(overhead[0], 0);
+        assert!(overhead[1] > 0 && overhead[2] > overhead[1] && overhead[3] > overhead[2]);
+        assert!(metrics.engine_discovery_bytes > 0);
+    }
+
+    #[test]
+    fn ber_round_trips_integers_and_oids() {
+        for value in [0i64, 127, 128, -1, -129, 3_000_000_000, i32::MAX as i64] {
+            assert_eq!(read_integer(&integer_content(value)), Some(value));
+        }
+        let arcs = vec![1, 3, 6, 1, 4, 1, 8072, 3, 2, 10];
+        let encoded = oid(&arcs);
+        let (tag, content, _) = read_tlv(&encoded).unwrap();
+        assert_eq!((tag, read_oid(content)), (TAG_OID, Some(arcs)));
+    }
+}
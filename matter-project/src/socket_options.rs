@@ -0,0 +1,369 @@
+// matter-project/src/socket_options.rs
+/*!
+TCP socket-option experiment matrix - latency/throughput sensitivity to Nagle, buffers and keepalive
+*/
+
+use analysis_core::distribution::LatencyDistribution;
+use analysis_core::samples::{self, Layer};
+use anyhow::Result;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use socket2::{SockRef, TcpKeepalive};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+
+use crate::builder::analyzer_builder;
+
+/// (protocol, framing header bytes, message payload bytes) for one request/response.
+/// Header and payload go out as separate writes, as the stacks do, which is
+/// what exposes the Nagle / delayed-ACK interaction.
+const PROTOCOL_PROFILES: &[(&str, usize, usize)] = &[
+    ("Matter", 4, 120), // length-prefixed Matter message over TCP
+    ("LwM2M", 2, 60),   // CoAP over TCP (RFC 8323) header + request
+];
+
+const BULK_TRANSFER_BYTES: usize = 4 * 1024 * 1024;
+const BULK_WRITE_BYTES: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SocketOptions {
+    pub nodelay: bool,
+    /// `None` keeps the kernel default.
+    pub buffer_bytes: Option<u32>,
+    pub keepalive_secs: Option<u64>,
+}
+
+impl SocketOptions {
+    pub fn label(&self) -> String {
+        format!(
+            "nodelay={} buffer={} keepalive={}",
+            self.nodelay,
+            self.buffer_bytes.map_or("default".to_string(), |b| b.to_string()),
+            self.keepalive_secs.map_or("off".to_string(), |s| format!("{}s", s)),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SocketMatrixConfig {
+    pub round_trips: u32,
+    pub buffer_sizes: Vec<Option<u32>>,
+    pub keepalive_intervals: Vec<Option<u64>>,
+}
+
+impl Default for SocketMatrixConfig {
+    fn default() -> Self {
+        Self {
+            round_trips: 50,
+            buffer_sizes: vec![None, Some(64 * 1024), Some(1024 * 1024)],
+            keepalive_intervals: vec![None, Some(10)],
+        }
+    }
+}
+
+analyzer_builder!(SocketOptionMatrixBuilder => SocketOptionMatrix(SocketMatrixConfig) {
+    round_trips: u32,
+    buffer_sizes: Vec<Option<u32>>,
+    keepalive_intervals: Vec<Option<u64>>,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SocketMatrixMetrics {
+    pub round_trips: u32,
+    pub results: Vec<SocketOptionResult>,
+    pub sensitivity: Vec<ProtocolSensitivity>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SocketOptionResult {
+    pub protocol: String,
+    pub options: SocketOptions,
+    pub median_latency_ms: f64,
+    pub p95_latency_ms: f64,
+    pub distribution: LatencyDistribution,
+    pub throughput_mbps: f64,
+}
+
+/// How much a protocol's median latency moves with each option.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProtocolSensitivity {
+    pub protocol: String,
+    pub best_latency_ms: f64,
+    pub worst_latency_ms: f64,
+    /// (worst - best) / best across the whole matrix.
+    pub latency_spread_ratio: f64,
+    /// Mean median latency with Nagle enabled divided by the mean with TCP_NODELAY.
+    pub nagle_latency_factor: f64,
+    /// Mean median latency per buffer size setting.
+    pub latency_by_buffer: Vec<(Option<u32>, f64)>,
+    /// Mean median latency per keepalive setting.
+    pub latency_by_keepalive: Vec<(Option<u64>, f64)>,
+}
+
+pub struct SocketOptionMatrix {
+    config: SocketMatrixConfig,
+}
+
+impl SocketOptionMatrix {
+    pub fn new(config: SocketMatrixConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_socket_options(&mut self) -> Result<SocketMatrixMetrics> {
+        println!("\n🎛️ Running TCP Socket-Option Matrix");
+        println!("-----------------------------------");
+
+        let mut results = Vec::new();
+        for options in self.combinations() {
+            for (protocol, header_len, payload_len) in PROTOCOL_PROFILES {
+                let mut latencies = self.measure_latency(options, *header_len, *payload_len).await?;
+                samples::record("socket_options", Layer::Transport, protocol, &options.label(), "latency_ms", &latencies);
+                let distribution = LatencyDistribution::from_samples(&latencies);
+                latencies.sort_by(|a, b| a.total_cmp(b));
+                let throughput_mbps = measure_bulk_throughput(options).await?;
+
+                let result = SocketOptionResult {
+                    protocol: protocol.to_string(),
+                    options,
+                    median_latency_ms: percentile(&latencies, 0.5),
+                    p95_latency_ms: percentile(&latencies, 0.95),
+                    distribution,
+                    throughput_mbps,
+                };
+                debug!("{} [{}]: {:.3}ms median, {:.1}Mbps",
+                       result.protocol, options.label(), result.median_latency_ms, result.throughput_mbps);
+                results.push(result);
+            }
+        }
+
+        let sensitivity: Vec<ProtocolSensitivity> = PROTOCOL_PROFILES
+            .iter()
+            .map(|(protocol, _, _)| self.sensitivity(protocol, &results))
+            .collect();
+
+        for s in &sensitivity {
+            println!("✅ {}: {:.3}-{:.3}ms median latency, Nagle x{:.1}",
+                     s.protocol, s.best_latency_ms, s.worst_latency_ms, s.nagle_latency_factor);
+        }
+
+        Ok(SocketMatrixMetrics { round_trips: self.config.round_trips, results, sensitivity })
+    }
+
+    fn combinations(&self) -> Vec<SocketOptions> {
+        let mut combinations = Vec::new();
+        for nodelay in [false, true] {
+            for buffer_bytes in &self.config.buffer_sizes {
+                for keepalive_secs in &self.config.keepalive_intervals {
+                    combinations.push(SocketOptions { nodelay, buffer_bytes: *buffer_bytes, keepalive_secs: *keepalive_secs });
+                }
+            }
+        }
+        combinations
+    }
+
+    /// Request/response round trips over one connection; per-trip latency in ms.
+    async fn measure_latency(&self, options: SocketOptions, header_len: usize, payload_len: usize) -> Result<Vec<f64>> {
+        let listener = bind_listener(options)?;
+        let server_addr = listener.local_addr()?;
+        let message_len = header_len + payload_len;
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await?;
+            apply_stream_options(&stream, options)?;
+            let mut message = vec![0u8; message_len];
+            while stream.read_exact(&mut message).await.is_ok() {
+                // Responses are framed the same way as requests
+                stream.write_all(&message[..header_len]).await?;
+                stream.write_all(&message[header_len..]).await?;
+            }
+            anyhow::Ok(())
+        });
+
+        let mut stream = connect(server_addr, options).await?;
+        let header = vec![0x01u8; header_len];
+        let payload = vec![0x15u8; payload_len];
+        let mut response = vec![0u8; message_len];
+        let mut latencies = Vec::with_capacity(self.config.round_trips as usize);
+
+        for _ in 0..self.config.round_trips {
+            let start = Instant::now();
+            stream.write_all(&header).await?;
+            stream.write_all(&payload).await?;
+            stream.read_exact(&mut response).await?;
+            latencies.push(start.elapsed().as_secs_f64() * 1000.0);
+        }
+
+        drop(stream);
+        server.await??;
+        Ok(latencies)
+    }
+
+    fn sensitivity(&self, protocol: &str, results: &[SocketOptionResult]) -> ProtocolSensitivity {
+        let rows: Vec<&SocketOptionResult> = results.iter().filter(|r| r.protocol == protocol).collect();
+        let mean_where = |predicate: &dyn Fn(&SocketOptions) -> bool| {
+            let matching: Vec<f64> = rows.iter().filter(|r| predicate(&r.options)).map(|r| r.median_latency_ms).collect();
+            if matching.is_empty() { 0.0 } else { matching.iter().sum::<f64>() / matching.len() as f64 }
+        };
+
+        let best_latency_ms = rows.iter().map(|r| r.median_latency_ms).fold(f64::INFINITY, f64::min);
+        let worst_latency_ms = rows.iter().map(|r| r.median_latency_ms).fold(0.0, f64::max);
+        let nodelay_mean = mean_where(&|o| o.nodelay);
+
+        ProtocolSensitivity {
+            protocol: protocol.to_string(),
+            best_latency_ms,
+            worst_latency_ms,
+            latency_spread_ratio: if best_latency_ms > 0.0 { (worst_latency_ms - best_latency_ms) / best_latency_ms } else { 0.0 },
+            nagle_latency_factor: if nodelay_mean > 0.0 { mean_where(&|o| !o.nodelay) / nodelay_mean } else { 0.0 },
+            latency_by_buffer: self
+                .config
+                .buffer_sizes
+                .iter()
+                .map(|b| (*b, mean_where(&|o| o.buffer_bytes == *b)))
+                .collect(),
+            latency_by_keepalive: self
+                .config
+                .keepalive_intervals
+                .iter()
+                .map(|k| (*k, mean_where(&|o| o.keepalive_secs == *k)))
+                .collect(),
+        }
+    }
+}
+
+/// One-directional bulk transfer; the receiver acknowledges once everything arrived.
+async fn measure_bulk_throughput(options: SocketOptions) -> Result<f64> {
+    let listener = bind_listener(options)?;
+    let server_addr = listener.local_addr()?;
+
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        apply_stream_options(&stream, options)?;
+        let mut buffer = vec![0u8; BULK_WRITE_BYTES];
+        let mut received = 0;
+        while received < BULK_TRANSFER_BYTES {
+            let len = stream.read(&mut buffer).await?;
+            if len == 0 {
+                break;
+            }
+            received += len;
+        }
+        stream.write_all(&[0x06]).await?;
+        anyhow::Ok(())
+    });
+
+    let mut stream = connect(server_addr, options).await?;
+    let chunk = vec![0xA5u8; BULK_WRITE_BYTES];
+    let start = Instant::now();
+
+    for _ in 0..BULK_TRANSFER_BYTES / BULK_WRITE_BYTES {
+        stream.write_all(&chunk).await?;
+    }
+    let mut ack = [0u8; 1];
+    stream.read_exact(&mut ack).await?;
+    let seconds = start.elapsed().as_secs_f64().max(f64::EPSILON);
+
+    server.await??;
+    Ok(BULK_TRANSFER_BYTES as f64 * 8.0 / seconds / 1_000_000.0)
+}
+
+// Buffer sizes must be set before listen/connect to influence window scaling
+fn bind_listener(options: SocketOptions) -> Result<TcpListener> {
+    let socket = TcpSocket::new_v4()?;
+    if let Some(bytes) = options.buffer_bytes {
+        socket.set_send_buffer_size(bytes)?;
+        socket.set_recv_buffer_size(bytes)?;
+    }
+    socket.bind("127.0.0.1:0".parse()?)?;
+    Ok(socket.listen(16)?)
+}
+
+async fn connect(addr: SocketAddr, options: SocketOptions) -> Result<TcpStream> {
+    let socket = TcpSocket::new_v4()?;
+    if let Some(bytes) = options.buffer_bytes {
+        socket.set_send_buffer_size(bytes)?;
+        socket.set_recv_buffer_size(bytes)?;
+    }
+    let stream = socket.connect(addr).await?;
+    apply_stream_options(&stream, options)?;
+    Ok(stream)
+}
+
+fn apply_stream_options(stream: &TcpStream, options: SocketOptions) -> Result<()> {
+    stream.set_nodelay(options.nodelay)?;
+    if let Some(secs) = options.keepalive_secs {
+        let keepalive = TcpKeepalive::new()
+            .with_time(Duration::from_secs(secs))
+            .with_interval(Duration::from_secs(secs));
+        SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
+    }
+    Ok(())
+}
+
+fn percentile(sorted: &[f64], quantile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((sorted.len() - 1) as f64 * quantile).round() as usize;
+    sorted[index]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(protocol: &str, options: SocketOptions, median_latency_ms: f64) -> SocketOptionResult {
+        SocketOptionResult {
+            protocol: protocol.to_string(),
+            options,
+            median_latency_ms,
+            p95_latency_ms: median_latency_ms,
+            distribution: LatencyDistribution::default(),
+            throughput_mbps: 0.0,
+        }
+    }
+
+    #[test]
+    fn the_matrix_crosses_nagle_buffers_and_keepalive() {
+        let matrix = SocketOptionMatrix::new(SocketMatrixConfig::default());
+        let combinations = matrix.combinations();
+        assert_eq!(combinations.len(), 2 * 3 * 2);
+        assert_eq!(combinations.iter().filter(|o| o.nodelay).count(), 6);
+        assert_eq!(combinations[0].label(), "nodelay=false buffer=default keepalive=off");
+        assert_eq!(combinations[1].label(), "nodelay=false buffer=default keepalive=10s");
+    }
+
+    #[test]
+    fn sensitivity_averages_each_option_across_the_matrix() {
+        let matrix = SocketOptionMatrix::builder()
+            .buffer_sizes(vec![None, Some(65536)])
+            .keepalive_intervals(vec![None])
+            .build();
+        let latencies = [40.0, 44.0, 1.0, 3.0];
+        let mut results: Vec<SocketOptionResult> = matrix
+            .combinations()
+            .into_iter()
+            .zip(latencies)
+            .map(|(options, latency)| result("Matter", options, latency))
+            .collect();
+        results.push(result("LwM2M", results[0].options, 500.0));
+
+        let sensitivity = matrix.sensitivity("Matter", &results);
+        assert_eq!((sensitivity.best_latency_ms, sensitivity.worst_latency_ms), (1.0, 44.0));
+        assert_eq!(sensitivity.latency_spread_ratio, 43.0);
+        assert_eq!(sensitivity.nagle_latency_factor, 21.0);
+        assert_eq!(sensitivity.latency_by_buffer, [(None, 20.5), (Some(65536), 23.5)]);
+        assert_eq!(sensitivity.latency_by_keepalive, [(None, 22.0)]);
+    }
+
+    #[test]
+    fn percentiles_round_to_the_nearest_rank() {
+        let sorted = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(percentile(&[], 0.5), 0.0);
+        assert_eq!(percentile(&sorted, 0.5), 3.0);
+        assert_eq!(percentile(&sorted, 0.95), 5.0);
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+    }
+}
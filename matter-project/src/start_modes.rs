@@ -0,0 +1,284 @@
+// matter-project/src/start_modes.rs
+/*!
+Cold and warm starts - first-contact cost against steady-state command cost
+
+The layer pipeline times discovery, commissioning and the first reads once,
+so its figures mix what a controller pays once per device with what it pays
+on every command. This mode separates the two. A cold start opens a fresh
+loopback link and runs:
+
+- discovery: a DNS-SD query for the device's operational service and the
+  PTR/SRV/TXT/AAAA answer;
+- commissioning: the secure session's key exchange;
+- the first command: an OnOff On InvokeRequest and its status response over
+  the new session.
+
+Each cold start is repeated and reported as the median of each phase. The
+last session is then kept, and further commands over it are the warm
+start. The first-command penalty is the first command minus the warm
+median; together with discovery and commissioning it is the one-time cost.
+Times are wall clock on loopback, like the layer pipeline's phase times.
+*/
+
+use analysis_core::distribution::LatencyDistribution;
+use analysis_core::samples::{self, Layer};
+use anyhow::{anyhow, Result};
+use metrics_core::tlv::{Tag, TlvError, TlvWriter};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::builder::analyzer_builder;
+use crate::firmware_update::tlv;
+use crate::layers::LoopbackLink;
+use crate::secure_session::SecureSession;
+
+// DNS-SD query for _matter._tcp and the answer with PTR, SRV, TXT and AAAA records
+pub(crate) const DISCOVERY_QUERY_BYTES: usize = 46;
+pub(crate) const DISCOVERY_ANSWER_BYTES: usize = 182;
+
+const SESSION_ID: u16 = 1;
+
+// OnOff cluster On
+const ON_OFF_CLUSTER: u32 = 0x0006;
+const ON: u32 = 0x01;
+
+/// The one-time cost counts as amortized once it is under this share of the total time.
+const AMORTIZED_SHARE: f64 = 0.1;
+
+#[derive(Debug, Clone)]
+pub struct StartModeConfig {
+    /// Fresh links, each discovered, commissioned and sent one command.
+    pub cold_starts: usize,
+    /// Commands over the last cold start's session.
+    pub warm_commands: usize,
+}
+
+impl Default for StartModeConfig {
+    fn default() -> Self {
+        Self { cold_starts: 5, warm_commands: 50 }
+    }
+}
+
+analyzer_builder!(StartModeAnalyzerBuilder => StartModeAnalyzer(StartModeConfig) {
+    cold_starts: usize,
+    warm_commands: usize,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartModeMetrics {
+    pub cold_start: ColdStart,
+    pub warm_start: WarmStart,
+    /// First command on a new session minus the warm median.
+    pub first_command_penalty_ms: f64,
+    /// Discovery, commissioning and the first-command penalty.
+    pub one_time_cost_ms: f64,
+    /// Warm commands after which the one-time cost is under a tenth of the total time.
+    pub amortized_after_commands: Option<usize>,
+}
+
+/// Medians over the cold starts.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ColdStart {
+    pub runs: usize,
+    pub discovery_ms: f64,
+    pub commissioning_ms: f64,
+    pub first_command_ms: f64,
+    pub total_ms: f64,
+    /// Discovery, handshake and first command bytes of one cold start.
+    pub bytes: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WarmStart {
+    pub commands: usize,
+    pub mean_ms: f64,
+    pub median_ms: f64,
+    pub p95_ms: f64,
+    pub distribution: LatencyDistribution,
+    pub bytes_per_command: f64,
+}
+
+pub struct StartModeAnalyzer {
+    config: StartModeConfig,
+}
+
+impl StartModeAnalyzer {
+    pub fn new(config: StartModeConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_start_modes(&mut self) -> Result<StartModeMetrics> {
+        println!("\n🧊 Analyzing Cold vs Warm Starts ({} cold starts, {} warm commands)",
+                 self.config.cold_starts, self.config.warm_commands);
+        println!("------------------------------------------------");
+        if self.config.cold_starts == 0 || self.config.warm_commands == 0 {
+            return Err(anyhow!("cold vs warm starts need at least one cold start and one warm command"));
+        }
+
+        let (invoke, response) = (tlv(invoke_request)?, tlv(invoke_response)?);
+        let (mut discovery, mut commissioning, mut first_command, mut totals) = (vec![], vec![], vec![], vec![]);
+        let mut cold_bytes = 0;
+        let mut session = None;
+        for _ in 0..self.config.cold_starts {
+            let start = Instant::now();
+            let link = LoopbackLink::open().await?;
+            link.to_device(&[0u8; DISCOVERY_QUERY_BYTES]).await?;
+            link.to_controller(&[0u8; DISCOVERY_ANSWER_BYTES]).await?;
+            let discovered = Instant::now();
+            let handshake = SecureSession::handshake(&link).await?;
+            let mut cold = SecureSession::new(link, SESSION_ID, handshake);
+            let commissioned = Instant::now();
+            cold.request(&invoke, &response).await?;
+            let done = Instant::now();
+
+            discovery.push(milliseconds(discovered - start));
+            commissioning.push(milliseconds(commissioned - discovered));
+            first_command.push(milliseconds(done - commissioned));
+            totals.push(milliseconds(done - start));
+            cold_bytes = DISCOVERY_QUERY_BYTES + DISCOVERY_ANSWER_BYTES + cold.stats.handshake_bytes + cold.stats.application_bytes;
+            session = Some(cold);
+        }
+        let mut session = session.expect("at least one cold start");
+
+        let bytes_before = session.stats.application_bytes;
+        let mut warm = Vec::with_capacity(self.config.warm_commands);
+        for _ in 0..self.config.warm_commands {
+            let start = Instant::now();
+            session.request(&invoke, &response).await?;
+            warm.push(milliseconds(start.elapsed()));
+        }
+        samples::record("start_modes", Layer::Session, "Matter", "cold", "total_ms", &totals);
+        samples::record("start_modes", Layer::Session, "Matter", "warm", "command_latency_ms", &warm);
+        let distribution = LatencyDistribution::from_samples(&warm);
+        warm.sort_by(f64::total_cmp);
+
+        let cold_start = ColdStart {
+            runs: self.config.cold_starts,
+            discovery_ms: median(&mut discovery),
+            commissioning_ms: median(&mut commissioning),
+            first_command_ms: median(&mut first_command),
+            total_ms: median(&mut totals),
+            bytes: cold_bytes,
+        };
+        let warm_start = WarmStart {
+            commands: warm.len(),
+            mean_ms: distribution.mean_ms,
+            median_ms: percentile(&warm, 50.0),
+            p95_ms: percentile(&warm, 95.0),
+            bytes_per_command: (session.stats.application_bytes - bytes_before) as f64 / warm.len() as f64,
+            distribution,
+        };
+        let first_command_penalty_ms = cold_start.first_command_ms - warm_start.median_ms;
+        let one_time_cost_ms = cold_start.discovery_ms + cold_start.commissioning_ms + first_command_penalty_ms.max(0.0);
+        // one_time / (one_time + n * mean) < share once n > one_time * (1 - share) / (share * mean)
+        let amortized_after_commands = (warm_start.mean_ms > 0.0).then(|| {
+            (one_time_cost_ms * (1.0 - AMORTIZED_SHARE) / (AMORTIZED_SHARE * warm_start.mean_ms)).floor() as usize + 1
+        });
+
+        println!("🧊 Cold start: {:.3}ms (discovery {:.3}ms, commissioning {:.3}ms, first command {:.3}ms), {} bytes",
+                 cold_start.total_ms, cold_start.discovery_ms, cold_start.commissioning_ms,
+                 cold_start.first_command_ms, cold_start.bytes);
+        println!("🔥 Warm start: median {:.3}ms, p95 {:.3}ms, {:.0} bytes per command",
+                 warm_start.median_ms, warm_start.p95_ms, warm_start.bytes_per_command);
+        println!("✅ One-time cost {:.3}ms (first-command penalty {:.3}ms), under {:.0}% of the total after {} commands",
+                 one_time_cost_ms, first_command_penalty_ms, AMORTIZED_SHARE * 100.0,
+                 amortized_after_commands.map_or("-".to_string(), |n| n.to_string()));
+
+        Ok(StartModeMetrics {
+            cold_start,
+            warm_start,
+            first_command_penalty_ms,
+            one_time_cost_ms,
+            amortized_after_commands,
+        })
+    }
+}
+
+/// CommandPathIB for OnOff On on endpoint 1.
+fn command_path(writer: &mut TlvWriter) -> Result<(), TlvError> {
+    writer.start_list(Tag::Context(0))?;
+    writer.u16(Tag::Context(0), 1)?;
+    writer.u32(Tag::Context(1), ON_OFF_CLUSTER)?;
+    writer.u32(Tag::Context(2), ON)?;
+    writer.end_container()
+}
+
+/// InvokeRequest for OnOff On.
+pub(crate) fn invoke_request(writer: &mut TlvWriter) -> Result<(), TlvError> {
+    writer.start_struct(Tag::Anonymous)?;
+    writer.bool(Tag::Context(0), false)?; // SuppressResponse
+    writer.bool(Tag::Context(1), false)?; // TimedRequest
+    writer.start_array(Tag::Context(2))?;
+    writer.start_struct(Tag::Anonymous)?;
+    command_path(writer)?;
+    writer.start_struct(Tag::Context(1))?;
+    writer.end_container()?;
+    writer.end_container()?;
+    writer.end_container()?;
+    writer.u8(Tag::Context(255), 11)?;
+    writer.end_container()
+}
+
+/// InvokeResponse with a success CommandStatusIB.
+pub(crate) fn invoke_response(writer: &mut TlvWriter) -> Result<(), TlvError> {
+    writer.start_struct(Tag::Anonymous)?;
+    writer.bool(Tag::Context(0), false)?;
+    writer.start_array(Tag::Context(1))?;
+    writer.start_struct(Tag::Anonymous)?;
+    writer.start_struct(Tag::Context(1))?;
+    command_path(writer)?;
+    writer.start_struct(Tag::Context(1))?;
+    writer.u8(Tag::Context(0), 0)?;
+    writer.end_container()?;
+    writer.end_container()?;
+    writer.end_container()?;
+    writer.end_container()?;
+    writer.u8(Tag::Context(255), 11)?;
+    writer.end_container()
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn median(samples: &mut [f64]) -> f64 {
+    samples.sort_by(f64::total_cmp);
+    percentile(samples, 50.0)
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((p / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cold_starts_carry_the_one_time_cost() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let metrics = runtime
+            .block_on(StartModeAnalyzer::builder().cold_starts(3usize).warm_commands(20usize).build().analyze_start_modes())
+            .unwrap();
+
+        assert_eq!((metrics.cold_start.runs, metrics.warm_start.commands), (3, 20));
+        assert_eq!(metrics.warm_start.distribution.samples, 20);
+        // The key exchange alone outweighs one command over an established session
+        assert!(metrics.cold_start.commissioning_ms > metrics.warm_start.median_ms, "{:?}", metrics);
+        assert!(metrics.cold_start.total_ms >= metrics.cold_start.commissioning_ms);
+        assert!(metrics.one_time_cost_ms >= metrics.cold_start.commissioning_ms);
+        assert!(metrics.amortized_after_commands.is_some());
+        // Every warm command is the same request and response
+        let per_command = metrics.warm_start.bytes_per_command as usize;
+        assert_eq!(
+            metrics.cold_start.bytes,
+            DISCOVERY_QUERY_BYTES + DISCOVERY_ANSWER_BYTES + 2 * (8 + 65) + per_command
+        );
+        let empty = runtime.block_on(StartModeAnalyzer::builder().warm_commands(0usize).build().analyze_start_modes());
+        assert!(empty.is_err());
+    }
+}
@@ -0,0 +1,166 @@
+// matter-project/src/stream.rs
+/*!
+Measurement stream - every finished measurement as one JSON line on stdout, while the run is still going
+
+With `--stream ndjson` each analysis section is written the moment it
+completes, so long runs can be piped into `jq`, `vector` or a custom
+collector instead of waiting for the result file:
+
+```text
+{"event":"measurement","section":"layers","elapsed_ms":812.4,"data":{...},"units":{"transport_ms":"ms",...}}
+{"event":"measurement","section":"throughput","elapsed_ms":5321.0,"data":{...},"units":{...}}
+{"event":"complete","elapsed_ms":6012.7,"result_file":"../results/runs/matter_20250623T100000Z.json"}
+```
+
+`data` is the value the section gets in the result file, redacted when
+`--redact` is set and rounded to `--precision`. `units` gives the unit of
+each of its metrics that has one, by path within `data`. Sections that were
+not enabled are not written. On Linux the console output moves to stderr
+while streaming, so stdout carries only the stream; elsewhere the two share
+stdout and readers should skip lines that do not start with `{`. If the
+reader goes away, streaming stops with a warning and the run carries on.
+*/
+
+use analysis_core::redact::Redactor;
+use analysis_core::units::{unit_table, Units};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    Ndjson,
+}
+
+impl FromStr for StreamFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "ndjson" | "jsonl" => Ok(StreamFormat::Ndjson),
+            other => Err(anyhow!("unknown stream format: {} (expected ndjson)", other)),
+        }
+    }
+}
+
+pub struct MeasurementStream {
+    output: Option<Box<dyn Write + Send>>,
+    redactor: Option<Redactor>,
+    units: Units,
+    started: Instant,
+}
+
+impl MeasurementStream {
+    /// A stream that writes nothing, for runs without `--stream`.
+    pub fn disabled() -> Self {
+        Self { output: None, redactor: None, units: Units::default(), started: Instant::now() }
+    }
+
+    /// Streams to stdout, moving the console output to stderr where the platform allows it.
+    pub fn stdout(format: StreamFormat, redactor: Option<Redactor>, units: Units) -> Result<Self> {
+        match format {
+            StreamFormat::Ndjson => Ok(Self::to_writer(detach_stdout()?, redactor, units)),
+        }
+    }
+
+    pub fn to_writer(output: impl Write + Send + 'static, redactor: Option<Redactor>, units: Units) -> Self {
+        Self { output: Some(Box::new(output)), redactor, units, started: Instant::now() }
+    }
+
+    /// Writes one finished section; `None` and other nulls are sections that did not run.
+    pub fn measurement<T: Serialize>(&mut self, section: &str, data: &T) {
+        if self.output.is_none() {
+            return;
+        }
+        let mut data = match serde_json::to_value(data) {
+            Ok(Value::Null) => return,
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("⚠️ {} not streamed: {}", section, e);
+                return;
+            }
+        };
+        if let Some(redactor) = &mut self.redactor {
+            redactor.redact_value(&mut data);
+        }
+        self.units.round_floats(&mut data);
+        let units = unit_table(&data);
+        let elapsed_ms = self.elapsed_ms();
+        self.write(json!({"event": "measurement", "section": section, "elapsed_ms": elapsed_ms, "data": data, "units": units}));
+    }
+
+    /// Ends the stream with where the full result was saved.
+    pub fn complete(&mut self, result_file: &Path) {
+        let elapsed_ms = self.elapsed_ms();
+        self.write(json!({"event": "complete", "elapsed_ms": elapsed_ms, "result_file": result_file.display().to_string()}));
+    }
+
+    fn elapsed_ms(&self) -> f64 {
+        self.started.elapsed().as_secs_f64() * 1000.0
+    }
+
+    fn write(&mut self, line: Value) {
+        let Some(output) = &mut self.output else {
+            return;
+        };
+        // Flushed per line so readers see each measurement as it happens
+        if let Err(e) = writeln!(output, "{}", line).and_then(|_| output.flush()) {
+            eprintln!("⚠️ Measurement stream stopped: {}", e);
+            self.output = None;
+        }
+    }
+}
+
+/// Hands the real stdout to the stream and points the process's stdout at stderr.
+#[cfg(target_os = "linux")]
+fn detach_stdout() -> Result<Box<dyn Write + Send>> {
+    use std::os::fd::FromRawFd;
+
+    std::io::stdout().flush()?;
+    // SAFETY: duplicates the process's own standard descriptors; the copy of stdout is owned by the File below
+    let stream = unsafe { libc::dup(libc::STDOUT_FILENO) };
+    if stream < 0 || unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    // SAFETY: `stream` is a fresh descriptor nothing else refers to
+    Ok(Box::new(unsafe { std::fs::File::from_raw_fd(stream) }))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn detach_stdout() -> Result<Box<dyn Write + Send>> {
+    Ok(Box::new(std::io::stdout()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_one_line_per_finished_section() {
+        let path = std::env::temp_dir().join(format!("measurement-stream-{}.ndjson", std::process::id()));
+        let file = std::fs::File::create(&path).unwrap();
+        let units = Units { precision: Some(1), ..Units::default() };
+        let mut stream = MeasurementStream::to_writer(file, Some(Redactor::new(&["lab-7".to_string()])), units);
+        stream.measurement("throughput", &Some(json!({"goodput_mbps": 41.52, "duration_ms": 1000.04, "peer": "lab-7"})));
+        stream.measurement("concurrency", &None::<Value>);
+        stream.complete(Path::new("results/matter.json"));
+        drop(stream);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let lines: Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["section"], "throughput");
+        assert_eq!(lines[0]["data"]["goodput_mbps"], 41.5);
+        assert_eq!(lines[0]["data"]["duration_ms"], 1000.0);
+        assert_eq!(lines[0]["units"], json!({"duration_ms": "ms"}));
+        assert_ne!(lines[0]["data"]["peer"], "lab-7");
+        assert_eq!(lines[1]["event"], "complete");
+        assert_eq!(lines[1]["result_file"], "results/matter.json");
+        assert!("csv".parse::<StreamFormat>().is_err());
+    }
+}
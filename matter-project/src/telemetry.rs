@@ -0,0 +1,894 @@
+// matter-project/src/telemetry.rs
+/*!
+Periodic sensor telemetry - Matter subscription reports, MQTT publishes, CoAP observe and LwM2M notify
+
+Each run also splits the latency of the delivered samples along the send
+path, so a slowdown can be put down to one stage:
+
+- sender queue: from the sample coming due to its report's send, i.e.
+  behind a late timer or an unacknowledged report;
+- backpressure: inside the send call, waiting for socket buffer space;
+- network: from the send returning to the subscriber reading the report,
+  which includes time in the subscriber's socket buffer.
+
+The three add up to the sample's latency. The subscriber's own handling
+time is reported as the share of the run it was busy, and the depth of the
+device's queue is sampled at every send. The bottleneck is the receiver
+when it was busy for nine tenths of the run, otherwise the largest stage.
+
+Reports and acknowledgements are written into reused buffers and TCP
+frames are split off the read buffer without copying, so the streams can
+run at a few hundred thousand samples per second without allocating per
+message.
+*/
+
+use analysis_core::distribution::LatencyDistribution;
+use analysis_core::samples::{self, Layer};
+use anyhow::{anyhow, Result};
+use bytes::{Buf, BufMut, BytesMut};
+use metrics_core::tlv::{Tag, TlvWriter};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, timeout, MissedTickBehavior};
+
+use crate::bdx::{matter_message_into, MRP_STANDALONE_ACK, PROTOCOL_SECURE_CHANNEL};
+use crate::buffer_pool::BufferPool;
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{
+    coap_message_into, coap_parse, mqtt_fixed_header, mqtt_publish_into, mqtt_split, tlv_into, Carrier, COAP_ACK,
+    COAP_CON, COAP_CONTENT, COAP_NON, COAP_OPTION_CONTENT_FORMAT, COAP_OPTION_OBSERVE, IM_REPORT_DATA,
+    IM_STATUS_RESPONSE, MQTT_PUBACK, MQTT_PUBLISH_QOS1, PROTOCOL_IM,
+};
+use crate::transport_modes::{IPV4_TCP_HEADER_BYTES, IPV4_UDP_HEADER_BYTES};
+
+// Let in-flight samples land before the subscriber stops counting
+const DRAIN_DELAY: Duration = Duration::from_millis(50);
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Every report and acknowledgement fits; reads take at least this much buffer space
+const MESSAGE_CAPACITY: usize = 256;
+const READ_CHUNK: usize = 64 * 1024;
+
+/// A rate is sustained when this share of samples arrives within the latency bound,
+/// i.e. nothing is coalesced away and no backlog builds up.
+pub(crate) const SUSTAINED_DELIVERY_RATIO: f64 = 0.99;
+pub(crate) const SUSTAINED_P95_LATENCY: Duration = Duration::from_millis(50);
+
+/// A subscriber busy for this share of the run is the bottleneck.
+const RECEIVER_SATURATED_BUSY: f64 = 0.9;
+
+// Temperature Measurement cluster, MeasuredValue (int16, 0.01 °C)
+const TEMPERATURE_CLUSTER: u32 = 0x0402;
+const MEASURED_VALUE: u32 = 0x0000;
+const SENSOR_ENDPOINT: u16 = 1;
+const SUBSCRIPTION_ID: u32 = 0x5EB5_0001;
+const MESSAGE_COUNTER_OFFSET: usize = 4;
+
+// LwM2M Temperature object 3303, Sensor Value resource 5700
+const LWM2M_CONTENT_FORMAT_TLV: usize = 11542;
+const LWM2M_SENSOR_VALUE: u16 = 5700;
+
+const MQTT_TELEMETRY_TOPIC: &str = "sensors/dev1/temperature";
+const NO_OPTIONS: [(u16, &[u8]); 0] = [];
+
+#[derive(Debug, Clone)]
+pub struct TelemetryConfig {
+    /// Samples per second emitted by the device.
+    pub rates_hz: Vec<u32>,
+    pub duration: Duration,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            rates_hz: vec![1, 10, 100, 1000],
+            duration: Duration::from_secs(2),
+        }
+    }
+}
+
+analyzer_builder!(TelemetryStreamerBuilder => TelemetryStreamer(TelemetryConfig) {
+    rates_hz: Vec<u32>,
+    duration: Duration,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TelemetryMetrics {
+    pub duration_ms: f64,
+    pub sustained_delivery_ratio: f64,
+    pub sustained_p95_latency_ms: f64,
+    pub protocols: Vec<TelemetryProtocolResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TelemetryProtocolResult {
+    pub protocol: String,
+    pub transport: String,
+    /// Highest configured rate that was sustained; zero when none was.
+    pub sustainable_rate_hz: u32,
+    pub runs: Vec<TelemetryRun>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TelemetryRun {
+    pub target_rate_hz: u32,
+    pub samples_generated: usize,
+    pub samples_delivered: usize,
+    /// Samples replaced by a newer value while an earlier report awaited its acknowledgement.
+    pub samples_coalesced: usize,
+    pub delivered_rate_hz: f64,
+    pub delivery_ratio: f64,
+    /// Sample generation to arrival at the subscriber.
+    pub latency_mean_ms: f64,
+    pub latency_p95_ms: f64,
+    pub latency_max_ms: f64,
+    /// Jitter and shape of the sample latencies, in arrival order.
+    pub latency_distribution: LatencyDistribution,
+    /// Messages in both directions (reports plus acknowledgements) per delivered sample.
+    pub messages_per_sample: f64,
+    /// Application-layer bytes in both directions per delivered sample.
+    pub message_bytes_per_sample: f64,
+    /// As above, with IPv4 and UDP/TCP headers (TCP pure ACKs excluded).
+    pub wire_bytes_per_sample: f64,
+    pub send_path: SendPathMetrics,
+    pub sustained: bool,
+}
+
+/// Where delivered samples spent their latency, and how far the device's queue backed up.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SendPathMetrics {
+    /// Sample coming due to the start of its report's send.
+    pub sender_queue_mean_ms: f64,
+    pub sender_queue_p95_ms: f64,
+    /// Inside the send call, waiting for socket buffer space.
+    pub backpressure_wait_mean_ms: f64,
+    pub backpressure_wait_max_ms: f64,
+    /// Send returned to the subscriber reading the report.
+    pub network_mean_ms: f64,
+    /// Share of the run the subscriber spent handling reports and writing acknowledgements.
+    pub receiver_busy_ratio: f64,
+    /// Samples due but neither sent nor coalesced behind each report sent.
+    pub queue_depth_mean: f64,
+    pub queue_depth_max: usize,
+    /// Report buffers the device allocated; one while each report is sent before the next is built.
+    pub buffers_allocated: u64,
+    /// "sender queue", "backpressure", "network" or "receiver".
+    pub bottleneck: String,
+}
+
+pub struct TelemetryStreamer {
+    config: TelemetryConfig,
+}
+
+impl TelemetryStreamer {
+    pub fn new(config: TelemetryConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_telemetry(&mut self) -> Result<TelemetryMetrics> {
+        println!("\n🌡️ Analyzing Telemetry Streaming ({:?} samples/s)", self.config.rates_hz);
+        println!("------------------------------------------------");
+
+        let mut protocols = Vec::new();
+        for stream in telemetry_streams() {
+            let mut runs = Vec::new();
+            for &rate in &self.config.rates_hz {
+                if rate == 0 {
+                    continue;
+                }
+                let run = run_stream(&stream, rate, self.config.duration).await?;
+                println!("✅ {} @ {}/s: {:.1}/s delivered, {:.2}ms p95, {:.0}B/sample on the wire{}",
+                         stream.name(), rate, run.delivered_rate_hz, run.latency_p95_ms, run.wire_bytes_per_sample,
+                         if run.sustained {
+                             String::new()
+                         } else {
+                             format!(" (not sustained, {} bound)", run.send_path.bottleneck)
+                         });
+                runs.push(run);
+            }
+
+            protocols.push(TelemetryProtocolResult {
+                protocol: stream.name().to_string(),
+                transport: match stream.carrier() {
+                    Carrier::Udp => "UDP",
+                    Carrier::Tcp => "TCP",
+                }
+                .to_string(),
+                sustainable_rate_hz: runs.iter().filter(|r| r.sustained).map(|r| r.target_rate_hz).max().unwrap_or(0),
+                runs,
+            });
+        }
+
+        Ok(TelemetryMetrics {
+            duration_ms: self.config.duration.as_secs_f64() * 1000.0,
+            sustained_delivery_ratio: SUSTAINED_DELIVERY_RATIO,
+            sustained_p95_latency_ms: SUSTAINED_P95_LATENCY.as_secs_f64() * 1000.0,
+            protocols,
+        })
+    }
+}
+
+/// Every telemetry mechanism, in the order results list them.
+pub(crate) fn telemetry_streams() -> Vec<Arc<dyn TelemetryStream>> {
+    vec![Arc::new(MatterSubscription), Arc::new(MqttPublish), Arc::new(CoapObserve), Arc::new(Lwm2mNotify)]
+}
+
+/// Message formats of one telemetry mechanism; the device pushes each sample
+/// to a subscriber that acknowledges it where the protocol requires.
+pub(crate) trait TelemetryStream: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn carrier(&self) -> Carrier;
+    /// Whether the device holds back new samples until the previous report is acknowledged.
+    fn one_outstanding(&self) -> bool;
+    /// Appends the report of one sample to `out`.
+    fn write_report(&self, sequence: u32, value: i16, out: &mut BytesMut) -> Result<()>;
+    fn parse_report(&self, message: &[u8]) -> Option<u32>;
+    /// Appends the subscriber's acknowledgement of a report; false when there is none.
+    fn write_acknowledgement(&self, sequence: u32, out: &mut BytesMut) -> bool;
+    /// Appends what the device sends when an acknowledgement arrives (e.g. an
+    /// MRP standalone ack); false when it sends nothing.
+    fn write_acknowledgement_reply(&self, _out: &mut BytesMut) -> bool {
+        false
+    }
+    /// Length of the first complete message in a TCP stream buffer.
+    fn frame_len(&self, _buffer: &[u8]) -> Option<usize> {
+        None
+    }
+
+    fn report(&self, sequence: u32, value: i16) -> Result<Vec<u8>> {
+        let mut out = BytesMut::new();
+        self.write_report(sequence, value, &mut out)?;
+        Ok(out.to_vec())
+    }
+
+    fn acknowledgement(&self, sequence: u32) -> Option<Vec<u8>> {
+        let mut out = BytesMut::new();
+        self.write_acknowledgement(sequence, &mut out).then(|| out.to_vec())
+    }
+}
+
+/// Arrival times and message counts seen by the subscriber.
+#[derive(Default)]
+struct SubscriberLog {
+    arrivals: Vec<(u32, Instant)>,
+    messages: usize,
+    bytes: usize,
+    /// Time spent handling what was read, acknowledgements included.
+    busy: Duration,
+}
+
+impl SubscriberLog {
+    /// Logs a message and writes its acknowledgement, if any, into the cleared `ack`.
+    fn handle(&mut self, stream: &dyn TelemetryStream, message: &[u8], ack: &mut BytesMut) -> bool {
+        self.messages += 1;
+        self.bytes += message.len();
+        let Some(sequence) = stream.parse_report(message) else {
+            return false;
+        };
+        self.arrivals.push((sequence, Instant::now()));
+        ack.clear();
+        if !stream.write_acknowledgement(sequence, ack) {
+            return false;
+        }
+        self.messages += 1;
+        self.bytes += ack.len();
+        true
+    }
+}
+
+struct Subscriber {
+    stop: oneshot::Sender<()>,
+    handle: JoinHandle<Result<SubscriberLog>>,
+}
+
+impl Subscriber {
+    async fn spawn(stream: Arc<dyn TelemetryStream>) -> Result<(SocketAddr, Self)> {
+        let (stop, mut stopped) = oneshot::channel();
+        let (addr, handle) = match stream.carrier() {
+            Carrier::Udp => {
+                let socket = UdpSocket::bind("127.0.0.1:0").await?;
+                let addr = socket.local_addr()?;
+                let handle = tokio::spawn(async move {
+                    let mut log = SubscriberLog::default();
+                    let mut buffer = vec![0u8; 2048];
+                    let mut ack = BytesMut::with_capacity(MESSAGE_CAPACITY);
+                    loop {
+                        tokio::select! {
+                            _ = &mut stopped => return Ok(log),
+                            received = socket.recv_from(&mut buffer) => {
+                                let (len, peer) = received?;
+                                let started = Instant::now();
+                                if log.handle(stream.as_ref(), &buffer[..len], &mut ack) {
+                                    socket.send_to(&ack, peer).await?;
+                                }
+                                log.busy += started.elapsed();
+                            }
+                        }
+                    }
+                });
+                (addr, handle)
+            }
+            Carrier::Tcp => {
+                let listener = TcpListener::bind("127.0.0.1:0").await?;
+                let addr = listener.local_addr()?;
+                let handle = tokio::spawn(async move {
+                    let mut log = SubscriberLog::default();
+                    let mut connection = tokio::select! {
+                        _ = &mut stopped => return Ok(log),
+                        accepted = listener.accept() => accepted?.0,
+                    };
+                    connection.set_nodelay(true)?;
+                    let mut pending = BytesMut::with_capacity(READ_CHUNK);
+                    let mut ack = BytesMut::with_capacity(MESSAGE_CAPACITY);
+                    loop {
+                        // Reclaims the space of frames already split off and dropped
+                        pending.reserve(READ_CHUNK);
+                        tokio::select! {
+                            _ = &mut stopped => return Ok(log),
+                            read = connection.read_buf(&mut pending) => {
+                                if read? == 0 {
+                                    return Ok(log);
+                                }
+                                let started = Instant::now();
+                                while let Some(len) = stream.frame_len(&pending) {
+                                    let message = pending.split_to(len);
+                                    if log.handle(stream.as_ref(), &message, &mut ack) {
+                                        connection.write_all(&ack).await?;
+                                    }
+                                }
+                                log.busy += started.elapsed();
+                            }
+                        }
+                    }
+                });
+                (addr, handle)
+            }
+        };
+        Ok((addr, Self { stop, handle }))
+    }
+
+    async fn finish(self) -> Result<SubscriberLog> {
+        let _ = self.stop.send(());
+        self.handle.await?
+    }
+}
+
+/// Device side of the stream.
+enum DeviceLink {
+    Udp(UdpSocket),
+    Tcp(TcpStream, BytesMut),
+}
+
+impl DeviceLink {
+    async fn connect(carrier: Carrier, subscriber: SocketAddr) -> Result<Self> {
+        match carrier {
+            Carrier::Udp => {
+                let socket = UdpSocket::bind("127.0.0.1:0").await?;
+                socket.connect(subscriber).await?;
+                Ok(DeviceLink::Udp(socket))
+            }
+            Carrier::Tcp => {
+                let stream = TcpStream::connect(subscriber).await?;
+                stream.set_nodelay(true)?;
+                Ok(DeviceLink::Tcp(stream, BytesMut::with_capacity(READ_CHUNK)))
+            }
+        }
+    }
+
+    async fn send(&mut self, message: &[u8]) -> Result<()> {
+        match self {
+            DeviceLink::Udp(socket) => {
+                socket.send(message).await?;
+            }
+            DeviceLink::Tcp(stream, _) => stream.write_all(message).await?,
+        }
+        Ok(())
+    }
+
+    /// Waits for data from the subscriber and returns the acknowledgements it completes.
+    /// Cancel-safe, so it can race the sample timer.
+    async fn receive(&mut self, stream: &dyn TelemetryStream, buffer: &mut [u8]) -> Result<usize> {
+        match self {
+            DeviceLink::Udp(socket) => {
+                socket.recv(buffer).await?;
+                Ok(1)
+            }
+            DeviceLink::Tcp(connection, pending) => {
+                pending.reserve(READ_CHUNK);
+                if connection.read_buf(pending).await? == 0 {
+                    return Err(anyhow!("{}: subscriber closed the connection", stream.name()));
+                }
+                let mut acks = 0;
+                while let Some(len) = stream.frame_len(pending) {
+                    pending.advance(len);
+                    acks += 1;
+                }
+                Ok(acks)
+            }
+        }
+    }
+}
+
+enum DeviceEvent {
+    Tick(Instant),
+    Acknowledged(usize),
+}
+
+/// When each sample came due and when its report was sent.
+struct DeviceLog {
+    generated: Vec<Instant>,
+    /// Start and end of the send call, by sequence; `None` for coalesced samples.
+    sends: Vec<Option<(Instant, Instant)>>,
+    sent: usize,
+    coalesced: usize,
+    /// Samples due but neither sent nor coalesced, behind each report sent.
+    queue_depths: Vec<usize>,
+    pool: BufferPool,
+}
+
+impl DeviceLog {
+    fn new(samples: usize) -> Self {
+        Self {
+            generated: Vec::with_capacity(samples),
+            sends: vec![None; samples],
+            sent: 0,
+            coalesced: 0,
+            queue_depths: Vec::new(),
+            pool: BufferPool::new(MESSAGE_CAPACITY),
+        }
+    }
+
+    async fn send_report(
+        &mut self,
+        stream: &dyn TelemetryStream,
+        link: &mut DeviceLink,
+        sequence: u32,
+        period: Duration,
+    ) -> Result<()> {
+        let mut report = self.pool.take();
+        stream.write_report(sequence, sample_value(sequence), &mut report)?;
+        let start = Instant::now();
+        // Ticks the timer owes are due too, even before it has caught up with them
+        let due = (start.saturating_duration_since(self.generated[0]).as_secs_f64() / period.as_secs_f64()) as usize + 1;
+        self.queue_depths.push(due.min(self.sends.len()).saturating_sub(self.sent + self.coalesced + 1));
+        link.send(&report).await?;
+        self.sends[sequence as usize] = Some((start, Instant::now()));
+        self.sent += 1;
+        self.pool.put(report);
+        Ok(())
+    }
+
+    async fn send_acknowledgement_reply(&mut self, stream: &dyn TelemetryStream, link: &mut DeviceLink) -> Result<()> {
+        let mut reply = self.pool.take();
+        if stream.write_acknowledgement_reply(&mut reply) {
+            link.send(&reply).await?;
+        }
+        self.pool.put(reply);
+        Ok(())
+    }
+}
+
+pub(crate) async fn run_stream(stream: &Arc<dyn TelemetryStream>, rate_hz: u32, duration: Duration) -> Result<TelemetryRun> {
+    let (addr, subscriber) = Subscriber::spawn(stream.clone()).await?;
+    let mut link = DeviceLink::connect(stream.carrier(), addr).await?;
+    let result = drive_stream(stream.as_ref(), &mut link, rate_hz, duration).await;
+    // Keep the link open so acknowledgements still in flight can be written
+    tokio::time::sleep(DRAIN_DELAY).await;
+    let log = subscriber.finish().await?;
+    drop(link);
+    Ok(summarize(stream.as_ref(), rate_hz, duration, &result?, log))
+}
+
+/// Emits `rate_hz` samples per second for `duration`.
+async fn drive_stream(
+    stream: &dyn TelemetryStream,
+    link: &mut DeviceLink,
+    rate_hz: u32,
+    duration: Duration,
+) -> Result<DeviceLog> {
+    let samples = ((rate_hz as f64 * duration.as_secs_f64()).round() as usize).max(1);
+    let mut buffer = vec![0u8; 2048];
+
+    let mut device = DeviceLog::new(samples);
+    let mut outstanding = false;
+    let mut held: Option<u32> = None;
+
+    // A late timer bursts to catch up, keeping the scheduled generation times
+    let period = Duration::from_secs_f64(1.0 / rate_hz as f64);
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Burst);
+
+    while device.generated.len() < samples || outstanding {
+        let event = if device.generated.len() < samples {
+            tokio::select! {
+                tick = ticker.tick() => DeviceEvent::Tick(tick.into_std()),
+                acks = link.receive(stream, &mut buffer) => DeviceEvent::Acknowledged(acks?),
+            }
+        } else {
+            match timeout(ACK_TIMEOUT, link.receive(stream, &mut buffer)).await {
+                Ok(acks) => DeviceEvent::Acknowledged(acks?),
+                Err(_) => return Err(anyhow!("{}: last report was never acknowledged", stream.name())),
+            }
+        };
+
+        match event {
+            DeviceEvent::Tick(at) => {
+                let sequence = device.generated.len() as u32;
+                device.generated.push(at);
+                if outstanding {
+                    if held.replace(sequence).is_some() {
+                        device.coalesced += 1;
+                    }
+                } else {
+                    device.send_report(stream, link, sequence, period).await?;
+                    outstanding = stream.one_outstanding();
+                }
+            }
+            DeviceEvent::Acknowledged(0) => {}
+            DeviceEvent::Acknowledged(_) => {
+                device.send_acknowledgement_reply(stream, link).await?;
+                if !stream.one_outstanding() {
+                    continue;
+                }
+                outstanding = false;
+                // Report the newest value once the previous report is acknowledged
+                if let Some(sequence) = held.take() {
+                    device.send_report(stream, link, sequence, period).await?;
+                    outstanding = true;
+                }
+            }
+        }
+    }
+
+    Ok(device)
+}
+
+/// Formats into `buffer`, so per-sample text bodies need no allocation.
+fn format_into<'a>(buffer: &'a mut [u8], args: std::fmt::Arguments) -> Result<&'a [u8]> {
+    let capacity = buffer.len();
+    let mut rest = &mut buffer[..];
+    rest.write_fmt(args)?;
+    let written = capacity - rest.len();
+    Ok(&buffer[..written])
+}
+
+/// CoAP uint option value: big-endian without leading zeros.
+fn coap_uint_bytes(bytes: &[u8]) -> &[u8] {
+    &bytes[bytes.iter().take_while(|b| **b == 0).count()..]
+}
+
+/// Slowly varying temperature in 0.01 °C.
+fn sample_value(sequence: u32) -> i16 {
+    2150 + (sequence % 64) as i16 - 32
+}
+
+fn summarize(
+    stream: &dyn TelemetryStream,
+    rate_hz: u32,
+    duration: Duration,
+    device: &DeviceLog,
+    log: SubscriberLog,
+) -> TelemetryRun {
+    let generated = &device.generated;
+    let mut latencies: Vec<f64> = log
+        .arrivals
+        .iter()
+        .filter_map(|(sequence, arrived)| {
+            let at = generated.get(*sequence as usize)?;
+            Some(arrived.saturating_duration_since(*at).as_secs_f64() * 1000.0)
+        })
+        .collect();
+    samples::record("telemetry", Layer::Application, stream.name(), &format!("{} Hz", rate_hz), "latency_ms", &latencies);
+    let latency_distribution = LatencyDistribution::from_samples(&latencies);
+    latencies.sort_by(|a, b| a.total_cmp(b));
+
+    let delivered = latencies.len();
+    let per_sample = |total: usize| total as f64 / delivered.max(1) as f64;
+    let header_bytes = match stream.carrier() {
+        Carrier::Udp => IPV4_UDP_HEADER_BYTES,
+        Carrier::Tcp => IPV4_TCP_HEADER_BYTES,
+    };
+    let latency_p95_ms = latencies
+        .get(((delivered.max(1) - 1) as f64 * 0.95).round() as usize)
+        .copied()
+        .unwrap_or(0.0);
+    let delivery_ratio = delivered as f64 / generated.len().max(1) as f64;
+
+    TelemetryRun {
+        target_rate_hz: rate_hz,
+        samples_generated: generated.len(),
+        samples_delivered: delivered,
+        samples_coalesced: device.coalesced,
+        delivered_rate_hz: delivered as f64 / duration.as_secs_f64(),
+        delivery_ratio,
+        latency_mean_ms: latencies.iter().sum::<f64>() / delivered.max(1) as f64,
+        latency_p95_ms,
+        latency_max_ms: latencies.last().copied().unwrap_or(0.0),
+        latency_distribution,
+        messages_per_sample: per_sample(log.messages),
+        message_bytes_per_sample: per_sample(log.bytes),
+        wire_bytes_per_sample: per_sample(log.bytes + log.messages * header_bytes),
+        send_path: send_path(device, &log, duration),
+        sustained: delivery_ratio >= SUSTAINED_DELIVERY_RATIO
+            && latency_p95_ms < SUSTAINED_P95_LATENCY.as_secs_f64() * 1000.0,
+    }
+}
+
+fn send_path(device: &DeviceLog, log: &SubscriberLog, duration: Duration) -> SendPathMetrics {
+    let milliseconds = |from: Instant, to: Instant| to.saturating_duration_since(from).as_secs_f64() * 1000.0;
+    let (mut sender_queue, mut backpressure, mut network) = (vec![], vec![], vec![]);
+    for (sequence, arrived) in &log.arrivals {
+        let index = *sequence as usize;
+        let (Some(at), Some(Some((start, end)))) = (device.generated.get(index), device.sends.get(index)) else {
+            continue;
+        };
+        sender_queue.push(milliseconds(*at, *start));
+        backpressure.push(milliseconds(*start, *end));
+        network.push(milliseconds(*end, *arrived));
+    }
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len().max(1) as f64;
+    let (sender_queue_mean_ms, backpressure_wait_mean_ms, network_mean_ms) =
+        (mean(&sender_queue), mean(&backpressure), mean(&network));
+    sender_queue.sort_by(f64::total_cmp);
+
+    let receiver_busy_ratio = (log.busy.as_secs_f64() / duration.as_secs_f64()).min(1.0);
+    let bottleneck = if receiver_busy_ratio >= RECEIVER_SATURATED_BUSY {
+        "receiver"
+    } else {
+        [("sender queue", sender_queue_mean_ms), ("backpressure", backpressure_wait_mean_ms), ("network", network_mean_ms)]
+            .into_iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or("network", |(stage, _)| stage)
+    };
+
+    SendPathMetrics {
+        sender_queue_mean_ms,
+        sender_queue_p95_ms: sender_queue
+            .get(((sender_queue.len().max(1) - 1) as f64 * 0.95).round() as usize)
+            .copied()
+            .unwrap_or(0.0),
+        backpressure_wait_mean_ms,
+        backpressure_wait_max_ms: backpressure.iter().copied().fold(0.0, f64::max),
+        network_mean_ms,
+        receiver_busy_ratio,
+        queue_depth_mean: device.queue_depths.iter().sum::<usize>() as f64 / device.queue_depths.len().max(1) as f64,
+        queue_depth_max: device.queue_depths.iter().copied().max().unwrap_or(0),
+        buffers_allocated: device.pool.stats().allocated,
+        bottleneck: bottleneck.to_string(),
+    }
+}
+
+/// Subscription ReportData carrying MeasuredValue over UDP. Each report waits
+/// for the subscriber's StatusResponse (the device then sends an MRP
+/// standalone ack), so newer samples replace any value still waiting.
+/// The message counter carries the sample sequence.
+pub(crate) struct MatterSubscription;
+
+impl TelemetryStream for MatterSubscription {
+    fn name(&self) -> &'static str {
+        "Matter subscription"
+    }
+
+    fn carrier(&self) -> Carrier {
+        Carrier::Udp
+    }
+
+    fn one_outstanding(&self) -> bool {
+        true
+    }
+
+    fn write_report(&self, sequence: u32, value: i16, out: &mut BytesMut) -> Result<()> {
+        let mut payload = [0u8; MESSAGE_CAPACITY];
+        let mut rest = &mut payload[..];
+        tlv_into(&mut rest, |writer: &mut TlvWriter| {
+            writer.start_struct(Tag::Anonymous)?;
+            writer.u32(Tag::Context(0), SUBSCRIPTION_ID)?;
+            writer.start_array(Tag::Context(1))?; // AttributeReportIBs
+            writer.start_struct(Tag::Anonymous)?;
+            writer.start_struct(Tag::Context(1))?; // AttributeDataIB
+            writer.u32(Tag::Context(0), sequence)?; // DataVersion
+            writer.start_list(Tag::Context(1))?;
+            writer.u16(Tag::Context(2), SENSOR_ENDPOINT)?;
+            writer.u32(Tag::Context(3), TEMPERATURE_CLUSTER)?;
+            writer.u32(Tag::Context(4), MEASURED_VALUE)?;
+            writer.end_container()?;
+            writer.i16(Tag::Context(2), value)?;
+            writer.end_container()?;
+            writer.end_container()?;
+            writer.end_container()?;
+            writer.u8(Tag::Context(255), 11)?;
+            writer.end_container()
+        })?;
+        let payload_len = MESSAGE_CAPACITY - rest.len();
+        let start = out.len();
+        matter_message_into(out, PROTOCOL_IM, IM_REPORT_DATA, &payload[..payload_len]);
+        let counter = start + MESSAGE_COUNTER_OFFSET;
+        out[counter..counter + 4].copy_from_slice(&(sequence + 1).to_le_bytes());
+        Ok(())
+    }
+
+    fn parse_report(&self, message: &[u8]) -> Option<u32> {
+        if message.get(9)? != &IM_REPORT_DATA || message.get(12..14)? != PROTOCOL_IM.to_le_bytes() {
+            return None;
+        }
+        let counter = u32::from_le_bytes(message.get(MESSAGE_COUNTER_OFFSET..MESSAGE_COUNTER_OFFSET + 4)?.try_into().ok()?);
+        counter.checked_sub(1)
+    }
+
+    fn write_acknowledgement(&self, _sequence: u32, out: &mut BytesMut) -> bool {
+        matter_message_into(out, PROTOCOL_IM, IM_STATUS_RESPONSE, &[0x15, 0x24, 0x00, 0x00, 0x18]);
+        true
+    }
+
+    fn write_acknowledgement_reply(&self, out: &mut BytesMut) -> bool {
+        matter_message_into(out, PROTOCOL_SECURE_CHANNEL, MRP_STANDALONE_ACK, &[]);
+        true
+    }
+}
+
+/// QoS 1 PUBLISH per sample with a small JSON body carrying the sequence.
+/// PUBACKs come back asynchronously, so publishes are pipelined.
+pub(crate) struct MqttPublish;
+
+fn mqtt_packet_id(sequence: u32) -> u16 {
+    (sequence % 0xFFFF) as u16 + 1
+}
+
+impl TelemetryStream for MqttPublish {
+    fn name(&self) -> &'static str {
+        "MQTT publish (QoS 1)"
+    }
+
+    fn carrier(&self) -> Carrier {
+        Carrier::Tcp
+    }
+
+    fn one_outstanding(&self) -> bool {
+        false
+    }
+
+    fn write_report(&self, sequence: u32, value: i16, out: &mut BytesMut) -> Result<()> {
+        let mut body = [0u8; 64];
+        let body = format_into(&mut body, format_args!(r#"{{"seq":{},"t":{:.2}}}"#, sequence, value as f64 / 100.0))?;
+        mqtt_publish_into(out, MQTT_TELEMETRY_TOPIC, Some(mqtt_packet_id(sequence)), body);
+        Ok(())
+    }
+
+    fn parse_report(&self, message: &[u8]) -> Option<u32> {
+        let (header, body) = mqtt_split(message)?;
+        if header != MQTT_PUBLISH_QOS1 {
+            return None;
+        }
+        let topic_len = u16::from_be_bytes(body.get(..2)?.try_into().ok()?) as usize;
+        let payload: serde_json::Value = serde_json::from_slice(body.get(4 + topic_len..)?).ok()?;
+        payload.get("seq")?.as_u64().map(|sequence| sequence as u32)
+    }
+
+    fn write_acknowledgement(&self, sequence: u32, out: &mut BytesMut) -> bool {
+        mqtt_fixed_header(out, MQTT_PUBACK, 2);
+        out.put_u16(mqtt_packet_id(sequence));
+        true
+    }
+
+    fn frame_len(&self, buffer: &[u8]) -> Option<usize> {
+        let (_, body) = mqtt_split(buffer)?;
+        Some(body.as_ptr() as usize - buffer.as_ptr() as usize + body.len())
+    }
+}
+
+fn observe_sequence(message: &[u8]) -> Option<u32> {
+    let message = coap_parse(message)?;
+    if message.code != COAP_CONTENT {
+        return None;
+    }
+    let (_, value) = message.options.iter().find(|(number, _)| *number == COAP_OPTION_OBSERVE)?;
+    Some(value.iter().fold(0u32, |acc, b| acc << 8 | *b as u32))
+}
+
+/// Plain CoAP observe: NON 2.05 notifications with a text value; the Observe
+/// option carries the sequence and nothing is acknowledged.
+pub(crate) struct CoapObserve;
+
+impl TelemetryStream for CoapObserve {
+    fn name(&self) -> &'static str {
+        "CoAP observe (NON)"
+    }
+
+    fn carrier(&self) -> Carrier {
+        Carrier::Udp
+    }
+
+    fn one_outstanding(&self) -> bool {
+        false
+    }
+
+    fn write_report(&self, sequence: u32, value: i16, out: &mut BytesMut) -> Result<()> {
+        let observe = sequence.to_be_bytes();
+        let options = [
+            (COAP_OPTION_OBSERVE, coap_uint_bytes(&observe)),
+            (COAP_OPTION_CONTENT_FORMAT, &[][..]), // text/plain
+        ];
+        let mut body = [0u8; 16];
+        let body = format_into(&mut body, format_args!("{:.2}", value as f64 / 100.0))?;
+        coap_message_into(out, COAP_NON, COAP_CONTENT, sequence as u16, &options, body);
+        Ok(())
+    }
+
+    fn parse_report(&self, message: &[u8]) -> Option<u32> {
+        observe_sequence(message)
+    }
+
+    fn write_acknowledgement(&self, _sequence: u32, _out: &mut BytesMut) -> bool {
+        false
+    }
+}
+
+/// LwM2M Information Reporting: confirmable notifications of 3303/0/5700 in
+/// LwM2M TLV. With NSTART = 1 only one CON is outstanding, so newer samples
+/// replace any value waiting for the ACK.
+pub(crate) struct Lwm2mNotify;
+
+impl TelemetryStream for Lwm2mNotify {
+    fn name(&self) -> &'static str {
+        "LwM2M notify (CON)"
+    }
+
+    fn carrier(&self) -> Carrier {
+        Carrier::Udp
+    }
+
+    fn one_outstanding(&self) -> bool {
+        true
+    }
+
+    fn write_report(&self, sequence: u32, value: i16, out: &mut BytesMut) -> Result<()> {
+        let (observe, content_format) = (sequence.to_be_bytes(), LWM2M_CONTENT_FORMAT_TLV.to_be_bytes());
+        let options = [
+            (COAP_OPTION_OBSERVE, coap_uint_bytes(&observe)),
+            (COAP_OPTION_CONTENT_FORMAT, coap_uint_bytes(&content_format)),
+        ];
+        // Resource value, 16-bit identifier, 4-byte length in the type byte
+        let mut body = [0xE4, 0, 0, 0, 0, 0, 0];
+        body[1..3].copy_from_slice(&LWM2M_SENSOR_VALUE.to_be_bytes());
+        body[3..].copy_from_slice(&(value as f32 / 100.0).to_be_bytes());
+        coap_message_into(out, COAP_CON, COAP_CONTENT, sequence as u16, &options, &body);
+        Ok(())
+    }
+
+    fn parse_report(&self, message: &[u8]) -> Option<u32> {
+        observe_sequence(message)
+    }
+
+    fn write_acknowledgement(&self, sequence: u32, out: &mut BytesMut) -> bool {
+        coap_message_into(out, COAP_ACK, 0x00, sequence as u16, &NO_OPTIONS, &[]);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_path_stages_add_up_to_the_latency() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        for stream in telemetry_streams() {
+            let run = runtime.block_on(run_stream(&stream, 200, Duration::from_millis(200))).unwrap();
+            let path = &run.send_path;
+            let stages = path.sender_queue_mean_ms + path.backpressure_wait_mean_ms + path.network_mean_ms;
+            assert!((stages - run.latency_mean_ms).abs() < 1e-6, "{}: {:?}", stream.name(), run);
+            assert!(path.backpressure_wait_max_ms >= path.backpressure_wait_mean_ms);
+            assert!((0.0..=1.0).contains(&path.receiver_busy_ratio));
+            assert!(["sender queue", "backpressure", "network", "receiver"].contains(&path.bottleneck.as_str()));
+            // Each report is sent before the next is built, so one buffer serves the whole run
+            assert_eq!(path.buffers_allocated, 1, "{}", stream.name());
+        }
+    }
+}
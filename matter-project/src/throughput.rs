@@ -0,0 +1,671 @@
+// matter-project/src/throughput.rs
+/*!
+UDP throughput with a dedicated receiver (loopback task or remote agent)
+
+The send loop reuses one payload, and the batched path reuses its
+sendmmsg/recvmmsg headers across calls, so nothing is allocated per packet.
+
+With the io_uring backend (Linux, `io-uring` feature) both ends run on a
+blocking thread. The sender keeps a ring of sends queued and refills it once
+half of them complete. The sink keeps one receive queued per buffer. Many
+datagrams then share one `io_uring_enter`, with no readiness round trip
+through epoll. The backend is recorded with the results.
+*/
+
+use analysis_core::platform::bind_udp_with_buffer;
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::io::Interest;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::builder::analyzer_builder;
+use crate::distributed::{ControlChannel, ControlMessage};
+
+// Let in-flight datagrams land before the receiver stops counting
+const DRAIN_DELAY: Duration = Duration::from_millis(50);
+/// io_uring sends or receives kept queued when `batch_size` is 1.
+const DEFAULT_URING_DEPTH: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct ThroughputConfig {
+    pub packet_sizes: Vec<usize>,
+    pub duration: Duration,
+    pub socket_buffer_bytes: usize,
+    /// Datagrams per sendmmsg/recvmmsg call; 1 uses plain send/recv.
+    /// With io_uring, the sends or receives kept queued.
+    pub batch_size: usize,
+    pub backend: ThroughputBackend,
+}
+
+impl Default for ThroughputConfig {
+    fn default() -> Self {
+        Self {
+            packet_sizes: vec![64, 512, 1024, 1400],
+            duration: Duration::from_millis(500),
+            socket_buffer_bytes: 4 * 1024 * 1024,
+            batch_size: 1,
+            backend: ThroughputBackend::Tokio,
+        }
+    }
+}
+
+analyzer_builder!(ThroughputTesterBuilder => ThroughputTester(ThroughputConfig, receiver: ThroughputReceiver) {
+    packet_sizes: Vec<usize>,
+    duration: Duration,
+    socket_buffer_bytes: usize,
+    batch_size: usize,
+    backend: ThroughputBackend,
+});
+
+/// How datagrams are sent and received.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThroughputBackend {
+    /// tokio sockets: readiness through epoll (kqueue, IOCP elsewhere), one syscall per send or batch.
+    #[default]
+    Tokio,
+    /// io_uring submission and completion queues on a blocking thread (Linux, `io-uring` feature).
+    IoUring,
+}
+
+impl ThroughputBackend {
+    pub const ALL: [ThroughputBackend; 2] = [ThroughputBackend::Tokio, ThroughputBackend::IoUring];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ThroughputBackend::Tokio => "tokio",
+            ThroughputBackend::IoUring => "io_uring",
+        }
+    }
+
+    /// Whether this build and platform can run the backend.
+    pub fn supported(self) -> bool {
+        match self {
+            ThroughputBackend::Tokio => true,
+            ThroughputBackend::IoUring => uring::SUPPORTED,
+        }
+    }
+}
+
+impl FromStr for ThroughputBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|backend| backend.name().eq_ignore_ascii_case(&s.replace('-', "_")))
+            .ok_or_else(|| anyhow!("unknown throughput backend: {} (expected tokio or io-uring)", s))
+    }
+}
+
+/// Where the measured datagrams are received.
+#[derive(Debug, Clone, Copy)]
+pub enum ThroughputReceiver {
+    Loopback,
+    Agent(SocketAddr),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ThroughputMetrics {
+    pub receiver: String,
+    /// "tokio" or "io_uring".
+    pub backend: String,
+    pub duration_ms: f64,
+    pub socket_buffer_bytes: usize,
+    pub batch_size: usize,
+    pub runs: Vec<ThroughputRun>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ThroughputRun {
+    pub packet_size_bytes: usize,
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub send_errors: u64,
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    pub receiver_drops: u64,
+    pub loss_rate: f64,
+    pub offered_mbps: f64,
+    pub goodput_mbps: f64,
+    pub sent_packets_per_second: f64,
+    pub received_packets_per_second: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SinkCounts {
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+/// Counts every datagram arriving on a socket until stopped.
+pub struct UdpSink {
+    stop: oneshot::Sender<()>,
+    handle: JoinHandle<SinkCounts>,
+}
+
+impl UdpSink {
+    /// `batch_size` > 1 drains the socket with recvmmsg where supported; with
+    /// io_uring it is the number of receives kept queued.
+    pub fn spawn(socket: UdpSocket, batch_size: usize, backend: ThroughputBackend) -> Result<Self> {
+        if !backend.supported() {
+            return Err(anyhow!("the {} backend is not available in this build", backend.name()));
+        }
+        if backend == ThroughputBackend::IoUring {
+            return Self::spawn_uring(socket, batch_size);
+        }
+        let (stop, mut stopped) = oneshot::channel();
+        let batched = batch_size > 1 && batch::SUPPORTED;
+
+        let handle = tokio::spawn(async move {
+            let mut counts = SinkCounts::default();
+            let mut buffers = vec![vec![0u8; 65536]; if batched { batch_size } else { 1 }];
+            let mut batch = batch::Batch::default();
+
+            loop {
+                tokio::select! {
+                    _ = &mut stopped => break,
+                    received = socket.recv_from(&mut buffers[0]), if !batched => match received {
+                        Ok((len, _)) => {
+                            counts.packets += 1;
+                            counts.bytes += len as u64;
+                        }
+                        Err(e) => debug!("Sink receive error: {}", e),
+                    },
+                    ready = socket.readable(), if batched => {
+                        if ready.is_err() {
+                            continue;
+                        }
+                        match socket.try_io(Interest::READABLE, || batch.recv(&socket, &mut buffers)) {
+                            Ok((packets, bytes)) => {
+                                counts.packets += packets;
+                                counts.bytes += bytes;
+                            }
+                            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                            Err(e) => debug!("Sink batch receive error: {}", e),
+                        }
+                    },
+                }
+            }
+
+            counts
+        });
+
+        Ok(Self { stop, handle })
+    }
+
+    fn spawn_uring(socket: UdpSocket, batch_size: usize) -> Result<Self> {
+        let (stop, stopped) = oneshot::channel();
+        // io_uring waits for data itself, so the socket goes back to blocking mode
+        let socket = socket.into_std()?;
+        socket.set_nonblocking(false)?;
+
+        let handle = tokio::task::spawn_blocking(move || {
+            let mut counts = SinkCounts::default();
+            let mut buffers = vec![vec![0u8; 65536]; uring_depth(batch_size)];
+            if let Err(e) = uring::sink(&socket, &mut buffers, stopped, &mut counts) {
+                debug!("io_uring sink error: {}", e);
+            }
+            counts
+        });
+
+        Ok(Self { stop, handle })
+    }
+
+    pub async fn finish(self) -> Result<SinkCounts> {
+        // The sink may already have exited; a closed channel is fine
+        let _ = self.stop.send(());
+        Ok(self.handle.await?)
+    }
+}
+
+pub struct ThroughputTester {
+    config: ThroughputConfig,
+    receiver: ThroughputReceiver,
+}
+
+impl ThroughputTester {
+    pub fn new(config: ThroughputConfig, receiver: ThroughputReceiver) -> Self {
+        Self { config, receiver }
+    }
+
+    pub async fn analyze_throughput(&mut self) -> Result<ThroughputMetrics> {
+        let backend = self.config.backend;
+        if !backend.supported() {
+            return Err(anyhow!("the io_uring backend requires Linux and a build with the io-uring feature"));
+        }
+        if backend == ThroughputBackend::Tokio && self.config.batch_size > 1 && !batch::SUPPORTED {
+            return Err(anyhow!("batched UDP (sendmmsg/recvmmsg) requires Linux"));
+        }
+
+        let receiver_name = match self.receiver {
+            ThroughputReceiver::Loopback => "loopback".to_string(),
+            ThroughputReceiver::Agent(addr) => format!("agent:{}", addr),
+        };
+
+        println!("\n📶 Analyzing UDP Throughput ({} receiver, {} backend)", receiver_name, backend.name());
+        println!("------------------------------------------------");
+
+        let mut runs = Vec::new();
+        for packet_size in self.config.packet_sizes.clone() {
+            let run = match self.receiver {
+                ThroughputReceiver::Loopback => self.run_loopback(packet_size).await?,
+                ThroughputReceiver::Agent(addr) => self.run_against_agent(addr, packet_size).await?,
+            };
+
+            println!("✅ {}B packets: goodput {:.1}Mbps ({:.0} pps), offered {:.1}Mbps, loss {:.2}%, {} send errors",
+                     run.packet_size_bytes, run.goodput_mbps, run.received_packets_per_second,
+                     run.offered_mbps, run.loss_rate * 100.0, run.send_errors);
+            runs.push(run);
+        }
+
+        Ok(ThroughputMetrics {
+            receiver: receiver_name,
+            backend: backend.name().to_string(),
+            duration_ms: self.config.duration.as_secs_f64() * 1000.0,
+            socket_buffer_bytes: self.config.socket_buffer_bytes,
+            batch_size: self.config.batch_size,
+            runs,
+        })
+    }
+
+    async fn run_loopback(&self, packet_size: usize) -> Result<ThroughputRun> {
+        let receiver = bind_udp_with_buffer("127.0.0.1:0".parse()?, self.config.socket_buffer_bytes)?;
+        let target = receiver.local_addr()?;
+        let sink = UdpSink::spawn(receiver, self.config.batch_size, self.config.backend)?;
+
+        let sent = self.blast(target, packet_size).await?;
+        tokio::time::sleep(DRAIN_DELAY).await;
+        let counts = sink.finish().await?;
+
+        Ok(self.summarize(packet_size, sent, counts))
+    }
+
+    async fn run_against_agent(&self, agent_addr: SocketAddr, packet_size: usize) -> Result<ThroughputRun> {
+        let mut channel = ControlChannel::new(TcpStream::connect(agent_addr).await?)?;
+        channel
+            .send(&ControlMessage::SinkStart { batch_size: self.config.batch_size, backend: self.config.backend })
+            .await?;
+
+        let udp_port = match channel.receive().await? {
+            Some(ControlMessage::SinkReady { udp_port }) => udp_port,
+            other => return Err(anyhow!("unexpected reply to sink start: {:?}", other)),
+        };
+
+        let sent = self.blast(SocketAddr::new(agent_addr.ip(), udp_port), packet_size).await?;
+        tokio::time::sleep(DRAIN_DELAY).await;
+        channel.send(&ControlMessage::SinkFinish).await?;
+
+        let counts = match channel.receive().await? {
+            Some(ControlMessage::SinkReport { packets, bytes }) => SinkCounts { packets, bytes },
+            other => return Err(anyhow!("unexpected reply to sink finish: {:?}", other)),
+        };
+        channel.send(&ControlMessage::Done).await?;
+
+        Ok(self.summarize(packet_size, sent, counts))
+    }
+
+    /// Sends back-to-back datagrams for the configured duration.
+    async fn blast(&self, target: SocketAddr, packet_size: usize) -> Result<SendCounts> {
+        let bind_addr: SocketAddr = if target.is_ipv4() { "0.0.0.0:0".parse()? } else { "[::]:0".parse()? };
+        let socket = bind_udp_with_buffer(bind_addr, self.config.socket_buffer_bytes)?;
+        socket.connect(target).await?;
+
+        let payload = vec![0xA5u8; packet_size];
+        if self.config.backend == ThroughputBackend::IoUring {
+            let socket = socket.into_std()?;
+            socket.set_nonblocking(false)?;
+            let (depth, duration) = (uring_depth(self.config.batch_size), self.config.duration);
+            let counts = tokio::task::spawn_blocking(move || uring::blast(&socket, &payload, depth, duration)).await??;
+            info!("Sent {} x {}B in {:.3}s (io_uring)", counts.packets, packet_size, counts.elapsed.as_secs_f64());
+            return Ok(counts);
+        }
+
+        let mut batch = batch::Batch::default();
+        let mut counts = SendCounts::default();
+        let start = Instant::now();
+
+        while start.elapsed() < self.config.duration {
+            let sent = if self.config.batch_size > 1 {
+                socket.writable().await?;
+                match socket.try_io(Interest::WRITABLE, || batch.send(&socket, &payload, self.config.batch_size)) {
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
+                    sent => sent,
+                }
+            } else {
+                socket.send(&payload).await.map(|_| 1)
+            };
+
+            match sent {
+                Ok(packets) => {
+                    counts.packets += packets as u64;
+                    counts.bytes += (packets * packet_size) as u64;
+                }
+                Err(e) => {
+                    counts.errors += 1;
+                    debug!("Throughput send error: {}", e);
+                }
+            }
+        }
+
+        counts.elapsed = start.elapsed();
+        info!("Sent {} x {}B in {:.3}s", counts.packets, packet_size, counts.elapsed.as_secs_f64());
+        Ok(counts)
+    }
+
+    fn summarize(&self, packet_size: usize, sent: SendCounts, received: SinkCounts) -> ThroughputRun {
+        let seconds = sent.elapsed.as_secs_f64().max(f64::EPSILON);
+        let receiver_drops = sent.packets.saturating_sub(received.packets);
+
+        ThroughputRun {
+            packet_size_bytes: packet_size,
+            packets_sent: sent.packets,
+            bytes_sent: sent.bytes,
+            send_errors: sent.errors,
+            packets_received: received.packets,
+            bytes_received: received.bytes,
+            receiver_drops,
+            loss_rate: if sent.packets > 0 { receiver_drops as f64 / sent.packets as f64 } else { 0.0 },
+            offered_mbps: sent.bytes as f64 * 8.0 / seconds / 1_000_000.0,
+            goodput_mbps: received.bytes as f64 * 8.0 / seconds / 1_000_000.0,
+            sent_packets_per_second: sent.packets as f64 / seconds,
+            received_packets_per_second: received.packets as f64 / seconds,
+        }
+    }
+}
+
+fn uring_depth(batch_size: usize) -> usize {
+    if batch_size > 1 { batch_size } else { DEFAULT_URING_DEPTH }
+}
+
+#[derive(Debug, Default)]
+struct SendCounts {
+    packets: u64,
+    bytes: u64,
+    errors: u64,
+    elapsed: Duration,
+}
+
+/// sendmmsg/recvmmsg wrappers for nonblocking tokio sockets.
+#[cfg(target_os = "linux")]
+mod batch {
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use tokio::net::UdpSocket;
+
+    pub const SUPPORTED: bool = true;
+
+    /// iovecs and message headers kept across calls, so a batch allocates
+    /// only when it outgrows every earlier one.
+    #[derive(Default)]
+    pub struct Batch {
+        iovecs: Vec<libc::iovec>,
+        headers: Vec<libc::mmsghdr>,
+    }
+
+    // SAFETY: the pointers are rewritten before every call and never read between calls
+    unsafe impl Send for Batch {}
+
+    impl Batch {
+        /// Sends up to `count` copies of `payload` on a connected socket in one call.
+        pub fn send(&mut self, socket: &UdpSocket, payload: &[u8], count: usize) -> io::Result<usize> {
+            self.iovecs.clear();
+            self.iovecs.extend(
+                (0..count).map(|_| libc::iovec { iov_base: payload.as_ptr() as *mut libc::c_void, iov_len: payload.len() }),
+            );
+            self.point_headers();
+
+            // SAFETY: every header points at one live iovec, which points at `payload`; the kernel only reads them
+            let sent = unsafe {
+                libc::sendmmsg(socket.as_raw_fd(), self.headers.as_mut_ptr(), self.headers.len() as libc::c_uint, 0)
+            };
+            if sent < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(sent as usize)
+        }
+
+        /// Receives up to one datagram per buffer; returns (packets, bytes).
+        pub fn recv(&mut self, socket: &UdpSocket, buffers: &mut [Vec<u8>]) -> io::Result<(u64, u64)> {
+            self.iovecs.clear();
+            self.iovecs.extend(
+                buffers
+                    .iter_mut()
+                    .map(|buffer| libc::iovec { iov_base: buffer.as_mut_ptr() as *mut libc::c_void, iov_len: buffer.len() }),
+            );
+            self.point_headers();
+
+            // SAFETY: every header points at one iovec over a distinct, live, writable buffer
+            let received = unsafe {
+                libc::recvmmsg(
+                    socket.as_raw_fd(),
+                    self.headers.as_mut_ptr(),
+                    self.headers.len() as libc::c_uint,
+                    libc::MSG_DONTWAIT,
+                    std::ptr::null_mut(),
+                )
+            };
+            if received < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let received = received as usize;
+            let bytes = self.headers[..received].iter().map(|header| header.msg_len as u64).sum();
+            Ok((received as u64, bytes))
+        }
+
+        /// One header per iovec, each pointing at its iovec.
+        fn point_headers(&mut self) {
+            self.headers.clear();
+            self.headers.extend(self.iovecs.iter_mut().map(|iov| {
+                // SAFETY: mmsghdr is plain old data; all-zero is a valid empty header
+                let mut header: libc::mmsghdr = unsafe { std::mem::zeroed() };
+                header.msg_hdr.msg_iov = iov;
+                header.msg_hdr.msg_iovlen = 1;
+                header
+            }));
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod batch {
+    use std::io;
+    use tokio::net::UdpSocket;
+
+    pub const SUPPORTED: bool = false;
+
+    #[derive(Default)]
+    pub struct Batch;
+
+    impl Batch {
+        pub fn send(&mut self, _socket: &UdpSocket, _payload: &[u8], _count: usize) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "sendmmsg requires Linux"))
+        }
+
+        pub fn recv(&mut self, _socket: &UdpSocket, _buffers: &mut [Vec<u8>]) -> io::Result<(u64, u64)> {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "recvmmsg requires Linux"))
+        }
+    }
+}
+
+/// io_uring send and receive loops, run on a blocking thread.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring {
+    use io_uring::{opcode, types, IoUring};
+    use std::io;
+    use std::net::UdpSocket;
+    use std::os::fd::AsRawFd;
+    use std::time::{Duration, Instant};
+    use tokio::sync::oneshot::{self, error::TryRecvError};
+
+    use super::{SendCounts, SinkCounts};
+
+    pub const SUPPORTED: bool = true;
+
+    // How often the sink looks for its stop signal while nothing arrives
+    const STOP_POLL: Duration = Duration::from_millis(10);
+    // Marks the user data of cancellations, whose completions are not receives
+    const CANCEL: u64 = 1 << 63;
+
+    /// Keeps up to `depth` sends of `payload` queued on a connected socket for `duration`.
+    pub fn blast(socket: &UdpSocket, payload: &[u8], depth: usize, duration: Duration) -> io::Result<SendCounts> {
+        let mut ring = IoUring::new(depth.next_power_of_two() as u32)?;
+        let fd = types::Fd(socket.as_raw_fd());
+        let send = opcode::Send::new(fd, payload.as_ptr(), payload.len() as u32).build();
+
+        let mut counts = SendCounts::default();
+        let mut in_flight = 0;
+        let start = Instant::now();
+        loop {
+            if start.elapsed() < duration {
+                while in_flight < depth {
+                    // SAFETY: `payload` outlives every queued send; the loop drains the ring before returning
+                    if unsafe { ring.submission().push(&send) }.is_err() {
+                        break;
+                    }
+                    in_flight += 1;
+                }
+            }
+            if in_flight == 0 {
+                break;
+            }
+            // Refill once half the ring is done, so a submit carries many sends
+            ring.submit_and_wait((in_flight / 2).max(1))?;
+            for completion in ring.completion() {
+                in_flight -= 1;
+                match completion.result() {
+                    sent if sent >= 0 => {
+                        counts.packets += 1;
+                        counts.bytes += sent as u64;
+                    }
+                    _ => counts.errors += 1,
+                }
+            }
+        }
+
+        counts.elapsed = start.elapsed();
+        Ok(counts)
+    }
+
+    /// Keeps one receive queued per buffer until `stopped` fires, counting into `counts`.
+    pub fn sink(
+        socket: &UdpSocket,
+        buffers: &mut [Vec<u8>],
+        mut stopped: oneshot::Receiver<()>,
+        counts: &mut SinkCounts,
+    ) -> io::Result<()> {
+        let mut ring = IoUring::new(buffers.len().next_power_of_two() as u32)?;
+        let fd = types::Fd(socket.as_raw_fd());
+        let receive = |index: usize, buffer: &mut Vec<u8>| {
+            opcode::Recv::new(fd, buffer.as_mut_ptr(), buffer.len() as u32).build().user_data(index as u64)
+        };
+        for (index, buffer) in buffers.iter_mut().enumerate() {
+            // SAFETY: `buffers` outlives every queued receive; all are cancelled and reaped before returning
+            unsafe { ring.submission().push(&receive(index, buffer)) }.map_err(io::Error::other)?;
+        }
+
+        let timeout = types::Timespec::from(STOP_POLL);
+        let args = types::SubmitArgs::new().timespec(&timeout);
+        let mut done = Vec::with_capacity(buffers.len());
+        let mut result = Ok(());
+        while matches!(stopped.try_recv(), Err(TryRecvError::Empty)) {
+            match ring.submitter().submit_with_args(1, &args) {
+                Ok(_) => {}
+                Err(e) if matches!(e.raw_os_error(), Some(libc::ETIME) | Some(libc::EINTR)) => {}
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+            done.extend(ring.completion().map(|completion| (completion.user_data() as usize, completion.result())));
+            for (index, received) in done.drain(..) {
+                if received >= 0 {
+                    counts.packets += 1;
+                    counts.bytes += received as u64;
+                }
+                // SAFETY: as above
+                unsafe { ring.submission().push(&receive(index, &mut buffers[index])) }.map_err(io::Error::other)?;
+            }
+        }
+
+        // The kernel must be done with every buffer before they are freed
+        let mut outstanding = buffers.len();
+        for index in 0..buffers.len() {
+            let cancel = opcode::AsyncCancel::new(index as u64).build().user_data(CANCEL | index as u64);
+            // SAFETY: cancellations reference no memory
+            while unsafe { ring.submission().push(&cancel) }.is_err() {
+                ring.submit()?;
+            }
+        }
+        while outstanding > 0 {
+            ring.submit_and_wait(1)?;
+            outstanding -= ring.completion().filter(|completion| completion.user_data() & CANCEL == 0).count();
+        }
+        result
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+mod uring {
+    use std::io;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+    use tokio::sync::oneshot;
+
+    use super::{SendCounts, SinkCounts};
+
+    pub const SUPPORTED: bool = false;
+
+    pub fn blast(_socket: &UdpSocket, _payload: &[u8], _depth: usize, _duration: Duration) -> io::Result<SendCounts> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "io_uring requires Linux and the io-uring feature"))
+    }
+
+    pub fn sink(
+        _socket: &UdpSocket,
+        _buffers: &mut [Vec<u8>],
+        _stopped: oneshot::Receiver<()>,
+        _counts: &mut SinkCounts,
+    ) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "io_uring requires Linux and the io-uring feature"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backend_is_recorded_and_unavailable_ones_fail_early() {
+        assert_eq!("io-uring".parse::<ThroughputBackend>().unwrap(), ThroughputBackend::IoUring);
+        assert_eq!("TOKIO".parse::<ThroughputBackend>().unwrap(), ThroughputBackend::Tokio);
+        assert!("epoll".parse::<ThroughputBackend>().is_err());
+
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+        for backend in ThroughputBackend::ALL {
+            let metrics = runtime.block_on(
+                ThroughputTester::builder()
+                    .packet_sizes([256usize])
+                    .duration(Duration::from_millis(50))
+                    .backend(backend)
+                    .build(ThroughputReceiver::Loopback)
+                    .analyze_throughput(),
+            );
+            if !backend.supported() {
+                assert!(metrics.is_err());
+                continue;
+            }
+            let metrics = metrics.unwrap();
+            assert_eq!(metrics.backend, backend.name());
+            let run = &metrics.runs[0];
+            assert!(run.packets_sent > 0 && run.packets_received > 0, "{}: {:?}", backend.name(), run);
+            assert!(run.packets_received <= run.packets_sent);
+        }
+    }
+}
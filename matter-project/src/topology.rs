@@ -0,0 +1,636 @@
+// matter-project/src/topology.rs
+/*!
+Multi-hop topologies - per-hop overhead of each protocol's typical architecture, device to hub to cloud
+
+Real deployments differ less in their protocol than in where their
+messages go. A Matter command stays in the home; an MQTT command goes to a
+cloud broker and back down; an LwM2M server sits in the cloud and talks to
+the device through the home router. Each topology here is the path of one
+"turn the light on" command and its response, made of legs and hops:
+
+- a leg is one transport session between the nodes that terminate it, with
+  that transport's recovery: MRP or CoAP CON retransmit the request until
+  the response arrives, TCP retransmits each segment on its RTO. Every
+  message on a leg also carries the leg's security overhead (TLS 1.3 or
+  DTLS 1.2 records; Matter's MIC is in its message);
+- a hop is one link within a leg, with its own delay, jitter, loss, rate
+  and framing. Radio hops (Wi-Fi, Thread) retry a lost frame at the MAC
+  layer before the loss reaches the leg's transport; WAN hops do not. The
+  node at the end of a hop forwards (a border router, the home router) or
+  terminates the leg (a broker or cloud service, which spends its
+  processing time on every message it relays).
+
+Per hop the result gives the mean transit time of one traversal, framing
+and bytes per command and the hop's share of the command's mean latency;
+the processing at nodes and the time lost waiting on retransmission timers
+make up the rest. The model is deterministic for a given configuration.
+*/
+
+use analysis_core::distribution::LatencyDistribution;
+use analysis_core::samples::{self, Layer};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::bdx::matter_message;
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{
+    coap_message, mqtt_publish, tlv, uri_path, COAP_ACK, COAP_CHANGED, COAP_CON, COAP_PUT, IM_INVOKE_REQUEST,
+    IM_INVOKE_RESPONSE, PROTOCOL_IM,
+};
+use crate::handshake::next_unit;
+use crate::lowpan::{lowpan_frames, LowpanPath, LowpanTransport, COAP_PORT};
+use crate::mdns::MATTER_PORT;
+use crate::start_modes::{invoke_request, invoke_response};
+use crate::transport_modes::{
+    IPV4_TCP_HEADER_BYTES, IPV4_UDP_HEADER_BYTES, MRP_ACTIVE_RETRANS_TIMEOUT, MRP_BACKOFF_BASE, MRP_MAX_TRANSMISSIONS,
+};
+
+const THREAD_RATE_KBIT: f64 = 250.0;
+// CSMA backoff and ack wait per 802.15.4 retry, macMaxFrameRetries
+const THREAD_MAC_RETRY_MS: f64 = 4.0;
+const THREAD_MAC_RETRIES: u32 = 3;
+// 802.11 retry cost and short retry limit
+const WIFI_MAC_RETRY_MS: f64 = 0.5;
+const WIFI_MAC_RETRIES: u32 = 7;
+const WIFI_DELAY_MS: f64 = 2.0;
+const WIFI_JITTER_MS: f64 = 2.0;
+const THREAD_DELAY_MS: f64 = 3.0;
+const THREAD_JITTER_MS: f64 = 3.0;
+
+// TLS 1.3 record header, inner content type and AES-GCM tag
+const TLS13_RECORD_BYTES: usize = 5 + 1 + 16;
+// DTLS 1.2 record header, explicit nonce and AES-CCM-8 tag
+const DTLS12_RECORD_BYTES: usize = 13 + 8 + 8;
+
+// Forwarding in a border router or home router, and handling in a terminating service
+const BORDER_ROUTER_FORWARDING_MS: f64 = 0.3;
+const HOME_ROUTER_FORWARDING_MS: f64 = 0.1;
+const CLOUD_PROCESSING_MS: f64 = 2.0;
+const HUB_CONTROLLER_PROCESSING_MS: f64 = 1.0;
+
+// RFC 7252 transmission parameters
+const COAP_ACK_TIMEOUT_MS: f64 = 2000.0;
+const COAP_ACK_RANDOM_FACTOR: f64 = 1.5;
+const COAP_MAX_RETRANSMIT: u32 = 4;
+// Linux's minimum RTO and default retry count
+const TCP_MIN_RTO_MS: f64 = 200.0;
+const TCP_MAX_RETRANSMISSIONS: u32 = 6;
+
+const MQTT_COMMAND_TOPIC: &str = "home/light1/set";
+const MQTT_STATE_TOPIC: &str = "home/light1/state";
+const CLOUD_COMMAND: &[u8] = br#"{"device":"light1","cluster":"OnOff","command":"On"}"#;
+const CLOUD_STATE: &[u8] = br#"{"device":"light1","onOff":true}"#;
+
+#[derive(Debug, Clone)]
+pub struct TopologyConfig {
+    /// Commands sent over each topology.
+    pub commands: usize,
+    /// One-way delay of each WAN hop, between the home and the cloud or the phone and the cloud.
+    pub wan_delay: Duration,
+    /// Up to this much extra delay per WAN traversal, uniformly distributed.
+    pub wan_jitter: Duration,
+    pub wan_loss: f64,
+    /// Frame loss on Wi-Fi and Thread hops, before MAC retries.
+    pub radio_loss: f64,
+}
+
+impl Default for TopologyConfig {
+    fn default() -> Self {
+        Self {
+            commands: 1000,
+            wan_delay: Duration::from_millis(25),
+            wan_jitter: Duration::from_millis(5),
+            wan_loss: 0.005,
+            radio_loss: 0.05,
+        }
+    }
+}
+
+analyzer_builder!(TopologyAnalyzerBuilder => TopologyAnalyzer(TopologyConfig) {
+    commands: usize,
+    wan_delay: Duration,
+    wan_jitter: Duration,
+    wan_loss: f64,
+    radio_loss: f64,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TopologyMetrics {
+    pub commands: usize,
+    pub wan_delay_ms: f64,
+    pub wan_loss: f64,
+    pub radio_loss: f64,
+    pub topologies: Vec<TopologyResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TopologyResult {
+    pub topology: String,
+    pub protocol: String,
+    /// The nodes a command passes, from the one that issues it to the device.
+    pub path: Vec<String>,
+    pub commands: usize,
+    pub failed: usize,
+    /// Command issued to response received, over every successful command.
+    pub latency_mean_ms: f64,
+    pub latency_p50_ms: f64,
+    pub latency_p95_ms: f64,
+    pub latency_p99_ms: f64,
+    pub distribution: LatencyDistribution,
+    pub hops: Vec<HopResult>,
+    /// Forwarding and service processing at the nodes, per command.
+    pub processing_ms_per_command: f64,
+    /// Latency not spent in transit or processing: waiting on retransmission timers.
+    pub recovery_ms_per_command: f64,
+    pub bytes_per_command: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HopResult {
+    pub hop: String,
+    pub medium: String,
+    /// Successful traversals per command, each direction counted.
+    pub traversals_per_command: f64,
+    /// One successful traversal: serialization, delay, jitter and MAC retries.
+    pub transit_ms: f64,
+    /// Link and network headers the hop adds to a message that fits one frame.
+    pub framing_bytes: usize,
+    /// Record or MIC bytes of the leg's security on every message.
+    pub security_bytes: usize,
+    /// Every transmission on the hop, retries included.
+    pub bytes_per_command: f64,
+    /// Share of the mean command latency spent crossing this hop.
+    pub latency_share: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Medium {
+    Wifi,
+    Thread,
+    Wan,
+}
+
+impl Medium {
+    fn name(self) -> &'static str {
+        match self {
+            Medium::Wifi => "Wi-Fi",
+            Medium::Thread => "Thread (802.15.4)",
+            Medium::Wan => "WAN",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Recovery {
+    Mrp,
+    CoapCon,
+    Tcp,
+}
+
+#[derive(Debug, Clone)]
+struct Hop {
+    medium: Medium,
+    /// The node the hop ends at.
+    to: &'static str,
+    delay_ms: f64,
+    jitter_ms: f64,
+    loss_rate: f64,
+    rate_kbit: Option<f64>,
+    mac_retries: u32,
+    mac_retry_ms: f64,
+    /// Spent by `to` before the next hop, when it only forwards.
+    forwarding_ms: f64,
+}
+
+/// One transport session, from the side that issues the command towards the device.
+#[derive(Debug, Clone)]
+struct Leg {
+    recovery: Recovery,
+    security_bytes: usize,
+    request_bytes: usize,
+    response_bytes: usize,
+    hops: Vec<Hop>,
+    /// Spent by the node ending the leg on each message it passes on; none for the device.
+    processing_ms: f64,
+}
+
+struct Topology {
+    name: &'static str,
+    protocol: &'static str,
+    origin: &'static str,
+    legs: Vec<Leg>,
+}
+
+impl Topology {
+    fn hops(&self) -> impl Iterator<Item = &Hop> {
+        self.legs.iter().flat_map(|leg| &leg.hops)
+    }
+
+    fn path(&self) -> Vec<String> {
+        std::iter::once(self.origin).chain(self.hops().map(|hop| hop.to)).map(str::to_string).collect()
+    }
+
+    /// Bytes on `hop` of a message with `payload` bytes on a leg with
+    /// `recovery`. Thread hops reach off-mesh peers through the border router,
+    /// as 6LoWPAN frames that fragment when the message does not fit one.
+    fn wire_bytes(hop: &Hop, recovery: Recovery, payload: usize) -> usize {
+        match (hop.medium, recovery) {
+            (Medium::Thread, Recovery::Tcp) => lowpan_frames(LowpanPath::OffMesh, LowpanTransport::Tcp, payload).on_air_bytes,
+            (Medium::Thread, Recovery::Mrp) => {
+                lowpan_frames(LowpanPath::OffMesh, LowpanTransport::udp(MATTER_PORT), payload).on_air_bytes
+            }
+            (Medium::Thread, Recovery::CoapCon) => {
+                lowpan_frames(LowpanPath::OffMesh, LowpanTransport::udp(COAP_PORT), payload).on_air_bytes
+            }
+            (_, Recovery::Tcp) => payload + IPV4_TCP_HEADER_BYTES,
+            _ => payload + IPV4_UDP_HEADER_BYTES,
+        }
+    }
+}
+
+pub struct TopologyAnalyzer {
+    config: TopologyConfig,
+}
+
+impl TopologyAnalyzer {
+    pub fn new(config: TopologyConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn analyze_topologies(&mut self) -> Result<TopologyMetrics> {
+        println!("\n🌐 Analyzing Multi-Hop Topologies ({} commands, {:.0}ms WAN hops)",
+                 self.config.commands, self.config.wan_delay.as_secs_f64() * 1000.0);
+        println!("------------------------------------------------");
+
+        let mut results = Vec::new();
+        for topology in self.topologies()? {
+            let result = self.simulate(&topology);
+            println!("✅ {}: p50 {:.1}ms, p99 {:.1}ms over {}", result.topology, result.latency_p50_ms,
+                     result.latency_p99_ms, result.path.join(" → "));
+            for hop in &result.hops {
+                println!("   {:<40} {:>6.2}ms x{:.1}  {:>5.1}%  {:>6.0} B/cmd", hop.hop, hop.transit_ms,
+                         hop.traversals_per_command, hop.latency_share * 100.0, hop.bytes_per_command);
+            }
+            results.push(result);
+        }
+
+        Ok(TopologyMetrics {
+            commands: self.config.commands,
+            wan_delay_ms: self.config.wan_delay.as_secs_f64() * 1000.0,
+            wan_loss: self.config.wan_loss,
+            radio_loss: self.config.radio_loss,
+            topologies: results,
+        })
+    }
+
+    fn topologies(&self) -> Result<Vec<Topology>> {
+        let wifi = |to: &'static str, forwarding_ms: f64| Hop {
+            medium: Medium::Wifi,
+            to,
+            delay_ms: WIFI_DELAY_MS,
+            jitter_ms: WIFI_JITTER_MS,
+            loss_rate: self.config.radio_loss,
+            rate_kbit: None,
+            mac_retries: WIFI_MAC_RETRIES,
+            mac_retry_ms: WIFI_MAC_RETRY_MS,
+            forwarding_ms,
+        };
+        let thread = |to: &'static str| Hop {
+            medium: Medium::Thread,
+            to,
+            delay_ms: THREAD_DELAY_MS,
+            jitter_ms: THREAD_JITTER_MS,
+            loss_rate: self.config.radio_loss,
+            rate_kbit: Some(THREAD_RATE_KBIT),
+            mac_retries: THREAD_MAC_RETRIES,
+            mac_retry_ms: THREAD_MAC_RETRY_MS,
+            forwarding_ms: 0.0,
+        };
+        let wan = |to: &'static str, forwarding_ms: f64| Hop {
+            medium: Medium::Wan,
+            to,
+            delay_ms: self.config.wan_delay.as_secs_f64() * 1000.0,
+            jitter_ms: self.config.wan_jitter.as_secs_f64() * 1000.0,
+            loss_rate: self.config.wan_loss,
+            rate_kbit: None,
+            mac_retries: 0,
+            mac_retry_ms: 0.0,
+            forwarding_ms,
+        };
+
+        let invoke = matter_message(PROTOCOL_IM, IM_INVOKE_REQUEST, &tlv(invoke_request)?).len();
+        let invoke_response = matter_message(PROTOCOL_IM, IM_INVOKE_RESPONSE, &tlv(invoke_response)?).len();
+        let matter_over_thread = |hops: Vec<Hop>| Leg {
+            recovery: Recovery::Mrp,
+            security_bytes: 0,
+            request_bytes: invoke,
+            response_bytes: invoke_response,
+            hops,
+            processing_ms: 0.0,
+        };
+        let tls = |request: usize, response: usize, hops: Vec<Hop>, processing_ms: f64| Leg {
+            recovery: Recovery::Tcp,
+            security_bytes: TLS13_RECORD_BYTES,
+            request_bytes: request,
+            response_bytes: response,
+            hops,
+            processing_ms,
+        };
+        let command = mqtt_publish(MQTT_COMMAND_TOPIC, Some(1), b"on").len();
+        let state = mqtt_publish(MQTT_STATE_TOPIC, Some(1), b"on").len();
+        let lwm2m_write = coap_message(COAP_CON, COAP_PUT, 1, &uri_path(&["3311", "0", "5850"]), b"1").len();
+        let lwm2m_changed = coap_message(COAP_ACK, COAP_CHANGED, 1, &[], &[]).len();
+
+        Ok(vec![
+            Topology {
+                name: "Matter local",
+                protocol: "Matter",
+                origin: "phone app",
+                legs: vec![matter_over_thread(vec![
+                    wifi("border router", BORDER_ROUTER_FORWARDING_MS),
+                    thread("light"),
+                ])],
+            },
+            Topology {
+                name: "Matter remote via hub",
+                protocol: "Matter",
+                origin: "phone app",
+                legs: vec![
+                    tls(CLOUD_COMMAND.len(), CLOUD_STATE.len(), vec![wan("ecosystem cloud", 0.0)], CLOUD_PROCESSING_MS),
+                    tls(
+                        CLOUD_COMMAND.len(),
+                        CLOUD_STATE.len(),
+                        vec![wan("home router", HOME_ROUTER_FORWARDING_MS), wifi("hub", 0.0)],
+                        HUB_CONTROLLER_PROCESSING_MS,
+                    ),
+                    matter_over_thread(vec![thread("light")]),
+                ],
+            },
+            Topology {
+                name: "MQTT via cloud broker",
+                protocol: "MQTT",
+                origin: "phone app",
+                legs: vec![
+                    tls(command, state, vec![wan("cloud broker", 0.0)], CLOUD_PROCESSING_MS),
+                    tls(command, state, vec![wan("home router", HOME_ROUTER_FORWARDING_MS), wifi("light", 0.0)], 0.0),
+                ],
+            },
+            Topology {
+                name: "LwM2M server",
+                protocol: "LwM2M",
+                origin: "LwM2M server",
+                legs: vec![Leg {
+                    recovery: Recovery::CoapCon,
+                    security_bytes: DTLS12_RECORD_BYTES,
+                    request_bytes: lwm2m_write,
+                    response_bytes: lwm2m_changed,
+                    hops: vec![wan("home router", HOME_ROUTER_FORWARDING_MS), wifi("light", 0.0)],
+                    processing_ms: 0.0,
+                }],
+            },
+        ])
+    }
+
+    fn simulate(&self, topology: &Topology) -> TopologyResult {
+        // Same seed for every topology, so each sees the same kind of luck
+        let mut network = Network { rng_state: 0x2545_F491_4F6C_DD1Du64, hops: vec![HopTally::default(); topology.hops().count()] };
+        let commands = self.config.commands.max(1);
+        let mut latencies = Vec::with_capacity(commands);
+        let (mut processing, mut transit, mut bytes) = (0.0, 0.0, 0usize);
+        for _ in 0..commands {
+            let before = network.totals();
+            let mut command_processing = 0.0;
+            if let Some(latency) = network.command(topology, &mut command_processing) {
+                latencies.push(latency);
+                processing += command_processing;
+                transit += network.totals().0 - before.0;
+            }
+            bytes += network.totals().1 - before.1;
+        }
+
+        samples::record("topologies", Layer::Network, topology.protocol, topology.name, "latency_ms", &latencies);
+        let distribution = LatencyDistribution::from_samples(&latencies);
+        latencies.sort_by(f64::total_cmp);
+        let delivered = latencies.len().max(1) as f64;
+        let mean = distribution.mean_ms;
+        let hops = topology
+            .legs
+            .iter()
+            .flat_map(|leg| leg.hops.iter().map(move |hop| (leg, hop)))
+            .zip(&network.hops)
+            .scan(topology.origin, |from, ((leg, hop), tally)| {
+                let name = format!("{} → {}", from, hop.to);
+                *from = hop.to;
+                let transit_ms = if tally.traversals > 0 { tally.transit_ms / tally.traversals as f64 } else { 0.0 };
+                Some(HopResult {
+                    hop: name,
+                    medium: hop.medium.name().to_string(),
+                    traversals_per_command: tally.traversals as f64 / commands as f64,
+                    transit_ms,
+                    framing_bytes: Topology::wire_bytes(hop, leg.recovery, 0),
+                    security_bytes: leg.security_bytes,
+                    bytes_per_command: tally.bytes as f64 / commands as f64,
+                    latency_share: if mean > 0.0 { tally.transit_ms / commands as f64 / mean } else { 0.0 },
+                })
+            })
+            .collect();
+
+        TopologyResult {
+            topology: topology.name.to_string(),
+            protocol: topology.protocol.to_string(),
+            path: topology.path(),
+            commands,
+            failed: commands - latencies.len(),
+            latency_mean_ms: mean,
+            latency_p50_ms: percentile(&latencies, 50.0),
+            latency_p95_ms: percentile(&latencies, 95.0),
+            latency_p99_ms: percentile(&latencies, 99.0),
+            distribution,
+            hops,
+            processing_ms_per_command: processing / delivered,
+            recovery_ms_per_command: (mean - (transit + processing) / delivered).max(0.0),
+            bytes_per_command: bytes as f64 / commands as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct HopTally {
+    traversals: usize,
+    transit_ms: f64,
+    bytes: usize,
+}
+
+/// Deterministic loss and jitter, with what each hop carried.
+struct Network {
+    rng_state: u64,
+    hops: Vec<HopTally>,
+}
+
+impl Network {
+    /// Transit time and bytes over all hops so far.
+    fn totals(&self) -> (f64, usize) {
+        self.hops.iter().fold((0.0, 0), |(ms, bytes), hop| (ms + hop.transit_ms, bytes + hop.bytes))
+    }
+
+    /// One command out to the device and its response back; `None` when a leg gave up.
+    fn command(&mut self, topology: &Topology, processing: &mut f64) -> Option<f64> {
+        let first_hops: Vec<usize> = topology
+            .legs
+            .iter()
+            .scan(0, |index, leg| {
+                let first = *index;
+                *index += leg.hops.len();
+                Some(first)
+            })
+            .collect();
+        let (last, relays) = topology.legs.split_last()?;
+        let mut at = 0.0;
+        for (leg, &first) in relays.iter().zip(&first_hops) {
+            at = self.segment(leg, first, true, at, processing)? + leg.processing_ms;
+            *processing += leg.processing_ms;
+        }
+        let last_first = first_hops[relays.len()];
+        at = match last.recovery {
+            Recovery::Tcp => {
+                let there = self.segment(last, last_first, true, at, processing)?;
+                self.segment(last, last_first, false, there, processing)?
+            }
+            Recovery::Mrp | Recovery::CoapCon => self.exchange(last, last_first, at, processing)?,
+        };
+        for (leg, &first) in relays.iter().zip(&first_hops).rev() {
+            at = self.segment(leg, first, false, at + leg.processing_ms, processing)?;
+            *processing += leg.processing_ms;
+        }
+        Some(at)
+    }
+
+    /// The leg's request (`outbound`) or response across its hops once; `None` when a hop lost it.
+    fn traverse(&mut self, leg: &Leg, first: usize, outbound: bool, at: f64, processing: &mut f64) -> Option<f64> {
+        let payload = if outbound { leg.request_bytes } else { leg.response_bytes } + leg.security_bytes;
+        let order: Vec<usize> = if outbound { (0..leg.hops.len()).collect() } else { (0..leg.hops.len()).rev().collect() };
+        let mut at = at;
+        for (step, &index) in order.iter().enumerate() {
+            let hop = &leg.hops[index];
+            let bytes = Topology::wire_bytes(hop, leg.recovery, payload);
+            let serialization = hop.rate_kbit.map_or(0.0, |rate| bytes as f64 * 8.0 / rate);
+            let mut spent = 0.0;
+            let mut arrived = false;
+            for attempt in 0..=hop.mac_retries {
+                self.hops[first + index].bytes += bytes;
+                spent += serialization + if attempt > 0 { hop.mac_retry_ms } else { 0.0 };
+                if next_unit(&mut self.rng_state) >= hop.loss_rate {
+                    arrived = true;
+                    break;
+                }
+            }
+            if !arrived {
+                return None;
+            }
+            spent += hop.delay_ms + hop.jitter_ms * next_unit(&mut self.rng_state);
+            let tally = &mut self.hops[first + index];
+            tally.traversals += 1;
+            tally.transit_ms += spent;
+            at += spent;
+            // The node just reached forwards to the next hop
+            if step + 1 < order.len() {
+                let forwarding_ms = leg.hops[if outbound { index } else { index - 1 }].forwarding_ms;
+                at += forwarding_ms;
+                *processing += forwarding_ms;
+            }
+        }
+        Some(at)
+    }
+
+    /// A TCP segment across the leg, retransmitted on its RTO until it arrives.
+    fn segment(&mut self, leg: &Leg, first: usize, outbound: bool, at: f64, processing: &mut f64) -> Option<f64> {
+        let (mut sent_at, mut rto) = (at, TCP_MIN_RTO_MS);
+        for _ in 0..=TCP_MAX_RETRANSMISSIONS {
+            if let Some(arrival) = self.traverse(leg, first, outbound, sent_at, processing) {
+                return Some(arrival);
+            }
+            sent_at += rto;
+            rto *= 2.0;
+        }
+        None
+    }
+
+    /// A request retransmitted on the leg's timer until its response arrives.
+    fn exchange(&mut self, leg: &Leg, first: usize, at: f64, processing: &mut f64) -> Option<f64> {
+        let mut sent_at = at;
+        let mut timeout = match leg.recovery {
+            Recovery::CoapCon => COAP_ACK_TIMEOUT_MS * (1.0 + next_unit(&mut self.rng_state) * (COAP_ACK_RANDOM_FACTOR - 1.0)),
+            _ => MRP_ACTIVE_RETRANS_TIMEOUT.as_secs_f64() * 1000.0,
+        };
+        let transmissions = match leg.recovery {
+            Recovery::CoapCon => COAP_MAX_RETRANSMIT + 1,
+            _ => MRP_MAX_TRANSMISSIONS,
+        };
+        let backoff = if leg.recovery == Recovery::CoapCon { 2.0 } else { MRP_BACKOFF_BASE };
+        let mut done: Option<f64> = None;
+        for _ in 0..transmissions {
+            let response = self
+                .traverse(leg, first, true, sent_at, processing)
+                .and_then(|arrival| self.traverse(leg, first, false, arrival, processing));
+            done = match (done, response) {
+                (Some(earlier), Some(later)) => Some(earlier.min(later)),
+                (earlier, later) => earlier.or(later),
+            };
+            // A response to an earlier transmission can still beat the next one
+            if done.is_some_and(|done| done <= sent_at + timeout) {
+                break;
+            }
+            sent_at += timeout;
+            timeout *= backoff;
+        }
+        done
+    }
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((p / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cloud_legs_dominate_latency_and_local_paths_stay_in_the_home() {
+        let metrics = TopologyAnalyzer::builder()
+            .commands(500usize)
+            .wan_jitter(Duration::ZERO)
+            .wan_loss(0.0)
+            .radio_loss(0.0)
+            .build()
+            .analyze_topologies()
+            .unwrap();
+        let topology = |name: &str| metrics.topologies.iter().find(|t| t.topology == name).unwrap();
+
+        let local = topology("Matter local");
+        assert_eq!(local.path, ["phone app", "border router", "light"]);
+        assert!(local.hops.iter().all(|hop| hop.medium != "WAN"));
+        assert!(local.latency_p99_ms < 30.0, "{}", local.latency_p99_ms);
+
+        // Four WAN traversals of 25ms, plus the cloud broker's processing twice
+        let mqtt = topology("MQTT via cloud broker");
+        assert!(mqtt.latency_p50_ms > 100.0 + 2.0 * CLOUD_PROCESSING_MS, "{}", mqtt.latency_p50_ms);
+        let wan_share: f64 = mqtt.hops.iter().filter(|hop| hop.medium == "WAN").map(|hop| hop.latency_share).sum();
+        assert!(wan_share > 0.8, "{}", wan_share);
+
+        for result in &metrics.topologies {
+            assert_eq!(result.failed, 0, "{}", result.topology);
+            assert!(result.recovery_ms_per_command < 1e-6, "{}: {}", result.topology, result.recovery_ms_per_command);
+            assert!(result.hops.iter().all(|hop| (hop.traversals_per_command - 2.0).abs() < 1e-9), "{}", result.topology);
+            let accounted: f64 = result.hops.iter().map(|hop| hop.latency_share).sum::<f64>()
+                + result.processing_ms_per_command / result.latency_mean_ms;
+            assert!((accounted - 1.0).abs() < 1e-6, "{}: {}", result.topology, accounted);
+        }
+        assert!(topology("Matter remote via hub").latency_p50_ms > local.latency_p50_ms + 100.0);
+    }
+}
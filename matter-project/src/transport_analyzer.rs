@@ -1,395 +0,0 @@
-// matter-project/src/transport_analyzer.rs
-/*!
-Real Matter Transport Layer Analysis using rs-matter
-*/
-
-use anyhow::Result;
-use log::{debug, info, warn};
-use rs_matter::transport::{UdpTransport, TcpTransport};
-use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
-use std::time::Instant;
-use tokio::net::{UdpSocket, TcpListener, TcpStream};
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct TransportMetrics {
-    pub protocol: String,
-    pub udp_discovery_time_ms: f64,
-    pub tcp_connection_time_ms: f64,
-    pub udp_overhead_bytes: u32,
-    pub tcp_overhead_bytes: u32,
-    pub total_transport_overhead: u32,
-    pub multi_transport_support: bool,
-    pub efficiency_score: f64,
-    pub real_network_performance: RealNetworkPerformance,
-    pub connection_statistics: ConnectionStatistics,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RealNetworkPerformance {
-    pub udp_throughput_mbps: f64,
-    pub tcp_throughput_mbps: f64,
-    pub packet_loss_rate: f64,
-    pub round_trip_time_ms: f64,
-    pub concurrent_connections: u32,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ConnectionStatistics {
-    pub successful_connections: u32,
-    pub failed_connections: u32,
-    pub timeout_connections: u32,
-    pub average_handshake_time_ms: f64,
-}
-
-pub struct RealTransportAnalyzer {
-    udp_socket: Option<UdpSocket>,
-    tcp_listener: Option<TcpListener>,
-    test_endpoints: Vec<SocketAddr>,
-}
-
-impl RealTransportAnalyzer {
-    pub async fn new() -> Result<Self> {
-        info!("🔧 Initializing Real Transport Analyzer");
-        
-        Ok(Self {
-            udp_socket: None,
-            tcp_listener: None,
-            test_endpoints: vec![
-                "127.0.0.1:5540".parse()?,  // Matter default UDP port
-                "127.0.0.1:5541".parse()?,  // Matter default TCP port
-            ],
-        })
-    }
-    
-    pub async fn analyze_transport_layer(&mut self) -> Result<TransportMetrics> {
-        info!("🚀 Starting Real Matter Transport Layer Analysis");
-        
-        // Initialize transport components
-        self.initialize_transports().await?;
-        
-        // Analyze UDP transport
-        let udp_metrics = self.analyze_udp_transport().await?;
-        info!("✅ UDP Analysis: {:.2}ms discovery time", udp_metrics.discovery_time);
-        
-        // Analyze TCP transport
-        let tcp_metrics = self.analyze_tcp_transport().await?;
-        info!("✅ TCP Analysis: {:.2}ms connection time", tcp_metrics.connection_time);
-        
-        // Measure real network performance
-        let network_perf = self.measure_comprehensive_network_performance().await?;
-        
-        // Calculate connection statistics
-        let conn_stats = self.calculate_connection_statistics().await?;
-        
-        // Calculate overall efficiency
-        let efficiency = self.calculate_transport_efficiency(&udp_metrics, &tcp_metrics, &network_perf);
-        
-        let metrics = TransportMetrics {
-            protocol: "Real_Matter_Dual_Stack".to_string(),
-            udp_discovery_time_ms: udp_metrics.discovery_time,
-            tcp_connection_time_ms: tcp_metrics.connection_time,
-            udp_overhead_bytes: udp_metrics.overhead,
-            tcp_overhead_bytes: tcp_metrics.overhead,
-            total_transport_overhead: udp_metrics.overhead + tcp_metrics.overhead,
-            multi_transport_support: true,
-            efficiency_score: efficiency,
-            real_network_performance: network_perf,
-            connection_statistics: conn_stats,
-        };
-        
-        info!("📊 Transport Analysis Summary:");
-        info!("   UDP Discovery: {:.2}ms", metrics.udp_discovery_time_ms);
-        info!("   TCP Connection: {:.2}ms", metrics.tcp_connection_time_ms);
-        info!("   Total Overhead: {} bytes", metrics.total_transport_overhead);
-        info!("   Efficiency Score: {:.3}", metrics.efficiency_score);
-        
-        Ok(metrics)
-    }
-    
-    async fn initialize_transports(&mut self) -> Result<()> {
-        debug!("🔧 Initializing UDP and TCP transports");
-        
-        // Initialize UDP socket for Matter discovery
-        let udp_socket = UdpSocket::bind("0.0.0.0:0").await?;
-        udp_socket.set_broadcast(true)?;
-        self.udp_socket = Some(udp_socket);
-        
-        // Initialize TCP listener for Matter operational communication
-        let tcp_listener = TcpListener::bind("127.0.0.1:0").await?;
-        self.tcp_listener = Some(tcp_listener);
-        
-        info!("✅ Transports initialized successfully");
-        Ok(())
-    }
-    
-    async fn analyze_udp_transport(&self) -> Result<UdpAnalysisResult> {
-        debug!("📡 Analyzing UDP transport performance");
-        
-        let start_time = Instant::now();
-        
-        if let Some(socket) = &self.udp_socket {
-            // Perform Matter-like UDP discovery
-            let discovery_message = self.create_matter_discovery_message();
-            
-            // Send to multicast address (Matter uses this for discovery)
-            let multicast_addr: SocketAddr = "224.0.0.251:5353".parse()?;
-            
-            let send_start = Instant::now();
-            match socket.send_to(&discovery_message, &multicast_addr).await {
-                Ok(bytes_sent) => {
-                    let discovery_time = send_start.elapsed().as_micros() as f64 / 1000.0;
-                    info!("📤 UDP Discovery: {} bytes sent in {:.2}ms", bytes_sent, discovery_time);
-                    
-                    return Ok(UdpAnalysisResult {
-                        discovery_time,
-                        overhead: 8 + discovery_message.len() as u32, // UDP header + payload
-                        bytes_sent: bytes_sent as u32,
-                        success: true,
-                    });
-                }
-                Err(e) => {
-                    warn!("⚠️ UDP Discovery failed (expected in test environment): {}", e);
-                    // Still return timing for analysis
-                    let discovery_time = send_start.elapsed().as_micros() as f64 / 1000.0;
-                    return Ok(UdpAnalysisResult {
-                        discovery_time,
-                        overhead: 8 + discovery_message.len() as u32,
-                        bytes_sent: 0,
-                        success: false,
-                    });
-                }
-            }
-        }
-        
-        Err(anyhow::anyhow!("UDP socket not initialized"))
-    }
-    
-    async fn analyze_tcp_transport(&self) -> Result<TcpAnalysisResult> {
-        debug!("🔗 Analyzing TCP transport performance");
-        
-        if let Some(listener) = &self.tcp_listener {
-            let local_addr = listener.local_addr()?;
-            
-            // Spawn a background task to accept connections
-            let listener_addr = local_addr;
-            tokio::spawn(async move {
-                if let Ok(listener) = TcpListener::bind(listener_addr).await {
-                    if let Ok((stream, _)) = listener.accept().await {
-                        debug!("📞 TCP connection accepted");
-                        // Handle the connection minimally for testing
-                        drop(stream);
-                    }
-                }
-            });
-            
-            // Attempt to connect to ourselves for timing measurement
-            let connect_start = Instant::now();
-            match TcpStream::connect(local_addr).await {
-                Ok(_stream) => {
-                    let connection_time = connect_start.elapsed().as_micros() as f64 / 1000.0;
-                    info!("🤝 TCP Connection established in {:.2}ms", connection_time);
-                    
-                    return Ok(TcpAnalysisResult {
-                        connection_time,
-                        overhead: 20 + 32, // TCP header + Matter session overhead estimate
-                        success: true,
-                    });
-                }
-                Err(e) => {
-                    warn!("⚠️ TCP Connection failed: {}", e);
-                    let connection_time = connect_start.elapsed().as_micros() as f64 / 1000.0;
-                    return Ok(TcpAnalysisResult {
-                        connection_time,
-                        overhead: 20 + 32,
-                        success: false,
-                    });
-                }
-            }
-        }
-        
-        Err(anyhow::anyhow!("TCP listener not initialized"))
-    }
-    
-    fn create_matter_discovery_message(&self) -> Vec<u8> {
-        // Create a realistic Matter discovery message
-        let mut message = Vec::new();
-        
-        // DNS query header for Matter service discovery
-        message.extend_from_slice(&[0x00, 0x00]); // Transaction ID
-        message.extend_from_slice(&[0x01, 0x00]); // Flags (standard query)
-        message.extend_from_slice(&[0x00, 0x01]); // Questions count
-        message.extend_from_slice(&[0x00, 0x00]); // Answer RRs
-        message.extend_from_slice(&[0x00, 0x00]); // Authority RRs
-        message.extend_from_slice(&[0x00, 0x00]); // Additional RRs
-        
-        // Query for Matter service
-        let service_name = "_matter._tcp.local.";
-        let labels: Vec<&str> = service_name.split('.').collect();
-        
-        for label in labels {
-            if !label.is_empty() {
-                message.push(label.len() as u8);
-                message.extend_from_slice(label.as_bytes());
-            }
-        }
-        message.push(0); // End of name
-        
-        message.extend_from_slice(&[0x00, 0x0C]); // QTYPE (PTR)
-        message.extend_from_slice(&[0x00, 0x01]); // QCLASS (IN)
-        
-        message
-    }
-    
-    async fn measure_comprehensive_network_performance(&self) -> Result<RealNetworkPerformance> {
-        debug!("📊 Measuring comprehensive network performance");
-        
-        // Measure UDP throughput
-        let udp_throughput = self.measure_udp_throughput().await?;
-        
-        // Measure TCP throughput
-        let tcp_throughput = self.measure_tcp_throughput().await?;
-        
-        // Measure RTT with actual ping-like test
-        let rtt = self.measure_round_trip_time().await?;
-        
-        // Simulate packet loss measurement
-        let packet_loss = self.measure_packet_loss().await?;
-        
-        Ok(RealNetworkPerformance {
-            udp_throughput_mbps: udp_throughput,
-            tcp_throughput_mbps: tcp_throughput,
-            packet_loss_rate: packet_loss,
-            round_trip_time_ms: rtt,
-            concurrent_connections: 10, // Simulated concurrent connection capability
-        })
-    }
-    
-    async fn measure_udp_throughput(&self) -> Result<f64> {
-        // Implement real UDP throughput measurement
-        // This would send/receive data and measure actual throughput
-        
-        let test_data = vec![0u8; 1024]; // 1KB test packet
-        let start_time = Instant::now();
-        let test_duration = std::time::Duration::from_millis(100);
-        
-        let mut bytes_sent = 0u64;
-        let mut packets_sent = 0u32;
-        
-        if let Some(socket) = &self.udp_socket {
-            let test_addr: SocketAddr = "127.0.0.1:12345".parse()?;
-            
-            while start_time.elapsed() < test_duration {
-                match socket.send_to(&test_data, &test_addr).await {
-                    Ok(bytes) => {
-                        bytes_sent += bytes as u64;
-                        packets_sent += 1;
-                    }
-                    Err(_) => break, // Expected - no one listening
-                }
-            }
-        }
-        
-        let elapsed_seconds = start_time.elapsed().as_secs_f64();
-        let throughput_bps = (bytes_sent as f64 * 8.0) / elapsed_seconds;
-        let throughput_mbps = throughput_bps / 1_000_000.0;
-        
-        debug!("📡 UDP Throughput: {:.2} Mbps ({} packets in {:.3}s)", 
-               throughput_mbps, packets_sent, elapsed_seconds);
-        
-        Ok(throughput_mbps.min(100.0)) // Cap at reasonable value
-    }
-    
-    async fn measure_tcp_throughput(&self) -> Result<f64> {
-        // Simplified TCP throughput measurement
-        // In a real implementation, this would establish connections and measure data transfer
-        
-        debug!("🔗 Measuring TCP throughput (simulated)");
-        
-        // Simulate realistic TCP throughput (typically lower than UDP due to overhead)
-        Ok(95.0)
-    }
-    
-    async fn measure_round_trip_time(&self) -> Result<f64> {
-        debug!("⏱️ Measuring round trip time");
-        
-        let start_time = Instant::now();
-        
-        // Simulate RTT measurement by attempting UDP communication
-        if let Some(socket) = &self.udp_socket {
-            let test_data = b"RTT_TEST";
-            let test_addr: SocketAddr = "127.0.0.1:12346".parse()?;
-            
-            match socket.send_to(test_data, &test_addr).await {
-                Ok(_) => {
-                    // In real implementation, we'd wait for response
-                    let rtt = start_time.elapsed().as_micros() as f64 / 1000.0;
-                    debug!("📡 Measured RTT: {:.2}ms", rtt);
-                    return Ok(rtt);
-                }
-                Err(_) => {
-                    // Even on error, return the time taken
-                    let rtt = start_time.elapsed().as_micros() as f64 / 1000.0;
-                    return Ok(rtt);
-                }
-            }
-        }
-        
-        Ok(2.5) // Default RTT for localhost
-    }
-    
-    async fn measure_packet_loss(&self) -> Result<f64> {
-        debug!("📉 Measuring packet loss rate");
-        
-        // In a real implementation, this would send multiple packets and count responses
-        // For localhost testing, simulate very low packet loss
-        Ok(0.001) // 0.1% packet loss
-    }
-    
-    async fn calculate_connection_statistics(&self) -> Result<ConnectionStatistics> {
-        debug!("📊 Calculating connection statistics");
-        
-        // In a real implementation, these would be tracked during actual connections
-        Ok(ConnectionStatistics {
-            successful_connections: 95,
-            failed_connections: 3,
-            timeout_connections: 2,
-            average_handshake_time_ms: 8.5,
-        })
-    }
-    
-    fn calculate_transport_efficiency(
-        &self,
-        udp: &UdpAnalysisResult,
-        tcp: &TcpAnalysisResult,
-        network: &RealNetworkPerformance,
-    ) -> f64 {
-        let base_efficiency = 0.75;
-        
-        // Factor in success rates
-        let success_factor = if udp.success && tcp.success { 1.0 } else { 0.8 };
-        
-        // Factor in network performance
-        let throughput_factor = (network.udp_throughput_mbps + network.tcp_throughput_mbps) / 200.0;
-        let rtt_penalty = (network.round_trip_time_ms / 100.0).min(0.3);
-        let loss_penalty = network.packet_loss_rate * 50.0;
-        
-        let efficiency = base_efficiency * success_factor * throughput_factor - rtt_penalty - loss_penalty;
-        efficiency.max(0.3).min(1.0)
-    }
-}
-
-#[derive(Debug)]
-struct UdpAnalysisResult {
-    discovery_time: f64,
-    overhead: u32,
-    bytes_sent: u32,
-    success: bool,
-}
-
-#[derive(Debug)]
-struct TcpAnalysisResult {
-    connection_time: f64,
-    overhead: u32,
-    success: bool,
-}
\ No newline at end of file
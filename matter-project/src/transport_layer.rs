@@ -1,224 +0,0 @@
-// matter-project/src/transport_layer.rs
-/*!
-Real Matter Transport Layer using rs-matter
-*/
-
-use rs_matter::transport::{Transport, UdpTransport, TcpTransport};
-use rs_matter::core::transport::{Network, NetworkContext};
-use serde::{Deserialize, Serialize};
-use std::time::Instant;
-use tokio::net::{UdpSocket, TcpListener};
-use std::net::SocketAddr;
-use log::{info, warn, debug};
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct TransportMetrics {
-    pub protocol: String,
-    pub udp_discovery_time_ms: f64,
-    pub tcp_connection_time_ms: f64,
-    pub udp_overhead_bytes: u32,
-    pub tcp_overhead_bytes: u32,
-    pub total_transport_overhead: u32,
-    pub multi_transport_support: bool,
-    pub efficiency_score: f64,
-    pub real_network_performance: NetworkPerformance,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct NetworkPerformance {
-    pub udp_throughput_mbps: f64,
-    pub tcp_throughput_mbps: f64,
-    pub packet_loss_rate: f64,
-    pub round_trip_time_ms: f64,
-}
-
-pub struct RealTransportAnalyzer {
-    udp_transport: Option<UdpTransport>,
-    tcp_transport: Option<TcpTransport>,
-}
-
-impl RealTransportAnalyzer {
-    pub fn new() -> Self {
-        Self {
-            udp_transport: None,
-            tcp_transport: None,
-        }
-    }
-    
-    pub async fn analyze_layer_4(&mut self) -> anyhow::Result<TransportMetrics> {
-        println!("\n>>> Analyzing OSI Layer 4 - Real Matter Transport");
-        println!("------------------------------------------------");
-        
-        // Initialize real Matter transports
-        let udp_metrics = self.analyze_real_udp_transport().await?;
-        let tcp_metrics = self.analyze_real_tcp_transport().await?;
-        let network_perf = self.measure_network_performance().await?;
-        
-        let total_transport_time = udp_metrics.discovery_time + tcp_metrics.connection_time;
-        let efficiency = self.calculate_real_efficiency(&udp_metrics, &tcp_metrics, &network_perf);
-        
-        let metrics = TransportMetrics {
-            protocol: "Real_Matter_UDP_TCP".to_string(),
-            udp_discovery_time_ms: udp_metrics.discovery_time,
-            tcp_connection_time_ms: tcp_metrics.connection_time,
-            udp_overhead_bytes: udp_metrics.overhead,
-            tcp_overhead_bytes: tcp_metrics.overhead,
-            total_transport_overhead: udp_metrics.overhead + tcp_metrics.overhead,
-            multi_transport_support: true,
-            efficiency_score: efficiency,
-            real_network_performance: network_perf,
-        };
-        
-        println!(">>> Real UDP Discovery: {:.2}ms", metrics.udp_discovery_time_ms);
-        println!(">>> Real TCP Connection: {:.2}ms", metrics.tcp_connection_time_ms);
-        println!(">>> Network Throughput: UDP {:.1}Mbps, TCP {:.1}Mbps", 
-                 metrics.real_network_performance.udp_throughput_mbps,
-                 metrics.real_network_performance.tcp_throughput_mbps);
-        
-        Ok(metrics)
-    }
-    
-    async fn analyze_real_udp_transport(&mut self) -> anyhow::Result<UdpMetrics> {
-        info!("Creating real Matter UDP transport...");
-        let start = Instant::now();
-        
-        // Create real UDP socket for Matter
-        let socket = UdpSocket::bind("0.0.0.0:0").await?;
-        let local_addr = socket.local_addr()?;
-        
-        // Create Matter UDP transport
-        self.udp_transport = Some(UdpTransport::new(socket).await?);
-        
-        // Perform real Matter discovery
-        let discovery_start = Instant::now();
-        
-        // Send real Matter discovery message
-        let discovery_payload = self.create_matter_discovery_message();
-        
-        // Broadcast discovery (Matter uses multicast)
-        let multicast_addr: SocketAddr = "224.0.0.251:5353".parse()?;
-        
-        if let Some(transport) = &self.udp_transport {
-            match transport.send_to(&discovery_payload, &multicast_addr).await {
-                Ok(_) => {
-                    info!("Real Matter discovery sent successfully");
-                }
-                Err(e) => {
-                    warn!("Discovery failed (expected in test environment): {}", e);
-                }
-            }
-        }
-        
-        let discovery_time = discovery_start.elapsed().as_micros() as f64 / 1000.0;
-        let overhead = 8 + discovery_payload.len() as u32; // UDP header + payload
-        
-        info!("UDP transport analysis: {:.2}ms", discovery_time);
-        
-        Ok(UdpMetrics {
-            discovery_time,
-            overhead,
-            throughput_mbps: self.measure_udp_throughput().await?,
-        })
-    }
-    
-    async fn analyze_real_tcp_transport(&mut self) -> anyhow::Result<TcpMetrics> {
-        info!("Creating real Matter TCP transport...");
-        let connection_start = Instant::now();
-        
-        // Create TCP listener for Matter operational channel
-        let listener = TcpListener::bind("127.0.0.1:0").await?;
-        let local_addr = listener.local_addr()?;
-        
-        // Create Matter TCP transport
-        self.tcp_transport = Some(TcpTransport::new(listener).await?);
-        
-        // Simulate connection establishment
-        let connection_time = connection_start.elapsed().as_micros() as f64 / 1000.0;
-        let overhead = 20 + 32; // TCP header + Matter session overhead
-        
-        info!("TCP transport analysis: {:.2}ms", connection_time);
-        
-        Ok(TcpMetrics {
-            connection_time,
-            overhead,
-            throughput_mbps: self.measure_tcp_throughput().await?,
-        })
-    }
-    
-    async fn measure_network_performance(&self) -> anyhow::Result<NetworkPerformance> {
-        debug!("Measuring real network performance...");
-        
-        // Measure round-trip time with ping-like test
-        let rtt_start = Instant::now();
-        
-        // Create test socket for RTT measurement
-        let test_socket = UdpSocket::bind("0.0.0.0:0").await?;
-        let test_data = b"Matter RTT Test";
-        
-        // Send to localhost and measure response time
-        match test_socket.send_to(test_data, "127.0.0.1:12345").await {
-            Ok(_) => debug!("RTT test packet sent"),
-            Err(_) => debug!("RTT test failed (expected)"),
-        }
-        
-        let rtt = rtt_start.elapsed().as_micros() as f64 / 1000.0;
-        
-        Ok(NetworkPerformance {
-            udp_throughput_mbps: 100.0, // Measured in real implementation
-            tcp_throughput_mbps: 95.0,  // Measured in real implementation
-            packet_loss_rate: 0.001,    // 0.1% loss rate
-            round_trip_time_ms: rtt,
-        })
-    }
-    
-    fn create_matter_discovery_message(&self) -> Vec<u8> {
-        // Real Matter discovery message format
-        let mut message = Vec::new();
-        
-        // Matter header
-        message.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // Message counter
-        message.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // Source node ID
-        message.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]); // Destination (broadcast)
-        
-        // Matter discovery payload
-        message.extend_from_slice(b"_matter._tcp.local.");
-        message.extend_from_slice(&[0x00, 0x0C, 0x00, 0x01]); // DNS query type
-        
-        message
-    }
-    
-    async fn measure_udp_throughput(&self) -> anyhow::Result<f64> {
-        // Simplified throughput measurement
-        // In real implementation, this would send/receive data and measure rate
-        Ok(100.0) // Mbps
-    }
-    
-    async fn measure_tcp_throughput(&self) -> anyhow::Result<f64> {
-        // Simplified throughput measurement
-        Ok(95.0) // Mbps
-    }
-    
-    fn calculate_real_efficiency(&self, udp: &UdpMetrics, tcp: &TcpMetrics, 
-                                network: &NetworkPerformance) -> f64 {
-        let base_efficiency = 0.75;
-        
-        // Factor in real network performance
-        let throughput_factor = (udp.throughput_mbps + tcp.throughput_mbps) / 200.0;
-        let rtt_penalty = (network.round_trip_time_ms / 100.0).min(0.2);
-        let loss_penalty = network.packet_loss_rate * 10.0;
-        
-        (base_efficiency * throughput_factor - rtt_penalty - loss_penalty).max(0.4)
-    }
-}
-
-struct UdpMetrics {
-    discovery_time: f64,
-    overhead: u32,
-    throughput_mbps: f64,
-}
-
-struct TcpMetrics {
-    connection_time: f64,
-    overhead: u32,
-    throughput_mbps: f64,
-}
\ No newline at end of file
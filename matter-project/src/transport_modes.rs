@@ -0,0 +1,415 @@
+// matter-project/src/transport_modes.rs
+/*!
+Matter operational exchanges over UDP (MRP) vs TCP - latency, overhead and reliability deltas
+*/
+
+use analysis_core::distribution::LatencyDistribution;
+use analysis_core::samples::{self, Layer};
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+
+use crate::builder::analyzer_builder;
+
+/// (exchange, request bytes, response bytes) - secured Matter message sizes
+/// including message header, protocol header and MIC.
+const OPERATIONAL_EXCHANGES: &[(&str, usize, usize)] = &[
+    ("ReadRequest", 60, 120),
+    ("WriteRequest", 80, 40),
+    ("InvokeCommand", 70, 50),
+    ("SubscribeRequest", 90, 150),
+];
+
+// Matter MRP defaults for an active peer
+pub(crate) const MRP_ACTIVE_RETRANS_TIMEOUT: Duration = Duration::from_millis(300);
+pub(crate) const MRP_BACKOFF_BASE: f64 = 1.6;
+pub(crate) const MRP_MAX_TRANSMISSIONS: u32 = 5;
+const STANDALONE_ACK_BYTES: usize = 26;
+
+// Header bytes added per packet below the Matter message
+pub const IPV4_UDP_HEADER_BYTES: usize = 20 + 8;
+pub(crate) const IPV4_TCP_HEADER_BYTES: usize = 20 + 20;
+pub(crate) const TCP_LENGTH_PREFIX_BYTES: usize = 4;
+pub(crate) const TCP_HANDSHAKE_BYTES: usize = 3 * IPV4_TCP_HEADER_BYTES;
+pub(crate) const TCP_TEARDOWN_BYTES: usize = 3 * IPV4_TCP_HEADER_BYTES;
+
+// Request layout: counter (u32 LE), kind, response length (u16 LE), padding
+const HEADER_BYTES: usize = 7;
+const KIND_REQUEST: u8 = 0x01;
+const KIND_RESPONSE: u8 = 0x02;
+const KIND_ACK: u8 = 0x10;
+
+#[derive(Debug, Clone)]
+pub struct TransportModeConfig {
+    pub rounds: u32,
+    /// Fraction of UDP datagrams the responder drops, to exercise MRP.
+    pub udp_loss_rate: f64,
+}
+
+impl Default for TransportModeConfig {
+    fn default() -> Self {
+        Self { rounds: 20, udp_loss_rate: 0.0 }
+    }
+}
+
+analyzer_builder!(TransportModeComparisonBuilder => TransportModeComparison(TransportModeConfig) {
+    rounds: u32,
+    udp_loss_rate: f64,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransportModeMetrics {
+    pub exchanges_per_mode: u32,
+    pub udp_loss_rate: f64,
+    pub udp_mrp: ModeMetrics,
+    pub tcp: ModeMetrics,
+    pub deltas: ModeDelta,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModeMetrics {
+    pub transport: String,
+    pub connection_setup_ms: f64,
+    pub median_latency_ms: f64,
+    pub p95_latency_ms: f64,
+    /// Estimated bytes on the wire per exchange, including IP/UDP/TCP headers,
+    /// MRP standalone ACKs and TCP length prefixes (pure TCP ACKs not counted).
+    pub wire_bytes_per_exchange: f64,
+    pub success_rate: f64,
+    pub retransmissions: u32,
+    /// Jitter and shape of the exchange latencies, in the order they ran.
+    pub distribution: LatencyDistribution,
+}
+
+/// TCP minus UDP/MRP; positive means TCP is slower / larger / more reliable.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModeDelta {
+    pub median_latency_ms: f64,
+    pub p95_latency_ms: f64,
+    pub jitter_ms: f64,
+    pub wire_bytes_per_exchange: f64,
+    pub success_rate: f64,
+}
+
+pub struct TransportModeComparison {
+    config: TransportModeConfig,
+}
+
+impl TransportModeComparison {
+    pub fn new(config: TransportModeConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_transport_modes(&mut self) -> Result<TransportModeMetrics> {
+        println!("\n🔁 Comparing Matter Operational Exchanges: UDP (MRP) vs TCP");
+        println!("-----------------------------------------------------------");
+
+        let udp_mrp = self.run_udp_mrp().await?;
+        let tcp = self.run_tcp().await?;
+
+        for mode in [&udp_mrp, &tcp] {
+            println!("✅ {}: {:.3}ms median, {:.3}ms jitter{}, {:.0} wire bytes/exchange, {:.1}% success, {} retransmissions",
+                     mode.transport, mode.median_latency_ms, mode.distribution.jitter_ms,
+                     if mode.distribution.bimodal { " (bimodal)" } else { "" },
+                     mode.wire_bytes_per_exchange, mode.success_rate * 100.0, mode.retransmissions);
+        }
+
+        let deltas = ModeDelta {
+            median_latency_ms: tcp.median_latency_ms - udp_mrp.median_latency_ms,
+            p95_latency_ms: tcp.p95_latency_ms - udp_mrp.p95_latency_ms,
+            jitter_ms: tcp.distribution.jitter_ms - udp_mrp.distribution.jitter_ms,
+            wire_bytes_per_exchange: tcp.wire_bytes_per_exchange - udp_mrp.wire_bytes_per_exchange,
+            success_rate: tcp.success_rate - udp_mrp.success_rate,
+        };
+
+        Ok(TransportModeMetrics {
+            exchanges_per_mode: self.config.rounds * OPERATIONAL_EXCHANGES.len() as u32,
+            udp_loss_rate: self.config.udp_loss_rate,
+            udp_mrp,
+            tcp,
+            deltas,
+        })
+    }
+
+    async fn run_udp_mrp(&self) -> Result<ModeMetrics> {
+        let responder = UdpSocket::bind("127.0.0.1:0").await?;
+        let responder_addr = responder.local_addr()?;
+        let responder_task = tokio::spawn(udp_responder(responder, self.config.udp_loss_rate));
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        socket.connect(responder_addr).await?;
+
+        let mut latencies = Vec::new();
+        let mut wire_bytes = 0usize;
+        let mut failures = 0u32;
+        let mut retransmissions = 0u32;
+        let mut counter = 0u32;
+        let mut buffer = vec![0u8; 2048];
+
+        for _ in 0..self.config.rounds {
+            for (exchange, request_len, response_len) in OPERATIONAL_EXCHANGES {
+                counter += 1;
+                let request = encode_request(counter, *request_len, *response_len);
+                let start = Instant::now();
+                let mut delivered = false;
+
+                for attempt in 0..MRP_MAX_TRANSMISSIONS {
+                    if attempt > 0 {
+                        retransmissions += 1;
+                    }
+                    socket.send(&request).await?;
+                    wire_bytes += request.len() + IPV4_UDP_HEADER_BYTES;
+
+                    let timeout = MRP_ACTIVE_RETRANS_TIMEOUT.mul_f64(MRP_BACKOFF_BASE.powi(attempt as i32));
+                    if wait_for_response(&socket, &mut buffer, counter, timeout).await? {
+                        delivered = true;
+                        break;
+                    }
+                }
+
+                if delivered {
+                    latencies.push(start.elapsed().as_secs_f64() * 1000.0);
+                    wire_bytes += response_len + IPV4_UDP_HEADER_BYTES;
+
+                    // MRP acknowledges every reliable message; the response needs a standalone ACK
+                    let mut ack = vec![0u8; STANDALONE_ACK_BYTES];
+                    ack[..4].copy_from_slice(&counter.to_le_bytes());
+                    ack[4] = KIND_ACK;
+                    socket.send(&ack).await?;
+                    wire_bytes += ack.len() + IPV4_UDP_HEADER_BYTES;
+                } else {
+                    failures += 1;
+                    debug!("{} #{} undelivered after {} transmissions", exchange, counter, MRP_MAX_TRANSMISSIONS);
+                }
+            }
+        }
+
+        responder_task.abort();
+        Ok(summarize("udp_mrp", 0.0, latencies, wire_bytes, failures, retransmissions))
+    }
+
+    async fn run_tcp(&self) -> Result<ModeMetrics> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let server_addr = listener.local_addr()?;
+        let server = tokio::spawn(tcp_responder(listener));
+
+        let setup_start = Instant::now();
+        let mut stream = TcpStream::connect(server_addr).await?;
+        stream.set_nodelay(true)?;
+        let connection_setup_ms = setup_start.elapsed().as_secs_f64() * 1000.0;
+
+        let mut latencies = Vec::new();
+        let mut wire_bytes = 0usize;
+        let mut failures = 0u32;
+        let mut counter = 0u32;
+
+        for _ in 0..self.config.rounds {
+            for (_, request_len, response_len) in OPERATIONAL_EXCHANGES {
+                counter += 1;
+                let request = encode_request(counter, *request_len, *response_len);
+                let start = Instant::now();
+
+                match tcp_exchange(&mut stream, &request, counter).await {
+                    Ok(()) => {
+                        latencies.push(start.elapsed().as_secs_f64() * 1000.0);
+                        wire_bytes += request_len + response_len
+                            + 2 * (TCP_LENGTH_PREFIX_BYTES + IPV4_TCP_HEADER_BYTES);
+                    }
+                    Err(e) => {
+                        failures += 1;
+                        debug!("TCP exchange #{} failed: {}", counter, e);
+                    }
+                }
+            }
+        }
+
+        drop(stream);
+        server.abort();
+
+        let total = latencies.len() + failures as usize;
+        // Spread the connection handshake over the exchanges it served
+        let wire_bytes = wire_bytes + if total > 0 { TCP_HANDSHAKE_BYTES } else { 0 };
+        Ok(summarize("tcp", connection_setup_ms, latencies, wire_bytes, failures, 0))
+    }
+}
+
+fn encode_request(counter: u32, request_len: usize, response_len: usize) -> Vec<u8> {
+    let mut request = vec![0u8; request_len.max(HEADER_BYTES)];
+    request[..4].copy_from_slice(&counter.to_le_bytes());
+    request[4] = KIND_REQUEST;
+    request[5..7].copy_from_slice(&(response_len as u16).to_le_bytes());
+    request
+}
+
+/// Builds the response for a request, or None for anything that isn't one.
+fn encode_response(request: &[u8]) -> Option<Vec<u8>> {
+    if request.len() < HEADER_BYTES || request[4] != KIND_REQUEST {
+        return None;
+    }
+    let response_len = u16::from_le_bytes([request[5], request[6]]) as usize;
+    let mut response = vec![0u8; response_len.max(HEADER_BYTES)];
+    response[..4].copy_from_slice(&request[..4]);
+    response[4] = KIND_RESPONSE;
+    Some(response)
+}
+
+/// Waits for the response carrying `counter`, discarding stale duplicates.
+async fn wait_for_response(socket: &UdpSocket, buffer: &mut [u8], counter: u32, timeout: Duration) -> Result<bool> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        match tokio::time::timeout_at(deadline, socket.recv(buffer)).await {
+            Err(_) => return Ok(false),
+            Ok(received) => {
+                let len = received?;
+                if len >= HEADER_BYTES && buffer[4] == KIND_RESPONSE && buffer[..4] == counter.to_le_bytes() {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+}
+
+async fn udp_responder(socket: UdpSocket, loss_rate: f64) -> Result<()> {
+    let mut buffer = vec![0u8; 2048];
+    let mut rng_state = 0x2545_F491_4F6C_DD1Du64;
+
+    loop {
+        let (len, peer) = socket.recv_from(&mut buffer).await?;
+        // Deterministic xorshift so impaired runs are repeatable
+        rng_state ^= rng_state << 13;
+        rng_state ^= rng_state >> 7;
+        rng_state ^= rng_state << 17;
+        let sample = (rng_state >> 11) as f64 / (1u64 << 53) as f64;
+        if sample < loss_rate {
+            continue;
+        }
+
+        if let Some(response) = encode_response(&buffer[..len]) {
+            socket.send_to(&response, peer).await?;
+        }
+    }
+}
+
+async fn tcp_exchange(stream: &mut TcpStream, request: &[u8], counter: u32) -> Result<()> {
+    stream.write_all(&frame(request)).await?;
+
+    let mut length = [0u8; TCP_LENGTH_PREFIX_BYTES];
+    stream.read_exact(&mut length).await?;
+    let mut response = vec![0u8; u32::from_le_bytes(length) as usize];
+    stream.read_exact(&mut response).await?;
+
+    if response.len() < HEADER_BYTES || response[..4] != counter.to_le_bytes() {
+        return Err(anyhow!("mismatched response"));
+    }
+    Ok(())
+}
+
+/// Length-prefixes a message so it leaves in a single segment.
+pub(crate) fn frame(message: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(TCP_LENGTH_PREFIX_BYTES + message.len());
+    framed.extend_from_slice(&(message.len() as u32).to_le_bytes());
+    framed.extend_from_slice(message);
+    framed
+}
+
+async fn tcp_responder(listener: TcpListener) -> Result<()> {
+    let (mut stream, _) = listener.accept().await?;
+    stream.set_nodelay(true)?;
+
+    loop {
+        let mut length = [0u8; TCP_LENGTH_PREFIX_BYTES];
+        if stream.read_exact(&mut length).await.is_err() {
+            return Ok(());
+        }
+        let mut request = vec![0u8; u32::from_le_bytes(length) as usize];
+        stream.read_exact(&mut request).await?;
+
+        if let Some(response) = encode_response(&request) {
+            stream.write_all(&frame(&response)).await?;
+        }
+    }
+}
+
+fn summarize(
+    transport: &str,
+    connection_setup_ms: f64,
+    mut latencies: Vec<f64>,
+    wire_bytes: usize,
+    failures: u32,
+    retransmissions: u32,
+) -> ModeMetrics {
+    samples::record("transport_modes", Layer::Transport, "Matter", transport, "latency_ms", &latencies);
+    let distribution = LatencyDistribution::from_samples(&latencies);
+    latencies.sort_by(|a, b| a.total_cmp(b));
+    let total = latencies.len() + failures as usize;
+    let percentile = |quantile: f64| {
+        if latencies.is_empty() {
+            0.0
+        } else {
+            latencies[((latencies.len() - 1) as f64 * quantile).round() as usize]
+        }
+    };
+
+    ModeMetrics {
+        transport: transport.to_string(),
+        connection_setup_ms,
+        median_latency_ms: percentile(0.5),
+        p95_latency_ms: percentile(0.95),
+        wire_bytes_per_exchange: if total > 0 { wire_bytes as f64 / total as f64 } else { 0.0 },
+        success_rate: if total > 0 { latencies.len() as f64 / total as f64 } else { 0.0 },
+        retransmissions,
+        distribution,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses_echo_the_request_counter_at_the_requested_length() {
+        let request = encode_request(7, 60, 120);
+        assert_eq!(request.len(), 60);
+
+        let response = encode_response(&request).unwrap();
+        assert_eq!(response.len(), 120);
+        assert_eq!(response[..4], 7u32.to_le_bytes());
+        assert_eq!(response[4], KIND_RESPONSE);
+        assert!(encode_response(&response).is_none(), "responses are not answered");
+        assert!(encode_response(&request[..HEADER_BYTES - 1]).is_none());
+
+        let framed = frame(&request);
+        assert_eq!(framed.len(), TCP_LENGTH_PREFIX_BYTES + 60);
+        assert_eq!(framed[..4], 60u32.to_le_bytes());
+    }
+
+    #[test]
+    fn failures_count_against_success_but_share_the_wire_bytes() {
+        let metrics = summarize("udp_mrp", 0.0, vec![3.0, 1.0, 2.0], 500, 1, 2);
+        assert_eq!(metrics.median_latency_ms, 2.0);
+        assert_eq!(metrics.p95_latency_ms, 3.0);
+        assert_eq!(metrics.wire_bytes_per_exchange, 125.0);
+        assert_eq!(metrics.success_rate, 0.75);
+
+        let empty = summarize("tcp", 0.0, Vec::new(), 0, 0, 0);
+        assert_eq!((empty.wire_bytes_per_exchange, empty.success_rate), (0.0, 0.0));
+    }
+
+    #[tokio::test]
+    async fn wire_overhead_adds_acks_for_udp_and_framing_plus_handshake_for_tcp() {
+        let metrics = TransportModeComparison::builder().rounds(1u32).build().analyze_transport_modes().await.unwrap();
+
+        let exchanges = OPERATIONAL_EXCHANGES.len() as f64;
+        let mean_payload = OPERATIONAL_EXCHANGES.iter().map(|(_, req, resp)| req + resp).sum::<usize>() as f64 / exchanges;
+        let udp_overhead = (3 * IPV4_UDP_HEADER_BYTES + STANDALONE_ACK_BYTES) as f64;
+        let tcp_overhead = (2 * (TCP_LENGTH_PREFIX_BYTES + IPV4_TCP_HEADER_BYTES)) as f64 + TCP_HANDSHAKE_BYTES as f64 / exchanges;
+
+        assert_eq!(metrics.udp_mrp.wire_bytes_per_exchange, mean_payload + udp_overhead);
+        assert_eq!(metrics.tcp.wire_bytes_per_exchange, mean_payload + tcp_overhead);
+        assert_eq!(metrics.deltas.wire_bytes_per_exchange, tcp_overhead - udp_overhead);
+        assert_eq!((metrics.udp_mrp.success_rate, metrics.udp_mrp.retransmissions), (1.0, 0));
+    }
+}
@@ -0,0 +1,554 @@
+// matter-project/src/wisun.rs
+/*!
+Wi-SUN mesh - measured single-hop exchanges carried across a large 802.15.4g mesh
+
+Wi-SUN FAN meshes for metering and street lighting are often ten or more hops
+deep. Each protocol's command exchange is first measured on loopback: a
+Matter InvokeRequest and InvokeResponse sealed and opened with the session
+keys, a CoAP CON PUT and its 2.04 ACK, and an MQTT QoS 1 PUBLISH and PUBACK
+on a kept TCP connection. The measured round trip stands for the two
+endpoints' own stack and processing time. The model then carries each
+exchange across the mesh:
+
+- the request travels down from the border router and the response travels
+  back up, hop by hop. Every hop costs a CSMA-CA backoff and the frame's
+  airtime at the PHY rate, with 802.15.4g PHY, MAC, security and
+  6LoWPAN-compressed headers. It also carries RPL's source routing header
+  on the way down and its RPL option on the way up. A frame that is not
+  acknowledged is retried up to the MAC retry limit after an ack wait. A
+  frame still lost after that is dropped. Every router forwards after a
+  random forwarding delay for queueing and processing.
+- the protocol's own timer recovers dropped exchanges. MRP uses its active
+  retransmission timeout and backoff, CoAP uses ACK_TIMEOUT with the random
+  factor and doubling, and TCP starts from the RFC 6298 initial RTO. A
+  round trip longer than the timer sends a spurious copy even when nothing
+  was lost.
+
+Runs are reported at fixed hop counts, and for the whole mesh with hop
+counts drawn from a distribution. Frequency hopping is assumed to follow
+the receiver's unicast schedule without waiting. TCP retransmits the
+whole exchange rather than each direction on its own timer. The mesh part
+is deterministic for a given configuration.
+*/
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::task::JoinHandle;
+
+use crate::bdx::matter_message;
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{
+    coap_message, mqtt_packet, mqtt_publish, tlv, uri_path, COAP_ACK, COAP_CHANGED, COAP_CON, COAP_PUT, IM_INVOKE_REQUEST,
+    IM_INVOKE_RESPONSE, MQTT_PUBACK, PROTOCOL_IM,
+};
+use crate::secure_session::{ephemeral_key, Endpoint, MESSAGE_HEADER_BYTES};
+use crate::start_modes::{invoke_request, invoke_response};
+use crate::transport_modes::{MRP_ACTIVE_RETRANS_TIMEOUT, MRP_BACKOFF_BASE, MRP_MAX_TRANSMISSIONS};
+use crate::workload::XorShift;
+
+/// SUN FSK preamble, SFD and PHR.
+const PHY_HEADER_BYTES: usize = 8 + 2 + 2;
+/// Frame control, sequence number, PAN ID, two extended addresses, UTT and
+/// terminator IEs, auxiliary security header, MIC-64 and 32-bit FCS.
+const MAC_OVERHEAD_BYTES: usize = 2 + 1 + 2 + 8 + 8 + 8 + 6 + 8 + 4;
+/// Enhanced ack with its time correction IE, secured.
+const ENH_ACK_BYTES: usize = PHY_HEADER_BYTES + 2 + 1 + 8 + 4 + 6 + 8 + 4;
+/// IPHC with context-compressed prefixes and inline IIDs, and NHC UDP.
+const IPHC_UDP_BYTES: usize = 2 + 8 + 8 + 7;
+/// IPHC with an inline next header and the uncompressed TCP header.
+const IPHC_TCP_BYTES: usize = 2 + 8 + 8 + 1 + 20;
+/// RFC 8138 RPI, upwards.
+const RPL_OPTION_BYTES: usize = 4;
+/// RFC 8138 source route header and one 8-byte address per router, downwards.
+const RPL_SOURCE_ROUTE_BYTES: usize = 3;
+const RPL_SOURCE_ROUTE_HOP_BYTES: usize = 8;
+/// macAckWaitDuration of a SUN PHY.
+const ACK_WAIT_MS: f64 = 10.0;
+
+// RFC 7252 transmission parameters
+const COAP_ACK_TIMEOUT_MS: f64 = 2000.0;
+const COAP_ACK_RANDOM_FACTOR: f64 = 1.5;
+const COAP_MAX_RETRANSMIT: u32 = 4;
+// RFC 6298 initial RTO and Linux's default retry count
+const TCP_INITIAL_RTO_MS: f64 = 1000.0;
+const TCP_MAX_RETRANSMISSIONS: u32 = 6;
+
+const SESSION_ID: u16 = 1;
+const MESH_SEED: u64 = 0x5715_4A6E_0000_0199;
+
+#[derive(Debug, Clone)]
+pub struct WisunConfig {
+    /// Fixed depths to report, in hops from the border router.
+    pub hop_counts: Vec<usize>,
+    /// Relative share of devices at 1, 2, 3, ... hops for the mesh-wide run.
+    pub hop_distribution: Vec<f64>,
+    pub rate_kbit: f64,
+    /// Mean queueing and processing delay in each forwarding router.
+    pub forwarding_delay: Duration,
+    /// Mean CSMA-CA backoff before each transmission.
+    pub csma_backoff: Duration,
+    /// Frame loss per transmission on each hop.
+    pub hop_loss: f64,
+    pub mac_retries: u32,
+    /// Exchanges measured on loopback per protocol.
+    pub iterations: usize,
+    /// Exchanges carried across the mesh per protocol and depth.
+    pub exchanges: usize,
+}
+
+impl Default for WisunConfig {
+    fn default() -> Self {
+        Self {
+            hop_counts: vec![1, 4, 8, 16],
+            hop_distribution: vec![4.0, 9.0, 14.0, 17.0, 16.0, 13.0, 10.0, 7.0, 5.0, 3.0, 2.0],
+            rate_kbit: 150.0,
+            forwarding_delay: Duration::from_millis(8),
+            csma_backoff: Duration::from_millis(4),
+            hop_loss: 0.05,
+            mac_retries: 3,
+            iterations: 500,
+            exchanges: 2000,
+        }
+    }
+}
+
+analyzer_builder!(WisunAnalyzerBuilder => WisunAnalyzer(WisunConfig) {
+    hop_counts: Vec<usize>,
+    hop_distribution: Vec<f64>,
+    rate_kbit: f64,
+    forwarding_delay: Duration,
+    csma_backoff: Duration,
+    hop_loss: f64,
+    mac_retries: u32,
+    iterations: usize,
+    exchanges: usize,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WisunMetrics {
+    pub single_hop: Vec<SingleHopExchange>,
+    pub runs: Vec<MeshRun>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SingleHopExchange {
+    pub protocol: String,
+    pub request_bytes: usize,
+    pub response_bytes: usize,
+    /// Measured loopback round trip.
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MeshRun {
+    pub protocol: String,
+    /// Fixed depth, or `None` for the mesh-wide hop distribution.
+    pub hops: Option<usize>,
+    pub mean_hops: f64,
+    pub delivered: usize,
+    pub failed: usize,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    /// End-to-end transmissions of the request per exchange.
+    pub transmissions_per_exchange: f64,
+    /// Transmissions sent while an earlier copy was still on its way to succeeding.
+    pub spurious_per_exchange: f64,
+    /// Every frame and ack on every hop, retries included.
+    pub air_bytes_per_exchange: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Recovery {
+    Mrp,
+    CoapCon,
+    Tcp,
+}
+
+struct Protocol {
+    name: &'static str,
+    recovery: Recovery,
+    request_bytes: usize,
+    response_bytes: usize,
+    /// Sorted loopback round trips.
+    measured_ms: Vec<f64>,
+}
+
+pub struct WisunAnalyzer {
+    config: WisunConfig,
+}
+
+impl WisunAnalyzer {
+    pub fn new(config: WisunConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_wisun(&mut self) -> Result<WisunMetrics> {
+        println!("\n🏙️ Analyzing Wi-SUN Mesh Latency ({} kbit/s, {:.0}% frame loss per hop)",
+                 self.config.rate_kbit, self.config.hop_loss * 100.0);
+        println!("-------------------------------------------------------------------");
+        if self.config.rate_kbit <= 0.0 || self.config.hop_distribution.iter().sum::<f64>() <= 0.0 {
+            return Err(anyhow!("the PHY rate and the hop distribution must be positive"));
+        }
+
+        let iterations = self.config.iterations.max(1);
+        let protocols = vec![
+            self.matter(iterations).await?,
+            self.coap(iterations).await?,
+            self.mqtt(iterations).await?,
+        ];
+
+        let mut single_hop = Vec::new();
+        for protocol in &protocols {
+            let exchange = SingleHopExchange {
+                protocol: protocol.name.to_string(),
+                request_bytes: protocol.request_bytes,
+                response_bytes: protocol.response_bytes,
+                p50_ms: percentile(&protocol.measured_ms, 50.0),
+                p95_ms: percentile(&protocol.measured_ms, 95.0),
+            };
+            println!("✅ {} measured on one hop: {}B → {}B, p50 {:.3}ms, p95 {:.3}ms",
+                     exchange.protocol, exchange.request_bytes, exchange.response_bytes, exchange.p50_ms, exchange.p95_ms);
+            single_hop.push(exchange);
+        }
+
+        let mut runs = Vec::new();
+        for protocol in &protocols {
+            let depths = self.config.hop_counts.iter().map(|&hops| Some(hops.max(1))).chain([None]);
+            for hops in depths {
+                let run = Mesh::new(&self.config).run(protocol, hops, self.config.exchanges.max(1));
+                let depth = hops.map_or_else(|| format!("mesh-wide ({:.1} hops)", run.mean_hops), |hops| format!("{} hop{}", hops, if hops == 1 { "" } else { "s" }));
+                println!("✅ {} @ {}: p50 {:.0}ms, p95 {:.0}ms, p99 {:.0}ms, {}/{} delivered, {:.2} tx/exchange ({:.2} spurious), {:.0}B on air",
+                         run.protocol, depth, run.p50_ms, run.p95_ms, run.p99_ms, run.delivered,
+                         run.delivered + run.failed, run.transmissions_per_exchange, run.spurious_per_exchange,
+                         run.air_bytes_per_exchange);
+                runs.push(run);
+            }
+        }
+
+        Ok(WisunMetrics { single_hop, runs })
+    }
+
+    /// InvokeRequest and InvokeResponse, sealed and opened by both ends of a session.
+    async fn matter(&self, iterations: usize) -> Result<Protocol> {
+        let request = tlv(invoke_request)?;
+        let response = tlv(invoke_response)?;
+        let (controller_key, controller_hello) = ephemeral_key()?;
+        let (device_key, device_hello) = ephemeral_key()?;
+        let controller = Endpoint::agree(controller_key, &device_hello[MESSAGE_HEADER_BYTES..], true)?;
+        let device = Endpoint::agree(device_key, &controller_hello[MESSAGE_HEADER_BYTES..], false)?;
+
+        let responder = UdpSocket::bind("127.0.0.1:0").await?;
+        let address = responder.local_addr()?;
+        let expected = request.clone();
+        let reply = response.clone();
+        let task: JoinHandle<Result<()>> = tokio::spawn(async move {
+            let mut buffer = vec![0u8; 1500];
+            let mut counter = 0;
+            loop {
+                let (len, from) = responder.recv_from(&mut buffer).await?;
+                if device.open(buffer[..len].to_vec())? != expected {
+                    return Err(anyhow!("device decrypted a different request"));
+                }
+                counter += 1;
+                responder.send_to(&device.seal(SESSION_ID, counter, &reply)?, from).await?;
+            }
+        });
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        socket.connect(address).await?;
+        let mut buffer = vec![0u8; 1500];
+        let mut measured_ms = Vec::with_capacity(iterations);
+        for counter in 1..=iterations as u32 {
+            let start = Instant::now();
+            socket.send(&controller.seal(SESSION_ID, counter, &request)?).await?;
+            let len = socket.recv(&mut buffer).await?;
+            if controller.open(buffer[..len].to_vec())? != response {
+                return Err(anyhow!("controller decrypted a different response"));
+            }
+            measured_ms.push(start.elapsed().as_secs_f64() * 1000.0);
+        }
+        task.abort();
+
+        measured_ms.sort_by(f64::total_cmp);
+        Ok(Protocol {
+            name: "Matter (MRP)",
+            recovery: Recovery::Mrp,
+            request_bytes: matter_message(PROTOCOL_IM, IM_INVOKE_REQUEST, &request).len(),
+            response_bytes: matter_message(PROTOCOL_IM, IM_INVOKE_RESPONSE, &response).len(),
+            measured_ms,
+        })
+    }
+
+    async fn coap(&self, iterations: usize) -> Result<Protocol> {
+        let request = coap_message(COAP_CON, COAP_PUT, 1, &uri_path(&["light", "on"]), b"1");
+        let response = coap_message(COAP_ACK, COAP_CHANGED, 1, &[], &[]);
+
+        let responder = UdpSocket::bind("127.0.0.1:0").await?;
+        let address = responder.local_addr()?;
+        let reply = response.clone();
+        let task: JoinHandle<Result<()>> = tokio::spawn(async move {
+            let mut buffer = vec![0u8; 1500];
+            loop {
+                let (_, from) = responder.recv_from(&mut buffer).await?;
+                responder.send_to(&reply, from).await?;
+            }
+        });
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        socket.connect(address).await?;
+        let mut buffer = vec![0u8; 1500];
+        let mut measured_ms = Vec::with_capacity(iterations);
+        for _ in 0..iterations {
+            let start = Instant::now();
+            socket.send(&request).await?;
+            let len = socket.recv(&mut buffer).await?;
+            if buffer[..len] != response[..] {
+                return Err(anyhow!("unexpected CoAP response"));
+            }
+            measured_ms.push(start.elapsed().as_secs_f64() * 1000.0);
+        }
+        task.abort();
+
+        measured_ms.sort_by(f64::total_cmp);
+        Ok(Protocol {
+            name: "CoAP CON",
+            recovery: Recovery::CoapCon,
+            request_bytes: request.len(),
+            response_bytes: response.len(),
+            measured_ms,
+        })
+    }
+
+    /// QoS 1 PUBLISH and PUBACK on a connection opened beforehand.
+    async fn mqtt(&self, iterations: usize) -> Result<Protocol> {
+        let request = mqtt_publish("home/light1/set", Some(1), b"on");
+        let response = mqtt_packet(MQTT_PUBACK, &1u16.to_be_bytes());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let (request_len, reply) = (request.len(), response.clone());
+        let task: JoinHandle<Result<()>> = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await?;
+            stream.set_nodelay(true)?;
+            let mut buffer = vec![0u8; request_len];
+            loop {
+                stream.read_exact(&mut buffer).await?;
+                stream.write_all(&reply).await?;
+            }
+        });
+
+        let mut stream = TcpStream::connect(address).await?;
+        stream.set_nodelay(true)?;
+        let mut buffer = vec![0u8; response.len()];
+        let mut measured_ms = Vec::with_capacity(iterations);
+        for _ in 0..iterations {
+            let start = Instant::now();
+            stream.write_all(&request).await?;
+            stream.read_exact(&mut buffer).await?;
+            measured_ms.push(start.elapsed().as_secs_f64() * 1000.0);
+        }
+        task.abort();
+
+        measured_ms.sort_by(f64::total_cmp);
+        Ok(Protocol {
+            name: "MQTT QoS 1",
+            recovery: Recovery::Tcp,
+            request_bytes: request.len(),
+            response_bytes: response.len(),
+            measured_ms,
+        })
+    }
+}
+
+/// One frame's trip across the mesh.
+struct Traversal {
+    arrival: Option<f64>,
+    air_bytes: usize,
+}
+
+struct Mesh<'a> {
+    config: &'a WisunConfig,
+    rng: XorShift,
+}
+
+impl<'a> Mesh<'a> {
+    fn new(config: &'a WisunConfig) -> Self {
+        Self { config, rng: XorShift(MESH_SEED) }
+    }
+
+    fn run(&mut self, protocol: &Protocol, hops: Option<usize>, exchanges: usize) -> MeshRun {
+        let mut latencies = Vec::with_capacity(exchanges);
+        let (mut failed, mut transmissions, mut spurious, mut air_bytes, mut total_hops) = (0, 0, 0, 0, 0);
+        for _ in 0..exchanges {
+            let depth = hops.unwrap_or_else(|| self.draw_hops());
+            total_hops += depth;
+            let service_ms = protocol.measured_ms[self.rng.below(protocol.measured_ms.len())];
+            let exchange = self.exchange(protocol, depth, service_ms);
+            transmissions += exchange.transmissions;
+            spurious += exchange.spurious;
+            air_bytes += exchange.air_bytes;
+            match exchange.latency_ms {
+                Some(latency) => latencies.push(latency),
+                None => failed += 1,
+            }
+        }
+        latencies.sort_by(f64::total_cmp);
+        let per = |count: usize| count as f64 / exchanges as f64;
+        MeshRun {
+            protocol: protocol.name.to_string(),
+            hops,
+            mean_hops: per(total_hops),
+            delivered: latencies.len(),
+            failed,
+            p50_ms: percentile(&latencies, 50.0),
+            p95_ms: percentile(&latencies, 95.0),
+            p99_ms: percentile(&latencies, 99.0),
+            transmissions_per_exchange: per(transmissions),
+            spurious_per_exchange: per(spurious),
+            air_bytes_per_exchange: per(air_bytes),
+        }
+    }
+
+    fn draw_hops(&mut self) -> usize {
+        let total: f64 = self.config.hop_distribution.iter().sum();
+        let mut draw = self.rng.unit() * total;
+        for (i, weight) in self.config.hop_distribution.iter().enumerate() {
+            if draw < *weight {
+                return i + 1;
+            }
+            draw -= weight;
+        }
+        self.config.hop_distribution.len()
+    }
+
+    /// Sends copies of the request on the protocol's timer until a response
+    /// arrives before the next copy would go out, or the copies run out.
+    fn exchange(&mut self, protocol: &Protocol, hops: usize, service_ms: f64) -> Exchange {
+        let (mut timeout, backoff, copies) = match protocol.recovery {
+            Recovery::Mrp => (MRP_ACTIVE_RETRANS_TIMEOUT.as_secs_f64() * 1000.0, MRP_BACKOFF_BASE, MRP_MAX_TRANSMISSIONS),
+            Recovery::CoapCon => (
+                COAP_ACK_TIMEOUT_MS * (1.0 + self.rng.unit() * (COAP_ACK_RANDOM_FACTOR - 1.0)),
+                2.0,
+                COAP_MAX_RETRANSMIT + 1,
+            ),
+            Recovery::Tcp => (TCP_INITIAL_RTO_MS, 2.0, TCP_MAX_RETRANSMISSIONS + 1),
+        };
+        let (request_frame, response_frame) = self.frames(protocol, hops);
+
+        let mut exchange = Exchange { latency_ms: None, transmissions: 0, spurious: 0, air_bytes: 0 };
+        let mut sent_at = 0.0;
+        for _ in 0..copies {
+            if exchange.latency_ms.is_some_and(|done| done <= sent_at) {
+                break;
+            }
+            if exchange.latency_ms.is_some() {
+                exchange.spurious += 1;
+            }
+            exchange.transmissions += 1;
+            let request = self.traverse(request_frame, hops, sent_at);
+            exchange.air_bytes += request.air_bytes;
+            if let Some(arrival) = request.arrival {
+                let response = self.traverse(response_frame, hops, arrival + service_ms);
+                exchange.air_bytes += response.air_bytes;
+                if let Some(done) = response.arrival {
+                    exchange.latency_ms = Some(exchange.latency_ms.map_or(done, |earlier: f64| earlier.min(done)));
+                }
+            }
+            sent_at += timeout;
+            timeout *= backoff;
+        }
+        exchange
+    }
+
+    /// On-air bytes of the request, travelling down with a source route, and of the response, travelling up.
+    fn frames(&self, protocol: &Protocol, hops: usize) -> (usize, usize) {
+        let headers = PHY_HEADER_BYTES
+            + MAC_OVERHEAD_BYTES
+            + match protocol.recovery {
+                Recovery::Tcp => IPHC_TCP_BYTES,
+                _ => IPHC_UDP_BYTES,
+            };
+        let source_route = RPL_SOURCE_ROUTE_BYTES + (hops - 1) * RPL_SOURCE_ROUTE_HOP_BYTES;
+        (headers + source_route + protocol.request_bytes, headers + RPL_OPTION_BYTES + protocol.response_bytes)
+    }
+
+    fn traverse(&mut self, frame_bytes: usize, hops: usize, at: f64) -> Traversal {
+        let airtime = |bytes: usize| bytes as f64 * 8.0 / self.config.rate_kbit;
+        let backoff_ms = self.config.csma_backoff.as_secs_f64() * 1000.0;
+        let forwarding_ms = self.config.forwarding_delay.as_secs_f64() * 1000.0;
+        let mut traversal = Traversal { arrival: None, air_bytes: 0 };
+        let mut now = at;
+        for hop in 0..hops {
+            if hop > 0 {
+                now += self.rng.exponential(forwarding_ms);
+            }
+            let mut delivered = false;
+            for _ in 0..=self.config.mac_retries {
+                now += self.rng.exponential(backoff_ms) + airtime(frame_bytes);
+                traversal.air_bytes += frame_bytes;
+                if self.rng.unit() >= self.config.hop_loss {
+                    now += airtime(ENH_ACK_BYTES);
+                    traversal.air_bytes += ENH_ACK_BYTES;
+                    delivered = true;
+                    break;
+                }
+                now += ACK_WAIT_MS;
+            }
+            if !delivered {
+                return traversal;
+            }
+        }
+        traversal.arrival = Some(now);
+        traversal
+    }
+}
+
+struct Exchange {
+    latency_ms: Option<f64>,
+    transmissions: usize,
+    spurious: usize,
+    air_bytes: usize,
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((p / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn latency_grows_with_depth_and_short_timers_fire_spuriously() {
+        let metrics = WisunAnalyzer::builder()
+            .hop_counts(vec![1usize, 16])
+            .iterations(50usize)
+            .exchanges(500usize)
+            .build()
+            .analyze_wisun()
+            .await
+            .unwrap();
+        let run = |protocol: &str, hops: Option<usize>| {
+            metrics.runs.iter().find(|run| run.protocol.starts_with(protocol) && run.hops == hops).unwrap()
+        };
+
+        for protocol in ["Matter", "CoAP", "MQTT"] {
+            assert!(run(protocol, Some(16)).p50_ms > 4.0 * run(protocol, Some(1)).p50_ms, "{}", protocol);
+            let mesh = run(protocol, None);
+            assert!(mesh.mean_hops > 3.0 && mesh.mean_hops < 7.0, "{}", mesh.mean_hops);
+        }
+        // MRP's 300 ms timer is shorter than a 16-hop round trip; CoAP's 2 s is not
+        assert!(run("Matter", Some(16)).spurious_per_exchange > 0.5);
+        assert!(run("CoAP", Some(16)).spurious_per_exchange < 0.1);
+        assert_eq!(run("Matter", Some(1)).spurious_per_exchange, 0.0);
+    }
+}
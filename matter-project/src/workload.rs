@@ -0,0 +1,447 @@
+// matter-project/src/workload.rs
+/*!
+Synthetic traffic workloads - periodic telemetry, bursty events, command storms and diurnal command cycles over a device fleet
+
+The generator produces a time-ordered list of device interactions that any
+analyzer can replay. The fleet cycles through the light, lock, thermostat and
+sensor scenarios, and every event names one of that scenario's interactions,
+so its cost per protocol comes straight from the scenario encodings. Arrivals
+are drawn from a seeded xorshift generator, so a seed always gives the same
+workload.
+*/
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::builder::analyzer_builder;
+use crate::scenarios::{interaction_costs, DeviceScenario, ProtocolCost};
+
+/// Time spread of the device events making up one burst.
+const BURST_SPREAD_MS: f64 = 2000.0;
+/// Time spread of the commands making up one storm (a scene or group command).
+const STORM_SPREAD_MS: f64 = 1000.0;
+/// Diurnal command rate swings ±80% around the mean, peaking at 19:00.
+const DIURNAL_AMPLITUDE: f64 = 0.8;
+const DIURNAL_PEAK_HOUR: f64 = 19.0;
+/// Periodic reports are jittered by up to ±10% of the period.
+const TELEMETRY_JITTER: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrafficPattern {
+    /// Every device reports on a fixed period with jitter.
+    PeriodicTelemetry,
+    /// Random bursts in which several devices report an event within a couple of seconds.
+    BurstyEvents,
+    /// A controller commands every light and lock at once, at a fixed interval.
+    CommandStorm,
+    /// User commands whose rate follows the time of day.
+    Diurnal,
+}
+
+impl TrafficPattern {
+    pub const ALL: [TrafficPattern; 4] =
+        [Self::PeriodicTelemetry, Self::BurstyEvents, Self::CommandStorm, Self::Diurnal];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PeriodicTelemetry => "periodic_telemetry",
+            Self::BurstyEvents => "bursty_events",
+            Self::CommandStorm => "command_storm",
+            Self::Diurnal => "diurnal",
+        }
+    }
+
+    /// Interaction of `scenario` a device performs for this pattern.
+    fn interaction(self, scenario: DeviceScenario) -> &'static str {
+        match (self, scenario) {
+            (Self::PeriodicTelemetry, DeviceScenario::Light) => "on_off_report",
+            (Self::PeriodicTelemetry, DeviceScenario::Lock) => "battery_report",
+            (Self::PeriodicTelemetry, DeviceScenario::Thermostat) => "local_temperature_report",
+            (Self::PeriodicTelemetry, DeviceScenario::Sensor) => "temperature_report",
+            (Self::BurstyEvents, DeviceScenario::Light) => "on_off_report",
+            (Self::BurstyEvents, DeviceScenario::Lock) => "lock_state_report",
+            (Self::BurstyEvents, DeviceScenario::Thermostat) => "local_temperature_report",
+            (Self::BurstyEvents, DeviceScenario::Sensor) => "humidity_report",
+            (Self::CommandStorm, DeviceScenario::Light) => "turn_off",
+            (Self::CommandStorm, DeviceScenario::Lock) => "lock",
+            (Self::CommandStorm, DeviceScenario::Thermostat) => "write_heating_setpoint",
+            (Self::CommandStorm, DeviceScenario::Sensor) => "read_temperature",
+            (Self::Diurnal, DeviceScenario::Light) => "turn_on",
+            (Self::Diurnal, DeviceScenario::Lock) => "unlock",
+            (Self::Diurnal, DeviceScenario::Thermostat) => "raise_setpoint",
+            (Self::Diurnal, DeviceScenario::Sensor) => "read_temperature",
+        }
+    }
+}
+
+impl FromStr for TrafficPattern {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|pattern| pattern.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                anyhow!("unknown traffic pattern: {} (expected periodic_telemetry, bursty_events, command_storm or diurnal)", name)
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkloadConfig {
+    pub patterns: Vec<TrafficPattern>,
+    pub devices: usize,
+    /// Simulated time; diurnal cycles assume the run starts at midnight.
+    pub duration: Duration,
+    pub telemetry_period: Duration,
+    /// Mean time between event bursts across the fleet.
+    pub burst_interval: Duration,
+    /// Devices reporting in one burst.
+    pub burst_size: usize,
+    pub storm_interval: Duration,
+    /// Mean user commands per device per hour over a day.
+    pub commands_per_device_hour: f64,
+    pub seed: u64,
+}
+
+impl Default for WorkloadConfig {
+    fn default() -> Self {
+        Self {
+            patterns: TrafficPattern::ALL.to_vec(),
+            devices: 40,
+            duration: Duration::from_secs(24 * 3600),
+            telemetry_period: Duration::from_secs(60),
+            burst_interval: Duration::from_secs(600),
+            burst_size: 8,
+            storm_interval: Duration::from_secs(4 * 3600),
+            commands_per_device_hour: 1.0,
+            seed: 0x2545_F491_4F6C_DD1D,
+        }
+    }
+}
+
+analyzer_builder!(WorkloadGeneratorBuilder => WorkloadGenerator(WorkloadConfig) {
+    patterns: Vec<TrafficPattern>,
+    devices: usize,
+    duration: Duration,
+    telemetry_period: Duration,
+    burst_interval: Duration,
+    burst_size: usize,
+    storm_interval: Duration,
+    commands_per_device_hour: f64,
+    seed: u64,
+});
+
+/// One device interaction of a generated workload.
+#[derive(Debug, Clone)]
+pub struct WorkloadEvent {
+    pub at_ms: f64,
+    pub device: usize,
+    pub scenario: DeviceScenario,
+    pub pattern: TrafficPattern,
+    /// Interaction name from the device's scenario.
+    pub interaction: &'static str,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkloadMetrics {
+    pub devices: usize,
+    pub duration_s: f64,
+    pub seed: u64,
+    pub patterns: Vec<PatternSummary>,
+    pub protocols: Vec<WorkloadProtocolResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PatternSummary {
+    pub pattern: TrafficPattern,
+    pub events: usize,
+    pub peak_events_per_s: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkloadProtocolResult {
+    pub protocol: String,
+    pub messages: usize,
+    /// Message bytes plus IPv4 and UDP/TCP headers.
+    pub wire_bytes: usize,
+    pub mean_messages_per_s: f64,
+    /// Busiest one-second window.
+    pub peak_messages_per_s: usize,
+    pub p99_messages_per_s: usize,
+    pub peak_wire_bytes_per_s: usize,
+    pub peak_to_mean: f64,
+}
+
+pub struct WorkloadGenerator {
+    config: WorkloadConfig,
+}
+
+impl WorkloadGenerator {
+    pub fn new(config: WorkloadConfig) -> Self {
+        Self { config }
+    }
+
+    /// The configured patterns merged into one time-ordered list.
+    pub fn generate(&self) -> Result<Vec<WorkloadEvent>> {
+        if self.config.devices == 0 {
+            return Err(anyhow!("a workload needs at least one device"));
+        }
+        let mut rng = XorShift(self.config.seed.max(1));
+        let mut events = Vec::new();
+        for &pattern in &self.config.patterns {
+            match pattern {
+                TrafficPattern::PeriodicTelemetry => self.periodic(&mut rng, &mut events),
+                TrafficPattern::BurstyEvents => self.bursts(&mut rng, &mut events),
+                TrafficPattern::CommandStorm => self.storms(&mut rng, &mut events),
+                TrafficPattern::Diurnal => self.diurnal(&mut rng, &mut events),
+            }
+        }
+        events.sort_by(|a, b| a.at_ms.total_cmp(&b.at_ms));
+        Ok(events)
+    }
+
+    pub fn analyze_workload(&mut self) -> Result<WorkloadMetrics> {
+        println!("\n📈 Analyzing Synthetic Workload ({} devices, {:.1} h)",
+                 self.config.devices, self.config.duration.as_secs_f64() / 3600.0);
+        println!("------------------------------------------------");
+
+        let events = self.generate()?;
+        let seconds = self.config.duration.as_secs().max(1) as usize;
+
+        let mut patterns = Vec::new();
+        for &pattern in &self.config.patterns {
+            let mut per_second = vec![0usize; seconds];
+            let mut count = 0;
+            for event in events.iter().filter(|e| e.pattern == pattern) {
+                per_second[second(event.at_ms, seconds)] += 1;
+                count += 1;
+            }
+            let peak = per_second.iter().copied().max().unwrap_or(0);
+            println!("✅ {}: {} events, peak {}/s", pattern.name(), count, peak);
+            patterns.push(PatternSummary { pattern, events: count, peak_events_per_s: peak });
+        }
+
+        // Replays every event through each protocol's encoding of its interaction
+        let mut costs: HashMap<(DeviceScenario, &str), Vec<ProtocolCost>> = HashMap::new();
+        let mut totals: Vec<(&'static str, Vec<usize>, Vec<usize>)> = Vec::new();
+        for event in &events {
+            let cost = match costs.entry((event.scenario, event.interaction)) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(interaction_costs(event.scenario, event.interaction)?),
+            };
+            for (index, &(protocol, messages, wire_bytes)) in cost.iter().enumerate() {
+                if totals.len() <= index {
+                    totals.push((protocol, vec![0; seconds], vec![0; seconds]));
+                }
+                let at = second(event.at_ms, seconds);
+                totals[index].1[at] += messages;
+                totals[index].2[at] += wire_bytes;
+            }
+        }
+
+        let mut protocols = Vec::new();
+        for (protocol, messages, wire_bytes) in totals {
+            let total_messages: usize = messages.iter().sum();
+            let mean = total_messages as f64 / seconds as f64;
+            let mut sorted = messages.clone();
+            sorted.sort_unstable();
+            let peak = sorted.last().copied().unwrap_or(0);
+            let p99 = sorted[((sorted.len() - 1) as f64 * 0.99) as usize];
+            let result = WorkloadProtocolResult {
+                protocol: protocol.to_string(),
+                messages: total_messages,
+                wire_bytes: wire_bytes.iter().sum(),
+                mean_messages_per_s: mean,
+                peak_messages_per_s: peak,
+                p99_messages_per_s: p99,
+                peak_wire_bytes_per_s: wire_bytes.iter().copied().max().unwrap_or(0),
+                peak_to_mean: if mean > 0.0 { peak as f64 / mean } else { 0.0 },
+            };
+            println!("✅ {}: {} messages, {:.1} MB, mean {:.2} msg/s, peak {} msg/s ({:.0}x mean)",
+                     result.protocol, result.messages, result.wire_bytes as f64 / 1_048_576.0,
+                     result.mean_messages_per_s, result.peak_messages_per_s, result.peak_to_mean);
+            protocols.push(result);
+        }
+
+        Ok(WorkloadMetrics {
+            devices: self.config.devices,
+            duration_s: self.config.duration.as_secs_f64(),
+            seed: self.config.seed,
+            patterns,
+            protocols,
+        })
+    }
+
+    fn duration_ms(&self) -> f64 {
+        self.config.duration.as_secs_f64() * 1000.0
+    }
+
+    fn event(&self, at_ms: f64, device: usize, pattern: TrafficPattern) -> WorkloadEvent {
+        let scenario = DeviceScenario::ALL[device % DeviceScenario::ALL.len()];
+        WorkloadEvent { at_ms, device, scenario, pattern, interaction: pattern.interaction(scenario) }
+    }
+
+    fn periodic(&self, rng: &mut XorShift, events: &mut Vec<WorkloadEvent>) {
+        let period_ms = self.config.telemetry_period.as_secs_f64() * 1000.0;
+        if period_ms <= 0.0 {
+            return;
+        }
+        for device in 0..self.config.devices {
+            // Devices boot at random phases, then report every period ± jitter
+            let mut at_ms = rng.unit() * period_ms;
+            while at_ms < self.duration_ms() {
+                events.push(self.event(at_ms, device, TrafficPattern::PeriodicTelemetry));
+                at_ms += period_ms * (1.0 + TELEMETRY_JITTER * (2.0 * rng.unit() - 1.0));
+            }
+        }
+    }
+
+    fn bursts(&self, rng: &mut XorShift, events: &mut Vec<WorkloadEvent>) {
+        let mean_ms = self.config.burst_interval.as_secs_f64() * 1000.0;
+        if mean_ms <= 0.0 {
+            return;
+        }
+        let mut at_ms = rng.exponential(mean_ms);
+        while at_ms < self.duration_ms() {
+            let first = rng.below(self.config.devices);
+            for offset in 0..self.config.burst_size.min(self.config.devices) {
+                let device = (first + offset) % self.config.devices;
+                let event_ms = (at_ms + rng.unit() * BURST_SPREAD_MS).min(self.duration_ms() - 1.0);
+                events.push(self.event(event_ms, device, TrafficPattern::BurstyEvents));
+            }
+            at_ms += rng.exponential(mean_ms);
+        }
+    }
+
+    fn storms(&self, rng: &mut XorShift, events: &mut Vec<WorkloadEvent>) {
+        let interval_ms = self.config.storm_interval.as_secs_f64() * 1000.0;
+        if interval_ms <= 0.0 {
+            return;
+        }
+        let mut at_ms = interval_ms;
+        while at_ms < self.duration_ms() {
+            for device in 0..self.config.devices {
+                let scenario = DeviceScenario::ALL[device % DeviceScenario::ALL.len()];
+                if matches!(scenario, DeviceScenario::Light | DeviceScenario::Lock) {
+                    let event_ms = (at_ms + rng.unit() * STORM_SPREAD_MS).min(self.duration_ms() - 1.0);
+                    events.push(self.event(event_ms, device, TrafficPattern::CommandStorm));
+                }
+            }
+            at_ms += interval_ms;
+        }
+    }
+
+    /// Non-homogeneous Poisson arrivals by thinning: candidates at the peak rate,
+    /// each kept with the ratio of the time-of-day rate to the peak.
+    fn diurnal(&self, rng: &mut XorShift, events: &mut Vec<WorkloadEvent>) {
+        let mean_per_ms = self.config.commands_per_device_hour * self.config.devices as f64 / 3_600_000.0;
+        if mean_per_ms <= 0.0 {
+            return;
+        }
+        let peak_per_ms = mean_per_ms * (1.0 + DIURNAL_AMPLITUDE);
+        let mut at_ms = rng.exponential(1.0 / peak_per_ms);
+        while at_ms < self.duration_ms() {
+            let hour = at_ms / 3_600_000.0 % 24.0;
+            let phase = (hour - DIURNAL_PEAK_HOUR + 6.0) / 24.0 * std::f64::consts::TAU;
+            let rate = mean_per_ms * (1.0 + DIURNAL_AMPLITUDE * phase.sin());
+            if rng.unit() < rate / peak_per_ms {
+                events.push(self.event(at_ms, rng.below(self.config.devices), TrafficPattern::Diurnal));
+            }
+            at_ms += rng.exponential(1.0 / peak_per_ms);
+        }
+    }
+}
+
+fn second(at_ms: f64, seconds: usize) -> usize {
+    ((at_ms / 1000.0) as usize).min(seconds - 1)
+}
+
+/// Deterministic xorshift so workloads are repeatable.
+pub(crate) struct XorShift(pub(crate) u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniform in [0, 1).
+    pub(crate) fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound.max(1) as u64) as usize
+    }
+
+    pub(crate) fn exponential(&mut self, mean: f64) -> f64 {
+        -(1.0 - self.unit()).ln() * mean
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_seed_always_gives_the_same_ordered_workload() {
+        let generator = |seed: u64| WorkloadGenerator::builder().duration(Duration::from_secs(3600)).seed(seed).build();
+        let first = generator(7).generate().unwrap();
+        let again = generator(7).generate().unwrap();
+        let other = generator(8).generate().unwrap();
+
+        let times = |events: &[WorkloadEvent]| events.iter().map(|e| e.at_ms).collect::<Vec<_>>();
+        assert_eq!(times(&first), times(&again));
+        assert_ne!(times(&first), times(&other));
+        assert!(first.windows(2).all(|pair| pair[0].at_ms <= pair[1].at_ms));
+        assert!(first.iter().all(|e| e.at_ms < 3_600_000.0 && e.interaction == e.pattern.interaction(e.scenario)));
+        assert!(WorkloadGenerator::builder().devices(0usize).build().generate().is_err());
+    }
+
+    #[test]
+    fn storms_command_every_light_and_lock_each_interval() {
+        let events = WorkloadGenerator::builder()
+            .patterns(vec![TrafficPattern::CommandStorm])
+            .devices(8usize)
+            .duration(Duration::from_secs(9 * 3600))
+            .build()
+            .generate()
+            .unwrap();
+
+        // Storms at 4 h and 8 h; devices 0, 1, 4 and 5 are lights and locks
+        assert_eq!(events.len(), 2 * 4);
+        let mut devices: Vec<usize> = events.iter().map(|e| e.device).collect();
+        devices.sort_unstable();
+        assert_eq!(devices, [0, 0, 1, 1, 4, 4, 5, 5]);
+        assert!(events[..4].iter().all(|e| (14_400_000.0..14_401_000.0).contains(&e.at_ms)));
+    }
+
+    #[test]
+    fn protocol_totals_sum_the_cost_of_every_event() {
+        let mut generator = WorkloadGenerator::builder()
+            .patterns(vec![TrafficPattern::PeriodicTelemetry])
+            .devices(4usize)
+            .duration(Duration::from_secs(600))
+            .build();
+        let events = generator.generate().unwrap();
+        let metrics = generator.analyze_workload().unwrap();
+
+        assert_eq!(metrics.patterns[0].events, events.len());
+        for (index, result) in metrics.protocols.iter().enumerate() {
+            let expected: usize = events.iter().map(|e| interaction_costs(e.scenario, e.interaction).unwrap()[index].1).sum();
+            assert_eq!(result.messages, expected, "{}", result.protocol);
+            assert!((result.mean_messages_per_s - expected as f64 / 600.0).abs() < 1e-9);
+            assert!((result.peak_to_mean - result.peak_messages_per_s as f64 / result.mean_messages_per_s).abs() < 1e-9);
+        }
+        assert_eq!(second(599_999.0, 600), 599);
+        assert_eq!(second(700_000.0, 600), 599);
+    }
+}
@@ -0,0 +1,950 @@
+// matter-project/src/zwave.rs
+/*!
+Z-Wave - inclusion time, command latency and frame overhead through a Serial API controller
+
+Z-Wave is the other dominant smart-home protocol. Hosts such as Z-Wave JS
+drive a USB controller over its Serial API. Each frame is SOF, length,
+request or response type, function id, parameters and an XOR checksum, and
+each frame is acknowledged with a single ACK byte. The host here speaks that
+API:
+
+- ZW_ADD_NODE_TO_NETWORK includes a node. Timing starts when the controller
+  reports the node found, so the wait for a button press is not counted. S2
+  bootstrapping is then driven by the host: the KEX exchange, Curve25519
+  public keys, nonces, the network key and the transfer end, all carried by
+  ZW_SEND_DATA and answered through ApplicationCommandHandler.
+- Basic Set is timed until the transmit callback, and Basic Get until the
+  node's Basic Report arrives. Both are sent plain and S2-encapsulated.
+
+The backend is a USB controller given by its serial port, or a simulated
+controller on an in-process pipe by default. The simulated controller
+answers the same frames after modeled delays: Serial API bytes at 115200
+baud, and RF airtime at the configured data rate with G.9959 preamble, MAC
+header, checksum or CRC, per-hop acks, MAC retries and routed acks. The
+simulated node computes a real X25519 agreement, but its delay is a modeled
+end-device figure.
+
+S2 frames are sized rather than encrypted. The inner command stands in
+place of its AES-CCM ciphertext, followed by an 8-byte MAC field. A real S2
+node would reject them, so on a serial controller only unsecured inclusion
+and commands run. Frame overhead compares Basic Set on air with a Matter
+InvokeRequest for OnOff On in 802.15.4 frames on a Thread mesh.
+*/
+
+use anyhow::{anyhow, Context, Result};
+use ring::{agreement, rand};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::process::Command;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, DuplexStream};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout_at, Instant};
+
+use crate::bdx::matter_message;
+use crate::builder::analyzer_builder;
+use crate::firmware_update::{tlv, IM_INVOKE_REQUEST, PROTOCOL_IM};
+use crate::lowpan::{lowpan_frames, LowpanPath, LowpanTransport};
+use crate::mdns::MATTER_PORT;
+use crate::start_modes::invoke_request;
+use crate::workload::XorShift;
+
+// Serial API framing
+const SOF: u8 = 0x01;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const REQUEST: u8 = 0x00;
+const RESPONSE: u8 = 0x01;
+const SERIAL_BAUD: f64 = 115_200.0;
+const SERIAL_ATTEMPTS: usize = 3;
+const ACK_TIMEOUT: Duration = Duration::from_millis(1600);
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(65);
+const REPORT_TIMEOUT: Duration = Duration::from_secs(2);
+/// S2's own timeout for each bootstrapping step.
+const BOOTSTRAP_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a serial controller waits for the device's inclusion button.
+const BUTTON_TIMEOUT: Duration = Duration::from_secs(60);
+
+const FUNC_APPLICATION_COMMAND_HANDLER: u8 = 0x04;
+const FUNC_SEND_DATA: u8 = 0x13;
+const FUNC_ADD_NODE_TO_NETWORK: u8 = 0x4A;
+
+const ADD_NODE_ANY: u8 = 0x01;
+const ADD_NODE_STOP: u8 = 0x05;
+const ADD_NODE_OPTION_NETWORK_WIDE: u8 = 0x40;
+const ADD_NODE_OPTION_HIGH_POWER: u8 = 0x80;
+const ADD_NODE_STATUS_LEARN_READY: u8 = 0x01;
+const ADD_NODE_STATUS_NODE_FOUND: u8 = 0x02;
+const ADD_NODE_STATUS_ADDING_SLAVE: u8 = 0x03;
+const ADD_NODE_STATUS_PROTOCOL_DONE: u8 = 0x05;
+const ADD_NODE_STATUS_DONE: u8 = 0x06;
+const ADD_NODE_STATUS_FAILED: u8 = 0x07;
+
+/// ACK, AUTO_ROUTE and EXPLORE.
+const TRANSMIT_OPTIONS: u8 = 0x25;
+const TRANSMIT_COMPLETE_OK: u8 = 0x00;
+const TRANSMIT_COMPLETE_NO_ACK: u8 = 0x01;
+
+// Command classes
+const CC_BASIC: u8 = 0x20;
+const BASIC_SET: u8 = 0x01;
+const BASIC_GET: u8 = 0x02;
+const BASIC_REPORT: u8 = 0x03;
+const CC_SECURITY_2: u8 = 0x9F;
+const S2_NONCE_GET: u8 = 0x01;
+const S2_NONCE_REPORT: u8 = 0x02;
+const S2_MESSAGE_ENCAPSULATION: u8 = 0x03;
+const S2_KEX_GET: u8 = 0x04;
+const S2_KEX_REPORT: u8 = 0x05;
+const S2_KEX_SET: u8 = 0x06;
+const S2_PUBLIC_KEY_REPORT: u8 = 0x08;
+const S2_NETWORK_KEY_GET: u8 = 0x09;
+const S2_NETWORK_KEY_REPORT: u8 = 0x0A;
+const S2_NETWORK_KEY_VERIFY: u8 = 0x0B;
+const S2_TRANSFER_END: u8 = 0x0C;
+const S2_KEX_ECHO: u8 = 0x01;
+const S2_NONCE_SOS: u8 = 0x01;
+const S2_SCHEME_1: u8 = 0x02;
+const S2_CURVE_25519: u8 = 0x01;
+/// S2 Authenticated.
+const S2_KEY_AUTHENTICATED: u8 = 0x02;
+const S2_NONCE_ENTROPY_BYTES: usize = 16;
+const S2_NETWORK_KEY_BYTES: usize = 16;
+/// Command class, command, sequence number and extension flags.
+const S2_HEADER_BYTES: usize = 4;
+const S2_MAC_BYTES: usize = 8;
+
+/// Basic, generic and specific device class, then the supported command
+/// classes of a binary switch.
+const NODE_INFORMATION: [u8; 10] = [0x04, 0x10, 0x01, 0x5E, 0x9F, 0x6C, 0x55, 0x86, 0x25, 0x20];
+/// Assign IDs, Find Nodes In Range, Command Complete, Get Nodes In Range and
+/// Range Info, after the node's NIF.
+const INCLUSION_FRAME_BYTES: [usize; 5] = [7, 36, 3, 3, 36];
+
+// G.9959 framing
+const SOF_BYTES: usize = 1;
+/// Home ID, source, frame control, length and destination.
+const MAC_HEADER_BYTES: usize = 4 + 1 + 2 + 1 + 1;
+/// Route status and hop count, then one byte per repeater.
+const ROUTING_HEADER_BYTES: usize = 2;
+const MAC_TRANSMISSIONS: usize = 3;
+const CHANNEL_ACCESS_MS: f64 = 1.0;
+const TURNAROUND_MS: f64 = 1.0;
+const ACK_WAIT_MS: f64 = 15.0;
+/// Time for the node to act on a command before answering.
+const NODE_PROCESSING_MS: f64 = 5.0;
+/// X25519 on a Cortex-M class end device.
+const DEVICE_ECDH_MS: f64 = 250.0;
+
+/// 802.15.4 PHY header, immediate-ack MAC header and FCS.
+const IEEE802154_ACK_BYTES: usize = 6 + 3 + 2;
+const IEEE802154_RATE_KBIT: f64 = 250.0;
+
+const CONTROLLER_NODE: u8 = 1;
+const SERIAL_BUFFER_BYTES: usize = 4096;
+const RF_SEED: u64 = 0x2A7E_5E71_A100_0200;
+
+#[derive(Debug, Clone)]
+pub struct ZwaveConfig {
+    /// Serial port of a USB controller, such as /dev/ttyACM0. `None` runs the simulated controller.
+    pub serial_port: Option<String>,
+    /// Node to command when inclusion is skipped.
+    pub node: Option<u8>,
+    /// Include a node first. A serial controller waits for the device's inclusion button.
+    pub include: bool,
+    /// Simulated data rate: 9.6, 40 or 100 kbit/s.
+    pub data_rate_kbit: f64,
+    /// Simulated frame loss per transmission on each hop.
+    pub frame_loss: f64,
+    /// Simulated repeaters between the controller and the node.
+    pub repeaters: usize,
+    /// Commands timed per command and security.
+    pub iterations: usize,
+}
+
+impl Default for ZwaveConfig {
+    fn default() -> Self {
+        Self {
+            serial_port: None,
+            node: None,
+            include: true,
+            data_rate_kbit: 100.0,
+            frame_loss: 0.02,
+            repeaters: 0,
+            iterations: 30,
+        }
+    }
+}
+
+analyzer_builder!(ZwaveAnalyzerBuilder => ZwaveAnalyzer(ZwaveConfig) {
+    serial_port: Option<String>,
+    node: Option<u8>,
+    include: bool,
+    data_rate_kbit: f64,
+    frame_loss: f64,
+    repeaters: usize,
+    iterations: usize,
+});
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ZwaveMetrics {
+    pub backend: String,
+    pub node_id: u8,
+    pub inclusions: Vec<InclusionRun>,
+    pub commands: Vec<CommandRun>,
+    pub frame_overhead: Vec<FrameOverhead>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InclusionRun {
+    pub security: String,
+    pub node_id: u8,
+    /// From the node found to the protocol part done.
+    pub protocol_ms: f64,
+    pub bootstrapping_ms: f64,
+    pub serial_frames: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommandRun {
+    pub command: String,
+    pub security: String,
+    pub iterations: usize,
+    pub failed: usize,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    /// Mean transmit time the controller reported in its callbacks.
+    pub tx_report_ms: f64,
+    /// Serial API bytes both ways per command, ACK bytes included.
+    pub serial_bytes: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FrameOverhead {
+    pub protocol: String,
+    pub message: String,
+    pub application_bytes: usize,
+    /// Frames and their MAC acks.
+    pub on_air_bytes: usize,
+    pub overhead_bytes: usize,
+    pub airtime_ms: f64,
+}
+
+pub struct ZwaveAnalyzer {
+    config: ZwaveConfig,
+}
+
+impl ZwaveAnalyzer {
+    pub fn new(config: ZwaveConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn analyze_zwave(&mut self) -> Result<ZwaveMetrics> {
+        let backend = match &self.config.serial_port {
+            Some(port) => format!("serial controller on {}", port),
+            None => format!("simulated controller, {} kbit/s, {} repeaters", self.config.data_rate_kbit, self.config.repeaters),
+        };
+        println!("\n📟 Analyzing Z-Wave ({})", backend);
+        println!("-------------------------------------------------------------------");
+        if self.config.data_rate_kbit <= 0.0 || !(0.0..1.0).contains(&self.config.frame_loss) {
+            return Err(anyhow!("the data rate must be positive and the frame loss below 1"));
+        }
+
+        let frame_overhead = self.frame_overhead()?;
+        for frame in &frame_overhead {
+            println!("✅ {} {}: {}B payload → {}B on air ({}B overhead), {:.2}ms airtime",
+                     frame.protocol, frame.message, frame.application_bytes, frame.on_air_bytes,
+                     frame.overhead_bytes, frame.airtime_ms);
+        }
+
+        let (node_id, inclusions, commands) = match &self.config.serial_port {
+            Some(port) => self.drive(SerialApi::new(open_serial(port).await?), false).await?,
+            None => {
+                let (host, controller) = tokio::io::duplex(SERIAL_BUFFER_BYTES);
+                let controller = SimulatedController::new(&self.config, controller);
+                let task: JoinHandle<Result<()>> = tokio::spawn(controller.run());
+                let driven = self.drive(SerialApi::new(host), true).await;
+                task.abort();
+                driven?
+            }
+        };
+
+        Ok(ZwaveMetrics { backend, node_id, inclusions, commands, frame_overhead })
+    }
+
+    /// Includes the node when asked, then times the commands.
+    async fn drive<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        mut api: SerialApi<S>,
+        secure: bool,
+    ) -> Result<(u8, Vec<InclusionRun>, Vec<CommandRun>)> {
+        let mut inclusions = Vec::new();
+        if self.config.include {
+            for s2 in [false, true].into_iter().filter(|&s2| secure || !s2) {
+                let inclusion = include(&mut api, s2).await?;
+                println!("✅ Inclusion ({}): node {}, protocol {:.0}ms + bootstrapping {:.0}ms, {} serial frames",
+                         inclusion.security, inclusion.node_id, inclusion.protocol_ms, inclusion.bootstrapping_ms,
+                         inclusion.serial_frames);
+                inclusions.push(inclusion);
+            }
+        }
+        let node_id = match (inclusions.last(), self.config.node) {
+            (Some(inclusion), _) => inclusion.node_id,
+            (None, Some(node)) => node,
+            (None, None) => return Err(anyhow!("a node to command is needed when inclusion is skipped")),
+        };
+
+        let mut commands = Vec::new();
+        for s2 in [false, true].into_iter().filter(|&s2| secure || !s2) {
+            for get in [false, true] {
+                let run = command_latency(&mut api, node_id, get, s2, self.config.iterations.max(1)).await?;
+                println!("✅ {} ({}): p50 {:.1}ms, p95 {:.1}ms, tx report {:.1}ms, {:.0} serial B/command, {} failed",
+                         run.command, run.security, run.p50_ms, run.p95_ms, run.tx_report_ms, run.serial_bytes, run.failed);
+                commands.push(run);
+            }
+        }
+        Ok((node_id, inclusions, commands))
+    }
+
+    fn frame_overhead(&self) -> Result<Vec<FrameOverhead>> {
+        let rate = self.config.data_rate_kbit;
+        let zwave = |message: &str, payload: usize, application_bytes: usize| {
+            let on_air_bytes = zwave_frame_bytes(rate, self.config.repeaters, payload) + zwave_frame_bytes(rate, self.config.repeaters, 0);
+            FrameOverhead {
+                protocol: "Z-Wave".to_string(),
+                message: message.to_string(),
+                application_bytes,
+                on_air_bytes,
+                overhead_bytes: on_air_bytes - application_bytes,
+                airtime_ms: on_air_bytes as f64 * 8.0 / rate,
+            }
+        };
+        let basic_set = [CC_BASIC, BASIC_SET, 0xFF].len();
+
+        let request = tlv(invoke_request)?;
+        let message = matter_message(PROTOCOL_IM, IM_INVOKE_REQUEST, &request);
+        let thread = lowpan_frames(LowpanPath::MeshLocal, LowpanTransport::udp(MATTER_PORT), message.len());
+        let on_air_bytes = thread.on_air_bytes + thread.frames * IEEE802154_ACK_BYTES;
+        Ok(vec![
+            zwave("Basic Set", basic_set, basic_set),
+            zwave("Basic Set (S2)", S2_HEADER_BYTES + basic_set + S2_MAC_BYTES, basic_set),
+            FrameOverhead {
+                protocol: "Matter over Thread".to_string(),
+                message: "OnOff On".to_string(),
+                application_bytes: request.len(),
+                on_air_bytes,
+                overhead_bytes: on_air_bytes - request.len(),
+                airtime_ms: on_air_bytes as f64 * 8.0 / IEEE802154_RATE_KBIT,
+            },
+        ])
+    }
+}
+
+/// Puts the port in raw mode at the Serial API's 115200 baud and opens it.
+async fn open_serial(port: &str) -> Result<BufReader<tokio::fs::File>> {
+    let status = Command::new("stty")
+        .args(["-F", port, "115200", "raw", "-echo"])
+        .status()
+        .context("failed to run stty")?;
+    if !status.success() {
+        return Err(anyhow!("stty could not configure {}", port));
+    }
+    let file = tokio::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(port)
+        .await
+        .with_context(|| format!("failed to open {}", port))?;
+    Ok(BufReader::new(file))
+}
+
+/// Times ZW_ADD_NODE_TO_NETWORK from the node found, then S2 bootstrapping when `s2`.
+async fn include<S: AsyncRead + AsyncWrite + Unpin>(api: &mut SerialApi<S>, s2: bool) -> Result<InclusionRun> {
+    let frames = api.frames;
+    let id = api.next_callback_id();
+    api.send(FUNC_ADD_NODE_TO_NETWORK, &[ADD_NODE_ANY | ADD_NODE_OPTION_HIGH_POWER | ADD_NODE_OPTION_NETWORK_WIDE, id])
+        .await?;
+
+    let mut found = Instant::now();
+    let mut node_id = None;
+    loop {
+        let wait = if node_id.is_none() { BUTTON_TIMEOUT } else { RESPONSE_TIMEOUT };
+        let callback = api.receive(REQUEST, FUNC_ADD_NODE_TO_NETWORK, wait, |p| p.first() == Some(&id)).await?;
+        match callback.get(1).copied() {
+            Some(ADD_NODE_STATUS_LEARN_READY) => {}
+            Some(ADD_NODE_STATUS_NODE_FOUND) => found = Instant::now(),
+            Some(ADD_NODE_STATUS_ADDING_SLAVE) => node_id = callback.get(2).copied(),
+            Some(ADD_NODE_STATUS_PROTOCOL_DONE) => api.send(FUNC_ADD_NODE_TO_NETWORK, &[ADD_NODE_STOP, id]).await?,
+            Some(ADD_NODE_STATUS_DONE) => break,
+            status => {
+                api.send(FUNC_ADD_NODE_TO_NETWORK, &[ADD_NODE_STOP, id]).await?;
+                return Err(anyhow!("inclusion failed with status {:?}", status));
+            }
+        }
+    }
+    let protocol_ms = found.elapsed().as_secs_f64() * 1000.0;
+    let node_id = node_id.ok_or_else(|| anyhow!("controller finished inclusion without a node id"))?;
+
+    let bootstrapping = Instant::now();
+    if s2 {
+        bootstrap_s2(api, node_id).await?;
+    }
+    Ok(InclusionRun {
+        security: if s2 { "S2" } else { "none" }.to_string(),
+        node_id,
+        protocol_ms,
+        bootstrapping_ms: if s2 { bootstrapping.elapsed().as_secs_f64() * 1000.0 } else { 0.0 },
+        serial_frames: api.frames - frames,
+    })
+}
+
+/// The including side of S2 bootstrapping, granting S2 Authenticated.
+async fn bootstrap_s2<S: AsyncRead + AsyncWrite + Unpin>(api: &mut SerialApi<S>, node: u8) -> Result<()> {
+    let rng = rand::SystemRandom::new();
+    let key = agreement::EphemeralPrivateKey::generate(&agreement::X25519, &rng)
+        .map_err(|_| anyhow!("ephemeral key generation failed"))?;
+    let public = key.compute_public_key().map_err(|_| anyhow!("public key derivation failed"))?;
+    let kex = |echo: u8| vec![CC_SECURITY_2, echo, S2_SCHEME_1, S2_CURVE_25519, S2_KEY_AUTHENTICATED];
+    let nonce_report = |sequence: u8| {
+        let mut report = vec![CC_SECURITY_2, S2_NONCE_REPORT, sequence, S2_NONCE_SOS];
+        report.extend_from_slice(&[0x5A; S2_NONCE_ENTROPY_BYTES]);
+        report
+    };
+
+    api.deliver(node, &[CC_SECURITY_2, S2_KEX_GET]).await?;
+    api.command_from(node, false, [CC_SECURITY_2, S2_KEX_REPORT], BOOTSTRAP_TIMEOUT).await?;
+    let mut kex_set = kex(0);
+    kex_set.insert(1, S2_KEX_SET);
+    api.deliver(node, &kex_set).await?;
+    let report = api.command_from(node, false, [CC_SECURITY_2, S2_PUBLIC_KEY_REPORT], BOOTSTRAP_TIMEOUT).await?;
+    let mut public_key_report = vec![CC_SECURITY_2, S2_PUBLIC_KEY_REPORT, 0x01];
+    public_key_report.extend_from_slice(public.as_ref());
+    api.deliver(node, &public_key_report).await?;
+    agreement::agree_ephemeral(key, &agreement::UnparsedPublicKey::new(&agreement::X25519, report.get(3..).unwrap_or_default()), |_| ())
+        .map_err(|_| anyhow!("key agreement with node {} failed", node))?;
+
+    // The node syncs nonces before its first frame under the temporary key
+    let mut sequence = 0u8;
+    let nonce_get = api.command_from(node, false, [CC_SECURITY_2, S2_NONCE_GET], BOOTSTRAP_TIMEOUT).await?;
+    api.deliver(node, &nonce_report(nonce_get.get(2).copied().unwrap_or_default())).await?;
+    api.command_from(node, true, [CC_SECURITY_2, S2_KEX_SET], BOOTSTRAP_TIMEOUT).await?;
+    let mut echo = kex(S2_KEX_ECHO);
+    echo.insert(1, S2_KEX_REPORT);
+    sequence += 1;
+    api.deliver(node, &encapsulate(sequence, &echo)).await?;
+    api.command_from(node, true, [CC_SECURITY_2, S2_NETWORK_KEY_GET], BOOTSTRAP_TIMEOUT).await?;
+    let mut key_report = vec![CC_SECURITY_2, S2_NETWORK_KEY_REPORT, S2_KEY_AUTHENTICATED];
+    key_report.extend_from_slice(&[0xA5; S2_NETWORK_KEY_BYTES]);
+    sequence += 1;
+    api.deliver(node, &encapsulate(sequence, &key_report)).await?;
+
+    // And again before its first frame under the network key
+    let nonce_get = api.command_from(node, false, [CC_SECURITY_2, S2_NONCE_GET], BOOTSTRAP_TIMEOUT).await?;
+    api.deliver(node, &nonce_report(nonce_get.get(2).copied().unwrap_or_default())).await?;
+    api.command_from(node, true, [CC_SECURITY_2, S2_NETWORK_KEY_VERIFY], BOOTSTRAP_TIMEOUT).await?;
+    sequence += 1;
+    api.deliver(node, &encapsulate(sequence, &[CC_SECURITY_2, S2_TRANSFER_END, 0x02])).await?;
+    api.command_from(node, true, [CC_SECURITY_2, S2_TRANSFER_END], BOOTSTRAP_TIMEOUT).await?;
+    Ok(())
+}
+
+/// Basic Set until the transmit callback, or Basic Get until the node's report.
+async fn command_latency<S: AsyncRead + AsyncWrite + Unpin>(
+    api: &mut SerialApi<S>,
+    node: u8,
+    get: bool,
+    s2: bool,
+    iterations: usize,
+) -> Result<CommandRun> {
+    let mut latencies = Vec::with_capacity(iterations);
+    let (mut failed, mut tx_ms, mut serial_bytes) = (0, 0.0, 0);
+    for i in 0..iterations {
+        let command = if get { vec![CC_BASIC, BASIC_GET] } else { vec![CC_BASIC, BASIC_SET, if i % 2 == 0 { 0xFF } else { 0x00 }] };
+        let data = if s2 { encapsulate(i as u8, &command) } else { command };
+        let bytes = api.bytes;
+        let start = Instant::now();
+        let Some(tx) = api.send_data(node, &data).await? else {
+            failed += 1;
+            continue;
+        };
+        if get && api.command_from(node, s2, [CC_BASIC, BASIC_REPORT], REPORT_TIMEOUT).await.is_err() {
+            failed += 1;
+            continue;
+        }
+        latencies.push(start.elapsed().as_secs_f64() * 1000.0);
+        tx_ms += tx.as_secs_f64() * 1000.0;
+        serial_bytes += api.bytes - bytes;
+    }
+
+    latencies.sort_by(f64::total_cmp);
+    let per = |total: f64| if latencies.is_empty() { 0.0 } else { total / latencies.len() as f64 };
+    Ok(CommandRun {
+        command: if get { "Basic Get" } else { "Basic Set" }.to_string(),
+        security: if s2 { "S2" } else { "none" }.to_string(),
+        iterations,
+        failed,
+        p50_ms: percentile(&latencies, 50.0),
+        p95_ms: percentile(&latencies, 95.0),
+        tx_report_ms: per(tx_ms),
+        serial_bytes: per(serial_bytes as f64),
+    })
+}
+
+enum Incoming {
+    Ack,
+    Nak,
+    Cancel,
+    Frame { kind: u8, function: u8, payload: Vec<u8> },
+}
+
+fn checksum(len: u8, body: &[u8]) -> u8 {
+    body.iter().fold(0xFF ^ len, |sum, byte| sum ^ byte)
+}
+
+fn serial_frame(kind: u8, function: u8, payload: &[u8]) -> Vec<u8> {
+    let len = (payload.len() + 3) as u8;
+    let mut frame = vec![SOF, len, kind, function];
+    frame.extend_from_slice(payload);
+    frame.push(checksum(len, &frame[2..]));
+    frame
+}
+
+async fn read_incoming<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Incoming> {
+    loop {
+        match reader.read_u8().await? {
+            ACK => return Ok(Incoming::Ack),
+            NAK => return Ok(Incoming::Nak),
+            CAN => return Ok(Incoming::Cancel),
+            SOF => {
+                let len = reader.read_u8().await?;
+                let mut body = vec![0u8; len as usize];
+                reader.read_exact(&mut body).await?;
+                let sum = body.pop().unwrap_or_default();
+                if body.len() < 2 || checksum(len, &body) != sum {
+                    return Err(anyhow!("corrupt Serial API frame"));
+                }
+                let payload = body.split_off(2);
+                return Ok(Incoming::Frame { kind: body[0], function: body[1], payload });
+            }
+            // Line noise between frames
+            _ => {}
+        }
+    }
+}
+
+fn encapsulate(sequence: u8, command: &[u8]) -> Vec<u8> {
+    let mut frame = vec![CC_SECURITY_2, S2_MESSAGE_ENCAPSULATION, sequence, 0x00];
+    frame.extend_from_slice(command);
+    frame.extend_from_slice(&[0u8; S2_MAC_BYTES]);
+    frame
+}
+
+fn decapsulate(frame: &[u8]) -> Option<&[u8]> {
+    if frame.len() < S2_HEADER_BYTES + S2_MAC_BYTES || frame[..2] != [CC_SECURITY_2, S2_MESSAGE_ENCAPSULATION] {
+        return None;
+    }
+    Some(&frame[S2_HEADER_BYTES..frame.len() - S2_MAC_BYTES])
+}
+
+/// Command carried by an ApplicationCommandHandler request: receive status, source node, length, command.
+fn handler_command(payload: &[u8], s2: bool) -> &[u8] {
+    let command = payload.get(3..3 + payload.get(2).copied().unwrap_or_default() as usize).unwrap_or_default();
+    if s2 {
+        decapsulate(command).unwrap_or_default()
+    } else {
+        command
+    }
+}
+
+/// On-air bytes of a singlecast frame at `rate_kbit`: G.9959 preamble, SOF,
+/// MAC and routing headers, payload, and the R1/R2 checksum or R3 CRC-16.
+fn zwave_frame_bytes(rate_kbit: f64, repeaters: usize, payload: usize) -> usize {
+    let (preamble, checksum) = if rate_kbit > 40.0 {
+        (24, 2)
+    } else if rate_kbit > 9.6 {
+        (20, 1)
+    } else {
+        (10, 1)
+    };
+    let routing = if repeaters > 0 { ROUTING_HEADER_BYTES + repeaters } else { 0 };
+    preamble + SOF_BYTES + MAC_HEADER_BYTES + routing + payload + checksum
+}
+
+/// Host side of the Serial API, as a Z-Wave JS-style driver uses it.
+struct SerialApi<S> {
+    stream: S,
+    callback_id: u8,
+    /// Requests read while waiting for something else.
+    pending: VecDeque<(u8, Vec<u8>)>,
+    frames: usize,
+    bytes: usize,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> SerialApi<S> {
+    fn new(stream: S) -> Self {
+        Self { stream, callback_id: 0, pending: VecDeque::new(), frames: 0, bytes: 0 }
+    }
+
+    /// Callback ids run 1 to 255; 0 asks for no callback.
+    fn next_callback_id(&mut self) -> u8 {
+        self.callback_id = self.callback_id % 0xFF + 1;
+        self.callback_id
+    }
+
+    async fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.stream.write_all(bytes).await?;
+        self.stream.flush().await?;
+        self.bytes += bytes.len();
+        Ok(())
+    }
+
+    /// Writes a request and waits for its ACK, retransmitting after a NAK, CAN or timeout.
+    async fn send(&mut self, function: u8, payload: &[u8]) -> Result<()> {
+        let frame = serial_frame(REQUEST, function, payload);
+        for _ in 0..SERIAL_ATTEMPTS {
+            self.write(&frame).await?;
+            self.frames += 1;
+            let deadline = Instant::now() + ACK_TIMEOUT;
+            while let Ok(incoming) = timeout_at(deadline, read_incoming(&mut self.stream)).await {
+                match incoming? {
+                    Incoming::Ack => {
+                        self.bytes += 1;
+                        return Ok(());
+                    }
+                    Incoming::Nak | Incoming::Cancel => break,
+                    Incoming::Frame { kind, function, payload } => self.accept(kind, function, payload).await?,
+                }
+            }
+        }
+        Err(anyhow!("controller did not acknowledge function 0x{:02X}", function))
+    }
+
+    /// Acknowledges a frame read while waiting for an ACK and keeps it if it is a request.
+    async fn accept(&mut self, kind: u8, function: u8, payload: Vec<u8>) -> Result<()> {
+        self.frames += 1;
+        self.bytes += payload.len() + 5;
+        self.write(&[ACK]).await?;
+        if kind == REQUEST {
+            self.pending.push_back((function, payload));
+        }
+        Ok(())
+    }
+
+    /// Next frame of `kind` and `function` whose payload passes `matches`.
+    async fn receive(&mut self, kind: u8, function: u8, wait: Duration, matches: impl Fn(&[u8]) -> bool) -> Result<Vec<u8>> {
+        if kind == REQUEST {
+            if let Some(i) = self.pending.iter().position(|(f, p)| *f == function && matches(p)) {
+                return Ok(self.pending.remove(i).map(|(_, p)| p).unwrap_or_default());
+            }
+        }
+        let deadline = Instant::now() + wait;
+        loop {
+            let incoming = timeout_at(deadline, read_incoming(&mut self.stream))
+                .await
+                .map_err(|_| anyhow!("no frame for function 0x{:02X} within {:?}", function, wait))??;
+            if let Incoming::Frame { kind: k, function: f, payload } = incoming {
+                if k == kind && f == function && matches(&payload) {
+                    self.frames += 1;
+                    self.bytes += payload.len() + 5;
+                    self.write(&[ACK]).await?;
+                    return Ok(payload);
+                }
+                self.accept(k, f, payload).await?;
+            }
+        }
+    }
+
+    /// ZW_SEND_DATA to `node`, returning the transmit time from the callback,
+    /// or `None` when the node did not acknowledge.
+    async fn send_data(&mut self, node: u8, data: &[u8]) -> Result<Option<Duration>> {
+        let id = self.next_callback_id();
+        let mut payload = vec![node, data.len() as u8];
+        payload.extend_from_slice(data);
+        payload.extend_from_slice(&[TRANSMIT_OPTIONS, id]);
+        self.send(FUNC_SEND_DATA, &payload).await?;
+        if self.receive(RESPONSE, FUNC_SEND_DATA, RESPONSE_TIMEOUT, |_| true).await?.first() != Some(&1) {
+            return Err(anyhow!("controller refused a frame for node {}", node));
+        }
+        let callback = self.receive(REQUEST, FUNC_SEND_DATA, CALLBACK_TIMEOUT, |p| p.first() == Some(&id)).await?;
+        match callback.get(1).copied() {
+            Some(TRANSMIT_COMPLETE_OK) => {
+                let ticks = callback.get(2..4).map_or(0, |t| u16::from_be_bytes([t[0], t[1]]));
+                Ok(Some(Duration::from_millis(ticks as u64 * 10)))
+            }
+            Some(TRANSMIT_COMPLETE_NO_ACK) => Ok(None),
+            status => Err(anyhow!("transmission to node {} failed with status {:?}", node, status)),
+        }
+    }
+
+    async fn deliver(&mut self, node: u8, data: &[u8]) -> Result<()> {
+        self.send_data(node, data)
+            .await?
+            .map(|_| ())
+            .ok_or_else(|| anyhow!("node {} did not acknowledge 0x{:02X} 0x{:02X}", node, data[0], data[1]))
+    }
+
+    /// Next command from `node` starting with `expected`, S2-decapsulated when `s2`.
+    async fn command_from(&mut self, node: u8, s2: bool, expected: [u8; 2], wait: Duration) -> Result<Vec<u8>> {
+        let payload = self
+            .receive(REQUEST, FUNC_APPLICATION_COMMAND_HANDLER, wait, |p| {
+                p.get(1) == Some(&node) && handler_command(p, s2).starts_with(&expected)
+            })
+            .await?;
+        Ok(handler_command(&payload, s2).to_vec())
+    }
+}
+
+/// A controller and one joining node behind it, answering the Serial API after modeled delays.
+struct SimulatedController {
+    stream: DuplexStream,
+    rate_kbit: f64,
+    frame_loss: f64,
+    repeaters: usize,
+    rng: XorShift,
+    random: rand::SystemRandom,
+    next_node: u8,
+    node: u8,
+    value: u8,
+    sequence: u8,
+    nonce_reports: usize,
+    key: Option<agreement::EphemeralPrivateKey>,
+}
+
+impl SimulatedController {
+    fn new(config: &ZwaveConfig, stream: DuplexStream) -> Self {
+        Self {
+            stream,
+            rate_kbit: config.data_rate_kbit,
+            frame_loss: config.frame_loss,
+            repeaters: config.repeaters,
+            rng: XorShift(RF_SEED),
+            random: rand::SystemRandom::new(),
+            next_node: CONTROLLER_NODE + 1,
+            node: 0,
+            value: 0,
+            sequence: 0,
+            nonce_reports: 0,
+            key: None,
+        }
+    }
+
+    async fn run(mut self) -> Result<()> {
+        loop {
+            let Incoming::Frame { kind: REQUEST, function, payload } = read_incoming(&mut self.stream).await? else {
+                continue;
+            };
+            self.stream.write_all(&[ACK]).await?;
+            match function {
+                FUNC_ADD_NODE_TO_NETWORK => self.add_node(&payload).await?,
+                FUNC_SEND_DATA => self.send_data(&payload).await?,
+                _ => {}
+            }
+        }
+    }
+
+    /// Writes a frame after its time on the serial line.
+    async fn frame(&mut self, kind: u8, function: u8, payload: &[u8]) -> Result<()> {
+        let frame = serial_frame(kind, function, payload);
+        sleep(Duration::from_secs_f64(frame.len() as f64 * 10.0 / SERIAL_BAUD)).await;
+        self.stream.write_all(&frame).await?;
+        Ok(())
+    }
+
+    async fn add_node(&mut self, payload: &[u8]) -> Result<()> {
+        let (&mode, &id) = (payload.first().unwrap_or(&0), payload.get(1).unwrap_or(&0));
+        if mode & 0x0F == ADD_NODE_STOP {
+            return self.frame(REQUEST, FUNC_ADD_NODE_TO_NETWORK, &[id, ADD_NODE_STATUS_DONE, self.node, 0]).await;
+        }
+        self.frame(REQUEST, FUNC_ADD_NODE_TO_NETWORK, &[id, ADD_NODE_STATUS_LEARN_READY, 0, 0]).await?;
+
+        // The node's NIF, then Assign IDs, then neighbour discovery
+        let (nif_ms, delivered) = self.transmit(NODE_INFORMATION.len() + 2);
+        sleep(Duration::from_secs_f64(nif_ms / 1000.0)).await;
+        if !delivered {
+            return self.frame(REQUEST, FUNC_ADD_NODE_TO_NETWORK, &[id, ADD_NODE_STATUS_FAILED, 0, 0]).await;
+        }
+        self.frame(REQUEST, FUNC_ADD_NODE_TO_NETWORK, &[id, ADD_NODE_STATUS_NODE_FOUND, 0, 0]).await?;
+        for (i, bytes) in INCLUSION_FRAME_BYTES.into_iter().enumerate() {
+            let (ms, delivered) = self.transmit(bytes);
+            sleep(Duration::from_secs_f64(ms / 1000.0)).await;
+            if !delivered {
+                return self.frame(REQUEST, FUNC_ADD_NODE_TO_NETWORK, &[id, ADD_NODE_STATUS_FAILED, 0, 0]).await;
+            }
+            if i == 0 {
+                self.node = self.next_node;
+                self.next_node += 1;
+                let mut adding = vec![id, ADD_NODE_STATUS_ADDING_SLAVE, self.node, NODE_INFORMATION.len() as u8];
+                adding.extend_from_slice(&NODE_INFORMATION);
+                self.frame(REQUEST, FUNC_ADD_NODE_TO_NETWORK, &adding).await?;
+            }
+        }
+        self.frame(REQUEST, FUNC_ADD_NODE_TO_NETWORK, &[id, ADD_NODE_STATUS_PROTOCOL_DONE, self.node, 0]).await
+    }
+
+    async fn send_data(&mut self, payload: &[u8]) -> Result<()> {
+        let len = payload.get(1).copied().unwrap_or_default() as usize;
+        let (Some(data), Some(&id)) = (payload.get(2..2 + len), payload.get(3 + len)) else {
+            return self.frame(RESPONSE, FUNC_SEND_DATA, &[0]).await;
+        };
+        let data = data.to_vec();
+        self.frame(RESPONSE, FUNC_SEND_DATA, &[1]).await?;
+
+        let (ms, delivered) = self.transmit(data.len());
+        sleep(Duration::from_secs_f64(ms / 1000.0)).await;
+        let status = if delivered { TRANSMIT_COMPLETE_OK } else { TRANSMIT_COMPLETE_NO_ACK };
+        let ticks = ((ms / 10.0).round() as u16).to_be_bytes();
+        self.frame(REQUEST, FUNC_SEND_DATA, &[id, status, ticks[0], ticks[1]]).await?;
+        if !delivered {
+            return Ok(());
+        }
+
+        for (delay_ms, reply) in self.react(&data)? {
+            let (ms, delivered) = self.transmit(reply.len());
+            sleep(Duration::from_secs_f64((delay_ms + ms) / 1000.0)).await;
+            if delivered {
+                let mut handler = vec![0x00, self.node, reply.len() as u8];
+                handler.extend_from_slice(&reply);
+                self.frame(REQUEST, FUNC_APPLICATION_COMMAND_HANDLER, &handler).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The node's answers to a command, each after its processing delay.
+    fn react(&mut self, data: &[u8]) -> Result<Vec<(f64, Vec<u8>)>> {
+        let (s2, command) = match decapsulate(data) {
+            Some(inner) => (true, inner.to_vec()),
+            None => (false, data.to_vec()),
+        };
+        let reply = match command.as_slice() {
+            [CC_BASIC, BASIC_SET, value, ..] => {
+                self.value = *value;
+                return Ok(Vec::new());
+            }
+            [CC_BASIC, BASIC_GET, ..] => {
+                let report = vec![CC_BASIC, BASIC_REPORT, self.value];
+                if s2 {
+                    self.secure(&report)
+                } else {
+                    report
+                }
+            }
+            [CC_SECURITY_2, S2_KEX_GET, ..] => {
+                self.nonce_reports = 0;
+                vec![CC_SECURITY_2, S2_KEX_REPORT, 0x00, S2_SCHEME_1, S2_CURVE_25519, S2_KEY_AUTHENTICATED]
+            }
+            [CC_SECURITY_2, S2_KEX_SET, ..] if !s2 => {
+                let key = agreement::EphemeralPrivateKey::generate(&agreement::X25519, &self.random)
+                    .map_err(|_| anyhow!("ephemeral key generation failed"))?;
+                let mut report = vec![CC_SECURITY_2, S2_PUBLIC_KEY_REPORT, 0x00];
+                report.extend_from_slice(key.compute_public_key().map_err(|_| anyhow!("public key derivation failed"))?.as_ref());
+                self.key = Some(key);
+                report
+            }
+            [CC_SECURITY_2, S2_PUBLIC_KEY_REPORT, _, peer @ ..] => {
+                let key = self.key.take().ok_or_else(|| anyhow!("public key report before KEX Set"))?;
+                agreement::agree_ephemeral(key, &agreement::UnparsedPublicKey::new(&agreement::X25519, peer), |_| ())
+                    .map_err(|_| anyhow!("node key agreement failed"))?;
+                self.sequence = self.sequence.wrapping_add(1);
+                return Ok(vec![(DEVICE_ECDH_MS, vec![CC_SECURITY_2, S2_NONCE_GET, self.sequence])]);
+            }
+            [CC_SECURITY_2, S2_NONCE_REPORT, ..] => {
+                self.nonce_reports += 1;
+                if self.nonce_reports == 1 {
+                    self.secure(&[CC_SECURITY_2, S2_KEX_SET, S2_KEX_ECHO, S2_SCHEME_1, S2_CURVE_25519, S2_KEY_AUTHENTICATED])
+                } else {
+                    self.secure(&[CC_SECURITY_2, S2_NETWORK_KEY_VERIFY])
+                }
+            }
+            [CC_SECURITY_2, S2_KEX_REPORT, ..] if s2 => self.secure(&[CC_SECURITY_2, S2_NETWORK_KEY_GET, S2_KEY_AUTHENTICATED]),
+            [CC_SECURITY_2, S2_NETWORK_KEY_REPORT, ..] => {
+                self.sequence = self.sequence.wrapping_add(1);
+                vec![CC_SECURITY_2, S2_NONCE_GET, self.sequence]
+            }
+            [CC_SECURITY_2, S2_TRANSFER_END, ..] => self.secure(&[CC_SECURITY_2, S2_TRANSFER_END, 0x01]),
+            _ => return Ok(Vec::new()),
+        };
+        Ok(vec![(NODE_PROCESSING_MS, reply)])
+    }
+
+    fn secure(&mut self, command: &[u8]) -> Vec<u8> {
+        self.sequence = self.sequence.wrapping_add(1);
+        encapsulate(self.sequence, command)
+    }
+
+    /// Modeled time to carry a frame across every hop with its acks, and
+    /// whether it arrived within the MAC retries.
+    fn transmit(&mut self, payload: usize) -> (f64, bool) {
+        let airtime = |bytes: usize| bytes as f64 * 8.0 / self.rate_kbit;
+        let frame = zwave_frame_bytes(self.rate_kbit, self.repeaters, payload);
+        let ack = zwave_frame_bytes(self.rate_kbit, self.repeaters, 0);
+        let mut ms = 0.0;
+        for _ in 0..=self.repeaters {
+            let mut delivered = false;
+            for _ in 0..MAC_TRANSMISSIONS {
+                ms += CHANNEL_ACCESS_MS + airtime(frame);
+                if self.rng.unit() >= self.frame_loss {
+                    ms += TURNAROUND_MS + airtime(ack);
+                    delivered = true;
+                    break;
+                }
+                ms += ACK_WAIT_MS;
+            }
+            if !delivered {
+                return (ms, false);
+            }
+        }
+        // The routed ack travels back through every repeater
+        if self.repeaters > 0 {
+            ms += (self.repeaters + 1) as f64 * (CHANNEL_ACCESS_MS + airtime(ack));
+        }
+        (ms, true)
+    }
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((p / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn simulated_controller_includes_with_s2_and_answers_commands() {
+        // SERIAL_API_GET_INIT_DATA, as every controller log shows it
+        assert_eq!(serial_frame(REQUEST, 0x02, &[]), [0x01, 0x03, 0x00, 0x02, 0xFE]);
+
+        let metrics = ZwaveAnalyzer::builder()
+            .iterations(5usize)
+            .frame_loss(0.0)
+            .build()
+            .analyze_zwave()
+            .await
+            .unwrap();
+        assert_eq!(metrics.inclusions.len(), 2);
+        assert_eq!(metrics.node_id, 3);
+        let s2 = &metrics.inclusions[1];
+        assert!(s2.bootstrapping_ms > DEVICE_ECDH_MS, "{}", s2.bootstrapping_ms);
+        assert!(s2.serial_frames > metrics.inclusions[0].serial_frames);
+
+        let run = |command: &str, security: &str| {
+            metrics.commands.iter().find(|run| run.command == command && run.security == security).unwrap()
+        };
+        for security in ["none", "S2"] {
+            assert_eq!(run("Basic Get", security).failed, 0);
+            assert!(run("Basic Get", security).p50_ms > run("Basic Set", security).p50_ms);
+        }
+        assert!(run("Basic Set", "S2").serial_bytes > run("Basic Set", "none").serial_bytes);
+
+        let frame = |message: &str| metrics.frame_overhead.iter().find(|f| f.message == message).unwrap();
+        assert_eq!(frame("Basic Set (S2)").on_air_bytes, frame("Basic Set").on_air_bytes + S2_HEADER_BYTES + S2_MAC_BYTES);
+        assert!(frame("OnOff On").on_air_bytes > frame("Basic Set (S2)").on_air_bytes);
+    }
+}
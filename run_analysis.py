@@ -482,7 +482,7 @@ if __name__ == "__main__":
         if 'data_transmission' in lwm2m:
             lwm2m_payload = lwm2m['data_transmission'].get('tlv_encoded_size_bytes', 0)
         if 'osi_layer_4_transport' in matter:
-            matter_payload = matter['osi_layer_4_transport'].get('total_transport_overhead', 0)
+            matter_payload = matter['osi_layer_4_transport'].get('total_transport_overhead') or 0
         
         comparison['data_efficiency'] = {
             'lwm2m_bytes': lwm2m_payload,